@@ -0,0 +1,123 @@
+//! Benchmarks for path lookup, LIST and RETR over generated images.
+//!
+//! Run with `cargo bench`. Pass a filter to run only the matching benchmarks, e.g.
+//! `cargo bench -- list`. Criterion keeps the results under `target/criterion` and reports how
+//! each benchmark changed since the last run; `cargo bench -- --save-baseline before` and
+//! `cargo bench -- --baseline before` compare against a named run instead.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use tokio::{io::AsyncReadExt, runtime::Runtime};
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{
+    Storage,
+    fixture::{IsoBuilder, TempImage},
+};
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+}
+
+/// A directory nested `depth` levels deep with a single file at the bottom.
+fn deep(depth: usize) -> (TempImage, String) {
+    let path: String = (0..depth).map(|i| format!("/level{i}")).collect();
+    let file = format!("{path}/leaf.txt");
+    (
+        IsoBuilder::new()
+            .joliet(true)
+            .file(&file, b"leaf")
+            .build_file(),
+        file,
+    )
+}
+
+/// A single directory holding `width` small files.
+fn wide(width: usize) -> TempImage {
+    (0..width)
+        .fold(IsoBuilder::new().joliet(true), |builder, i| {
+            builder.file(&format!("/wide/file{i:05}.txt"), b"contents")
+        })
+        .build_file()
+}
+
+fn sized(size: usize) -> TempImage {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/blob.bin", &vec![0xa5; size])
+        .build_file()
+}
+
+fn lookup(c: &mut Criterion) {
+    let rt = runtime();
+    let user = DefaultUser {};
+    let mut group = c.benchmark_group("lookup");
+    for depth in [1, 8, 32] {
+        let (image, file) = deep(depth);
+        let storage = Storage::new(image.path());
+        group.bench_function(BenchmarkId::new("depth", depth), |b| {
+            b.to_async(&rt).iter(|| async {
+                let meta = storage.metadata(&user, &file).await.unwrap();
+                black_box(meta.len());
+            });
+        });
+    }
+    for width in [10, 1_000, 5_000] {
+        let image = wide(width);
+        let storage = Storage::new(image.path());
+        let last = format!("/wide/file{:05}.txt", width - 1);
+        group.bench_function(BenchmarkId::new("last-of", width), |b| {
+            b.to_async(&rt).iter(|| async {
+                black_box(storage.metadata(&user, &last).await.unwrap().len());
+            });
+        });
+    }
+    group.finish();
+}
+
+fn list(c: &mut Criterion) {
+    let rt = runtime();
+    let user = DefaultUser {};
+    let mut group = c.benchmark_group("list");
+    for width in [10, 1_000, 5_000] {
+        let image = wide(width);
+        let storage = Storage::new(image.path());
+        group.throughput(Throughput::Elements(width as u64));
+        group.bench_function(BenchmarkId::new("width", width), |b| {
+            b.to_async(&rt).iter(|| async {
+                black_box(storage.list(&user, "/wide").await.unwrap());
+            });
+        });
+    }
+    group.finish();
+}
+
+fn get(c: &mut Criterion) {
+    let rt = runtime();
+    let user = DefaultUser {};
+    let mut group = c.benchmark_group("get");
+    for size in [4 << 10, 1 << 20, 16 << 20] {
+        let image = sized(size);
+        let storage = Storage::new(image.path());
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(
+            BenchmarkId::from_parameter(format!("{}KiB", size >> 10)),
+            |b| {
+                b.to_async(&rt).iter(|| async {
+                    let mut reader = storage.get(&user, "/blob.bin", 0).await.unwrap();
+                    let mut buf = Vec::with_capacity(size);
+                    reader.read_to_end(&mut buf).await.unwrap();
+                    black_box(buf);
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, lookup, list, get);
+criterion_main!(benches);
@@ -7,7 +7,7 @@ use unftp_sbe_iso::Storage;
 async fn main() {
     let addr = "127.0.0.1:2121";
 
-    let server = ServerBuilder::new(Box::new(move || Storage::new("examples/my.iso")))
+    let server = ServerBuilder::new(Storage::shared("examples/my.iso"))
         .greeting("Welcome to my ISO over FTP")
         .passive_ports(50000..=65535)
         .build()
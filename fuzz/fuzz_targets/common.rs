@@ -0,0 +1,42 @@
+//! Helpers shared by the fuzz targets.
+
+use std::{path::PathBuf, sync::OnceLock};
+use tokio::runtime::Runtime;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::Storage;
+
+/// A small, valid image used as the starting point for mutations.
+pub const SEED_IMAGE: &[u8] = include_bytes!("../../examples/my.iso");
+
+pub fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("could not create runtime")
+    })
+}
+
+/// Writes the image to a file private to this fuzzing process and returns its path.
+pub fn write_image(bytes: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("unftp-sbe-iso-fuzz-{}.iso", std::process::id()));
+    std::fs::write(&path, bytes).expect("could not write image");
+    path
+}
+
+/// Runs every read-only operation of the back-end against `path`. Errors are fine, panics and
+/// hangs are not.
+pub async fn exercise(storage: &Storage, path: &str) {
+    let user = DefaultUser;
+    let _ = storage.metadata(&user, path).await;
+    let _ = storage.cwd(&user, path).await;
+    let _ = storage.get(&user, path, 0).await;
+    let _ = storage.get(&user, path, 1).await;
+    if let Ok(entries) = storage.list(&user, path).await {
+        for entry in entries.iter().take(16) {
+            let child = entry.path.to_string_lossy();
+            let _ = storage.metadata(&user, format!("{path}/{child}")).await;
+            let _ = storage.get(&user, format!("{path}/{child}"), 0).await;
+        }
+    }
+}
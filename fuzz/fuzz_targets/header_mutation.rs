@@ -0,0 +1,33 @@
+//! Overwrites parts of a valid image, starting at the volume descriptors, with fuzzer input.
+//!
+//! The first four bytes of the input select the offset (relative to the first volume descriptor)
+//! at which the rest of the input is written. This keeps most of the image intact so the fuzzer
+//! spends its time in the directory and path handling code instead of failing at the first
+//! descriptor.
+
+#![no_main]
+
+mod common;
+
+use libfuzzer_sys::fuzz_target;
+use unftp_sbe_iso::Storage;
+
+const DESCRIPTORS_START: usize = 16 * 2048;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 4 {
+        return;
+    }
+    let (offset, patch) = data.split_at(4);
+    let mut image = common::SEED_IMAGE.to_vec();
+    let span = image.len() - DESCRIPTORS_START;
+    let offset = DESCRIPTORS_START + u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize % span;
+    let end = (offset + patch.len()).min(image.len());
+    image[offset..end].copy_from_slice(&patch[..end - offset]);
+
+    let storage = Storage::new(common::write_image(&image));
+    common::runtime().block_on(async {
+        common::exercise(&storage, "/").await;
+        common::exercise(&storage, "/hello.txt").await;
+    });
+});
@@ -0,0 +1,16 @@
+//! Feeds hostile path strings through the back-end, using a valid image.
+
+#![no_main]
+
+mod common;
+
+use libfuzzer_sys::fuzz_target;
+use std::sync::OnceLock;
+use unftp_sbe_iso::Storage;
+
+fuzz_target!(|data: &[u8]| {
+    static STORAGE: OnceLock<Storage> = OnceLock::new();
+    let storage = STORAGE.get_or_init(|| Storage::new(common::write_image(common::SEED_IMAGE)));
+    let path = String::from_utf8_lossy(data);
+    common::runtime().block_on(common::exercise(storage, &path));
+});
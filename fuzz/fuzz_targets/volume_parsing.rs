@@ -0,0 +1,13 @@
+//! Feeds arbitrary bytes to the back-end as if they were an ISO image.
+
+#![no_main]
+
+mod common;
+
+use libfuzzer_sys::fuzz_target;
+use unftp_sbe_iso::Storage;
+
+fuzz_target!(|data: &[u8]| {
+    let storage = Storage::new(common::write_image(data));
+    common::runtime().block_on(common::exercise(&storage, "/"));
+});
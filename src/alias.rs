@@ -0,0 +1,231 @@
+//! Renaming and relocating paths of the image in the tree presented to clients.
+
+use std::{
+    io,
+    path::{Component, Path},
+};
+
+/// A table of aliases that present paths of the image under a different name or location, for
+/// example `/x86_64/images/boot.iso` as `/boot.iso`.
+///
+/// An aliased path is only reachable under its alias: it disappears from its original location
+/// in both listings and lookups. The parent of the alias must be a directory in the presented
+/// tree. Aliases are applied below the [root](crate::StorageBuilder::root) and symbolic links in
+/// the image are not affected by them.
+///
+/// Aliases can be added one by one:
+///
+/// ```
+/// use unftp_sbe_iso::Aliases;
+///
+/// let aliases = Aliases::new()
+///     .alias("/boot.iso", "/x86_64/images/boot.iso")
+///     .alias("/docs", "/usr/share/doc");
+/// ```
+///
+/// or read from a config file with one `alias = path` pair per line, where empty lines and lines
+/// starting with `#` are ignored:
+///
+/// ```
+/// use unftp_sbe_iso::Aliases;
+///
+/// let aliases = Aliases::parse(
+///     "# Where the installer expects them\n\
+///      /boot.iso = /x86_64/images/boot.iso\n",
+/// )
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Aliases {
+    /// Pairs of the presented names and the names in the image.
+    entries: Vec<(Vec<String>, Vec<String>)>,
+}
+
+fn names(path: &Path) -> Vec<String> {
+    let mut names = Vec::new();
+    for comp in path.components() {
+        match comp {
+            Component::Normal(name) => names.push(name.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                names.pop();
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+fn has_prefix(names: &[String], prefix: &[String], eq: impl Fn(&str, &str) -> bool) -> bool {
+    names.len() >= prefix.len() && names.iter().zip(prefix).all(|(a, b)| eq(a, b))
+}
+
+impl Aliases {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Presents the path `actual` of the image as `presented`.
+    pub fn alias<P: AsRef<Path>, Q: AsRef<Path>>(mut self, presented: P, actual: Q) -> Self {
+        self.entries
+            .push((names(presented.as_ref()), names(actual.as_ref())));
+        self
+    }
+
+    /// Parses a table from the config file format described [above](Self).
+    pub fn parse(config: &str) -> io::Result<Self> {
+        let mut aliases = Self::new();
+        for (number, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((presented, actual))
+                    if !presented.trim().is_empty() && !actual.trim().is_empty() =>
+                {
+                    aliases = aliases.alias(presented.trim(), actual.trim());
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: expected `alias = path`", number + 1),
+                    ));
+                }
+            }
+        }
+        Ok(aliases)
+    }
+
+    /// Reads a table from a config file in the format described [above](Self).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Maps presented names to the names in the image, or `None` if the path was aliased away.
+    /// `eq` compares a presented name with a name from a client path.
+    pub(crate) fn to_image(
+        &self,
+        names: &[String],
+        eq: impl Fn(&str, &str) -> bool + Copy,
+    ) -> Option<Vec<String>> {
+        let alias = self
+            .entries
+            .iter()
+            .filter(|(presented, _)| has_prefix(names, presented, eq))
+            .max_by_key(|(presented, _)| presented.len());
+        let (mapped, depth) = match alias {
+            Some((presented, actual)) => (
+                actual
+                    .iter()
+                    .chain(&names[presented.len()..])
+                    .cloned()
+                    .collect(),
+                actual.len(),
+            ),
+            None => (names.to_vec(), 0),
+        };
+        // Paths that were relocated by a more specific alias are only reachable under it.
+        let relocated = self.entries.iter().any(|(_, actual)| {
+            (alias.is_none() || actual.len() > depth) && has_prefix(&mapped, actual, eq)
+        });
+        (!relocated).then_some(mapped)
+    }
+
+    /// Returns the name and the path in the image of every alias that lives directly in the
+    /// presented directory `dir`.
+    pub(crate) fn children<'a>(
+        &'a self,
+        dir: &'a [String],
+        eq: impl Fn(&str, &str) -> bool + Copy + 'a,
+    ) -> impl Iterator<Item = (&'a str, &'a [String])> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(presented, _)| {
+                presented.len() == dir.len() + 1 && has_prefix(presented, dir, eq)
+            })
+            .map(|(presented, actual)| (presented.last().unwrap().as_str(), actual.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eq(a: &str, b: &str) -> bool {
+        a == b
+    }
+
+    fn path(s: &str) -> Vec<String> {
+        names(Path::new(s))
+    }
+
+    #[test]
+    fn maps_paths() {
+        let aliases = Aliases::new()
+            .alias("/boot.iso", "/x86_64/images/boot.iso")
+            .alias("/os", "/x86_64")
+            .alias("/os/boot", "/x86_64/isolinux");
+        assert_eq!(
+            aliases.to_image(&path("/boot.iso"), eq),
+            Some(path("/x86_64/images/boot.iso"))
+        );
+        assert_eq!(
+            aliases.to_image(&path("/os/images/efi.img"), eq),
+            Some(path("/x86_64/images/efi.img"))
+        );
+        assert_eq!(
+            aliases.to_image(&path("/os/boot/vmlinuz"), eq),
+            Some(path("/x86_64/isolinux/vmlinuz"))
+        );
+        assert_eq!(aliases.to_image(&path("/os/images/boot.iso"), eq), None);
+        assert_eq!(aliases.to_image(&path("/os/isolinux"), eq), None);
+        assert_eq!(aliases.to_image(&path("/x86_64"), eq), None);
+        assert_eq!(aliases.to_image(&path("/x86_64/images/boot.iso"), eq), None);
+        assert_eq!(aliases.to_image(&path("/other"), eq), Some(path("/other")));
+    }
+
+    #[test]
+    fn swaps() {
+        let aliases = Aliases::new().alias("/a", "/b").alias("/b", "/a");
+        assert_eq!(aliases.to_image(&path("/a/x"), eq), Some(path("/b/x")));
+        assert_eq!(aliases.to_image(&path("/b"), eq), Some(path("/a")));
+    }
+
+    #[test]
+    fn children() {
+        let aliases = Aliases::new()
+            .alias("/boot.iso", "/x86_64/images/boot.iso")
+            .alias("/extra/docs", "/usr/share/doc");
+        let root: Vec<_> = aliases.children(&[], eq).collect();
+        assert_eq!(
+            root,
+            [("boot.iso", path("/x86_64/images/boot.iso").as_slice())]
+        );
+        assert_eq!(aliases.children(&path("/extra"), eq).count(), 1);
+        assert_eq!(aliases.children(&path("/x86_64"), eq).count(), 0);
+    }
+
+    #[test]
+    fn parses_config() {
+        let aliases = Aliases::parse(
+            "# comment\n\n  /boot.iso =  /x86_64/images/boot.iso \n/docs=/usr/share/doc\n",
+        )
+        .unwrap();
+        assert_eq!(
+            aliases.entries,
+            [
+                (path("/boot.iso"), path("/x86_64/images/boot.iso")),
+                (path("/docs"), path("/usr/share/doc"))
+            ]
+        );
+        for config in ["/boot.iso", "= /x", "/x =", "/a = /b\nbroken"] {
+            let err = Aliases::parse(config).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{config}");
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Per-user download rate limits.
+//!
+//! A [`Bandwidth`] paces the downloads of every user to the rate of their class, as told by a
+//! pluggable [`BandwidthClasses`]. [`TieredBandwidth`] gives anonymous users one rate and
+//! everyone else another, the shaping public mirrors need.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    time::{Instant, Sleep},
+};
+use unftp_core::auth::UserDetail;
+
+/// Tells the download rate of every user.
+pub trait BandwidthClasses: Send + Sync + Debug {
+    /// The bytes per second `user` may download at, over all their downloads together, or
+    /// `None` for no limit.
+    fn rate(&self, user: &dyn UserDetail) -> Option<u64>;
+}
+
+/// [`BandwidthClasses`] of two tiers: one rate for anonymous users, logged in as `anonymous` or
+/// `ftp`, and another for everyone else.
+///
+/// ```
+/// use unftp_sbe_iso::{Bandwidth, TieredBandwidth};
+///
+/// // 1 MB/s for anonymous users, no limit for the others
+/// let bandwidth = Bandwidth::new(TieredBandwidth::new(Some(1_000_000), None));
+/// ```
+#[derive(Debug, Clone)]
+pub struct TieredBandwidth {
+    anonymous: Option<u64>,
+    authenticated: Option<u64>,
+}
+
+impl TieredBandwidth {
+    /// Creates the tiers, with the rates in bytes per second, `None` for no limit.
+    pub fn new(anonymous: Option<u64>, authenticated: Option<u64>) -> Self {
+        Self {
+            anonymous,
+            authenticated,
+        }
+    }
+}
+
+impl BandwidthClasses for TieredBandwidth {
+    fn rate(&self, user: &dyn UserDetail) -> Option<u64> {
+        let name = user.to_string();
+        if name.eq_ignore_ascii_case("anonymous") || name.eq_ignore_ascii_case("ftp") {
+            self.anonymous
+        } else {
+            self.authenticated
+        }
+    }
+}
+
+/// Download rate limits applied to the users of the storage back-end.
+///
+/// A user is allowed a second's worth of bytes at once, then paced to the rate of their class.
+/// Their downloads share the rate, so opening more connections doesn't make them faster. The
+/// class is asked again at the start of every download.
+#[derive(Debug, Clone)]
+pub struct Bandwidth {
+    classes: Arc<dyn BandwidthClasses>,
+    /// The buckets of the users, dropped once refilled after their last download
+    buckets: Arc<Mutex<HashMap<String, Arc<Mutex<Bucket>>>>>,
+}
+
+impl Bandwidth {
+    /// Limits the rates of users to those of `classes`.
+    pub fn new(classes: impl BandwidthClasses + 'static) -> Self {
+        Self {
+            classes: Arc::new(classes),
+            buckets: Arc::default(),
+        }
+    }
+
+    /// The pace of the downloads of `user`, if their class is limited.
+    pub(crate) fn pace(&self, user: &dyn UserDetail) -> Option<Pace> {
+        self.classes.rate(user).map(|rate| {
+            let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+            // A full bucket left alone is as good as a new one
+            buckets.retain(|_, bucket| {
+                Arc::strong_count(bucket) > 1
+                    || !bucket.lock().unwrap_or_else(|e| e.into_inner()).full()
+            });
+            let bucket = buckets
+                .entry(user.to_string())
+                .or_insert_with(|| Arc::new(Mutex::new(Bucket::new(rate))))
+                .clone();
+            bucket.lock().unwrap_or_else(|e| e.into_inner()).rate = rate.max(1);
+            Pace(bucket)
+        })
+    }
+}
+
+/// The bucket a download of a user draws from, obtained via [`Bandwidth::pace`].
+#[derive(Debug)]
+pub(crate) struct Pace(Arc<Mutex<Bucket>>);
+
+impl Pace {
+    /// Paces `inner`, a download of the user.
+    pub(crate) fn throttle<R>(self, inner: R) -> Throttled<R> {
+        Throttled {
+            inner,
+            bucket: self.0,
+            sleep: None,
+        }
+    }
+}
+
+/// The bytes a user may download right away, refilled at their rate up to a second's worth.
+#[derive(Debug)]
+struct Bucket {
+    rate: u64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(rate: u64) -> Self {
+        let rate = rate.max(1);
+        Self {
+            rate,
+            tokens: rate as f64,
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Whether it's been refilled to a second's worth.
+    fn full(&self) -> bool {
+        let elapsed = self.refilled.elapsed().as_secs_f64();
+        self.tokens + elapsed * self.rate as f64 >= self.rate as f64
+    }
+
+    /// The bytes that may be read now, or how long until one may.
+    fn available(&mut self) -> Result<usize, Duration> {
+        let now = Instant::now();
+        let rate = self.rate as f64;
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            Ok(self.tokens as usize)
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+/// Wraps the reader handed to libunftp and holds reads back to the rate of the user.
+pub(crate) struct Throttled<R> {
+    inner: R,
+    bucket: Arc<Mutex<Bucket>>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Throttled<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let bucket = &this.bucket;
+        let available = loop {
+            if let Some(sleep) = &mut this.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                this.sleep = None;
+            }
+            match bucket.lock().unwrap_or_else(|e| e.into_inner()).available() {
+                Ok(available) => break available,
+                Err(wait) => this.sleep = Some(Box::pin(tokio::time::sleep(wait))),
+            }
+        };
+        let allowed = available.min(buf.remaining());
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(allowed));
+        let result = Pin::new(&mut this.inner).poll_read(cx, &mut limited);
+        let read = limited.filled().len();
+        buf.advance(read);
+        bucket.lock().unwrap_or_else(|e| e.into_inner()).tokens -= read as f64;
+        result
+    }
+}
@@ -0,0 +1,238 @@
+//! A memory budget shared by the caches and the transfer buffers of one or more back-ends, for
+//! [`StorageBuilder::memory_budget`](crate::StorageBuilder::memory_budget).
+
+use std::{
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+use unftp_core::storage::{Error, ErrorKind, Result};
+
+/// The most memory the block cache, the content cache and the buffers of downloads in flight
+/// may take together, split between them in proportion to their [shares](Self::shares).
+///
+/// A budget replaces the sizes of the block and content caches in
+/// [`CacheConfig`](crate::CacheConfig), enabling both. Caches drop entries to stay within their
+/// share, and a download whose buffer doesn't fit in what is left of the share of transfers
+/// fails with [`ErrorKind::TransientFileNotAvailable`], for the client to try again later.
+///
+/// Clones share what is used, so giving clones of one budget to several back-ends, or to a
+/// [directory](crate::Storage::directory) of images whose back-ends all get it, limits them all
+/// together:
+///
+/// ```
+/// use unftp_sbe_iso::{MemoryBudget, Storage};
+///
+/// let budget = MemoryBudget::new(512 * 1024 * 1024);
+/// let debian = Storage::builder("/srv/images/debian.iso")
+///     .memory_budget(budget.clone())
+///     .build();
+/// let ubuntu = Storage::builder("/srv/images/ubuntu.iso")
+///     .memory_budget(budget)
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    total: usize,
+    pools: Arc<Pools>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Pools {
+    pub(crate) blocks: Arc<Pool>,
+    pub(crate) contents: Arc<Pool>,
+    pub(crate) transfers: Arc<Pool>,
+}
+
+impl MemoryBudget {
+    /// A budget of `bytes` in all, a quarter of it for the block cache, half for the content
+    /// cache and a quarter for transfers.
+    pub fn new(bytes: usize) -> Self {
+        Self::split(bytes, [1, 2, 1])
+    }
+
+    /// Splits the budget between the block cache, the content cache and transfers in
+    /// proportion to `blocks`, `contents` and `transfers`. A share of 0 disables the cache, or
+    /// downloads. Forgets what the budget was used for so far, so set the shares before
+    /// handing the budget out.
+    pub fn shares(self, blocks: u32, contents: u32, transfers: u32) -> Self {
+        Self::split(self.total, [blocks, contents, transfers])
+    }
+
+    fn split(total: usize, shares: [u32; 3]) -> Self {
+        let sum: u64 = shares.iter().map(|&s| u64::from(s)).sum();
+        let [blocks, contents, transfers] = shares.map(|share| {
+            let limit = (total as u128 * u128::from(share))
+                .checked_div(u128::from(sum))
+                .unwrap_or(0);
+            Arc::new(Pool::new(limit as usize))
+        });
+        Self {
+            total,
+            pools: Arc::new(Pools {
+                blocks,
+                contents,
+                transfers,
+            }),
+        }
+    }
+
+    /// The bytes in use by the caches and transfers sharing the budget.
+    pub fn used(&self) -> usize {
+        let pools = &self.pools;
+        pools.blocks.used() + pools.contents.used() + pools.transfers.used()
+    }
+
+    pub(crate) fn pools(&self) -> &Pools {
+        &self.pools
+    }
+}
+
+/// A share of a budget, of which parts are taken and given back.
+#[derive(Debug)]
+pub(crate) struct Pool {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl Pool {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Takes `bytes` if they fit in what is left.
+    pub(crate) fn take(&self, bytes: usize) -> bool {
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                used.checked_add(bytes).filter(|&used| used <= self.limit)
+            })
+            .is_ok()
+    }
+
+    /// Gives `bytes` taken before back.
+    pub(crate) fn give(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::AcqRel);
+    }
+}
+
+/// Bytes taken from the share of transfers, given back on drop.
+#[derive(Debug, Default)]
+pub(crate) struct Reservation {
+    taken: Option<(Arc<Pool>, usize)>,
+}
+
+impl Reservation {
+    /// Takes `bytes` from `pool`, or fails if they don't fit. Without a pool nothing is taken.
+    pub(crate) fn take(pool: Option<&Arc<Pool>>, bytes: u64) -> Result<Self> {
+        let Some(pool) = pool else {
+            return Ok(Self::default());
+        };
+        let bytes = usize::try_from(bytes).unwrap_or(usize::MAX);
+        if !pool.take(bytes) {
+            return Err(Error::new(
+                ErrorKind::TransientFileNotAvailable,
+                format!(
+                    "no room for another {bytes} bytes in the {} bytes of the memory budget for transfers",
+                    pool.limit
+                ),
+            ));
+        }
+        Ok(Self {
+            taken: Some((pool.clone(), bytes)),
+        })
+    }
+
+    /// Keeps the reservation until `inner`, which reads from what was reserved, is dropped.
+    pub(crate) fn hold<R>(self, inner: R) -> Reserved<R> {
+        Reserved {
+            inner,
+            _reservation: self,
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some((pool, bytes)) = &self.taken {
+            pool.give(*bytes);
+        }
+    }
+}
+
+/// A reader holding on to the [`Reservation`] of the buffer it reads from.
+pub(crate) struct Reserved<R> {
+    inner: R,
+    /// Only held, to be given back with the reader
+    _reservation: Reservation,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Reserved<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<R: crate::stats::Prepared> crate::stats::Prepared for Reserved<R> {
+    fn prepared(&self) -> u64 {
+        self.inner.prepared()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares() {
+        let budget = MemoryBudget::new(1000);
+        assert_eq!(budget.pools().blocks.limit(), 250);
+        assert_eq!(budget.pools().contents.limit(), 500);
+        assert_eq!(budget.pools().transfers.limit(), 250);
+        let budget = budget.shares(0, 0, 3);
+        assert_eq!(budget.pools().blocks.limit(), 0);
+        assert_eq!(budget.pools().transfers.limit(), 1000);
+        assert_eq!(
+            MemoryBudget::new(10)
+                .shares(0, 0, 0)
+                .pools()
+                .transfers
+                .limit(),
+            0
+        );
+    }
+
+    #[test]
+    fn reservations() {
+        let budget = MemoryBudget::new(400);
+        let transfers = &budget.pools().transfers;
+        let first = Reservation::take(Some(transfers), 60).unwrap();
+        let second = Reservation::take(Some(transfers), 40).unwrap();
+        assert_eq!(budget.used(), 100);
+        let e = Reservation::take(Some(transfers), 1).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::TransientFileNotAvailable);
+        drop(first);
+        assert!(Reservation::take(Some(transfers), 60).is_ok());
+        drop(second);
+        assert_eq!(budget.used(), 0);
+        assert!(Reservation::take(None, u64::MAX).is_ok());
+    }
+}
@@ -0,0 +1,788 @@
+//! In-memory caches for blocks of the image, directory listings and file contents.
+
+#[cfg(feature = "checksums")]
+use crate::hash::{Digest, HASHES_KEPT, HashAlgorithm};
+use crate::{
+    budget::{MemoryBudget, Pool},
+    fsck::FsckReport,
+    lenient::Repairs,
+    path::PathOptions,
+    path_table::PathTable,
+    records::RecordIndex,
+    session::{ReadSession, SESSION_TTL, SESSIONS_KEPT},
+    source::IsoSource,
+    views::Views,
+};
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    hash::Hash,
+    io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// How much memory the caches of a [`Storage`](crate::Storage) may use and how they make room.
+///
+/// Every cache is off by default. Set a budget to enable it:
+///
+/// ```
+/// use std::time::Duration;
+/// use unftp_sbe_iso::{CacheConfig, EvictionPolicy, Storage};
+///
+/// let storage = Storage::builder("/path/to/your/image.iso")
+///     .cache(CacheConfig {
+///         block_cache_mb: 64,
+///         listing_ttl: Some(Duration::from_secs(300)),
+///         content_cache_mb: 256,
+///         policy: EvictionPolicy::Lru,
+///     })
+///     .build();
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Megabytes of the image kept in memory, in blocks of 64 KiB. Speeds up the lookups of paths
+    /// and listings, which read the same directory records over and over.
+    pub block_cache_mb: usize,
+    /// How long a directory listing is served from memory. Up to 4096 listings are kept.
+    pub listing_ttl: Option<Duration>,
+    /// Megabytes of file contents kept in memory, for images with a few popular files. Files
+    /// larger than an eighth of the budget are not cached.
+    pub content_cache_mb: usize,
+    /// Which entries make room when a cache is full.
+    pub policy: EvictionPolicy,
+}
+
+/// Which entries a full cache drops first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// The least recently used entries
+    #[default]
+    Lru,
+    /// The oldest entries, no matter how often they are used
+    Fifo,
+}
+
+/// How well the caches of a [`Storage`](crate::Storage) do, as returned by
+/// [`Storage::cache_stats`](crate::Storage::cache_stats). The counters of disabled caches stay at
+/// zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The block cache
+    pub blocks: CacheCounters,
+    /// The listing cache
+    pub listings: CacheCounters,
+    /// The content cache
+    pub contents: CacheCounters,
+    /// The read sessions of downloads, which are always kept so that resumed downloads skip
+    /// resolving the path again
+    pub sessions: CacheCounters,
+}
+
+/// The counters of a single cache since the [`Storage`](crate::Storage) was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheCounters {
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups that had to go to the image
+    pub misses: u64,
+    /// Entries dropped to make room or because they expired
+    pub evictions: u64,
+}
+
+/// The size of the blocks the block cache holds.
+pub(crate) const BLOCK_SIZE: u64 = 64 * 1024;
+
+/// The number of listings the listing cache holds.
+const LISTINGS_KEPT: usize = 4096;
+
+/// The bytes of directory record indexes kept, which are always kept.
+const INDEX_BYTES_KEPT: usize = 16 * 1024 * 1024;
+
+/// The number of coalesced reads the block cache holds when it is only enabled for them.
+const COALESCED_KEPT: usize = 16;
+
+/// The caches of a [`Storage`](crate::Storage), shared by its clones.
+#[derive(Debug)]
+pub(crate) struct Caches {
+    pub(crate) blocks: Option<Arc<Cache<u64, Arc<[u8]>>>>,
+    /// The number of blocks read at once on a miss of the block cache.
+    span: u64,
+    pub(crate) listings: Option<Cache<Vec<String>, Arc<[crate::Listed]>>>,
+    pub(crate) contents: Option<Cache<Vec<String>, Arc<[u8]>>>,
+    /// The record indexes of directories, by their extent and its length.
+    pub(crate) indexes: Arc<Cache<(u32, u32), Arc<RecordIndex>>>,
+    /// Where the contents of recently downloaded files lie, by their path.
+    pub(crate) sessions: Cache<Vec<String>, Arc<ReadSession>>,
+    /// The path table of the served hierarchy, by the extent of its root. Always kept, as it is
+    /// read once per image.
+    path_table: Mutex<Option<(u32, Option<Arc<PathTable>>)>>,
+    /// How paths are interpreted with the root descended into, for
+    /// [`crate::StorageBuilder::descend_single_directory`].
+    descended: Mutex<Option<Arc<PathOptions>>>,
+    /// The repaired volume descriptors of the image, for lenient parsing.
+    repairs: Mutex<Option<Arc<Repairs>>>,
+    /// What checking the structure of the image found, for [`crate::StorageBuilder::validation`].
+    validation: Mutex<Option<Arc<FsckReport>>>,
+    /// The identifier of the volume, read the first time an operation is traced.
+    #[cfg(feature = "otel")]
+    volume_id: Mutex<Option<Option<Arc<str>>>>,
+    /// The virtual views of the image, built the first time a client enters one.
+    views: Mutex<Option<Arc<Views>>>,
+    /// The part of the catalog for the image, opened the first time an index is needed. `None`
+    /// inside once it couldn't be.
+    #[cfg(feature = "catalog")]
+    catalog: Mutex<Option<Option<Arc<crate::catalog::ImageCatalog>>>>,
+    /// The checksum lists of the image, read the first time a file is verified.
+    #[cfg(feature = "checksums")]
+    checksums: Mutex<Option<Arc<crate::checksums::Checksums>>>,
+    /// What checking the image file against its signature found: what is wrong with it, if
+    /// anything.
+    #[cfg(feature = "checksums")]
+    signature: Mutex<Option<Option<Arc<str>>>>,
+    /// The digests of files, by the extent of their contents, its length and the hash function.
+    #[cfg(feature = "checksums")]
+    pub(crate) hashes: Cache<(u32, u64, HashAlgorithm), Digest>,
+}
+
+impl Caches {
+    /// Creates the caches `config` asks for. With a `budget`, the block and content caches are
+    /// sized by their share of it instead. Misses of the block cache read `coalesce` bytes at
+    /// once, rounded up to whole blocks, enabling the block cache if need be.
+    pub(crate) fn new(
+        config: &CacheConfig,
+        budget: Option<&MemoryBudget>,
+        coalesce: usize,
+    ) -> Self {
+        let megabytes = |mb: usize| mb.saturating_mul(1024 * 1024);
+        fn pooled<K: Hash + Eq + Clone, V: Clone>(
+            pool: &Arc<Pool>,
+            policy: EvictionPolicy,
+        ) -> Option<Cache<K, V>> {
+            (pool.limit() > 0).then(|| Cache::new(0, policy).pool(pool.clone()))
+        }
+        let (blocks, contents) = match budget {
+            Some(budget) => {
+                let pools = budget.pools();
+                (
+                    pooled(&pools.blocks, config.policy),
+                    pooled(&pools.contents, config.policy),
+                )
+            }
+            None => (
+                (config.block_cache_mb > 0)
+                    .then(|| Cache::new(megabytes(config.block_cache_mb), config.policy)),
+                (config.content_cache_mb > 0)
+                    .then(|| Cache::new(megabytes(config.content_cache_mb), config.policy)),
+            ),
+        };
+        let span = (coalesce as u64).div_ceil(BLOCK_SIZE).max(1);
+        let blocks = blocks.or_else(|| {
+            let kept = span as usize * BLOCK_SIZE as usize * COALESCED_KEPT;
+            (span > 1 && budget.is_none()).then(|| Cache::new(kept, config.policy))
+        });
+        Self {
+            blocks: blocks.map(Arc::new),
+            span,
+            listings: config
+                .listing_ttl
+                .map(|ttl| Cache::new(LISTINGS_KEPT, config.policy).ttl(ttl)),
+            contents,
+            indexes: Arc::new(Cache::new(INDEX_BYTES_KEPT, config.policy)),
+            sessions: Cache::new(SESSIONS_KEPT, config.policy).ttl(SESSION_TTL),
+            path_table: Mutex::new(None),
+            descended: Mutex::new(None),
+            repairs: Mutex::new(None),
+            validation: Mutex::new(None),
+            #[cfg(feature = "otel")]
+            volume_id: Mutex::new(None),
+            views: Mutex::new(None),
+            #[cfg(feature = "catalog")]
+            catalog: Mutex::new(None),
+            #[cfg(feature = "checksums")]
+            checksums: Mutex::new(None),
+            #[cfg(feature = "checksums")]
+            signature: Mutex::new(None),
+            #[cfg(feature = "checksums")]
+            hashes: Cache::new(HASHES_KEPT, config.policy),
+        }
+    }
+
+    /// Wraps the source of the image in the block cache, if there is one.
+    pub(crate) fn source(&self, source: Arc<dyn IsoSource>) -> Arc<dyn IsoSource> {
+        match &self.blocks {
+            Some(blocks) => Arc::new(CachedSource {
+                inner: source,
+                blocks: blocks.clone(),
+                span: self.span,
+            }),
+            None => source,
+        }
+    }
+
+    /// Drops everything cached, for when the image changed.
+    pub(crate) fn clear(&self) {
+        if let Some(blocks) = &self.blocks {
+            blocks.clear();
+        }
+        if let Some(listings) = &self.listings {
+            listings.clear();
+        }
+        if let Some(contents) = &self.contents {
+            contents.clear();
+        }
+        self.indexes.clear();
+        self.sessions.clear();
+        #[cfg(feature = "checksums")]
+        self.hashes.clear();
+        *self.path_table.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        *self.descended.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        *self.repairs.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        *self.validation.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        #[cfg(feature = "otel")]
+        {
+            *self.volume_id.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+        *self.views.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        #[cfg(feature = "catalog")]
+        {
+            *self.catalog.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+        #[cfg(feature = "checksums")]
+        {
+            *self.checksums.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            *self.signature.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+    }
+
+    /// Returns the path table of the hierarchy whose root is at `root`, loading it the first
+    /// time.
+    pub(crate) fn path_table(
+        &self,
+        root: u32,
+        load: impl FnOnce() -> Option<PathTable>,
+    ) -> Option<Arc<PathTable>> {
+        let mut kept = self.path_table.lock().unwrap_or_else(|e| e.into_inner());
+        match &*kept {
+            Some((extent, table)) if *extent == root => table.clone(),
+            _ => {
+                let table = load().map(Arc::new);
+                *kept = Some((root, table.clone()));
+                table
+            }
+        }
+    }
+
+    /// Returns how paths are interpreted with the root descended into, finding the directory to
+    /// descend into the first time.
+    pub(crate) fn descended(&self, find: impl FnOnce() -> Arc<PathOptions>) -> Arc<PathOptions> {
+        self.descended
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(find)
+            .clone()
+    }
+
+    /// Returns the repairs of the volume descriptors of the image, finding them the first time.
+    pub(crate) fn repairs(&self, find: impl FnOnce() -> Repairs) -> Arc<Repairs> {
+        self.repairs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(|| Arc::new(find()))
+            .clone()
+    }
+
+    /// Returns what checking the structure of the image found, checking it the first time.
+    pub(crate) fn validation(&self, check: impl FnOnce() -> FsckReport) -> Arc<FsckReport> {
+        self.validation
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(|| Arc::new(check()))
+            .clone()
+    }
+
+    /// Returns the identifier of the volume, reading it the first time.
+    #[cfg(feature = "otel")]
+    pub(crate) fn volume_id(&self, read: impl FnOnce() -> Option<Arc<str>>) -> Option<Arc<str>> {
+        self.volume_id
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(read)
+            .clone()
+    }
+
+    /// Returns the catalog file of the image, opening it the first time.
+    #[cfg(feature = "catalog")]
+    pub(crate) fn catalog(
+        &self,
+        open: impl FnOnce() -> Option<crate::catalog::ImageCatalog>,
+    ) -> Option<Arc<crate::catalog::ImageCatalog>> {
+        self.catalog
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(|| open().map(Arc::new))
+            .clone()
+    }
+
+    /// Returns the views of the image, building them the first time. A failed build is tried
+    /// again next time.
+    pub(crate) fn views<E>(
+        &self,
+        build: impl FnOnce() -> Result<Views, E>,
+    ) -> Result<Arc<Views>, E> {
+        if let Some(views) = &*self.views.lock().unwrap_or_else(|e| e.into_inner()) {
+            return Ok(views.clone());
+        }
+        // Built without holding the lock, as building walks the image through the other caches
+        let views = Arc::new(build()?);
+        Ok(self
+            .views
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert(views)
+            .clone())
+    }
+
+    /// Returns the checksum lists of the image, loading them the first time.
+    #[cfg(feature = "checksums")]
+    pub(crate) fn checksums(
+        &self,
+        load: impl FnOnce() -> crate::checksums::Checksums,
+    ) -> Arc<crate::checksums::Checksums> {
+        self.checksums
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(|| Arc::new(load()))
+            .clone()
+    }
+
+    /// Returns what checking the image file against its signature found, checking it the first
+    /// time. Operations wait for the check to finish. A check that fails to read the image is
+    /// tried again.
+    #[cfg(feature = "checksums")]
+    pub(crate) fn signature(
+        &self,
+        check: impl FnOnce() -> io::Result<Option<Arc<str>>>,
+    ) -> io::Result<Option<Arc<str>>> {
+        let mut kept = self.signature.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(found) = &*kept {
+            return Ok(found.clone());
+        }
+        let found = check()?;
+        *kept = Some(found.clone());
+        Ok(found)
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            blocks: self
+                .blocks
+                .as_deref()
+                .map(Cache::counters)
+                .unwrap_or_default(),
+            listings: self
+                .listings
+                .as_ref()
+                .map(Cache::counters)
+                .unwrap_or_default(),
+            contents: self
+                .contents
+                .as_ref()
+                .map(Cache::counters)
+                .unwrap_or_default(),
+            sessions: self.sessions.counters(),
+        }
+    }
+
+    /// The largest file kept in the content cache.
+    pub(crate) fn max_content_len(&self) -> usize {
+        self.contents.as_ref().map_or(0, |c| c.budget / 8)
+    }
+}
+
+/// A map that holds values up to a total size, dropping entries as the [`EvictionPolicy`]
+/// dictates to make room. Safe to share between threads; a single lock guards the whole map.
+pub(crate) struct Cache<K, V> {
+    budget: usize,
+    policy: EvictionPolicy,
+    ttl: Option<Duration>,
+    /// The share of a [`MemoryBudget`] the entries are taken from, which other caches may take
+    /// from too
+    pool: Option<Arc<Pool>>,
+    state: Mutex<State<K, V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+struct State<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// The keys by the tick they were inserted or, with [`EvictionPolicy::Lru`], last used at.
+    /// The lowest tick goes first.
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    used: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    tick: u64,
+    inserted: Instant,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Cache<K, V> {
+    /// Creates a cache holding values with sizes adding up to at most `budget`.
+    pub(crate) fn new(budget: usize, policy: EvictionPolicy) -> Self {
+        Self {
+            budget,
+            policy,
+            ttl: None,
+            pool: None,
+            state: Mutex::new(State {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+                used: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Makes entries expire `ttl` after they were inserted.
+    pub(crate) fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Takes the sizes of entries from `pool`, whose limit becomes the budget. When the pool is
+    /// used up by others, entries of this cache make room, and values are not stored once none
+    /// are left.
+    pub(crate) fn pool(mut self, pool: Arc<Pool>) -> Self {
+        self.budget = pool.limit();
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Gives the `size` of a dropped entry back to the pool.
+    fn release(&self, size: usize) {
+        if let Some(pool) = &self.pool {
+            pool.give(size);
+        }
+    }
+
+    pub(crate) fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let state = &mut *state;
+        let Some(entry) = state.entries.get_mut(key) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        if self.ttl.is_some_and(|ttl| entry.inserted.elapsed() >= ttl) {
+            let tick = entry.tick;
+            let stale = state.order.remove(&tick).expect("every entry is ordered");
+            let entry = state
+                .entries
+                .remove::<K>(&stale)
+                .expect("the entry was found");
+            state.used -= entry.size;
+            self.release(entry.size);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        if self.policy == EvictionPolicy::Lru {
+            state.tick += 1;
+            let key = state
+                .order
+                .remove(&entry.tick)
+                .expect("every entry is ordered");
+            entry.tick = state.tick;
+            state.order.insert(state.tick, key);
+        }
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(entry.value.clone())
+    }
+
+    /// Stores `value`, taking up `size` of the budget. Values larger than the whole budget are
+    /// not stored.
+    pub(crate) fn insert(&self, key: K, value: V, size: usize) {
+        if size > self.budget {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let state = &mut *state;
+        if let Some(old) = state.entries.remove(&key) {
+            state.order.remove(&old.tick);
+            state.used -= old.size;
+            self.release(old.size);
+        }
+        while state.used + size > self.budget
+            || self.pool.as_ref().is_some_and(|pool| !pool.take(size))
+        {
+            let Some((_, oldest)) = state.order.pop_first() else {
+                return;
+            };
+            let entry = state
+                .entries
+                .remove(&oldest)
+                .expect("every ordered key has an entry");
+            state.used -= entry.size;
+            self.release(entry.size);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        state.tick += 1;
+        state.order.insert(state.tick, key.clone());
+        state.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                tick: state.tick,
+                inserted: Instant::now(),
+            },
+        );
+        state.used += size;
+    }
+
+    /// Drops all entries. They don't count as evictions.
+    pub(crate) fn clear(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.entries.clear();
+        state.order.clear();
+        self.release(state.used);
+        state.used = 0;
+    }
+
+    pub(crate) fn counters(&self) -> CacheCounters {
+        CacheCounters {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<K, V> Drop for Cache<K, V> {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.pool {
+            pool.give(self.state.get_mut().unwrap_or_else(|e| e.into_inner()).used);
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Cache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("budget", &self.budget)
+            .field("policy", &self.policy)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An [`IsoSource`] that reads whole blocks and keeps them in a cache. A miss reads the `span`
+/// blocks around the block missed, aligned to the span, in one go, for sources where every read
+/// costs a round trip.
+struct CachedSource {
+    inner: Arc<dyn IsoSource>,
+    blocks: Arc<Cache<u64, Arc<[u8]>>>,
+    span: u64,
+}
+
+impl CachedSource {
+    fn block(&self, index: u64) -> io::Result<Arc<[u8]>> {
+        if let Some(block) = self.blocks.get(&index) {
+            return Ok(block);
+        }
+        let first = index - index % self.span;
+        let mut data = vec![0; (self.span * BLOCK_SIZE) as usize];
+        let mut filled = 0;
+        while filled < data.len() {
+            match self
+                .inner
+                .read_at(first * BLOCK_SIZE + filled as u64, &mut data[filled..])
+            {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut missed = None;
+        for i in 0..self.span {
+            // Blocks past the end of the image are empty
+            let start = ((i * BLOCK_SIZE) as usize).min(filled);
+            let end = (((i + 1) * BLOCK_SIZE) as usize).min(filled);
+            let block: Arc<[u8]> = data[start..end].into();
+            if first + i == index {
+                missed = Some(block.clone());
+            }
+            self.blocks.insert(first + i, block, end - start);
+        }
+        Ok(missed.expect("the block missed is in its span"))
+    }
+}
+
+impl IsoSource for CachedSource {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let block = self.block(offset / BLOCK_SIZE)?;
+        let start = ((offset % BLOCK_SIZE) as usize).min(block.len());
+        let n = buf.len().min(block.len() - start);
+        buf[..n].copy_from_slice(&block[start..start + n]);
+        Ok(n)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        self.inner.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lru_keeps_used_entries() {
+        let cache = Cache::new(3, EvictionPolicy::Lru);
+        cache.insert("a", 1, 1);
+        cache.insert("b", 2, 1);
+        cache.insert("c", 3, 1);
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("d", 4, 1);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("d"), Some(4));
+        assert_eq!(
+            cache.counters(),
+            CacheCounters {
+                hits: 3,
+                misses: 1,
+                evictions: 1
+            }
+        );
+    }
+
+    #[test]
+    fn fifo_drops_oldest() {
+        let cache = Cache::new(3, EvictionPolicy::Fifo);
+        cache.insert("a", 1, 1);
+        cache.insert("b", 2, 1);
+        cache.insert("c", 3, 1);
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("d", 4, 2);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(3));
+    }
+
+    #[test]
+    fn oversized_values_are_not_stored() {
+        let cache = Cache::new(3, EvictionPolicy::Lru);
+        cache.insert("a", 1, 1);
+        cache.insert("b", 2, 4);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+    }
+
+    #[test]
+    fn entries_expire() {
+        let cache = Cache::new(3, EvictionPolicy::Lru).ttl(Duration::ZERO);
+        cache.insert("a", 1, 1);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.counters().evictions, 1);
+    }
+
+    #[test]
+    fn pooled_caches_share_the_budget() {
+        let budget = MemoryBudget::new(16).shares(0, 1, 0);
+        let pool = &budget.pools().contents;
+        let first = Cache::new(0, EvictionPolicy::Lru).pool(pool.clone());
+        let second = Cache::new(0, EvictionPolicy::Lru).pool(pool.clone());
+        first.insert("a", 1, 6);
+        first.insert("b", 2, 6);
+        // No room left, and nothing of its own to drop
+        second.insert("c", 3, 6);
+        assert_eq!(second.get("c"), None);
+        assert_eq!(budget.used(), 12);
+        first.insert("a", 4, 2);
+        second.insert("c", 3, 6);
+        assert_eq!(second.get("c"), Some(3));
+        // Makes room by dropping its own entries
+        first.insert("d", 5, 8);
+        assert_eq!(first.get("b"), None);
+        assert_eq!(first.get("a"), Some(4));
+        assert_eq!(budget.used(), 16);
+        second.clear();
+        assert_eq!(budget.used(), 10);
+        drop(first);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn cached_source() {
+        let image: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        let blocks = Arc::new(Cache::new(1 << 20, EvictionPolicy::Lru));
+        let source = CachedSource {
+            inner: Arc::new(image.clone()),
+            blocks: blocks.clone(),
+            span: 1,
+        };
+        let mut buf = [0; 10];
+        assert_eq!(source.read_at(65_530, &mut buf).unwrap(), 6);
+        assert_eq!(buf[..6], image[65_530..65_536]);
+        assert_eq!(source.read_at(99_995, &mut buf).unwrap(), 5);
+        assert_eq!(buf[..5], image[99_995..]);
+        assert_eq!(source.read_at(200_000, &mut buf).unwrap(), 0);
+        assert!(blocks.get(&0).is_some());
+        assert_eq!(blocks.get(&1).unwrap().len(), 100_000 - 65_536);
+    }
+
+    /// Counts the reads of an image.
+    struct Counting(Vec<u8>, AtomicU64);
+
+    impl IsoSource for Counting {
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            self.0.read_at(offset, buf)
+        }
+
+        fn len(&self) -> io::Result<u64> {
+            Ok(self.0.len() as u64)
+        }
+    }
+
+    #[test]
+    fn coalesced_reads() {
+        let image: Vec<u8> = (0..600_000u32).map(|i| (i / 7) as u8).collect();
+        let counting = Arc::new(Counting(image.clone(), AtomicU64::new(0)));
+        let source = CachedSource {
+            inner: counting.clone(),
+            blocks: Arc::new(Cache::new(1 << 20, EvictionPolicy::Lru)),
+            span: 4,
+        };
+        let mut buf = [0; 2048];
+        // Blocks 4 to 7, in one read
+        assert_eq!(source.read_at(5 * BLOCK_SIZE, &mut buf).unwrap(), 2048);
+        assert_eq!(buf[..], image[5 * 65_536..5 * 65_536 + 2048]);
+        assert_eq!(source.read_at(7 * BLOCK_SIZE + 10, &mut buf).unwrap(), 2048);
+        assert_eq!(source.read_at(4 * BLOCK_SIZE, &mut buf).unwrap(), 2048);
+        assert_eq!(counting.1.load(Ordering::Relaxed), 1);
+        // The last span is cut short by the end of the image
+        assert_eq!(source.read_at(599_000, &mut buf).unwrap(), 1000);
+        assert_eq!(buf[..1000], image[599_000..]);
+        assert_eq!(source.read_at(700_000, &mut buf).unwrap(), 0);
+        assert_eq!(source.read_at(210_000, &mut buf).unwrap(), 2048);
+        assert_eq!(counting.1.load(Ordering::Relaxed), 4);
+    }
+}
@@ -0,0 +1,80 @@
+//! Giving up on the reads of a download once nobody waits for it anymore, because the client
+//! aborted or went away while the file was still being read into memory.
+
+use std::{
+    io::{self, Read},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// The most read at once between checks of whether the download was given up on.
+const CHECK_EVERY: usize = 64 * 1024;
+
+/// Whether the download a read is for was given up on. Clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    /// Returns a guard that gives up on the download when it is dropped, which happens when the
+    /// future waiting for the download is.
+    pub(crate) fn on_drop(&self) -> CancelOnDrop {
+        CancelOnDrop(self.clone())
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Wraps `inner` so that it fails once the download is given up on.
+    pub(crate) fn reader<R: Read>(&self, inner: R) -> Cancellable<R> {
+        Cancellable {
+            inner,
+            cancel: self.clone(),
+        }
+    }
+}
+
+/// Gives up on a download when dropped.
+pub(crate) struct CancelOnDrop(Cancel);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        (self.0).0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A reader that fails once the download it reads for is given up on, checking at least every
+/// [`CHECK_EVERY`] bytes.
+pub(crate) struct Cancellable<R> {
+    inner: R,
+    cancel: Cancel,
+}
+
+impl<R: Read> Read for Cancellable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            return Err(io::Error::other("the download was given up on"));
+        }
+        let n = buf.len().min(CHECK_EVERY);
+        self.inner.read(&mut buf[..n])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_stop_once_cancelled() {
+        let cancel = Cancel::default();
+        let guard = cancel.on_drop();
+        let mut reader = cancel.reader(io::repeat(1));
+        let mut buf = vec![0; 3 * CHECK_EVERY];
+        assert_eq!(reader.read(&mut buf).unwrap(), CHECK_EVERY);
+        drop(guard);
+        assert!(cancel.is_cancelled());
+        assert!(reader.read(&mut buf).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! A catalog on disk of the record indexes of images, for
+//! [`StorageBuilder::catalog`](crate::StorageBuilder::catalog), so that a restart doesn't have to
+//! read every directory of every image again to build them.
+//!
+//! The catalog is an SQLite database. Indexes are stored under the SHA-256 digest of the contents
+//! of their image and the options that decide how records are named, so an image that changes
+//! gets its indexes built again however much of it stays the same, and one rebuilt to the same
+//! bytes keeps them. Computing a digest takes reading the whole image, so the digests of image
+//! files are kept too, by their path, size, modification time and inode, and only computed
+//! again once the file is replaced. Every index is a row holding every record as the length of
+//! its identifier, the identifier and the offset of the record, all little-endian, and is only
+//! read when the index is first needed.
+
+use crate::{IsoSource, records::RecordIndex};
+use ring::digest::{Context, SHA256};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+/// The tables of the catalog.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS digests (
+        stamp BLOB PRIMARY KEY,
+        digest BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS indexes (
+        image BLOB NOT NULL,
+        extent INTEGER NOT NULL,
+        len INTEGER NOT NULL,
+        records BLOB NOT NULL,
+        PRIMARY KEY (image, extent, len)
+    );
+";
+
+/// How long to wait for the back-ends of other images sharing the catalog to finish writing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How much of the image is read at once to compute its digest.
+const CHUNK: usize = 1024 * 1024;
+
+/// The extent of a directory and its length, which the index is cached by.
+type Key = (u32, u32);
+
+/// The indexes of one image in the catalog.
+#[derive(Debug)]
+pub(crate) struct ImageCatalog {
+    path: PathBuf,
+    /// The digest of the image followed by the naming options, which its indexes are stored
+    /// under
+    image: Vec<u8>,
+    db: Mutex<Connection>,
+}
+
+impl ImageCatalog {
+    /// Opens the catalog at `path` for the image read from `source`, creating the database if
+    /// there is none. `stamp` tells an image file apart from those replacing it, so that its
+    /// digest is computed only once, and `naming` the options that decide how records are
+    /// named.
+    pub(crate) fn open(
+        path: &Path,
+        source: &dyn IsoSource,
+        stamp: Option<&[u8]>,
+        naming: &[u8],
+    ) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let db = Connection::open(path).map_err(io::Error::other)?;
+        db.busy_timeout(BUSY_TIMEOUT).map_err(io::Error::other)?;
+        db.execute_batch(SCHEMA).map_err(io::Error::other)?;
+        let digest = match stamp {
+            Some(stamp) => {
+                let known: Option<Vec<u8>> = db
+                    .query_row(
+                        "SELECT digest FROM digests WHERE stamp = ?1",
+                        [stamp],
+                        |row| row.get(0),
+                    )
+                    .optional()
+                    .map_err(io::Error::other)?;
+                match known {
+                    Some(digest) => digest,
+                    None => {
+                        let digest = digest(source)?;
+                        db.execute(
+                            "INSERT OR REPLACE INTO digests (stamp, digest) VALUES (?1, ?2)",
+                            params![stamp, digest],
+                        )
+                        .map_err(io::Error::other)?;
+                        digest
+                    }
+                }
+            }
+            None => digest(source)?,
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            image: [&digest[..], naming].concat(),
+            db: Mutex::new(db),
+        })
+    }
+
+    /// Reads the index of the directory at `key` from the catalog, if it is there.
+    pub(crate) fn get(&self, key: Key) -> Option<RecordIndex> {
+        let db = self.db.lock().unwrap_or_else(|e| e.into_inner());
+        let records = db
+            .query_row(
+                "SELECT records FROM indexes WHERE image = ?1 AND extent = ?2 AND len = ?3",
+                params![self.image, key.0, key.1],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(io::Error::other)
+            .and_then(|records| records.as_deref().map(decode).transpose());
+        match records {
+            Ok(records) => records.map(RecordIndex::from_records),
+            Err(e) => {
+                log::warn!("could not read from the catalog {:?}: {e}", self.path);
+                None
+            }
+        }
+    }
+
+    /// Adds the index of the directory at `key` to the catalog, replacing one that couldn't be
+    /// read.
+    pub(crate) fn insert(&self, key: Key, index: &RecordIndex) {
+        let mut records = Vec::new();
+        for (identifier, offset) in index.records() {
+            let Ok(len) = u16::try_from(identifier.len()) else {
+                return;
+            };
+            records.extend(len.to_le_bytes());
+            records.extend(identifier.as_bytes());
+            records.extend(offset.to_le_bytes());
+        }
+        let db = self.db.lock().unwrap_or_else(|e| e.into_inner());
+        let inserted = db.execute(
+            "INSERT OR REPLACE INTO indexes (image, extent, len, records) VALUES (?1, ?2, ?3, ?4)",
+            params![self.image, key.0, key.1, records],
+        );
+        if let Err(e) = inserted {
+            log::warn!("could not write to the catalog {:?}: {e}", self.path);
+        }
+    }
+}
+
+/// The SHA-256 digest of the contents of the image read from `source`.
+fn digest(source: &dyn IsoSource) -> io::Result<Vec<u8>> {
+    let mut context = Context::new(&SHA256);
+    let mut buf = vec![0; CHUNK];
+    let mut at = 0;
+    loop {
+        let n = source.read_at(at, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        context.update(&buf[..n]);
+        at += n as u64;
+    }
+    Ok(context.finish().as_ref().to_vec())
+}
+
+/// Reads the records of an index back.
+fn decode(mut rest: &[u8]) -> io::Result<Vec<(String, u64)>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "corrupt index");
+    let mut records = Vec::new();
+    while !rest.is_empty() {
+        let (len, after) = rest.split_first_chunk::<2>().ok_or_else(invalid)?;
+        let len = usize::from(u16::from_le_bytes(*len));
+        if after.len() < len + 8 {
+            return Err(invalid());
+        }
+        let identifier = std::str::from_utf8(&after[..len]).map_err(|_| invalid())?;
+        let offset = u64::from_le_bytes(after[len..len + 8].try_into().unwrap());
+        records.push((identifier.to_string(), offset));
+        rest = &after[len + 8..];
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(records: &[(&str, u64)]) -> RecordIndex {
+        RecordIndex::from_records(records.iter().map(|&(n, o)| (n.to_string(), o)).collect())
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir =
+            std::env::temp_dir().join(format!("unftp-sbe-iso-catalog-{}", std::process::id()));
+        let path = dir.join("catalog.sqlite");
+        let image = vec![0x20; 40 * 2048];
+        let catalog = ImageCatalog::open(&path, &image, None, b"").unwrap();
+        assert!(catalog.get((20, 2048)).is_none());
+        catalog.insert((20, 2048), &index(&[("README.TXT", 68), ("SRC", 102)]));
+        catalog.insert((21, 4096), &index(&[]));
+        drop(catalog);
+
+        let catalog = ImageCatalog::open(&path, &image, None, b"").unwrap();
+        let read = catalog.get((20, 2048)).unwrap();
+        assert_eq!(
+            read.records(),
+            index(&[("README.TXT", 68), ("SRC", 102)]).records()
+        );
+        assert_eq!(read.exact("SRC"), Some(102));
+        assert!(catalog.get((21, 4096)).unwrap().records().is_empty());
+        assert!(catalog.get((22, 2048)).is_none());
+
+        // Other naming options and other contents, even of the same size, are indexed apart
+        let other = ImageCatalog::open(&path, &image, None, b"versions").unwrap();
+        assert!(other.get((20, 2048)).is_none());
+        let mut changed = image.clone();
+        changed[100] = 0;
+        let other = ImageCatalog::open(&path, &changed, None, b"").unwrap();
+        assert!(other.get((20, 2048)).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn digests_kept_by_stamp() {
+        let dir = std::env::temp_dir().join(format!(
+            "unftp-sbe-iso-catalog-stamp-{}",
+            std::process::id()
+        ));
+        let path = dir.join("catalog.sqlite");
+        let image = vec![0x20; 4096];
+        let catalog = ImageCatalog::open(&path, &image, Some(b"one"), b"").unwrap();
+        catalog.insert((20, 2048), &index(&[("A", 1)]));
+        // The digest of a stamp seen before isn't computed again
+        let other = ImageCatalog::open(&path, &vec![0; 4096], Some(b"one"), b"").unwrap();
+        assert_eq!(other.get((20, 2048)).unwrap().exact("A"), Some(1));
+        let other = ImageCatalog::open(&path, &vec![0; 4096], Some(b"two"), b"").unwrap();
+        assert!(other.get((20, 2048)).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+//! The checksum lists distributions ship inside their images, like Debian's `md5sum.txt` or
+//! `SHA256SUMS`, used to catch corrupt images while serving them.
+
+use crate::hash::HashAlgorithm;
+use std::{collections::HashMap, io};
+
+/// What happens when a file doesn't match its checksum, as set with
+/// [`StorageBuilder::integrity`](crate::StorageBuilder::integrity).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityMode {
+    /// Logs a warning and serves the file anyway
+    Log,
+    /// Refuses to serve the file
+    Abort,
+}
+
+/// The checksum lists looked for in the root directory, strongest first. A file listed in
+/// several is checked against the strongest.
+pub(crate) const LISTS: [(&str, HashAlgorithm); 8] = [
+    ("sha512sum.txt", HashAlgorithm::Sha512),
+    ("SHA512SUMS", HashAlgorithm::Sha512),
+    ("sha256sum.txt", HashAlgorithm::Sha256),
+    ("SHA256SUMS", HashAlgorithm::Sha256),
+    ("sha1sum.txt", HashAlgorithm::Sha1),
+    ("SHA1SUMS", HashAlgorithm::Sha1),
+    ("md5sum.txt", HashAlgorithm::Md5),
+    ("MD5SUMS", HashAlgorithm::Md5),
+];
+
+/// The largest checksum list read.
+pub(crate) const MAX_LIST_LEN: u64 = 64 * 1024 * 1024;
+
+/// A file whose contents don't match the checksum listed for it.
+#[derive(Debug)]
+pub(crate) struct Mismatch {
+    pub(crate) algorithm: HashAlgorithm,
+    pub(crate) list: &'static str,
+}
+
+/// The checksums listed in an image, by the path of the file they are for.
+#[derive(Debug, Default)]
+pub(crate) struct Checksums {
+    files: HashMap<String, Listed>,
+}
+
+#[derive(Debug)]
+struct Listed {
+    algorithm: HashAlgorithm,
+    digest: Vec<u8>,
+    list: &'static str,
+}
+
+/// The key of a path in [`Checksums`]. Names match regardless of ASCII case, like client paths
+/// do.
+fn key<S: AsRef<str>>(names: impl IntoIterator<Item = S>) -> String {
+    let names: Vec<String> = names
+        .into_iter()
+        .map(|name| name.as_ref().to_ascii_lowercase())
+        .collect();
+    names.join("/")
+}
+
+impl Checksums {
+    /// Adds the checksums of a list for the files not listed in an earlier one. Lines that
+    /// aren't in the GNU (`<hex>  <path>`) or BSD (`SHA256 (<path>) = <hex>`) format are skipped.
+    pub(crate) fn add(&mut self, list: &'static str, algorithm: HashAlgorithm, text: &str) {
+        for line in text.lines() {
+            let Some((path, hex)) = parse_line(line, algorithm) else {
+                continue;
+            };
+            let Some(digest) = decode_hex(hex).filter(|d| Some(d.len()) == algorithm.output_len())
+            else {
+                continue;
+            };
+            let names = path
+                .split('/')
+                .filter(|name| !name.is_empty() && *name != ".");
+            self.files.entry(key(names)).or_insert(Listed {
+                algorithm,
+                digest,
+                list,
+            });
+        }
+    }
+
+    /// Checks the contents of the file at the path made up of `names`, which `digest` computes
+    /// the digest of with the algorithm it is given. Files that aren't listed pass, without
+    /// their digest computed.
+    pub(crate) fn verify_with(
+        &self,
+        names: &[String],
+        digest: impl FnOnce(HashAlgorithm) -> io::Result<Vec<u8>>,
+    ) -> io::Result<Result<(), Mismatch>> {
+        match self.files.get(&key(names)) {
+            Some(listed) if digest(listed.algorithm)? != listed.digest => Ok(Err(Mismatch {
+                algorithm: listed.algorithm,
+                list: listed.list,
+            })),
+            _ => Ok(Ok(())),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+pub(crate) fn parse_line(line: &str, algorithm: HashAlgorithm) -> Option<(&str, &str)> {
+    let line = line.trim_end_matches('\r');
+    if let Some(rest) = line.strip_prefix(algorithm.tag())
+        && let Some(rest) = rest.trim_start().strip_prefix('(')
+    {
+        let (path, hex) = rest.rsplit_once(") = ")?;
+        return Some((path, hex.trim()));
+    }
+    let (hex, path) = line.split_once(char::is_whitespace)?;
+    // GNU lists mark files read in binary mode with a `*`
+    let path = path.trim_start_matches(' ').trim_start_matches('*');
+    Some((path, hex))
+}
+
+pub(crate) fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(path: &str) -> Vec<String> {
+        path.split('/').map(str::to_string).collect()
+    }
+
+    fn verify(sums: &Checksums, path: &str, contents: &[u8]) -> Result<(), Mismatch> {
+        sums.verify_with(&names(path), |algorithm| Ok(algorithm.digest(contents)))
+            .unwrap()
+    }
+
+    #[test]
+    fn gnu_and_bsd_lists() {
+        let mut sums = Checksums::default();
+        sums.add(
+            "md5sum.txt",
+            HashAlgorithm::Md5,
+            "5d41402abc4b2a76b9719d911017c592  ./docs/hello.txt\r\n\
+             not a checksum line\n\
+             d41d8cd98f00b204e9800998ecf8427e *empty\n",
+        );
+        sums.add(
+            "SHA256SUMS",
+            HashAlgorithm::Sha256,
+            "SHA256 (docs/hello.txt) = \
+             2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824\n",
+        );
+        assert!(!sums.is_empty());
+        assert!(verify(&sums, "docs/hello.txt", b"hello").is_ok());
+        assert!(verify(&sums, "DOCS/Hello.TXT", b"hello").is_ok());
+        assert!(verify(&sums, "empty", b"").is_ok());
+        let mismatch = verify(&sums, "docs/hello.txt", b"jello").unwrap_err();
+        assert_eq!(mismatch.algorithm, HashAlgorithm::Md5);
+        assert_eq!(mismatch.list, "md5sum.txt");
+        // Unlisted files pass
+        assert!(verify(&sums, "other", b"anything").is_ok());
+    }
+
+    #[test]
+    fn digests() {
+        assert_eq!(
+            HashAlgorithm::Sha1.digest(b"hello"),
+            decode_hex("aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d").unwrap()
+        );
+        assert_eq!(HashAlgorithm::Sha512.digest(b"").len(), 64);
+        assert_eq!(decode_hex("0g"), None);
+    }
+}
@@ -0,0 +1,354 @@
+//! CloneCD dumps: a `.ccd` sheet describing the sessions and tracks of the disc, an `.img` file
+//! of its raw sectors and, for copy protections that hide in it, a `.sub` file of the subchannel
+//! data of every sector. Only the data track is served; the subchannels are left alone.
+
+use crate::{
+    raw::{RAW_SECTOR, RawSectors, SectorStats},
+    source::IsoSource,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The subchannel data stored for every sector in a `.sub` file.
+const SUBCHANNEL: u64 = 96;
+
+/// The lead-out of a session in the table of contents.
+const LEAD_OUT: i64 = 0xa2;
+
+/// An [`IsoSource`] that serves the data track of a CloneCD dump, from the raw sectors of its
+/// `.img` file, as described by its `.ccd` sheet.
+///
+/// The `.sub` file of subchannel data, if the dump has one, is not read, but is reported by
+/// [`info`](Self::info), with the tracks of the sheet:
+///
+/// ```no_run
+/// use unftp_sbe_iso::{CloneCd, Storage};
+///
+/// let dump = CloneCd::open("/srv/dumps/game.ccd")?.verify(true);
+/// if let Some(subchannel) = &dump.info().subchannel {
+///     println!("{} sectors of subchannel data", subchannel.sectors);
+/// }
+/// let storage = Storage::from_source(dump);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// The volume of the data track is expected to count its sectors from the start of the track,
+/// as the volume of the first track of a disc does.
+pub struct CloneCd {
+    sectors: RawSectors<File>,
+    info: CloneCdInfo,
+}
+
+/// What the sheet of a CloneCD dump describes, and which of its files are there, as returned by
+/// [`CloneCd::info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloneCdInfo {
+    /// The tracks of the disc, ordered by number
+    pub tracks: Vec<CcdTrack>,
+    /// The number of the data track served, the first one
+    pub data_track: u8,
+    /// The file of raw sectors
+    pub image: PathBuf,
+    /// The file of subchannel data, if the dump has one
+    pub subchannel: Option<Subchannel>,
+}
+
+/// A track of a CloneCD dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CcdTrack {
+    /// The number of the track
+    pub number: u8,
+    /// The number of the session the track belongs to
+    pub session: u8,
+    /// The mode of the sectors of the track: 0 for audio, 1 or 2 for data
+    pub mode: u8,
+    /// Whether the table of contents marks the track as data
+    pub data: bool,
+    /// The first sector of the track
+    pub start: u64,
+    /// The number of sectors of the track, if the sheet tells where it ends
+    pub sectors: Option<u64>,
+}
+
+/// The `.sub` file of a CloneCD dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subchannel {
+    /// Where the file is
+    pub path: PathBuf,
+    /// The number of sectors the file holds the subchannel data of
+    pub sectors: u64,
+    /// Whether the file covers every sector of the `.img` file. Dumps cut short or padded
+    /// don't.
+    pub complete: bool,
+}
+
+impl CloneCd {
+    /// Opens the dump whose sheet is at `path`, with its `.img` and `.sub` files next to it,
+    /// named after it. Naming the `.img` file instead finds the sheet the same way.
+    ///
+    /// Fails if the sheet or the `.img` file can't be read, or if the sheet names no data
+    /// track. A missing or unreadable `.sub` file is only left out of the [`info`](Self::info).
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let sheet = path.as_ref().with_extension("ccd");
+        let tracks = parse(&std::fs::read_to_string(&sheet)?)?;
+        let data = tracks
+            .iter()
+            .find(|track| track.data && track.mode != 0)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the CloneCD sheet names no data track",
+                )
+            })?;
+        let image = sheet.with_extension("img");
+        let file = File::open(&image)?;
+        let sectors = file.metadata()?.len() / RAW_SECTOR as u64;
+        let subchannel_path = sheet.with_extension("sub");
+        let subchannel = std::fs::metadata(&subchannel_path)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| Subchannel {
+                path: subchannel_path,
+                sectors: metadata.len() / SUBCHANNEL,
+                complete: metadata.len() == sectors * SUBCHANNEL,
+            });
+        Ok(Self {
+            sectors: RawSectors::new(file).track(data.start, data.sectors),
+            info: CloneCdInfo {
+                data_track: data.number,
+                tracks,
+                image,
+                subchannel,
+            },
+        })
+    }
+
+    /// Verifies the EDC and ECC of every sector read, as [`RawSectors::verify`] does. Off by
+    /// default.
+    pub fn verify(mut self, enabled: bool) -> Self {
+        self.sectors = self.sectors.verify(enabled);
+        self
+    }
+
+    /// The tracks and files of the dump.
+    pub fn info(&self) -> &CloneCdInfo {
+        &self.info
+    }
+
+    /// The sectors checked so far, as [`RawSectors::stats`] counts them.
+    pub fn stats(&self) -> SectorStats {
+        self.sectors.stats()
+    }
+}
+
+impl IsoSource for CloneCd {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.sectors.read_at(offset, buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        self.sectors.len()
+    }
+}
+
+impl fmt::Debug for CloneCd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CloneCd")
+            .field("info", &self.info)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reads the tracks of a `.ccd` sheet, an INI file with a `[TRACK n]` section for every track
+/// and an `[Entry n]` section for every entry of the table of contents.
+fn parse(sheet: &str) -> io::Result<Vec<CcdTrack>> {
+    let mut sections: Vec<(String, HashMap<String, String>)> = Vec::new();
+    for line in sheet.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((name.trim().to_ascii_uppercase(), HashMap::new()));
+        } else if let (Some((key, value)), Some((_, keys))) =
+            (line.split_once('='), sections.last_mut())
+        {
+            keys.insert(key.trim().to_ascii_uppercase(), value.trim().to_string());
+        }
+    }
+    let number = |keys: &HashMap<String, String>, key: &str| keys.get(key).and_then(|v| integer(v));
+
+    // The entries whose point is a track number describe that track, those of the lead-out
+    // where its session ends
+    let mut entries = HashMap::new();
+    let mut lead_outs = HashMap::new();
+    for (_, keys) in sections
+        .iter()
+        .filter(|(name, _)| name.starts_with("ENTRY "))
+    {
+        let (Some(point), Some(session)) = (number(keys, "POINT"), number(keys, "SESSION")) else {
+            continue;
+        };
+        let lba = number(keys, "PLBA");
+        match point {
+            1..=99 => {
+                entries.insert(point, (session, number(keys, "CONTROL"), lba));
+            }
+            LEAD_OUT => {
+                if let Some(lba) = lba {
+                    lead_outs.insert(session, lba);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut tracks = Vec::new();
+    for (name, keys) in &sections {
+        let Some(track) = name
+            .strip_prefix("TRACK ")
+            .and_then(integer)
+            .filter(|n| (1..=99).contains(n))
+        else {
+            continue;
+        };
+        let entry = entries.get(&track);
+        let mode = number(keys, "MODE").unwrap_or(0);
+        // Where the track starts is its index 1, after the pregap of index 0
+        let Some(start) = number(keys, "INDEX 1")
+            .or_else(|| entry.and_then(|&(_, _, lba)| lba))
+            .filter(|&start| start >= 0)
+        else {
+            continue;
+        };
+        tracks.push(CcdTrack {
+            number: track as u8,
+            session: entry.map_or(1, |&(session, _, _)| session as u8),
+            mode: mode as u8,
+            data: entry
+                .and_then(|&(_, control, _)| control)
+                .map_or(mode != 0, |control| control & 0x04 != 0),
+            start: start as u64,
+            sectors: None,
+        });
+    }
+    tracks.sort_by_key(|track| track.number);
+    for i in 0..tracks.len() {
+        let next = tracks
+            .get(i + 1)
+            .filter(|next| next.session == tracks[i].session)
+            .map(|next| next.start);
+        let lead_out = lead_outs
+            .get(&i64::from(tracks[i].session))
+            .and_then(|&lba| u64::try_from(lba).ok());
+        tracks[i].sectors = next
+            .or(lead_out)
+            .map(|end| end.saturating_sub(tracks[i].start));
+    }
+    if tracks.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the CloneCD sheet has no tracks",
+        ));
+    }
+    Ok(tracks)
+}
+
+/// Reads a number of a sheet, written in decimal or, with a `0x` prefix, in hexadecimal.
+fn integer(value: &str) -> Option<i64> {
+    let value = value.trim();
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sheet CloneCD writes for a disc with a data track followed by an audio track.
+    const SHEET: &str = "\
+[CloneCD]
+Version=3
+[Disc]
+TocEntries=5
+Sessions=1
+DataTracksScrambled=0
+CDTextLength=0
+[Session 1]
+PreGapMode=1
+PreGapSubC=0
+[Entry 0]
+Session=1
+Point=0xa0
+ADR=0x01
+Control=0x04
+PMin=1
+PSec=0
+PFrame=0
+PLBA=4350
+[Entry 2]
+Session=1
+Point=0xa2
+ADR=0x01
+Control=0x04
+PLBA=1200
+[Entry 3]
+Session=1
+Point=0x01
+ADR=0x01
+Control=0x04
+PLBA=0
+[Entry 4]
+Session=1
+Point=0x02
+ADR=0x01
+Control=0x00
+PLBA=900
+[TRACK 1]
+MODE=1
+INDEX 1=0
+[TRACK 2]
+MODE=0
+INDEX 0=750
+INDEX 1=900
+";
+
+    #[test]
+    fn sheet() {
+        let tracks = parse(SHEET).unwrap();
+        assert_eq!(
+            tracks,
+            [
+                CcdTrack {
+                    number: 1,
+                    session: 1,
+                    mode: 1,
+                    data: true,
+                    start: 0,
+                    sectors: Some(900),
+                },
+                CcdTrack {
+                    number: 2,
+                    session: 1,
+                    mode: 0,
+                    data: false,
+                    start: 900,
+                    sectors: Some(300),
+                },
+            ]
+        );
+        // Without a table of contents, the modes tell the data tracks
+        let tracks = parse("[TRACK 1]\nMODE=2\nINDEX 1=0\n").unwrap();
+        assert!(tracks[0].data);
+        assert_eq!(tracks[0].sectors, None);
+        assert!(parse("[CloneCD]\nVersion=3\n").is_err());
+        assert_eq!(integer("0x1F"), Some(31));
+        assert_eq!(integer("-150"), Some(-150));
+    }
+}
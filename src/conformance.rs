@@ -0,0 +1,284 @@
+//! How closely the primary hierarchy of an image keeps to ISO 9660, as reported by
+//! [`Storage::conformance`](crate::Storage::conformance).
+
+use crate::{
+    descriptor::{self, DescriptorKind},
+    source::IsoSource,
+    susp::{self, Records, le32},
+};
+use std::{cmp::Ordering, collections::HashSet, fmt, io, path::PathBuf};
+
+/// The deepest a directory may be, counting the root as the first level.
+const MAX_DEPTH: u32 = 8;
+
+/// The longest a path may be, in bytes of its identifiers and separators.
+const MAX_PATH: usize = 255;
+
+/// The longest identifiers of levels 2 and 3: 30 bytes of name and extension for files, 31 for
+/// directories.
+const MAX_FILE_IDENTIFIER: usize = 30;
+const MAX_DIRECTORY_IDENTIFIER: usize = 31;
+
+/// What [`Storage::conformance`](crate::Storage::conformance) found.
+///
+/// A mastered image that is to be published should have no [`violations`](Self::violations).
+/// Many tools bend the rules on purpose, with lowercase or long names, and readers cope; the
+/// report tells what a strict reader would refuse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceReport {
+    /// The lowest interchange level of ISO 9660 the primary hierarchy keeps to, counting
+    /// violations out: 1 for 8.3 names, 2 for names of up to 30 characters, 3 for files in
+    /// several extents
+    pub interchange_level: u8,
+    /// The level of the Joliet volume, if there is one
+    pub joliet: Option<u8>,
+    /// Whether the primary hierarchy has Rock Ridge entries
+    pub rock_ridge: bool,
+    /// The depth of the deepest directory, counting the root as 1
+    pub max_depth: u32,
+    /// The length of the longest path, in bytes of its identifiers and separators
+    pub max_path_len: usize,
+    /// The rules of ISO 9660 broken, in the order found
+    pub violations: Vec<Violation>,
+}
+
+impl ConformanceReport {
+    /// Tells whether no rule is broken.
+    pub fn conforms(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// A rule of ISO 9660 broken by an entry of the primary hierarchy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The path of the entry, in its ISO 9660 identifiers
+    pub path: PathBuf,
+    /// The rule broken
+    pub kind: ViolationKind,
+}
+
+/// The kinds of [`Violation`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The identifier has characters other than the uppercase letters, digits and `_` of
+    /// d-characters, besides the separators of a file identifier
+    Characters,
+    /// The identifier is longer than any interchange level allows
+    IdentifierLength(usize),
+    /// The identifier of a file has no `;` version
+    NoVersion,
+    /// The directory is deeper than the 8 levels allowed
+    Depth(u32),
+    /// The path is longer than the 255 bytes allowed
+    PathLength(usize),
+    /// The record comes before the one it follows in the order of identifiers
+    Unsorted,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.kind)
+    }
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViolationKind::Characters => write!(f, "identifier with other than d-characters"),
+            ViolationKind::IdentifierLength(len) => {
+                write!(
+                    f,
+                    "identifier of {len} characters is too long for any level"
+                )
+            }
+            ViolationKind::NoVersion => write!(f, "file identifier without a version"),
+            ViolationKind::Depth(depth) => {
+                write!(f, "directory at depth {depth}, deeper than {MAX_DEPTH}")
+            }
+            ViolationKind::PathLength(len) => {
+                write!(f, "path of {len} bytes, longer than {MAX_PATH}")
+            }
+            ViolationKind::Unsorted => write!(f, "record out of the order of identifiers"),
+        }
+    }
+}
+
+/// Checks the primary hierarchy of the image in `source`.
+pub(crate) fn check(source: &dyn IsoSource) -> io::Result<ConformanceReport> {
+    let descriptors = descriptor::read(source)?;
+    let mut joliet = None;
+    let mut primary = None;
+    for descriptor in &descriptors {
+        match &descriptor.kind {
+            DescriptorKind::Primary(_) if primary.is_none() => primary = Some(&descriptor.raw),
+            DescriptorKind::Supplementary(info) => joliet = joliet.or(info.joliet_level()),
+            _ => {}
+        }
+    }
+    let root = &primary
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no primary volume"))?[156..190];
+    let extensions = susp::inventory(source)?;
+    let mut report = ConformanceReport {
+        interchange_level: 1,
+        joliet,
+        rock_ridge: extensions.declares_rock_ridge() || extensions.contains("PX"),
+        max_depth: 1,
+        max_path_len: 0,
+        violations: Vec::new(),
+    };
+    let len = source.len()?;
+    let mut directories = vec![(le32(root, 2), le32(root, 10), PathBuf::from("/"), 1)];
+    let mut visited = HashSet::new();
+    while let Some((extent, size, path, depth)) = directories.pop() {
+        if !visited.insert(extent) {
+            continue;
+        }
+        let raw = susp::directory(source, len, extent, size)?;
+        let mut previous: Option<Vec<u8>> = None;
+        for record in Records::new(&raw) {
+            let identifier = &record[33..33 + usize::from(record[32])];
+            if identifier == [0] || identifier == [1] {
+                continue;
+            }
+            let directory = record[25] & 0x02 != 0;
+            let child = path.join(String::from_utf8_lossy(identifier).as_ref());
+            let mut violation = |kind| {
+                report.violations.push(Violation {
+                    path: child.clone(),
+                    kind,
+                })
+            };
+            if previous
+                .as_deref()
+                .is_some_and(|previous| order(previous, identifier) == Ordering::Greater)
+            {
+                violation(ViolationKind::Unsorted);
+            }
+            previous = Some(identifier.to_vec());
+            let level = identifier_level(identifier, directory, &mut violation);
+            // A file recorded in several extents has records flagged for all but the last
+            let level = if record[25] & 0x80 != 0 { 3 } else { level };
+            report.interchange_level = report.interchange_level.max(level);
+            let path_len = child.as_os_str().len() - 1;
+            report.max_path_len = report.max_path_len.max(path_len);
+            if path_len > MAX_PATH {
+                violation(ViolationKind::PathLength(path_len));
+            }
+            if directory {
+                let depth = depth + 1;
+                report.max_depth = report.max_depth.max(depth);
+                if depth > MAX_DEPTH {
+                    violation(ViolationKind::Depth(depth));
+                }
+                directories.push((le32(record, 2), le32(record, 10), child, depth));
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// The lowest interchange level `identifier` keeps to, reporting what breaks every level.
+fn identifier_level(
+    identifier: &[u8],
+    directory: bool,
+    violation: &mut impl FnMut(ViolationKind),
+) -> u8 {
+    let d_character = |&c: &u8| c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'_';
+    if directory {
+        if !identifier.iter().all(d_character) {
+            violation(ViolationKind::Characters);
+        }
+        return match identifier.len() {
+            0..=8 => 1,
+            9..=MAX_DIRECTORY_IDENTIFIER => 2,
+            len => {
+                violation(ViolationKind::IdentifierLength(len));
+                2
+            }
+        };
+    }
+    let (name, version) = match identifier.iter().rposition(|&c| c == b';') {
+        Some(at) => (&identifier[..at], Some(&identifier[at + 1..])),
+        None => (identifier, None),
+    };
+    let (stem, extension) = match name.iter().position(|&c| c == b'.') {
+        Some(at) => (&name[..at], &name[at + 1..]),
+        None => (name, &[][..]),
+    };
+    let version_ok = version.is_none_or(|v| !v.is_empty() && v.iter().all(u8::is_ascii_digit));
+    if !stem.iter().chain(extension).all(d_character) || !version_ok {
+        violation(ViolationKind::Characters);
+    }
+    if version.is_none() {
+        violation(ViolationKind::NoVersion);
+    }
+    match stem.len() + extension.len() {
+        _ if stem.len() <= 8 && extension.len() <= 3 => 1,
+        ..=MAX_FILE_IDENTIFIER => 2,
+        len => {
+            violation(ViolationKind::IdentifierLength(len));
+            2
+        }
+    }
+}
+
+/// The order of identifiers in a directory: by name, then by extension, each padded with
+/// spaces, then by version, the highest first.
+fn order(a: &[u8], b: &[u8]) -> Ordering {
+    let split = |identifier: &[u8]| -> (Vec<u8>, Vec<u8>, u32) {
+        let (name, version) = match identifier.iter().rposition(|&c| c == b';') {
+            Some(at) => (&identifier[..at], &identifier[at + 1..]),
+            None => (identifier, &[][..]),
+        };
+        let (stem, extension) = match name.iter().position(|&c| c == b'.') {
+            Some(at) => (&name[..at], &name[at + 1..]),
+            None => (name, &[][..]),
+        };
+        let version = std::str::from_utf8(version)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        (stem.to_vec(), extension.to_vec(), version)
+    };
+    let padded = |a: &[u8], b: &[u8]| {
+        let len = a.len().max(b.len());
+        let pad = |s: &[u8]| {
+            let mut s = s.to_vec();
+            s.resize(len, b' ');
+            s
+        };
+        pad(a).cmp(&pad(b))
+    };
+    let (a, b) = (split(a), split(b));
+    padded(&a.0, &b.0)
+        .then_with(|| padded(&a.1, &b.1))
+        .then(b.2.cmp(&a.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        let mut violations = Vec::new();
+        let mut level = |identifier: &str, directory| {
+            identifier_level(identifier.as_bytes(), directory, &mut |kind| {
+                violations.push(kind)
+            })
+        };
+        assert_eq!(level("README.TXT;1", false), 1);
+        assert_eq!(level("INSTALL", true), 1);
+        assert_eq!(level("RELEASE_NOTES.HTML;1", false), 2);
+        assert_eq!(level("DOCUMENTATION", true), 2);
+        assert_eq!(level("readme.txt", false), 1);
+        assert_eq!(
+            violations,
+            [ViolationKind::Characters, ViolationKind::NoVersion]
+        );
+        assert_eq!(order(b"A;1", b"A.TXT;1"), Ordering::Less);
+        assert_eq!(order(b"AB;1", b"A.TXT;1"), Ordering::Greater);
+        assert_eq!(order(b"A;2", b"A;1"), Ordering::Less);
+    }
+}
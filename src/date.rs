@@ -0,0 +1,121 @@
+//! Calendar dates in UTC, which listings show times in, and the times images record as text.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The year, month and day of `time` in UTC.
+pub(crate) fn civil(time: SystemTime) -> (i64, u32, u32) {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        // A time part of a second before a whole one falls in the second before
+        Err(before) => {
+            let before = before.duration();
+            -(before.as_secs() as i64) - i64::from(before.subsec_nanos() > 0)
+        }
+    };
+    // Howard Hinnant's civil_from_days
+    let days = secs.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The days from 1970-01-01 to the given day.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Howard Hinnant's days_from_civil
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let day_of_year = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Decodes a date and time as volume descriptors record them: 16 digits for the local time down
+/// to hundredths of a second, then its offset from UTC in 15 minute steps. `None` when it is
+/// all zeros, which means it wasn't recorded, or not a date at all.
+pub(crate) fn parse_dec_datetime(raw: &[u8; 17]) -> Option<SystemTime> {
+    let digits = &raw[..16];
+    if !digits.iter().all(u8::is_ascii_digit) || digits.iter().all(|&d| d == b'0') {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| {
+        digits[range]
+            .iter()
+            .fold(0, |n, d| n * 10 + u32::from(d - b'0'))
+    };
+    let (month, day) = (number(4..6), number(6..8));
+    let (hour, minute, second) = (number(8..10), number(10..12), number(12..14));
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    let days = days_from_civil(i64::from(number(0..4)), month, day);
+    let offset = i64::from(raw[16] as i8) * 15 * 60;
+    let local = days * 86_400 + i64::from(hour * 3600 + minute * 60 + second.min(59));
+    let secs = local - offset;
+    let hundredths = Duration::from_millis(u64::from(number(14..16)) * 10);
+    let time = match u64::try_from(secs) {
+        Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+        Err(_) => UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()),
+    };
+    Some(time + hundredths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> SystemTime {
+        if secs >= 0 {
+            UNIX_EPOCH + Duration::from_secs(secs as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(-secs as u64)
+        }
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil(at(0)), (1970, 1, 1));
+        assert_eq!(civil(at(-1)), (1969, 12, 31));
+        assert_eq!(civil(at(-86_400)), (1969, 12, 31));
+        assert_eq!(civil(UNIX_EPOCH - Duration::from_millis(1)), (1969, 12, 31));
+        // 2000-02-29T23:59:59Z and the second after it
+        assert_eq!(civil(at(951_868_799)), (2000, 2, 29));
+        assert_eq!(civil(at(951_868_800)), (2000, 3, 1));
+        // 2022-04-21T12:00:00Z
+        assert_eq!(civil(at(1_650_542_400)), (2022, 4, 21));
+        // 2023-12-31T23:59:59Z
+        assert_eq!(civil(at(1_704_067_199)), (2023, 12, 31));
+        for secs in [-86_400 * 1000, 0, 951_868_800, 1_704_067_199] {
+            let (year, month, day) = civil(at(secs));
+            assert_eq!(days_from_civil(year, month, day), secs.div_euclid(86_400));
+        }
+    }
+
+    #[test]
+    fn dec_datetimes() {
+        let parse = |digits: &[u8; 16], offset: i8| {
+            let mut raw = [0; 17];
+            raw[..16].copy_from_slice(digits);
+            raw[16] = offset as u8;
+            parse_dec_datetime(&raw)
+        };
+        assert_eq!(parse(b"2022042112000000", 0), Some(at(1_650_542_400)));
+        // 14:00 two hours east of UTC is noon in UTC
+        assert_eq!(parse(b"2022042114000000", 8), Some(at(1_650_542_400)));
+        assert_eq!(
+            parse(b"2022042112000050", 0),
+            Some(at(1_650_542_400) + Duration::from_millis(500))
+        );
+        assert_eq!(parse(b"0000000000000000", 0), None);
+        assert_eq!(parse(b"2022130112000000", 0), None);
+        assert_eq!(parse(b"        00000000", 0), None);
+    }
+}
@@ -0,0 +1,322 @@
+//! The volume descriptors at the start of an image, which announce the namespaces it offers.
+
+use crate::{date, source::IsoSource};
+use std::{io, time::SystemTime};
+
+/// The size of a logical sector, and of every volume descriptor.
+pub(crate) const SECTOR: usize = 2048;
+
+/// The sector of the first volume descriptor. The sectors before it are the system area.
+pub(crate) const FIRST_SECTOR: u64 = 16;
+
+/// How many descriptors are read at most before giving up on finding the terminator.
+pub(crate) const MAX_DESCRIPTORS: u64 = 64;
+
+/// A volume descriptor of the image, as returned by
+/// [`Storage::volume_descriptors`](crate::Storage::volume_descriptors).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeDescriptor {
+    /// The sector the descriptor was read from
+    pub sector: u64,
+    /// The version of the descriptor, 1 for most
+    pub version: u8,
+    /// What the descriptor describes, with the fields that tell descriptors of a kind apart
+    pub kind: DescriptorKind,
+    /// All 2048 bytes of the descriptor, for fields not decoded here
+    pub raw: Vec<u8>,
+}
+
+/// The kinds of volume descriptors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DescriptorKind {
+    /// A boot record, like the one of El Torito bootable images
+    BootRecord {
+        /// The system that can boot the image, `EL TORITO SPECIFICATION` for El Torito
+        boot_system_id: String,
+        /// An identifier of the boot system's choosing
+        boot_id: String,
+    },
+    /// The primary volume, with the plain ISO 9660 namespace
+    Primary(VolumeInfo),
+    /// A supplementary or enhanced volume, like the Joliet namespace
+    Supplementary(VolumeInfo),
+    /// A volume partition
+    Partition {
+        /// The system that can use the partition
+        system_id: String,
+        /// The identifier of the partition
+        partition_id: String,
+        /// The first sector of the partition
+        location: u32,
+        /// The size of the partition in sectors
+        size: u32,
+    },
+    /// A type this crate doesn't know
+    Unknown(u8),
+}
+
+/// The fields of a primary or supplementary volume descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeInfo {
+    /// The system that can use the first 16 sectors of the image
+    pub system_id: String,
+    /// The name of the volume
+    pub volume_id: String,
+    /// The name of the set of volumes this volume belongs to
+    pub volume_set_id: String,
+    /// Who published the volume
+    pub publisher_id: String,
+    /// Who prepared the data of the volume
+    pub preparer_id: String,
+    /// The application the volume was recorded with
+    pub application_id: String,
+    /// The size of the volume in logical blocks
+    pub volume_space_size: u32,
+    /// The size of a logical block in bytes
+    pub logical_block_size: u16,
+    /// The first logical block of the root directory
+    pub root_extent: u32,
+    /// When the volume was created, if that is recorded
+    pub created: Option<SystemTime>,
+    /// The escape sequences naming the character set of a supplementary volume
+    pub escape_sequences: [u8; 32],
+}
+
+impl VolumeInfo {
+    /// The Joliet level announced by the escape sequences, if the volume is a Joliet volume.
+    pub fn joliet_level(&self) -> Option<u8> {
+        joliet_level(&self.escape_sequences)
+    }
+
+    /// A greeting for FTP clients naming the volume, like `Serving 'UBUNTU_22_04' (created
+    /// 2022-04-21, 3.6 GB)`. `name` is the volume identifier to show.
+    pub(crate) fn greeting(&self, name: &str) -> String {
+        let size = u64::from(self.volume_space_size) * u64::from(self.logical_block_size);
+        let size = decimal_size(size);
+        let name = match name {
+            "" => "an unnamed volume".to_string(),
+            name => format!("'{name}'"),
+        };
+        match self.created {
+            Some(created) => {
+                let (year, month, day) = date::civil(created);
+                format!("Serving {name} (created {year:04}-{month:02}-{day:02}, {size})")
+            }
+            None => format!("Serving {name} ({size})"),
+        }
+    }
+}
+
+/// Formats `bytes` with one decimal in the largest decimal unit it reaches, like `3.6 GB`.
+fn decimal_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{bytes} bytes");
+    }
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 999.95 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+fn joliet_level(escapes: &[u8]) -> Option<u8> {
+    match escapes.get(..3)? {
+        b"%/@" => Some(1),
+        b"%/C" => Some(2),
+        b"%/E" => Some(3),
+        _ => None,
+    }
+}
+
+/// Reads the descriptors from sector 16 up to and excluding the set terminator.
+pub(crate) fn read(source: &dyn IsoSource) -> io::Result<Vec<VolumeDescriptor>> {
+    let mut descriptors = Vec::new();
+    for sector in FIRST_SECTOR..FIRST_SECTOR + MAX_DESCRIPTORS {
+        let mut raw = vec![0; SECTOR];
+        read_exact_at(source, sector * SECTOR as u64, &mut raw)?;
+        if &raw[1..6] != b"CD001" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("sector {sector} holds no volume descriptor"),
+            ));
+        }
+        if raw[0] == 255 {
+            return Ok(descriptors);
+        }
+        descriptors.push(parse(sector, raw));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("no volume descriptor set terminator in the first {MAX_DESCRIPTORS} descriptors"),
+    ))
+}
+
+pub(crate) fn read_exact_at(source: &dyn IsoSource, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    let mut read = 0;
+    while read < buf.len() {
+        match source.read_at(offset + read as u64, &mut buf[read..]) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+fn parse(sector: u64, raw: Vec<u8>) -> VolumeDescriptor {
+    let ascii = |range: std::ops::Range<usize>| text(&raw[range], false);
+    let le32 = |at: usize| u32::from_le_bytes(raw[at..at + 4].try_into().unwrap());
+    let kind = match raw[0] {
+        0 => DescriptorKind::BootRecord {
+            boot_system_id: ascii(7..39),
+            boot_id: ascii(39..71),
+        },
+        kind @ (1 | 2) => {
+            let mut escape_sequences = [0; 32];
+            escape_sequences.copy_from_slice(&raw[88..120]);
+            let ucs2 = kind == 2 && joliet_level(&escape_sequences).is_some();
+            let field = |range: std::ops::Range<usize>| text(&raw[range], ucs2);
+            let info = VolumeInfo {
+                system_id: field(8..40),
+                volume_id: field(40..72),
+                volume_set_id: field(190..318),
+                publisher_id: field(318..446),
+                preparer_id: field(446..574),
+                application_id: field(574..702),
+                volume_space_size: le32(80),
+                logical_block_size: u16::from_le_bytes([raw[128], raw[129]]),
+                // The root directory record starts at 156, its extent 2 bytes in
+                root_extent: le32(158),
+                created: date::parse_dec_datetime(raw[813..830].try_into().unwrap()),
+                escape_sequences,
+            };
+            if kind == 1 {
+                DescriptorKind::Primary(info)
+            } else {
+                DescriptorKind::Supplementary(info)
+            }
+        }
+        3 => DescriptorKind::Partition {
+            system_id: ascii(8..40),
+            partition_id: ascii(40..72),
+            location: le32(72),
+            size: le32(80),
+        },
+        other => DescriptorKind::Unknown(other),
+    };
+    VolumeDescriptor {
+        sector,
+        version: raw[6],
+        kind,
+        raw,
+    }
+}
+
+/// Decodes a padded text field, as UCS-2 for Joliet volumes.
+fn text(bytes: &[u8], ucs2: bool) -> String {
+    let text = if ucs2 {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    };
+    text.trim_end_matches([' ', '\0']).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(descriptors: &[[u8; SECTOR]]) -> Vec<u8> {
+        let mut image = vec![0; 16 * SECTOR];
+        for d in descriptors {
+            image.extend_from_slice(d);
+        }
+        image
+    }
+
+    fn descriptor(kind: u8) -> [u8; SECTOR] {
+        let mut d = [0; SECTOR];
+        d[0] = kind;
+        d[1..6].copy_from_slice(b"CD001");
+        d[6] = 1;
+        d
+    }
+
+    #[test]
+    fn greetings() {
+        assert_eq!(decimal_size(999), "999 bytes");
+        assert_eq!(decimal_size(1000), "1.0 kB");
+        assert_eq!(decimal_size(3_654_957_056), "3.7 GB");
+        assert_eq!(decimal_size(999_999_999), "1.0 GB");
+        assert_eq!(decimal_size(u64::MAX), "18446744.1 TB");
+
+        let mut primary = descriptor(1);
+        primary[40..52].copy_from_slice(b"UBUNTU_22_04");
+        primary[80..84].copy_from_slice(&1_784_647u32.to_le_bytes());
+        primary[128..130].copy_from_slice(&2048u16.to_le_bytes());
+        primary[813..829].copy_from_slice(b"2022042110260100");
+        let descriptors = read(&image(&[primary, descriptor(255)])).unwrap();
+        let DescriptorKind::Primary(info) = &descriptors[0].kind else {
+            panic!("no primary volume");
+        };
+        assert_eq!(
+            info.greeting(&info.volume_id),
+            "Serving 'UBUNTU_22_04' (created 2022-04-21, 3.7 GB)"
+        );
+        let info = VolumeInfo {
+            created: None,
+            ..info.clone()
+        };
+        assert_eq!(info.greeting(""), "Serving an unnamed volume (3.7 GB)");
+    }
+
+    #[test]
+    fn boot_record_and_partition() {
+        let mut boot = descriptor(0);
+        boot[7..30].copy_from_slice(b"EL TORITO SPECIFICATION");
+        let mut partition = descriptor(3);
+        partition[8..13].copy_from_slice(b"LINUX");
+        partition[40..44].copy_from_slice(b"SWAP");
+        partition[72..76].copy_from_slice(&100u32.to_le_bytes());
+        partition[80..84].copy_from_slice(&50u32.to_le_bytes());
+        let image = image(&[boot, partition, descriptor(9), descriptor(255)]);
+
+        let descriptors = read(&image).unwrap();
+        assert_eq!(descriptors.len(), 3);
+        assert_eq!(
+            descriptors[0].kind,
+            DescriptorKind::BootRecord {
+                boot_system_id: "EL TORITO SPECIFICATION".into(),
+                boot_id: String::new(),
+            }
+        );
+        assert_eq!(
+            descriptors[1].kind,
+            DescriptorKind::Partition {
+                system_id: "LINUX".into(),
+                partition_id: "SWAP".into(),
+                location: 100,
+                size: 50,
+            }
+        );
+        assert_eq!(descriptors[2].kind, DescriptorKind::Unknown(9));
+        assert_eq!(descriptors[2].sector, 18);
+    }
+
+    #[test]
+    fn missing_terminator() {
+        let image = image(&[descriptor(1)]);
+        assert!(read(&image).is_err());
+        let mut image = image;
+        image.resize(image.len() + SECTOR, 0);
+        assert!(read(&image).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! Comparing the trees of two images, as done by [`diff`].
+
+#[cfg(feature = "checksums")]
+use crate::HashAlgorithm;
+use crate::{IsoMeta, Storage, date};
+use std::{collections::BTreeMap, fmt, path::PathBuf, time::SystemTime};
+use unftp_core::storage::Result;
+
+/// What [`diff`] found between two images. Paths are relative to the roots of the images, like
+/// `install/vmlinuz`, and sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffReport {
+    /// The entries only the new image holds
+    pub added: Vec<PathBuf>,
+    /// The entries only the old image holds
+    pub removed: Vec<PathBuf>,
+    /// The entries both images hold that differ
+    pub changed: Vec<Change>,
+}
+
+impl DiffReport {
+    /// Tells whether the images hold the same tree.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// An entry that differs between two images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// The path of the entry
+    pub path: PathBuf,
+    /// How it differs, in the order of [`ChangeKind`]
+    pub kinds: Vec<ChangeKind>,
+}
+
+/// How an entry differs between two images.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// A file, directory or symbolic link became another of them, and nothing else is compared
+    Type,
+    /// The file has another size
+    Size {
+        /// The size in the old image
+        old: u64,
+        /// The size in the new image
+        new: u64,
+    },
+    /// The file was modified at another time
+    Modified {
+        /// When it was modified according to the old image
+        old: SystemTime,
+        /// When it was modified according to the new image
+        new: SystemTime,
+    },
+    /// The file has the same size but other contents, as found by `diff_with_hash`
+    Contents,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.path.display())?;
+        for (i, kind) in self.kinds.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{separator}{kind}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeKind::Type => write!(f, "type changed"),
+            ChangeKind::Size { old, new } => write!(f, "size {old} -> {new}"),
+            ChangeKind::Modified { old, new } => {
+                write!(f, "modified {} -> {}", stamp(*old), stamp(*new))
+            }
+            ChangeKind::Contents => write!(f, "contents changed"),
+        }
+    }
+}
+
+/// Formats `time` in UTC like `2024-01-02 03:04:05`.
+fn stamp(time: SystemTime) -> String {
+    let (year, month, day) = date::civil(time);
+    let secs = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64) - 1,
+    };
+    let of_day = secs.rem_euclid(86_400);
+    format!(
+        "{year}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60
+    )
+}
+
+/// What an entry is, as far as comparing goes.
+fn kind(meta: &IsoMeta) -> u8 {
+    match (meta.dir, meta.sym) {
+        (true, _) => 0,
+        (false, true) => 1,
+        (false, false) => 2,
+    }
+}
+
+/// The entries of a tree by path, relative to its root.
+pub(crate) type Tree = BTreeMap<PathBuf, IsoMeta>;
+
+/// The entries of the tree of `storage`.
+async fn tree(storage: &Storage) -> Result<Tree> {
+    Ok(storage
+        .list_recursive("/")
+        .await?
+        .into_iter()
+        .map(|info| (info.path, info.metadata))
+        .collect())
+}
+
+/// Compares the trees of `old` and `new`, for release engineers checking what changed between
+/// two builds of an image. Files differ by size and modification time; directories and
+/// symbolic links only when one became the other. The trees are walked like
+/// [`Storage::list_recursive`], under the names clients see and with the [`Filter`](crate::Filter)
+/// of each storage applied, and fail it past its walk limits.
+///
+/// ```no_run
+/// use unftp_sbe_iso::{Storage, diff};
+///
+/// # async fn changes() -> Result<(), Box<dyn std::error::Error>> {
+/// let old = Storage::new("/srv/images/installer-1.0.iso");
+/// let new = Storage::new("/srv/images/installer-1.1.iso");
+/// let report = diff(&old, &new).await?;
+/// for path in &report.added {
+///     println!("+ {}", path.display());
+/// }
+/// for change in &report.changed {
+///     println!("~ {change}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn diff(old: &Storage, new: &Storage) -> Result<DiffReport> {
+    Ok(compare(old, new).await?.0)
+}
+
+/// Like [`diff`], but also compares the digests of files of the same size, computed with
+/// `algorithm` by [`Storage::hash`], to tell rebuilt files that kept their size apart from
+/// unchanged ones. That reads every such file of both images, once: digests are kept until an
+/// image changes.
+#[cfg(feature = "checksums")]
+pub async fn diff_with_hash(
+    old: &Storage,
+    new: &Storage,
+    algorithm: HashAlgorithm,
+) -> Result<DiffReport> {
+    let (mut report, same_size) = compare(old, new).await?;
+    for path in same_size {
+        let absolute = std::path::Path::new("/").join(&path);
+        if old.hash(&absolute, algorithm).await? == new.hash(&absolute, algorithm).await? {
+            continue;
+        }
+        match report
+            .changed
+            .binary_search_by(|change| change.path.cmp(&path))
+        {
+            Ok(at) => report.changed[at].kinds.push(ChangeKind::Contents),
+            Err(at) => report.changed.insert(
+                at,
+                Change {
+                    path,
+                    kinds: vec![ChangeKind::Contents],
+                },
+            ),
+        }
+    }
+    Ok(report)
+}
+
+/// Compares the trees of `old` and `new`, returning the files of the same size in both too.
+async fn compare(old: &Storage, new: &Storage) -> Result<(DiffReport, Vec<PathBuf>)> {
+    Ok(compare_trees(tree(old).await?, tree(new).await?))
+}
+
+/// Compares the trees `before` and `after`, returning the files of the same size in both too.
+pub(crate) fn compare_trees(before: Tree, mut after: Tree) -> (DiffReport, Vec<PathBuf>) {
+    let mut report = DiffReport::default();
+    let mut same_size = Vec::new();
+    for (path, was) in before {
+        let Some(is) = after.remove(&path) else {
+            report.removed.push(path);
+            continue;
+        };
+        let mut kinds = Vec::new();
+        if kind(&was) != kind(&is) {
+            kinds.push(ChangeKind::Type);
+        } else if !was.dir && !was.sym {
+            if was.len != is.len {
+                kinds.push(ChangeKind::Size {
+                    old: was.len,
+                    new: is.len,
+                });
+            } else {
+                same_size.push(path.clone());
+            }
+            if was.modified != is.modified {
+                kinds.push(ChangeKind::Modified {
+                    old: was.modified,
+                    new: is.modified,
+                });
+            }
+        }
+        if !kinds.is_empty() {
+            report.changed.push(Change { path, kinds });
+        }
+    }
+    report.added = after.into_keys().collect();
+    (report, same_size)
+}
@@ -0,0 +1,358 @@
+//! Keeping the blocks read from a remote image on local disk, across restarts.
+
+use crate::{AsyncIsoSource, cache::BLOCK_SIZE, pool};
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// The bytes kept on disk unless [`DiskCache::max_bytes`] says otherwise: 1 GiB.
+const DEFAULT_MAX_BYTES: u64 = 1 << 30;
+
+/// The file of the cache directory that records the size of the image the blocks are of.
+const IMAGE_FILE: &str = "image";
+
+/// The extension of the files of blocks.
+const BLOCK_EXTENSION: &str = "blk";
+
+/// The extension of the files of blocks being written, which are renamed once they are whole.
+const PARTIAL_EXTENSION: &str = "part";
+
+/// An [`AsyncIsoSource`] that keeps the blocks it reads from another one, like an image on an
+/// HTTP server or in an object store, in a directory on local disk. Regions of the image read
+/// once are served from disk from then on, after restarts too, and the least recently used
+/// blocks go once the cache holds more than [`max_bytes`](Self::max_bytes).
+///
+/// Blocks are 64 KiB, each kept in a file of its own, and recency survives restarts by the
+/// modification times of the files. The directory must be one image's own; blocks left from an
+/// image of another size are thrown away, but an image replaced by one of the same size isn't
+/// noticed, so give every build of an image a directory of its own. Blocks found shorter than
+/// they should be, as after a crash or a full disk, are fetched again. The disk is read and
+/// written on tokio's blocking pool, so the cache never blocks the task reading from it.
+///
+/// ```no_run
+/// use unftp_sbe_iso::{AsyncIsoSource, DiskCache, Storage};
+///
+/// # fn serve(remote: impl AsyncIsoSource + 'static) {
+/// let cached = DiskCache::new(remote, "/var/cache/isos/debian-12.0")
+///     // 10 GiB
+///     .max_bytes(10 << 30);
+/// let storage = Storage::from_async_source(cached);
+/// # }
+/// ```
+pub struct DiskCache<S> {
+    source: S,
+    disk: Disk,
+}
+
+/// The directory of a [`DiskCache`] and what it holds, handed to the blocking pool to work on.
+#[derive(Clone)]
+struct Disk {
+    dir: PathBuf,
+    max_bytes: u64,
+    state: Arc<Mutex<Option<State>>>,
+}
+
+/// What the cache holds, read from the directory on first use.
+#[derive(Debug)]
+struct State {
+    /// The size of the image
+    len: u64,
+    /// The blocks on disk, with their sizes and the tick they were last used at
+    blocks: HashMap<u64, (u64, u64)>,
+    /// The blocks by the tick they were last used at, least recent first
+    recency: BTreeMap<u64, u64>,
+    tick: u64,
+    bytes: u64,
+}
+
+impl State {
+    fn touch(&mut self, index: u64) {
+        self.tick += 1;
+        if let Some((_, used)) = self.blocks.get_mut(&index) {
+            self.recency.remove(used);
+            *used = self.tick;
+            self.recency.insert(self.tick, index);
+        }
+    }
+}
+
+fn block_path(dir: &Path, index: u64) -> PathBuf {
+    dir.join(format!("{index:016x}.{BLOCK_EXTENSION}"))
+}
+
+/// How long the block at `index` of an image of `len` bytes is.
+fn block_len(index: u64, len: u64) -> u64 {
+    BLOCK_SIZE.min(len.saturating_sub(index * BLOCK_SIZE))
+}
+
+impl<S: AsyncIsoSource> DiskCache<S> {
+    /// Keeps the blocks read from `source` in `dir`, which is created if it doesn't exist.
+    pub fn new<P: Into<PathBuf>>(source: S, dir: P) -> Self {
+        Self {
+            source,
+            disk: Disk {
+                dir: dir.into(),
+                max_bytes: DEFAULT_MAX_BYTES,
+                state: Arc::new(Mutex::new(None)),
+            },
+        }
+    }
+
+    /// Keeps at most `bytes` of blocks on disk. 1 GiB by default.
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.disk.max_bytes = bytes;
+        self
+    }
+
+    /// Runs `work` on the disk on tokio's blocking pool.
+    async fn blocking<T, F>(&self, work: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Disk) -> T + Send + 'static,
+    {
+        let disk = self.disk.clone();
+        pool::spawn(None, move || work(&disk)).await
+    }
+
+    /// Reads what the directory holds, the first time it is needed.
+    async fn open(&self) -> io::Result<()> {
+        if self.disk.lock().is_some() {
+            return Ok(());
+        }
+        let len = self.source.len().await?;
+        self.blocking(move |disk| {
+            let state = disk.scan(len)?;
+            let mut opened = disk.lock();
+            if opened.is_none() {
+                *opened = Some(state);
+                disk.evict(opened.as_mut().expect("just opened"));
+            }
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Fetches the blocks `first..end` from the source in one request and keeps them.
+    async fn fetch(&self, first: u64, end: u64, len: u64) -> io::Result<Vec<Vec<u8>>> {
+        let offset = first * BLOCK_SIZE;
+        let wanted = (end * BLOCK_SIZE).min(len) - offset;
+        let data = self.source.read_range(offset, wanted as usize).await?;
+        let blocks: Vec<Vec<u8>> = data
+            .chunks(BLOCK_SIZE as usize)
+            .map(<[u8]>::to_vec)
+            .collect();
+        self.blocking(move |disk| {
+            for (index, block) in (first..).zip(&blocks) {
+                // Only whole blocks, or the last one of the image, are blocks as they are
+                if block.len() as u64 == block_len(index, len) {
+                    disk.store(index, block);
+                }
+            }
+            blocks
+        })
+        .await
+    }
+}
+
+impl Disk {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<State>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Finds the blocks in the directory, throwing them away if they are of an image of
+    /// another size than `len`.
+    fn scan(&self, len: u64) -> io::Result<State> {
+        fs::create_dir_all(&self.dir)?;
+        let recorded = fs::read_to_string(self.dir.join(IMAGE_FILE)).ok();
+        let same = recorded.is_some_and(|recorded| recorded.trim() == len.to_string());
+        let mut found = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            // Blocks being written when the process stopped are incomplete
+            if path.extension().is_some_and(|ext| ext == PARTIAL_EXTENSION) {
+                fs::remove_file(&path)?;
+                continue;
+            }
+            if path.extension().is_none_or(|ext| ext != BLOCK_EXTENSION) {
+                continue;
+            }
+            let index = path
+                .file_stem()
+                .and_then(|stem| u64::from_str_radix(&stem.to_string_lossy(), 16).ok());
+            match index {
+                Some(index) if same => {
+                    let meta = entry.metadata()?;
+                    let used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    found.push((used, index, meta.len()));
+                }
+                _ => fs::remove_file(&path)?,
+            }
+        }
+        if !same {
+            fs::write(self.dir.join(IMAGE_FILE), format!("{len}\n"))?;
+        }
+        found.sort();
+        let mut state = State {
+            len,
+            blocks: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+        };
+        for (_, index, size) in found {
+            state.tick += 1;
+            state.blocks.insert(index, (size, state.tick));
+            state.recency.insert(state.tick, index);
+            state.bytes += size;
+        }
+        Ok(state)
+    }
+
+    /// Removes the least recently used blocks until the cache fits.
+    fn evict(&self, state: &mut State) {
+        while state.bytes > self.max_bytes {
+            let Some((_, index)) = state.recency.pop_first() else {
+                break;
+            };
+            if let Some((size, _)) = state.blocks.remove(&index) {
+                state.bytes -= size;
+            }
+            // A block that can't be removed is found again on the next start
+            if let Err(e) = fs::remove_file(block_path(&self.dir, index)) {
+                log::warn!("could not remove a cached block of {:?}: {e}", self.dir);
+            }
+        }
+    }
+
+    fn is_cached(&self, index: u64) -> bool {
+        self.lock()
+            .as_ref()
+            .is_some_and(|state| state.blocks.contains_key(&index))
+    }
+
+    /// Reads the block at `index` from disk if it is there, and whole.
+    fn cached(&self, index: u64) -> Option<Vec<u8>> {
+        let len = self.lock().as_ref()?.len;
+        if !self.is_cached(index) {
+            return None;
+        }
+        let read = fs::read(block_path(&self.dir, index));
+        match read {
+            Ok(block) if block.len() as u64 == block_len(index, len) => {
+                let mut state = self.lock();
+                let state = state.as_mut()?;
+                state.touch(index);
+                // Recency survives restarts by the modification time
+                let _ = fs::File::options()
+                    .write(true)
+                    .open(block_path(&self.dir, index))
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+                Some(block)
+            }
+            // Removed behind the cache's back, or cut short, so fetched again
+            _ => {
+                if read.is_ok() {
+                    let _ = fs::remove_file(block_path(&self.dir, index));
+                }
+                let mut state = self.lock();
+                let state = state.as_mut()?;
+                if let Some((size, used)) = state.blocks.remove(&index) {
+                    state.recency.remove(&used);
+                    state.bytes -= size;
+                }
+                None
+            }
+        }
+    }
+
+    /// Keeps `block` on disk as the block at `index`.
+    fn store(&self, index: u64, block: &[u8]) {
+        let path = block_path(&self.dir, index);
+        let partial = path.with_extension(PARTIAL_EXTENSION);
+        let written = fs::write(&partial, block).and_then(|()| fs::rename(&partial, &path));
+        if let Err(e) = written {
+            log::warn!("could not cache a block in {:?}: {e}", self.dir);
+            let _ = fs::remove_file(&partial);
+            return;
+        }
+        let mut state = self.lock();
+        let Some(state) = state.as_mut() else {
+            return;
+        };
+        if !state.blocks.contains_key(&index) {
+            state.tick += 1;
+            state.blocks.insert(index, (block.len() as u64, state.tick));
+            state.recency.insert(state.tick, index);
+            state.bytes += block.len() as u64;
+        } else {
+            state.touch(index);
+        }
+        self.evict(state);
+    }
+}
+
+#[async_trait]
+impl<S: AsyncIsoSource> AsyncIsoSource for DiskCache<S> {
+    async fn read_range(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        self.open().await?;
+        let image_len = self.disk.lock().as_ref().map_or(0, |state| state.len);
+        let end = (offset + len as u64).min(image_len);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+        let (first, last) = (offset / BLOCK_SIZE, (end - 1) / BLOCK_SIZE);
+        let mut data = Vec::with_capacity((end - offset) as usize);
+        let mut index = first;
+        while index <= last {
+            let cached = match self.disk.is_cached(index) {
+                true => self.blocking(move |disk| disk.cached(index)).await?,
+                false => None,
+            };
+            let blocks = match cached {
+                Some(block) => vec![block],
+                None => {
+                    // The blocks missing from here on are fetched together
+                    let mut missing_end = index + 1;
+                    while missing_end <= last && !self.disk.is_cached(missing_end) {
+                        missing_end += 1;
+                    }
+                    self.fetch(index, missing_end, image_len).await?
+                }
+            };
+            for block in blocks {
+                let start = index * BLOCK_SIZE;
+                let from = offset.saturating_sub(start) as usize;
+                let to = ((end - start) as usize).min(block.len());
+                if from < to {
+                    data.extend_from_slice(&block[from..to]);
+                }
+                index += 1;
+                if (block.len() as u64) < BLOCK_SIZE {
+                    // The image ends early
+                    return Ok(data);
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    async fn len(&self) -> io::Result<u64> {
+        self.open().await?;
+        Ok(self.disk.lock().as_ref().map_or(0, |state| state.len))
+    }
+}
+
+impl<S> fmt::Debug for DiskCache<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiskCache")
+            .field("dir", &self.disk.dir)
+            .field("max_bytes", &self.disk.max_bytes)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,128 @@
+//! Directories of badly mastered images that hold several records with the same identifier.
+
+use std::collections::{HashMap, HashSet};
+
+/// Which of several records with the same name in one directory is exposed, as set with
+/// [`StorageBuilder::duplicates`](crate::StorageBuilder::duplicates). Names only count as the
+/// same when they are exactly equal; names differing in case are distinct entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Duplicates {
+    /// The first record in the order of the directory
+    #[default]
+    First,
+    /// The last record in the order of the directory
+    Last,
+    /// The record with the largest extent, the first of them on a tie
+    Largest,
+    /// Every record: the first under the plain name and each later one with `~N` appended,
+    /// counting from 2 and skipping names already taken, e.g. `FILE.TXT~2`
+    Suffix,
+}
+
+/// Resolves duplicate names among the records of a directory, given with their names and
+/// sizes in the order of the directory. The records kept keep their order.
+pub(crate) fn resolve<T>(
+    records: Vec<(String, u64, T)>,
+    duplicates: Duplicates,
+) -> Vec<(String, T)> {
+    let mut keep = vec![true; records.len()];
+    // The position of the record kept for each name so far
+    let mut kept: HashMap<&str, usize> = HashMap::new();
+    let mut suffixed = Vec::new();
+    for (i, (name, size, _)) in records.iter().enumerate() {
+        let Some(&other) = kept.get(name.as_str()) else {
+            kept.insert(name, i);
+            continue;
+        };
+        let replace = match duplicates {
+            Duplicates::First => false,
+            Duplicates::Last => true,
+            Duplicates::Largest => *size > records[other].1,
+            Duplicates::Suffix => {
+                suffixed.push(i);
+                continue;
+            }
+        };
+        if replace {
+            keep[other] = false;
+            kept.insert(name, i);
+        } else {
+            keep[i] = false;
+        }
+    }
+    let mut taken: HashSet<String> = kept.keys().map(|name| name.to_string()).collect();
+    let mut names: Vec<Option<String>> = vec![None; records.len()];
+    for i in suffixed {
+        let name = &records[i].0;
+        let mut n = 2;
+        while taken.contains(&format!("{name}~{n}")) {
+            n += 1;
+        }
+        let suffixed = format!("{name}~{n}");
+        taken.insert(suffixed.clone());
+        names[i] = Some(suffixed);
+    }
+    records
+        .into_iter()
+        .zip(keep)
+        .zip(names)
+        .filter(|((_, keep), _)| *keep)
+        .map(|(((name, _, record), _), renamed)| (renamed.unwrap_or(name), record))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<(String, u64, u32)> {
+        [
+            ("A", 5, 0),
+            ("B", 1, 1),
+            ("A", 9, 2),
+            ("A~2", 1, 3),
+            ("A", 9, 4),
+            ("a", 1, 5),
+        ]
+        .into_iter()
+        .map(|(name, size, record)| (name.to_string(), size, record))
+        .collect()
+    }
+
+    fn resolved(duplicates: Duplicates) -> Vec<(String, u32)> {
+        resolve(records(), duplicates)
+    }
+
+    fn expected(records: &[(&str, u32)]) -> Vec<(String, u32)> {
+        records.iter().map(|(n, r)| (n.to_string(), *r)).collect()
+    }
+
+    #[test]
+    fn winners() {
+        let others = [("B", 1), ("A~2", 3), ("a", 5)];
+        let with = |record| {
+            let mut all = expected(&others);
+            all.push(("A".to_string(), record));
+            all.sort_by_key(|(_, r)| *r);
+            all
+        };
+        assert_eq!(resolved(Duplicates::First), with(0));
+        assert_eq!(resolved(Duplicates::Last), with(4));
+        assert_eq!(resolved(Duplicates::Largest), with(2));
+    }
+
+    #[test]
+    fn suffix() {
+        assert_eq!(
+            resolved(Duplicates::Suffix),
+            expected(&[
+                ("A", 0),
+                ("B", 1),
+                ("A~3", 2),
+                ("A~2", 3),
+                ("A~4", 4),
+                ("a", 5)
+            ])
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! The boot images of El Torito bootable images, served as virtual files.
+
+use crate::{
+    descriptor::{self, DescriptorKind, SECTOR, read_exact_at},
+    source::IsoSource,
+};
+use std::io;
+
+/// The name of the file serving the BIOS boot image.
+pub(crate) const BIOS_NAME: &str = "boot.img";
+
+/// The name of the file serving the UEFI boot image.
+pub(crate) const EFI_NAME: &str = "efi.img";
+
+/// The platform ID of UEFI in the boot catalog.
+const PLATFORM_EFI: u8 = 0xEF;
+
+/// The size of the virtual sectors the boot catalog counts in.
+const VIRTUAL_SECTOR: u64 = 512;
+
+/// Where a boot image lies in the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BootImage {
+    pub(crate) offset: u64,
+    pub(crate) len: u64,
+}
+
+/// The boot images named in the boot catalog.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct BootImages {
+    pub(crate) bios: Option<BootImage>,
+    pub(crate) efi: Option<BootImage>,
+}
+
+impl BootImages {
+    /// The names of the boot images there are, with their locations.
+    pub(crate) fn files(&self) -> impl Iterator<Item = (&'static str, BootImage)> {
+        [(BIOS_NAME, self.bios), (EFI_NAME, self.efi)]
+            .into_iter()
+            .filter_map(|(name, image)| Some((name, image?)))
+    }
+}
+
+/// Finds the boot images of the image. Images that aren't bootable, or whose boot catalog is
+/// malformed, have none.
+pub(crate) fn boot_images(source: &dyn IsoSource) -> io::Result<BootImages> {
+    let mut images = BootImages::default();
+    let Some(catalog) = descriptor::read(source)?
+        .into_iter()
+        .find_map(|d| match d.kind {
+            DescriptorKind::BootRecord { boot_system_id, .. }
+                if boot_system_id == "EL TORITO SPECIFICATION" =>
+            {
+                Some(u32::from_le_bytes(d.raw[0x47..0x4B].try_into().unwrap()))
+            }
+            _ => None,
+        })
+    else {
+        return Ok(images);
+    };
+    let mut entries = vec![0; SECTOR];
+    read_exact_at(source, u64::from(catalog) * SECTOR as u64, &mut entries)?;
+    // The validation entry: header ID 1, and a key of 55 AA
+    if entries[0] != 1 || entries[30..32] != [0x55, 0xAA] {
+        return Ok(images);
+    }
+    let mut platform = entries[1];
+    let mut slot = |platform: u8, entry: &[u8]| -> io::Result<()> {
+        // Only bootable entries
+        if entry[0] != 0x88 {
+            return Ok(());
+        }
+        let image = locate(source, entry)?;
+        let target = if platform == PLATFORM_EFI {
+            &mut images.efi
+        } else {
+            &mut images.bios
+        };
+        target.get_or_insert(image);
+        Ok(())
+    };
+    // The initial entry, then sections of entries for further platforms
+    slot(platform, &entries[32..64])?;
+    let mut at = 64;
+    while at + 32 <= entries.len() {
+        let header = &entries[at..at + 32];
+        if header[0] != 0x90 && header[0] != 0x91 {
+            break;
+        }
+        platform = header[1];
+        let count = u16::from_le_bytes([header[2], header[3]]) as usize;
+        at += 32;
+        for _ in 0..count {
+            if at + 32 > entries.len() {
+                break;
+            }
+            slot(platform, &entries[at..at + 32])?;
+            at += 32;
+        }
+        if header[0] == 0x91 {
+            break;
+        }
+    }
+    Ok(images)
+}
+
+/// Works out where the image of a boot entry lies and how large it is.
+fn locate(source: &dyn IsoSource, entry: &[u8]) -> io::Result<BootImage> {
+    let sectors = u64::from(u16::from_le_bytes([entry[6], entry[7]]));
+    let offset = u64::from(u32::from_le_bytes(entry[8..12].try_into().unwrap())) * SECTOR as u64;
+    let len = match entry[1] & 0x0F {
+        // Emulated floppies of 1.2, 1.44 and 2.88 MB
+        1 => 1_228_800,
+        2 => 1_474_560,
+        3 => 2_949_120,
+        // No emulation and hard disk emulation. UEFI images are usually FAT file systems whose
+        // size the boot catalog doesn't record properly, so their own says better.
+        _ => fat_len(source, offset)?.unwrap_or(sectors * VIRTUAL_SECTOR),
+    };
+    let len = len.min(source.len()?.saturating_sub(offset));
+    Ok(BootImage { offset, len })
+}
+
+/// Reads the size of the FAT file system at `offset` from its boot sector, if there is one.
+fn fat_len(source: &dyn IsoSource, offset: u64) -> io::Result<Option<u64>> {
+    let mut boot = [0; 512];
+    if read_exact_at(source, offset, &mut boot).is_err() || boot[510..512] != [0x55, 0xAA] {
+        return Ok(None);
+    }
+    let bytes_per_sector = u64::from(u16::from_le_bytes([boot[11], boot[12]]));
+    let sectors = match u16::from_le_bytes([boot[19], boot[20]]) {
+        0 => u64::from(u32::from_le_bytes(boot[32..36].try_into().unwrap())),
+        n => u64::from(n),
+    };
+    if !bytes_per_sector.is_power_of_two() || !(512..=4096).contains(&bytes_per_sector) {
+        return Ok(None);
+    }
+    Ok(Some(bytes_per_sector * sectors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(kind: u8) -> Vec<u8> {
+        let mut d = vec![0; SECTOR];
+        d[0] = kind;
+        d[1..6].copy_from_slice(b"CD001");
+        d[6] = 1;
+        d
+    }
+
+    /// An image with a BIOS entry for a floppy at sector 20 and a UEFI entry for a FAT image at
+    /// sector 22.
+    fn bootable() -> Vec<u8> {
+        let mut image = vec![0; 16 * SECTOR];
+        let mut boot = descriptor(0);
+        boot[7..30].copy_from_slice(b"EL TORITO SPECIFICATION");
+        boot[0x47..0x4B].copy_from_slice(&19u32.to_le_bytes());
+        image.extend(boot);
+        image.extend(descriptor(1));
+        image.extend(descriptor(255));
+
+        let mut catalog = vec![0; SECTOR];
+        catalog[0] = 1;
+        catalog[30..32].copy_from_slice(&[0x55, 0xAA]);
+        catalog[32] = 0x88;
+        catalog[33] = 2;
+        catalog[40..44].copy_from_slice(&20u32.to_le_bytes());
+        catalog[64] = 0x91;
+        catalog[65] = PLATFORM_EFI;
+        catalog[66] = 1;
+        catalog[96] = 0x88;
+        catalog[102] = 1;
+        catalog[104..108].copy_from_slice(&22u32.to_le_bytes());
+        image.extend(catalog);
+
+        image.resize(22 * SECTOR, 0);
+        let mut fat = vec![0; 4 * SECTOR];
+        fat[11..13].copy_from_slice(&512u16.to_le_bytes());
+        fat[19..21].copy_from_slice(&16u16.to_le_bytes());
+        fat[510..512].copy_from_slice(&[0x55, 0xAA]);
+        image.extend(fat);
+        image
+    }
+
+    #[test]
+    fn bios_and_efi() {
+        let images = boot_images(&bootable()).unwrap();
+        assert_eq!(
+            images.bios,
+            Some(BootImage {
+                offset: 20 * SECTOR as u64,
+                // The floppy is cut short by the end of the image
+                len: 6 * SECTOR as u64,
+            })
+        );
+        assert_eq!(
+            images.efi,
+            Some(BootImage {
+                offset: 22 * SECTOR as u64,
+                len: 16 * 512,
+            })
+        );
+    }
+
+    #[test]
+    fn not_bootable() {
+        let mut image = vec![0; 16 * SECTOR];
+        image.extend(descriptor(1));
+        image.extend(descriptor(255));
+        assert_eq!(boot_images(&image).unwrap(), BootImages::default());
+    }
+}
@@ -0,0 +1,330 @@
+//! How failures are reported to clients. The kind of an error picks the FTP reply, and clients
+//! retry the 4xx ones but not the 5xx ones, so an error is only reported as transient when
+//! trying again can succeed: a path that doesn't exist in the image never will.
+
+use crate::{raw::CorruptSector, retry, source::Origin};
+use cdfs::ISOError;
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+};
+use unftp_core::storage::{Error, ErrorKind};
+
+/// What went wrong in the back-end, carried as the source of the [`Error`] handed to libunftp
+/// so that library users can tell failures apart without parsing messages:
+///
+/// ```no_run
+/// # async fn check(storage: unftp_sbe_iso::Storage) {
+/// use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+/// use unftp_sbe_iso::IsoStorageError;
+///
+/// let e = storage.list(&DefaultUser {}, "/missing").await.err().unwrap();
+/// if let Some(IsoStorageError::NotFound(path)) = IsoStorageError::from_storage_error(&e) {
+///     println!("no {path:?} in the image");
+/// }
+/// # }
+/// ```
+///
+/// Not every failure has a variant of its own yet; the others carry a message.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum IsoStorageError {
+    /// A path that doesn't exist in the image, or that is hidden from clients
+    NotFound(PathBuf),
+    /// A component of a path that isn't in its directory
+    ComponentNotFound(String),
+    /// A path that exists but isn't a directory, where one is expected
+    NotADirectory(PathBuf),
+    /// An image cdfs can't parse
+    UnsupportedImage {
+        /// Where the image comes from
+        image: String,
+        /// Why it can't be parsed
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// An image that can't be opened or read at all
+    Image {
+        /// Where the image comes from
+        image: String,
+        /// The failure to read it
+        source: io::Error,
+    },
+    /// A failed read of the image on behalf of a client, like of the contents of a file
+    Io {
+        /// What was being read
+        what: String,
+        /// The failure
+        source: io::Error,
+    },
+    /// A restart of a download, with `REST`, past the end of the file
+    PastEnd {
+        /// The path of the file
+        path: PathBuf,
+        /// The byte the download was to start at
+        start: u64,
+        /// The length of the file
+        len: u64,
+    },
+    /// A damaged sector of a [raw dump](crate::RawSectors)
+    Corrupt {
+        /// The number of the sector in the dump
+        lba: u64,
+        /// What was being read
+        what: String,
+        /// The failure, carrying a [`CorruptSector`]
+        source: io::Error,
+    },
+}
+
+impl IsoStorageError {
+    /// The error of the back-end behind `error`, if it has one.
+    pub fn from_storage_error(error: &Error) -> Option<&Self> {
+        error.source()?.downcast_ref()
+    }
+
+    /// The kind of error, and so the FTP reply, this error is reported to clients as.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NotFound(_) | Self::ComponentNotFound(_) => ErrorKind::PermanentFileNotAvailable,
+            Self::NotADirectory(_) => ErrorKind::PermanentDirectoryNotAvailable,
+            Self::PastEnd { .. } => ErrorKind::PermanentFileNotAvailable,
+            Self::UnsupportedImage { source, .. } => match source.downcast_ref() {
+                Some(ISOError::Io(e)) => image_kind(e),
+                _ => ErrorKind::LocalError,
+            },
+            Self::Image { source, .. } => image_kind(source),
+            Self::Io { source, .. } => read_kind(source),
+            Self::Corrupt { .. } => ErrorKind::PermanentFileNotAvailable,
+        }
+    }
+}
+
+impl Display for IsoStorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(path) => write!(f, "{path:?} not found"),
+            Self::ComponentNotFound(name) => write!(f, "Path component '{name}' not found"),
+            Self::NotADirectory(path) => write!(f, "{path:?} is not a directory"),
+            Self::PastEnd { path, start, len } => {
+                write!(
+                    f,
+                    "can't start at byte {start} of {path:?}, which is {len} bytes"
+                )
+            }
+            Self::UnsupportedImage { image, source } => {
+                write!(f, "could not open ISO image {image}: {source}")
+            }
+            Self::Image { image, source } => {
+                write!(f, "could not read ISO image {image}: {source}")
+            }
+            Self::Io { what, source } | Self::Corrupt { what, source, .. } => {
+                write!(f, "{what}: {source}")
+            }
+        }
+    }
+}
+
+impl StdError for IsoStorageError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::UnsupportedImage { source, .. } => Some(&**source),
+            Self::Image { source, .. } | Self::Io { source, .. } | Self::Corrupt { source, .. } => {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<IsoStorageError> for Error {
+    fn from(e: IsoStorageError) -> Self {
+        Error::new(e.kind(), e)
+    }
+}
+
+/// The error for paths that don't exist, or that are hidden from clients.
+pub(crate) fn not_found(path: &Path) -> Error {
+    IsoStorageError::NotFound(path.to_path_buf()).into()
+}
+
+/// The error for a component of a path that isn't in its directory.
+pub(crate) fn component_not_found(name: &str) -> Error {
+    IsoStorageError::ComponentNotFound(name.to_string()).into()
+}
+
+/// The error for a path that exists but isn't a directory, where one is expected.
+pub(crate) fn not_a_directory(path: &Path) -> Error {
+    IsoStorageError::NotADirectory(path.to_path_buf()).into()
+}
+
+/// Fails a download of the file at `path`, `len` bytes long, that is to start past its end.
+pub(crate) fn check_start(path: &Path, start: u64, len: u64) -> Result<(), Error> {
+    if start > len {
+        return Err(IsoStorageError::PastEnd {
+            path: path.to_path_buf(),
+            start,
+            len,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// The error for a failed read of the image on behalf of a client, like of the contents of a
+/// file. `what` says what failed.
+pub(crate) fn read(what: impl Display, e: io::Error) -> Error {
+    let what = what.to_string();
+    let corrupt = e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<CorruptSector>())
+        .map(|corrupt| corrupt.sector);
+    match corrupt {
+        Some(lba) => IsoStorageError::Corrupt {
+            lba,
+            what,
+            source: e,
+        },
+        None => IsoStorageError::Io { what, source: e },
+    }
+    .into()
+}
+
+/// The error for an image that can't be opened or read at all, with `origin` where it comes
+/// from. Unless it is likely to go away, that is a problem of the server rather than of what
+/// the client asked for.
+pub(crate) fn image(origin: &Origin, e: io::Error) -> Error {
+    IsoStorageError::Image {
+        image: format!("{origin:?}"),
+        source: e,
+    }
+    .into()
+}
+
+/// The error for an image cdfs can't parse.
+pub(crate) fn unparsable(origin: &Origin, e: ISOError) -> Error {
+    IsoStorageError::UnsupportedImage {
+        image: format!("{origin:?}"),
+        source: Box::new(e),
+    }
+    .into()
+}
+
+fn read_kind(e: &io::Error) -> ErrorKind {
+    if retry::is_transient(e) {
+        return ErrorKind::TransientFileNotAvailable;
+    }
+    match e.kind() {
+        io::ErrorKind::PermissionDenied | io::ErrorKind::FileTooLarge => {
+            ErrorKind::PermissionDenied
+        }
+        io::ErrorKind::InvalidFilename => ErrorKind::FileNameNotAllowedError,
+        // A truncated or corrupt image stays that way
+        io::ErrorKind::NotFound
+        | io::ErrorKind::UnexpectedEof
+        | io::ErrorKind::InvalidData
+        | io::ErrorKind::InvalidInput
+        | io::ErrorKind::Unsupported => ErrorKind::PermanentFileNotAvailable,
+        _ => ErrorKind::LocalError,
+    }
+}
+
+fn image_kind(e: &io::Error) -> ErrorKind {
+    if retry::is_transient(e) {
+        ErrorKind::TransientFileNotAvailable
+    } else {
+        ErrorKind::LocalError
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_kinds() {
+        for (kind, expected) in [
+            (
+                io::ErrorKind::TimedOut,
+                ErrorKind::TransientFileNotAvailable,
+            ),
+            (
+                io::ErrorKind::ConnectionReset,
+                ErrorKind::TransientFileNotAvailable,
+            ),
+            (
+                io::ErrorKind::UnexpectedEof,
+                ErrorKind::PermanentFileNotAvailable,
+            ),
+            (
+                io::ErrorKind::InvalidData,
+                ErrorKind::PermanentFileNotAvailable,
+            ),
+            (io::ErrorKind::PermissionDenied, ErrorKind::PermissionDenied),
+            (
+                io::ErrorKind::InvalidFilename,
+                ErrorKind::FileNameNotAllowedError,
+            ),
+            (io::ErrorKind::OutOfMemory, ErrorKind::LocalError),
+        ] {
+            assert_eq!(read(kind, io::Error::from(kind)).kind(), expected, "{kind}");
+        }
+    }
+
+    #[test]
+    fn image_kinds() {
+        let origin = Origin::Source(std::sync::Arc::new(Vec::new()));
+        for (kind, expected) in [
+            (
+                io::ErrorKind::TimedOut,
+                ErrorKind::TransientFileNotAvailable,
+            ),
+            // The image missing isn't the client's file missing
+            (io::ErrorKind::NotFound, ErrorKind::LocalError),
+            (io::ErrorKind::PermissionDenied, ErrorKind::LocalError),
+        ] {
+            let e = io::Error::from(kind);
+            assert_eq!(image(&origin, e).kind(), expected, "{kind}");
+        }
+        let e = ISOError::InvalidFs("no primary volume");
+        assert_eq!(unparsable(&origin, e).kind(), ErrorKind::LocalError);
+        let e = ISOError::Io(io::ErrorKind::Interrupted.into());
+        assert_eq!(
+            unparsable(&origin, e).kind(),
+            ErrorKind::TransientFileNotAvailable
+        );
+    }
+
+    #[test]
+    fn inspectable() {
+        let e = not_found(Path::new("/missing"));
+        assert!(matches!(
+            IsoStorageError::from_storage_error(&e),
+            Some(IsoStorageError::NotFound(path)) if path == Path::new("/missing")
+        ));
+        assert_eq!(e.source().unwrap().to_string(), "\"/missing\" not found");
+        let damaged = io::Error::new(
+            io::ErrorKind::InvalidData,
+            CorruptSector {
+                sector: 42,
+                check: crate::raw::Check::Edc,
+            },
+        );
+        let e = read("read error", damaged);
+        assert_eq!(e.kind(), ErrorKind::PermanentFileNotAvailable);
+        let Some(IsoStorageError::Corrupt { lba, .. }) = IsoStorageError::from_storage_error(&e)
+        else {
+            panic!("{e:?}");
+        };
+        assert_eq!(*lba, 42);
+        // The cause stays reachable through the chain
+        let cause = e.source().unwrap().source().unwrap();
+        assert!(
+            cause
+                .to_string()
+                .starts_with("sector 42 of the dump is damaged")
+        );
+        assert!(IsoStorageError::from_storage_error(&ErrorKind::LocalError.into()).is_none());
+    }
+}
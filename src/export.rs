@@ -0,0 +1,186 @@
+//! Writing the tree of an image as a tar archive (POSIX ustar, with pax records for long names),
+//! for [`Storage::export_tar`](crate::Storage::export_tar).
+
+use std::io::{self, Write};
+
+/// The size of tar blocks, which headers and contents are padded to.
+const BLOCK: usize = 512;
+
+/// How [`Storage::export_tar`](crate::Storage::export_tar) compresses the archive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    /// A plain `.tar`. The default without the `zstd` feature.
+    #[cfg_attr(not(feature = "zstd"), default)]
+    None,
+    /// A `.tar.zst`, compressed at the default level of zstd. Needs the `zstd` feature.
+    #[cfg(feature = "zstd")]
+    #[default]
+    Zstd,
+}
+
+/// The kind of an entry of an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// An entry of an archive, its contents aside.
+#[derive(Debug)]
+pub(crate) struct Header<'a> {
+    /// The path, relative and without a trailing `/`
+    pub(crate) path: &'a str,
+    pub(crate) kind: EntryKind,
+    /// The permission bits
+    pub(crate) mode: u32,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) size: u64,
+    /// Seconds since the Unix epoch
+    pub(crate) mtime: u64,
+    /// The target of a link
+    pub(crate) link: &'a str,
+}
+
+/// Writes `value` in octal into `field`, NUL-terminated, or in base-256 where it doesn't fit,
+/// as GNU tar and others read.
+fn numeric(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    if value < 1 << (3 * digits) {
+        let octal = format!("{value:0digits$o}");
+        field[..digits].copy_from_slice(octal.as_bytes());
+        field[digits] = 0;
+    } else {
+        field.fill(0);
+        field[digits - 7..].copy_from_slice(&value.to_be_bytes());
+        field[0] = 0x80;
+    }
+}
+
+/// A pax record, which is prefixed with its own length in decimal.
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let rest = format!(" {key}={value}\n");
+    let mut len = rest.len() + 1;
+    while (len.to_string().len() + rest.len()) != len {
+        len += 1;
+    }
+    format!("{len}{rest}").into_bytes()
+}
+
+/// A tar archive written to `W`.
+pub(crate) struct Archive<W: Write> {
+    out: W,
+    written: u64,
+}
+
+impl<W: Write> Archive<W> {
+    pub(crate) fn new(out: W) -> Self {
+        Self { out, written: 0 }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Pads what was written to a whole block.
+    fn pad(&mut self) -> io::Result<()> {
+        let over = (self.written % BLOCK as u64) as usize;
+        if over > 0 {
+            self.write(&[0; BLOCK][over..])?;
+        }
+        Ok(())
+    }
+
+    fn raw_header(&mut self, name: &[u8], kind: u8, header: &Header, size: u64) -> io::Result<()> {
+        let mut block = [0; BLOCK];
+        block[..name.len().min(100)].copy_from_slice(&name[..name.len().min(100)]);
+        numeric(&mut block[100..108], header.mode as u64);
+        numeric(&mut block[108..116], header.uid as u64);
+        numeric(&mut block[116..124], header.gid as u64);
+        numeric(&mut block[124..136], size);
+        numeric(&mut block[136..148], header.mtime);
+        block[156] = kind;
+        let link = header.link.as_bytes();
+        if link.len() <= 100 {
+            block[157..157 + link.len()].copy_from_slice(link);
+        }
+        block[257..263].copy_from_slice(b"ustar\0");
+        block[263..265].copy_from_slice(b"00");
+        // The checksum is summed with its own field taken as spaces
+        block[148..156].fill(b' ');
+        let sum: u32 = block.iter().map(|&b| b as u32).sum();
+        block[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+        self.write(&block)
+    }
+
+    /// Writes the header of an entry, which `size` bytes of contents are to follow for files.
+    pub(crate) fn header(&mut self, header: &Header) -> io::Result<()> {
+        let mut name = header.path.to_string();
+        if header.kind == EntryKind::Dir {
+            name.push('/');
+        }
+        let mut records = Vec::new();
+        if name.len() > 100 {
+            records.extend(pax_record("path", &name));
+        }
+        if header.link.len() > 100 {
+            records.extend(pax_record("linkpath", header.link));
+        }
+        if !records.is_empty() {
+            self.raw_header(b"././@PaxHeader", b'x', header, records.len() as u64)?;
+            self.write(&records)?;
+            self.pad()?;
+        }
+        let (kind, size) = match header.kind {
+            EntryKind::File => (b'0', header.size),
+            EntryKind::Dir => (b'5', 0),
+            EntryKind::Symlink => (b'2', 0),
+        };
+        self.raw_header(name.as_bytes(), kind, header, size)
+    }
+
+    /// Writes part of the contents of the file whose header was written last.
+    pub(crate) fn contents(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write(bytes)
+    }
+
+    /// Ends the contents of a file.
+    pub(crate) fn end_file(&mut self) -> io::Result<()> {
+        self.pad()
+    }
+
+    /// Ends the archive with its two empty blocks, returning how long it is and `W`.
+    pub(crate) fn finish(mut self) -> io::Result<(u64, W)> {
+        self.write(&[0; 2 * BLOCK])?;
+        self.out.flush()?;
+        Ok((self.written, self.out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_fields() {
+        let mut field = [0; 12];
+        numeric(&mut field, 0o644);
+        assert_eq!(&field, b"00000000644\0");
+        numeric(&mut field, 10 << 30);
+        assert_eq!(field[0], 0x80);
+        assert_eq!(field[4..], (10u64 << 30).to_be_bytes());
+    }
+
+    #[test]
+    fn pax_records() {
+        assert_eq!(pax_record("path", "a"), b"9 path=a\n");
+        let long = "x".repeat(92);
+        let record = pax_record("path", &long);
+        assert_eq!(record.len(), 102);
+        assert!(record.starts_with(b"102 path=x"));
+    }
+}
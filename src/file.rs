@@ -0,0 +1,185 @@
+//! Random access to a file of the image, for library users that need more than the sequential
+//! reads of [`StorageBackend::get`](unftp_core::storage::StorageBackend::get).
+
+use crate::{
+    descriptor::read_exact_at,
+    pool::{self, IoPool, Spawned},
+    source::IsoSource,
+};
+use std::{
+    fmt, io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+/// The most read from the image at once.
+const CHUNK: u64 = 64 * 1024;
+
+/// A file of the image opened with [`Storage::open`](crate::Storage::open), readable from any
+/// position.
+///
+/// The file reads straight from the image, a chunk at a time. Reads from an
+/// [`AsyncIsoSource`](crate::AsyncIsoSource) run on tokio's blocking thread pool, and all reads
+/// run on the [I/O pool](crate::StorageBuilder::io_pool) if there is one; other sources are read
+/// from the calling task, like the rest of the back-end does.
+///
+/// ```no_run
+/// use std::io::SeekFrom;
+/// use tokio::io::{AsyncReadExt, AsyncSeekExt};
+/// use unftp_sbe_iso::Storage;
+///
+/// # async fn tail() -> Result<(), Box<dyn std::error::Error>> {
+/// let storage = Storage::new("/srv/images/debian.iso");
+/// let mut file = storage.open("/README.txt").await?;
+/// file.seek(SeekFrom::End(-100)).await?;
+/// let mut tail = String::new();
+/// file.read_to_string(&mut tail).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct IsoAsyncFile {
+    source: Arc<dyn IsoSource>,
+    /// Where the file starts in the image
+    offset: u64,
+    len: u64,
+    /// The position in the file reads continue from
+    pos: u64,
+    /// Whether reads run on the blocking thread pool, or on `pool`
+    blocks: bool,
+    pool: Option<IoPool>,
+    /// Bytes read ahead of `pos`
+    buf: Vec<u8>,
+    consumed: usize,
+    read: Option<Spawned<io::Result<Vec<u8>>>>,
+}
+
+impl IsoAsyncFile {
+    pub(crate) fn new(
+        source: Arc<dyn IsoSource>,
+        offset: u64,
+        len: u64,
+        blocks: bool,
+        pool: Option<IoPool>,
+    ) -> Self {
+        Self {
+            source,
+            offset,
+            len,
+            pos: 0,
+            blocks,
+            pool,
+            buf: Vec::new(),
+            consumed: 0,
+            read: None,
+        }
+    }
+
+    /// The size of the file in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Tells whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The position in the file the next read starts at.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Reads up to `len` bytes of the file at `offset`, fewer where the file ends sooner,
+    /// without moving the position.
+    pub(crate) fn read_range(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let offset = offset.min(self.len);
+        let len = len.min(self.len - offset);
+        read_chunk(&*self.source, self.offset + offset, len)
+    }
+}
+
+impl fmt::Debug for IsoAsyncFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IsoAsyncFile")
+            .field("offset", &self.offset)
+            .field("len", &self.len)
+            .field("pos", &self.pos)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reads `len` bytes of the image at `offset`.
+fn read_chunk(source: &dyn IsoSource, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    let mut chunk = vec![0; len as usize];
+    read_exact_at(source, offset, &mut chunk)?;
+    Ok(chunk)
+}
+
+impl AsyncRead for IsoAsyncFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.consumed == this.buf.len() {
+            if this.pos >= this.len || out.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            let offset = this.offset + this.pos;
+            let want = CHUNK.min(this.len - this.pos);
+            let chunk = if this.blocks {
+                let task = this.read.get_or_insert_with(|| {
+                    let source = this.source.clone();
+                    pool::spawn(this.pool.as_ref(), move || {
+                        read_chunk(&*source, offset, want)
+                    })
+                });
+                let joined = ready!(Pin::new(task).poll(cx));
+                this.read = None;
+                joined?
+            } else {
+                read_chunk(&*this.source, offset, want)
+            };
+            this.buf = chunk?;
+            this.consumed = 0;
+        }
+        let n = out.remaining().min(this.buf.len() - this.consumed);
+        out.put_slice(&this.buf[this.consumed..this.consumed + n]);
+        this.consumed += n;
+        this.pos += n as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for IsoAsyncFile {
+    fn start_seek(self: Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        if this.read.is_some() {
+            return Err(io::Error::other("a read is still in progress"));
+        }
+        let target = match position {
+            io::SeekFrom::Start(n) => Some(n),
+            io::SeekFrom::End(delta) => this.len.checked_add_signed(delta),
+            io::SeekFrom::Current(delta) => this.pos.checked_add_signed(delta),
+        };
+        let target = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to a negative or overflowing position",
+            )
+        })?;
+        if target != this.pos {
+            this.pos = target;
+            this.buf.clear();
+            this.consumed = 0;
+        }
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
@@ -0,0 +1,233 @@
+//! Deciding which files are served based on their extension or type.
+
+/// Decides which files are served, by file extension or by MIME type sniffed from the first bytes
+/// of the file. Files that are not served are left out of listings and can't be downloaded.
+/// Directories are not affected.
+///
+/// A file is served when it matches at least one of the included extensions or types, or when
+/// nothing is included explicitly, and it matches none of the excluded ones. Extensions are
+/// compared case-insensitively. Types can end in `/*` to match all subtypes, e.g. `image/*`.
+///
+/// ```
+/// use unftp_sbe_iso::Filter;
+///
+/// // Only serve packages and repository metadata
+/// let filter = Filter::new().include_extensions(["rpm", "xml"]);
+/// // Serve everything but executables
+/// let filter = Filter::new().exclude_types(["application/x-executable", "application/x-msdownload"]);
+/// ```
+///
+/// Sniffing types means reading the start of every file in a listing, so type rules make LIST
+/// noticeably slower on large directories.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    include_extensions: Vec<String>,
+    exclude_extensions: Vec<String>,
+    include_types: Vec<String>,
+    exclude_types: Vec<String>,
+}
+
+/// The number of bytes read from the start of a file to sniff its type.
+pub(crate) const SNIFF_LEN: usize = 512;
+
+fn lowercase<I, S>(items: I) -> impl Iterator<Item = String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    items.into_iter().map(|s| {
+        let s = s.as_ref();
+        s.strip_prefix('.').unwrap_or(s).to_ascii_lowercase()
+    })
+}
+
+fn type_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(top) => mime.split('/').next() == Some(top),
+        None => pattern == mime,
+    }
+}
+
+impl Filter {
+    /// Creates a filter that serves every file.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves files with one of the given extensions, with or without the leading dot.
+    pub fn include_extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.include_extensions.extend(lowercase(extensions));
+        self
+    }
+
+    /// Hides files with one of the given extensions, with or without the leading dot.
+    pub fn exclude_extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.exclude_extensions.extend(lowercase(extensions));
+        self
+    }
+
+    /// Serves files of one of the given MIME types.
+    pub fn include_types<I, S>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.include_types.extend(lowercase(types));
+        self
+    }
+
+    /// Hides files of one of the given MIME types.
+    pub fn exclude_types<I, S>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.exclude_types.extend(lowercase(types));
+        self
+    }
+
+    /// Tells whether the file called `name` is served. `head` returns the first [`SNIFF_LEN`]
+    /// bytes of the file and is only called when there are type rules.
+    pub(crate) fn allows(&self, name: &str, head: impl FnOnce() -> Vec<u8>) -> bool {
+        let extension = match name.rfind('.') {
+            Some(dot) if dot > 0 => name[dot + 1..].to_ascii_lowercase(),
+            _ => String::new(),
+        };
+        let mime = if self.include_types.is_empty() && self.exclude_types.is_empty() {
+            None
+        } else {
+            Some(sniff(&head()))
+        };
+        let has_type = |patterns: &[String]| {
+            mime.is_some_and(|mime| patterns.iter().any(|p| type_matches(p, mime)))
+        };
+
+        let included = (self.include_extensions.is_empty() && self.include_types.is_empty())
+            || self.include_extensions.contains(&extension)
+            || has_type(&self.include_types);
+        included && !self.exclude_extensions.contains(&extension) && !has_type(&self.exclude_types)
+    }
+}
+
+/// Magic numbers: the offset they are found at, the bytes and the type they identify.
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, &[0xed, 0xab, 0xee, 0xdb], "application/x-rpm"),
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, &[0xff, 0xd8, 0xff], "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, &[0x1f, 0x8b], "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xfd7zXZ\x00", "application/x-xz"),
+    (0, &[0x28, 0xb5, 0x2f, 0xfd], "application/zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"\x7fELF", "application/x-executable"),
+    (0, b"MZ", "application/x-msdownload"),
+    (
+        0,
+        b"!<arch>\ndebian",
+        "application/vnd.debian.binary-package",
+    ),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"<?xml", "application/xml"),
+];
+
+/// Guesses the MIME type of a file from its first bytes.
+pub(crate) fn sniff(head: &[u8]) -> &'static str {
+    for (offset, magic, mime) in MAGIC {
+        if head.get(*offset..offset + magic.len()) == Some(magic) {
+            return mime;
+        }
+    }
+    let text = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+    let text = match std::str::from_utf8(text) {
+        Ok(text) => text,
+        // The head may cut a character in two
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&text[..e.valid_up_to()]).unwrap(),
+        Err(_) => return "application/octet-stream",
+    };
+    if text.trim_start().starts_with("<?xml") {
+        "application/xml"
+    } else if text.chars().all(|c| !c.is_control() || c.is_whitespace()) {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_types() {
+        assert_eq!(sniff(&[0xed, 0xab, 0xee, 0xdb, 3, 0]), "application/x-rpm");
+        assert_eq!(
+            sniff(b"<?xml version=\"1.0\"?><repomd/>"),
+            "application/xml"
+        );
+        assert_eq!(
+            sniff(b"\xef\xbb\xbf  <?xml version=\"1.0\"?>"),
+            "application/xml"
+        );
+        assert_eq!(sniff(b"\x7fELF\x02\x01\x01"), "application/x-executable");
+        assert_eq!(sniff(b"Hello, World!\n"), "text/plain");
+        assert_eq!(sniff("caf\u{e9}".as_bytes()), "text/plain");
+        assert_eq!(sniff(&"\u{e9}".as_bytes()[..1]), "text/plain");
+        assert_eq!(sniff(&[0, 1, 2, 3]), "application/octet-stream");
+        let mut tar = vec![0; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(sniff(&tar), "application/x-tar");
+    }
+
+    #[test]
+    fn extensions() {
+        let filter = Filter::new().include_extensions(["rpm", ".XML"]);
+        assert!(filter.allows("package.rpm", Vec::new));
+        assert!(filter.allows("REPOMD.XML", Vec::new));
+        assert!(!filter.allows("readme.txt", Vec::new));
+        assert!(!filter.allows("rpm", Vec::new));
+
+        let filter = Filter::new().exclude_extensions(["exe"]);
+        assert!(filter.allows("readme.txt", Vec::new));
+        assert!(filter.allows("README", Vec::new));
+        assert!(!filter.allows("setup.EXE", Vec::new));
+    }
+
+    #[test]
+    fn types() {
+        let elf = || b"\x7fELF".to_vec();
+        let text = || b"text".to_vec();
+        let filter = Filter::new().exclude_types(["application/x-executable"]);
+        assert!(!filter.allows("tool", elf));
+        assert!(filter.allows("tool", text));
+
+        let filter = Filter::new().include_types(["text/*"]);
+        assert!(filter.allows("notes", text));
+        assert!(!filter.allows("notes", elf));
+
+        let filter = Filter::new()
+            .include_extensions(["sh"])
+            .include_types(["text/plain"])
+            .exclude_extensions(["log"]);
+        assert!(filter.allows("run.sh", elf));
+        assert!(filter.allows("notes", text));
+        assert!(!filter.allows("debug.log", text));
+    }
+
+    #[test]
+    fn sniffs_lazily() {
+        let filter = Filter::new().include_extensions(["txt"]);
+        assert!(filter.allows("a.txt", || panic!("sniffed without type rules")));
+    }
+}
@@ -0,0 +1,914 @@
+//! Programmatic authoring of small ISO 9660 images for tests.
+//!
+//! Enabled with the `test-util` feature. [`IsoBuilder`] lays out a primary volume with optional
+//! Rock Ridge entries, an optional Joliet supplementary volume and optional El Torito boot images,
+//! so tests can describe the tree they need instead of relying on binary fixtures:
+//!
+//! ```
+//! use unftp_sbe_iso::fixture::IsoBuilder;
+//!
+//! let image = IsoBuilder::new()
+//!     .joliet(true)
+//!     .file("/docs/readme.txt", b"hello")
+//!     .dir("/empty")
+//!     .build_file();
+//! let storage = unftp_sbe_iso::Storage::new(image.path());
+//! ```
+//!
+//! Names given to the builder are used as-is for the Joliet and Rock Ridge namespaces. The primary
+//! (ISO 9660) identifier is derived by upper-casing the name and adding the `;1` version suffix to
+//! files, unless an explicit one is set with [`IsoBuilder::primary_name`].
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const BLOCK: usize = 2048;
+
+/// The recording time stamped on every record and descriptor: 2024-01-02 03:04:05 UTC.
+const RECORDED: [u8; 7] = [124, 1, 2, 3, 4, 5, 0];
+const RECORDED_ASCII: &[u8; 16] = b"2024010203040500";
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Dir(Vec<Node>),
+    File(Vec<u8>),
+    Symlink(String),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    name: String,
+    primary: Option<String>,
+    /// The recording time, if not [`RECORDED`]
+    recorded: Option<[u8; 7]>,
+    /// File flags set besides the directory flag
+    flags: u8,
+    kind: Kind,
+}
+
+impl Node {
+    fn dir(name: &str) -> Self {
+        Node {
+            name: name.to_string(),
+            primary: None,
+            recorded: None,
+            flags: 0,
+            kind: Kind::Dir(Vec::new()),
+        }
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<Node> {
+        match &mut self.kind {
+            Kind::Dir(children) => children,
+            _ => panic!("{:?} is not a directory", self.name),
+        }
+    }
+
+    fn primary_identifier(&self) -> String {
+        if let Some(primary) = &self.primary {
+            return primary.clone();
+        }
+        let upper = self.name.to_uppercase();
+        match self.kind {
+            Kind::Dir(_) => upper,
+            _ if upper.contains('.') => format!("{upper};1"),
+            _ => format!("{upper}.;1"),
+        }
+    }
+
+    fn joliet_identifier(&self) -> String {
+        match self.kind {
+            Kind::Dir(_) => self.name.clone(),
+            _ => format!("{};1", self.name),
+        }
+    }
+
+    fn mode(&self) -> u32 {
+        match self.kind {
+            Kind::Dir(_) => 0o040755,
+            Kind::File(_) => 0o100644,
+            Kind::Symlink(_) => 0o120777,
+        }
+    }
+}
+
+/// Describes an ISO 9660 image and serializes it. See the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct IsoBuilder {
+    volume_id: String,
+    joliet: bool,
+    rock_ridge: bool,
+    root: Node,
+    /// El Torito boot images with their platform IDs.
+    boot: Vec<(u8, Vec<u8>)>,
+}
+
+impl Default for IsoBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IsoBuilder {
+    /// Creates a builder for an empty image without Joliet or Rock Ridge extensions.
+    pub fn new() -> Self {
+        IsoBuilder {
+            volume_id: "TEST".to_string(),
+            joliet: false,
+            rock_ridge: false,
+            root: Node::dir(""),
+            boot: Vec::new(),
+        }
+    }
+
+    /// Sets the volume identifier. Defaults to `TEST`.
+    pub fn volume_id(mut self, id: &str) -> Self {
+        self.volume_id = id.to_string();
+        self
+    }
+
+    /// Adds a Joliet supplementary volume descriptor and directory hierarchy.
+    pub fn joliet(mut self, enabled: bool) -> Self {
+        self.joliet = enabled;
+        self
+    }
+
+    /// Adds Rock Ridge (RRIP 1.09) entries to the primary directory hierarchy.
+    pub fn rock_ridge(mut self, enabled: bool) -> Self {
+        self.rock_ridge = enabled;
+        self
+    }
+
+    /// Makes the image bootable with an El Torito boot catalog naming the given BIOS boot image,
+    /// without emulation. The first boot image added is the initial entry of the catalog.
+    pub fn boot_image(mut self, contents: &[u8]) -> Self {
+        self.boot.push((0, contents.to_vec()));
+        self
+    }
+
+    /// Like [`boot_image`](Self::boot_image), for a UEFI boot image.
+    pub fn efi_image(mut self, contents: &[u8]) -> Self {
+        self.boot.push((0xEF, contents.to_vec()));
+        self
+    }
+
+    /// Adds a directory, creating missing parents.
+    pub fn dir(mut self, path: &str) -> Self {
+        self.insert(path, Kind::Dir(Vec::new()));
+        self
+    }
+
+    /// Adds a regular file, creating missing parents.
+    pub fn file(mut self, path: &str, contents: &[u8]) -> Self {
+        self.insert(path, Kind::File(contents.to_vec()));
+        self
+    }
+
+    /// Adds a Rock Ridge symbolic link, creating missing parents. Symbolic links are left out of
+    /// the Joliet hierarchy, which can't represent them.
+    pub fn symlink(mut self, path: &str, target: &str) -> Self {
+        self.insert(path, Kind::Symlink(target.to_string()));
+        self
+    }
+
+    /// Overrides the primary (ISO 9660) identifier of an entry added earlier. The identifier is
+    /// written verbatim, so files need their own version suffix, e.g. `README.TXT;1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no entry exists at `path`.
+    pub fn primary_name(mut self, path: &str, identifier: &str) -> Self {
+        let node = self
+            .node_mut(path)
+            .unwrap_or_else(|| panic!("no entry at {path:?}"));
+        node.primary = Some(identifier.to_string());
+        self
+    }
+
+    /// Sets the recording time of an entry added earlier, which is its modification time, to
+    /// midnight UTC of the given day. Entries are recorded on 2024-01-02 otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no entry exists at `path` or the year isn't between 1900 and 2155.
+    pub fn recorded(mut self, path: &str, year: u16, month: u8, day: u8) -> Self {
+        let year = u8::try_from(year - 1900).expect("a year between 1900 and 2155");
+        let node = self
+            .node_mut(path)
+            .unwrap_or_else(|| panic!("no entry at {path:?}"));
+        node.recorded = Some([year, month, day, 0, 0, 0, 0]);
+        self
+    }
+
+    /// Marks an entry added earlier hidden, setting the existence bit of its records, as
+    /// mastering tools do for files that aren't meant to be listed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no entry exists at `path`.
+    pub fn hidden(mut self, path: &str) -> Self {
+        let node = self
+            .node_mut(path)
+            .unwrap_or_else(|| panic!("no entry at {path:?}"));
+        node.flags |= 0x01;
+        self
+    }
+
+    fn node_mut(&mut self, path: &str) -> Option<&mut Node> {
+        let mut node = &mut self.root;
+        for name in path.split('/').filter(|c| !c.is_empty()) {
+            node = match &mut node.kind {
+                Kind::Dir(children) => children.iter_mut().find(|c| c.name == name)?,
+                _ => return None,
+            };
+        }
+        Some(node)
+    }
+
+    fn insert(&mut self, path: &str, kind: Kind) {
+        let mut components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        let name = components.pop().expect("path must not be the root");
+        let mut parent = &mut self.root;
+        for component in components {
+            let children = parent.children_mut();
+            let index = match children.iter().position(|c| c.name == component) {
+                Some(index) => index,
+                None => {
+                    children.push(Node::dir(component));
+                    children.len() - 1
+                }
+            };
+            parent = &mut children[index];
+        }
+        let children = parent.children_mut();
+        children.retain(|c| c.name != name);
+        children.push(Node {
+            name: name.to_string(),
+            primary: None,
+            recorded: None,
+            flags: 0,
+            kind,
+        });
+    }
+
+    /// Serializes the image.
+    pub fn build(&self) -> Vec<u8> {
+        Layout::new(self).write()
+    }
+
+    /// Serializes the image into a temporary file that is removed when the returned value is
+    /// dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file can't be written.
+    pub fn build_file(&self) -> TempImage {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "unftp-sbe-iso-{}-{}.iso",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, self.build()).expect("could not write the test image");
+        TempImage { path }
+    }
+}
+
+/// Encodes `image` as raw Mode 1 sectors of 2352 bytes with their EDC and ECC, the way a BIN/CUE
+/// dump stores it, to be read with [`RawSectors`](crate::RawSectors).
+pub fn raw_sectors(image: &[u8]) -> Vec<u8> {
+    crate::raw::encode(image)
+}
+
+/// An image written to a temporary file by [`IsoBuilder::build_file`]. The file is removed on
+/// drop.
+#[derive(Debug)]
+pub struct TempImage {
+    path: PathBuf,
+}
+
+impl TempImage {
+    /// The location of the image.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempImage {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Which directory hierarchy is being laid out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tree {
+    Primary,
+    Joliet,
+}
+
+/// A directory in one of the hierarchies, in path table order.
+struct DirLayout<'a> {
+    node: &'a Node,
+    parent: usize,
+    children: Vec<&'a Node>,
+    lba: u32,
+    size: u32,
+}
+
+struct Layout<'a> {
+    builder: &'a IsoBuilder,
+    primary: Vec<DirLayout<'a>>,
+    joliet: Vec<DirLayout<'a>>,
+    files: Vec<(&'a Node, u32)>,
+    /// The boot catalog and the boot images, if the image is bootable.
+    boot_catalog: u32,
+    boot_images: Vec<u32>,
+    path_tables: [u32; 4],
+    path_table_sizes: [u32; 2],
+    total: u32,
+}
+
+impl<'a> Layout<'a> {
+    fn new(builder: &'a IsoBuilder) -> Self {
+        let mut layout = Layout {
+            builder,
+            primary: Self::directories(&builder.root, Tree::Primary),
+            joliet: if builder.joliet {
+                Self::directories(&builder.root, Tree::Joliet)
+            } else {
+                Vec::new()
+            },
+            files: Vec::new(),
+            boot_catalog: 0,
+            boot_images: Vec::new(),
+            path_tables: [0; 4],
+            path_table_sizes: [0; 2],
+            total: 0,
+        };
+
+        // System area and the descriptors: primary, optional Joliet, optional boot record and the
+        // set terminator.
+        let mut next = 16 + 2 + u32::from(builder.joliet) + u32::from(!builder.boot.is_empty());
+
+        layout.path_table_sizes = [
+            layout.path_table(Tree::Primary, false).len() as u32,
+            layout.path_table(Tree::Joliet, false).len() as u32,
+        ];
+        for (i, size) in [0, 0, 1, 1].into_iter().enumerate() {
+            layout.path_tables[i] = next;
+            next += blocks(layout.path_table_sizes[size] as usize).max(1);
+        }
+
+        for tree in [Tree::Primary, Tree::Joliet] {
+            for index in 0..layout.dirs(tree).len() {
+                let size = layout.directory_records(tree, index).len() as u32;
+                let dir = &mut layout.dirs_mut(tree)[index];
+                dir.lba = next;
+                dir.size = size;
+                next += blocks(size as usize);
+            }
+        }
+
+        let mut files = Vec::new();
+        collect_files(&builder.root, &mut files);
+        for file in files {
+            if let Kind::File(contents) = &file.kind {
+                if contents.is_empty() {
+                    layout.files.push((file, 0));
+                } else {
+                    layout.files.push((file, next));
+                    next += blocks(contents.len());
+                }
+            }
+        }
+        if !builder.boot.is_empty() {
+            layout.boot_catalog = next;
+            next += 1;
+            for (_, contents) in &builder.boot {
+                layout.boot_images.push(next);
+                next += blocks(contents.len()).max(1);
+            }
+        }
+        layout.total = next;
+        layout
+    }
+
+    fn dirs(&self, tree: Tree) -> &[DirLayout<'a>] {
+        match tree {
+            Tree::Primary => &self.primary,
+            Tree::Joliet => &self.joliet,
+        }
+    }
+
+    fn dirs_mut(&mut self, tree: Tree) -> &mut Vec<DirLayout<'a>> {
+        match tree {
+            Tree::Primary => &mut self.primary,
+            Tree::Joliet => &mut self.joliet,
+        }
+    }
+
+    /// Lists the directories of a hierarchy breadth first, which is the path table order.
+    fn directories(root: &'a Node, tree: Tree) -> Vec<DirLayout<'a>> {
+        let mut dirs = vec![DirLayout {
+            node: root,
+            parent: 0,
+            children: Vec::new(),
+            lba: 0,
+            size: 0,
+        }];
+        let mut i = 0;
+        while i < dirs.len() {
+            let Kind::Dir(children) = &dirs[i].node.kind else {
+                unreachable!()
+            };
+            let mut children: Vec<&Node> = children
+                .iter()
+                .filter(|c| tree == Tree::Primary || !matches!(c.kind, Kind::Symlink(_)))
+                .collect();
+            children.sort_by_key(|c| identifier_bytes(c, tree));
+            for child in &children {
+                if matches!(child.kind, Kind::Dir(_)) {
+                    dirs.push(DirLayout {
+                        node: child,
+                        parent: i,
+                        children: Vec::new(),
+                        lba: 0,
+                        size: 0,
+                    });
+                }
+            }
+            dirs[i].children = children;
+            i += 1;
+        }
+        dirs
+    }
+
+    fn dir_index(&self, tree: Tree, node: &Node) -> usize {
+        self.dirs(tree)
+            .iter()
+            .position(|d| std::ptr::eq(d.node, node))
+            .expect("directory is laid out")
+    }
+
+    /// The location of a file's contents, or 0 while directories are still being sized.
+    fn file_lba(&self, node: &Node) -> u32 {
+        self.files
+            .iter()
+            .find(|(f, _)| std::ptr::eq(*f, node))
+            .map_or(0, |(_, lba)| *lba)
+    }
+
+    fn path_table(&self, tree: Tree, big_endian: bool) -> Vec<u8> {
+        let mut table = Vec::new();
+        for (i, dir) in self.dirs(tree).iter().enumerate() {
+            let id = if i == 0 {
+                vec![0]
+            } else {
+                identifier_bytes(dir.node, tree)
+            };
+            table.push(id.len() as u8);
+            table.push(0);
+            let (lba, parent) = (dir.lba, dir.parent as u16 + 1);
+            if big_endian {
+                table.extend_from_slice(&lba.to_be_bytes());
+                table.extend_from_slice(&parent.to_be_bytes());
+            } else {
+                table.extend_from_slice(&lba.to_le_bytes());
+                table.extend_from_slice(&parent.to_le_bytes());
+            }
+            table.extend_from_slice(&id);
+            if id.len() % 2 == 1 {
+                table.push(0);
+            }
+        }
+        table
+    }
+
+    /// Serializes the records of a directory, padded so no record crosses a block boundary.
+    fn directory_records(&self, tree: Tree, index: usize) -> Vec<u8> {
+        let dirs = self.dirs(tree);
+        let dir = &dirs[index];
+        let parent = &dirs[dir.parent];
+        let rr = self.builder.rock_ridge && tree == Tree::Primary;
+
+        let mut records = Vec::new();
+        let mut dot_susp = Vec::new();
+        if rr {
+            if index == 0 {
+                dot_susp.extend(susp_sp());
+                dot_susp.extend(susp_er());
+            }
+            dot_susp.extend(susp_px(dir.node.mode()));
+            dot_susp.extend(susp_tf(&RECORDED));
+        }
+        records.push(record(&[0], dir.lba, dir.size, true, &dot_susp));
+        let dotdot_susp = if rr {
+            [susp_px(parent.node.mode()), susp_tf(&RECORDED)].concat()
+        } else {
+            Vec::new()
+        };
+        records.push(record(&[1], parent.lba, parent.size, true, &dotdot_susp));
+
+        for child in &dir.children {
+            let recorded = child.recorded.unwrap_or(RECORDED);
+            let susp = if rr {
+                let mut susp = susp_nm(&child.name);
+                susp.extend(susp_px(child.mode()));
+                susp.extend(susp_tf(&recorded));
+                if let Kind::Symlink(target) = &child.kind {
+                    susp.extend(susp_sl(target));
+                }
+                susp
+            } else {
+                Vec::new()
+            };
+            let id = identifier_bytes(child, tree);
+            let mut rec = match &child.kind {
+                Kind::Dir(_) => {
+                    let d = &dirs[self.dir_index(tree, child)];
+                    record(&id, d.lba, d.size, true, &susp)
+                }
+                Kind::File(contents) => record(
+                    &id,
+                    self.file_lba(child),
+                    contents.len() as u32,
+                    false,
+                    &susp,
+                ),
+                Kind::Symlink(_) => record(&id, 0, 0, false, &susp),
+            };
+            rec[18..25].copy_from_slice(&recorded);
+            rec[25] |= child.flags;
+            records.push(rec);
+        }
+
+        let mut out = Vec::new();
+        for rec in records {
+            let used = out.len() % BLOCK;
+            if used + rec.len() > BLOCK {
+                out.resize(out.len() + BLOCK - used, 0);
+            }
+            out.extend(rec);
+        }
+        out.resize(blocks(out.len()) as usize * BLOCK, 0);
+        out
+    }
+
+    fn write(&self) -> Vec<u8> {
+        let mut image = vec![0; self.total as usize * BLOCK];
+        let mut put = |lba: u32, bytes: &[u8]| {
+            let start = lba as usize * BLOCK;
+            image[start..start + bytes.len()].copy_from_slice(bytes);
+        };
+
+        let mut lba = 16;
+        put(lba, &self.descriptor(Tree::Primary));
+        if self.builder.joliet {
+            lba += 1;
+            put(lba, &self.descriptor(Tree::Joliet));
+        }
+        if !self.builder.boot.is_empty() {
+            lba += 1;
+            put(lba, &self.boot_record());
+            put(self.boot_catalog, &self.boot_catalog());
+            for ((_, contents), image) in self.builder.boot.iter().zip(&self.boot_images) {
+                put(*image, contents);
+            }
+        }
+        let mut terminator = [0u8; 7];
+        terminator[0] = 255;
+        terminator[1..6].copy_from_slice(b"CD001");
+        terminator[6] = 1;
+        put(lba + 1, &terminator);
+
+        put(self.path_tables[0], &self.path_table(Tree::Primary, false));
+        put(self.path_tables[1], &self.path_table(Tree::Primary, true));
+        if self.builder.joliet {
+            put(self.path_tables[2], &self.path_table(Tree::Joliet, false));
+            put(self.path_tables[3], &self.path_table(Tree::Joliet, true));
+        }
+
+        for tree in [Tree::Primary, Tree::Joliet] {
+            for (index, dir) in self.dirs(tree).iter().enumerate() {
+                put(dir.lba, &self.directory_records(tree, index));
+            }
+        }
+
+        for (file, lba) in &self.files {
+            if let Kind::File(contents) = &file.kind
+                && !contents.is_empty()
+            {
+                put(*lba, contents);
+            }
+        }
+        image
+    }
+
+    fn boot_record(&self) -> Vec<u8> {
+        let mut d = vec![0u8; BLOCK];
+        d[1..6].copy_from_slice(b"CD001");
+        d[6] = 1;
+        d[7..30].copy_from_slice(b"EL TORITO SPECIFICATION");
+        d[0x47..0x4B].copy_from_slice(&self.boot_catalog.to_le_bytes());
+        d
+    }
+
+    /// The validation entry and the initial entry, followed by a section per further image.
+    fn boot_catalog(&self) -> Vec<u8> {
+        let entry = |index: usize| {
+            let mut e = [0u8; 32];
+            e[0] = 0x88;
+            let sectors = self.builder.boot[index].1.len().div_ceil(512).max(1);
+            e[6..8].copy_from_slice(&(sectors.min(u16::MAX as usize) as u16).to_le_bytes());
+            e[8..12].copy_from_slice(&self.boot_images[index].to_le_bytes());
+            e
+        };
+        let mut catalog = vec![0u8; 32];
+        catalog[0] = 1;
+        catalog[1] = self.builder.boot[0].0;
+        catalog[30] = 0x55;
+        catalog[31] = 0xAA;
+        let sum = catalog.chunks_exact(2).fold(0u16, |sum, word| {
+            sum.wrapping_add(u16::from_le_bytes([word[0], word[1]]))
+        });
+        catalog[28..30].copy_from_slice(&0u16.wrapping_sub(sum).to_le_bytes());
+        catalog.extend(entry(0));
+        let rest = self.builder.boot.len() - 1;
+        for index in 1..=rest {
+            let mut header = [0u8; 32];
+            header[0] = if index == rest { 0x91 } else { 0x90 };
+            header[1] = self.builder.boot[index].0;
+            header[2..4].copy_from_slice(&1u16.to_le_bytes());
+            catalog.extend(header);
+            catalog.extend(entry(index));
+        }
+        catalog
+    }
+
+    fn descriptor(&self, tree: Tree) -> Vec<u8> {
+        let joliet = tree == Tree::Joliet;
+        let text = |s: &str, len: usize| -> Vec<u8> {
+            let mut field = if joliet {
+                s.encode_utf16().flat_map(u16::to_be_bytes).collect()
+            } else {
+                s.as_bytes().to_vec()
+            };
+            let pad: &[u8] = if joliet { &[0, b' '] } else { b" " };
+            while field.len() < len {
+                field.extend_from_slice(pad);
+            }
+            field.truncate(len);
+            field
+        };
+
+        let mut d = vec![0u8; BLOCK];
+        d[0] = if joliet { 2 } else { 1 };
+        d[1..6].copy_from_slice(b"CD001");
+        d[6] = 1;
+        d[8..40].copy_from_slice(&text("", 32));
+        d[40..72].copy_from_slice(&text(&self.builder.volume_id, 32));
+        d[80..88].copy_from_slice(&both32(self.total));
+        if joliet {
+            d[88..91].copy_from_slice(&[0x25, 0x2F, 0x45]);
+        }
+        d[120..124].copy_from_slice(&both16(1));
+        d[124..128].copy_from_slice(&both16(1));
+        d[128..132].copy_from_slice(&both16(BLOCK as u16));
+        let (size, l, m) = match tree {
+            Tree::Primary => (
+                self.path_table_sizes[0],
+                self.path_tables[0],
+                self.path_tables[1],
+            ),
+            Tree::Joliet => (
+                self.path_table_sizes[1],
+                self.path_tables[2],
+                self.path_tables[3],
+            ),
+        };
+        d[132..140].copy_from_slice(&both32(size));
+        d[140..144].copy_from_slice(&l.to_le_bytes());
+        d[148..152].copy_from_slice(&m.to_be_bytes());
+        let root = &self.dirs(tree)[0];
+        d[156..190].copy_from_slice(&record(&[0], root.lba, root.size, true, &[]));
+        d[190..318].copy_from_slice(&text("", 128));
+        d[318..446].copy_from_slice(&text("", 128));
+        d[446..574].copy_from_slice(&text("", 128));
+        d[574..702].copy_from_slice(&text("UNFTP-SBE-ISO FIXTURE", 128));
+        d[702..813].copy_from_slice(&text("", 111));
+        for start in [813, 830] {
+            d[start..start + 16].copy_from_slice(RECORDED_ASCII);
+        }
+        for start in [847, 864] {
+            d[start..start + 16].copy_from_slice(b"0000000000000000");
+        }
+        d[881] = 1;
+        d
+    }
+}
+
+fn collect_files<'a>(node: &'a Node, files: &mut Vec<&'a Node>) {
+    if let Kind::Dir(children) = &node.kind {
+        for child in children {
+            match child.kind {
+                Kind::Dir(_) => collect_files(child, files),
+                Kind::File(_) => files.push(child),
+                Kind::Symlink(_) => {}
+            }
+        }
+    }
+}
+
+fn identifier_bytes(node: &Node, tree: Tree) -> Vec<u8> {
+    match tree {
+        Tree::Primary => node.primary_identifier().into_bytes(),
+        Tree::Joliet => node
+            .joliet_identifier()
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect(),
+    }
+}
+
+fn blocks(len: usize) -> u32 {
+    len.div_ceil(BLOCK) as u32
+}
+
+fn both16(v: u16) -> [u8; 4] {
+    let (le, be) = (v.to_le_bytes(), v.to_be_bytes());
+    [le[0], le[1], be[0], be[1]]
+}
+
+fn both32(v: u32) -> [u8; 8] {
+    let mut out = [0; 8];
+    out[..4].copy_from_slice(&v.to_le_bytes());
+    out[4..].copy_from_slice(&v.to_be_bytes());
+    out
+}
+
+/// Serializes a directory record (ECMA-119 § 9.1).
+///
+/// # Panics
+///
+/// Panics if the record doesn't fit in the 255 bytes allowed.
+fn record(id: &[u8], lba: u32, size: u32, dir: bool, susp: &[u8]) -> Vec<u8> {
+    let mut rec = vec![0u8; 33];
+    rec[2..10].copy_from_slice(&both32(lba));
+    rec[10..18].copy_from_slice(&both32(size));
+    rec[18..25].copy_from_slice(&RECORDED);
+    rec[25] = if dir { 0x02 } else { 0 };
+    rec[28..32].copy_from_slice(&both16(1));
+    rec[32] = id.len() as u8;
+    rec.extend_from_slice(id);
+    if id.len().is_multiple_of(2) {
+        rec.push(0);
+    }
+    rec.extend_from_slice(susp);
+    assert!(rec.len() <= 255, "directory record too long: {id:?}");
+    rec[0] = rec.len() as u8;
+    rec
+}
+
+fn susp_entry(sig: &[u8; 2], data: &[u8]) -> Vec<u8> {
+    let mut entry = vec![sig[0], sig[1], (data.len() + 4) as u8, 1];
+    entry.extend_from_slice(data);
+    entry
+}
+
+fn susp_sp() -> Vec<u8> {
+    susp_entry(b"SP", &[0xBE, 0xEF, 0])
+}
+
+fn susp_er() -> Vec<u8> {
+    let (id, description, source) = (
+        &b"RRIP_1991A"[..],
+        &b"ROCK RIDGE"[..],
+        &b"UNFTP-SBE-ISO"[..],
+    );
+    let mut data = vec![
+        id.len() as u8,
+        description.len() as u8,
+        source.len() as u8,
+        1,
+    ];
+    data.extend_from_slice(id);
+    data.extend_from_slice(description);
+    data.extend_from_slice(source);
+    susp_entry(b"ER", &data)
+}
+
+fn susp_px(mode: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    for v in [mode, 1, 0, 0, 0] {
+        data.extend_from_slice(&both32(v));
+    }
+    susp_entry(b"PX", &data)
+}
+
+fn susp_tf(recorded: &[u8; 7]) -> Vec<u8> {
+    let mut data = vec![0x02];
+    data.extend_from_slice(recorded);
+    susp_entry(b"TF", &data)
+}
+
+fn susp_nm(name: &str) -> Vec<u8> {
+    let mut data = vec![0];
+    data.extend_from_slice(name.as_bytes());
+    susp_entry(b"NM", &data)
+}
+
+fn susp_sl(target: &str) -> Vec<u8> {
+    let mut data = vec![0];
+    if target.starts_with('/') {
+        data.extend_from_slice(&[0x08, 0]);
+    }
+    for component in target.split('/').filter(|c| !c.is_empty()) {
+        match component {
+            "." => data.extend_from_slice(&[0x02, 0]),
+            ".." => data.extend_from_slice(&[0x04, 0]),
+            name => {
+                data.extend_from_slice(&[0, name.len() as u8]);
+                data.extend_from_slice(name.as_bytes());
+            }
+        }
+    }
+    susp_entry(b"SL", &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IsoBuilder;
+    use crate::Storage;
+    use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+
+    fn tree() -> IsoBuilder {
+        IsoBuilder::new()
+            .file("/readme.txt", b"hello")
+            .file("/docs/guide.txt", &[7; 5000])
+            .dir("/empty")
+    }
+
+    async fn names(storage: &Storage, path: &str) -> Vec<String> {
+        let mut names: Vec<String> = storage
+            .list(&DefaultUser, path)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path.to_string_lossy().into_owned())
+            .filter(|n| n != "." && n != "..")
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn primary_only() {
+        let image = tree().build_file();
+        let storage = Storage::new(image.path());
+        assert_eq!(names(&storage, "/").await, ["DOCS", "EMPTY", "README.TXT"]);
+        assert_eq!(names(&storage, "/docs").await, ["GUIDE.TXT"]);
+    }
+
+    #[tokio::test]
+    async fn joliet_names() {
+        let image = tree().joliet(true).build_file();
+        let storage = Storage::new(image.path());
+        assert_eq!(names(&storage, "/").await, ["docs", "empty", "readme.txt"]);
+    }
+
+    #[tokio::test]
+    async fn rock_ridge_names_and_links() {
+        let image = tree()
+            .rock_ridge(true)
+            .joliet(true)
+            .symlink("/link", "docs/guide.txt")
+            .build_file();
+        let storage = Storage::new(image.path());
+        assert_eq!(
+            names(&storage, "/").await,
+            ["docs", "empty", "link", "readme.txt"]
+        );
+        let meta = storage.metadata(&DefaultUser, "/link").await.unwrap();
+        assert!(meta.sym);
+    }
+
+    #[tokio::test]
+    async fn file_contents() {
+        use tokio::io::AsyncReadExt;
+
+        let image = tree().joliet(true).build_file();
+        let storage = Storage::new(image.path());
+        let mut contents = Vec::new();
+        storage
+            .get(&DefaultUser, "/docs/guide.txt", 0)
+            .await
+            .unwrap()
+            .read_to_end(&mut contents)
+            .await
+            .unwrap();
+        assert_eq!(contents, vec![7; 5000]);
+    }
+}
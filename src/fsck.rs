@@ -0,0 +1,314 @@
+//! A structural check of a whole image, as run by [`Storage::fsck`](crate::Storage::fsck).
+
+use crate::{
+    IsoReader, Namespace, RetryPolicy,
+    descriptor::{self, DescriptorKind, SECTOR},
+    interleave,
+    retry::Retrying,
+    source::{IsoSource, SourceReader},
+};
+use cdfs::{DirectoryEntry, ExtraAttributes, ISO9660, ISODirectory};
+use std::{
+    collections::HashSet,
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// What [`Storage::fsck`](crate::Storage::fsck) found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsckReport {
+    /// The directories walked, over all hierarchies
+    pub directories: u64,
+    /// The files and symbolic links seen, over all hierarchies
+    pub files: u64,
+    /// The problems found, in the order they were found
+    pub problems: Vec<Problem>,
+}
+
+impl FsckReport {
+    /// Tells whether no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// How images with structural problems are treated, as set with
+/// [`StorageBuilder::validation`](crate::StorageBuilder::validation).
+///
+/// Checking an image reads every directory of it, once, the first time it is opened and again
+/// whenever it changes, like [`Storage::fsck`](crate::Storage::fsck) does. Strict validation in
+/// CI and lenient serving in production take the same images:
+///
+/// ```
+/// use unftp_sbe_iso::{Storage, ValidationMode};
+///
+/// let ci = Storage::builder("/srv/images/release.iso")
+///     .validation(ValidationMode::Strict)
+///     .build();
+/// let production = Storage::builder("/srv/images/release.iso")
+///     .validation(ValidationMode::Lenient)
+///     .build();
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Refuses images with any problem, failing every command on them
+    Strict,
+    /// Serves images that cdfs can open, without checking them further
+    #[default]
+    Standard,
+    /// Repairs the volume descriptors where that is safe, like
+    /// [`StorageBuilder::lenient`](crate::StorageBuilder::lenient), and serves what is
+    /// readable, logging every problem as a warning
+    Lenient,
+}
+
+/// A problem with the structure of an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// The hierarchy the problem was found in, or `None` for problems with the volume as a whole
+    pub namespace: Option<Namespace>,
+    /// The path of the entry with the problem within its hierarchy, `/` for the volume
+    pub path: PathBuf,
+    /// What is wrong
+    pub kind: ProblemKind,
+}
+
+/// The kinds of [`Problem`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// The volume descriptors can't be read
+    Descriptors(String),
+    /// There is no primary volume descriptor
+    NoPrimaryVolume,
+    /// A volume uses a logical block size other than 2048 bytes
+    BlockSize(u16),
+    /// A volume is larger than the image, which is what a truncated image looks like
+    Truncated {
+        /// The size of the volume in bytes
+        volume_len: u64,
+        /// The size of the image in bytes
+        image_len: u64,
+    },
+    /// The directory hierarchies can't be opened
+    Unreadable(String),
+    /// A record of a directory can't be decoded. The records after it are not checked.
+    BadRecord(String),
+    /// The extent of an entry reaches beyond the end of the image
+    ExtentOutOfBounds {
+        /// The first logical block of the extent
+        extent: u32,
+        /// The byte the extent ends at
+        end: u64,
+        /// The size of the image in bytes
+        image_len: u64,
+    },
+    /// A directory is its own ancestor
+    Cycle {
+        /// The first logical block of the directory
+        extent: u32,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(namespace) = self.namespace {
+            write!(f, "{namespace:?} ")?;
+        }
+        write!(f, "{}: {}", self.path.display(), self.kind)
+    }
+}
+
+impl fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProblemKind::Descriptors(e) => write!(f, "unreadable volume descriptors: {e}"),
+            ProblemKind::NoPrimaryVolume => write!(f, "no primary volume descriptor"),
+            ProblemKind::BlockSize(size) => write!(f, "unsupported logical block size {size}"),
+            ProblemKind::Truncated {
+                volume_len,
+                image_len,
+            } => write!(
+                f,
+                "the volume is {volume_len} bytes but the image only {image_len}; is the image truncated?"
+            ),
+            ProblemKind::Unreadable(e) => write!(f, "unreadable directory hierarchy: {e}"),
+            ProblemKind::BadRecord(e) => write!(f, "undecodable directory record: {e}"),
+            ProblemKind::ExtentOutOfBounds {
+                extent,
+                end,
+                image_len,
+            } => write!(
+                f,
+                "extent at block {extent} ends at byte {end} but the image is only {image_len} bytes long"
+            ),
+            ProblemKind::Cycle { extent } => {
+                write!(f, "directory at block {extent} is its own ancestor")
+            }
+        }
+    }
+}
+
+/// Checks the structure of the image in `source`.
+pub(crate) fn check(source: Arc<dyn IsoSource>, retry: RetryPolicy) -> io::Result<FsckReport> {
+    let len = source.len()?;
+    let mut report = FsckReport::default();
+    check_descriptors(&*source, len, &mut report);
+    match ISO9660::new(Retrying::new(SourceReader::new(source), retry)) {
+        Ok(iso) => check_hierarchies(&iso, len, &mut report),
+        Err(e) => report
+            .problems
+            .push(volume(ProblemKind::Unreadable(e.to_string()))),
+    }
+    Ok(report)
+}
+
+/// A problem with the volume as a whole.
+fn volume(kind: ProblemKind) -> Problem {
+    Problem {
+        namespace: None,
+        path: PathBuf::from("/"),
+        kind,
+    }
+}
+
+/// Checks the volume descriptors of the image in `source`.
+fn check_descriptors(source: &dyn IsoSource, len: u64, report: &mut FsckReport) {
+    let descriptors = match descriptor::read(source) {
+        Ok(descriptors) => descriptors,
+        Err(e) => {
+            report
+                .problems
+                .push(volume(ProblemKind::Descriptors(e.to_string())));
+            return;
+        }
+    };
+    let mut primary = false;
+    for d in &descriptors {
+        let info = match &d.kind {
+            DescriptorKind::Primary(info) => {
+                primary = true;
+                info
+            }
+            DescriptorKind::Supplementary(info) => info,
+            _ => continue,
+        };
+        if usize::from(info.logical_block_size) != SECTOR {
+            report
+                .problems
+                .push(volume(ProblemKind::BlockSize(info.logical_block_size)));
+        }
+        let volume_len = u64::from(info.volume_space_size) * SECTOR as u64;
+        if volume_len > len {
+            report.problems.push(volume(ProblemKind::Truncated {
+                volume_len,
+                image_len: len,
+            }));
+        }
+    }
+    if !primary {
+        report.problems.push(volume(ProblemKind::NoPrimaryVolume));
+    }
+    report.problems.dedup();
+}
+
+/// Walks every directory hierarchy of the image.
+fn check_hierarchies(iso: &ISO9660<IsoReader>, len: u64, report: &mut FsckReport) {
+    let primary = if iso.is_rr() {
+        Namespace::RockRidge
+    } else {
+        Namespace::Primary
+    };
+    let roots = [
+        (primary, iso.root_at(0)),
+        (Namespace::Joliet, iso.root_at(1)),
+    ];
+    for (namespace, root) in roots {
+        if let Some(root) = root {
+            Walk {
+                namespace,
+                len,
+                report: &mut *report,
+                visited: HashSet::new(),
+            }
+            .dir(root, Path::new("/"), &mut Vec::new());
+        }
+    }
+}
+
+struct Walk<'a> {
+    namespace: Namespace,
+    len: u64,
+    report: &'a mut FsckReport,
+    /// The extents of the directories walked, so that each is walked once
+    visited: HashSet<u32>,
+}
+
+impl Walk<'_> {
+    fn problem(&mut self, path: &Path, kind: ProblemKind) {
+        self.report.problems.push(Problem {
+            namespace: Some(self.namespace),
+            path: path.to_path_buf(),
+            kind,
+        });
+    }
+
+    /// Checks that the extent of `entry` lies within the image.
+    fn extent<E: ExtraAttributes>(&mut self, entry: &E, path: &Path) -> bool {
+        let header = entry.header();
+        let end = interleave::extent_end(entry);
+        if end > self.len && header.extent_length > 0 {
+            self.problem(
+                path,
+                ProblemKind::ExtentOutOfBounds {
+                    extent: header.extent_loc,
+                    end,
+                    image_len: self.len,
+                },
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Walks `dir` at `path`, below the directories with the extents in `ancestors`.
+    fn dir(&mut self, dir: &ISODirectory<IsoReader>, path: &Path, ancestors: &mut Vec<u32>) {
+        let extent = dir.header().extent_loc;
+        if ancestors.contains(&extent) {
+            self.problem(path, ProblemKind::Cycle { extent });
+            return;
+        }
+        if !self.visited.insert(extent) {
+            return;
+        }
+        self.report.directories += 1;
+        if !self.extent(dir, path) {
+            return;
+        }
+        ancestors.push(extent);
+        for entry in dir.contents() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    // The cdfs iterator yields the same error from here on
+                    self.problem(path, ProblemKind::BadRecord(e.to_string()));
+                    break;
+                }
+            };
+            let name = entry.identifier();
+            if name == "." || name == ".." {
+                continue;
+            }
+            let child = path.join(name);
+            match &entry {
+                DirectoryEntry::Directory(sub) => self.dir(sub, &child, ancestors),
+                DirectoryEntry::File(file) => {
+                    self.report.files += 1;
+                    self.extent(file, &child);
+                }
+                DirectoryEntry::Symlink(_) => self.report.files += 1,
+            }
+        }
+        ancestors.pop();
+    }
+}
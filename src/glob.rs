@@ -0,0 +1,128 @@
+//! Shell-style patterns in the last component of a listed path, like `LIST *.iso` or
+//! `NLST RELEASE-*`, which mirror scripts and old command-line clients expect the server to
+//! expand.
+
+/// Tells whether `name` has any of the characters that make it a pattern.
+pub(crate) fn is_pattern(name: &str) -> bool {
+    name.contains(['*', '?', '['])
+}
+
+/// Tells whether `name` matches `pattern`: `*` matches any run of characters, `?` any one, and
+/// `[...]` any one of those listed, which may include ranges like `a-z` and be negated with a
+/// leading `!` or `^`. A leading `.` is only matched by a `.` in the pattern, like in the shell.
+/// Letters compare without regard to ASCII case, like names in client paths do.
+pub(crate) fn matches(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*`: the pattern after it, and the name it has consumed to
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            Some('[') => {
+                if let Some((matched, len)) = class(&pattern[p..], name[n]) {
+                    if matched {
+                        p += len;
+                        n += 1;
+                        continue;
+                    }
+                } else if name[n] == '[' {
+                    // An unclosed `[` stands for itself
+                    p += 1;
+                    n += 1;
+                    continue;
+                }
+            }
+            Some(c) if c.eq_ignore_ascii_case(&name[n]) => {
+                p += 1;
+                n += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match backtrack {
+            Some((after, consumed)) => {
+                p = after;
+                n = consumed + 1;
+                backtrack = Some((after, consumed + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the class at the start of `pattern`, returning whether it matched and
+/// the length of the class, or `None` if the class isn't closed.
+fn class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let c = c.to_ascii_lowercase();
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let start = *pattern.get(i)?;
+        // A `]` right after the opening bracket is part of the class
+        if start == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            let (start, end) = (
+                start.to_ascii_lowercase(),
+                pattern[i + 2].to_ascii_lowercase(),
+            );
+            matched |= (start..=end).contains(&c);
+            i += 3;
+        } else {
+            matched |= start.to_ascii_lowercase() == c;
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        for (pattern, name, expected) in [
+            ("*.iso", "debian.iso", true),
+            ("*.iso", "DEBIAN.ISO", true),
+            ("*.iso", "debian.iso.sig", false),
+            ("RELEASE-*", "release-1.2", true),
+            ("RELEASE-*", "RELEASE", false),
+            ("a*b*c", "aXXbYYbZc", true),
+            ("a*b*c", "aXXbYYbZ", false),
+            ("file?.txt", "file1.txt", true),
+            ("file?.txt", "file10.txt", false),
+            ("[a-c]*", "Beta", true),
+            ("[!a-c]*", "beta", false),
+            ("[^a-c]*", "delta", true),
+            ("[]x]", "]", true),
+            ("[x", "[x", true),
+            ("*", ".hidden", false),
+            (".*", ".hidden", true),
+            ("*", "", true),
+        ] {
+            assert_eq!(matches(pattern, name), expected, "{pattern} {name}");
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Decompression of gzip data with flate2, for serving the compressed files of an image
+//! decompressed.
+
+use flate2::read::MultiGzDecoder;
+use std::io::{self, Read, Write};
+
+/// The uncompressed size recorded at the end of a single member of gzip data, modulo 2^32.
+pub(crate) fn recorded_len(data: &[u8]) -> Option<u64> {
+    let trailer = data.len().checked_sub(4).map(|at| &data[at..])?;
+    Some(u64::from(u32::from_le_bytes(trailer.try_into().ok()?)))
+}
+
+/// Decompresses all members of the gzip data read from `compressed`, checking their checksums.
+/// Fails once the output would grow beyond `limit` bytes.
+pub(crate) fn decompress(compressed: impl Read, limit: u64) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    decompress_to(compressed, limit, &mut out)?;
+    Ok(out)
+}
+
+/// Like [`decompress`], but writes the output to `sink` as it goes. Returns the number of bytes
+/// written.
+pub(crate) fn decompress_to(
+    compressed: impl Read,
+    limit: u64,
+    sink: &mut dyn Write,
+) -> io::Result<u64> {
+    // One byte more than the limit tells a file that fits exactly from one that doesn't
+    let mut decoder = MultiGzDecoder::new(compressed).take(limit.saturating_add(1));
+    let len = io::copy(&mut decoder, sink)?;
+    if len > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            format!("decompresses to more than {limit} bytes"),
+        ));
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compression, write::GzEncoder};
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn members() {
+        let data: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        let mut members = compress(&data);
+        members.extend(compress(b"Hello, gzip!\n"));
+        assert_eq!(recorded_len(&members), Some(13));
+        let out = decompress(&members[..], u64::MAX).unwrap();
+        assert_eq!(out[..data.len()], data[..]);
+        assert_eq!(&out[data.len()..], b"Hello, gzip!\n");
+        let mut sink = Vec::new();
+        let len = decompress_to(&members[..], u64::MAX, &mut sink).unwrap();
+        assert_eq!((len, sink), (out.len() as u64, out));
+    }
+
+    #[test]
+    fn limited() {
+        let data = compress(b"Hello, gzip!\n");
+        assert_eq!(decompress(&data[..], 13).unwrap(), b"Hello, gzip!\n");
+        assert_eq!(
+            decompress(&data[..], 12).unwrap_err().kind(),
+            io::ErrorKind::FileTooLarge
+        );
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut data = compress(b"Hello, gzip!\n");
+        let at = data.len() - 6;
+        data[at] ^= 0xFF;
+        assert!(decompress(&data[..], 1000).is_err());
+        assert!(decompress(&data[..data.len() - 10], 1000).is_err());
+        assert!(decompress(&b"not gzip"[..], 1000).is_err());
+    }
+}
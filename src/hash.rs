@@ -0,0 +1,290 @@
+//! Digests of the files in the image, for [`Storage::hash`](crate::Storage::hash) and the
+//! `SITE MD5` command, computed by streaming the contents rather than holding them in memory.
+//!
+//! MD5 and the SHA family are built in. Other hash functions, like BLAKE3 or xxHash, are
+//! implemented as a [`HashFunction`] and registered with
+//! [`StorageBuilder::hash_function`](crate::StorageBuilder::hash_function).
+
+use crate::{
+    retry::{RetryPolicy, Retrying},
+    source::{IsoSource, SourceReader},
+};
+use md5::{Digest as _, Md5};
+use ring::digest;
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    io::{self, Read, Seek, SeekFrom},
+    sync::Arc,
+};
+
+/// How many digests are kept, each under the extent of the file it is of.
+pub(crate) const HASHES_KEPT: usize = 4096;
+
+/// The most hashed at once.
+const CHUNK: usize = 256 * 1024;
+
+/// A hash function to compute the [`Digest`] of a file with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum HashAlgorithm {
+    /// MD5, which `SITE MD5` and `md5sum.txt` use
+    Md5,
+    /// SHA-1
+    Sha1,
+    /// SHA-256
+    Sha256,
+    /// SHA-512
+    Sha512,
+    /// The [`HashFunction`] registered under this name
+    Custom(&'static str),
+}
+
+impl HashAlgorithm {
+    /// Computes the digest of `data` with a built-in hash function. Panics on a
+    /// [`Custom`](Self::Custom) one.
+    pub(crate) fn digest(self, data: &[u8]) -> Vec<u8> {
+        let mut hasher = Builtin::new(self).expect("a built-in hash function");
+        hasher.update(data);
+        Box::new(hasher).finish()
+    }
+
+    /// The length of the digests of a built-in hash function in bytes.
+    pub(crate) fn output_len(self) -> Option<usize> {
+        match self {
+            HashAlgorithm::Md5 => Some(16),
+            HashAlgorithm::Sha1 => Some(20),
+            HashAlgorithm::Sha256 => Some(32),
+            HashAlgorithm::Sha512 => Some(64),
+            HashAlgorithm::Custom(_) => None,
+        }
+    }
+
+    /// The name BSD style checksum lists use, like `SHA256`, or the name a custom hash function
+    /// was registered under.
+    pub fn tag(self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "MD5",
+            HashAlgorithm::Sha1 => "SHA1",
+            HashAlgorithm::Sha256 => "SHA256",
+            HashAlgorithm::Sha512 => "SHA512",
+            HashAlgorithm::Custom(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.tag())
+    }
+}
+
+/// The digest of a file, as returned by [`Storage::hash`](crate::Storage::hash). Displays as
+/// lowercase hex, like `sha256sum` prints it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Digest {
+    algorithm: HashAlgorithm,
+    bytes: Vec<u8>,
+}
+
+impl Digest {
+    /// The hash function the digest was computed with.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// The digest itself.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.bytes.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+/// A hash function besides the built-in ones, registered with
+/// [`StorageBuilder::hash_function`](crate::StorageBuilder::hash_function) and picked with
+/// [`HashAlgorithm::Custom`].
+///
+/// ```
+/// use std::sync::Arc;
+/// use unftp_sbe_iso::{HashFunction, HashState, Storage};
+///
+/// /// 64-bit FNV-1a, for quick checks.
+/// #[derive(Debug)]
+/// struct Fnv1a;
+///
+/// struct Fnv1aState(u64);
+///
+/// impl HashFunction for Fnv1a {
+///     fn start(&self) -> Box<dyn HashState> {
+///         Box::new(Fnv1aState(0xcbf2_9ce4_8422_2325))
+///     }
+/// }
+///
+/// impl HashState for Fnv1aState {
+///     fn update(&mut self, data: &[u8]) {
+///         for &b in data {
+///             self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x100_0000_01b3);
+///         }
+///     }
+///
+///     fn finish(self: Box<Self>) -> Vec<u8> {
+///         self.0.to_be_bytes().to_vec()
+///     }
+/// }
+///
+/// let storage = Storage::builder("/srv/images/debian.iso")
+///     .hash_function("FNV1A", Arc::new(Fnv1a))
+///     .build();
+/// ```
+pub trait HashFunction: Send + Sync + Debug {
+    /// Starts computing a digest.
+    fn start(&self) -> Box<dyn HashState>;
+}
+
+/// A digest being computed by a [`HashFunction`], fed the contents of a file in order.
+pub trait HashState: Send {
+    /// Adds the next bytes of the contents.
+    fn update(&mut self, data: &[u8]);
+
+    /// Returns the digest of all the bytes added.
+    fn finish(self: Box<Self>) -> Vec<u8>;
+}
+
+/// The hash functions registered by name, for [`HashAlgorithm::Custom`].
+pub(crate) type HashFunctions = HashMap<&'static str, Arc<dyn HashFunction>>;
+
+/// The state of a built-in hash function.
+enum Builtin {
+    Md5(Md5),
+    Ring(digest::Context),
+}
+
+impl Builtin {
+    fn new(algorithm: HashAlgorithm) -> Option<Self> {
+        let ring = match algorithm {
+            HashAlgorithm::Md5 => return Some(Builtin::Md5(Md5::new())),
+            HashAlgorithm::Sha1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            HashAlgorithm::Sha256 => &digest::SHA256,
+            HashAlgorithm::Sha512 => &digest::SHA512,
+            HashAlgorithm::Custom(_) => return None,
+        };
+        Some(Builtin::Ring(digest::Context::new(ring)))
+    }
+}
+
+impl HashState for Builtin {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Builtin::Md5(md5) => md5.update(data),
+            Builtin::Ring(context) => context.update(data),
+        }
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        match *self {
+            Builtin::Md5(md5) => md5.finalize().to_vec(),
+            Builtin::Ring(context) => context.finish().as_ref().to_vec(),
+        }
+    }
+}
+
+/// Starts a digest with `algorithm`, which is built in or one of `registered`. Returns `None` for
+/// a name nothing was registered under.
+pub(crate) fn start(
+    algorithm: HashAlgorithm,
+    registered: &HashFunctions,
+) -> Option<Box<dyn HashState>> {
+    match algorithm {
+        HashAlgorithm::Custom(name) => registered.get(name).map(|function| function.start()),
+        builtin => Some(Box::new(Builtin::new(builtin)?)),
+    }
+}
+
+/// Hashes the `len` bytes at `offset` of `source` a chunk at a time, retrying failed reads like
+/// cdfs's do.
+/// `hasher` computes the digest with `algorithm`.
+pub(crate) fn stream(
+    source: Arc<dyn IsoSource>,
+    offset: u64,
+    len: u64,
+    algorithm: HashAlgorithm,
+    mut hasher: Box<dyn HashState>,
+    retry: RetryPolicy,
+) -> io::Result<Digest> {
+    let mut reader = Retrying::new(SourceReader::new(source), retry);
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut reader = reader.take(len);
+    let mut buf = vec![0; CHUNK.min(len as usize)];
+    let mut hashed = 0;
+    while hashed < len {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        hasher.update(&buf[..n]);
+        hashed += n as u64;
+    }
+    Ok(Digest {
+        algorithm,
+        bytes: hasher.finish(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_all(data: &[u8], algorithm: HashAlgorithm) -> Digest {
+        let hasher = start(algorithm, &HashFunctions::new()).unwrap();
+        let len = data.len() as u64;
+        let source = Arc::new(data.to_vec());
+        stream(source, 0, len, algorithm, hasher, RetryPolicy::default()).unwrap()
+    }
+
+    #[test]
+    fn streamed() {
+        let data: Vec<u8> = (0..600_000u32).map(|i| (i % 253) as u8).collect();
+        for algorithm in [
+            HashAlgorithm::Md5,
+            HashAlgorithm::Sha1,
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+        ] {
+            let digest = stream_all(&data, algorithm);
+            assert_eq!(digest.as_bytes(), algorithm.digest(&data));
+            assert_eq!(Some(digest.as_bytes().len()), algorithm.output_len());
+        }
+        let hasher = start(HashAlgorithm::Md5, &HashFunctions::new()).unwrap();
+        let source = Arc::new(data);
+        let short = stream(
+            source,
+            500_000,
+            200_000,
+            HashAlgorithm::Md5,
+            hasher,
+            RetryPolicy::default(),
+        );
+        assert_eq!(short.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(
+            stream_all(b"hello", HashAlgorithm::Md5).to_string(),
+            "5d41402abc4b2a76b9719d911017c592"
+        );
+    }
+
+    #[test]
+    fn unregistered() {
+        let custom = HashAlgorithm::Custom("BLAKE3");
+        assert!(start(custom, &HashFunctions::new()).is_none());
+        assert_eq!(custom.to_string(), "BLAKE3");
+        assert_eq!(custom.output_len(), None);
+    }
+}
@@ -0,0 +1,504 @@
+//! A read-only HTTP/1.1 file server over the same [`Storage`], so that one process can serve the
+//! tree of the image over FTP and HTTP alike. Connections are served by hyper.
+
+use crate::{Storage, date};
+use http_body_util::{Either, Full};
+use hyper::{
+    Method, Request, Response, StatusCode,
+    body::{Body, Bytes, Frame, Incoming, SizeHint},
+    header::{
+        ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, HeaderName, LAST_MODIFIED,
+        LOCATION, RANGE,
+    },
+    server::conn::http1,
+    service::service_fn,
+};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use std::{
+    convert::Infallible,
+    fmt::Write as _,
+    io,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpListener,
+    sync::Semaphore,
+};
+use unftp_core::{
+    auth::{DefaultUser, UserDetail},
+    storage::{ErrorKind, Metadata, StorageBackend},
+};
+
+/// The most bytes the request line and headers of a request may take.
+const MAX_HEAD: usize = 16 * 1024;
+/// How long clients have to send the head of their request unless told otherwise.
+const DEFAULT_HEAD_TIMEOUT: Duration = Duration::from_secs(30);
+/// How many connections are served at once unless told otherwise.
+const DEFAULT_CONNECTIONS: usize = 256;
+/// How much of a file is read from the image at once.
+const CHUNK: usize = 64 * 1024;
+
+/// Serves a [`Storage`] over HTTP: files with `GET` and `HEAD`, including single byte ranges,
+/// and directories as index pages linking to their entries. Everything is read as one user, the
+/// [`DefaultUser`] unless [another](Self::for_user) is given, so a per-user back-end serves the
+/// tree of that user.
+///
+/// Each connection serves one request and is closed after the response. Clients that are slow
+/// to send their request are dropped after a [timeout](Self::head_timeout), and only so many
+/// [connections](Self::max_connections) are served at once, so stalled clients can't tie up
+/// the server.
+///
+/// ```no_run
+/// use tokio::net::TcpListener;
+/// use unftp_sbe_iso::{HttpGateway, Storage};
+///
+/// # async fn run() -> std::io::Result<()> {
+/// let storage = Storage::new("/path/to/your/image.iso");
+/// // The same storage, cloned, can be handed to libunftp too
+/// let listener = TcpListener::bind("127.0.0.1:8080").await?;
+/// HttpGateway::new(storage).serve(listener).await
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct HttpGateway<User = DefaultUser> {
+    storage: Storage,
+    user: Arc<User>,
+    head_timeout: Duration,
+    max_connections: usize,
+}
+
+impl<User> Clone for HttpGateway<User> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            user: self.user.clone(),
+            head_timeout: self.head_timeout,
+            max_connections: self.max_connections,
+        }
+    }
+}
+
+impl HttpGateway {
+    /// Serves `storage` as the [`DefaultUser`].
+    pub fn new(storage: Storage) -> Self {
+        Self::for_user(storage, DefaultUser {})
+    }
+}
+
+/// The body of a response: a page or message held in memory, or a file.
+type ResponseBody = Either<Full<Bytes>, FileBody>;
+
+/// A response with `body`, held in memory.
+fn full(status: StatusCode, body: impl Into<Bytes>) -> Response<ResponseBody> {
+    let mut response = Response::new(Either::Left(Full::new(body.into())));
+    *response.status_mut() = status;
+    response
+}
+
+/// A response with a short text body saying what went wrong.
+fn error(status: StatusCode) -> Response<ResponseBody> {
+    let mut response = full(status, format!("{status}\n"));
+    header(&mut response, CONTENT_TYPE, "text/plain; charset=utf-8");
+    response
+}
+
+/// Sets the header `name` of `response` to `value`, which is always valid in a header.
+fn header(response: &mut Response<ResponseBody>, name: HeaderName, value: &str) {
+    let value = value.parse().expect("the value is valid in a header");
+    response.headers_mut().insert(name, value);
+}
+
+/// The body of a file, read from the image as the client takes it.
+struct FileBody {
+    reader: Box<dyn AsyncRead + Send + Sync + Unpin>,
+    path: String,
+    len: u64,
+    left: u64,
+    buf: Vec<u8>,
+}
+
+impl Body for FileBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<Frame<Bytes>>>> {
+        let this = &mut *self;
+        if this.left == 0 {
+            return Poll::Ready(None);
+        }
+        let want = this.left.min(CHUNK as u64) as usize;
+        this.buf.resize(want, 0);
+        let mut buf = ReadBuf::new(&mut this.buf);
+        ready!(Pin::new(&mut this.reader).poll_read(cx, &mut buf))?;
+        let read = buf.filled().len();
+        if read == 0 {
+            // The length was promised, so the client can tell the response was cut short
+            let copied = this.len - this.left;
+            return Poll::Ready(Some(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} ended after {copied} of {} bytes", this.path, this.len),
+            ))));
+        }
+        this.left -= read as u64;
+        Poll::Ready(Some(Ok(Frame::data(Bytes::copy_from_slice(
+            &this.buf[..read],
+        )))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.left == 0
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.left)
+    }
+}
+
+impl<User> HttpGateway<User> {
+    /// Serves `storage` as `user`.
+    pub fn for_user(storage: Storage, user: User) -> Self {
+        Self {
+            storage,
+            user: Arc::new(user),
+            head_timeout: DEFAULT_HEAD_TIMEOUT,
+            max_connections: DEFAULT_CONNECTIONS,
+        }
+    }
+
+    /// Drops connections whose client hasn't sent the head of its request within `timeout`.
+    /// Defaults to 30 seconds.
+    pub fn head_timeout(mut self, timeout: Duration) -> Self {
+        self.head_timeout = timeout;
+        self
+    }
+
+    /// Serves at most `max` connections at once; further ones aren't accepted until one of
+    /// those ends. Defaults to 256.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max;
+        self
+    }
+}
+
+impl<User: UserDetail + 'static> HttpGateway<User> {
+    /// Accepts connections from `listener` and serves each on a task of its own, until
+    /// accepting fails.
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let connections = Arc::new(Semaphore::new(self.max_connections));
+        loop {
+            // Connections over the limit wait in the backlog of the listener
+            let permit = connections
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("the semaphore is never closed");
+            let (stream, peer) = listener.accept().await?;
+            let gateway = self.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                if let Err(e) = gateway.handle(stream).await {
+                    log::debug!("HTTP connection from {peer} failed: {e}");
+                }
+            });
+        }
+    }
+
+    /// Serves one request read from `stream` and writes the response to it. Requests that
+    /// can't be parsed are answered by hyper with the status saying why.
+    pub async fn handle<S: AsyncRead + AsyncWrite + Unpin>(&self, stream: S) -> io::Result<()> {
+        let served = http1::Builder::new()
+            .timer(TokioTimer::new())
+            .header_read_timeout(self.head_timeout)
+            .max_buf_size(MAX_HEAD)
+            .keep_alive(false)
+            .serve_connection(
+                TokioIo::new(stream),
+                service_fn(
+                    |request| async move { Ok::<_, Infallible>(self.respond(request).await) },
+                ),
+            )
+            .await;
+        match served {
+            Ok(()) => Ok(()),
+            Err(e) if e.is_timeout() => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the head of the request took too long",
+            )),
+            // Already answered
+            Err(e) if e.is_parse() => Ok(()),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+
+    /// The response to `request`.
+    async fn respond(&self, request: Request<Incoming>) -> Response<ResponseBody> {
+        let head_only = match *request.method() {
+            Method::GET => false,
+            Method::HEAD => true,
+            _ => return error(StatusCode::METHOD_NOT_ALLOWED),
+        };
+        let Some(path) = decode(request.uri().path()).filter(|path| path.starts_with('/')) else {
+            return error(StatusCode::BAD_REQUEST);
+        };
+        let range = request
+            .headers()
+            .get(RANGE)
+            .and_then(|range| range.to_str().ok());
+        let meta = match self.storage.metadata(&*self.user, &path).await {
+            Ok(meta) => meta,
+            Err(e) => return error_response(e.kind()),
+        };
+        if meta.is_dir() {
+            return match path.ends_with('/') {
+                true => self.index(&path).await,
+                // Relative links in the index resolve against the directory
+                false => {
+                    let mut response = full(StatusCode::MOVED_PERMANENTLY, Bytes::new());
+                    header(&mut response, LOCATION, &encode(&format!("{path}/")));
+                    response
+                }
+            };
+        }
+
+        let len = meta.len();
+        let (start, end) = match range.map(|r| parse_range(r, len)) {
+            None | Some(Range::Ignored) => (0, len),
+            Some(Range::Bytes(start, end)) => (start, end),
+            Some(Range::Unsatisfiable) => {
+                let mut response = error(StatusCode::RANGE_NOT_SATISFIABLE);
+                header(&mut response, CONTENT_RANGE, &format!("bytes */{len}"));
+                return response;
+            }
+        };
+        let mut response = match head_only || start == end {
+            true => full(StatusCode::OK, Bytes::new()),
+            false => match self.storage.get(&*self.user, &path, start).await {
+                Ok(reader) => Response::new(Either::Right(FileBody {
+                    reader,
+                    path,
+                    len: end - start,
+                    left: end - start,
+                    buf: Vec::new(),
+                })),
+                Err(e) => return error_response(e.kind()),
+            },
+        };
+        if range.is_some() && (start, end) != (0, len) {
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            let content_range = format!("bytes {start}-{}/{len}", end - 1);
+            header(&mut response, CONTENT_RANGE, &content_range);
+        }
+        header(&mut response, CONTENT_TYPE, "application/octet-stream");
+        header(&mut response, CONTENT_LENGTH, &(end - start).to_string());
+        header(&mut response, ACCEPT_RANGES, "bytes");
+        if let Ok(modified) = meta.modified() {
+            header(&mut response, LAST_MODIFIED, &http_date(modified));
+        }
+        response
+    }
+
+    /// The index page of the directory at `path`.
+    async fn index(&self, path: &str) -> Response<ResponseBody> {
+        let mut entries = match self.storage.list(&*self.user, path).await {
+            Ok(entries) => entries,
+            Err(e) => return error_response(e.kind()),
+        };
+        entries.retain(|entry| entry.path != Path::new(".") && entry.path != Path::new(".."));
+        entries
+            .sort_by(|a, b| (!a.metadata.is_dir(), &a.path).cmp(&(!b.metadata.is_dir(), &b.path)));
+        let title = escape(path);
+        let mut page = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Index of {title}</title>\
+             </head><body><h1>Index of {title}</h1><ul>\n"
+        );
+        if path != "/" {
+            page.push_str("<li><a href=\"../\">../</a></li>\n");
+        }
+        for entry in &entries {
+            let mut name = entry.path.to_string_lossy().into_owned();
+            if entry.metadata.is_dir() {
+                name.push('/');
+            }
+            let _ = writeln!(
+                page,
+                "<li><a href=\"{}\">{}</a></li>",
+                escape(&encode(&name)),
+                escape(&name)
+            );
+        }
+        page.push_str("</ul></body></html>\n");
+        let mut response = full(StatusCode::OK, page);
+        header(&mut response, CONTENT_TYPE, "text/html; charset=utf-8");
+        response
+    }
+}
+
+fn error_response(kind: ErrorKind) -> Response<ResponseBody> {
+    error(match kind {
+        ErrorKind::PermanentFileNotAvailable
+        | ErrorKind::PermanentDirectoryNotAvailable
+        | ErrorKind::FileNameNotAllowedError => StatusCode::NOT_FOUND,
+        ErrorKind::ExceededStorageAllocationError => StatusCode::TOO_MANY_REQUESTS,
+        ErrorKind::TransientFileNotAvailable => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    })
+}
+
+/// What a `Range` header asks for of a file of `len` bytes.
+#[derive(Debug, PartialEq)]
+enum Range {
+    /// The bytes from the first up to the second, exclusive
+    Bytes(u64, u64),
+    /// Bytes past the end of the file
+    Unsatisfiable,
+    /// A range that isn't understood, or several, which are served as the whole file
+    Ignored,
+}
+
+fn parse_range(header: &str, len: u64) -> Range {
+    let Some(spec) = header.strip_prefix("bytes=") else {
+        return Range::Ignored;
+    };
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return Range::Ignored;
+    };
+    if spec.contains(',') {
+        return Range::Ignored;
+    }
+    let number = |s: &str| s.trim().parse::<u64>().ok();
+    match (first.trim().is_empty(), number(first), number(last)) {
+        // The last bytes
+        (true, _, Some(suffix)) if suffix > 0 && len > 0 => {
+            Range::Bytes(len - suffix.min(len), len)
+        }
+        (true, _, _) => Range::Unsatisfiable,
+        (false, Some(start), _) if start >= len => Range::Unsatisfiable,
+        (false, Some(start), None) if last.trim().is_empty() => Range::Bytes(start, len),
+        (false, Some(start), Some(end)) if end >= start => {
+            Range::Bytes(start, end.min(len - 1) + 1)
+        }
+        _ => Range::Ignored,
+    }
+}
+
+/// Decodes the `%XX` escapes of a request target, if it is valid UTF-8 once decoded.
+fn decode(target: &str) -> Option<String> {
+    let bytes = target.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Escapes a path for a URL, leaving its separators and the characters safe in a path as they
+/// are.
+fn encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+/// Escapes text for HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats `time` the way HTTP headers want dates, e.g. `Tue, 02 Jan 2024 00:00:00 GMT`.
+fn http_date(time: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64) - 1,
+    };
+    let (year, month, day) = date::civil(time);
+    let of_day = secs.rem_euclid(86_400);
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} GMT",
+        DAYS[secs.div_euclid(86_400).rem_euclid(7) as usize],
+        MONTHS[month as usize - 1],
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("bytes=0-9", 100), Range::Bytes(0, 10));
+        assert_eq!(parse_range("bytes=90-", 100), Range::Bytes(90, 100));
+        assert_eq!(parse_range("bytes=90-200", 100), Range::Bytes(90, 100));
+        assert_eq!(parse_range("bytes=-10", 100), Range::Bytes(90, 100));
+        assert_eq!(parse_range("bytes=-200", 100), Range::Bytes(0, 100));
+        assert_eq!(parse_range("bytes=100-", 100), Range::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 100), Range::Unsatisfiable);
+        assert_eq!(parse_range("bytes=9-0", 100), Range::Ignored);
+        assert_eq!(parse_range("bytes=0-1,5-6", 100), Range::Ignored);
+        assert_eq!(parse_range("lines=0-1", 100), Range::Ignored);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(encode("/a b/%0A<x>.txt"), "/a%20b/%250A%3Cx%3E.txt");
+        assert_eq!(
+            decode("/a%20b/%250A%3Cx%3E.txt").unwrap(),
+            "/a b/%0A<x>.txt"
+        );
+        assert_eq!(decode("/%zz"), None);
+        assert_eq!(decode("/%ff"), None);
+        assert_eq!(
+            escape("<a href=\"x\">&'"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;"
+        );
+    }
+
+    #[test]
+    fn dates() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_704_153_600 + 3 * 3600 + 4 * 60 + 5);
+        assert_eq!(http_date(time), "Tue, 02 Jan 2024 03:04:05 GMT");
+    }
+}
@@ -0,0 +1,145 @@
+//! Isohybrid images, which carry a partition table in the system area so that the same image
+//! boots from a USB stick. The ISO 9660 volume is unaffected: it still starts at byte 0 and its
+//! sectors count from there, wherever the partitions say they start and however much padding
+//! follows the volume.
+
+use crate::{descriptor::read_exact_at, source::IsoSource};
+use std::io;
+
+/// The most GPT entries read.
+const MAX_GPT_ENTRIES: u32 = 256;
+
+/// The partition tables of an isohybrid image, as returned by
+/// [`Storage::hybrid_layout`](crate::Storage::hybrid_layout).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HybridLayout {
+    /// The partitions of the master boot record at the start of the image
+    pub mbr: Vec<Partition>,
+    /// The partitions of the GUID partition table, if there is one
+    pub gpt: Option<Vec<Partition>>,
+}
+
+/// A partition of the partition table of an isohybrid image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    /// The number of the partition, counting from 1 in the order of the table
+    pub number: u32,
+    /// The byte of the image the partition starts at
+    pub start: u64,
+    /// The size of the partition in bytes
+    pub len: u64,
+    /// The type of the partition
+    pub kind: PartitionKind,
+    /// Whether the partition is marked active (MBR) or legacy BIOS bootable (GPT)
+    pub bootable: bool,
+    /// The name of the partition. MBR partitions have none.
+    pub name: String,
+}
+
+/// The type of a [`Partition`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionKind {
+    /// An MBR partition type, like `0x00` or `0x17` for the ISO itself, `0xEF` for an EFI
+    /// system partition or `0xEE` for the protective partition of a GPT
+    Mbr(u8),
+    /// A GPT partition type GUID, in the mixed-endian byte order it is stored in
+    Gpt([u8; 16]),
+}
+
+/// Reads the partition tables of an isohybrid image. Returns `None` for images whose system
+/// area holds no partition table.
+pub(crate) fn read(source: &dyn IsoSource) -> io::Result<Option<HybridLayout>> {
+    let mut mbr = [0; 512];
+    if !read_table(source, 0, &mut mbr)? || mbr[510..512] != [0x55, 0xAA] {
+        return Ok(None);
+    }
+    let partitions: Vec<Partition> = (0..4)
+        .filter_map(|i| {
+            let entry = &mbr[446 + i * 16..446 + (i + 1) * 16];
+            let le32 =
+                |at: usize| u64::from(u32::from_le_bytes(entry[at..at + 4].try_into().unwrap()));
+            (entry[4] != 0).then(|| Partition {
+                number: i as u32 + 1,
+                start: le32(8) * 512,
+                len: le32(12) * 512,
+                kind: PartitionKind::Mbr(entry[4]),
+                bootable: entry[0] & 0x80 != 0,
+                name: String::new(),
+            })
+        })
+        .collect();
+    // The GPT header follows the MBR in the next sector, of 512 bytes or, made by some tools
+    // for the sake of the ISO, of 2048 bytes
+    let mut gpt = None;
+    for sector in [512, 2048] {
+        if let Some(table) = read_gpt(source, sector)? {
+            gpt = Some(table);
+            break;
+        }
+    }
+    if partitions.is_empty() && gpt.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(HybridLayout {
+        mbr: partitions,
+        gpt,
+    }))
+}
+
+fn read_gpt(source: &dyn IsoSource, sector: u64) -> io::Result<Option<Vec<Partition>>> {
+    let mut header = [0; 92];
+    if !read_table(source, sector, &mut header)? || &header[..8] != b"EFI PART" {
+        return Ok(None);
+    }
+    let le32 = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+    let le64 = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+    let entries_at = le64(72).saturating_mul(sector);
+    let count = le32(80).min(MAX_GPT_ENTRIES);
+    let entry_len = le32(84) as usize;
+    if !(128..=4096).contains(&entry_len) {
+        return Ok(None);
+    }
+    let mut entries = vec![0; count as usize * entry_len];
+    if !read_table(source, entries_at, &mut entries)? {
+        return Ok(None);
+    }
+    let partitions = entries
+        .chunks_exact(entry_len)
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            let kind: [u8; 16] = entry[..16].try_into().unwrap();
+            if kind == [0; 16] {
+                return None;
+            }
+            let le64 = |at: usize| u64::from_le_bytes(entry[at..at + 8].try_into().unwrap());
+            let (first, last) = (le64(32), le64(40));
+            let units: Vec<u16> = entry[56..128]
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .take_while(|&unit| unit != 0)
+                .collect();
+            Some(Partition {
+                number: i as u32 + 1,
+                start: first.saturating_mul(sector),
+                len: last
+                    .saturating_add(1)
+                    .saturating_sub(first)
+                    .saturating_mul(sector),
+                kind: PartitionKind::Gpt(kind),
+                // Attribute bit 2: legacy BIOS bootable
+                bootable: le64(48) & 0b100 != 0,
+                name: String::from_utf16_lossy(&units),
+            })
+        })
+        .collect();
+    Ok(Some(partitions))
+}
+
+/// Reads part of a partition table, telling whether the image is long enough to hold it.
+fn read_table(source: &dyn IsoSource, offset: u64, buf: &mut [u8]) -> io::Result<bool> {
+    match read_exact_at(source, offset, buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
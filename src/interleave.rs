@@ -0,0 +1,169 @@
+//! Files recorded in interleaved mode (ECMA-119 § 6.4.3): in units of a number of sectors, each
+//! followed by a gap of sectors that belong to something else. Legacy images used it to feed
+//! audio and video to players while reading. cdfs reads every extent as contiguous, which turns
+//! such files into garbage, so they are read through [`Interleaved`] instead.
+
+use crate::{IsoReader, descriptor::SECTOR, source::IsoSource};
+use cdfs::{ExtraAttributes, ISOFile, ISOFileReader};
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    sync::Arc,
+};
+
+/// Where the bytes of an interleaved file lie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    /// The byte of the image the first unit starts at
+    start: u64,
+    /// The bytes in a unit
+    unit: u64,
+    /// The bytes from the start of one unit to that of the next
+    stride: u64,
+}
+
+impl Layout {
+    /// The layout of the extent of `entry`, if it is interleaved.
+    fn of<E: ExtraAttributes>(entry: &E) -> Option<Self> {
+        let header = entry.header();
+        if header.file_unit_size == 0 {
+            return None;
+        }
+        let unit = u64::from(header.file_unit_size) * SECTOR as u64;
+        Some(Self {
+            start: u64::from(header.extent_loc) * SECTOR as u64,
+            unit,
+            stride: unit + u64::from(header.interleave_gap_size) * SECTOR as u64,
+        })
+    }
+
+    /// The byte of the image that byte `pos` of the file lies at, and how many bytes of the file
+    /// follow it contiguously.
+    fn locate(&self, pos: u64) -> (u64, u64) {
+        let (unit, within) = (pos / self.unit, pos % self.unit);
+        (self.start + unit * self.stride + within, self.unit - within)
+    }
+}
+
+/// The byte of the image the extent of `entry` ends at, counting the gaps between its units.
+pub(crate) fn extent_end<E: ExtraAttributes>(entry: &E) -> u64 {
+    let header = entry.header();
+    let len = u64::from(header.extent_length);
+    match Layout::of(entry) {
+        Some(layout) if len > 0 => layout.locate(len - 1).0 + 1,
+        _ => u64::from(header.extent_loc) * SECTOR as u64 + len,
+    }
+}
+
+/// The bytes of an interleaved file, as a source of their own.
+pub(crate) struct Interleaved {
+    source: Arc<dyn IsoSource>,
+    layout: Layout,
+    len: u64,
+}
+
+impl Interleaved {
+    /// The bytes of `file`, if it is interleaved, with `source` the image.
+    fn of(source: &Arc<dyn IsoSource>, file: &ISOFile<IsoReader>) -> Option<Self> {
+        Some(Self {
+            source: source.clone(),
+            layout: Layout::of(file)?,
+            len: file.size() as u64,
+        })
+    }
+}
+
+impl IsoSource for Interleaved {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.len {
+            return Ok(0);
+        }
+        let (at, contiguous) = self.layout.locate(offset);
+        let n = (buf.len() as u64).min(contiguous).min(self.len - offset) as usize;
+        self.source.read_at(at, &mut buf[..n])
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+}
+
+/// The source and offset to read the bytes of `file` from, with `source` the image.
+pub(crate) fn file_source(
+    source: &Arc<dyn IsoSource>,
+    file: &ISOFile<IsoReader>,
+) -> (Arc<dyn IsoSource>, u64) {
+    match Interleaved::of(source, file) {
+        Some(interleaved) => (Arc::new(interleaved), 0),
+        None => (
+            source.clone(),
+            u64::from(file.header().extent_loc) * SECTOR as u64,
+        ),
+    }
+}
+
+/// Reads the contents of a file, interleaved or not.
+pub(crate) enum FileReader {
+    Contiguous(Box<ISOFileReader<IsoReader>>),
+    Interleaved { file: Interleaved, pos: u64 },
+}
+
+impl FileReader {
+    /// A reader of `file`, with `source` the image.
+    pub(crate) fn new(source: &Arc<dyn IsoSource>, file: &ISOFile<IsoReader>) -> Self {
+        match Interleaved::of(source, file) {
+            Some(file) => FileReader::Interleaved { file, pos: 0 },
+            None => FileReader::Contiguous(Box::new(file.read())),
+        }
+    }
+}
+
+impl Read for FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            FileReader::Contiguous(reader) => reader.read(buf),
+            FileReader::Interleaved { file, pos } => {
+                let n = file.read_at(*pos, buf)?;
+                *pos += n as u64;
+                Ok(n)
+            }
+        }
+    }
+}
+
+impl Seek for FileReader {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        match self {
+            FileReader::Contiguous(reader) => reader.seek(to),
+            FileReader::Interleaved { file, pos } => {
+                let base = match to {
+                    SeekFrom::Start(offset) => Some(offset),
+                    SeekFrom::End(offset) => file.len.checked_add_signed(offset),
+                    SeekFrom::Current(offset) => pos.checked_add_signed(offset),
+                };
+                *pos = base.ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+                })?;
+                Ok(*pos)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate() {
+        // Units of 2 sectors with gaps of 1
+        let layout = Layout {
+            start: 10 * 2048,
+            unit: 2 * 2048,
+            stride: 3 * 2048,
+        };
+        assert_eq!(layout.locate(0), (10 * 2048, 4096));
+        assert_eq!(layout.locate(4095), (10 * 2048 + 4095, 1));
+        assert_eq!(layout.locate(4096), (13 * 2048, 4096));
+        assert_eq!(layout.locate(9000), (16 * 2048 + 808, 4096 - 808));
+    }
+}
@@ -0,0 +1,205 @@
+//! Repairs of the volume descriptors of non-conforming images, for
+//! [`StorageBuilder::lenient`](crate::StorageBuilder::lenient).
+//!
+//! cdfs refuses to open an image over a flaw in any descriptor: a version byte other than 1, a
+//! date that isn't digits, a Joliet volume with an odd block size, a missing set terminator. Real
+//! images have all of these. Rather than parse descriptors a second way, the sectors holding them
+//! are repaired where the right reading is safe to guess, and cdfs reads the repaired copies.
+
+use crate::{
+    descriptor::{FIRST_SECTOR, MAX_DESCRIPTORS, SECTOR, read_exact_at},
+    source::IsoSource,
+};
+use std::{io, sync::Arc};
+
+/// The type a descriptor is rewritten to for cdfs to skip it: one no standard assigns.
+const IGNORED: u8 = 0xFE;
+
+/// The repaired descriptor sectors of an image, by sector.
+#[derive(Debug, Default)]
+pub(crate) struct Repairs {
+    sectors: Vec<(u64, Box<[u8]>)>,
+}
+
+impl Repairs {
+    /// Reads the descriptors of the image in `source` and repairs what can be. Every repair is
+    /// logged as a warning.
+    pub(crate) fn find(source: &dyn IsoSource) -> Self {
+        let mut repairs = Self::default();
+        let sectors = source.len().map_or(0, |len| len / SECTOR as u64);
+        for sector in FIRST_SECTOR..FIRST_SECTOR + MAX_DESCRIPTORS {
+            let mut raw = vec![0; SECTOR];
+            if read_exact_at(source, sector * SECTOR as u64, &mut raw).is_err() {
+                // cdfs fails on the short read; there is no sector a terminator could go in
+                break;
+            }
+            if &raw[1..6] != b"CD001" {
+                if sector == FIRST_SECTOR {
+                    break;
+                }
+                log::warn!(
+                    "no volume descriptor set terminator before sector {sector}; assuming one"
+                );
+                raw.fill(0);
+                raw[0] = 255;
+                raw[1..6].copy_from_slice(b"CD001");
+                raw[6] = 1;
+                repairs.sectors.push((sector, raw.into()));
+                break;
+            }
+            let terminator = raw[0] == 255;
+            if repair(sector, &mut raw, sectors) {
+                repairs.sectors.push((sector, raw.into()));
+            }
+            if terminator {
+                break;
+            }
+        }
+        repairs
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.sectors.is_empty()
+    }
+}
+
+/// Repairs the descriptor in `raw`, read from `sector` of an image of `sectors` sectors. Returns
+/// whether anything was repaired.
+fn repair(sector: u64, raw: &mut [u8], sectors: u64) -> bool {
+    let original = raw.to_vec();
+    let kind = raw[0];
+    if raw[6] != 1 {
+        if kind == 2 && raw[6] == 2 {
+            // An enhanced volume descriptor (ISO 9660:1999), whose records cdfs would misread.
+            // cdfs still wants version 1 of descriptors it skips
+            log::warn!("ignoring the enhanced volume descriptor in sector {sector}");
+            raw[0] = IGNORED;
+            raw[6] = 1;
+            return true;
+        }
+        log::warn!(
+            "volume descriptor in sector {sector} has version {}; reading it as version 1",
+            raw[6]
+        );
+        raw[6] = 1;
+    }
+    if kind == 1 || kind == 2 {
+        let plausible_block = |size: u32| size == SECTOR as u32;
+        let plausible_sector = |lba: u32| u64::from(lba) < sectors.max(1);
+        both_endian32(sector, raw, "volume space size", 80, |n| {
+            n > 0 && u64::from(n) <= sectors
+        });
+        both_endian16(sector, raw, "logical block size", 128, plausible_block);
+        both_endian32(sector, raw, "path table size", 132, |n| n > 0);
+        both_endian32(sector, raw, "root directory extent", 158, plausible_sector);
+        both_endian32(sector, raw, "root directory size", 166, |n| {
+            n > 0 && n.is_multiple_of(SECTOR as u32)
+        });
+        if u16::from_le_bytes([raw[128], raw[129]]) != SECTOR as u16 && kind == 2 {
+            log::warn!(
+                "ignoring the supplementary volume in sector {sector}, whose logical blocks aren't \
+                 2048 bytes"
+            );
+            raw[0] = IGNORED;
+            return true;
+        }
+        for (field, at) in [
+            ("creation", 813),
+            ("modification", 830),
+            ("expiration", 847),
+            ("effective", 864),
+        ] {
+            let digits = &raw[at..at + 16];
+            if !(digits.iter().all(u8::is_ascii_digit) || digits.iter().all(|&b| b == 0)) {
+                log::warn!(
+                    "volume descriptor in sector {sector} has an invalid {field} date; leaving it unset"
+                );
+                raw[at..at + 16].copy_from_slice(b"0000000000000000");
+                raw[at + 16] = 0;
+            }
+        }
+    }
+    *raw != original[..]
+}
+
+/// Makes the little- and big-endian copies of the 32-bit field at `at` agree, on the one that is
+/// `plausible`, or on the little-endian one cdfs reads if both or neither are.
+fn both_endian32(
+    sector: u64,
+    raw: &mut [u8],
+    field: &str,
+    at: usize,
+    plausible: impl Fn(u32) -> bool,
+) {
+    let le = u32::from_le_bytes(raw[at..at + 4].try_into().unwrap());
+    let be = u32::from_be_bytes(raw[at + 4..at + 8].try_into().unwrap());
+    if le == be {
+        return;
+    }
+    let value = if !plausible(le) && plausible(be) {
+        be
+    } else {
+        le
+    };
+    log::warn!(
+        "volume descriptor in sector {sector} records its {field} as {le} and as {be}; using {value}"
+    );
+    raw[at..at + 4].copy_from_slice(&value.to_le_bytes());
+    raw[at + 4..at + 8].copy_from_slice(&value.to_be_bytes());
+}
+
+/// Like [`both_endian32`], for 16-bit fields.
+fn both_endian16(
+    sector: u64,
+    raw: &mut [u8],
+    field: &str,
+    at: usize,
+    plausible: impl Fn(u32) -> bool,
+) {
+    let le = u16::from_le_bytes([raw[at], raw[at + 1]]);
+    let be = u16::from_be_bytes([raw[at + 2], raw[at + 3]]);
+    if le == be {
+        return;
+    }
+    let value = if !plausible(le.into()) && plausible(be.into()) {
+        be
+    } else {
+        le
+    };
+    log::warn!(
+        "volume descriptor in sector {sector} records its {field} as {le} and as {be}; using {value}"
+    );
+    raw[at..at + 2].copy_from_slice(&value.to_le_bytes());
+    raw[at + 2..at + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+/// An image read with its descriptors repaired.
+pub(crate) struct Repaired {
+    pub(crate) source: Arc<dyn IsoSource>,
+    pub(crate) repairs: Arc<Repairs>,
+}
+
+impl IsoSource for Repaired {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let sector = offset / SECTOR as u64;
+        // The first repaired sector at or after the one read from
+        let next = self.repairs.sectors.iter().find(|(s, _)| *s >= sector);
+        match next {
+            Some((s, raw)) if *s == sector => {
+                let within = (offset % SECTOR as u64) as usize;
+                let n = buf.len().min(SECTOR - within);
+                buf[..n].copy_from_slice(&raw[within..within + n]);
+                Ok(n)
+            }
+            Some((s, _)) => {
+                let before = (s * SECTOR as u64 - offset).min(buf.len() as u64) as usize;
+                self.source.read_at(offset, &mut buf[..before])
+            }
+            None => self.source.read_at(offset, buf),
+        }
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        self.source.len()
+    }
+}
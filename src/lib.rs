@@ -22,7 +22,7 @@
 //! async fn main() {
 //!     let addr = "127.0.0.1:2121";
 //!
-//!     let server = ServerBuilder::new(Box::new(move || Storage::new("/path/to/your/image.iso")))
+//!     let server = ServerBuilder::new(Storage::shared("/path/to/your/image.iso"))
 //!         .greeting("Welcome to my ISO over FTP")
 //!         .passive_ports(50000..=65535)
 //!         .build()
@@ -38,95 +38,3595 @@
 //! ```sh
 //! lftp localhost -p 2121
 //! ```
+//!
+//! ## Configuration
+//!
+//! Use [`Storage::builder`] instead of [`Storage::new`] to enable optional behaviour, for example a
+//! per-user download [`Quota`]:
+//!
+//! ```no_run
+//! use unftp_sbe_iso::{Quota, Storage};
+//!
+//! let storage = Storage::builder("/path/to/your/image.iso")
+//!     .quota(Quota::new(10 * 1024 * 1024 * 1024))
+//!     .build();
+//! ```
+
+mod alias;
+mod bandwidth;
+mod budget;
+mod cache;
+mod cancel;
+#[cfg(feature = "catalog")]
+mod catalog;
+#[cfg(feature = "checksums")]
+mod checksums;
+mod clonecd;
+mod conformance;
+mod date;
+mod descriptor;
+mod diff;
+mod disk_cache;
+mod duplicates;
+mod el_torito;
+mod error;
+mod export;
+mod file;
+mod filter;
+#[cfg(feature = "test-util")]
+pub mod fixture;
+mod fsck;
+mod glob;
+mod gzip;
+#[cfg(feature = "checksums")]
+mod hash;
+#[cfg(feature = "http-gateway")]
+mod http;
+mod hybrid;
+mod interleave;
+mod lenient;
+mod library;
+mod namespace;
+#[cfg(feature = "otel")]
+mod otel;
+mod path;
+mod path_table;
+mod pool;
+mod quota;
+mod raw;
+mod records;
+mod retry;
+mod reveal;
+mod session;
+#[cfg(feature = "sftp")]
+mod sftp;
+mod short_names;
+#[cfg(feature = "checksums")]
+mod signature;
+mod slow;
+mod source;
+mod spool;
+mod stats;
+mod stream;
+mod susp;
+mod template;
+#[cfg(feature = "tftp")]
+mod tftp;
+mod versions;
+mod views;
+mod virtual_file;
+mod walk;
+#[cfg(feature = "watch")]
+mod watch;
+mod window;
+
+pub use alias::Aliases;
+pub use bandwidth::{Bandwidth, BandwidthClasses, TieredBandwidth};
+pub use budget::MemoryBudget;
+pub use cache::{CacheConfig, CacheCounters, CacheStats, EvictionPolicy};
+#[cfg(feature = "checksums")]
+pub use checksums::IntegrityMode;
+pub use clonecd::{CcdTrack, CloneCd, CloneCdInfo, Subchannel};
+pub use conformance::{ConformanceReport, Violation, ViolationKind};
+pub use descriptor::{DescriptorKind, VolumeDescriptor, VolumeInfo};
+#[cfg(feature = "checksums")]
+pub use diff::diff_with_hash;
+pub use diff::{Change, ChangeKind, DiffReport, diff};
+pub use disk_cache::DiskCache;
+pub use duplicates::Duplicates;
+pub use error::IsoStorageError;
+pub use export::Compression;
+pub use file::IsoAsyncFile;
+pub use filter::Filter;
+pub use fsck::{FsckReport, Problem, ProblemKind, ValidationMode};
+#[cfg(feature = "checksums")]
+pub use hash::{Digest, HashAlgorithm, HashFunction, HashState};
+#[cfg(feature = "http-gateway")]
+pub use http::HttpGateway;
+pub use hybrid::{HybridLayout, Partition, PartitionKind};
+pub use library::ImageNames;
+pub use namespace::Namespace;
+pub use pool::IoPool;
+pub use quota::{MemoryQuotaStore, Quota, QuotaStore};
+pub use raw::{Check, CorruptSector, RawSectors, SectorStats};
+pub use retry::RetryPolicy;
+#[cfg(feature = "sftp")]
+pub use sftp::SftpSubsystem;
+#[cfg(feature = "checksums")]
+pub use signature::ImageSignature;
+pub use source::{AsyncIsoSource, IsoSource};
+pub use stats::{PathStats, TransferStats};
+pub use susp::Extensions;
+#[cfg(feature = "tftp")]
+pub use tftp::TftpServer;
+pub use versions::FileVersions;
+pub use views::View;
+pub use walk::{Walk, WalkLimits};
+pub use window::AccessWindows;
+
+use async_trait::async_trait;
+use budget::Reservation;
+use bytes::Bytes;
+use cache::{Cache, Caches};
+use cancel::Cancel;
+use cdfs::{DirectoryEntry, ExtraAttributes, ISO9660, ISODirectory, ISOFile};
+use interleave::FileReader;
+use library::{Library, Shelf};
+use path::PathOptions;
+use path_table::PathTable;
+use records::RecordIndex;
+use retry::Retrying;
+use reveal::{RevealPolicy, Revealed};
+use session::ReadSession;
+use slow::SlowLog;
+use source::{Buffered, Origin, SharedFile, SourceReader};
+use spool::{Materialized, Spooled};
+use stats::StatsRegistry;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Debug,
+    io::{self, Cursor, Read},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use template::Tenants;
+use tokio::io::AsyncRead;
+use unftp_core::{
+    auth::UserDetail,
+    storage::{Error, ErrorKind, FEATURE_RESTART, Fileinfo, Metadata, Result, StorageBackend},
+};
+use views::Views;
+use virtual_file::VirtualFile;
+
+/// A virtual file system that tells the unftp server how to access ".iso" (ISO 9660) files.
+///
+/// Clones share the open image file, the caches and the download statistics, so hand out clones
+/// of a single instance from the libunftp factory closure rather than building one per
+/// connection. [`Storage::shared`] and [`Storage::factory`] return such a closure.
+///
+/// # Concurrency
+///
+/// `Storage` is `Send` and `Sync`, and cloning it only bumps a reference count. Its state is
+/// immutable apart from the open image file, each cache and the statistics, which sit behind
+/// locks of their own that are held only to look up or update an entry, never while reading from
+/// the image. Operations of different sessions therefore run in parallel, each parsing the
+/// directory records it needs itself.
+#[derive(Debug, Clone)]
+pub struct Storage {
+    inner: Arc<Inner>,
+}
+
+/// The state shared by all clones of a [`Storage`].
+#[derive(Debug)]
+struct Inner {
+    origin: Origin,
+    quota: Option<Quota>,
+    bandwidth: Option<Bandwidth>,
+    filter: Option<Filter>,
+    windows: Option<AccessWindows>,
+    max_file_size: Option<u64>,
+    read_timeout: Option<Duration>,
+    retry: RetryPolicy,
+    io_pool: Option<IoPool>,
+    boot_images: bool,
+    gunzip: bool,
+    spool: Option<PathBuf>,
+    validation: ValidationMode,
+    slow: SlowLog,
+    #[cfg(feature = "catalog")]
+    catalog: Option<PathBuf>,
+    #[cfg(feature = "checksums")]
+    integrity: Option<IntegrityMode>,
+    #[cfg(feature = "checksums")]
+    signature: Option<(ImageSignature, IntegrityMode)>,
+    #[cfg(feature = "checksums")]
+    hash_functions: hash::HashFunctions,
+    views: Vec<View>,
+    virtual_files: Vec<VirtualFile>,
+    walk_limits: WalkLimits,
+    paths: Arc<PathOptions>,
+    stats: Arc<StatsRegistry>,
+    budget: Option<MemoryBudget>,
+    caches: Caches,
+    /// The back-ends of the users of a [templated](Storage::templated) storage
+    tenants: Option<Tenants>,
+    /// The images of a [directory](Storage::directory) of them
+    library: Option<Library>,
+    /// The back-end showing the entries flagged hidden, for the users allowed to see them
+    revealed: Option<Revealed>,
+}
+
+/// Builds a [`Storage`] with optional behaviour enabled. Obtained via [`Storage::builder`].
+#[derive(Debug, Clone)]
+pub struct StorageBuilder {
+    origin: Origin,
+    quota: Option<Quota>,
+    bandwidth: Option<Bandwidth>,
+    filter: Option<Filter>,
+    windows: Option<AccessWindows>,
+    max_file_size: Option<u64>,
+    read_timeout: Option<Duration>,
+    retry: RetryPolicy,
+    io_pool: Option<IoPool>,
+    paths: PathOptions,
+    cache: CacheConfig,
+    budget: Option<MemoryBudget>,
+    coalesce: usize,
+    boot_images: bool,
+    gunzip: bool,
+    spool: Option<PathBuf>,
+    validation: ValidationMode,
+    slow: SlowLog,
+    #[cfg(feature = "catalog")]
+    catalog: Option<PathBuf>,
+    #[cfg(feature = "checksums")]
+    integrity: Option<IntegrityMode>,
+    #[cfg(feature = "checksums")]
+    signature: Option<(ImageSignature, IntegrityMode)>,
+    #[cfg(feature = "checksums")]
+    hash_functions: hash::HashFunctions,
+    views: Vec<View>,
+    virtual_files: Vec<VirtualFile>,
+    walk_limits: WalkLimits,
+    image_names: ImageNames,
+    changed: Option<(String, String)>,
+    hidden: Option<RevealPolicy>,
+}
+
+impl StorageBuilder {
+    /// Limits the number of bytes every user may download. Once a user's quota is used up,
+    /// further RETRs are refused, and a download that would go past it fails at the limit.
+    pub fn quota(mut self, quota: Quota) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Limits the rate every user may download at to that of their class, so that, say,
+    /// anonymous users can't crowd out the others.
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> Self {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// Only serves the files the given filter allows. Other files are left out of listings and
+    /// can't be downloaded.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Hides the entries the image flags hidden, with the existence bit, and its associated files
+    /// from the users `policy` returns `false` for, as if they weren't there. The users it returns
+    /// `true` for, like administrators, see every entry. Without a policy everyone does.
+    ///
+    /// ```
+    /// use unftp_sbe_iso::Storage;
+    ///
+    /// let storage = Storage::builder("/srv/images/archive.iso")
+    ///     .hidden_entries(|user| user.to_string() == "admin")
+    ///     .build();
+    /// ```
+    ///
+    /// Users who see the hidden entries are served by a back-end of their own, with caches of its
+    /// own, built the first time one of them logs in.
+    pub fn hidden_entries<F>(mut self, policy: F) -> Self
+    where
+        F: Fn(&dyn UserDetail) -> bool + Send + Sync + 'static,
+    {
+        self.hidden = Some(RevealPolicy(Arc::new(policy)));
+        self
+    }
+
+    /// Refuses to serve files larger than `bytes`. They are still listed.
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = Some(bytes);
+        self
+    }
+
+    /// Gives up on operations that take longer than `timeout` to read from the image, failing
+    /// them with [`ErrorKind::TransientFileNotAvailable`] instead of wedging the session. Meant
+    /// for images on flaky network mounts or physical drives. Without a timeout, reads happen on
+    /// the thread that runs the session. A download under way fails with a read error once the
+    /// next part of the file takes longer than `timeout` to read.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Retries reads from the image that fail with errors that are likely to be transient, as
+    /// they are for images on network mounts. Nothing is retried by default. Combine with a
+    /// [read timeout](Self::read_timeout) so sessions don't wait on the backoff.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Reads the image on the threads of `pool` rather than on the thread that runs the session
+    /// or on tokio's blocking thread pool, so that a burst of downloads can't starve other
+    /// blocking work of the application. Every operation that reads from the image runs there,
+    /// downloads a chunk at a time.
+    pub fn io_pool(mut self, pool: IoPool) -> Self {
+        self.io_pool = Some(pool);
+        self
+    }
+
+    /// Serves the boot images of El Torito bootable images as `/boot.img` and, for UEFI entries,
+    /// `/efi.img`, so that netboot tooling can fetch them. Their contents are the exact bytes
+    /// the firmware loads. Files of the same name in the image take precedence. Off by default.
+    pub fn boot_images(mut self, enabled: bool) -> Self {
+        self.boot_images = enabled;
+        self
+    }
+
+    /// Serves `NAME` by decompressing `NAME.gz` when the image has no `NAME`, for images that
+    /// ship compressed documentation or manual pages. Listings still show only `NAME.gz`. The
+    /// file is decompressed into memory as it is read, up to the
+    /// [maximum file size](Self::max_file_size) or 256 MiB, whichever is less, unless it is
+    /// [spooled](Self::spool_dir). Off by default.
+    pub fn gunzip(mut self, enabled: bool) -> Self {
+        self.gunzip = enabled;
+        self
+    }
+
+    /// Spills the files that have to be read whole before they are served, those
+    /// [decompressed](Self::gunzip) and, unless they fit the content cache, those checked against
+    /// the [checksum lists](Self::integrity) of the image, to temporary files in `dir` once they
+    /// hold more than 1 MiB, rather than keeping them in memory for as long as their downloads
+    /// take. Spooled files take only the buffers of a download from the
+    /// [memory budget](Self::memory_budget), and are decompressed up to the maximum file size or
+    /// 4 GiB, as the compressed file is read.
+    ///
+    /// The temporary files are removed as soon as they are created on systems that allow it, so
+    /// none are left behind by a crash, and once their downloads end elsewhere. Digests computed
+    /// by [`Storage::hash`] are computed a chunk at a time regardless, but for those of
+    /// decompressed files, which are spooled too.
+    pub fn spool_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.spool = Some(dir.into());
+        self
+    }
+
+    /// Opens images whose volume descriptors don't conform instead of refusing them: copies of a
+    /// field that disagree between byte orders are settled on the plausible one, invalid dates
+    /// are left unset, descriptors of an unknown version are read as version 1, enhanced and
+    /// unreadable supplementary volumes are skipped and a missing set terminator is assumed.
+    /// Every repair is logged as a warning. Off by default.
+    ///
+    /// The same as [`validation`](Self::validation) with [`ValidationMode::Lenient`] or, when
+    /// disabled, [`ValidationMode::Standard`].
+    pub fn lenient(mut self, enabled: bool) -> Self {
+        self.validation = if enabled {
+            ValidationMode::Lenient
+        } else {
+            ValidationMode::Standard
+        };
+        self
+    }
+
+    /// Logs every lookup, listing, opening of a download and chunk read for one that takes
+    /// longer than `threshold`, as a warning with the path and the entries or bytes involved,
+    /// to tell slowness of the image apart from slowness of the network. Off by default.
+    pub fn log_slow_operations(mut self, threshold: Duration) -> Self {
+        self.slow = SlowLog(Some(threshold));
+        self
+    }
+
+    /// Sets how images with structural problems, the ones [`Storage::fsck`] finds, are treated:
+    /// refused, served as far as cdfs opens them, or repaired where possible and served with
+    /// their problems logged. [`ValidationMode::Standard`] by default.
+    pub fn validation(mut self, mode: ValidationMode) -> Self {
+        self.validation = mode;
+        self
+    }
+
+    /// Keeps the indexes of the directories of the image in a catalog, the SQLite database at
+    /// `path`, created if there is none, so that they survive restarts instead of being built
+    /// again by reading every directory the first time it is looked in. Meant for large
+    /// collections of images, like a [directory](Storage::directory) of hundreds of them, which
+    /// can all share one catalog.
+    ///
+    /// Images are told apart by the SHA-256 digest of their contents, so an image that changes
+    /// gets its indexes built again, and one rebuilt to the same bytes keeps them. The digest is
+    /// computed the first time an image is opened, which reads it whole, and kept by the path,
+    /// size, modification time and inode of the image file, so it is only computed again once
+    /// the file is replaced; a custom [source](Storage::from_source) is read whole every time.
+    /// The indexes of images that are gone stay until the catalog is removed. A catalog that
+    /// can't be read or written is logged as a warning and left out.
+    #[cfg(feature = "catalog")]
+    pub fn catalog<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.catalog = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Checks served files against the checksum lists in the root directory of the image, like
+    /// `sha256sum.txt`, `SHA256SUMS` or `md5sum.txt`, to catch images that got corrupted. The
+    /// `mode` says whether a file that doesn't match is logged or refused. The lists are read
+    /// by [`open`](Self::open), or the first time a file is served. Files the lists don't name
+    /// are served unchecked, and so are files opened with [`Storage::open`]. Verified files are
+    /// read whole before they are sent, so that their checksum is known up front.
+    #[cfg(feature = "checksums")]
+    pub fn integrity(mut self, mode: IntegrityMode) -> Self {
+        self.integrity = Some(mode);
+        self
+    }
+
+    /// Serves the image only if the image file matches `signature`, to prove that the image
+    /// served is the one released. The file is read whole to compute its SHA-256 digest before
+    /// anything is served from it, by [`open`](Self::open) or the first operation, and again
+    /// whenever it is replaced. An image that doesn't match is refused with
+    /// [`ErrorKind::LocalError`] with [`IntegrityMode::Abort`], so that `open` fails, and served
+    /// with a warning logged with [`IntegrityMode::Log`]; [`Storage::image_verified`] tells
+    /// which it was either way.
+    ///
+    /// ```no_run
+    /// use unftp_sbe_iso::{ImageSignature, IntegrityMode, Storage};
+    ///
+    /// # async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+    /// let signature = ImageSignature::ed25519_files(
+    ///     "/etc/mirror/release.pub",
+    ///     "/srv/images/SHA256SUMS",
+    ///     "/srv/images/SHA256SUMS.sig",
+    /// )?;
+    /// let storage = Storage::builder("/srv/images/installer-1.0.iso")
+    ///     .image_signature(signature, IntegrityMode::Abort)
+    ///     .open()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Signed checksum lists name the image by its file name. The images of a
+    /// [directory](Storage::directory) are each looked up in the list by theirs, and images
+    /// from a [source](Storage::from_source) match a list of a single file.
+    #[cfg(feature = "checksums")]
+    pub fn image_signature(mut self, signature: ImageSignature, mode: IntegrityMode) -> Self {
+        self.signature = Some((signature, mode));
+        self
+    }
+
+    /// Registers a hash function besides the built-in ones under `name`, for
+    /// [`Storage::hash`] with [`HashAlgorithm::Custom`]. A function registered under a name
+    /// already taken replaces the earlier one.
+    #[cfg(feature = "checksums")]
+    pub fn hash_function(mut self, name: &'static str, function: Arc<dyn HashFunction>) -> Self {
+        self.hash_functions.insert(name, function);
+        self
+    }
+
+    /// Keeps parts of the image, listings and file contents in memory as configured. Nothing is
+    /// cached by default.
+    pub fn cache(mut self, config: CacheConfig) -> Self {
+        self.cache = config;
+        self
+    }
+
+    /// Reads `bytes` of the image at once, aligned to their size, whenever the block cache misses,
+    /// rather than the 64 KiB block missed, and keeps them in the block cache. Meant for images on
+    /// NFS, object stores or HTTP, where every read costs a round trip and the lookups of paths
+    /// read many small directory records. Rounded up to whole blocks of 64 KiB; 1 MiB suits most
+    /// such sources. Without a [block cache](Self::cache), one is enabled with room for 16 of
+    /// these reads, except with a [memory budget](Self::memory_budget) that leaves the block cache
+    /// out.
+    pub fn coalesce_reads(mut self, bytes: usize) -> Self {
+        self.coalesce = bytes;
+        self
+    }
+
+    /// Keeps the block cache, the content cache and the buffers of downloads within `budget`,
+    /// which may be shared with other back-ends. The budget sizes the block and content caches
+    /// instead of the [cache configuration](Self::cache), which still sets the listing cache and
+    /// the eviction policy. Downloads that don't fit in what is left of the budget fail with
+    /// [`ErrorKind::TransientFileNotAvailable`]. The images of a [directory](Storage::directory)
+    /// or a [templated](Storage::templated) storage share it.
+    pub fn memory_budget(mut self, budget: MemoryBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Treats `\` in client paths as a separator, so that legacy Windows clients sending paths
+    /// like `DIR\FILE.TXT` work. Names in listings are not affected. Off by default.
+    pub fn backslash_separators(mut self, enabled: bool) -> Self {
+        self.paths.backslash_separators = enabled;
+        self
+    }
+
+    /// Matches names regardless of their Unicode normalization form. macOS clients send names
+    /// decomposed (NFD) while Joliet images usually store them composed (NFC), so without this an
+    /// `é` in a name only resolves for some clients. Off by default.
+    pub fn unicode_normalization(mut self, enabled: bool) -> Self {
+        self.paths.unicode_normalization = enabled;
+        self
+    }
+
+    /// Presents every name as an uppercase 8.3 name, for DOS-era clients. Names that don't fit
+    /// are shortened to `BASE~N.EXT` with the lowest `N` that keeps them unique in their
+    /// directory. The shortened names are accepted in paths, as are the names stored in the
+    /// image. Off by default.
+    pub fn short_names(mut self, enabled: bool) -> Self {
+        self.paths.short_names = enabled;
+        self
+    }
+
+    /// Serves the given hierarchy of the image. Defaults to [`Namespace::Auto`].
+    pub fn namespace(mut self, namespace: Namespace) -> Self {
+        self.paths.namespace = namespace;
+        self
+    }
+
+    /// Looks paths that aren't found in the served [namespace](Self::namespace) up in the other
+    /// hierarchy of the image, so that scripts asking for `README.TXT` find `Read Me.txt` while
+    /// Joliet names are served, and the other way round. Files found that way are served under
+    /// the path they were asked for but aren't listed. Off by default. The primary hierarchy
+    /// only has 8.3 names on images without Rock Ridge entries, whose long names replace them.
+    pub fn namespace_fallback(mut self, enabled: bool) -> Self {
+        self.paths.namespace_fallback = enabled;
+        self
+    }
+
+    /// Chooses which versions of a file recorded in several, like `FILE.TXT;1` next to
+    /// `FILE.TXT;2`, are exposed. Defaults to [`FileVersions::Highest`]; archival users can
+    /// retrieve superseded revisions with [`FileVersions::All`].
+    pub fn file_versions(mut self, versions: FileVersions) -> Self {
+        self.paths.versions = versions;
+        self
+    }
+
+    /// Chooses which of several records with the same name in one directory is exposed, for
+    /// badly mastered images. Defaults to [`Duplicates::First`].
+    pub fn duplicates(mut self, duplicates: Duplicates) -> Self {
+        self.paths.duplicates = duplicates;
+        self
+    }
+
+    /// Serves the given directory of the image as the FTP root, exposing only the subtree below
+    /// it. Defaults to `/`, the root of the image. Absolute symbolic link targets resolve from
+    /// the directory, and links that climb out of it with `..` can't be followed.
+    pub fn root<P: AsRef<Path>>(mut self, root: P) -> Self {
+        self.paths.root = root.as_ref().to_path_buf();
+        self
+    }
+
+    /// Serves the directory of an image that wraps all of its contents in one, often named
+    /// after the release, as the FTP root, so that clients land on the contents. Applies when
+    /// the only entry of the [root](Self::root) is a directory; otherwise the root is served as
+    /// it is. Off by default.
+    pub fn descend_single_directory(mut self, descend: bool) -> Self {
+        self.paths.descend = descend;
+        self
+    }
+
+    /// Renames or relocates paths of the image in the tree presented to clients.
+    pub fn aliases(mut self, aliases: Aliases) -> Self {
+        self.paths.aliases = aliases;
+        self
+    }
+
+    /// Makes subtrees of the presented tree accessible to clients within windows of time only.
+    pub fn access_windows(mut self, windows: AccessWindows) -> Self {
+        self.windows = Some(windows);
+        self
+    }
+
+    /// Adds a virtual top-level directory that presents the files of the image in another
+    /// arrangement, like [by date](View::ByDate), or one hierarchy of the image, like
+    /// [Joliet](View::Joliet). Can be called several times for several views.
+    pub fn view(mut self, view: View) -> Self {
+        if !self.views.contains(&view) {
+            self.views.push(view);
+        }
+        self
+    }
+
+    /// Serves a file that isn't in the image at `path`, holding `contents`, like a notice of the
+    /// policy of a mirror at `/MIRROR-POLICY.txt` of every image it serves. The file is listed
+    /// in its directory, with the times of the root directory, and served like the files of the
+    /// image, but an entry of the same name in the image takes precedence, and a directory the
+    /// image doesn't have isn't made up for it. Can be called several times for several files.
+    /// A path naming the root is ignored.
+    ///
+    /// ```
+    /// use unftp_sbe_iso::Storage;
+    ///
+    /// let storage = Storage::directory_builder("/srv/images")
+    ///     .virtual_file("/MIRROR-POLICY.txt", "Mirrored for internal use only.\n")
+    ///     .build();
+    /// ```
+    pub fn virtual_file<P, C>(mut self, path: P, contents: C) -> Self
+    where
+        P: AsRef<Path>,
+        C: Into<Bytes>,
+    {
+        let path = path.as_ref().to_string_lossy();
+        self.virtual_files
+            .extend(VirtualFile::new(&path, contents.into()));
+        self
+    }
+
+    /// Like [`virtual_file`](Self::virtual_file), but serves what the local file at `local`
+    /// holds, read every time it is served so that edits show right away, with its size and
+    /// modification time. A local file that can't be read fails the operations on the virtual
+    /// one, and leaves it out of listings.
+    pub fn virtual_file_from<P, L>(mut self, path: P, local: L) -> Self
+    where
+        P: AsRef<Path>,
+        L: Into<PathBuf>,
+    {
+        let path = path.as_ref().to_string_lossy();
+        self.virtual_files
+            .extend(VirtualFile::local(&path, local.into()));
+        self
+    }
+
+    /// Limits how deep and how far recursive listings and other walks of the tree go, so that a
+    /// crafted image can't make them run away.
+    pub fn walk_limits(mut self, limits: WalkLimits) -> Self {
+        self.walk_limits = limits;
+        self
+    }
+
+    /// Picks what the directories of the images of a [directory](Storage::directory) are
+    /// called. Images that would get the same name get `~2`, `~3` and so on after it.
+    pub fn image_names(mut self, names: ImageNames) -> Self {
+        self.image_names = names;
+        self
+    }
+
+    /// Adds a `/CHANGED` directory to a [directory](Storage::directory) of images, holding the
+    /// files of the image called `new` that the image called `old` doesn't hold as they are:
+    /// those added and those of another size or modification time, in the tree they have in
+    /// `new`. QA can download exactly what changed between two builds served side by side.
+    /// The images are compared, like [`diff`] does, the first time the directory is entered and
+    /// again after either image changes, on the blocking pool. The directory is only there while
+    /// both images are, and hides an image called `CHANGED`.
+    ///
+    /// ```no_run
+    /// use unftp_sbe_iso::Storage;
+    ///
+    /// // /v1 and /v2 serve /srv/builds/v1.iso and v2.iso, /CHANGED the files v2 changed
+    /// let storage = Storage::directory_builder("/srv/builds")
+    ///     .changed_view("v1", "v2")
+    ///     .build();
+    /// ```
+    pub fn changed_view<S: Into<String>>(mut self, old: S, new: S) -> Self {
+        self.changed = Some((old.into(), new.into()));
+        self
+    }
+
+    /// Creates the storage back-end without touching the image. It is opened on the first FTP
+    /// command instead, which keeps startup fast when serving many images but leaves a missing
+    /// or broken image unnoticed until a client runs into it. See [`open`](Self::open) for the
+    /// opposite.
+    pub fn build(self) -> Storage {
+        self.build_sharing(Arc::default())
+    }
+
+    /// Creates the storage back-end keeping its download statistics in `stats`.
+    fn build_sharing(self, stats: Arc<StatsRegistry>) -> Storage {
+        let tenants = match &self.origin {
+            Origin::Templated(template) => {
+                Some(Tenants::new(template.clone(), self.clone(), stats.clone()))
+            }
+            _ => None,
+        };
+        let library = match &self.origin {
+            Origin::Directory(dir) => {
+                Some(Library::new(dir.clone(), self.image_names, self.clone()))
+            }
+            _ => None,
+        };
+        let revealed = match (&self.origin, &self.hidden) {
+            // The back-ends of the images decide for themselves
+            (Origin::Templated(_) | Origin::Directory(_), _) | (_, None) => None,
+            (_, Some(policy)) => Some(Revealed::new(policy.clone(), self.clone(), stats.clone())),
+        };
+        let mut paths = self.paths;
+        paths.flagged = self.hidden.is_none();
+        let inner = Inner {
+            origin: self.origin,
+            quota: self.quota,
+            bandwidth: self.bandwidth,
+            filter: self.filter,
+            windows: self.windows,
+            max_file_size: self.max_file_size,
+            read_timeout: self.read_timeout,
+            retry: self.retry,
+            io_pool: self.io_pool,
+            boot_images: self.boot_images,
+            gunzip: self.gunzip,
+            spool: self.spool,
+            validation: self.validation,
+            slow: self.slow,
+            #[cfg(feature = "catalog")]
+            catalog: self.catalog,
+            #[cfg(feature = "checksums")]
+            integrity: self.integrity,
+            #[cfg(feature = "checksums")]
+            signature: self.signature,
+            #[cfg(feature = "checksums")]
+            hash_functions: self.hash_functions,
+            views: self.views,
+            virtual_files: self.virtual_files,
+            walk_limits: self.walk_limits,
+            paths: Arc::new(paths),
+            stats,
+            caches: Caches::new(&self.cache, self.budget.as_ref(), self.coalesce),
+            budget: self.budget,
+            tenants,
+            library,
+            revealed,
+        };
+        Storage {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Creates the storage back-end and opens the image right away, failing if it can't be read
+    /// or if the [root](Self::root) is not a directory. The root listing is read to warm the
+    /// [caches](Self::cache).
+    pub async fn open(self) -> Result<Storage> {
+        let storage = self.build();
+        // Images of a templated storage are opened as their users log in
+        if storage.inner.tenants.is_some() {
+            return Ok(storage);
+        }
+        if storage.inner.library.is_some() {
+            let finder = storage.clone();
+            storage
+                .on_pool(move || finder.find_image("", Path::new("/")).map(drop))
+                .await?;
+            return Ok(storage);
+        }
+        storage
+            .blocking(|storage| {
+                storage.read_dir(Path::new("/"))?;
+                #[cfg(feature = "checksums")]
+                if storage.inner.integrity.is_some() {
+                    storage.checksums(&storage.open_iso()?);
+                }
+                Ok(())
+            })
+            .await?;
+        Ok(storage)
+    }
+}
+
+impl Storage {
+    /// Creates the storage back-end, pointing it to the ".iso" file
+    /// given in the `iso_path` parameter.
+    pub fn new<P: AsRef<Path>>(iso_path: P) -> Self {
+        Self::builder(iso_path).build()
+    }
+
+    /// Creates the storage back-end for the ".iso" file given in the `iso_path` parameter and
+    /// returns the per-connection factory libunftp's `ServerBuilder` wants. Every connection
+    /// gets a clone that shares the one open image, its indexes and caches.
+    ///
+    /// ```no_run
+    /// use libunftp::ServerBuilder;
+    /// use unftp_sbe_iso::Storage;
+    ///
+    /// let server = ServerBuilder::new(Storage::shared("/srv/images/debian.iso"))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn shared<P: AsRef<Path>>(iso_path: P) -> Box<dyn Fn() -> Storage + Send + Sync> {
+        Self::new(iso_path).factory()
+    }
+
+    /// Returns a per-connection factory for libunftp's `ServerBuilder` that hands out clones of
+    /// this back-end, like [`shared`](Self::shared) does for one built with a
+    /// [`StorageBuilder`].
+    pub fn factory(&self) -> Box<dyn Fn() -> Storage + Send + Sync> {
+        let storage = self.clone();
+        Box::new(move || storage.clone())
+    }
+
+    /// Returns a [`StorageBuilder`] for the ".iso" file given in the `iso_path` parameter.
+    ///
+    /// The file is checked before every operation. Once another file takes its path, as when a
+    /// new release is moved over it, the back-end switches over to that one, while downloads
+    /// under way finish reading the file they started on. A file that is removed is served on
+    /// until another one takes its place.
+    pub fn builder<P: AsRef<Path>>(iso_path: P) -> StorageBuilder {
+        Self::origin_builder(Origin::Path(Arc::new(SharedFile::new(
+            iso_path.as_ref().to_path_buf(),
+        ))))
+    }
+
+    /// Creates the storage back-end for an image read from the given source.
+    pub fn from_source<S: IsoSource + 'static>(source: S) -> Self {
+        Self::source_builder(source).build()
+    }
+
+    /// Returns a [`StorageBuilder`] for an image read from the given source.
+    pub fn source_builder<S: IsoSource + 'static>(source: S) -> StorageBuilder {
+        Self::origin_builder(Origin::Source(Arc::new(source)))
+    }
+
+    /// Creates the storage back-end for an image read from the given asynchronous source.
+    pub fn from_async_source<S: AsyncIsoSource + 'static>(source: S) -> Self {
+        Self::async_source_builder(source).build()
+    }
+
+    /// Returns a [`StorageBuilder`] for an image read from the given asynchronous source.
+    pub fn async_source_builder<S: AsyncIsoSource + 'static>(source: S) -> StorageBuilder {
+        Self::origin_builder(Origin::Async(Arc::new(Buffered::new(source))))
+    }
+
+    /// Creates a storage back-end that serves every user the ".iso" file named by putting their
+    /// username in place of `{username}` in `template`.
+    ///
+    /// The username is what the [`Display`](std::fmt::Display) representation of libunftp's
+    /// `UserDetail` gives, as for [quotas](Quota). Names that are empty, `.` or `..`, or that
+    /// hold a path separator or a control character are refused, so that no user gets at an
+    /// image the template doesn't name for them.
+    ///
+    /// ```no_run
+    /// use libunftp::ServerBuilder;
+    /// use unftp_sbe_iso::Storage;
+    ///
+    /// let storage = Storage::templated("/srv/isos/{username}.iso");
+    /// let server = ServerBuilder::new(storage.factory()).build().unwrap();
+    /// ```
+    pub fn templated(template: &str) -> Self {
+        Self::templated_builder(template).build()
+    }
+
+    /// Returns a [`StorageBuilder`] for the ".iso" files named by `template`, as for
+    /// [`templated`](Self::templated). Every user gets a back-end built with the same options,
+    /// with caches of its own, and the [statistics](Self::stats) of all of them are kept
+    /// together. The images are opened when their users first need them, and methods that
+    /// aren't given a user fail.
+    pub fn templated_builder(template: &str) -> StorageBuilder {
+        Self::origin_builder(Origin::Templated(template.into()))
+    }
+
+    /// Creates a storage back-end that serves every ".iso" file in `dir` as a directory at the
+    /// root, named after the file.
+    ///
+    /// ```no_run
+    /// use libunftp::ServerBuilder;
+    /// use unftp_sbe_iso::Storage;
+    ///
+    /// // /debian-12.iso/README.txt is README.txt of /srv/isos/debian-12.iso
+    /// let storage = Storage::directory("/srv/isos");
+    /// let server = ServerBuilder::new(storage.factory()).build().unwrap();
+    /// ```
+    pub fn directory<P: AsRef<Path>>(dir: P) -> Self {
+        Self::directory_builder(dir).build()
+    }
+
+    /// Returns a [`StorageBuilder`] for the ".iso" files in `dir`, as for
+    /// [`directory`](Self::directory). Every image gets a back-end built with the same options,
+    /// with caches of its own, so a [root](StorageBuilder::root) is the directory served within
+    /// each image. [`image_names`](StorageBuilder::image_names) picks what the directories
+    /// are called.
+    ///
+    /// The directory is read when a client first needs it, and read again by the next operation
+    /// after images are added, removed or renamed, which changes its modification time. The
+    /// images themselves are opened when clients open their directories, and the back-end of
+    /// an image that is gone goes once the operations still using it are done. See
+    /// `Storage::watch`, with the `watch` feature, for noticing changes without checking the
+    /// directory before every operation. Only FTP commands find their way to the images:
+    /// methods that aren't given a path at the root of the directory fail.
+    pub fn directory_builder<P: AsRef<Path>>(dir: P) -> StorageBuilder {
+        Self::origin_builder(Origin::Directory(dir.as_ref().to_path_buf()))
+    }
+
+    /// Returns the back-end that shows `user` the entries flagged hidden if they may see them,
+    /// this one otherwise.
+    fn revealed_to(&self, user: &impl UserDetail) -> Storage {
+        self.inner
+            .revealed
+            .as_ref()
+            .and_then(|revealed| revealed.storage(user))
+            .unwrap_or_else(|| self.clone())
+    }
+
+    /// Finds where an operation of `user` on `path` goes: to the back-end of the image at the
+    /// path within it, or to the directory of images itself. Images of a directory or of a
+    /// templated storage are found on the blocking pool, as finding them reads the directory.
+    async fn route(&self, user: &impl UserDetail, path: &Path) -> Result<Routed> {
+        // Templated back-ends reveal the hidden entries by the policy of the image of the user
+        let storage = match &self.inner.tenants {
+            Some(_) => self.clone(),
+            None => self.revealed_to(user),
+        };
+        let routed = match storage.inner.library.is_some() || storage.inner.tenants.is_some() {
+            true => {
+                let (username, path) = (user.to_string(), path.to_path_buf());
+                let finder = storage.clone();
+                storage
+                    .on_pool(move || finder.find_image(&username, &path))
+                    .await?
+            }
+            false => Routed::Image(storage, path.to_path_buf()),
+        };
+        let Routed::Image(storage, within) = routed else {
+            return Ok(routed);
+        };
+        let storage = storage.revealed_to(user);
+        // Outside their windows, entries are missing
+        if storage.inner.windows.is_some() {
+            let names = self.inner.paths.normalize(path)?;
+            let within = storage.inner.paths.normalize(&within)?;
+            let prefix = names[..names.len() - within.len()].to_vec();
+            let shut = storage
+                .blocking(move |storage| storage.shut(&prefix, &within))
+                .await?;
+            if shut {
+                return Err(error::not_found(path));
+            }
+        }
+        Ok(Routed::Image(storage, within))
+    }
+
+    /// Finds the back-end of the image `username` reaches `path` in, for [`route`]: that of the
+    /// image of the user if it is [templated](Self::templated), the one `path` starts with for a
+    /// [directory](Self::directory) of images. They are yet to be
+    /// [revealed](Self::revealed_to) to the user.
+    ///
+    /// [`route`]: Self::route
+    fn find_image(&self, username: &str, path: &Path) -> Result<Routed> {
+        if let Some(tenants) = &self.inner.tenants {
+            return Ok(Routed::Image(
+                tenants.storage(username)?,
+                path.to_path_buf(),
+            ));
+        }
+        let Some(library) = &self.inner.library else {
+            return Ok(Routed::Image(self.clone(), path.to_path_buf()));
+        };
+        let shelf = library.shelf()?;
+        let names = self.inner.paths.normalize(path)?;
+        if let Some((name, rest)) = names.split_first()
+            && let Some((new, delta)) = library.changed(&shelf, name, &self.inner.paths)?
+        {
+            if delta.holds(rest, &self.inner.paths).is_none() {
+                return Err(error::not_found(path));
+            }
+            return Ok(Routed::Image(new, path::absolute(rest)));
+        }
+        match library::split(&names) {
+            None => Ok(Routed::Directory(shelf)),
+            Some((name, within)) => match shelf.image(name, &self.inner.paths) {
+                // Keeps the separator a path ends in, which only names a directory
+                Some(image) if self.inner.paths.names_directory(path) => {
+                    Ok(Routed::Image(image.clone(), within.join("")))
+                }
+                Some(image) => Ok(Routed::Image(image.clone(), within)),
+                None => Err(error::not_found(path)),
+            },
+        }
+    }
+
+    /// Runs `work`, which touches the file system but doesn't read the image, on the
+    /// [I/O pool](StorageBuilder::io_pool) or tokio's blocking thread pool.
+    async fn on_pool<T, F>(&self, work: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        match pool::spawn(self.inner.io_pool.as_ref(), work).await {
+            Ok(result) => result,
+            Err(e) => Err(Error::new(ErrorKind::LocalError, e)),
+        }
+    }
+
+    /// Tells whether the entry at the path made up of `names` is outside its [access
+    /// window](StorageBuilder::access_windows). `prefix` holds the names of the path clients
+    /// reach the image under, like the name of the image in a directory of images. Both the
+    /// path as given and what it resolves to count: the file of the image an entry of a
+    /// [view](View) is, or the name without the version a client might add, like `FILE.TXT` for
+    /// `FILE.TXT;1`.
+    fn shut(&self, prefix: &[String], names: &[String]) -> Result<bool> {
+        let Some(windows) = &self.inner.windows else {
+            return Ok(false);
+        };
+        let paths = &self.inner.paths;
+        let now = SystemTime::now();
+        let shut = |names: &[String]| {
+            let full = [prefix, names].concat();
+            windows.shut(&full, now, |a, b| paths.matches(a, b))
+        };
+        Ok(shut(names) || shut(&self.resolved_names(names)?))
+    }
+
+    /// The names of the path of the entry at the path made up of `names`, for [`shut`]: that of
+    /// the file a view presents, the path within the hierarchy for the entries of a namespace
+    /// view, and `names` without the versions and trailing dots clients might add otherwise.
+    ///
+    /// [`shut`]: Self::shut
+    fn resolved_names(&self, names: &[String]) -> Result<Vec<String>> {
+        let paths = &self.inner.paths;
+        let plain = |names: &[String]| -> Vec<String> {
+            names
+                .iter()
+                .map(|name| {
+                    let name = versions::split_version(name).map_or(name.as_str(), |(n, _)| n);
+                    records::canonical(name).into_owned()
+                })
+                .collect()
+        };
+        let path = path::absolute(names);
+        let viewed = self.view_node(&path, names, |node| match node {
+            views::Node::File { path, .. } => paths.normalize(Path::new(path)),
+            views::Node::Dir(_) | views::Node::Entry { .. } => {
+                let namespace = self
+                    .inner
+                    .views
+                    .iter()
+                    .any(|view| view.is_namespace() && paths.matches(view.name(), &names[0]));
+                Ok(match namespace {
+                    true => plain(&names[1..]),
+                    false => names.to_vec(),
+                })
+            }
+        });
+        match viewed {
+            Ok(Some(names)) => names,
+            Ok(None) => Ok(plain(names)),
+            // Missing from the view, so the operation fails either way
+            Err(_) => Ok(names.to_vec()),
+        }
+    }
+
+    fn origin_builder(origin: Origin) -> StorageBuilder {
+        StorageBuilder {
+            origin,
+            quota: None,
+            bandwidth: None,
+            filter: None,
+            windows: None,
+            max_file_size: None,
+            read_timeout: None,
+            retry: RetryPolicy::default(),
+            io_pool: None,
+            paths: PathOptions::default(),
+            cache: CacheConfig::default(),
+            budget: None,
+            coalesce: 0,
+            boot_images: false,
+            gunzip: false,
+            spool: None,
+            validation: ValidationMode::default(),
+            slow: SlowLog::default(),
+            #[cfg(feature = "catalog")]
+            catalog: None,
+            #[cfg(feature = "checksums")]
+            integrity: None,
+            #[cfg(feature = "checksums")]
+            signature: None,
+            #[cfg(feature = "checksums")]
+            hash_functions: hash::HashFunctions::new(),
+            views: Vec::new(),
+            virtual_files: Vec::new(),
+            walk_limits: WalkLimits::default(),
+            image_names: ImageNames::default(),
+            changed: None,
+            hidden: None,
+        }
+    }
+
+    /// Returns the number of downloads and bytes served per path since the back-end was created.
+    pub fn stats(&self) -> HashMap<PathBuf, PathStats> {
+        let mut stats = self.inner.stats.snapshot();
+        // The paths of images in a directory of them start with the directory of the image
+        if let Some(library) = &self.inner.library
+            && let Ok(shelf) = library.shelf()
+        {
+            for (name, image) in shelf.images() {
+                stats.extend(image.stats().into_iter().map(|(path, counts)| {
+                    let within = path.strip_prefix("/").unwrap_or(&path);
+                    (Path::new("/").join(name).join(within), counts)
+                }));
+            }
+        }
+        stats
+    }
+
+    /// Returns how many downloads are under way, how many were served to the end and how many
+    /// were aborted since the back-end was created, with the bytes read for aborted downloads
+    /// that never reached the client. Counts the images of a [directory](Self::directory) too.
+    pub fn transfer_stats(&self) -> TransferStats {
+        let own = self.inner.stats.transfers();
+        match &self.inner.library {
+            Some(library) => match library.shelf() {
+                Ok(shelf) => std::iter::once(own)
+                    .chain(shelf.images().map(|(_, image)| image.transfer_stats()))
+                    .sum(),
+                Err(_) => own,
+            },
+            None => own,
+        }
+    }
+
+    /// Returns the hits, misses and evictions of the [caches](StorageBuilder::cache) since the
+    /// back-end was created, to tell whether their budgets fit the workload.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.inner.caches.stats()
+    }
+
+    /// Reads the volume descriptors of the image: the primary volume, supplementary volumes like
+    /// Joliet, boot records and partitions, in the order they are recorded.
+    pub async fn volume_descriptors(&self) -> Result<Vec<VolumeDescriptor>> {
+        self.blocking(|storage| {
+            descriptor::read(&*storage.source()?).map_err(|e| {
+                Error::new(
+                    ErrorKind::LocalError,
+                    format!(
+                        "could not read the volume descriptors of {:?}: {e}",
+                        storage.inner.origin
+                    ),
+                )
+            })
+        })
+        .await
+    }
+
+    /// Suggests a greeting for FTP clients from the primary volume descriptor, like `Serving
+    /// 'UBUNTU_22_04' (created 2022-04-21, 3.6 GB)`, with the name of the Joliet volume where
+    /// the primary one has none.
+    ///
+    /// ```no_run
+    /// # async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+    /// let storage = unftp_sbe_iso::Storage::new("/srv/images/ubuntu.iso");
+    /// let greeting = storage.suggested_greeting().await?;
+    /// let server = libunftp::ServerBuilder::new(storage.factory())
+    ///     .greeting(Box::leak(greeting.into_boxed_str()))
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn suggested_greeting(&self) -> Result<String> {
+        let descriptors = self.volume_descriptors().await?;
+        let volumes: Vec<&VolumeInfo> = descriptors
+            .iter()
+            .filter_map(|d| match &d.kind {
+                DescriptorKind::Primary(info) | DescriptorKind::Supplementary(info) => Some(info),
+                _ => None,
+            })
+            .collect();
+        let Some(primary) = descriptors.iter().find_map(|d| match &d.kind {
+            DescriptorKind::Primary(info) => Some(info),
+            _ => None,
+        }) else {
+            return Err(Error::new(
+                ErrorKind::LocalError,
+                format!("{:?} has no primary volume", self.inner.origin),
+            ));
+        };
+        let name = volumes
+            .iter()
+            .map(|info| info.volume_id.as_str())
+            .find(|name| !name.is_empty())
+            .unwrap_or_default();
+        Ok(primary.greeting(name))
+    }
+
+    /// Takes an inventory of the SUSP entries of the primary hierarchy, like the `PX`, `NM`,
+    /// `SL` and `TF` entries of Rock Ridge or the `ZF` entries of zisofs, with the extensions
+    /// declared by `ER` entries, for tooling that decides whether to trust the Rock Ridge
+    /// names and attributes of an image. Reads every directory of the hierarchy.
+    pub async fn extensions(&self) -> Result<Extensions> {
+        self.blocking(|storage| {
+            susp::inventory(&*storage.source()?).map_err(|e| {
+                Error::new(
+                    ErrorKind::LocalError,
+                    format!(
+                        "could not read the SUSP entries of {:?}: {e}",
+                        storage.inner.origin
+                    ),
+                )
+            })
+        })
+        .await
+    }
+
+    /// Checks how closely the primary hierarchy keeps to ISO 9660: the interchange level its
+    /// identifiers need, whether there are Joliet and Rock Ridge namespaces, how deep and long
+    /// its paths get, and which rules its records break, for checking mastered images before
+    /// they are published. Reads every directory of the hierarchy.
+    pub async fn conformance(&self) -> Result<ConformanceReport> {
+        self.blocking(|storage| {
+            conformance::check(&*storage.source()?).map_err(|e| {
+                Error::new(
+                    ErrorKind::LocalError,
+                    format!("could not check {:?}: {e}", storage.inner.origin),
+                )
+            })
+        })
+        .await
+    }
+
+    /// Reads the partition tables of an isohybrid image, the MBR and GPT in its system area that
+    /// let it boot from a USB stick. Returns `None` for plain images. The tables don't change
+    /// how the image is served: its sectors count from the start of the image regardless, and
+    /// the padding isohybrid adds after the volume goes unused.
+    pub async fn hybrid_layout(&self) -> Result<Option<HybridLayout>> {
+        self.blocking(|storage| {
+            hybrid::read(&*storage.source()?).map_err(|e| {
+                Error::new(
+                    ErrorKind::LocalError,
+                    format!(
+                        "could not read the partition tables of {:?}: {e}",
+                        storage.inner.origin
+                    ),
+                )
+            })
+        })
+        .await
+    }
+
+    /// Checks the structure of the whole image: that the volume descriptors are sound and the
+    /// volumes fit the image, and that every record of every directory hierarchy decodes and
+    /// points within the image. Problems are reported rather than failing the check; only an
+    /// image that can't be read at all fails it.
+    ///
+    /// ```no_run
+    /// # async fn check() -> Result<(), Box<dyn std::error::Error>> {
+    /// let report = unftp_sbe_iso::Storage::new("/srv/images/debian.iso").fsck().await?;
+    /// for problem in &report.problems {
+    ///     eprintln!("{problem}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn fsck(&self) -> Result<FsckReport> {
+        self.blocking(|storage| {
+            fsck::check(storage.source()?, storage.inner.retry).map_err(|e| {
+                Error::new(
+                    ErrorKind::LocalError,
+                    format!("could not check {:?}: {e}", storage.inner.origin),
+                )
+            })
+        })
+        .await
+    }
+
+    /// Lists the whole subtree at `path` in one go, like `ls -R`, for mirroring clients and
+    /// library users that need every entry. Each directory's entries are followed by those of its
+    /// subdirectories in turn, depth-first, under paths relative to `path` like
+    /// `manual/chapter1.txt`. Symbolic links are listed but not followed, and a directory that
+    /// is its own ancestor in a crafted image is listed but not descended into, and so are
+    /// [views](StorageBuilder::view) from the root. A file lists as itself. Fails past the
+    /// [walk limits](StorageBuilder::walk_limits).
+    ///
+    /// libunftp doesn't pass the options of `LIST -R` on to back-ends, so FTP clients can't
+    /// reach this.
+    pub async fn list_recursive<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Vec<Fileinfo<PathBuf, IsoMeta>>> {
+        let path = path.as_ref().to_path_buf();
+        self.blocking(move |storage| {
+            Ok(storage
+                .walk_tree(&path)?
+                .into_iter()
+                .map(|(name, metadata)| Fileinfo {
+                    path: name.into(),
+                    metadata,
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Writes every entry of the image to a tar archive at `dest`, compressed as `compression`,
+    /// for operators who need the logical contents of an image in one file to process further.
+    /// Entries come in the order of [`list_recursive`](Self::list_recursive) under the names
+    /// clients see, and the [`Filter`] and views apply like there. Rock Ridge permissions,
+    /// owners and symbolic links carry over into the archive; without them, directories are
+    /// `0555` and files `0444`. Files are read one chunk at a time, so memory stays flat however
+    /// large the image. Returns how many entries the archive holds.
+    ///
+    /// ```no_run
+    /// use unftp_sbe_iso::{Compression, Storage};
+    ///
+    /// # async fn dump() -> Result<(), Box<dyn std::error::Error>> {
+    /// let storage = Storage::new("/srv/images/debian.iso");
+    /// let entries = storage
+    ///     .export_tar("/var/tmp/debian.tar.zst", Compression::Zstd)
+    ///     .await?;
+    /// println!("{entries} entries exported");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn export_tar<P: AsRef<Path>>(
+        &self,
+        dest: P,
+        compression: Compression,
+    ) -> Result<u64> {
+        let dest = dest.as_ref().to_path_buf();
+        self.blocking(move |storage| {
+            let failed = |e: io::Error| {
+                Error::new(
+                    ErrorKind::LocalError,
+                    format!("could not write {dest:?}: {e}"),
+                )
+            };
+            let out = io::BufWriter::new(std::fs::File::create(&dest).map_err(failed)?);
+            match compression {
+                Compression::None => {
+                    let (entries, archive) = storage.export(out)?;
+                    archive.finish().map_err(failed)?;
+                    Ok(entries)
+                }
+                #[cfg(feature = "zstd")]
+                Compression::Zstd => {
+                    let encoder = zstd::Encoder::new(out, 0).map_err(failed)?;
+                    let (entries, archive) = storage.export(encoder)?;
+                    let (_, encoder) = archive.finish().map_err(failed)?;
+                    encoder.finish().map_err(failed)?;
+                    Ok(entries)
+                }
+            }
+        })
+        .await
+    }
+
+    /// Walks the subtree at `root` as a [`Walk`], a stream of its entries in the order of
+    /// [`list_recursive`](Self::list_recursive), for library users that want to stop early,
+    /// skip parts of the tree or keep memory flat on large images. Each directory is only
+    /// listed once the walk reaches it.
+    pub fn walk<P: AsRef<Path>>(&self, root: P) -> Walk {
+        Walk::new(
+            self.clone(),
+            root.as_ref().to_path_buf(),
+            self.inner.walk_limits,
+        )
+    }
+
+    /// Opens the file at `path` for reading from any position, for library users that need
+    /// random access. The path resolves like it does for clients, and the [`Filter`] and
+    /// [maximum file size](StorageBuilder::max_file_size) apply, but reads don't count towards
+    /// [quotas](StorageBuilder::quota) or the [statistics](Self::stats).
+    pub async fn open<P: AsRef<Path>>(&self, path: P) -> Result<IsoAsyncFile> {
+        let path = path.as_ref().to_path_buf();
+        self.blocking(move |storage| storage.open_file(&path)).await
+    }
+
+    /// Reads up to `len` bytes of the file at `path` from `offset`, for library users and
+    /// gateways that need part of a file without a reader. Fewer bytes come back where the file
+    /// ends sooner, and none from past its end. The path resolves like for
+    /// [`open`](Self::open), and like there reads don't count towards quotas or the statistics.
+    ///
+    /// ```no_run
+    /// # async fn header() -> Result<(), Box<dyn std::error::Error>> {
+    /// let storage = unftp_sbe_iso::Storage::new("/srv/images/debian.iso");
+    /// let magic = storage.read_range("/install/initrd.gz", 0, 2).await?;
+    /// assert_eq!(&magic[..], b"\x1f\x8b");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn read_range<P: AsRef<Path>>(
+        &self,
+        path: P,
+        offset: u64,
+        len: u64,
+    ) -> Result<Bytes> {
+        let path = path.as_ref().to_path_buf();
+        self.blocking(move |storage| {
+            let file = storage.open_file(&path)?;
+            let contents = file
+                .read_range(offset, len)
+                .map_err(|e| error::read("read error", e))?;
+            Ok(contents.into())
+        })
+        .await
+    }
+
+    /// Computes the digest of the file at `path` with `algorithm`, streaming its contents from
+    /// the image. Digests are kept by the extent of the file until the image changes, so asking
+    /// again, for the same file under another path too, reads nothing. The path resolves like
+    /// for [`open`](Self::open). `SITE MD5` uses this for MD5.
+    ///
+    /// ```no_run
+    /// use unftp_sbe_iso::{HashAlgorithm, Storage};
+    ///
+    /// # async fn manifest() -> Result<(), Box<dyn std::error::Error>> {
+    /// let storage = Storage::new("/srv/images/debian.iso");
+    /// let digest = storage.hash("/install/vmlinuz", HashAlgorithm::Sha256).await?;
+    /// println!("{digest}  install/vmlinuz");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "checksums")]
+    pub async fn hash<P: AsRef<Path>>(&self, path: P, algorithm: HashAlgorithm) -> Result<Digest> {
+        let path = path.as_ref().to_path_buf();
+        self.blocking(move |storage| storage.digest(&path, algorithm))
+            .await
+    }
+
+    /// Tells whether the image file matches the signature given with
+    /// [`StorageBuilder::image_signature`], checking it if it wasn't yet. `false` without a
+    /// signature, and for images served with a mismatch logged. Fails for storages of many
+    /// images, whose images are checked by their own back-ends.
+    #[cfg(feature = "checksums")]
+    pub async fn image_verified(&self) -> Result<bool> {
+        if self.inner.signature.is_none() {
+            return Ok(false);
+        }
+        self.blocking(|storage| {
+            let source = storage.image_file()?;
+            Ok(storage.signature_problem(&source)?.is_none())
+        })
+        .await
+    }
+
+    /// Spawns a task on the current tokio runtime that calls `callback` with the output of
+    /// [`stats`](Self::stats) every `every`, so the images of a [directory](Self::directory)
+    /// are reported too. The task ends once the `Storage` and all its clones are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called outside of a tokio runtime.
+    pub fn report_stats<F>(&self, every: Duration, callback: F) -> tokio::task::JoinHandle<()>
+    where
+        F: Fn(&HashMap<PathBuf, PathStats>) + Send + 'static,
+    {
+        let inner = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(inner) = inner.upgrade() else {
+                    break;
+                };
+                callback(&Storage { inner }.stats());
+            }
+        })
+    }
+
+    /// Spawns a task on the current tokio runtime that checks every `every` whether the image
+    /// changed, judging by its size and the modification date in its primary volume descriptor,
+    /// and drops the [caches](StorageBuilder::cache) if it did. Meant for custom sources whose
+    /// contents may change underneath, like images regenerated nightly on a network share. Image
+    /// files given by path are checked for replacement before every operation anyway. The task
+    /// ends once the `Storage` and all its clones are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called outside of a tokio runtime.
+    pub fn refresh_every(&self, every: Duration) -> tokio::task::JoinHandle<()> {
+        let inner = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            let mut last = None;
+            loop {
+                interval.tick().await;
+                let Some(inner) = inner.upgrade() else {
+                    break;
+                };
+                let storage = Storage { inner };
+                // An image that can't be read right now is checked again next time
+                let Ok(identity) = storage.blocking(Storage::volume_identity).await else {
+                    continue;
+                };
+                if last.as_ref().is_some_and(|last| *last != identity) {
+                    storage.inner.caches.clear();
+                    if let Some(revealed) = &storage.inner.revealed {
+                        revealed.clear();
+                    }
+                }
+                last = Some(identity);
+            }
+        })
+    }
+
+    /// Watches the image file, dropping the [caches](StorageBuilder::cache) as soon
+    /// as it is modified, replaced or removed, and switching over to the file at the path on the
+    /// next operation. Saves checking the file before every operation. For a
+    /// [directory](Storage::directory) of images, watches the directory instead, and looks for
+    /// the images again as soon as one is added, removed or renamed. The watch ends once the
+    /// `Storage` and all its clones are dropped.
+    ///
+    /// Uses the file notifications of the platform, through the `notify` crate: inotify on
+    /// Linux, FSEvents on macOS and `ReadDirectoryChangesW` on Windows. Fails for images that
+    /// were not given by path, or if the directory of the file can't be watched.
+    #[cfg(feature = "watch")]
+    pub fn watch(&self) -> std::io::Result<()> {
+        watch::watch(&self.inner)
+    }
+
+    /// Returns the size of the image and the modification date of its primary volume.
+    fn volume_identity(&self) -> Result<(u64, [u8; 17])> {
+        // The date is 17 bytes at offset 830 of the descriptor, which is the first in sector 16
+        const DATE_OFFSET: u64 = 16 * 2048 + 830;
+        // Read around the block cache, which would hold on to the old date
+        let (source, changed) = self.inner.origin.open().map_err(|e| self.image_error(e))?;
+        if changed {
+            self.inner.caches.clear();
+        }
+        let mut date = [0; 17];
+        let mut read = 0;
+        while read < date.len() {
+            match source
+                .read_at(DATE_OFFSET + read as u64, &mut date[read..])
+                .map_err(|e| self.image_error(e))?
+            {
+                0 => break,
+                n => read += n,
+            }
+        }
+        Ok((source.len().map_err(|e| self.image_error(e))?, date))
+    }
+
+    /// The error for an image that can't be read at all.
+    fn image_error(&self, e: io::Error) -> Error {
+        error::image(&self.inner.origin, e)
+    }
+
+    /// Returns the source of the image, dropping the caches if the image changed and refusing
+    /// images that don't match their [signature](StorageBuilder::image_signature).
+    fn source(&self) -> Result<Arc<dyn IsoSource>> {
+        let source = self.image_file()?;
+        #[cfg(feature = "checksums")]
+        if let Some(problem) = self.signature_problem(&source)?
+            && let Some((_, IntegrityMode::Abort)) = &self.inner.signature
+        {
+            return Err(Error::new(
+                ErrorKind::LocalError,
+                format!("refusing ISO image {:?}: {problem}", self.inner.origin),
+            ));
+        }
+        Ok(self.inner.caches.source(source))
+    }
+
+    /// Returns the source of the image as it is, dropping the caches if the image changed.
+    fn image_file(&self) -> Result<Arc<dyn IsoSource>> {
+        let (source, changed) = self.inner.origin.open().map_err(|e| self.image_error(e))?;
+        if changed {
+            self.inner.caches.clear();
+        }
+        Ok(source)
+    }
+
+    /// Checks the image file read from `source` against its
+    /// [signature](StorageBuilder::image_signature) the first time, logging a mismatch, and
+    /// returns what is wrong with it, if anything.
+    #[cfg(feature = "checksums")]
+    fn signature_problem(&self, source: &Arc<dyn IsoSource>) -> Result<Option<Arc<str>>> {
+        let Some((signature, _)) = &self.inner.signature else {
+            return Ok(None);
+        };
+        self.inner
+            .caches
+            .signature(|| {
+                let name = match &self.inner.origin {
+                    Origin::Path(file) => file.path().file_name(),
+                    _ => None,
+                };
+                let name = name.map(|name| name.to_string_lossy());
+                let problem = match signature.expected(name.as_deref()) {
+                    Err(problem) => Some(problem),
+                    Ok(expected) => {
+                        let hasher = hash::start(HashAlgorithm::Sha256, &self.inner.hash_functions)
+                            .expect("a built-in hash function");
+                        let len = source.len()?;
+                        let digest = hash::stream(
+                            source.clone(),
+                            0,
+                            len,
+                            HashAlgorithm::Sha256,
+                            hasher,
+                            self.inner.retry,
+                        )?;
+                        (digest.as_bytes() != expected)
+                            .then(|| format!("the image file's SHA-256 digest is {digest}"))
+                    }
+                };
+                if let Some(problem) = &problem {
+                    log::warn!(
+                        "{:?} doesn't match its signature: {problem}",
+                        self.inner.origin
+                    );
+                }
+                Ok(problem.map(Arc::from))
+            })
+            .map_err(|e| self.image_error(e))
+    }
+
+    fn open_iso(&self) -> Result<Image> {
+        let mut source = self.source()?;
+        let validation = self.inner.validation;
+        if validation == ValidationMode::Lenient {
+            let repairs = self
+                .inner
+                .caches
+                .repairs(|| lenient::Repairs::find(&*source));
+            if !repairs.is_empty() {
+                source = Arc::new(lenient::Repaired { source, repairs });
+            }
+        }
+        if validation != ValidationMode::Standard {
+            self.validate(&source)?;
+        }
+        let len = source.len().map_err(|e| self.image_error(e))?;
+        let reader = Retrying::new(SourceReader::new(source.clone()), self.inner.retry);
+        let iso = ISO9660::new(reader).map_err(|e| error::unparsable(&self.inner.origin, e))?;
+        let path_table = self.path_table(&iso, &*source);
+        #[cfg(feature = "catalog")]
+        let catalog = self.catalog(&*source);
+        let mut image = Image {
+            iso,
+            source,
+            len,
+            paths: self.inner.paths.clone(),
+            path_table,
+            indexes: self.inner.caches.indexes.clone(),
+            #[cfg(feature = "catalog")]
+            catalog,
+        };
+        if image.paths.descend {
+            image.paths = self.inner.caches.descended(|| {
+                let mut paths = PathOptions::clone(&image.paths);
+                if let Some(name) = image.single_directory() {
+                    paths.root.push(name);
+                }
+                Arc::new(paths)
+            });
+        }
+        Ok(image)
+    }
+
+    /// Checks the structure of the image the first time it is opened, as
+    /// [`StorageBuilder::validation`] asks: refusing it from then on if it has problems in
+    /// strict mode, logging them in lenient mode.
+    fn validate(&self, source: &Arc<dyn IsoSource>) -> Result<()> {
+        let report = self.inner.caches.validation(|| {
+            let report =
+                fsck::check(source.clone(), self.inner.retry).unwrap_or_else(|e| FsckReport {
+                    problems: vec![Problem {
+                        namespace: None,
+                        path: PathBuf::from("/"),
+                        kind: ProblemKind::Unreadable(e.to_string()),
+                    }],
+                    ..FsckReport::default()
+                });
+            if self.inner.validation == ValidationMode::Lenient {
+                for problem in &report.problems {
+                    log::warn!("{:?}: {problem}", self.inner.origin);
+                }
+            }
+            report
+        });
+        match report.problems.first() {
+            Some(problem) if self.inner.validation == ValidationMode::Strict => Err(Error::new(
+                ErrorKind::LocalError,
+                format!(
+                    "refusing ISO image {:?} with {} structural problems, the first: {problem}",
+                    self.inner.origin,
+                    report.problems.len()
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The part of the catalog for the image, if the storage keeps a
+    /// [catalog](StorageBuilder::catalog).
+    #[cfg(feature = "catalog")]
+    fn catalog(&self, source: &dyn IsoSource) -> Option<Arc<catalog::ImageCatalog>> {
+        let path = self.inner.catalog.as_ref()?;
+        self.inner.caches.catalog(|| {
+            // How records are named is part of what is indexed
+            let paths = &self.inner.paths;
+            let naming = [
+                paths.versions as u8,
+                paths.duplicates as u8,
+                (self.inner.validation == ValidationMode::Lenient) as u8,
+                paths.flagged as u8,
+            ];
+            let stamp = self.inner.origin.stamp();
+            catalog::ImageCatalog::open(path, source, stamp.as_deref(), &naming)
+                .inspect_err(|e| log::warn!("could not open the catalog {path:?}: {e}"))
+                .ok()
+        })
+    }
+
+    /// The path table of the served hierarchy, if lookups can use it. Rock Ridge names and links
+    /// aren't in the path table, nor are the short names of [`StorageBuilder::short_names`], and
+    /// it can't tell which of [duplicate](StorageBuilder::duplicates) names to take but the
+    /// first.
+    fn path_table(
+        &self,
+        iso: &ISO9660<IsoReader>,
+        source: &dyn IsoSource,
+    ) -> Option<Arc<PathTable>> {
+        // Nor can the path table tell hidden directories
+        let paths = &self.inner.paths;
+        if paths.short_names || !paths.flagged || paths.duplicates != Duplicates::First {
+            return None;
+        }
+        let root = self
+            .inner
+            .paths
+            .namespace
+            .root(iso)
+            .ok()?
+            .header()
+            .extent_loc;
+        self.inner.caches.path_table(root, || {
+            let primary = iso.root_at(0).map(|dir| dir.header().extent_loc);
+            if primary == Some(root) && iso.is_rr() {
+                return None;
+            }
+            // An unreadable path table only means lookups walk the directories
+            PathTable::read(source, root).ok().flatten()
+        })
+    }
+
+    /// Runs an operation that reads from the image. With a [read
+    /// timeout](StorageBuilder::read_timeout) or an asynchronous source it runs on tokio's
+    /// blocking thread pool, so that the session can give up on a read that hangs or so that the
+    /// source can be waited on. With an [I/O pool](StorageBuilder::io_pool) it always runs there.
+    async fn blocking<T, F>(&self, op: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Storage) -> Result<T> + Send + 'static,
+    {
+        #[cfg(feature = "otel")]
+        let op = {
+            let span = tracing::Span::current();
+            move |storage: &Storage| {
+                storage.record_volume(&span);
+                op(storage)
+            }
+        };
+        if self.inner.read_timeout.is_none()
+            && !self.inner.origin.blocks()
+            && self.inner.io_pool.is_none()
+        {
+            // A panic, like one of cdfs on a damaged image, fails the operation instead of the
+            // session, as it does on the pools
+            return panic::catch_unwind(AssertUnwindSafe(|| op(self))).unwrap_or_else(|_| {
+                Err(Error::new(
+                    ErrorKind::LocalError,
+                    format!("reading {:?} panicked", self.inner.origin),
+                ))
+            });
+        }
+        let storage = self.clone();
+        let task = pool::spawn(self.inner.io_pool.as_ref(), move || op(&storage));
+        let joined = match self.inner.read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, task).await,
+            None => Ok(task.await),
+        };
+        match joined {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => Err(Error::new(ErrorKind::LocalError, e)),
+            // The read carries on in the background until the source gives up itself
+            Err(_) => Err(Error::new(
+                ErrorKind::TransientFileNotAvailable,
+                format!(
+                    "reading {:?} timed out after {:?}",
+                    self.inner.origin,
+                    self.inner.read_timeout.unwrap_or_default()
+                ),
+            )),
+        }
+    }
+
+    /// Tells whether the [`Filter`] allows serving the given file.
+    fn serves(&self, image: &Image, file: &ISOFile<IsoReader>) -> bool {
+        self.inner.filter.as_ref().is_none_or(|filter| {
+            filter.allows(&file.identifier, || {
+                let mut head = Vec::new();
+                // An unreadable file sniffs as empty
+                let _ = FileReader::new(&image.source, file)
+                    .take(filter::SNIFF_LEN as u64)
+                    .read_to_end(&mut head);
+                head
+            })
+        })
+    }
+}
+
+/// A directory entry as listed to clients.
+type Listed = (String, IsoMeta);
+
+/// The names of the path of a directory and the entries listed in it.
+type DirListing = (Vec<String>, Vec<Fileinfo<PathBuf, IsoMeta>>);
+
+/// The metadata of an entry of the image.
+fn entry_meta(entry: &DirectoryEntry<IsoReader>) -> IsoMeta {
+    let len = match entry {
+        DirectoryEntry::Directory(d) => d.header().length as u64,
+        DirectoryEntry::File(f) => f.size() as u64,
+        DirectoryEntry::Symlink(l) => l.header().length as u64,
+    };
+    IsoMeta {
+        len,
+        dir: matches!(entry, DirectoryEntry::Directory(_)),
+        sym: matches!(entry, DirectoryEntry::Symlink(_)),
+        group: entry.group().unwrap_or(0),
+        owner: entry.owner().unwrap_or(0),
+        modified: entry.modify_time().into(),
+    }
+}
+
+/// The metadata of a boot image served as a file. It takes the times of the root directory.
+fn boot_meta(image: &Image, boot: el_torito::BootImage) -> Result<IsoMeta> {
+    let root = image.root_dir()?;
+    Ok(IsoMeta {
+        len: boot.len,
+        dir: false,
+        sym: false,
+        group: 0,
+        owner: 0,
+        modified: root.modify_time().into(),
+    })
+}
+
+/// What cdfs reads the image from.
+type IsoReader = Retrying<SourceReader>;
+
+/// An opened ISO image together with the size of the file backing it.
+struct Image {
+    iso: ISO9660<IsoReader>,
+    source: Arc<dyn IsoSource>,
+    len: u64,
+    paths: Arc<PathOptions>,
+    path_table: Option<Arc<PathTable>>,
+    indexes: Arc<Cache<(u32, u32), Arc<RecordIndex>>>,
+    #[cfg(feature = "catalog")]
+    catalog: Option<Arc<catalog::ImageCatalog>>,
+}
+
+/// Where the contents of a file lie: `len` bytes at `offset` of `source`.
+struct Located {
+    source: Arc<dyn IsoSource>,
+    offset: u64,
+    len: u64,
+    /// The first sector of the contents in the image, unless they are decompressed
+    extent: Option<u32>,
+}
+
+/// The most bytes a file served decompressed with [`StorageBuilder::gunzip`] may hold.
+const MAX_GUNZIPPED: u64 = 256 * 1024 * 1024;
+
+/// The maximum number of symbolic links followed while resolving a single path.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// How much of a file [`Storage::export_tar`] reads at a time.
+const EXPORT_CHUNK: u64 = 1024 * 1024;
+
+/// A step left to take while resolving a path.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Step {
+    Name(String),
+    Parent,
+}
+
+impl Image {
+    /// Resolves `path` starting at the root, following Rock Ridge symbolic links on the way.
+    fn find<P: AsRef<Path>>(&self, path: P) -> Result<DirectoryEntry<IsoReader>> {
+        self.resolve(path, true)
+    }
+
+    /// Like [`find`](Self::find), but returns a symbolic link in the last component of `path`
+    /// itself instead of its target.
+    fn find_link<P: AsRef<Path>>(&self, path: P) -> Result<DirectoryEntry<IsoReader>> {
+        self.resolve(path, false)
+    }
+
+    /// The name of the directory that is the only entry of the root, for
+    /// [`StorageBuilder::descend_single_directory`].
+    fn single_directory(&self) -> Option<String> {
+        let root = self.root_dir().ok()?;
+        let flagged = self.paths.flagged;
+        let records = records::Records::new(&root).filter(|(_, entry)| {
+            let identifier = entry.identifier();
+            identifier != "." && identifier != ".." && (flagged || !records::flagged(entry))
+        });
+        let mut children = records::name(
+            records,
+            |(_, entry)| entry,
+            self.paths.versions,
+            self.paths.duplicates,
+        );
+        match children.pop() {
+            Some((name, (_, DirectoryEntry::Directory(_)))) if children.is_empty() => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The directory clients see as `/`: the root of the image, or the directory configured with
+    /// [`StorageBuilder::root`].
+    fn root_dir(&self) -> Result<ISODirectory<IsoReader>> {
+        let root = self.paths.namespace.root(&self.iso)?;
+        if self.paths.root == Path::new("/") {
+            return Ok(root);
+        }
+        let names = self.paths.normalize(&self.paths.root)?;
+        match self.resolve_from(root, names, true)? {
+            DirectoryEntry::Directory(dir) => Ok(dir),
+            _ => Err(Error::new(
+                ErrorKind::PermanentDirectoryNotAvailable,
+                format!("root {:?} is not a directory", self.paths.root),
+            )),
+        }
+    }
+
+    fn resolve<P: AsRef<Path>>(
+        &self,
+        path: P,
+        follow_last: bool,
+    ) -> Result<DirectoryEntry<IsoReader>> {
+        let names = self.image_names(path.as_ref())?;
+        let resolved = self.resolve_from(self.root_dir()?, names.clone(), follow_last);
+        if resolved.is_err() && self.paths.namespace_fallback {
+            let fallback = self
+                .fallback_root_dir()
+                .and_then(|root| self.resolve_from(root, names, follow_last).ok());
+            if let Some(entry) = fallback {
+                return Ok(entry);
+            }
+        }
+        resolved
+    }
+
+    /// The directory of the other hierarchy that corresponds to the one clients see as `/`, for
+    /// [`StorageBuilder::namespace_fallback`].
+    fn fallback_root_dir(&self) -> Option<ISODirectory<IsoReader>> {
+        self.subtree_root(self.paths.namespace.other_root(&self.iso)?)
+    }
+
+    /// The directory of the hierarchy presented by the [namespace view](View::NAMESPACES)
+    /// `view` that corresponds to the one clients see as `/`, if the image has the hierarchy.
+    fn hierarchy(&self, view: View) -> Option<ISODirectory<IsoReader>> {
+        let root = match view {
+            View::Primary => self.iso.root_at(0),
+            View::Joliet => self.iso.root_at(1),
+            View::RockRidge if self.iso.is_rr() => self.iso.root_at(0),
+            _ => None,
+        };
+        self.subtree_root(root?.clone())
+    }
+
+    /// The directory below `root`, the root of a hierarchy, that corresponds to the one clients
+    /// see as `/`.
+    fn subtree_root(&self, root: ISODirectory<IsoReader>) -> Option<ISODirectory<IsoReader>> {
+        if self.paths.root == Path::new("/") {
+            return Some(root);
+        }
+        let names = self.paths.normalize(&self.paths.root).ok()?;
+        match self.resolve_from(root, names, true) {
+            Ok(DirectoryEntry::Directory(dir)) => Some(dir),
+            _ => None,
+        }
+    }
+
+    /// Normalizes a client path and maps it to the names of the path in the image.
+    fn image_names(&self, path: &Path) -> Result<Vec<String>> {
+        let names = self.paths.normalize(path)?;
+        if self.paths.aliases.is_empty() {
+            return Ok(names);
+        }
+        self.paths
+            .aliases
+            .to_image(&names, |a, b| self.paths.matches(a, b))
+            .ok_or_else(|| error::not_found(path))
+    }
+
+    /// Resolves the path made up of `names`, starting at `root`. Absolute link targets resolve
+    /// from `root`, and link targets whose `..` would climb above it are refused when clients
+    /// see a [subtree](StorageBuilder::root) of the image, as they'd point outside of it.
+    fn resolve_from(
+        &self,
+        root: ISODirectory<IsoReader>,
+        names: Vec<String>,
+        follow_last: bool,
+    ) -> Result<DirectoryEntry<IsoReader>> {
+        let mut pending: VecDeque<Step> = names.into_iter().map(Step::Name).collect();
+
+        let mut current_dir = root.clone();
+        // The directories above `current_dir` with their names, needed to resolve `..` in link
+        // targets and to tell links apart.
+        let mut ancestors: Vec<(String, ISODirectory<IsoReader>)> = Vec::new();
+        let mut followed = HashSet::new();
+        // The last link followed, which `..` steps come from
+        let mut link_location = String::new();
+        let confined = self.paths.root != Path::new("/");
+
+        while let Some(step) = pending.pop_front() {
+            let name = match step {
+                Step::Name(name) => name,
+                Step::Parent => {
+                    match ancestors.pop() {
+                        Some((_, parent)) => current_dir = parent,
+                        None if confined => {
+                            return Err(Error::new(
+                                ErrorKind::PermanentFileNotAvailable,
+                                format!(
+                                    "symbolic link '/{link_location}' points outside of the root"
+                                ),
+                            ));
+                        }
+                        // Like on a Unix file system, `..` at the root stays there
+                        None => {}
+                    }
+                    continue;
+                }
+            };
+
+            // Find the next entry in the current directory. Directories on the way to the last
+            // component are looked up in the path table first, as far as the records agree.
+            let jumped = if pending.is_empty() {
+                None
+            } else {
+                self.table_lookup(&current_dir, &name)
+            };
+            let next_entry: DirectoryEntry<IsoReader> = match jumped {
+                Some(dir) => DirectoryEntry::Directory(dir),
+                None => self
+                    .lookup(&current_dir, &name)
+                    .ok_or_else(|| error::component_not_found(&name))?,
+            };
+
+            let identifier = next_entry.identifier().to_string();
+            match next_entry {
+                DirectoryEntry::Symlink(link) if pending.is_empty() && !follow_last => {
+                    return Ok(DirectoryEntry::Symlink(link));
+                }
+                DirectoryEntry::Symlink(link) => {
+                    // Reaching the same link with the same steps left means the resolution goes
+                    // round in circles.
+                    let location: Vec<&str> = ancestors
+                        .iter()
+                        .map(|(name, _)| name.as_str())
+                        .chain([link.identifier.as_str()])
+                        .collect();
+                    let location = location.join("/");
+                    if followed.len() == MAX_SYMLINK_HOPS {
+                        return Err(Error::new(
+                            ErrorKind::PermanentFileNotAvailable,
+                            format!("too many levels of symbolic links at '/{location}'"),
+                        ));
+                    }
+                    if !followed.insert((location.clone(), pending.clone())) {
+                        return Err(Error::new(
+                            ErrorKind::PermanentFileNotAvailable,
+                            format!("symbolic link loop at '/{location}'"),
+                        ));
+                    }
+                    let target = link.target().ok_or_else(|| {
+                        Error::new(
+                            ErrorKind::PermanentFileNotAvailable,
+                            format!("symbolic link '/{location}' has no target"),
+                        )
+                    })?;
+                    link_location = location;
+                    if target.starts_with('/') {
+                        ancestors.clear();
+                        current_dir = root.clone();
+                    }
+                    for comp in target.rsplit('/') {
+                        match comp {
+                            "" | "." => {}
+                            ".." => pending.push_front(Step::Parent),
+                            name => pending.push_front(Step::Name(name.to_string())),
+                        }
+                    }
+                }
+                DirectoryEntry::Directory(dir) if !pending.is_empty() => {
+                    self.check_extent(&dir)?;
+                    check_cycle(&dir, ancestors.iter().map(|(_, d)| d).chain([&current_dir]))?;
+                    let parent = std::mem::replace(&mut current_dir, dir);
+                    ancestors.push((identifier, parent));
+                }
+                entry @ (DirectoryEntry::Directory(_) | DirectoryEntry::File(_))
+                    if pending.is_empty() =>
+                {
+                    // This is the last component — return the entry
+                    return Ok(entry);
+                }
+                DirectoryEntry::File(_) | DirectoryEntry::Directory(_) => {
+                    return Err(Error::new(
+                        ErrorKind::PermanentFileNotAvailable,
+                        "Intermediate path component is not a directory",
+                    ));
+                }
+            }
+        }
+
+        // Either the path was `/` or empty, or it ended in a link to a directory
+        Ok(DirectoryEntry::Directory(current_dir))
+    }
+
+    /// Finds the entry called `name` in `dir`. An entry whose identifier is exactly `name` wins
+    /// over one that only matches after case folding or normalization, so every name shown in a
+    /// listing resolves to the entry it was shown for. A name that isn't found but ends in a
+    /// version, like `FILE.TXT;1` copied from a raw listing, finds the file of that name if it is
+    /// exposed with that version, and one that isn't found as it is, like `README.` or ` A.TXT`,
+    /// finds the file of its [canonical](records::canonical) name.
+    fn lookup(
+        &self,
+        dir: &ISODirectory<IsoReader>,
+        name: &str,
+    ) -> Option<DirectoryEntry<IsoReader>> {
+        self.lookup_presented(dir, name)
+            .or_else(|| {
+                let (name, version) = versions::split_version(name)?;
+                let entry = self.lookup_presented(dir, name)?;
+                (versions::version(&entry) == Some(version)).then_some(entry)
+            })
+            .or_else(|| match records::canonical(name) {
+                Cow::Owned(canonical) => self.lookup_presented(dir, &canonical),
+                Cow::Borrowed(_) => None,
+            })
+    }
+
+    /// Finds the entry presented as `name` in `dir`, like [`lookup`](Self::lookup) does.
+    fn lookup_presented(
+        &self,
+        dir: &ISODirectory<IsoReader>,
+        name: &str,
+    ) -> Option<DirectoryEntry<IsoReader>> {
+        let index = self.index(dir);
+        if self.paths.short_names {
+            let identifiers: Vec<&str> = index.identifiers().collect();
+            let names = self.paths.display_names(&identifiers);
+            if let Some(i) = names
+                .iter()
+                .position(|short| short.eq_ignore_ascii_case(name))
+            {
+                return records::read(dir, index.offset(i)?);
+            }
+        }
+        let offset = index
+            .exact(name)
+            .or_else(|| index.first(|identifier| self.paths.matches(identifier, name)))?;
+        records::read(dir, offset)
+    }
+
+    /// The record index of `dir`, built the first time the directory is looked in.
+    fn index(&self, dir: &ISODirectory<IsoReader>) -> Arc<RecordIndex> {
+        let header = dir.header();
+        let key = (header.extent_loc, header.extent_length);
+        if let Some(index) = self.indexes.get(&key) {
+            return index;
+        }
+        let index = Arc::new(self.catalog_index(key).unwrap_or_else(|| {
+            let paths = &self.paths;
+            let index = RecordIndex::build(dir, paths.versions, paths.duplicates, paths.flagged);
+            #[cfg(feature = "catalog")]
+            if let Some(catalog) = &self.catalog {
+                catalog.insert(key, &index);
+            }
+            index
+        }));
+        self.indexes.insert(key, index.clone(), index.size());
+        index
+    }
+
+    /// The index of the directory at `key` from the [catalog](StorageBuilder::catalog), if
+    /// it is there.
+    #[cfg(feature = "catalog")]
+    fn catalog_index(&self, key: (u32, u32)) -> Option<RecordIndex> {
+        self.catalog.as_ref()?.get(key)
+    }
+
+    #[cfg(not(feature = "catalog"))]
+    fn catalog_index(&self, _key: (u32, u32)) -> Option<RecordIndex> {
+        None
+    }
+
+    /// Finds the directory called exactly `name` in `dir` through the path table. The table is
+    /// only followed where the records of `dir` name the same directory, so an image whose path
+    /// table disagrees with its records resolves a path the same way its parents and listings
+    /// do; other directories are left to [`lookup`](Self::lookup).
+    fn table_lookup(
+        &self,
+        dir: &ISODirectory<IsoReader>,
+        name: &str,
+    ) -> Option<ISODirectory<IsoReader>> {
+        let table = self.path_table.as_ref()?;
+        let to = table.child(dir.header().extent_loc, name)?;
+        let offset = self.index(dir).exact(name)?;
+        match records::read(dir, offset)? {
+            DirectoryEntry::Directory(found) if found.header().extent_loc == to => Some(found),
+            _ => None,
+        }
+    }
+
+    /// Applies the aliases to the `children` of the presented directory `dir`: entries that were
+    /// aliased away are removed and the aliases living in `dir` are added.
+    fn alias_children(
+        &self,
+        dir: &[String],
+        children: &mut Vec<(String, DirectoryEntry<IsoReader>)>,
+    ) -> Result<()> {
+        let aliases = &self.paths.aliases;
+        if aliases.is_empty() {
+            return Ok(());
+        }
+        let eq = |a: &str, b: &str| self.paths.matches(a, b);
+        let Some(actual_dir) = aliases.to_image(dir, eq) else {
+            return Ok(());
+        };
+        children.retain(|(name, _)| {
+            if name == "." || name == ".." {
+                return true;
+            }
+            let presented: Vec<String> = dir.iter().chain([name]).cloned().collect();
+            let actual = actual_dir.iter().chain([name]);
+            aliases.to_image(&presented, eq).is_some_and(|mapped| {
+                mapped.len() == actual_dir.len() + 1
+                    && mapped.iter().zip(actual).all(|(a, b)| eq(a, b))
+            })
+        });
+        for (name, target) in aliases.children(dir, eq) {
+            // An alias of a path that doesn't exist is left out, like a dangling symbolic link
+            if let Ok(entry) = self.resolve_from(self.root_dir()?, target.to_vec(), true) {
+                children.push((name.to_string(), entry));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if the extent of the given entry reaches beyond the end of the image, which is what
+    /// a truncated image looks like. Reading such an extent silently yields garbage otherwise.
+    fn check_extent<E: ExtraAttributes>(&self, entry: &E) -> Result<()> {
+        let header = entry.header();
+        let end = interleave::extent_end(entry);
+        // Nothing is read of an empty extent, wherever it is recorded to be
+        if end > self.len && header.extent_length > 0 {
+            return Err(Error::new(
+                ErrorKind::PermanentFileNotAvailable,
+                format!(
+                    "extent at block {} ends at byte {end} but the image is only {} bytes long; is the image truncated?",
+                    header.extent_loc, self.len
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Fails if `dir` shares its extent with one of its `ancestors`. Crafted images can point a
+/// directory record back at a parent, which would make any traversal of the tree endless.
+fn check_cycle<'a>(
+    dir: &ISODirectory<IsoReader>,
+    mut ancestors: impl Iterator<Item = &'a ISODirectory<IsoReader>>,
+) -> Result<()> {
+    let extent = dir.header().extent_loc;
+    if ancestors.any(|ancestor| ancestor.header().extent_loc == extent) {
+        return Err(Error::new(
+            ErrorKind::PermanentFileNotAvailable,
+            format!(
+                "directory '{}' at block {extent} is its own ancestor",
+                dir.identifier
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Iterates over the entries of a directory, decoding them as they are consumed.
+fn contents(dir: &ISODirectory<IsoReader>) -> impl Iterator<Item = DirectoryEntry<IsoReader>> + '_ {
+    records::Records::new(dir).map(|(_, entry)| entry)
+}
+
+// The operations of the back-end. They block on reads from the image, so the `StorageBackend`
+// implementation runs them through `Storage::blocking`.
+impl Storage {
+    fn stat(&self, path: &Path) -> Result<IsoMeta> {
+        let names = self.inner.paths.normalize(path)?;
+        let viewed = self.view_node(path, &names, |node| match node {
+            views::Node::Dir(dir) => Ok(dir.meta.clone()),
+            views::Node::File { path, .. } => self.stat(Path::new(path)),
+            views::Node::Entry { meta, .. } => Ok(meta.clone()),
+        })?;
+        if let Some(meta) = viewed {
+            return meta;
+        }
+        let image = self.open_iso()?;
+        let entry = match image.find_link(path) {
+            Ok(entry) => entry,
+            Err(e) => {
+                if let Some(file) = self.virtual_file(&image, &names) {
+                    let modified = image.root_dir()?.modify_time().into();
+                    return file
+                        .meta(modified)
+                        .map_err(|e| error::read("could not read a virtual file", e));
+                }
+                if let Some(boot) = self.boot_image(&image, &names)? {
+                    return boot_meta(&image, boot);
+                }
+                return match self.gzipped(&image, &names) {
+                    Some(file) => self.gzipped_meta(&image, file),
+                    None => Err(e),
+                };
+            }
+        };
+        if let DirectoryEntry::File(file) = &entry
+            && !self.serves(&image, file)
+        {
+            return Err(error::not_found(path));
+        }
+        Ok(entry_meta(&entry))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<Fileinfo<PathBuf, IsoMeta>>> {
+        Ok(self.read_dir_in(path)?.1)
+    }
+
+    /// Lists the directory at `path` like [`read_dir`](Self::read_dir), and returns the names of
+    /// the path of the directory the entries are in along with them, which is the one above
+    /// `path` for a pattern.
+    fn read_dir_in(&self, path: &Path) -> Result<DirListing> {
+        let mut dir_names = self.inner.paths.normalize(path)?;
+        self.source()?;
+        // A name that looks like a pattern only is one if nothing has that name
+        let listing = match dir_names.split_last() {
+            Some((pattern, dir)) if glob::is_pattern(pattern) && self.stat(path).is_err() => {
+                let dir_path = path::absolute(dir);
+                let listing = self.listing(&dir_path, dir)?;
+                let matched: Vec<Listed> = listing
+                    .iter()
+                    .filter(|(name, _)| name != "." && name != ".." && glob::matches(pattern, name))
+                    .cloned()
+                    .collect();
+                if matched.is_empty() {
+                    return Err(error::not_found(path));
+                }
+                dir_names.pop();
+                matched.into()
+            }
+            _ => self.listing(path, &dir_names)?,
+        };
+        let listing = listing
+            .iter()
+            .map(|(name, metadata)| Fileinfo {
+                path: name.into(),
+                metadata: metadata.clone(),
+            })
+            .collect();
+        Ok((dir_names, listing))
+    }
+
+    /// The listing of `path`, made up of `dir_names`, from the listing cache if it is there.
+    fn listing(&self, path: &Path, dir_names: &[String]) -> Result<Arc<[Listed]>> {
+        let cache = self.inner.caches.listings.as_ref();
+        if let Some(listing) = cache.and_then(|c| c.get(dir_names)) {
+            return Ok(listing);
+        }
+        let Some(listing) = self.list_entries(path, dir_names)? else {
+            // Like on Unix, a path that ends in a separator only names a directory
+            if self.inner.paths.names_directory(path) {
+                return Err(error::not_a_directory(path));
+            }
+            return Ok(self.list_file(path, dir_names)?.into());
+        };
+        // Only directories are cached, so the path of a listing found there is one
+        let listing: Arc<[Listed]> = listing.into();
+        if let Some(cache) = cache {
+            cache.insert(dir_names.to_vec(), listing.clone(), 1);
+        }
+        Ok(listing)
+    }
+
+    /// Lists the directory at `path`, made up of `dir_names`, or the file there as its own
+    /// entry.
+    fn list_dir(&self, path: &Path, dir_names: &[String]) -> Result<Vec<Listed>> {
+        match self.list_entries(path, dir_names)? {
+            Some(listing) => Ok(listing),
+            None => self.list_file(path, dir_names),
+        }
+    }
+
+    /// Like `ls FILE`, lists the file at `path`, made up of `dir_names`, as its own entry, under
+    /// the name it was asked for.
+    fn list_file(&self, path: &Path, dir_names: &[String]) -> Result<Vec<Listed>> {
+        let name = dir_names.last().cloned().unwrap_or_default();
+        Ok(vec![(name, self.stat(path)?)])
+    }
+
+    /// Lists the directory at `path`, made up of `dir_names`, or returns `None` if there is a
+    /// file there instead.
+    fn list_entries(&self, path: &Path, dir_names: &[String]) -> Result<Option<Vec<Listed>>> {
+        let viewed = self.view_node(path, dir_names, |node| match node {
+            views::Node::Dir(dir) => Some(dir.listing()),
+            views::Node::File { .. } | views::Node::Entry { .. } => None,
+        })?;
+        if let Some(listing) = viewed {
+            return Ok(listing);
+        }
+        let image = self.open_iso()?;
+        let d = match image.find(path) {
+            Ok(DirectoryEntry::Directory(d)) => d,
+            // Files the filter hides don't exist as far as clients are concerned
+            Ok(DirectoryEntry::File(file)) if !self.serves(&image, &file) => {
+                return Err(error::not_found(path));
+            }
+            Ok(DirectoryEntry::File(_) | DirectoryEntry::Symlink(_)) => return Ok(None),
+            // Boot images, virtual files and files served decompressed aren't found, but are
+            // files all the same
+            Err(e) => {
+                let served = self.virtual_file(&image, dir_names).is_some()
+                    || self.boot_image(&image, dir_names)?.is_some()
+                    || self.gzipped(&image, dir_names).is_some();
+                return if served { Ok(None) } else { Err(e) };
+            }
+        };
+        let children = self.children(&image, &d, path, dir_names)?;
+        Ok(Some(
+            children.into_iter().map(|(listed, _)| listed).collect(),
+        ))
+    }
+
+    /// The entries of the directory `d` at `path`, made up of `dir_names`, as listed to clients.
+    /// Subdirectories come with the directory to descend into; links to them don't.
+    fn children(
+        &self,
+        image: &Image,
+        d: &ISODirectory<IsoReader>,
+        path: &Path,
+        dir_names: &[String],
+    ) -> Result<Vec<(Listed, Option<ISODirectory<IsoReader>>)>> {
+        let mut entries = Vec::new();
+        image.check_extent(d)?;
+        let flagged = image.paths.flagged;
+        let mut children = records::name(
+            contents(d).filter(|entry| flagged || !records::flagged(entry)),
+            |entry| entry,
+            image.paths.versions,
+            image.paths.duplicates,
+        );
+        image.alias_children(dir_names, &mut children)?;
+        if self.inner.filter.is_some() {
+            children.retain(|(name, entry)| match entry {
+                DirectoryEntry::File(file) => self.serves(image, file),
+                // Links are judged by the file they point to
+                DirectoryEntry::Symlink(_) => match image.find(path.join(name)) {
+                    Ok(DirectoryEntry::File(file)) => self.serves(image, &file),
+                    _ => true,
+                },
+                DirectoryEntry::Directory(_) => true,
+            });
+        }
+        let identifiers: Vec<&str> = children.iter().map(|(name, _)| name.as_str()).collect();
+        let names = image.paths.display_names(&identifiers);
+        for ((_, e), name) in children.into_iter().zip(names) {
+            let meta = entry_meta(&e);
+            let dir = match e {
+                DirectoryEntry::Directory(dir) => Some(dir),
+                _ => None,
+            };
+            entries.push(((name, meta), dir));
+        }
+        for file in &self.inner.virtual_files {
+            let taken = |((listed, _), _): &(Listed, _)| image.paths.matches(listed, file.name());
+            if file.is_in(dir_names, &image.paths) && !entries.iter().any(taken) {
+                // A local file that can't be read only fails the operations on the file itself
+                match file.meta(d.modify_time().into()) {
+                    Ok(meta) => entries.push(((file.name().to_string(), meta), None)),
+                    Err(e) => log::warn!("could not read the virtual file {:?}: {e}", file.name()),
+                }
+            }
+        }
+        if dir_names.is_empty() && self.inner.boot_images {
+            for (name, boot) in self.boot_images(image)?.files() {
+                if !entries.iter().any(|((listed, _), _)| listed == name) {
+                    entries.push(((name.to_string(), boot_meta(image, boot)?), None));
+                }
+            }
+        }
+        if dir_names.is_empty() {
+            // Views are entered like other directories but not walked into, as they only hold
+            // what the rest of the tree does
+            for &view in &self.inner.views {
+                if view.is_namespace() && image.hierarchy(view).is_none() {
+                    continue;
+                }
+                let name = view.name();
+                let taken = |((listed, _), _): &(Listed, _)| image.paths.matches(listed, name);
+                if !entries.iter().any(taken) {
+                    let meta = views::dir_meta(d.modify_time().into());
+                    entries.push(((name.to_string(), meta), None));
+                }
+            }
+        }
+        Ok(entries)
+    }
 
-use async_trait::async_trait;
-use cdfs::{DirectoryEntry, ExtraAttributes, ISO9660, ISODirectory, ISOFileReader};
-use std::{
-    fmt::Debug,
-    fs::File,
-    io::{Cursor, Read, Seek, SeekFrom},
-    path::{Path, PathBuf},
-    time::SystemTime,
-};
-use tokio::io::AsyncRead;
-use unftp_core::{
-    auth::UserDetail,
-    storage::{Error, ErrorKind, Fileinfo, Metadata, Result, StorageBackend},
-};
+    /// Lists the subtree at `path` depth-first, each directory's entries followed by those of
+    /// its subdirectories in turn, under paths relative to `path`. Links aren't followed, and a
+    /// directory that is its own ancestor is listed but not descended into.
+    fn walk_tree(&self, path: &Path) -> Result<Vec<Listed>> {
+        let dir_names = self.inner.paths.normalize(path)?;
+        let viewed = self.view_node(path, &dir_names, |node| match node {
+            views::Node::Dir(dir) => Ok(views::walk(dir)),
+            views::Node::File { .. } | views::Node::Entry { .. } => self.list_dir(path, &dir_names),
+        })?;
+        if let Some(entries) = viewed {
+            return entries;
+        }
+        let image = self.open_iso()?;
+        let root = match image.find(path) {
+            Ok(DirectoryEntry::Directory(d)) => d,
+            _ => return self.list_dir(path, &dir_names),
+        };
+        let mut entries = Vec::new();
+        let mut budget = walk::Budget::new(self.inner.walk_limits);
+        // The directories left to list, with their names below `path` and the extents of the
+        // directories above them. The stack keeps deep trees off the call stack.
+        let mut pending = vec![(root, Vec::new(), Vec::new())];
+        while let Some((dir, names, mut ancestors)) = pending.pop() {
+            let presented: Vec<String> = dir_names.iter().chain(&names).cloned().collect();
+            let children = self.children(&image, &dir, &path::absolute(&presented), &presented)?;
+            ancestors.push(dir.header().extent_loc);
+            let mut subdirs = Vec::new();
+            for ((name, meta), subdir) in children {
+                if name == "." || name == ".." {
+                    continue;
+                }
+                let below: Vec<String> = names.iter().cloned().chain([name]).collect();
+                budget.admit(below.len(), &meta, || path.join(below.join("/")))?;
+                entries.push((below.join("/"), meta));
+                if let Some(subdir) = subdir
+                    && !ancestors.contains(&subdir.header().extent_loc)
+                {
+                    subdirs.push((subdir, below, ancestors.clone()));
+                }
+            }
+            // The first subdirectory is listed next
+            pending.extend(subdirs.into_iter().rev());
+        }
+        Ok(entries)
+    }
 
-/// A virtual file system that tells the unftp server how to access ".iso" (ISO 9660) files.
-#[derive(Debug, Clone)]
-pub struct Storage {
-    iso_path: PathBuf,
-}
+    /// Writes the entries of the image to an archive for [`export_tar`](Self::export_tar),
+    /// returning how many there were and the archive to finish.
+    fn export<W: io::Write>(&self, out: W) -> Result<(u64, export::Archive<W>)> {
+        let entries = self.walk_tree(Path::new("/"))?;
+        let image = self.open_iso()?;
+        let mut archive = export::Archive::new(out);
+        let failed = |e: io::Error| error::read("could not write the archive", e);
+        let mut count = 0;
+        for (name, meta) in entries {
+            // Views only hold what the rest of the tree does
+            if !name.contains('/')
+                && self
+                    .inner
+                    .views
+                    .iter()
+                    .any(|view| image.paths.matches(&name, view.name()))
+            {
+                continue;
+            }
+            let path = Path::new("/").join(&name);
+            let entry = image.find_link(&path).ok();
+            let target = match &entry {
+                Some(DirectoryEntry::Symlink(link)) => link.target().cloned(),
+                _ => None,
+            };
+            let kind = match (meta.dir, &target) {
+                (true, _) => export::EntryKind::Dir,
+                (false, Some(_)) => export::EntryKind::Symlink,
+                (false, None) => export::EntryKind::File,
+            };
+            let file = match kind {
+                export::EntryKind::File => Some(self.open_file(&path)?),
+                _ => None,
+            };
+            let mode = entry
+                .as_ref()
+                .and_then(|entry| entry.mode())
+                .map(|mode| mode.bits() & 0o7777)
+                .unwrap_or(if meta.dir { 0o555 } else { 0o444 });
+            let mtime = meta
+                .modified
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs());
+            archive
+                .header(&export::Header {
+                    path: &name,
+                    kind,
+                    mode,
+                    uid: meta.owner,
+                    gid: meta.group,
+                    size: file.as_ref().map_or(0, |file| file.len()),
+                    mtime,
+                    link: target.as_deref().unwrap_or(""),
+                })
+                .map_err(failed)?;
+            if let Some(file) = file {
+                let mut offset = 0;
+                while offset < file.len() {
+                    let chunk = file
+                        .read_range(offset, EXPORT_CHUNK)
+                        .map_err(|e| error::read("read error", e))?;
+                    if chunk.is_empty() {
+                        return Err(error::read(
+                            "read error",
+                            io::Error::from(io::ErrorKind::UnexpectedEof),
+                        ));
+                    }
+                    offset += chunk.len() as u64;
+                    archive.contents(&chunk).map_err(failed)?;
+                }
+                archive.end_file().map_err(failed)?;
+            }
+            count += 1;
+        }
+        Ok((count, archive))
+    }
 
-impl Storage {
-    /// Creates the storage back-end, pointing it to the ".iso" file
-    /// given in the `iso_path` parameter.
-    pub fn new<P: AsRef<Path>>(iso_path: P) -> Self {
-        Self {
-            iso_path: iso_path.as_ref().to_path_buf(),
+    /// Lists the directory at `path` for one step of a [`Walk`], each entry with whether to walk
+    /// into it: not into links, nor into a directory whose extent is among `ancestors`. Comes
+    /// with the extent of the directory itself, which views don't have.
+    fn walk_step(&self, path: &Path, ancestors: &[u32]) -> Result<walk::Step> {
+        let dir_names = self.inner.paths.normalize(path)?;
+        let leaves = |listing: Vec<Listed>| listing.into_iter().map(|l| (l, false)).collect();
+        let viewed = self.view_node(path, &dir_names, |node| match node {
+            views::Node::Dir(dir) => Ok(dir
+                .listing()
+                .into_iter()
+                .skip(2)
+                .map(|listed| {
+                    let descend = listed.1.dir;
+                    (listed, descend)
+                })
+                .collect()),
+            views::Node::File { .. } | views::Node::Entry { .. } => {
+                self.list_dir(path, &dir_names).map(leaves)
+            }
+        })?;
+        if let Some(children) = viewed {
+            return Ok((None, children?));
+        }
+        let image = self.open_iso()?;
+        let d = match image.find(path) {
+            Ok(DirectoryEntry::Directory(d)) => d,
+            _ => return Ok((None, leaves(self.list_dir(path, &dir_names)?))),
+        };
+        let extent = d.header().extent_loc;
+        let children = self
+            .children(&image, &d, path, &dir_names)?
+            .into_iter()
+            .filter(|((name, _), _)| name != "." && name != "..")
+            .map(|(listed, subdir)| {
+                let descend = subdir.is_some_and(|subdir| {
+                    let subdir = subdir.header().extent_loc;
+                    subdir != extent && !ancestors.contains(&subdir)
+                });
+                (listed, descend)
+            })
+            .collect();
+        Ok((Some(extent), children))
+    }
+
+    /// Applies `f` to the entry of a [view](View) at `path`, made up of `names`. Returns `None`
+    /// for paths outside of the views, which includes views hidden by an entry of the image.
+    fn view_node<T>(
+        &self,
+        path: &Path,
+        names: &[String],
+        f: impl FnOnce(&views::Node) -> T,
+    ) -> Result<Option<T>> {
+        let eq = |a: &str, b: &str| self.inner.paths.matches(a, b);
+        if !views::may_contain(&self.inner.views, names, eq)
+            || self.open_iso()?.find(path::absolute(&names[..1])).is_ok()
+        {
+            return Ok(None);
+        }
+        let views = self.inner.caches.views(|| {
+            let tree = self.walk_tree(Path::new("/"))?;
+            let image = self.open_iso()?;
+            let mut hierarchies = Vec::new();
+            for &view in &self.inner.views {
+                if let Some(root) = image.hierarchy(view) {
+                    hierarchies.push((view, self.hierarchy_view(&image, view, root)?));
+                }
+            }
+            let modified = image.root_dir()?.modify_time().into();
+            Ok::<_, Error>(Views::build(
+                &self.inner.views,
+                &tree,
+                hierarchies,
+                modified,
+            ))
+        })?;
+        match views.find(names, eq) {
+            Some(node) => Ok(Some(f(node))),
+            None => Err(error::not_found(path)),
         }
     }
 
-    fn open_iso(&self) -> std::io::Result<ISO9660<std::fs::File>> {
-        let file = std::fs::File::open(&self.iso_path)?;
-        Ok(ISO9660::new(file).unwrap())
+    /// Lists the whole hierarchy below `root` for the namespace view `view`, by the names it
+    /// records, with the files the [`Filter`] allows. Links aren't followed, and a directory
+    /// that is its own ancestor is listed but not descended into.
+    fn hierarchy_view(
+        &self,
+        image: &Image,
+        view: View,
+        root: ISODirectory<IsoReader>,
+    ) -> Result<views::Dir> {
+        // cdfs names the records of the primary volume by their Rock Ridge names where there are
+        let recorded = view == View::Primary && image.iso.is_rr();
+        let mut budget = walk::Budget::new(self.inner.walk_limits);
+        let mut top = views::Dir::new(root.modify_time().into());
+        let mut pending = vec![(root, Vec::new(), Vec::new())];
+        while let Some((dir, names, mut ancestors)) = pending.pop() {
+            image.check_extent(&dir)?;
+            ancestors.push(dir.header().extent_loc);
+            let records = records::Records::new(&dir).filter(|(_, entry)| {
+                let identifier = entry.identifier();
+                identifier != "."
+                    && identifier != ".."
+                    && (image.paths.flagged || !records::flagged(entry))
+            });
+            let children = records::name_by(
+                records,
+                |(_, entry)| entry,
+                |(offset, entry)| match recorded {
+                    true => records::recorded_identifier(&*image.source, &dir, *offset)
+                        .unwrap_or_else(|| entry.identifier().to_string()),
+                    false => entry.identifier().to_string(),
+                },
+                image.paths.versions,
+                image.paths.duplicates,
+            );
+            let mut parent = &mut top;
+            for name in &names {
+                match parent.entries.get_mut(name) {
+                    Some(views::Node::Dir(dir)) => parent = dir,
+                    _ => unreachable!("directories are added before they are walked"),
+                }
+            }
+            for (name, (_, entry)) in children {
+                let below: Vec<String> = names.iter().cloned().chain([name.clone()]).collect();
+                let meta = entry_meta(&entry);
+                let node = match entry {
+                    DirectoryEntry::Directory(subdir) => {
+                        if !ancestors.contains(&subdir.header().extent_loc) {
+                            pending.push((subdir, below.clone(), ancestors.clone()));
+                        }
+                        views::Node::Dir(views::Dir::new(meta.modified))
+                    }
+                    DirectoryEntry::File(file) if self.serves(image, &file) => {
+                        let contents = image.check_extent(&file).ok().map(|()| {
+                            let (source, offset) = interleave::file_source(&image.source, &file);
+                            views::Contents {
+                                session: ReadSession {
+                                    source,
+                                    offset,
+                                    len: file.size() as u64,
+                                },
+                                extent: file.header().extent_loc,
+                            }
+                        });
+                        views::Node::Entry {
+                            meta: meta.clone(),
+                            contents,
+                        }
+                    }
+                    DirectoryEntry::File(_) => continue,
+                    DirectoryEntry::Symlink(_) => views::Node::Entry {
+                        meta: meta.clone(),
+                        contents: None,
+                    },
+                };
+                budget.admit(below.len(), &meta, || {
+                    Path::new("/").join(view.name()).join(below.join("/"))
+                })?;
+                parent.entries.insert(name, node);
+            }
+        }
+        Ok(top)
     }
 
-    fn find<P: AsRef<Path> + Send + Debug>(&self, path: P) -> Result<DirectoryEntry<File>> {
-        let iso: ISO9660<File> = self.open_iso()?;
-        let mut current_dir: ISODirectory<File> = iso.root().clone();
+    /// Serves the file of a record of a namespace view from `start_pos` on.
+    fn read_entry(
+        &self,
+        user: String,
+        names: &[String],
+        contents: &views::Contents,
+        start_pos: u64,
+    ) -> Result<Box<dyn AsyncRead + Send + Sync + Unpin>> {
+        self.check_size(names, contents.session.len)?;
+        self.resume(user, names, &contents.session, start_pos)
+    }
 
-        let mut components = path.as_ref().components().peekable();
+    /// Serves the file at `path` from `start_pos` on. Files read whole stop being read once
+    /// the download is given up on, as told by `cancel`.
+    fn read_file(
+        &self,
+        user: String,
+        path: &Path,
+        start_pos: u64,
+        cancel: &Cancel,
+    ) -> Result<Box<dyn AsyncRead + Send + Sync + Unpin>> {
+        let names = self.inner.paths.normalize(path)?;
+        self.source()?;
+        let viewed = self.view_node(path, &names, |node| match node {
+            views::Node::File { path, .. } => {
+                self.read_file(user.clone(), Path::new(path), start_pos, cancel)
+            }
+            views::Node::Entry {
+                contents: Some(contents),
+                ..
+            } => self.read_entry(user.clone(), &names, contents, start_pos),
+            views::Node::Dir(_) | views::Node::Entry { contents: None, .. } => {
+                Err(Error::from(ErrorKind::PermanentFileNotAvailable))
+            }
+        })?;
+        if let Some(read) = viewed {
+            return read;
+        }
+        if let Some(contents) = self
+            .inner
+            .caches
+            .contents
+            .as_ref()
+            .and_then(|c| c.get(&names))
+        {
+            error::check_start(path, start_pos, contents.len() as u64)?;
+            let start = start_pos as usize;
+            let reservation = self.reserve((contents.len() - start) as u64)?;
+            return Ok(self.serve(user, &names, contents[start..].to_vec(), reservation));
+        }
+        if let Some(session) = self.inner.caches.sessions.get(&names) {
+            return self.resume(user, &names, &session, start_pos);
+        }
+        let image = self.open_iso()?;
+        let entry: DirectoryEntry<IsoReader> = match image.find(path) {
+            Ok(entry) => entry,
+            Err(e) => {
+                if let Some(file) = self.virtual_file(&image, &names) {
+                    let contents = file
+                        .read()
+                        .map_err(|e| error::read("could not read a virtual file", e))?;
+                    error::check_start(path, start_pos, contents.len() as u64)?;
+                    let start = start_pos as usize;
+                    let reservation = self.reserve((contents.len() - start) as u64)?;
+                    return Ok(self.serve(user, &names, contents[start..].to_vec(), reservation));
+                }
+                if let Some(boot) = self.boot_image(&image, &names)? {
+                    return self.read_boot_image(user, &image, &names, boot, start_pos);
+                }
+                let Some(file) = self.gzipped(&image, &names) else {
+                    return Err(e);
+                };
+                let mut contents = match self.gunzip(&image, &names, &file, cancel)? {
+                    Materialized::Memory(contents) => contents,
+                    Materialized::Spooled(spooled) => {
+                        return self.serve_spooled(user, &names, Arc::new(spooled), start_pos);
+                    }
+                };
+                // Only known once decompressed
+                error::check_start(path, start_pos, contents.len() as u64)?;
+                let reservation = self.reserve(contents.len() as u64)?;
+                if let Some(cache) = &self.inner.caches.contents
+                    && contents.len() <= self.inner.caches.max_content_len()
+                {
+                    let kept: Arc<[u8]> = contents.as_slice().into();
+                    cache.insert(names.clone(), kept, contents.len());
+                }
+                contents.drain(..(start_pos as usize).min(contents.len()));
+                return Ok(self.serve(user, &names, contents, reservation));
+            }
+        };
+        match entry {
+            DirectoryEntry::File(file_entry) => {
+                if !self.serves(&image, &file_entry) {
+                    return Err(error::not_found(path));
+                }
+                self.check_size(&names, file_entry.size() as u64)?;
+                error::check_start(path, start_pos, file_entry.size() as u64)?;
+                // Empty files often have no extent of their own, and there is nothing to read
+                if file_entry.size() == 0 {
+                    return Ok(self.serve(user, &names, Vec::new(), self.reserve(0)?));
+                }
+                image.check_extent(&file_entry)?;
+                // Files that fit the content cache are read whole, to keep them for later, and so
+                // are files that are verified
+                let cached =
+                    self.inner.caches.contents.as_ref().filter(|_| {
+                        file_entry.size() as usize <= self.inner.caches.max_content_len()
+                    });
+                if cached.is_none() && !self.verifies() {
+                    let (source, offset) = interleave::file_source(&image.source, &file_entry);
+                    let session = Arc::new(ReadSession {
+                        source,
+                        offset,
+                        len: file_entry.size() as u64,
+                    });
+                    self.inner
+                        .caches
+                        .sessions
+                        .insert(names.clone(), session.clone(), 1);
+                    return self.resume(user, &names, &session, start_pos);
+                }
+                if cached.is_none()
+                    && let Some(dir) = self.spool_for(file_entry.size() as u64)
+                {
+                    let mut spooled = Spooled::create(dir)
+                        .map_err(|e| error::read("could not create a temporary file", e))?;
+                    io::copy(
+                        &mut cancel.reader(FileReader::new(&image.source, &file_entry)),
+                        &mut spooled,
+                    )
+                    .map_err(|e| error::read("read error", e))?;
+                    let spooled = Arc::new(spooled);
+                    #[cfg(feature = "checksums")]
+                    self.verify_spooled(&image, &names, &spooled)?;
+                    return self.serve_spooled(user, &names, spooled, start_pos);
+                }
+                let reservation = self.reserve(file_entry.size() as u64)?;
+                let mut buf = Vec::new();
+                cancel
+                    .reader(FileReader::new(&image.source, &file_entry))
+                    .read_to_end(&mut buf)
+                    .map_err(|e| error::read("read error", e))?;
 
-        while let Some(comp) = components.next() {
-            use std::path::Component;
+                #[cfg(feature = "checksums")]
+                self.verify(&image, &names, &buf)?;
 
-            let name = match comp {
-                Component::RootDir => continue,
-                Component::Normal(name) => name.to_str().unwrap().to_uppercase(),
-                _ => {
-                    return Err(Error::new(
-                        ErrorKind::PermanentFileNotAvailable,
-                        "Unsupported path component",
-                    ));
+                if let Some(cache) = cached {
+                    let contents: Arc<[u8]> = buf.into();
+                    cache.insert(names.clone(), contents.clone(), contents.len());
+                    let start = (start_pos as usize).min(contents.len());
+                    buf = contents[start..].to_vec();
+                } else {
+                    buf.drain(..(start_pos as usize).min(buf.len()));
                 }
-            };
+                Ok(self.serve(user, &names, buf, reservation))
+            }
 
-            // Find the next entry in the current directory
-            let next_entry: DirectoryEntry<File> = current_dir
-                .contents()
-                .filter_map(|e| e.ok())
-                .find(|e| e.identifier().eq_ignore_ascii_case(&name))
-                .ok_or_else(|| {
-                    Error::new(
-                        ErrorKind::TransientFileNotAvailable,
-                        format!("Path component '{}' not found", name),
-                    )
-                })?;
+            DirectoryEntry::Directory(_) => Err(ErrorKind::PermanentFileNotAvailable.into()),
+            DirectoryEntry::Symlink(_) => Err(ErrorKind::PermanentFileNotAvailable.into()),
+        }
+    }
+
+    /// Streams the file of a read session from `start_pos` on.
+    fn resume(
+        &self,
+        user: String,
+        names: &[String],
+        session: &ReadSession,
+        start_pos: u64,
+    ) -> Result<Box<dyn AsyncRead + Send + Sync + Unpin>> {
+        error::check_start(&path::absolute(names), start_pos, session.len)?;
+        let left = session.len - start_pos;
+        let reservation = self.reserve(stream::buffered(left))?;
+        let streamed = session
+            .stream(
+                start_pos,
+                self.inner.retry,
+                self.inner.read_timeout,
+                self.inner.io_pool.clone(),
+                self.inner.slow,
+                format!("{:?} in {:?}", path::absolute(names), self.inner.origin),
+            )
+            .map_err(|e| error::read("read error", e))?;
+        Ok(self.serve_reader(user, names, reservation.hold(streamed)))
+    }
+
+    /// The [virtual file](StorageBuilder::virtual_file) at the path made up of `names`, if any
+    /// and if `image` has the directory it is in.
+    fn virtual_file(&self, image: &Image, names: &[String]) -> Option<&VirtualFile> {
+        let file = self
+            .inner
+            .virtual_files
+            .iter()
+            .find(|file| file.is_at(names, &self.inner.paths))?;
+        let dir = path::absolute(&names[..names.len() - 1]);
+        matches!(image.find(dir), Ok(DirectoryEntry::Directory(_))).then_some(file)
+    }
+
+    /// Finds the boot images of the image, if they are served at all.
+    fn boot_images(&self, image: &Image) -> Result<el_torito::BootImages> {
+        if !self.inner.boot_images {
+            return Ok(el_torito::BootImages::default());
+        }
+        el_torito::boot_images(&*image.source).map_err(|e| {
+            Error::new(
+                ErrorKind::LocalError,
+                format!("could not read the boot catalog: {e}"),
+            )
+        })
+    }
+
+    /// Returns the boot image served at the given path, if any.
+    fn boot_image(&self, image: &Image, names: &[String]) -> Result<Option<el_torito::BootImage>> {
+        let [name] = names else {
+            return Ok(None);
+        };
+        Ok(self
+            .boot_images(image)?
+            .files()
+            .find(|(file, _)| image.paths.matches(file, name))
+            .map(|(_, boot)| boot))
+    }
+
+    fn read_boot_image(
+        &self,
+        user: String,
+        image: &Image,
+        names: &[String],
+        boot: el_torito::BootImage,
+        start_pos: u64,
+    ) -> Result<Box<dyn AsyncRead + Send + Sync + Unpin>> {
+        self.check_size(names, boot.len)?;
+        // Streamed like the files of the image, as its size comes from the image
+        let session = Arc::new(ReadSession {
+            source: image.source.clone(),
+            offset: boot.offset,
+            len: boot.len,
+        });
+        self.inner
+            .caches
+            .sessions
+            .insert(names.to_vec(), session.clone(), 1);
+        self.resume(user, names, &session, start_pos)
+    }
+
+    /// Finds the file `NAME.gz` served decompressed as the path made up of `names`, if
+    /// [`StorageBuilder::gunzip`] is on.
+    fn gzipped(&self, image: &Image, names: &[String]) -> Option<ISOFile<IsoReader>> {
+        if !self.inner.gunzip {
+            return None;
+        }
+        let (name, dir) = names.split_last()?;
+        let compressed: Vec<String> = dir.iter().cloned().chain([format!("{name}.gz")]).collect();
+        match image.find(path::absolute(&compressed)) {
+            Ok(DirectoryEntry::File(file)) if self.serves(image, &file) => Some(file),
+            _ => None,
+        }
+    }
+
+    /// The metadata of a file served decompressed. Its size is the one recorded at the end of
+    /// the compressed file.
+    fn gzipped_meta(&self, image: &Image, file: ISOFile<IsoReader>) -> Result<IsoMeta> {
+        image.check_extent(&file)?;
+        let len = Self::recorded_len(image, &file)?;
+        let mut meta = entry_meta(&DirectoryEntry::File(file));
+        meta.len = len;
+        Ok(meta)
+    }
+
+    /// The size recorded at the end of the compressed `file`, that of the last member, which is
+    /// the size of the file decompressed unless it has several.
+    fn recorded_len(image: &Image, file: &ISOFile<IsoReader>) -> Result<u64> {
+        let (source, offset) = interleave::file_source(&image.source, file);
+        let end = offset + file.size() as u64;
+        let mut trailer = [0; 4];
+        if end >= 4 {
+            descriptor::read_exact_at(&*source, end - 4, &mut trailer)
+                .map_err(|e| error::read("read error", e))?;
+        }
+        Ok(gzip::recorded_len(&trailer).unwrap_or(0))
+    }
+
+    /// Decompresses a file found by [`gzipped`](Self::gzipped) as it is read, into a temporary
+    /// file if it is to be [spooled](StorageBuilder::spool_dir).
+    fn gunzip(
+        &self,
+        image: &Image,
+        names: &[String],
+        file: &ISOFile<IsoReader>,
+        cancel: &Cancel,
+    ) -> Result<Materialized> {
+        image.check_extent(file)?;
+        let recorded = Self::recorded_len(image, file)?;
+        let compressed = cancel.reader(FileReader::new(&image.source, file));
+        let spool = self.spool_for(recorded.max(file.size() as u64));
+        let max = if spool.is_some() {
+            spool::MAX_SPOOLED
+        } else {
+            MAX_GUNZIPPED
+        };
+        let limit = self.inner.max_file_size.unwrap_or(u64::MAX).min(max);
+        let decompressed = match spool {
+            Some(dir) => Spooled::create(dir)
+                .map_err(|e| error::read("could not create a temporary file", e))
+                .and_then(|mut spooled| {
+                    gzip::decompress_to(compressed, limit, &mut spooled)
+                        .map(|_| Materialized::Spooled(spooled))
+                        .map_err(|e| Self::gunzip_error(names, limit, e))
+                })?,
+            None => gzip::decompress(compressed, limit)
+                .map(Materialized::Memory)
+                .map_err(|e| Self::gunzip_error(names, limit, e))?,
+        };
+        Ok(decompressed)
+    }
 
-            if components.peek().is_none() {
-                // This is the last component — return the entry
-                return Ok(next_entry);
+    /// The error a file fails to decompress with, or the compressed file fails to be read with.
+    fn gunzip_error(names: &[String], limit: u64, e: io::Error) -> Error {
+        let path = path::absolute(names);
+        match e.kind() {
+            io::ErrorKind::FileTooLarge => Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{path:?} is more than the maximum of {limit} bytes served"),
+            ),
+            io::ErrorKind::InvalidData
+            | io::ErrorKind::InvalidInput
+            | io::ErrorKind::UnexpectedEof => Error::new(
+                ErrorKind::PermanentFileNotAvailable,
+                format!("could not decompress {}.gz: {e}", path.display()),
+            ),
+            _ => error::read("read error", e),
+        }
+    }
+
+    /// The directory to spool a file of `len` bytes to, if it is to be spooled at all.
+    fn spool_for(&self, len: u64) -> Option<&Path> {
+        self.inner
+            .spool
+            .as_deref()
+            .filter(|_| len > spool::SPOOL_ABOVE)
+    }
+
+    /// Streams a file read whole into a temporary file from `start_pos` on.
+    fn serve_spooled(
+        &self,
+        user: String,
+        names: &[String],
+        spooled: Arc<Spooled>,
+        start_pos: u64,
+    ) -> Result<Box<dyn AsyncRead + Send + Sync + Unpin>> {
+        let session = ReadSession {
+            len: spooled.size(),
+            source: spooled,
+            offset: 0,
+        };
+        self.resume(user, names, &session, start_pos)
+    }
+
+    /// Tells whether served files are checked against the checksum lists of the image.
+    fn verifies(&self) -> bool {
+        #[cfg(feature = "checksums")]
+        return self.inner.integrity.is_some();
+        #[cfg(not(feature = "checksums"))]
+        false
+    }
+
+    /// Checks the whole `contents` of the file at the path made up of `names` against the
+    /// checksum lists, as [`StorageBuilder::integrity`] asks.
+    #[cfg(feature = "checksums")]
+    fn verify(&self, image: &Image, names: &[String], contents: &[u8]) -> Result<()> {
+        self.verify_with(image, names, |algorithm| Ok(algorithm.digest(contents)))
+    }
+
+    /// Checks a file [spooled](StorageBuilder::spool_dir) whole like [`verify`](Self::verify),
+    /// reading it back a chunk at a time.
+    #[cfg(feature = "checksums")]
+    fn verify_spooled(
+        &self,
+        image: &Image,
+        names: &[String],
+        spooled: &Arc<Spooled>,
+    ) -> Result<()> {
+        self.verify_with(image, names, |algorithm| {
+            let hasher = hash::start(algorithm, &self.inner.hash_functions)
+                .ok_or_else(|| io::Error::other(format!("no hash function {algorithm}")))?;
+            let source = spooled.clone();
+            hash::stream(
+                source,
+                0,
+                spooled.size(),
+                algorithm,
+                hasher,
+                RetryPolicy::default(),
+            )
+            .map(|digest| digest.as_bytes().to_vec())
+        })
+    }
+
+    /// Checks the file at the path made up of `names` against the checksum lists, as
+    /// [`StorageBuilder::integrity`] asks, with `digest` computing the digest of its contents.
+    #[cfg(feature = "checksums")]
+    fn verify_with(
+        &self,
+        image: &Image,
+        names: &[String],
+        digest: impl FnOnce(HashAlgorithm) -> io::Result<Vec<u8>>,
+    ) -> Result<()> {
+        let Some(mode) = self.inner.integrity else {
+            return Ok(());
+        };
+        let checked = self
+            .checksums(image)
+            .verify_with(names, digest)
+            .map_err(|e| error::read("read error", e))?;
+        let Err(mismatch) = checked else {
+            return Ok(());
+        };
+        let message = format!(
+            "{:?} does not match its {} checksum in /{}; is the image corrupt?",
+            path::absolute(names),
+            mismatch.algorithm,
+            mismatch.list
+        );
+        match mode {
+            IntegrityMode::Log => {
+                log::warn!("{message}");
+                Ok(())
             }
+            IntegrityMode::Abort => Err(Error::new(ErrorKind::PermanentFileNotAvailable, message)),
+        }
+    }
 
-            // Not the last component — must be a directory
-            match next_entry {
-                DirectoryEntry::Directory(dir) => {
-                    current_dir = dir; // move the directory, no borrow
+    /// The checksum lists of the image, read the first time they are needed.
+    #[cfg(feature = "checksums")]
+    fn checksums(&self, image: &Image) -> Arc<checksums::Checksums> {
+        self.inner.caches.checksums(|| {
+            let mut found = checksums::Checksums::default();
+            for (list, algorithm) in checksums::LISTS {
+                let Ok(DirectoryEntry::File(file)) = image.find(format!("/{list}")) else {
+                    continue;
+                };
+                if file.size() as u64 > checksums::MAX_LIST_LEN
+                    || image.check_extent(&file).is_err()
+                {
+                    continue;
                 }
-                _ => {
-                    return Err(Error::new(
-                        ErrorKind::PermanentFileNotAvailable,
-                        "Intermediate path component is not a directory",
-                    ));
+                let mut text = String::new();
+                if FileReader::new(&image.source, &file)
+                    .read_to_string(&mut text)
+                    .is_ok()
+                {
+                    found.add(list, algorithm, &text);
                 }
             }
+            if found.is_empty() {
+                log::warn!(
+                    "{:?} has no checksum lists; its files are served unchecked",
+                    self.inner.origin
+                );
+            }
+            found
+        })
+    }
+
+    /// Fails for files larger than the [maximum file size](StorageBuilder::max_file_size).
+    fn check_size(&self, names: &[String], len: u64) -> Result<()> {
+        match self.inner.max_file_size {
+            Some(max) if len > max => Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "{:?} is {len} bytes, more than the maximum of {max} bytes served",
+                    path::absolute(names)
+                ),
+            )),
+            _ => Ok(()),
         }
+    }
 
-        // If we get here, it means the path was `/` or empty — return root dir entry
-        Ok(DirectoryEntry::Directory(current_dir))
+    fn open_file(&self, path: &Path) -> Result<IsoAsyncFile> {
+        let file = self.locate(path)?;
+        let pool = self.inner.io_pool.clone();
+        let blocks = file.extent.is_some() && (self.inner.origin.blocks() || pool.is_some());
+        Ok(IsoAsyncFile::new(
+            file.source,
+            file.offset,
+            file.len,
+            blocks,
+            pool,
+        ))
+    }
+
+    /// Finds where the contents of the file at `path` lie, for reads that bypass
+    /// [`read_file`](Self::read_file).
+    fn locate(&self, path: &Path) -> Result<Located> {
+        let names = self.inner.paths.normalize(path)?;
+        let viewed = self.view_node(path, &names, |node| match node {
+            views::Node::File { path, .. } => self.locate(Path::new(path)),
+            views::Node::Entry {
+                contents: Some(contents),
+                ..
+            } => {
+                self.check_size(&names, contents.session.len)?;
+                Ok(Located {
+                    source: contents.session.source.clone(),
+                    offset: contents.session.offset,
+                    len: contents.session.len,
+                    extent: Some(contents.extent),
+                })
+            }
+            views::Node::Dir(_) | views::Node::Entry { contents: None, .. } => {
+                Err(Error::from(ErrorKind::PermanentFileNotAvailable))
+            }
+        })?;
+        if let Some(located) = viewed {
+            return located;
+        }
+        let image = self.open_iso()?;
+        let located = match image.find(path) {
+            Ok(DirectoryEntry::File(file)) => {
+                if !self.serves(&image, &file) {
+                    return Err(error::not_found(path));
+                }
+                image.check_extent(&file)?;
+                let (source, offset) = interleave::file_source(&image.source, &file);
+                Located {
+                    source,
+                    offset,
+                    len: file.size() as u64,
+                    extent: Some(file.header().extent_loc),
+                }
+            }
+            Ok(_) => return Err(ErrorKind::PermanentFileNotAvailable.into()),
+            Err(e) => {
+                if let Some(file) = self.virtual_file(&image, &names) {
+                    let contents = file
+                        .read()
+                        .map_err(|e| error::read("could not read a virtual file", e))?;
+                    return Ok(Located {
+                        len: contents.len() as u64,
+                        source: Arc::new(contents.to_vec()),
+                        offset: 0,
+                        extent: None,
+                    });
+                }
+                match self.boot_image(&image, &names)? {
+                    Some(boot) => Located {
+                        source: image.source.clone(),
+                        offset: boot.offset,
+                        len: boot.len,
+                        extent: Some((boot.offset / descriptor::SECTOR as u64) as u32),
+                    },
+                    None => match self.gzipped(&image, &names) {
+                        Some(file) => {
+                            let (source, len): (Arc<dyn IsoSource>, u64) =
+                                match self.gunzip(&image, &names, &file, &Cancel::default())? {
+                                    Materialized::Memory(contents) => {
+                                        let len = contents.len() as u64;
+                                        (Arc::new(contents), len)
+                                    }
+                                    Materialized::Spooled(spooled) => {
+                                        let len = spooled.size();
+                                        (Arc::new(spooled), len)
+                                    }
+                                };
+                            return Ok(Located {
+                                source,
+                                offset: 0,
+                                len,
+                                extent: None,
+                            });
+                        }
+                        None => return Err(e),
+                    },
+                }
+            }
+        };
+        self.check_size(&names, located.len)?;
+        Ok(located)
+    }
+
+    /// Computes the digest of the file at `path`, or returns the one computed before for the
+    /// same contents.
+    #[cfg(feature = "checksums")]
+    fn digest(&self, path: &Path, algorithm: HashAlgorithm) -> Result<Digest> {
+        let file = self.locate(path)?;
+        let key = file.extent.map(|extent| (extent, file.len, algorithm));
+        if let Some(digest) = key.and_then(|key| self.inner.caches.hashes.get(&key)) {
+            return Ok(digest);
+        }
+        let hasher = hash::start(algorithm, &self.inner.hash_functions).ok_or_else(|| {
+            Error::new(
+                ErrorKind::CommandNotImplemented,
+                format!("no hash function {algorithm} registered"),
+            )
+        })?;
+        let (source, offset, len) = (file.source, file.offset, file.len);
+        let digest = hash::stream(source, offset, len, algorithm, hasher, self.inner.retry)
+            .map_err(|e| error::read("read error", e))?;
+        if let Some(key) = key {
+            self.inner.caches.hashes.insert(key, digest.clone(), 1);
+        }
+        Ok(digest)
+    }
+
+    /// Takes `bytes` for a file read into memory from the [budget](StorageBuilder::memory_budget)
+    /// of transfers, if there is one.
+    fn reserve(&self, bytes: u64) -> Result<Reservation> {
+        let pool = self.inner.budget.as_ref().map(|b| &b.pools().transfers);
+        Reservation::take(pool, bytes)
+    }
+
+    /// Returns a cursor over the bytes served for the path, to provide async access. The
+    /// `reservation` of the bytes is given back once the cursor is dropped.
+    fn serve(
+        &self,
+        user: String,
+        names: &[String],
+        buf: Vec<u8>,
+        reservation: Reservation,
+    ) -> Box<dyn AsyncRead + Send + Sync + Unpin> {
+        self.serve_reader(user, names, reservation.hold(Cursor::new(buf)))
+    }
+
+    /// Returns `reader`, which reads the bytes served for the path, counting them in the
+    /// statistics and the quota.
+    fn serve_reader<R>(
+        &self,
+        user: String,
+        names: &[String],
+        reader: R,
+    ) -> Box<dyn AsyncRead + Send + Sync + Unpin>
+    where
+        R: AsyncRead + stats::Prepared + Send + Sync + Unpin + 'static,
+    {
+        let reader = self.inner.stats.track(&path::absolute(names), reader);
+        match &self.inner.quota {
+            Some(quota) => Box::new(quota.meter(user, reader)),
+            None => Box::new(reader),
+        }
+    }
+
+    /// Looks up the metadata of `path` for `user`, for [`StorageBackend::metadata`].
+    async fn lookup_routed(&self, user: &impl UserDetail, path: &Path) -> Result<IsoMeta> {
+        match self.route(user, path).await? {
+            Routed::Image(storage, path) => {
+                let timer = storage.inner.slow.start();
+                let origin = storage.inner.origin.clone();
+                let shown = path.clone();
+                let metadata = storage.blocking(move |storage| storage.stat(&path)).await;
+                timer.finish(|| format!("lookup of {shown:?} in {origin:?}"));
+                metadata
+            }
+            Routed::Directory(shelf) => Ok(shelf.meta()),
+        }
+    }
+
+    /// Lists the directory at `path` for `user`, for [`StorageBackend::list`]. Entries outside
+    /// their windows are left out.
+    async fn list_routed(
+        &self,
+        user: &impl UserDetail,
+        path: &Path,
+    ) -> Result<Vec<Fileinfo<PathBuf, IsoMeta>>> {
+        let listed = path;
+        let (storage, path) = match self.route(user, path).await? {
+            Routed::Image(storage, path) => (storage, path),
+            Routed::Directory(shelf) => {
+                let paths = &self.inner.paths;
+                let now = SystemTime::now();
+                let shut = |name: &String| {
+                    let windows = self.inner.windows.as_ref();
+                    windows.is_some_and(|w| {
+                        w.shut(std::slice::from_ref(name), now, |a, b| paths.matches(a, b))
+                    })
+                };
+                return Ok(shelf
+                    .listing()
+                    .into_iter()
+                    .filter(|(name, _)| !shut(name))
+                    .map(|(name, metadata)| Fileinfo {
+                        path: name.into(),
+                        metadata,
+                    })
+                    .collect());
+            }
+        };
+        let names = self.inner.paths.normalize(listed)?;
+        let within = storage.inner.paths.normalize(&path)?;
+        let prefix = names[..names.len() - within.len()].to_vec();
+        let timer = storage.inner.slow.start();
+        let shown = path.clone();
+        let mut listing = storage
+            .blocking(move |storage| {
+                let (dir, listing) = storage.read_dir_in(&path)?;
+                let mut open = Vec::with_capacity(listing.len());
+                for entry in listing {
+                    let name = entry.path.to_string_lossy().into_owned();
+                    let dots = name == "." || name == "..";
+                    if dots || !storage.shut(&prefix, &[dir.as_slice(), &[name]].concat())? {
+                        open.push(entry);
+                    }
+                }
+                Ok(open)
+            })
+            .await;
+        timer.finish(|| {
+            let entries = listing.as_ref().map_or(0, Vec::len);
+            let origin = &storage.inner.origin;
+            format!("listing of {shown:?} in {origin:?}, {entries} entries")
+        });
+        // Only what changed is listed in the changed view
+        if let (Some(_), Ok(entries)) = (&self.inner.library, &mut listing) {
+            let paths = &self.inner.paths;
+            let mut names = paths.normalize(listed)?;
+            let finder = self.clone();
+            let first = names.first().cloned().unwrap_or_default();
+            let changed = self.on_pool(move || {
+                let library = finder
+                    .inner
+                    .library
+                    .as_ref()
+                    .expect("a directory of images");
+                library.changed(&*library.shelf()?, &first, &finder.inner.paths)
+            });
+            if !names.is_empty()
+                && let Some((_, delta)) = changed.await?
+            {
+                names.remove(0);
+                entries.retain(|entry| {
+                    let name = entry.path.to_string_lossy().into_owned();
+                    if name == "." || name == ".." {
+                        return true;
+                    }
+                    names.push(name);
+                    let held = delta.holds(&names, paths).is_some();
+                    names.pop();
+                    held
+                });
+            }
+        }
+        listing
+    }
+
+    /// Starts the download of `path` from `start_pos` on for `user`, for
+    /// [`StorageBackend::get`].
+    async fn get_routed(
+        &self,
+        user: &impl UserDetail,
+        path: &Path,
+        start_pos: u64,
+    ) -> Result<Box<dyn AsyncRead + Send + Sync + Unpin>> {
+        let Routed::Image(storage, path) = self.route(user, path).await? else {
+            return Err(Error::from(ErrorKind::PermanentFileNotAvailable));
+        };
+        let pace = storage
+            .inner
+            .bandwidth
+            .as_ref()
+            .and_then(|bandwidth| bandwidth.pace(user));
+        let user = user.to_string();
+        if let Some(quota) = &storage.inner.quota
+            && quota.exhausted(&user)
+        {
+            return Err(Error::new(
+                ErrorKind::ExceededStorageAllocationError,
+                format!("download quota of {} bytes exceeded", quota.limit()),
+            ));
+        }
+        // The reads of files read whole stop once the server stops waiting for them
+        let cancel = Cancel::default();
+        let _cancel_on_drop = cancel.on_drop();
+        let timer = storage.inner.slow.start();
+        let shown = path.clone();
+        let reader = storage
+            .blocking(move |storage| storage.read_file(user, &path, start_pos, &cancel))
+            .await;
+        timer.finish(|| {
+            let origin = &storage.inner.origin;
+            format!("opening of {shown:?} in {origin:?} from byte {start_pos}")
+        });
+        match pace {
+            Some(pace) => Ok(Box::new(pace.throttle(reader?))),
+            None => reader,
+        }
     }
 }
 
@@ -134,103 +3634,58 @@ impl Storage {
 impl<User: UserDetail> StorageBackend<User> for Storage {
     type Metadata = IsoMeta;
 
+    fn supported_features(&self) -> u32 {
+        let features = FEATURE_RESTART;
+        #[cfg(feature = "checksums")]
+        let features = features | unftp_core::storage::FEATURE_SITEMD5;
+        features
+    }
+
     async fn metadata<P: AsRef<Path> + Send + Debug>(
         &self,
-        _user: &User,
+        user: &User,
         path: P,
     ) -> Result<Self::Metadata> {
-        let entry = self.find(path)?;
-        let size = match &entry {
-            DirectoryEntry::Directory(d) => d.header().length as u64,
-            DirectoryEntry::File(f) => f.size() as u64,
-            DirectoryEntry::Symlink(l) => l.header().length as u64,
-        };
-        Ok(IsoMeta {
-            len: size,
-            dir: matches!(entry, DirectoryEntry::Directory(_)),
-            sym: matches!(entry, DirectoryEntry::Symlink(_)),
-            group: entry.group().unwrap_or(0),
-            owner: entry.owner().unwrap_or(0),
-            modified: entry.modify_time().into(),
-        })
+        let op = self.lookup_routed(user, path.as_ref());
+        #[cfg(feature = "otel")]
+        let op = otel::traced("iso.lookup", user, path.as_ref(), op);
+        op.await
+    }
+
+    #[cfg(feature = "checksums")]
+    async fn md5<P: AsRef<Path> + Send + Debug>(&self, user: &User, path: P) -> Result<String> {
+        match self.route(user, path.as_ref()).await? {
+            Routed::Image(storage, path) => {
+                Ok(storage.hash(path, HashAlgorithm::Md5).await?.to_string())
+            }
+            Routed::Directory(_) => Err(Error::from(ErrorKind::PermanentFileNotAvailable)),
+        }
     }
 
     async fn list<P: AsRef<Path> + Send + Debug>(
         &self,
-        _user: &User,
+        user: &User,
         path: P,
     ) -> Result<Vec<Fileinfo<PathBuf, Self::Metadata>>>
     where
         <Self as StorageBackend<User>>::Metadata: Metadata,
     {
-        let mut entries = Vec::new();
-        let e = self.find(path)?;
-        let d = match e {
-            DirectoryEntry::Directory(d) => d,
-            DirectoryEntry::File(_) => return Err(Error::from(ErrorKind::FileNameNotAllowedError)),
-            DirectoryEntry::Symlink(_) => {
-                return Err(Error::from(ErrorKind::FileNameNotAllowedError));
-            }
-        };
-        for entry in d.contents() {
-            let e = entry.unwrap();
-            let size = match &e {
-                DirectoryEntry::Directory(d) => d.header().length as u64,
-                DirectoryEntry::File(f) => f.size() as u64,
-                DirectoryEntry::Symlink(l) => l.header().length as u64,
-            };
-            entries.push(Fileinfo {
-                path: e.identifier().into(),
-                metadata: IsoMeta {
-                    len: size,
-                    dir: matches!(e, DirectoryEntry::Directory(_)),
-                    sym: matches!(e, DirectoryEntry::Symlink(_)),
-                    group: e.group().unwrap_or(0),
-                    owner: e.owner().unwrap_or(0),
-                    modified: e.modify_time().into(),
-                },
-            });
-        }
-        Ok(entries)
+        let op = self.list_routed(user, path.as_ref());
+        #[cfg(feature = "otel")]
+        let op = otel::traced("iso.list", user, path.as_ref(), op);
+        op.await
     }
 
     async fn get<P: AsRef<Path> + Send + Debug>(
         &self,
-        _user: &User,
+        user: &User,
         path: P,
         start_pos: u64,
     ) -> Result<Box<dyn AsyncRead + Send + Sync + Unpin>> {
-        let entry: DirectoryEntry<File> = self.find(path)?;
-        match entry {
-            DirectoryEntry::File(file_entry) => {
-                let mut reader: ISOFileReader<File> = file_entry.read();
-                // Seek to the requested start position
-                if start_pos > 0 {
-                    reader.seek(SeekFrom::Start(start_pos)).map_err(|e| {
-                        Error::new(
-                            ErrorKind::PermanentFileNotAvailable,
-                            format!("seek error: {e}"),
-                        )
-                    })?;
-                }
-
-                // Read entire contents into a Vec<u8>
-                let mut buf = Vec::new();
-                reader.read_to_end(&mut buf).map_err(|e| {
-                    Error::new(
-                        ErrorKind::PermanentFileNotAvailable,
-                        format!("read error: {e}"),
-                    )
-                })?;
-
-                // Return a cursor over the buffer to provide async access
-                let cursor = Cursor::new(buf);
-                Ok(Box::new(cursor))
-            }
-
-            DirectoryEntry::Directory(_) => Err(ErrorKind::PermanentFileNotAvailable.into()),
-            DirectoryEntry::Symlink(_) => Err(ErrorKind::PermanentFileNotAvailable.into()),
-        }
+        let op = self.get_routed(user, path.as_ref(), start_pos);
+        #[cfg(feature = "otel")]
+        let op = otel::traced_download(user, path.as_ref(), op);
+        op.await
     }
 
     async fn put<P: AsRef<Path> + Send + Debug, R: AsyncRead + Send + Sync + Unpin + 'static>(
@@ -264,13 +3719,46 @@ impl<User: UserDetail> StorageBackend<User> for Storage {
         Err(Error::from(ErrorKind::PermissionDenied))
     }
 
-    async fn cwd<P: AsRef<Path> + Send + Debug>(&self, _user: &User, path: P) -> Result<()> {
-        self.find(path).map(|_d| ())
+    async fn cwd<P: AsRef<Path> + Send + Debug>(&self, user: &User, path: P) -> Result<()> {
+        let Routed::Image(storage, path) = self.route(user, path.as_ref()).await? else {
+            return Ok(());
+        };
+        storage
+            .blocking(move |storage| {
+                let names = storage.inner.paths.normalize(&path)?;
+                let viewed = storage.view_node(&path, &names, |node| match node {
+                    views::Node::Dir(_) => Ok(()),
+                    views::Node::File { .. } | views::Node::Entry { .. } => {
+                        Err(error::not_a_directory(&path))
+                    }
+                })?;
+                if let Some(entered) = viewed {
+                    return entered;
+                }
+                let image = storage.open_iso()?;
+                match image.find(&path)? {
+                    DirectoryEntry::Directory(_) => Ok(()),
+                    // Files the filter hides don't exist as far as clients are concerned
+                    DirectoryEntry::File(file) if !storage.serves(&image, &file) => {
+                        Err(error::not_found(&path))
+                    }
+                    _ => Err(error::not_a_directory(&path)),
+                }
+            })
+            .await
     }
 }
 
+/// Where an operation goes, as found by [`Storage::route`].
+enum Routed {
+    /// To the back-end of an image, at the path within it.
+    Image(Storage, PathBuf),
+    /// To the [directory](Storage::directory) of images itself.
+    Directory(Arc<Shelf>),
+}
+
 /// Implements unftp-core's Metadata trait
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IsoMeta {
     /// The file size in bytes
     pub len: u64,
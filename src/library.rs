@@ -0,0 +1,370 @@
+//! Serving every ".iso" file of a directory as a directory of its own, for
+//! [`Storage::directory`](crate::Storage::directory).
+
+use crate::{
+    DescriptorKind, IsoMeta, Listed, Storage, StorageBuilder, descriptor, diff,
+    path::{self, PathOptions},
+    source::{Origin, SharedFile},
+    views,
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use unftp_core::storage::{Error, ErrorKind, Result};
+
+/// What the directories of the images of a [directory](crate::Storage::directory) are called.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageNames {
+    /// The name of the file without its extension, so `ubuntu-22.04.iso` is `ubuntu-22.04`.
+    #[default]
+    FileName,
+    /// The identifier of the primary volume, or the name of the file for images without one.
+    VolumeLabel,
+}
+
+/// The name of the directory of the files that changed between two images, as set with
+/// [`StorageBuilder::changed_view`](crate::StorageBuilder::changed_view).
+pub(crate) const CHANGED: &str = "CHANGED";
+
+/// The images of a directory, found the first time they are needed and again whenever the
+/// directory changes.
+pub(crate) struct Library {
+    dir: PathBuf,
+    names: ImageNames,
+    prototype: StorageBuilder,
+    shelf: Mutex<Option<Arc<Shelf>>>,
+    /// The files that changed between the images of the changed view, while neither image does
+    delta: Mutex<Option<Arc<Delta>>>,
+    /// Set while a watcher looks for the images as the directory changes, which makes checking
+    /// it before every operation moot.
+    watched: AtomicBool,
+}
+
+/// The images found in the directory, by the names clients see.
+#[derive(Debug)]
+pub(crate) struct Shelf {
+    modified: SystemTime,
+    images: BTreeMap<String, Shelved>,
+    /// Whether both images of the [changed view](CHANGED) are on the shelf
+    changed: bool,
+}
+
+/// The files of the new image of the [changed view](CHANGED) that the old one doesn't hold as
+/// they are, by their names.
+#[derive(Debug)]
+pub(crate) struct Delta {
+    /// The paths, sizes and modification times of the images compared
+    images: [(PathBuf, u64, SystemTime); 2],
+    files: Vec<Vec<String>>,
+}
+
+impl Delta {
+    /// Tells what the path of the given names is in the view: `Some(true)` for one of the files,
+    /// `Some(false)` for a directory holding some and `None` for anything else.
+    pub(crate) fn holds(&self, names: &[String], paths: &PathOptions) -> Option<bool> {
+        let mut found = None;
+        for file in &self.files {
+            let prefix = file.len() >= names.len()
+                && file.iter().zip(names).all(|(a, b)| paths.matches(a, b));
+            match prefix {
+                true if file.len() == names.len() => return Some(true),
+                true => found = Some(false),
+                false => {}
+            }
+        }
+        found
+    }
+}
+
+/// An image of the directory and the back-end serving it.
+#[derive(Debug, Clone)]
+struct Shelved {
+    path: PathBuf,
+    storage: Storage,
+    modified: SystemTime,
+}
+
+impl Library {
+    pub(crate) fn new(dir: PathBuf, names: ImageNames, prototype: StorageBuilder) -> Self {
+        Self {
+            dir,
+            names,
+            prototype,
+            shelf: Mutex::new(None),
+            delta: Mutex::new(None),
+            watched: AtomicBool::new(false),
+        }
+    }
+
+    #[cfg(feature = "watch")]
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    #[cfg(feature = "watch")]
+    pub(crate) fn set_watched(&self, watched: bool) {
+        self.watched.store(watched, Ordering::Relaxed);
+    }
+
+    /// Returns the images of the directory, looking for them again if the directory was
+    /// modified since the last time, as it is when images are added, removed or renamed.
+    pub(crate) fn shelf(&self) -> Result<Arc<Shelf>> {
+        let mut shelf = self.shelf.lock().unwrap_or_else(|e| e.into_inner());
+        if self.watched.load(Ordering::Relaxed)
+            && let Some(shelf) = &*shelf
+        {
+            return Ok(shelf.clone());
+        }
+        let modified = modified(&self.dir).map_err(|e| self.error(e))?;
+        if let Some(shelf) = &*shelf
+            && shelf.modified == modified
+        {
+            return Ok(shelf.clone());
+        }
+        let scanned = Arc::new(self.scan(shelf.as_deref()).map_err(|e| self.error(e))?);
+        *shelf = Some(scanned.clone());
+        Ok(scanned)
+    }
+
+    /// Looks for the images of the directory again, for a watcher that saw it change.
+    #[cfg(feature = "watch")]
+    pub(crate) fn rescan(&self) {
+        let mut shelf = self.shelf.lock().unwrap_or_else(|e| e.into_inner());
+        // A directory that can't be read right now is looked at again by the next operation
+        *shelf = self.scan(shelf.as_deref()).ok().map(Arc::new);
+    }
+
+    /// Returns the back-end of the new image of the [changed view](CHANGED) and the files it
+    /// holds if `name`, the first of a path, names the view, `None` otherwise. The images are
+    /// compared again whenever either changes.
+    pub(crate) fn changed(
+        &self,
+        shelf: &Shelf,
+        name: &str,
+        paths: &PathOptions,
+    ) -> Result<Option<(Storage, Arc<Delta>)>> {
+        let Some((old, new)) = &self.prototype.changed else {
+            return Ok(None);
+        };
+        if !paths.matches(CHANGED, name) {
+            return Ok(None);
+        }
+        let (Some(old), Some(new)) = (shelf.shelved(old, paths), shelf.shelved(new, paths)) else {
+            return Ok(None);
+        };
+        let stamp = |image: &Shelved| -> Result<(PathBuf, u64, SystemTime)> {
+            let meta = std::fs::metadata(&image.path).map_err(|e| self.error(e))?;
+            let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+            Ok((image.path.clone(), meta.len(), modified))
+        };
+        let images = [stamp(old)?, stamp(new)?];
+        if let Some(delta) = &*self.delta.lock().unwrap_or_else(|e| e.into_inner())
+            && delta.images == images
+        {
+            return Ok(Some((new.storage.clone(), delta.clone())));
+        }
+        // Walked without holding the lock, so that operations on the view don't wait on each
+        // other; operations that find no delta at once each compute the same one
+        let tree = |image: &Shelved| -> Result<diff::Tree> {
+            let entries = image.storage.walk_tree(Path::new("/"))?;
+            Ok(entries
+                .into_iter()
+                .map(|(name, meta)| (PathBuf::from(name), meta))
+                .collect())
+        };
+        let after = tree(new)?;
+        let new_files: HashSet<PathBuf> = after
+            .iter()
+            .filter(|(_, meta)| !meta.dir && !meta.sym)
+            .map(|(path, _)| path.clone())
+            .collect();
+        let (report, _) = diff::compare_trees(tree(old)?, after);
+        let changed = report.changed.into_iter().map(|change| change.path);
+        let mut files: Vec<Vec<String>> = report
+            .added
+            .into_iter()
+            .chain(changed)
+            .filter(|path| new_files.contains(path))
+            .map(|path| {
+                path.iter()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .collect()
+            })
+            .collect();
+        files.sort();
+        let computed = Arc::new(Delta { images, files });
+        *self.delta.lock().unwrap_or_else(|e| e.into_inner()) = Some(computed.clone());
+        Ok(Some((new.storage.clone(), computed)))
+    }
+
+    fn error(&self, e: io::Error) -> Error {
+        Error::new(
+            ErrorKind::LocalError,
+            format!("could not read the directory of images {:?}: {e}", self.dir),
+        )
+    }
+
+    /// Finds the images of the directory. Those that were on the `previous` shelf keep their
+    /// names and back-ends, with the caches they built up. Those that are gone are dropped, and
+    /// their back-ends with them once the operations still using them are done.
+    fn scan(&self, previous: Option<&Shelf>) -> io::Result<Shelf> {
+        let modified = modified(&self.dir)?;
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let is_iso = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("iso"));
+            // Symbolic links to images count, other entries and broken links don't
+            match std::fs::metadata(&path) {
+                Ok(meta) if is_iso && meta.is_file() => {
+                    files.push((path, meta.modified().unwrap_or(UNIX_EPOCH)));
+                }
+                _ => {}
+            }
+        }
+        files.sort();
+        let mut images = BTreeMap::new();
+        if let Some(previous) = previous {
+            files.retain(|(path, modified)| {
+                let Some((name, image)) = previous.images.iter().find(|(_, i)| i.path == *path)
+                else {
+                    return true;
+                };
+                let image = Shelved {
+                    modified: *modified,
+                    ..image.clone()
+                };
+                images.insert(name.clone(), image);
+                false
+            });
+        }
+        for (path, modified) in files {
+            let mut builder = self.prototype.clone();
+            builder.origin = Origin::Path(Arc::new(SharedFile::new(path.clone())));
+            let storage = builder.build();
+            let stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let label = match self.names {
+                ImageNames::FileName => None,
+                ImageNames::VolumeLabel => volume_label(&storage),
+            };
+            let name = label
+                .filter(|label| !images.contains_key(label))
+                .unwrap_or_else(|| unique(&images, stem));
+            let image = Shelved {
+                path,
+                storage,
+                modified,
+            };
+            images.insert(name, image);
+        }
+        let changed = self.prototype.changed.as_ref().is_some_and(|(old, new)| {
+            let paths = &self.prototype.paths;
+            let shelved = |name: &str| images.keys().any(|image| paths.matches(image, name));
+            shelved(old) && shelved(new)
+        });
+        Ok(Shelf {
+            modified,
+            images,
+            changed,
+        })
+    }
+}
+
+impl fmt::Debug for Library {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Library")
+            .field("dir", &self.dir)
+            .field("names", &self.names)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Shelf {
+    /// The metadata of the directory of images itself.
+    pub(crate) fn meta(&self) -> IsoMeta {
+        views::dir_meta(self.modified)
+    }
+
+    /// The listing of the directory of images, with a directory for every image and the
+    /// [changed view](CHANGED).
+    pub(crate) fn listing(&self) -> Vec<Listed> {
+        let dots = [".", ".."].map(|dot| (dot.to_string(), self.meta()));
+        let images = self
+            .images
+            .iter()
+            .filter(|(name, _)| !self.changed || name.as_str() != CHANGED)
+            .map(|(name, image)| (name.clone(), views::dir_meta(image.modified)));
+        let changed = self.changed.then(|| (CHANGED.to_string(), self.meta()));
+        dots.into_iter().chain(images).chain(changed).collect()
+    }
+
+    /// Returns the back-end of the image called `name`, exactly or as `paths` compares names.
+    pub(crate) fn image(&self, name: &str, paths: &PathOptions) -> Option<&Storage> {
+        self.shelved(name, paths).map(|image| &image.storage)
+    }
+
+    fn shelved(&self, name: &str, paths: &PathOptions) -> Option<&Shelved> {
+        self.images.get(name).or_else(|| {
+            self.images
+                .iter()
+                .find(|(image, _)| paths.matches(image, name))
+                .map(|(_, image)| image)
+        })
+    }
+
+    /// The images by their names.
+    pub(crate) fn images(&self) -> impl Iterator<Item = (&str, &Storage)> {
+        self.images
+            .iter()
+            .map(|(name, image)| (name.as_str(), &image.storage))
+    }
+}
+
+/// Reads the identifier of the primary volume of the image of `storage`, with separators
+/// replaced so that it is a single name. `None` if it can't be read or is blank.
+fn volume_label(storage: &Storage) -> Option<String> {
+    let source = storage.source().ok()?;
+    let descriptors = descriptor::read(&*source).ok()?;
+    let label = descriptors.into_iter().find_map(|d| match d.kind {
+        DescriptorKind::Primary(info) => Some(info.volume_id),
+        _ => None,
+    })?;
+    let label = label.replace(['/', '\\'], "_");
+    match label.as_str() {
+        "" | "." | ".." => None,
+        _ => Some(label),
+    }
+}
+
+fn modified(path: &Path) -> io::Result<SystemTime> {
+    std::fs::metadata(path)?.modified()
+}
+
+/// Returns `name`, or `name~2`, `name~3` and so on if an image already has it.
+fn unique(images: &BTreeMap<String, Shelved>, name: String) -> String {
+    if !images.contains_key(&name) {
+        return name;
+    }
+    (2..)
+        .map(|n| format!("{name}~{n}"))
+        .find(|name| !images.contains_key(name))
+        .expect("an unused name")
+}
+
+/// Splits a path of a directory of images into the name of the image and the path within it,
+/// or `None` for the directory itself.
+pub(crate) fn split(names: &[String]) -> Option<(&str, PathBuf)> {
+    let (image, within) = names.split_first()?;
+    Some((image.as_str(), path::absolute(within)))
+}
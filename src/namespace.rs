@@ -0,0 +1,58 @@
+//! Choosing which of the directory hierarchies of an image is served.
+
+use crate::IsoReader;
+use cdfs::{ExtraAttributes, ISO9660, ISODirectory};
+use unftp_core::storage::{Error, ErrorKind, Result};
+
+/// The directory hierarchy of the image that is served. Images usually record the same tree
+/// several times: with the short names of plain ISO 9660 in the primary volume, possibly
+/// extended by Rock Ridge entries, and with long names in a Joliet supplementary volume.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Namespace {
+    /// The primary volume if it has Rock Ridge entries, else the Joliet volume if there is one,
+    /// else the primary volume
+    #[default]
+    Auto,
+    /// The primary volume. Rock Ridge names, where recorded, still replace the ISO 9660
+    /// identifiers.
+    Primary,
+    /// The Joliet volume. Images without one fail to open.
+    Joliet,
+    /// The primary volume with its Rock Ridge entries. Images without them fail to open.
+    RockRidge,
+}
+
+impl Namespace {
+    /// Returns the root directory of the hierarchy in the given image.
+    pub(crate) fn root(self, iso: &ISO9660<IsoReader>) -> Result<ISODirectory<IsoReader>> {
+        let missing = |what: &str| {
+            Error::new(
+                ErrorKind::LocalError,
+                format!("the image has no {what} to serve"),
+            )
+        };
+        match self {
+            Namespace::Auto => Ok(iso.root().clone()),
+            Namespace::Primary => Ok(iso.root_at(0).expect("there is a primary volume").clone()),
+            Namespace::Joliet => iso
+                .root_at(1)
+                .cloned()
+                .ok_or_else(|| missing("Joliet volume")),
+            Namespace::RockRidge if iso.is_rr() => {
+                Ok(iso.root_at(0).expect("there is a primary volume").clone())
+            }
+            Namespace::RockRidge => Err(missing("Rock Ridge entries")),
+        }
+    }
+
+    /// Returns the root directory of the other hierarchy of the given image: the primary volume
+    /// when the Joliet volume is served, and the Joliet volume otherwise, if there is one.
+    pub(crate) fn other_root(self, iso: &ISO9660<IsoReader>) -> Option<ISODirectory<IsoReader>> {
+        let served = self.root(iso).ok()?.header().extent_loc;
+        [iso.root_at(0), iso.root_at(1)]
+            .into_iter()
+            .flatten()
+            .find(|root| root.header().extent_loc != served)
+            .cloned()
+    }
+}
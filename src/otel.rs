@@ -0,0 +1,134 @@
+//! Spans of the operations of the back-end, with the attributes OpenTelemetry's conventions name,
+//! for the `otel` feature. `tracing-opentelemetry` exports them as spans of the trace the FTP
+//! server's own spans belong to: `otel.name` names the span, `otel.status_code` and
+//! `error.message` tell failed operations.
+
+use crate::{Error, Storage, descriptor::SECTOR};
+use std::{
+    fmt,
+    future::Future,
+    io,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+use tracing::{Instrument, Span, field};
+
+/// Where the volume identifier is in the primary volume descriptor.
+const VOLUME_ID: u64 = 16 * SECTOR as u64 + 40;
+
+/// Runs `op`, the operation named `operation`, like `iso.list`, by `user` on `path`, in its
+/// span.
+pub(crate) async fn traced<T>(
+    operation: &'static str,
+    user: &impl fmt::Display,
+    path: &Path,
+    op: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    let span = span(operation, user, path);
+    let result = op.instrument(span.clone()).await;
+    outcome(&span, &result);
+    result
+}
+
+/// Runs `op`, which starts the download by `user` of `path`, in its span, which stays open
+/// until the download is done.
+pub(crate) async fn traced_download(
+    user: &impl fmt::Display,
+    path: &Path,
+    op: impl Future<Output = Result<Download, Error>>,
+) -> Result<Download, Error> {
+    let span = span("iso.get", user, path);
+    let result = op.instrument(span.clone()).await;
+    outcome(&span, &result);
+    result.map(|reader| Box::new(Traced::new(reader, span)) as Download)
+}
+
+type Download = Box<dyn AsyncRead + Send + Sync + Unpin>;
+
+fn span(operation: &'static str, user: &impl fmt::Display, path: &Path) -> Span {
+    tracing::info_span!(
+        target: "unftp_sbe_iso",
+        "iso",
+        "otel.name" = operation,
+        "otel.kind" = "internal",
+        "otel.status_code" = field::Empty,
+        "error.message" = field::Empty,
+        "iso.volume_id" = field::Empty,
+        "ftp.user" = %user,
+        "file.path" = %path.display(),
+        "bytes" = field::Empty,
+    )
+}
+
+fn outcome<T>(span: &Span, result: &Result<T, Error>) {
+    match result {
+        Ok(_) => span.record("otel.status_code", "OK"),
+        Err(e) => span
+            .record("otel.status_code", "ERROR")
+            .record("error.message", field::display(e)),
+    };
+}
+
+impl Storage {
+    /// Records the identifier of the volume in `span`, the span of the operation about to read
+    /// the image, reading it the first time. Blocks, like the reads of the operation.
+    pub(crate) fn record_volume(&self, span: &Span) {
+        if span.is_disabled() {
+            return;
+        }
+        let id = self.inner.caches.volume_id(|| {
+            let source = self.source().ok()?;
+            let mut id = [0; 32];
+            crate::descriptor::read_exact_at(&*source, VOLUME_ID, &mut id).ok()?;
+            Some(String::from_utf8_lossy(&id).trim_end().into())
+        });
+        if let Some(id) = id {
+            span.record("iso.volume_id", &*id);
+        }
+    }
+}
+
+/// A download, whose span stays open until it is done and records the bytes served.
+struct Traced<R> {
+    inner: R,
+    span: Span,
+    bytes: u64,
+}
+
+impl<R> Traced<R> {
+    fn new(inner: R, span: Span) -> Self {
+        Self {
+            inner,
+            span,
+            bytes: 0,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Traced<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let _entered = this.span.enter();
+        let before = buf.filled().len();
+        let polled = Pin::new(&mut this.inner).poll_read(cx, buf);
+        this.bytes += (buf.filled().len() - before) as u64;
+        if let Poll::Ready(Err(e)) = &polled {
+            this.span
+                .record("otel.status_code", "ERROR")
+                .record("error.message", field::display(e));
+        }
+        polled
+    }
+}
+
+impl<R> Drop for Traced<R> {
+    fn drop(&mut self) {
+        self.span.record("bytes", self.bytes);
+    }
+}
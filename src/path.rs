@@ -0,0 +1,225 @@
+//! Normalization of the paths clients send.
+//!
+//! FTP clients are sloppy with paths: `//DIR///FILE.TXT`, `DIR/`, `./DIR` and `DIR/../FILE.TXT`
+//! all show up in the wild. Every operation passes its path through [`PathOptions::normalize`]
+//! so they all agree on what such a path means.
+
+use crate::{Aliases, Duplicates, FileVersions, Namespace, short_names};
+use std::{
+    borrow::Cow,
+    path::{Component, Path, PathBuf},
+};
+use unftp_core::storage::{Error, ErrorKind, Result};
+use unicode_normalization::UnicodeNormalization;
+
+/// How client paths are interpreted.
+#[derive(Debug, Clone)]
+pub(crate) struct PathOptions {
+    /// The directory in the image that clients see as `/`.
+    pub(crate) root: PathBuf,
+    /// Serve the only directory in the root as the root instead.
+    pub(crate) descend: bool,
+    /// Treat `\` as a separator, for legacy Windows clients.
+    pub(crate) backslash_separators: bool,
+    /// Compare names by their canonical decomposition, so NFC and NFD forms match.
+    pub(crate) unicode_normalization: bool,
+    /// Present names in uppercase 8.3 form.
+    pub(crate) short_names: bool,
+    /// Renamed and relocated paths.
+    pub(crate) aliases: Aliases,
+    /// The hierarchy of the image that is served.
+    pub(crate) namespace: Namespace,
+    /// Look paths that aren't found in the served hierarchy up in the other one.
+    pub(crate) namespace_fallback: bool,
+    /// The versions of files recorded in several that are exposed.
+    pub(crate) versions: FileVersions,
+    /// Which of several records with the same name is exposed.
+    pub(crate) duplicates: Duplicates,
+    /// Expose the records flagged hidden or as associated files too.
+    pub(crate) flagged: bool,
+}
+
+impl Default for PathOptions {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("/"),
+            descend: false,
+            backslash_separators: false,
+            unicode_normalization: false,
+            short_names: false,
+            aliases: Aliases::default(),
+            namespace: Namespace::default(),
+            namespace_fallback: false,
+            versions: FileVersions::default(),
+            duplicates: Duplicates::default(),
+            flagged: true,
+        }
+    }
+}
+
+impl PathOptions {
+    /// Splits `path` into the names it consists of, relative to the root of the image.
+    ///
+    /// Repeated and trailing separators and `.` components are ignored and `..` removes the name
+    /// before it. Like on a Unix file system, `..` at the root stays at the root.
+    pub(crate) fn normalize(&self, path: &Path) -> Result<Vec<String>> {
+        let path = match path.to_str() {
+            Some(s) if self.backslash_separators && s.contains('\\') => {
+                Cow::Owned(PathBuf::from(s.replace('\\', "/")))
+            }
+            _ => Cow::Borrowed(path),
+        };
+        let mut names = Vec::new();
+        for comp in path.components() {
+            match comp {
+                Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+                Component::ParentDir => {
+                    names.pop();
+                }
+                Component::Normal(name) => {
+                    let name = name.to_str().ok_or_else(|| {
+                        Error::new(
+                            ErrorKind::FileNameNotAllowedError,
+                            format!("path {path:?} is not valid UTF-8"),
+                        )
+                    })?;
+                    names.push(name.to_string());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    /// Returns the names to show clients for the entries of a directory with the given
+    /// identifiers, in the same order.
+    pub(crate) fn display_names(&self, identifiers: &[&str]) -> Vec<String> {
+        if self.short_names {
+            short_names::short_names(identifiers)
+        } else {
+            identifiers.iter().map(|id| id.to_string()).collect()
+        }
+    }
+
+    /// Tells whether `path` ends in a separator, so that it only names a directory.
+    pub(crate) fn names_directory(&self, path: &Path) -> bool {
+        match path.as_os_str().as_encoded_bytes().last() {
+            Some(b'/') => true,
+            Some(b'\\') => self.backslash_separators,
+            _ => false,
+        }
+    }
+
+    /// Tells whether a name from a client path refers to the entry with the given identifier.
+    pub(crate) fn matches(&self, identifier: &str, name: &str) -> bool {
+        identifier.eq_ignore_ascii_case(name)
+            || (self.unicode_normalization
+                && !(identifier.is_ascii() && name.is_ascii())
+                // Canonically equivalent names have the same decomposition
+                && identifier
+                    .nfd()
+                    .map(|c| c.to_ascii_lowercase())
+                    .eq(name.nfd().map(|c| c.to_ascii_lowercase())))
+    }
+}
+
+/// Joins names returned by [`PathOptions::normalize`] into an absolute path.
+pub(crate) fn absolute(names: &[String]) -> PathBuf {
+    let mut path = PathBuf::from("/");
+    path.extend(names);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(path: &str) -> Vec<String> {
+        PathOptions::default().normalize(Path::new(path)).unwrap()
+    }
+
+    #[test]
+    fn root() {
+        for path in ["", "/", "//", ".", "/./", "..", "/../.."] {
+            assert!(names(path).is_empty(), "{path}");
+        }
+    }
+
+    #[test]
+    fn redundant_separators() {
+        for path in [
+            "/DIR/FILE.TXT",
+            "DIR/FILE.TXT",
+            "//DIR///FILE.TXT",
+            "/DIR/FILE.TXT/",
+            "./DIR/./FILE.TXT",
+        ] {
+            assert_eq!(names(path), ["DIR", "FILE.TXT"], "{path}");
+        }
+    }
+
+    #[test]
+    fn parent_components() {
+        assert_eq!(names("/DIR/.."), Vec::<String>::new());
+        assert_eq!(names("/DIR/SUB/../FILE.TXT"), ["DIR", "FILE.TXT"]);
+        assert_eq!(names("/../DIR"), ["DIR"]);
+        assert_eq!(names("A/B/../../C/"), ["C"]);
+    }
+
+    #[test]
+    fn absolute_paths() {
+        assert_eq!(
+            absolute(&names("//DIR///FILE.TXT/")),
+            Path::new("/DIR/FILE.TXT")
+        );
+        assert_eq!(absolute(&[]), Path::new("/"));
+    }
+
+    #[test]
+    fn backslash_separators() {
+        let options = PathOptions {
+            backslash_separators: true,
+            ..PathOptions::default()
+        };
+        for path in [
+            "DIR\\FILE.TXT",
+            "\\DIR\\FILE.TXT",
+            "/DIR\\\\FILE.TXT\\",
+            "DIR/FILE.TXT",
+        ] {
+            assert_eq!(
+                options.normalize(Path::new(path)).unwrap(),
+                ["DIR", "FILE.TXT"],
+                "{path}"
+            );
+        }
+        assert_eq!(names("DIR\\FILE.TXT"), ["DIR\\FILE.TXT"]);
+    }
+
+    #[test]
+    fn unicode_normalization() {
+        let (nfc, nfd) = ("caf\u{e9}.txt", "cafe\u{301}.txt");
+        assert!(!PathOptions::default().matches(nfc, nfd));
+        let options = PathOptions {
+            unicode_normalization: true,
+            ..PathOptions::default()
+        };
+        assert!(options.matches(nfc, nfd));
+        assert!(options.matches(nfd, nfc));
+        assert!(options.matches("CAF\u{c9}.TXT", "CAFE\u{301}.TXT"));
+        assert!(!options.matches(nfc, "cafe.txt"));
+        // Combining marks in another order, and Hangul syllables spelled out in jamo
+        assert!(options.matches("\u{1e69}", "s\u{307}\u{323}"));
+        assert!(options.matches("\u{d55c}", "\u{1112}\u{1161}\u{11ab}"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let err = PathOptions::default()
+            .normalize(Path::new(OsStr::from_bytes(b"/DIR/\xff")))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::FileNameNotAllowedError);
+    }
+}
@@ -0,0 +1,156 @@
+//! The path tables of ISO 9660, which list every directory of a hierarchy with its extent, so
+//! that a directory can be found without scanning the records of its ancestors.
+
+use crate::{
+    descriptor::{self, DescriptorKind, read_exact_at},
+    source::IsoSource,
+};
+use std::{collections::HashMap, io};
+
+/// The largest path table read. Larger tables are ignored, and lookups walk the directories.
+const MAX_SIZE: u32 = 64 * 1024 * 1024;
+
+/// The directories of a hierarchy, by number. Directory 1 is the root.
+#[derive(Debug)]
+pub(crate) struct PathTable {
+    /// The extent of every directory, directory 1 first.
+    extents: Vec<u32>,
+    numbers: HashMap<u32, u16>,
+    /// The directories by their parent and identifier.
+    children: HashMap<(u16, String), u16>,
+}
+
+impl PathTable {
+    /// Reads the path table of the volume whose root directory is at `root_extent`. Returns
+    /// `None` if there is no such volume or its path table is unusable.
+    pub(crate) fn read(source: &dyn IsoSource, root_extent: u32) -> io::Result<Option<Self>> {
+        for d in descriptor::read(source)? {
+            let (info, kind) = match &d.kind {
+                DescriptorKind::Primary(info) => (info, 1),
+                DescriptorKind::Supplementary(info) => (info, 2),
+                _ => continue,
+            };
+            if info.root_extent != root_extent {
+                continue;
+            }
+            let le32 = |at: usize| u32::from_le_bytes(d.raw[at..at + 4].try_into().unwrap());
+            let (size, location) = (le32(132), le32(140));
+            if size > MAX_SIZE {
+                return Ok(None);
+            }
+            let mut table = vec![0; size as usize];
+            read_exact_at(
+                source,
+                u64::from(location) * descriptor::SECTOR as u64,
+                &mut table,
+            )?;
+            let ucs2 = kind == 2 && info.joliet_level().is_some();
+            return Ok(Self::parse(&table, ucs2, root_extent));
+        }
+        Ok(None)
+    }
+
+    fn parse(table: &[u8], ucs2: bool, root_extent: u32) -> Option<Self> {
+        let mut extents = Vec::new();
+        let mut numbers = HashMap::new();
+        let mut children = HashMap::new();
+        let mut at = 0;
+        while at + 8 <= table.len() {
+            let len = table[at] as usize;
+            if len == 0 || at + 8 + len > table.len() {
+                break;
+            }
+            let extent = u32::from_le_bytes(table[at + 2..at + 6].try_into().unwrap());
+            let parent = u16::from_le_bytes([table[at + 6], table[at + 7]]);
+            let identifier = &table[at + 8..at + 8 + len];
+            let number = u16::try_from(extents.len() + 1).ok()?;
+            // Parents are listed before their children
+            if number > 1 && (parent == 0 || parent >= number) {
+                return None;
+            }
+            if number > 1 {
+                let name = if ucs2 {
+                    let units: Vec<u16> = identifier
+                        .chunks_exact(2)
+                        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                        .collect();
+                    String::from_utf16(&units).ok()?
+                } else {
+                    String::from_utf8(identifier.to_vec()).ok()?
+                };
+                // The first of duplicate names, as the records are resolved by default
+                children.entry((parent, name)).or_insert(number);
+            }
+            extents.push(extent);
+            numbers.entry(extent).or_insert(number);
+            at += 8 + len + len % 2;
+        }
+        (extents.first() == Some(&root_extent)).then_some(Self {
+            extents,
+            numbers,
+            children,
+        })
+    }
+
+    /// Returns the extent of the directory called exactly `name` in the directory at `extent`.
+    pub(crate) fn child(&self, extent: u32, name: &str) -> Option<u32> {
+        let parent = *self.numbers.get(&extent)?;
+        let child = *self.children.get(&(parent, name.to_string()))?;
+        self.extents.get(usize::from(child) - 1).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(extent: u32, parent: u16, identifier: &[u8]) -> Vec<u8> {
+        let mut e = vec![identifier.len() as u8, 0];
+        e.extend(extent.to_le_bytes());
+        e.extend(parent.to_le_bytes());
+        e.extend(identifier);
+        if identifier.len() % 2 == 1 {
+            e.push(0);
+        }
+        e
+    }
+
+    #[test]
+    fn children() {
+        let table = [
+            entry(20, 1, &[0]),
+            entry(21, 1, b"A"),
+            entry(22, 1, b"BB"),
+            entry(23, 2, b"C"),
+        ]
+        .concat();
+        let table = PathTable::parse(&table, false, 20).unwrap();
+        assert_eq!(table.child(20, "A"), Some(21));
+        assert_eq!(table.child(20, "BB"), Some(22));
+        assert_eq!(table.child(21, "C"), Some(23));
+        assert_eq!(table.child(22, "C"), None);
+        assert_eq!(table.child(20, "a"), None);
+        // Of duplicate names, the first counts
+        let table = [entry(20, 1, &[0]), entry(21, 1, b"A"), entry(22, 1, b"A")].concat();
+        let table = PathTable::parse(&table, false, 20).unwrap();
+        assert_eq!(table.child(20, "A"), Some(21));
+    }
+
+    #[test]
+    fn joliet() {
+        let name: Vec<u8> = "Dé".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let table = [entry(20, 1, &[0]), entry(21, 1, &name)].concat();
+        let table = PathTable::parse(&table, true, 20).unwrap();
+        assert_eq!(table.child(20, "Dé"), Some(21));
+    }
+
+    #[test]
+    fn malformed() {
+        // A child listed before its parent
+        let table = [entry(20, 1, &[0]), entry(21, 3, b"A"), entry(22, 1, b"B")].concat();
+        assert!(PathTable::parse(&table, false, 20).is_none());
+        // The table of another volume
+        let table = entry(20, 1, &[0]);
+        assert!(PathTable::parse(&table, false, 30).is_none());
+    }
+}
@@ -0,0 +1,151 @@
+//! A pool of threads dedicated to reading images, for [`StorageBuilder::io_pool`], and running
+//! blocking reads either there or on tokio's blocking pool.
+
+use std::{
+    fmt,
+    future::Future,
+    io,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Mutex, mpsc},
+    task::{Context, Poll},
+    thread,
+};
+use tokio::{sync::oneshot, task::JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads that read from images, so that a burst of downloads doesn't take up tokio's blocking
+/// thread pool, which the rest of the application may need, or the other way round.
+///
+/// Reads queue up once every thread is busy. Downloads hand a chunk at a time to the pool, so a
+/// slow client doesn't hold a thread. The threads exit once the pool and every back-end given it
+/// are dropped. A single pool may serve several back-ends:
+///
+/// ```
+/// use unftp_sbe_iso::{IoPool, Storage};
+///
+/// let pool = IoPool::new(8);
+/// let debian = Storage::builder("/srv/images/debian.iso")
+///     .io_pool(pool.clone())
+///     .build();
+/// let ubuntu = Storage::builder("/srv/images/ubuntu.iso")
+///     .io_pool(pool)
+///     .build();
+/// ```
+///
+/// [`StorageBuilder::io_pool`]: crate::StorageBuilder::io_pool
+#[derive(Clone)]
+pub struct IoPool {
+    jobs: mpsc::Sender<Job>,
+    threads: usize,
+}
+
+impl IoPool {
+    /// Starts `threads` threads, at least one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thread can't be started.
+    pub fn new(threads: usize) -> Self {
+        let threads = threads.max(1);
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for i in 0..threads {
+            let queue = queue.clone();
+            thread::Builder::new()
+                .name(format!("unftp-sbe-iso-io-{i}"))
+                .spawn(move || {
+                    loop {
+                        // The lock is only held while waiting for the next job
+                        let job = queue.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    }
+                })
+                .expect("could not start a thread of the I/O pool");
+        }
+        Self { jobs, threads }
+    }
+
+    /// The number of threads of the pool.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+}
+
+impl fmt::Debug for IoPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IoPool")
+            .field("threads", &self.threads)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Runs `work`, which may block, on `pool` or, without one, on tokio's blocking pool. Work on
+/// the pool runs within the runtime it was spawned from, so that it can wait on asynchronous
+/// sources.
+pub(crate) fn spawn<T, F>(pool: Option<&IoPool>, work: F) -> Spawned<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let Some(pool) = pool else {
+        return Spawned::Tokio(tokio::task::spawn_blocking(work));
+    };
+    let (done, result) = oneshot::channel();
+    let runtime = tokio::runtime::Handle::try_current().ok();
+    let job: Job = Box::new(move || {
+        let _entered = runtime.as_ref().map(|runtime| runtime.enter());
+        // A panic drops `done`, which fails the work instead of the thread
+        if let Ok(output) = panic::catch_unwind(AssertUnwindSafe(work)) {
+            let _ = done.send(output);
+        }
+    });
+    // The threads only exit once every sender is gone
+    let _ = pool.jobs.send(job);
+    Spawned::Pool(result)
+}
+
+/// Work started by [`spawn`], which fails if the work panicked.
+pub(crate) enum Spawned<T> {
+    Tokio(JoinHandle<T>),
+    Pool(oneshot::Receiver<T>),
+}
+
+impl<T> Future for Spawned<T> {
+    type Output = io::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.get_mut() {
+            Self::Tokio(task) => Pin::new(task).poll(cx).map_err(io::Error::other),
+            Self::Pool(result) => Pin::new(result)
+                .poll(cx)
+                .map_err(|_| io::Error::other("a read on the I/O pool panicked")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_on_the_pool() {
+        let pool = IoPool::new(2);
+        let name = spawn(Some(&pool), || thread::current().name().map(str::to_string));
+        let name = name.await.unwrap().unwrap();
+        assert!(name.starts_with("unftp-sbe-iso-io-"));
+        // The runtime is entered, and a panic only fails its own work
+        let entered = spawn(Some(&pool), || {
+            tokio::runtime::Handle::try_current().is_ok()
+        });
+        assert!(entered.await.unwrap());
+        assert!(spawn(Some(&pool), || panic!("broken")).await.is_err());
+        assert_eq!(spawn(Some(&pool), || 1 + 1).await.unwrap(), 2);
+        assert_eq!(spawn(None, || 2 + 2).await.unwrap(), 4);
+        assert_eq!(IoPool::new(0).threads(), 1);
+    }
+}
@@ -0,0 +1,140 @@
+//! Per-user download quotas.
+//!
+//! A [`Quota`] counts the bytes served to every user and makes the back-end refuse further
+//! RETRs once a user has used up their allowance. A download that runs past what is left is
+//! cut off with an error at the limit, so no user gets more than their quota. How the counters
+//! are persisted is decided by a pluggable [`QuotaStore`]; [`MemoryQuotaStore`] keeps them in
+//! memory for the lifetime of the process.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Persists the number of bytes each user has downloaded.
+///
+/// Users are identified by the [`Display`](std::fmt::Display) representation of their
+/// [`UserDetail`](unftp_core::auth::UserDetail), which is the username for most implementations.
+pub trait QuotaStore: Send + Sync + Debug {
+    /// Returns the number of bytes downloaded by the given user so far.
+    fn used(&self, user: &str) -> u64;
+
+    /// Adds `bytes` to the number of bytes downloaded by the given user.
+    fn add(&self, user: &str, bytes: u64);
+}
+
+/// A [`QuotaStore`] that keeps its counters in memory. Counters are lost when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryQuotaStore {
+    used: Mutex<HashMap<String, u64>>,
+}
+
+impl MemoryQuotaStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl QuotaStore for MemoryQuotaStore {
+    fn used(&self, user: &str) -> u64 {
+        let used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        used.get(user).copied().unwrap_or(0)
+    }
+
+    fn add(&self, user: &str, bytes: u64) {
+        let mut used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        *used.entry(user.to_string()).or_insert(0) += bytes;
+    }
+}
+
+/// A download quota applied to every user of the storage back-end.
+#[derive(Debug, Clone)]
+pub struct Quota {
+    limit: u64,
+    store: Arc<dyn QuotaStore>,
+}
+
+impl Quota {
+    /// Creates a quota of `limit` bytes per user, tracked in a [`MemoryQuotaStore`].
+    pub fn new(limit: u64) -> Self {
+        Self::with_store(limit, Arc::new(MemoryQuotaStore::new()))
+    }
+
+    /// Creates a quota of `limit` bytes per user, tracked in the given store.
+    pub fn with_store(limit: u64, store: Arc<dyn QuotaStore>) -> Self {
+        Self { limit, store }
+    }
+
+    /// The number of bytes every user may download.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Returns the number of bytes the given user may still download.
+    pub fn remaining(&self, user: &str) -> u64 {
+        self.limit.saturating_sub(self.store.used(user))
+    }
+
+    pub(crate) fn exhausted(&self, user: &str) -> bool {
+        self.remaining(user) == 0
+    }
+
+    pub(crate) fn meter<R>(&self, user: String, inner: R) -> QuotaReader<R> {
+        QuotaReader {
+            inner,
+            user,
+            limit: self.limit,
+            store: self.store.clone(),
+        }
+    }
+}
+
+/// Wraps the reader handed to libunftp and charges every byte read to the user, failing once
+/// reading on would take them past the limit.
+pub(crate) struct QuotaReader<R> {
+    inner: R,
+    user: String,
+    limit: u64,
+    store: Arc<dyn QuotaStore>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for QuotaReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let remaining = self.limit.saturating_sub(self.store.used(&self.user));
+        if remaining >= buf.remaining() as u64 {
+            let before = buf.filled().len();
+            ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+            let read = buf.filled().len() - before;
+            if read > 0 {
+                self.store.add(&self.user, read as u64);
+            }
+            return Poll::Ready(Ok(()));
+        }
+        // Near the limit only what is left is read. Once nothing is, one byte more shows
+        // whether the file goes on past it
+        let mut chunk = vec![0; remaining.max(1) as usize];
+        let mut limited = ReadBuf::new(&mut chunk);
+        ready!(Pin::new(&mut self.inner).poll_read(cx, &mut limited))?;
+        let read = limited.filled().len();
+        if remaining == 0 && read > 0 {
+            return Poll::Ready(Err(std::io::Error::other(format!(
+                "download quota of {} bytes exceeded",
+                self.limit
+            ))));
+        }
+        buf.put_slice(limited.filled());
+        if read > 0 {
+            self.store.add(&self.user, read as u64);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,443 @@
+//! Images dumped as raw sectors of 2352 bytes, like the `.bin` files of BIN/CUE dumps, whose
+//! sectors carry a sync pattern, a header and error detection and correction codes around the
+//! 2048 bytes of user data that make up the ISO image.
+
+use crate::source::IsoSource;
+use std::{
+    fmt, io,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// The size of a raw sector.
+pub(crate) const RAW_SECTOR: usize = 2352;
+
+/// The size of the user data of a sector.
+const DATA: usize = 2048;
+
+/// What every data sector starts with.
+const SYNC: [u8; 12] = [
+    0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0,
+];
+
+/// Where the P and Q parity of the error correction code start.
+const P_PARITY: usize = 0x81c;
+const Q_PARITY: usize = 0x8c8;
+
+/// An [`IsoSource`] that serves the user data of an image dumped as raw sectors of 2352 bytes,
+/// in Mode 1 or Mode 2 Form 1, optionally verifying every sector read against its EDC and ECC.
+///
+/// Without verification a damaged dump is served as it is, garbage included. With it, a sector
+/// whose codes don't match fails the read with [`io::ErrorKind::InvalidData`] carrying a
+/// [`CorruptSector`], which clients see as a permanent failure of the download, and is counted
+/// in the [`stats`](Self::stats). Keep an [`Arc`](std::sync::Arc) of the source to read them:
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use unftp_sbe_iso::{RawSectors, Storage};
+///
+/// let dump = std::fs::File::open("/srv/dumps/game.bin")?;
+/// let raw = Arc::new(RawSectors::new(dump).verify(true));
+/// let storage = Storage::from_source(raw.clone());
+/// // Later
+/// println!("{} damaged sectors so far", raw.stats().corrupt);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct RawSectors<S> {
+    inner: S,
+    verify: bool,
+    /// The sectors of the dump served, the first and how many, all unless limited
+    track: (u64, Option<u64>),
+    verified: AtomicU64,
+    corrupt: AtomicU64,
+}
+
+/// The sectors a [`RawSectors`] source checked, as returned by [`RawSectors::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SectorStats {
+    /// Sectors read whose codes matched
+    pub verified: u64,
+    /// Sectors read whose codes didn't match
+    pub corrupt: u64,
+}
+
+/// The error inside the [`io::Error`] of a read of a damaged sector of a [`RawSectors`] source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorruptSector {
+    /// The number of the sector in the dump
+    pub sector: u64,
+    /// Which check failed
+    pub check: Check,
+}
+
+/// The checks of the integrity of a raw sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// The sync pattern or the mode in the header, which tell a data sector
+    Header,
+    /// The error detection code, a CRC of the header and the data
+    Edc,
+    /// The error correction code, Reed-Solomon parity of the header and the data
+    Ecc,
+}
+
+impl fmt::Display for CorruptSector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let check = match self.check {
+            Check::Header => "its header is not one of a data sector",
+            Check::Edc => "its EDC doesn't match",
+            Check::Ecc => "its ECC doesn't match",
+        };
+        write!(f, "sector {} of the dump is damaged: {check}", self.sector)
+    }
+}
+
+impl std::error::Error for CorruptSector {}
+
+impl<S: IsoSource> RawSectors<S> {
+    /// Serves the user data of the raw sectors of `inner`, without verifying them.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            verify: false,
+            track: (0, None),
+            verified: AtomicU64::new(0),
+            corrupt: AtomicU64::new(0),
+        }
+    }
+
+    /// Verifies the EDC and ECC of every sector read, failing reads of damaged sectors. Costs a
+    /// few microseconds a sector. Off by default.
+    pub fn verify(mut self, enabled: bool) -> Self {
+        self.verify = enabled;
+        self
+    }
+
+    /// Serves only the `sectors` sectors of the dump starting at sector `first`, or all those
+    /// that follow it.
+    pub(crate) fn track(mut self, first: u64, sectors: Option<u64>) -> Self {
+        self.track = (first, sectors);
+        self
+    }
+
+    /// The sectors checked so far, counting every read of a sector.
+    pub fn stats(&self) -> SectorStats {
+        SectorStats {
+            verified: self.verified.load(Ordering::Relaxed),
+            corrupt: self.corrupt.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Reads sector `sector` and returns where its user data starts.
+    fn sector(&self, sector: u64, raw: &mut [u8; RAW_SECTOR]) -> io::Result<usize> {
+        crate::descriptor::read_exact_at(&self.inner, sector * RAW_SECTOR as u64, raw)?;
+        let data = match data_offset(raw) {
+            Some(data) => data,
+            None if self.verify => return Err(self.corrupt(sector, Check::Header)),
+            // Served as Mode 1 at a guess
+            None => 16,
+        };
+        if self.verify {
+            if let Some(check) = damage(raw, data) {
+                return Err(self.corrupt(sector, check));
+            }
+            self.verified.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(data)
+    }
+
+    fn corrupt(&self, sector: u64, check: Check) -> io::Error {
+        self.corrupt.fetch_add(1, Ordering::Relaxed);
+        io::Error::new(io::ErrorKind::InvalidData, CorruptSector { sector, check })
+    }
+}
+
+impl<S: IsoSource> IsoSource for RawSectors<S> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.len()?;
+        if offset >= len || buf.is_empty() {
+            return Ok(0);
+        }
+        let mut raw = [0; RAW_SECTOR];
+        let mut filled = 0;
+        let end = (offset + buf.len() as u64).min(len);
+        let mut at = offset;
+        while at < end {
+            let sector = self.track.0 + at / DATA as u64;
+            let within = (at % DATA as u64) as usize;
+            let data = self.sector(sector, &mut raw)?;
+            let n = (DATA - within).min((end - at) as usize);
+            buf[filled..filled + n].copy_from_slice(&raw[data + within..data + within + n]);
+            filled += n;
+            at += n as u64;
+        }
+        Ok(filled)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        let (first, sectors) = self.track;
+        let available = (self.inner.len()? / RAW_SECTOR as u64).saturating_sub(first);
+        let sectors = sectors.map_or(available, |sectors| sectors.min(available));
+        Ok(sectors * DATA as u64)
+    }
+}
+
+impl<S> fmt::Debug for RawSectors<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawSectors")
+            .field("verify", &self.verify)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where the user data of a data sector starts: after the header in Mode 1, after the subheader
+/// too in Mode 2 Form 1. `None` for audio and Mode 2 Form 2 sectors, which hold no image data.
+fn data_offset(raw: &[u8; RAW_SECTOR]) -> Option<usize> {
+    if raw[..12] != SYNC {
+        return None;
+    }
+    match raw[15] {
+        1 => Some(16),
+        // The form is in the submode byte of the subheader
+        2 if raw[18] & 0x20 == 0 => Some(24),
+        _ => None,
+    }
+}
+
+/// Checks the codes of a data sector whose user data starts at `data`, returning the one that
+/// doesn't match.
+fn damage(raw: &[u8; RAW_SECTOR], data: usize) -> Option<Check> {
+    let (covered, edc_at) = match data {
+        16 => (0..16 + DATA, 16 + DATA),
+        _ => (16..24 + DATA, 24 + DATA),
+    };
+    let stored = u32::from_le_bytes(raw[edc_at..edc_at + 4].try_into().unwrap());
+    if edc(&raw[covered]) != stored {
+        return Some(Check::Edc);
+    }
+    if ecc(raw, data == 24) != raw[P_PARITY..] {
+        return Some(Check::Ecc);
+    }
+    None
+}
+
+struct Tables {
+    edc: [u32; 256],
+    /// Multiplication by α in GF(2⁸)
+    forward: [u8; 256],
+    /// Division by 1 + α
+    backward: [u8; 256],
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut tables = Tables {
+            edc: [0; 256],
+            forward: [0; 256],
+            backward: [0; 256],
+        };
+        for i in 0..256 {
+            let j = ((i << 1) ^ if i & 0x80 != 0 { 0x11d } else { 0 }) as u8;
+            tables.forward[i] = j;
+            tables.backward[i ^ j as usize] = i as u8;
+            let mut edc = i as u32;
+            for _ in 0..8 {
+                edc = (edc >> 1) ^ if edc & 1 != 0 { 0xd801_8001 } else { 0 };
+            }
+            tables.edc[i] = edc;
+        }
+        tables
+    })
+}
+
+/// The error detection code of `bytes`, a CRC-32 with the polynomial of the CD-ROM standard.
+fn edc(bytes: &[u8]) -> u32 {
+    let edc = &tables().edc;
+    bytes.iter().fold(0, |crc, &byte| {
+        (crc >> 8) ^ edc[((crc ^ u32::from(byte)) & 0xff) as usize]
+    })
+}
+
+/// The P and Q parity of a sector, of which Mode 2 leaves the address out.
+fn ecc(raw: &[u8; RAW_SECTOR], mode2: bool) -> [u8; RAW_SECTOR - P_PARITY] {
+    let mut sector = *raw;
+    if mode2 {
+        sector[12..16].fill(0);
+    }
+    let p = Q_PARITY - P_PARITY;
+    let mut parity = [0; RAW_SECTOR - P_PARITY];
+    parity_block(&sector[12..], 86, 24, 2, 86, &mut parity[..p]);
+    // The Q parity covers the P parity too
+    sector[P_PARITY..Q_PARITY].copy_from_slice(&parity[..p]);
+    parity_block(&sector[12..], 52, 43, 86, 88, &mut parity[p..]);
+    parity
+}
+
+/// Computes the Reed-Solomon parity of the `major` vectors of `minor` bytes of `src`, as laid
+/// out by the CD-ROM standard.
+fn parity_block(
+    src: &[u8],
+    major: usize,
+    minor: usize,
+    major_mult: usize,
+    minor_inc: usize,
+    dest: &mut [u8],
+) {
+    let tables = tables();
+    let size = major * minor;
+    for m in 0..major {
+        let mut index = (m >> 1) * major_mult + (m & 1);
+        let (mut a, mut b) = (0u8, 0u8);
+        for _ in 0..minor {
+            let byte = src[index];
+            index += minor_inc;
+            if index >= size {
+                index -= size;
+            }
+            a ^= byte;
+            b ^= byte;
+            a = tables.forward[a as usize];
+        }
+        a = tables.backward[(tables.forward[a as usize] ^ b) as usize];
+        dest[m] = a;
+        dest[m + major] = a ^ b;
+    }
+}
+
+/// Encodes `image` as raw Mode 1 sectors, with their codes, for tests.
+#[cfg(any(test, feature = "test-util"))]
+pub(crate) fn encode(image: &[u8]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(image.len().div_ceil(DATA) * RAW_SECTOR);
+    for (lba, data) in image.chunks(DATA).enumerate() {
+        let mut sector = [0; RAW_SECTOR];
+        sector[..12].copy_from_slice(&SYNC);
+        // The address in minutes, seconds and frames, in BCD, counting the two seconds of lead-in
+        let frames = lba + 150;
+        let bcd = |n: usize| (((n / 10) << 4) | (n % 10)) as u8;
+        sector[12] = bcd(frames / (60 * 75));
+        sector[13] = bcd(frames / 75 % 60);
+        sector[14] = bcd(frames % 75);
+        sector[15] = 1;
+        sector[16..16 + data.len()].copy_from_slice(data);
+        let edc = edc(&sector[..16 + DATA]);
+        sector[16 + DATA..20 + DATA].copy_from_slice(&edc.to_le_bytes());
+        let parity = ecc(&sector, false);
+        sector[P_PARITY..].copy_from_slice(&parity);
+        raw.extend_from_slice(&sector);
+    }
+    raw
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Without an initial value or a final xor, the code is linear, and zero for zeros.
+    #[test]
+    fn edc_is_linear() {
+        let a: Vec<u8> = (0..100u8).collect();
+        let b: Vec<u8> = (0..100u8).map(|i| i.wrapping_mul(31)).collect();
+        let both: Vec<u8> = a.iter().zip(&b).map(|(a, b)| a ^ b).collect();
+        assert_eq!(edc(&a) ^ edc(&b), edc(&both));
+        assert_eq!(edc(&[0; 100]), 0);
+        assert_ne!(edc(&a), 0);
+    }
+
+    /// Checks the Reed-Solomon property of the P vectors independently of how they are made:
+    /// every column of 24 bytes with its 2 parity bytes has both syndromes zero.
+    #[test]
+    fn p_parity_has_zero_syndromes() {
+        let image: Vec<u8> = (0..DATA as u32).map(|i| (i * 7 + 3) as u8).collect();
+        let sector: [u8; RAW_SECTOR] = encode(&image).try_into().unwrap();
+        let forward = &tables().forward;
+        for column in 0..86 {
+            let (mut s0, mut s1) = (0u8, 0u8);
+            for k in 0..26 {
+                let byte = sector[12 + column + 86 * k];
+                s0 ^= byte;
+                s1 = forward[s1 as usize] ^ byte;
+            }
+            assert_eq!((s0, s1), (0, 0), "column {column}");
+        }
+    }
+
+    #[test]
+    fn damaged_sectors() {
+        let image: Vec<u8> = (0..3 * DATA as u32).map(|i| i as u8).collect();
+        let raw = encode(&image);
+        let source = RawSectors::new(raw.clone()).verify(true);
+        assert_eq!(source.len().unwrap(), image.len() as u64);
+        let mut buf = vec![0; 3000];
+        assert_eq!(source.read_at(1000, &mut buf).unwrap(), 3000);
+        assert_eq!(buf, image[1000..4000]);
+        assert_eq!(source.stats().verified, 2);
+
+        let mut damaged = raw.clone();
+        damaged[RAW_SECTOR + 100] ^= 1;
+        let source = RawSectors::new(damaged.clone()).verify(true);
+        let e = source.read_at(DATA as u64, &mut buf).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let corrupt = e
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<CorruptSector>()
+            .unwrap();
+        assert_eq!(
+            *corrupt,
+            CorruptSector {
+                sector: 1,
+                check: Check::Edc
+            }
+        );
+        assert_eq!(
+            source.stats(),
+            SectorStats {
+                verified: 0,
+                corrupt: 1
+            }
+        );
+        // Damage in the parity only shows in the ECC
+        let mut parity = raw.clone();
+        parity[P_PARITY + 5] ^= 0x80;
+        let e = RawSectors::new(parity).verify(true).read_at(0, &mut buf);
+        let e = e.unwrap_err();
+        let corrupt = e
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<CorruptSector>()
+            .unwrap();
+        assert_eq!(corrupt.check, Check::Ecc);
+        // Unverified, the damage is served
+        let n = RawSectors::new(damaged)
+            .read_at(DATA as u64, &mut buf)
+            .unwrap();
+        assert_eq!(n, buf.len());
+        assert_ne!(buf, image[DATA..DATA + n]);
+    }
+
+    #[test]
+    fn mode2_form1() {
+        let mut sector = [0; RAW_SECTOR];
+        sector[..12].copy_from_slice(&SYNC);
+        sector[15] = 2;
+        sector[24..24 + DATA].fill(0x42);
+        let edc = edc(&sector[16..24 + DATA]);
+        sector[24 + DATA..28 + DATA].copy_from_slice(&edc.to_le_bytes());
+        let parity = ecc(&sector, true);
+        sector[P_PARITY..].copy_from_slice(&parity);
+        // The address isn't covered by the ECC of Mode 2
+        sector[12] = 0x12;
+        let source = RawSectors::new(sector.to_vec()).verify(true);
+        let mut buf = [0; 4];
+        assert_eq!(source.read_at(0, &mut buf).unwrap(), 4);
+        assert_eq!(buf, [0x42; 4]);
+        sector[18] |= 0x20;
+        let e = RawSectors::new(sector.to_vec())
+            .verify(true)
+            .read_at(0, &mut buf);
+        assert!(e.is_err());
+    }
+}
@@ -0,0 +1,272 @@
+//! Access to the records of a directory: a lazy iterator decoding them from a sector buffer, and
+//! an index of them by identifier, so that a lookup in a directory of tens of thousands of entries
+//! reads a single record instead of all of them.
+
+use crate::{
+    IsoReader, IsoSource, descriptor,
+    duplicates::{self, Duplicates},
+    versions::{self, FileVersions},
+};
+use cdfs::{BlockBuffer, BlockBufferCtor, DirectoryEntry, ExtraAttributes, ISODirectory};
+use std::borrow::Cow;
+
+/// The identifiers of the records of a directory with where each record starts.
+#[derive(Debug)]
+pub(crate) struct RecordIndex {
+    /// The name and offset of every record, in the order of the directory, named by
+    /// [`name`].
+    records: Vec<(String, u64)>,
+    /// Positions in `records`, sorted by identifier.
+    sorted: Vec<u32>,
+}
+
+impl RecordIndex {
+    /// Reads the records of `dir`, up to the first that can't be decoded, and names them with
+    /// [`name`]. Records [`flagged`] hidden are left out unless `flagged` says otherwise.
+    pub(crate) fn build(
+        dir: &ISODirectory<IsoReader>,
+        versions: FileVersions,
+        duplicates: Duplicates,
+        flagged: bool,
+    ) -> Self {
+        let records = Records::new(dir).filter(|(_, entry)| flagged || !self::flagged(entry));
+        let records: Vec<(String, u64)> = name(records, |(_, entry)| entry, versions, duplicates)
+            .into_iter()
+            .map(|(name, (offset, _))| (name, offset))
+            .collect();
+        Self::from_records(records)
+    }
+
+    /// Indexes records named and given in the order of the directory, like ones read back
+    /// from a [catalog](crate::StorageBuilder::catalog).
+    pub(crate) fn from_records(records: Vec<(String, u64)>) -> Self {
+        let mut sorted: Vec<u32> = (0..records.len() as u32).collect();
+        // Records are sorted on disc already, which the sort is quick to notice, but Rock Ridge
+        // names aren't. The sort is stable, so equal identifiers keep the order of the directory.
+        sorted.sort_by(|&a, &b| records[a as usize].0.cmp(&records[b as usize].0));
+        Self { records, sorted }
+    }
+
+    /// The memory the index takes, roughly.
+    pub(crate) fn size(&self) -> usize {
+        self.records
+            .iter()
+            .map(|(name, _)| name.len() + size_of::<(String, u64, u32)>())
+            .sum()
+    }
+
+    /// Finds the first record whose identifier is exactly `name` by binary search.
+    pub(crate) fn exact(&self, name: &str) -> Option<u64> {
+        let first = self
+            .sorted
+            .partition_point(|&i| self.records[i as usize].0.as_str() < name);
+        let (identifier, offset) = &self.records[*self.sorted.get(first)? as usize];
+        (identifier == name).then_some(*offset)
+    }
+
+    /// The identifiers of the records with their offsets, in the order of the directory.
+    #[cfg(feature = "catalog")]
+    pub(crate) fn records(&self) -> &[(String, u64)] {
+        &self.records
+    }
+
+    /// The identifiers of the records, in the order of the directory.
+    pub(crate) fn identifiers(&self) -> impl Iterator<Item = &str> {
+        self.records
+            .iter()
+            .map(|(identifier, _)| identifier.as_str())
+    }
+
+    /// The offset of the record at `position` in the order of the directory.
+    pub(crate) fn offset(&self, position: usize) -> Option<u64> {
+        self.records.get(position).map(|(_, offset)| *offset)
+    }
+
+    /// Finds the first record, in the order of the directory, whose identifier matches.
+    pub(crate) fn first(&self, mut matches: impl FnMut(&str) -> bool) -> Option<u64> {
+        self.records
+            .iter()
+            .find(|(identifier, _)| matches(identifier))
+            .map(|(_, offset)| *offset)
+    }
+}
+
+/// Names the records of a directory, given in the order of the directory with a way to get at
+/// their entry, the way clients see them: the [file versions](FileVersions) and
+/// [duplicates](Duplicates) chosen are exposed and the rest are dropped.
+pub(crate) fn name<T>(
+    records: impl Iterator<Item = T>,
+    entry: impl Fn(&T) -> &DirectoryEntry<IsoReader>,
+    versions: FileVersions,
+    duplicates: Duplicates,
+) -> Vec<(String, T)> {
+    let identifier = |record: &T| entry(record).identifier().to_string();
+    name_by(records, &entry, identifier, versions, duplicates)
+}
+
+/// Like [`name`], with the identifier of each record given by `identifier` rather than the one
+/// cdfs decoded.
+pub(crate) fn name_by<T>(
+    records: impl Iterator<Item = T>,
+    entry: impl Fn(&T) -> &DirectoryEntry<IsoReader>,
+    identifier: impl Fn(&T) -> String,
+    versions: FileVersions,
+    duplicates: Duplicates,
+) -> Vec<(String, T)> {
+    let records = records
+        .map(|record| {
+            let e = entry(&record);
+            let mut identifier = identifier(&record);
+            if let Cow::Owned(canonical) = canonical(&identifier) {
+                identifier = canonical;
+            }
+            let version = versions::version(e);
+            let size = u64::from(e.header().extent_length);
+            (identifier, version, (size, record))
+        })
+        .collect();
+    let records = versions::present(records, versions)
+        .into_iter()
+        .map(|(name, (size, record))| (name, size, record))
+        .collect();
+    duplicates::resolve(records, duplicates)
+}
+
+/// The name clients see for an identifier of a sloppily mastered or crafted image, which they
+/// couldn't reach under the identifier itself, or which would break the lines of a listing:
+/// separators become `_`, control characters like `\r` and `\n` are escaped as the `%XX` of
+/// their UTF-8 bytes, and leading spaces and trailing dots and spaces are dropped, as clients and
+/// their users drop them from paths. An identifier that would be left empty stays as it is.
+pub(crate) fn canonical(identifier: &str) -> Cow<'_, str> {
+    let trimmed = identifier
+        .trim_start_matches(' ')
+        .trim_end_matches(['.', ' ']);
+    if trimmed.is_empty() {
+        return Cow::Borrowed(identifier);
+    }
+    if trimmed.len() == identifier.len()
+        && !identifier.contains(|c: char| c == '/' || c.is_control())
+    {
+        return Cow::Borrowed(identifier);
+    }
+    let mut name = String::with_capacity(trimmed.len());
+    for c in trimmed.chars() {
+        match c {
+            '/' => name.push('_'),
+            c if c.is_control() => {
+                for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    name.push_str(&format!("%{byte:02X}"));
+                }
+            }
+            c => name.push(c),
+        }
+    }
+    Cow::Owned(name)
+}
+
+/// Reads the ISO 9660 identifier of the record of `dir` at `offset` from `source`, which cdfs
+/// replaces with the Rock Ridge name where there is one. Like cdfs does, the version and the
+/// dot that ends names without an extension are dropped.
+pub(crate) fn recorded_identifier(
+    source: &dyn IsoSource,
+    dir: &ISODirectory<IsoReader>,
+    offset: u64,
+) -> Option<String> {
+    let start = u64::from(dir.header().extent_loc) * u64::from(cdfs::BLOCK_SIZE) + offset;
+    let mut header = [0; 33];
+    descriptor::read_exact_at(source, start, &mut header).ok()?;
+    let mut identifier = vec![0; usize::from(header[32])];
+    descriptor::read_exact_at(source, start + 33, &mut identifier).ok()?;
+    let mut identifier = String::from_utf8_lossy(&identifier).into_owned();
+    if header[25] & 2 == 0 {
+        if let Some(version) = identifier.rfind(';') {
+            identifier.truncate(version);
+        }
+        if identifier.ends_with('.') {
+            identifier.pop();
+        }
+    }
+    Some(identifier)
+}
+
+/// Tells whether `entry` is flagged hidden, with the existence bit, or as an associated file,
+/// like the resource forks of classic Mac OS files.
+pub(crate) fn flagged(entry: &DirectoryEntry<IsoReader>) -> bool {
+    // The existence bit is bit 0 of the flags, the associated file bit bit 2
+    entry.header().file_flags.bits() & 0b101 != 0
+}
+
+/// Reads the record of `dir` at `offset`.
+pub(crate) fn read(
+    dir: &ISODirectory<IsoReader>,
+    offset: u64,
+) -> Option<DirectoryEntry<IsoReader>> {
+    dir.read_entry_at(&mut BlockBuffer::new(), &mut None, offset)
+        .ok()
+        .map(|(entry, _)| entry)
+}
+
+/// The records of a directory with their offsets, decoded one at a time as the iterator is
+/// advanced. Each sector is read once into a buffer the records are decoded from. Iteration
+/// stops at the first record that can't be decoded; the cdfs iterator keeps yielding the same
+/// error otherwise.
+pub(crate) struct Records<'a> {
+    dir: &'a ISODirectory<IsoReader>,
+    block: BlockBuffer,
+    block_num: Option<u64>,
+    next: Option<u64>,
+}
+
+impl<'a> Records<'a> {
+    pub(crate) fn new(dir: &'a ISODirectory<IsoReader>) -> Self {
+        Self {
+            dir,
+            block: BlockBuffer::new(),
+            block_num: None,
+            next: Some(0),
+        }
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = (u64, DirectoryEntry<IsoReader>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.next.take()?;
+        let (entry, next) = self
+            .dir
+            .read_entry_at(&mut self.block, &mut self.block_num, offset)
+            .ok()?;
+        self.next = next;
+        Some((offset, entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_names() {
+        for (identifier, name) in [
+            ("README.TXT", "README.TXT"),
+            ("README.", "README"),
+            ("  LEAD", "LEAD"),
+            ("TRAIL . ", "TRAIL"),
+            ("A/B/C", "A_B_C"),
+            ("EVIL\r\n226 OK", "EVIL%0D%0A226 OK"),
+            ("TAB\tBED\0", "TAB%09BED%00"),
+            ("C1\u{85}", "C1%C2%85"),
+            ("\n", "%0A"),
+            ("100%.TXT", "100%.TXT"),
+            ("IN SIDE.TXT", "IN SIDE.TXT"),
+            (".", "."),
+            ("..", ".."),
+            (" ", " "),
+            (".hidden", ".hidden"),
+        ] {
+            assert_eq!(canonical(identifier), name, "{identifier:?}");
+        }
+        assert!(matches!(canonical("README.TXT"), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,213 @@
+//! Retrying reads that fail for reasons that are likely to go away, like a network blip.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    time::Duration,
+};
+
+/// How often and how patiently failed reads from the image are retried.
+///
+/// Only errors that are likely to be transient are retried: timeouts, interrupted or reset
+/// connections, stale NFS handles and generic I/O errors. The wait before each retry starts at
+/// the initial backoff and doubles with every attempt, up to the maximum backoff.
+///
+/// ```
+/// use std::time::Duration;
+/// use unftp_sbe_iso::RetryPolicy;
+///
+/// let policy = RetryPolicy::new(5).backoff(Duration::from_millis(50), Duration::from_secs(2));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Doesn't retry at all.
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl RetryPolicy {
+    /// Retries a failed read up to `retries` times, waiting 100ms before the first retry and at
+    /// most 5s between retries.
+    pub fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+
+    /// Sets the wait before the first retry and the maximum wait between retries.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// The number of times a failed read is retried.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+/// Tells whether a read failing with `err` is worth retrying.
+pub(crate) fn is_transient(err: &io::Error) -> bool {
+    use io::ErrorKind::*;
+
+    #[cfg(unix)]
+    const EIO: i32 = 5;
+    #[cfg(unix)]
+    if err.raw_os_error() == Some(EIO) {
+        return true;
+    }
+    matches!(
+        err.kind(),
+        Interrupted
+            | TimedOut
+            | WouldBlock
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+            | StaleNetworkFileHandle
+            | ResourceBusy
+    )
+}
+
+/// Wraps the reader the image is read from and retries failed reads according to a
+/// [`RetryPolicy`]. Before retrying, the reader is seeked back to where the failed read started.
+pub(crate) struct Retrying<R> {
+    inner: R,
+    pos: u64,
+    policy: RetryPolicy,
+}
+
+impl<R> Retrying<R> {
+    pub(crate) fn new(inner: R, policy: RetryPolicy) -> Self {
+        Self {
+            inner,
+            pos: 0,
+            policy,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for Retrying<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut retry = 0;
+        loop {
+            match self.inner.read(buf) {
+                Ok(n) => {
+                    self.pos += n as u64;
+                    return Ok(n);
+                }
+                Err(e) if retry < self.policy.retries && is_transient(&e) => {
+                    std::thread::sleep(self.policy.delay(retry));
+                    retry += 1;
+                    // A failing seek shows up in the next read attempt
+                    let _ = self.inner.seek(SeekFrom::Start(self.pos));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<R: Seek> Seek for Retrying<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Fails the given number of reads with `kind` before each successful one, and ruins the
+    /// position of the reader on failure like a half-done read would.
+    struct Flaky {
+        inner: Cursor<Vec<u8>>,
+        failures: u32,
+        failed: u32,
+        kind: io::ErrorKind,
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.failed < self.failures {
+                self.failed += 1;
+                self.inner.seek(SeekFrom::Current(1))?;
+                return Err(io::Error::new(self.kind, "flaky"));
+            }
+            self.failed = 0;
+            let n = buf.len().min(3);
+            self.inner.read(&mut buf[..n])
+        }
+    }
+
+    impl Seek for Flaky {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn flaky(failures: u32, kind: io::ErrorKind) -> Flaky {
+        Flaky {
+            inner: Cursor::new((0..100).collect()),
+            failures,
+            failed: 0,
+            kind,
+        }
+    }
+
+    fn quick(retries: u32) -> RetryPolicy {
+        RetryPolicy::new(retries).backoff(Duration::from_millis(1), Duration::from_millis(2))
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let mut reader = Retrying::new(flaky(2, io::ErrorKind::TimedOut), quick(2));
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, (10..100).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn gives_up() {
+        let mut reader = Retrying::new(flaky(3, io::ErrorKind::TimedOut), quick(2));
+        let err = reader.read(&mut [0; 10]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn permanent_errors_are_not_retried() {
+        let mut reader = Retrying::new(flaky(1, io::ErrorKind::InvalidData), quick(5));
+        let err = reader.read(&mut [0; 10]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy =
+            RetryPolicy::new(10).backoff(Duration::from_millis(100), Duration::from_millis(500));
+        let delays: Vec<_> = (0..5)
+            .map(|retry| policy.delay(retry).as_millis())
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 500, 500]);
+        assert_eq!(RetryPolicy::default().retries(), 0);
+    }
+}
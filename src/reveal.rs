@@ -0,0 +1,85 @@
+//! Showing the entries an image flags hidden to some users only, for
+//! [`StorageBuilder::hidden_entries`](crate::StorageBuilder::hidden_entries).
+
+use crate::{
+    Storage, StorageBuilder,
+    source::{Origin, SharedFile},
+    stats::StatsRegistry,
+};
+use std::{
+    fmt,
+    sync::{Arc, OnceLock},
+};
+use unftp_core::auth::UserDetail;
+
+/// Tells whether a user sees the entries flagged hidden.
+pub(crate) type Policy = dyn Fn(&dyn UserDetail) -> bool + Send + Sync;
+
+/// The [`Policy`] of a builder.
+#[derive(Clone)]
+pub(crate) struct RevealPolicy(pub(crate) Arc<Policy>);
+
+impl fmt::Debug for RevealPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RevealPolicy")
+    }
+}
+
+/// The back-end that shows the entries the image flags hidden, built like the one that hides
+/// them the first time a user the policy lets see them comes along. It has caches of its own, as
+/// its listings and indexes differ, and an image file of its own, so that it notices the image
+/// being replaced by itself.
+pub(crate) struct Revealed {
+    policy: RevealPolicy,
+    prototype: StorageBuilder,
+    stats: Arc<StatsRegistry>,
+    storage: OnceLock<Storage>,
+}
+
+impl Revealed {
+    /// `prototype` builds the back-end that hides the entries, `stats` are its statistics.
+    pub(crate) fn new(
+        policy: RevealPolicy,
+        mut prototype: StorageBuilder,
+        stats: Arc<StatsRegistry>,
+    ) -> Self {
+        prototype.hidden = None;
+        prototype.paths.flagged = true;
+        if let Origin::Path(file) = &prototype.origin {
+            prototype.origin = Origin::Path(Arc::new(SharedFile::new(file.path().to_path_buf())));
+        }
+        Self {
+            policy,
+            prototype,
+            stats,
+            storage: OnceLock::new(),
+        }
+    }
+
+    /// The back-end that shows `user` the entries flagged hidden, if the policy lets them see
+    /// them.
+    pub(crate) fn storage(&self, user: &dyn UserDetail) -> Option<Storage> {
+        if !(self.policy.0)(user) {
+            return None;
+        }
+        let storage = self
+            .storage
+            .get_or_init(|| self.prototype.clone().build_sharing(self.stats.clone()));
+        Some(storage.clone())
+    }
+
+    /// Drops the caches of the back-end that shows the entries, if it was built.
+    pub(crate) fn clear(&self) {
+        if let Some(storage) = self.storage.get() {
+            storage.inner.caches.clear();
+        }
+    }
+}
+
+impl fmt::Debug for Revealed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Revealed")
+            .field("built", &self.storage.get().is_some())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,61 @@
+//! Read sessions, which keep where the contents of a downloaded file lie so that resuming the
+//! download doesn't open the image and resolve the path again. Clients like FileZilla abort and
+//! resume the same file over and over.
+
+use crate::{
+    pool::IoPool,
+    retry::{RetryPolicy, Retrying},
+    slow::SlowLog,
+    source::{IsoSource, SourceReader},
+    stream::{self, Streamed},
+};
+use std::{
+    fmt,
+    io::{self, Seek, SeekFrom},
+    sync::Arc,
+    time::Duration,
+};
+
+/// The number of read sessions kept.
+pub(crate) const SESSIONS_KEPT: usize = 256;
+
+/// How long a read session is kept after the download that opened it.
+pub(crate) const SESSION_TTL: Duration = Duration::from_secs(300);
+
+/// Where the contents of a file that passed every check lie: `len` bytes at `offset` of
+/// `source`, which is the image itself or, for interleaved files, their bytes in order.
+pub(crate) struct ReadSession {
+    pub(crate) source: Arc<dyn IsoSource>,
+    pub(crate) offset: u64,
+    pub(crate) len: u64,
+}
+
+impl fmt::Debug for ReadSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadSession")
+            .field("offset", &self.offset)
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ReadSession {
+    /// Streams the contents from `start` to the end, retrying failed reads like cdfs's do and
+    /// giving up on chunks that take longer than `timeout` and logging those slower than `slow`,
+    /// as reads of `what`. Chunks are read on `pool`, or on
+    /// tokio's blocking thread pool without one.
+    pub(crate) fn stream(
+        &self,
+        start: u64,
+        retry: RetryPolicy,
+        timeout: Option<Duration>,
+        pool: Option<IoPool>,
+        slow: SlowLog,
+        what: String,
+    ) -> io::Result<Streamed> {
+        let start = start.min(self.len);
+        let mut reader = Retrying::new(SourceReader::new(self.source.clone()), retry);
+        reader.seek(SeekFrom::Start(self.offset + start))?;
+        stream::stream(reader, self.len - start, timeout, pool, slow, what)
+    }
+}
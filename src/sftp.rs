@@ -0,0 +1,418 @@
+//! A read-only SFTP subsystem over the same [`Storage`], for serving the tree of the image to
+//! SFTP clients as well as FTP ones.
+//!
+//! The protocol is spoken by russh-sftp, version 3 of it, the one OpenSSH and nearly every client
+//! speak, over the stream of the channel an SSH server opens for the `sftp` subsystem. The SSH
+//! side is left to the server: russh hands the subsystem a channel stream, and an OpenSSH
+//! `Subsystem` command its standard input and output.
+
+use crate::Storage;
+use russh_sftp::{
+    protocol::{Attrs, Data, File, FileAttributes, Handle, Name, OpenFlags, Status, StatusCode},
+    server::{Handler, StatusReply},
+};
+use std::{collections::HashMap, sync::Arc, time::UNIX_EPOCH};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite},
+    sync::oneshot,
+};
+use unftp_core::{
+    auth::{DefaultUser, UserDetail},
+    storage::{Error, ErrorKind, Metadata, StorageBackend},
+};
+
+/// The most bytes returned by one read.
+const MAX_READ: u32 = 64 * 1024;
+/// The most entries returned by one read of a directory.
+const ENTRIES_PER_READ: usize = 128;
+/// The most handles a client may hold open at once.
+const MAX_HANDLES: usize = 256;
+
+/// Serves a [`Storage`] to an SFTP client, read-only: files can be opened for reading, and
+/// directories listed, but requests that would change anything are denied. Everything is read as
+/// one user, the [`DefaultUser`] unless [another](Self::for_user) is given, typically the one the
+/// SSH server authenticated.
+///
+/// ```no_run
+/// use unftp_sbe_iso::{SftpSubsystem, Storage};
+///
+/// # async fn run() {
+/// // Run as the `Subsystem sftp` command of an SSH server
+/// let storage = Storage::new("/path/to/your/image.iso");
+/// let channel = tokio::io::join(tokio::io::stdin(), tokio::io::stdout());
+/// SftpSubsystem::new(storage).serve(channel).await
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SftpSubsystem<User = DefaultUser> {
+    storage: Storage,
+    user: Arc<User>,
+}
+
+impl<User> Clone for SftpSubsystem<User> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            user: self.user.clone(),
+        }
+    }
+}
+
+impl SftpSubsystem {
+    /// Serves `storage` as the [`DefaultUser`].
+    pub fn new(storage: Storage) -> Self {
+        Self::for_user(storage, DefaultUser {})
+    }
+}
+
+impl<User> SftpSubsystem<User> {
+    /// Serves `storage` as `user`.
+    pub fn for_user(storage: Storage, user: User) -> Self {
+        Self {
+            storage,
+            user: Arc::new(user),
+        }
+    }
+}
+
+impl<User: UserDetail + 'static> SftpSubsystem<User> {
+    /// Serves the requests read from `stream`, the channel of the subsystem, writing the
+    /// responses to it, until the client closes it. Failures of the channel are logged by
+    /// russh-sftp.
+    pub async fn serve<S>(&self, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (done, ended) = oneshot::channel();
+        let session = Session {
+            subsystem: self.clone(),
+            handles: HashMap::new(),
+            next_handle: 0,
+            _done: done,
+        };
+        russh_sftp::server::run(stream, session).await;
+        // russh-sftp serves on a task of its own, which drops the session once the channel is
+        // closed
+        let _ = ended.await;
+    }
+}
+
+/// What a handle a client opened refers to.
+enum Opened {
+    File {
+        path: String,
+        /// The download under way and the offset it is at, continued by reads that follow on
+        reader: Option<(u64, Box<dyn AsyncRead + Send + Sync + Unpin>)>,
+    },
+    Dir {
+        path: String,
+        /// The entries not yet returned, listed on the first read
+        pending: Option<std::vec::IntoIter<File>>,
+    },
+}
+
+/// The refusal of a request that would change the file system.
+fn read_only() -> StatusReply {
+    StatusCode::PermissionDenied.with_message("The file system is read-only")
+}
+
+/// The refusal of a request for a handle that isn't open.
+fn no_handle() -> StatusReply {
+    StatusCode::Failure.with_message("No such handle")
+}
+
+/// The status for a failed operation of the storage.
+fn failed(e: &Error) -> StatusReply {
+    match e.kind() {
+        ErrorKind::PermanentFileNotAvailable | ErrorKind::PermanentDirectoryNotAvailable => {
+            StatusCode::NoSuchFile.into()
+        }
+        _ => StatusCode::Failure.with_message(e.to_string()),
+    }
+}
+
+/// The attributes of an entry with `meta`.
+fn attrs(meta: &impl Metadata) -> FileAttributes {
+    let mode = if meta.is_dir() { 0o040_555 } else { 0o100_444 };
+    let secs = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    // As the protocol's 32 bits carry them
+    let mtime = u32::try_from(secs).unwrap_or(u32::MAX);
+    FileAttributes {
+        size: Some(meta.len()),
+        permissions: Some(mode),
+        atime: Some(mtime),
+        mtime: Some(mtime),
+        ..FileAttributes::empty()
+    }
+}
+
+/// Resolves `path` to an absolute path without `.` and `..`, as REALPATH does.
+fn real_path(path: &str) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                names.pop();
+            }
+            name => names.push(name),
+        }
+    }
+    format!("/{}", names.join("/"))
+}
+
+/// The state of one session with a client, which russh-sftp hands the requests to.
+struct Session<User> {
+    subsystem: SftpSubsystem<User>,
+    handles: HashMap<String, Opened>,
+    next_handle: u64,
+    /// Dropped with the session, which tells `serve` the session is over
+    _done: oneshot::Sender<()>,
+}
+
+impl<User: UserDetail + 'static> Session<User> {
+    /// Registers `opened` under a new handle, answering with it.
+    fn handle(&mut self, id: u32, opened: Opened) -> Result<Handle, StatusReply> {
+        if self.handles.len() >= MAX_HANDLES {
+            return Err(StatusCode::Failure.with_message("Too many open handles"));
+        }
+        let handle = self.next_handle.to_string();
+        self.next_handle += 1;
+        self.handles.insert(handle.clone(), opened);
+        Ok(Handle { id, handle })
+    }
+
+    async fn attrs(&self, id: u32, path: &str) -> Result<Attrs, StatusReply> {
+        let sftp = &self.subsystem;
+        match sftp.storage.metadata(&*sftp.user, path).await {
+            Ok(meta) => Ok(Attrs {
+                id,
+                attrs: attrs(&meta),
+            }),
+            Err(e) => Err(failed(&e)),
+        }
+    }
+}
+
+impl<User: UserDetail + 'static> Handler for Session<User> {
+    type Error = StatusReply;
+
+    fn unimplemented(&self) -> StatusReply {
+        StatusCode::OpUnsupported.into()
+    }
+
+    async fn open(
+        &mut self,
+        id: u32,
+        path: String,
+        flags: OpenFlags,
+        _: FileAttributes,
+    ) -> Result<Handle, StatusReply> {
+        if flags.intersects(
+            OpenFlags::WRITE
+                | OpenFlags::APPEND
+                | OpenFlags::CREATE
+                | OpenFlags::TRUNCATE
+                | OpenFlags::EXCLUDE,
+        ) {
+            return Err(read_only());
+        }
+        let sftp = &self.subsystem;
+        match sftp.storage.metadata(&*sftp.user, &path).await {
+            Ok(meta) if meta.is_dir() => Err(StatusCode::Failure.with_message("Is a directory")),
+            Ok(_) => self.handle(id, Opened::File { path, reader: None }),
+            Err(e) => Err(failed(&e)),
+        }
+    }
+
+    async fn close(&mut self, id: u32, handle: String) -> Result<Status, StatusReply> {
+        match self.handles.remove(&handle) {
+            Some(_) => Ok(Status {
+                id,
+                status_code: StatusCode::Ok,
+                error_message: "Closed".into(),
+                language_tag: "en-US".into(),
+            }),
+            None => Err(no_handle()),
+        }
+    }
+
+    async fn read(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, StatusReply> {
+        let sftp = &self.subsystem;
+        let Some(Opened::File { path, reader }) = self.handles.get_mut(&handle) else {
+            return Err(no_handle());
+        };
+        // Reads that don't follow on from the last start a download of their own
+        let (at, mut download) = match reader.take() {
+            Some((at, download)) if at == offset => (at, download),
+            _ => match sftp.storage.get(&*sftp.user, &*path, offset).await {
+                Ok(download) => (offset, download),
+                // Like a read past the end of a file
+                Err(e) if e.kind() == ErrorKind::PermanentFileNotAvailable => {
+                    return Err(StatusCode::Eof.into());
+                }
+                Err(e) => return Err(failed(&e)),
+            },
+        };
+        let len = len.min(MAX_READ);
+        let mut data = Vec::with_capacity(len as usize);
+        match (&mut download)
+            .take(len.into())
+            .read_to_end(&mut data)
+            .await
+        {
+            Ok(_) if data.is_empty() => Err(StatusCode::Eof.into()),
+            Ok(_) => {
+                *reader = Some((at + data.len() as u64, download));
+                Ok(Data { id, data })
+            }
+            Err(e) => Err(StatusCode::Failure.with_message(e.to_string())),
+        }
+    }
+
+    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, StatusReply> {
+        self.attrs(id, &path).await
+    }
+
+    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, StatusReply> {
+        self.attrs(id, &path).await
+    }
+
+    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, StatusReply> {
+        match self.handles.get(&handle) {
+            Some(Opened::File { path, .. } | Opened::Dir { path, .. }) => {
+                let path = path.clone();
+                self.attrs(id, &path).await
+            }
+            None => Err(no_handle()),
+        }
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, StatusReply> {
+        let sftp = &self.subsystem;
+        match sftp.storage.metadata(&*sftp.user, &path).await {
+            Ok(meta) if meta.is_dir() => self.handle(
+                id,
+                Opened::Dir {
+                    path,
+                    pending: None,
+                },
+            ),
+            Ok(_) => Err(StatusCode::Failure.with_message("Not a directory")),
+            Err(e) => Err(failed(&e)),
+        }
+    }
+
+    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, StatusReply> {
+        let sftp = &self.subsystem;
+        let Some(Opened::Dir { path, pending }) = self.handles.get_mut(&handle) else {
+            return Err(no_handle());
+        };
+        let pending = match pending {
+            Some(pending) => pending,
+            None => {
+                let listing = match sftp.storage.list(&*sftp.user, &*path).await {
+                    Ok(listing) => listing,
+                    Err(e) => return Err(failed(&e)),
+                };
+                let files: Vec<File> = listing
+                    .into_iter()
+                    .map(|entry| {
+                        let name = entry.path.to_string_lossy().into_owned();
+                        File::new(name, attrs(&entry.metadata))
+                    })
+                    .collect();
+                pending.insert(files.into_iter())
+            }
+        };
+        let files: Vec<File> = pending.take(ENTRIES_PER_READ).collect();
+        if files.is_empty() {
+            return Err(StatusCode::Eof.into());
+        }
+        Ok(Name { id, files })
+    }
+
+    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, StatusReply> {
+        Ok(Name {
+            id,
+            files: vec![File::dummy(real_path(&path))],
+        })
+    }
+
+    async fn write(
+        &mut self,
+        _: u32,
+        _: String,
+        _: u64,
+        _: Vec<u8>,
+    ) -> Result<Status, StatusReply> {
+        Err(read_only())
+    }
+
+    async fn setstat(
+        &mut self,
+        _: u32,
+        _: String,
+        _: FileAttributes,
+    ) -> Result<Status, StatusReply> {
+        Err(read_only())
+    }
+
+    async fn fsetstat(
+        &mut self,
+        _: u32,
+        _: String,
+        _: FileAttributes,
+    ) -> Result<Status, StatusReply> {
+        Err(read_only())
+    }
+
+    async fn remove(&mut self, _: u32, _: String) -> Result<Status, StatusReply> {
+        Err(read_only())
+    }
+
+    async fn mkdir(&mut self, _: u32, _: String, _: FileAttributes) -> Result<Status, StatusReply> {
+        Err(read_only())
+    }
+
+    async fn rmdir(&mut self, _: u32, _: String) -> Result<Status, StatusReply> {
+        Err(read_only())
+    }
+
+    async fn rename(&mut self, _: u32, _: String, _: String) -> Result<Status, StatusReply> {
+        Err(read_only())
+    }
+
+    async fn symlink(&mut self, _: u32, _: String, _: String) -> Result<Status, StatusReply> {
+        Err(read_only())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn real_paths() {
+        for (path, real) in [
+            ("", "/"),
+            (".", "/"),
+            ("/", "/"),
+            ("docs", "/docs"),
+            ("/docs/../docs/./guide/", "/docs/guide"),
+            ("/../..", "/"),
+        ] {
+            assert_eq!(real_path(path), real, "{path}");
+        }
+    }
+}
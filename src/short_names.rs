@@ -0,0 +1,163 @@
+//! Mapping of names to uppercase 8.3 form for DOS-era clients.
+//!
+//! Names that already are valid 8.3 names are kept (uppercased). Others are cleaned up and
+//! shortened to `BASE~N.EXT`, where `N` is the lowest number that makes the name unique in its
+//! directory. Names are assigned in directory order, so the mapping is the same every time a
+//! directory is read.
+
+use std::collections::HashSet;
+
+/// Characters allowed in 8.3 names besides letters and digits.
+const SPECIAL: &str = "!#$%&'()-@^_`{}~";
+
+fn clean(part: &str) -> String {
+    part.chars()
+        .filter(|&c| c != ' ' && c != '.')
+        .map(|c| match c.to_ascii_uppercase() {
+            c @ ('A'..='Z' | '0'..='9') => c,
+            c if SPECIAL.contains(c) => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Splits a name into its base and extension, both cleaned up for use in an 8.3 name.
+fn split(name: &str) -> (String, String) {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => (clean(&name[..dot]), clean(&name[dot + 1..])),
+        _ => (clean(name), String::new()),
+    }
+}
+
+fn join(base: &str, ext: &str) -> String {
+    if ext.is_empty() {
+        base.to_string()
+    } else {
+        format!("{base}.{ext}")
+    }
+}
+
+/// Returns the 8.3 name for every name in `names`, which are the identifiers of the entries of a
+/// single directory in directory order.
+pub(crate) fn short_names(names: &[&str]) -> Vec<String> {
+    let mut taken = HashSet::new();
+    let mut short: Vec<Option<String>> = vec![None; names.len()];
+
+    // Names that are valid 8.3 names already go first, so they never lose their name to a
+    // shortened one.
+    for (i, name) in names.iter().enumerate() {
+        if *name == "." || *name == ".." {
+            short[i] = Some(name.to_string());
+            continue;
+        }
+        let (base, ext) = split(name);
+        let candidate = join(&base, &ext);
+        if !base.is_empty()
+            && base.len() <= 8
+            && ext.len() <= 3
+            && candidate.eq_ignore_ascii_case(name)
+            && taken.insert(candidate.clone())
+        {
+            short[i] = Some(candidate);
+        }
+    }
+
+    for (i, name) in names.iter().enumerate() {
+        if short[i].is_some() {
+            continue;
+        }
+        let (base, ext) = split(name);
+        let base = if base.is_empty() {
+            "_".to_string()
+        } else {
+            base
+        };
+        let ext: String = ext.chars().take(3).collect();
+        let candidate = (1..)
+            .map(|n| {
+                let suffix = format!("~{n}");
+                let stem: String = base.chars().take(8 - suffix.len()).collect();
+                join(&format!("{stem}{suffix}"), &ext)
+            })
+            .find(|candidate| !taken.contains(candidate))
+            .unwrap();
+        taken.insert(candidate.clone());
+        short[i] = Some(candidate);
+    }
+
+    short.into_iter().map(Option::unwrap).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::short_names;
+
+    #[test]
+    fn keeps_valid_names() {
+        assert_eq!(
+            short_names(&[".", "..", "README.TXT", "readme.md", "A", "LOGO.PNG"]),
+            [".", "..", "README.TXT", "README.MD", "A", "LOGO.PNG"]
+        );
+    }
+
+    #[test]
+    fn shortens_long_names() {
+        assert_eq!(
+            short_names(&[
+                "Long File Name.html",
+                "archive.tar.gz",
+                ".hidden",
+                "caf\u{e9}.txt"
+            ]),
+            ["LONGFI~1.HTM", "ARCHIV~1.GZ", "HIDDEN~1", "CAF_~1.TXT"]
+        );
+    }
+
+    #[test]
+    fn resolves_collisions() {
+        assert_eq!(
+            short_names(&[
+                "averylongname1.txt",
+                "averylongname2.txt",
+                "AVERYL~1.TXT",
+                "Readme.txt",
+                "README.TXT",
+            ]),
+            [
+                "AVERYL~2.TXT",
+                "AVERYL~3.TXT",
+                "AVERYL~1.TXT",
+                "README.TXT",
+                "README~1.TXT"
+            ]
+        );
+    }
+
+    #[test]
+    fn is_deterministic() {
+        let names = [
+            "one long name.txt",
+            "one long name.doc",
+            "one long name.txt2",
+        ];
+        assert_eq!(short_names(&names), short_names(&names));
+        assert_eq!(
+            short_names(&names),
+            ["ONELON~1.TXT", "ONELON~1.DOC", "ONELON~2.TXT"]
+        );
+    }
+
+    #[test]
+    fn many_collisions() {
+        let names: Vec<String> = (0..120).map(|i| format!("collision {i}.txt")).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let short = short_names(&names);
+        assert_eq!(short[8], "COLLIS~9.TXT");
+        assert_eq!(short[9], "COLLI~10.TXT");
+        assert_eq!(short[99], "COLL~100.TXT");
+        assert_eq!(
+            short.iter().collect::<std::collections::HashSet<_>>().len(),
+            120
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! The digest or signature an image file must match to be served, as set with
+//! [`StorageBuilder::image_signature`](crate::StorageBuilder::image_signature).
+
+use crate::{
+    checksums::{decode_hex, parse_line},
+    hash::HashAlgorithm,
+};
+use ring::signature::{ED25519, UnparsedPublicKey};
+use std::{fmt, fs, io, path::Path};
+
+/// The DER encoding of an Ed25519 public key, as `openssl pkey -pubout -outform DER` writes it,
+/// is this followed by the key itself.
+const ED25519_DER_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// What proves an image file is the one meant to be served: its SHA-256 digest, or a checksum
+/// list naming it that was signed with Ed25519, like the `SHA256SUMS` and detached signature
+/// distributions publish next to their images. Checked with
+/// [`StorageBuilder::image_signature`](crate::StorageBuilder::image_signature).
+///
+/// OpenPGP signatures aren't understood; verify those with `gpg --verify` when the checksum list
+/// is published, and give the digest it vouches for as [`sha256`](Self::sha256).
+#[derive(Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImageSignature {
+    /// The SHA-256 digest of the image file
+    Sha256([u8; 32]),
+    /// A checksum list in the format of `sha256sum` or BSD's `sha256`, signed with Ed25519
+    Ed25519 {
+        /// The public key of the signer
+        public_key: [u8; 32],
+        /// The checksum list that was signed, as it was signed
+        list: Vec<u8>,
+        /// The detached signature of the list
+        signature: [u8; 64],
+    },
+}
+
+impl ImageSignature {
+    /// The SHA-256 digest of the image file in hex, as `sha256sum` prints it, or `None` if
+    /// `hex` isn't one.
+    pub fn sha256(hex: &str) -> Option<Self> {
+        let digest = decode_hex(hex.trim())?;
+        Some(ImageSignature::Sha256(digest.try_into().ok()?))
+    }
+
+    /// A checksum list signed with Ed25519, read from the files at `list` and `signature`,
+    /// checked with the public key at `public_key`. The signature is the raw 64 bytes, as
+    /// `openssl pkeyutl -sign -rawin` writes them, and the key is the raw 32 bytes or their DER
+    /// encoding.
+    pub fn ed25519_files<P, Q, R>(public_key: P, list: Q, signature: R) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        R: AsRef<Path>,
+    {
+        let key = fs::read(public_key)?;
+        let key = key.strip_prefix(&ED25519_DER_PREFIX[..]).unwrap_or(&key);
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+        Ok(ImageSignature::Ed25519 {
+            public_key: key
+                .try_into()
+                .map_err(|_| invalid("not an Ed25519 public key"))?,
+            list: fs::read(list)?,
+            signature: fs::read(signature)?
+                .try_into()
+                .map_err(|_| invalid("not an Ed25519 signature"))?,
+        })
+    }
+
+    /// The SHA-256 digest the image file named `name` must have, or why none can be trusted.
+    /// Without a name, the list must list a single file.
+    pub(crate) fn expected(&self, name: Option<&str>) -> Result<Vec<u8>, String> {
+        let (public_key, list, signature) = match self {
+            ImageSignature::Sha256(digest) => return Ok(digest.to_vec()),
+            ImageSignature::Ed25519 {
+                public_key,
+                list,
+                signature,
+            } => (public_key, list, signature),
+        };
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(list, signature)
+            .map_err(|_| "the checksum list doesn't match its signature".to_string())?;
+        let text =
+            std::str::from_utf8(list).map_err(|_| "the checksum list is not text".to_string())?;
+        let listed: Vec<(&str, Vec<u8>)> = text
+            .lines()
+            .filter_map(|line| parse_line(line, HashAlgorithm::Sha256))
+            .filter_map(|(path, hex)| Some((path, decode_hex(hex).filter(|d| d.len() == 32)?)))
+            .collect();
+        let found = match name {
+            Some(name) => listed
+                .into_iter()
+                .find(|(path, _)| path.rsplit('/').next() == Some(name)),
+            None if listed.len() == 1 => listed.into_iter().next(),
+            None => None,
+        };
+        match (found, name) {
+            (Some((_, digest)), _) => Ok(digest),
+            (None, Some(name)) => Err(format!("the signed checksum list doesn't list {name}")),
+            (None, None) => Err("the signed checksum list doesn't list a single image".into()),
+        }
+    }
+}
+
+impl fmt::Debug for ImageSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+        match self {
+            ImageSignature::Sha256(digest) => f.debug_tuple("Sha256").field(&hex(digest)).finish(),
+            ImageSignature::Ed25519 { public_key, .. } => f
+                .debug_struct("Ed25519")
+                .field("public_key", &hex(public_key))
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    const DIGEST: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn signed(list: &str) -> ImageSignature {
+        let pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        ImageSignature::Ed25519 {
+            public_key: pair.public_key().as_ref().try_into().unwrap(),
+            list: list.as_bytes().to_vec(),
+            signature: pair.sign(list.as_bytes()).as_ref().try_into().unwrap(),
+        }
+    }
+
+    #[test]
+    fn digests() {
+        let expected = ImageSignature::sha256(DIGEST).unwrap();
+        assert_eq!(
+            expected.expected(None).unwrap(),
+            decode_hex(DIGEST).unwrap()
+        );
+        assert!(ImageSignature::sha256("2cf24dba").is_none());
+        assert!(ImageSignature::sha256("not hex").is_none());
+    }
+
+    #[test]
+    fn signed_lists() {
+        let list = format!(
+            "{DIGEST}  ./netinst.iso\nSHA256 (dvd.iso) = {}\n",
+            "00".repeat(32)
+        );
+        let signature = signed(&list);
+        assert_eq!(
+            signature.expected(Some("netinst.iso")).unwrap(),
+            decode_hex(DIGEST).unwrap()
+        );
+        assert_eq!(signature.expected(Some("dvd.iso")).unwrap(), [0; 32]);
+        assert!(signature.expected(Some("other.iso")).is_err());
+        // Which of them is meant is unknown
+        assert!(signature.expected(None).is_err());
+        assert!(
+            signed(&format!("{DIGEST} *only.iso\n"))
+                .expected(None)
+                .is_ok()
+        );
+
+        let ImageSignature::Ed25519 {
+            public_key,
+            signature,
+            ..
+        } = signature
+        else {
+            unreachable!()
+        };
+        let forged = ImageSignature::Ed25519 {
+            public_key,
+            list: list.replace("netinst", "netinsT").into_bytes(),
+            signature,
+        };
+        assert!(forged.expected(Some("netinsT.iso")).is_err());
+    }
+}
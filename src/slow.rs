@@ -0,0 +1,31 @@
+//! Logging operations that take longer than the threshold set with
+//! [`StorageBuilder::log_slow_operations`](crate::StorageBuilder::log_slow_operations).
+
+use std::time::{Duration, Instant};
+
+/// The threshold past which operations are logged, if they are.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SlowLog(pub(crate) Option<Duration>);
+
+impl SlowLog {
+    /// Starts timing an operation.
+    pub(crate) fn start(self) -> Timer {
+        Timer(self.0.map(|threshold| (threshold, Instant::now())))
+    }
+}
+
+/// The timing of an operation, which [`finish`](Self::finish) logs if it was slow.
+pub(crate) struct Timer(Option<(Duration, Instant)>);
+
+impl Timer {
+    /// Logs the operation `what` describes as a warning if it took longer than the threshold.
+    /// `what` is only called then.
+    pub(crate) fn finish(self, what: impl FnOnce() -> String) {
+        if let Some((threshold, started)) = self.0 {
+            let took = started.elapsed();
+            if took > threshold {
+                log::warn!("slow {}: took {} ms", what(), took.as_millis());
+            }
+        }
+    }
+}
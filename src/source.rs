@@ -0,0 +1,517 @@
+//! Where the bytes of an image come from.
+
+use crate::cache::{BLOCK_SIZE, Cache, EvictionPolicy};
+use async_trait::async_trait;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::SystemTime,
+};
+
+/// A source of the bytes of an ISO image, read at arbitrary positions.
+///
+/// Implemented for [`File`], for images held in memory as a `Vec<u8>` and for [`Arc`]s of other
+/// sources. Implement it to serve images from block devices, memory maps or remote storage, and
+/// pass the source to [`Storage::from_source`](crate::Storage::from_source).
+pub trait IsoSource: Send + Sync {
+    /// Reads bytes starting at `offset` into `buf`, returning how many were read. Like
+    /// [`Read::read`], fewer bytes than requested may be read, and 0 means `offset` is at or
+    /// beyond the end of the image.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// The size of the image in bytes.
+    fn len(&self) -> io::Result<u64>;
+
+    /// Tells whether the image has no bytes at all.
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+impl IsoSource for File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::FileExt::read_at(self, buf, offset)
+        }
+        #[cfg(windows)]
+        {
+            std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+        }
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl IsoSource for Vec<u8> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(self.len());
+        let n = buf.len().min(self.len() - start);
+        buf[..n].copy_from_slice(&self[start..start + n]);
+        Ok(n)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(Vec::len(self) as u64)
+    }
+}
+
+impl<S: IsoSource + ?Sized> IsoSource for Arc<S> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        (**self).len()
+    }
+}
+
+/// A source of the bytes of an ISO image that is read asynchronously, for remote storage like
+/// HTTP servers or object stores.
+///
+/// cdfs reads images synchronously, so reads are bridged by blocking a thread of tokio's
+/// blocking pool on them. Ranges are fetched in blocks of 64 KiB and the most recently used 4 MiB
+/// are kept in memory, so that the many small reads of directory records don't each turn into a
+/// request. Pass the source to [`Storage::from_async_source`](crate::Storage::from_async_source).
+#[async_trait]
+pub trait AsyncIsoSource: Send + Sync {
+    /// Reads `len` bytes starting at `offset`. Fewer bytes may be returned at the end of the image.
+    async fn read_range(&self, offset: u64, len: usize) -> io::Result<Vec<u8>>;
+
+    /// The size of the image in bytes.
+    async fn len(&self) -> io::Result<u64>;
+
+    /// Tells whether the image has no bytes at all.
+    async fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+}
+
+/// The number of bytes of an [`AsyncIsoSource`] kept in memory.
+const ASYNC_BYTES_KEPT: usize = 4 * 1024 * 1024;
+
+/// Bridges an [`AsyncIsoSource`] into an [`IsoSource`], buffering the blocks read most recently.
+/// Must be read from a thread that may block and that runs within a tokio runtime, such as one of
+/// tokio's blocking pool.
+pub(crate) struct Buffered<S> {
+    source: S,
+    len: OnceLock<u64>,
+    blocks: Cache<u64, Arc<[u8]>>,
+}
+
+impl<S: AsyncIsoSource> Buffered<S> {
+    pub(crate) fn new(source: S) -> Self {
+        Self {
+            source,
+            len: OnceLock::new(),
+            blocks: Cache::new(ASYNC_BYTES_KEPT, EvictionPolicy::Lru),
+        }
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> io::Result<F::Output> {
+        let handle = tokio::runtime::Handle::try_current().map_err(|_| {
+            io::Error::other("asynchronous sources can only be read within a tokio runtime")
+        })?;
+        Ok(handle.block_on(future))
+    }
+
+    fn block(&self, index: u64) -> io::Result<Arc<[u8]>> {
+        if let Some(block) = self.blocks.get(&index) {
+            return Ok(block);
+        }
+        let data: Arc<[u8]> = self
+            .block_on(
+                self.source
+                    .read_range(index * BLOCK_SIZE, BLOCK_SIZE as usize),
+            )??
+            .into();
+        self.blocks.insert(index, data.clone(), data.len());
+        Ok(data)
+    }
+}
+
+impl<S: AsyncIsoSource> IsoSource for Buffered<S> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        // Reads of several blocks, like the coalesced reads of the block cache, are requests of
+        // their own
+        if buf.len() > BLOCK_SIZE as usize {
+            let data = self.block_on(self.source.read_range(offset, buf.len()))??;
+            let n = data.len().min(buf.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            return Ok(n);
+        }
+        let block = self.block(offset / BLOCK_SIZE)?;
+        let start = ((offset % BLOCK_SIZE) as usize).min(block.len());
+        let n = buf.len().min(block.len() - start);
+        buf[..n].copy_from_slice(&block[start..start + n]);
+        Ok(n)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        if let Some(len) = self.len.get() {
+            return Ok(*len);
+        }
+        let len = self.block_on(self.source.len())??;
+        Ok(*self.len.get_or_init(|| len))
+    }
+}
+
+/// An image file opened once and shared by all clones of a [`Storage`](crate::Storage).
+///
+/// Downloads under way hold on to the file they started on, so they finish even if the file is
+/// replaced or removed. A file that is removed is served on until another one takes its path,
+/// which the next operation switches over to.
+pub(crate) struct SharedFile {
+    path: PathBuf,
+    open: Mutex<Option<Opened>>,
+    /// Set while a watcher reports changes, which makes checking before every operation moot.
+    watched: AtomicBool,
+    /// Set by the watcher when the path changed, for the next operation to check it.
+    #[cfg(feature = "watch")]
+    recheck: AtomicBool,
+}
+
+/// The file open at the moment.
+struct Opened {
+    identity: Identity,
+    file: Arc<File>,
+    /// Whether the path was found removed, which is only logged once
+    removed: bool,
+}
+
+/// What tells a file apart from the one replacing it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Identity {
+    len: u64,
+    modified: Option<SystemTime>,
+    #[cfg(unix)]
+    inode: (u64, u64),
+}
+
+impl Identity {
+    fn of(metadata: &std::fs::Metadata) -> Self {
+        Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            #[cfg(unix)]
+            inode: {
+                use std::os::unix::fs::MetadataExt;
+                (metadata.dev(), metadata.ino())
+            },
+        }
+    }
+
+    /// The identity as bytes, for keeping it on disk.
+    #[cfg(feature = "catalog")]
+    fn to_bytes(self) -> Vec<u8> {
+        let modified = self
+            .modified
+            .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos());
+        let mut bytes = self.len.to_le_bytes().to_vec();
+        bytes.extend(modified.to_le_bytes());
+        #[cfg(unix)]
+        {
+            bytes.extend(self.inode.0.to_le_bytes());
+            bytes.extend(self.inode.1.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+impl SharedFile {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            open: Mutex::new(None),
+            watched: AtomicBool::new(false),
+            #[cfg(feature = "watch")]
+            recheck: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    /// What tells the file open at the moment apart from any other at the path, before or
+    /// after: the path with the size, modification time and inode of the file.
+    #[cfg(feature = "catalog")]
+    pub(crate) fn stamp(&self) -> Option<Vec<u8>> {
+        let open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        let identity = open.as_ref()?.identity;
+        let mut stamp = self.path.as_os_str().as_encoded_bytes().to_vec();
+        stamp.push(0);
+        stamp.extend(identity.to_bytes());
+        Some(stamp)
+    }
+
+    #[cfg(feature = "watch")]
+    pub(crate) fn set_watched(&self, watched: bool) {
+        self.watched.store(watched, Ordering::Relaxed);
+    }
+
+    /// Makes the next operation check whether the file at the path changed, despite the watch.
+    #[cfg(feature = "watch")]
+    pub(crate) fn recheck(&self) {
+        self.recheck.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the file open is known to be the one at the path.
+    fn current(&self) -> bool {
+        #[cfg(feature = "watch")]
+        if self.recheck.swap(false, Ordering::Relaxed) {
+            return false;
+        }
+        self.watched.load(Ordering::Relaxed)
+    }
+
+    /// Returns the open file, switching over to the file at the path if it was modified or
+    /// replaced since the open one was opened. Tells whether it switched. Keeps returning the
+    /// open file while the path is removed.
+    fn open(&self) -> io::Result<(Arc<File>, bool)> {
+        if self.current()
+            && let Some(opened) = &*self.open.lock().unwrap_or_else(|e| e.into_inner())
+        {
+            return Ok((opened.file.clone(), false));
+        }
+        let found = std::fs::metadata(&self.path).map(|m| Identity::of(&m));
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        let found = match (found, &mut *open) {
+            (Ok(identity), Some(opened)) if opened.identity == identity => {
+                opened.removed = false;
+                return Ok((opened.file.clone(), false));
+            }
+            (Ok(_), _) => File::open(&self.path),
+            (Err(e), _) => Err(e),
+        };
+        match (found, &mut *open) {
+            (Ok(file), _) => {
+                // The identity of the file opened, in case the path changed since it was looked at
+                let identity = Identity::of(&file.metadata()?);
+                let file = Arc::new(file);
+                let changed = open.is_some();
+                if changed {
+                    log::info!("switching over to the new {:?}", self.path);
+                }
+                *open = Some(Opened {
+                    identity,
+                    file: file.clone(),
+                    removed: false,
+                });
+                Ok((file, changed))
+            }
+            (Err(e), Some(opened)) if e.kind() == io::ErrorKind::NotFound => {
+                if !opened.removed {
+                    opened.removed = true;
+                    log::warn!(
+                        "{:?} was removed; serving the file it was until it is replaced",
+                        self.path
+                    );
+                }
+                Ok((opened.file.clone(), false))
+            }
+            (Err(e), _) => Err(e),
+        }
+    }
+}
+
+/// Where a [`Storage`](crate::Storage) gets its image from.
+#[derive(Clone)]
+pub(crate) enum Origin {
+    /// A file that is checked for modification and replacement before every operation.
+    Path(Arc<SharedFile>),
+    Source(Arc<dyn IsoSource>),
+    /// An [`AsyncIsoSource`] wrapped in [`Buffered`].
+    Async(Arc<dyn IsoSource>),
+    /// A path template that names a different image for every user. Operations run on the
+    /// back-end of the user, so there is no image to open without one.
+    Templated(Arc<str>),
+    /// A directory whose images are served by back-ends of their own.
+    Directory(PathBuf),
+}
+
+impl Origin {
+    /// Returns the source to read the image from, and whether the image changed since the last
+    /// time, in which case anything cached is stale.
+    pub(crate) fn open(&self) -> io::Result<(Arc<dyn IsoSource>, bool)> {
+        match self {
+            Origin::Path(file) => {
+                let (file, changed) = file.open()?;
+                Ok((file, changed))
+            }
+            Origin::Source(source) | Origin::Async(source) => Ok((source.clone(), false)),
+            Origin::Templated(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the image depends on the user",
+            )),
+            Origin::Directory(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "a directory of images has no image of its own",
+            )),
+        }
+    }
+
+    /// What tells the image file open at the moment apart from the others at its path, if the
+    /// image is a file.
+    #[cfg(feature = "catalog")]
+    pub(crate) fn stamp(&self) -> Option<Vec<u8>> {
+        match self {
+            Origin::Path(file) => file.stamp(),
+            _ => None,
+        }
+    }
+
+    /// Tells whether the image must be read on a thread that may block.
+    pub(crate) fn blocks(&self) -> bool {
+        matches!(self, Origin::Async(_))
+    }
+}
+
+impl fmt::Debug for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Path(file) => file.path.fmt(f),
+            Origin::Source(_) => f.write_str("custom source"),
+            Origin::Async(_) => f.write_str("custom asynchronous source"),
+            Origin::Templated(template) => template.fmt(f),
+            Origin::Directory(dir) => dir.fmt(f),
+        }
+    }
+}
+
+/// Reads a source sequentially, which is what cdfs expects.
+pub(crate) struct SourceReader {
+    source: Arc<dyn IsoSource>,
+    pos: u64,
+}
+
+impl SourceReader {
+    pub(crate) fn new(source: Arc<dyn IsoSource>) -> Self {
+        Self { source, pos: 0 }
+    }
+}
+
+impl Read for SourceReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.source.read_at(self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for SourceReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let base = match pos {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => self.source.len()?.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = base.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_source() {
+        let source: Vec<u8> = (0..10).collect();
+        let mut buf = [0; 4];
+        assert_eq!(source.read_at(8, &mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], [8, 9]);
+        assert_eq!(source.read_at(10, &mut buf).unwrap(), 0);
+        assert_eq!(source.read_at(u64::MAX, &mut buf).unwrap(), 0);
+        assert_eq!(IsoSource::len(&source).unwrap(), 10);
+    }
+
+    struct Remote {
+        image: Vec<u8>,
+        requests: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl AsyncIsoSource for Remote {
+        async fn read_range(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+            self.requests
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            tokio::task::yield_now().await;
+            let start = (offset as usize).min(self.image.len());
+            let end = (start + len).min(self.image.len());
+            Ok(self.image[start..end].to_vec())
+        }
+
+        async fn len(&self) -> io::Result<u64> {
+            Ok(self.image.len() as u64)
+        }
+    }
+
+    #[tokio::test]
+    async fn buffered() {
+        let image: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let source = Arc::new(Buffered::new(Remote {
+            image: image.clone(),
+            requests: Default::default(),
+        }));
+        let reader = source.clone();
+        let read = tokio::task::spawn_blocking(move || {
+            let mut reader = SourceReader::new(reader);
+            let mut buf = Vec::new();
+            reader.seek(SeekFrom::Start(1000)).unwrap();
+            reader.read_to_end(&mut buf).unwrap();
+            reader.seek(SeekFrom::Start(70_000)).unwrap();
+            let mut again = [0; 10];
+            reader.read_exact(&mut again).unwrap();
+            (buf, again, IsoSource::len(&*reader.source).unwrap())
+        });
+        let (buf, again, len) = read.await.unwrap();
+        assert_eq!(buf, image[1000..]);
+        assert_eq!(again, image[70_000..70_010]);
+        assert_eq!(len, 200_000);
+        // Four blocks, each fetched once
+        assert_eq!(
+            source
+                .source
+                .requests
+                .load(std::sync::atomic::Ordering::Relaxed),
+            4
+        );
+    }
+
+    #[test]
+    fn buffered_outside_runtime() {
+        let source = Buffered::new(Remote {
+            image: vec![0; 10],
+            requests: Default::default(),
+        });
+        assert!(source.read_at(0, &mut [0; 4]).is_err());
+    }
+
+    #[test]
+    fn reader() {
+        let mut reader = SourceReader::new(Arc::new((0..10).collect::<Vec<u8>>()));
+        assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), 7);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, [7, 8, 9]);
+        assert_eq!(reader.seek(SeekFrom::Current(-10)).unwrap(), 0);
+        assert!(reader.seek(SeekFrom::Current(-1)).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Temporary files for the contents of files that have to be read whole before they are
+//! served, as set with [`StorageBuilder::spool_dir`](crate::StorageBuilder::spool_dir).
+
+use crate::IsoSource;
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Files of at most this many bytes are held in memory even with a spool directory.
+pub(crate) const SPOOL_ABOVE: u64 = 1024 * 1024;
+
+/// The most bytes a file served decompressed may hold once it is spooled.
+pub(crate) const MAX_SPOOLED: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Tells the temporary files of a process apart.
+static NEXT: AtomicU64 = AtomicU64::new(0);
+
+/// The contents of a file read whole.
+pub(crate) enum Materialized {
+    Memory(Vec<u8>),
+    Spooled(Spooled),
+}
+
+/// A temporary file, read back as an [`IsoSource`]. It is removed as soon as it is created
+/// where open files can be removed, so it goes away with the process, and once dropped
+/// elsewhere.
+#[derive(Debug)]
+pub(crate) struct Spooled {
+    file: File,
+    len: u64,
+    /// Dropped after the file, which might not be removable while it is open
+    _removal: Removal,
+}
+
+/// Removes a temporary file that couldn't be removed while it was open.
+#[derive(Debug)]
+struct Removal(Option<PathBuf>);
+
+impl Spooled {
+    /// Creates an empty temporary file in `dir`.
+    pub(crate) fn create(dir: &Path) -> io::Result<Self> {
+        let name = format!(
+            "unftp-sbe-iso-{}-{}.spool",
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let left = fs::remove_file(&path).err().map(|_| path);
+        Ok(Self {
+            file,
+            len: 0,
+            _removal: Removal(left),
+        })
+    }
+
+    /// The number of bytes written.
+    pub(crate) fn size(&self) -> u64 {
+        self.len
+    }
+}
+
+impl Write for Spooled {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl IsoSource for Spooled {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        IsoSource::read_at(&self.file, offset, buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+}
+
+impl Drop for Removal {
+    fn drop(&mut self) {
+        if let Some(path) = &self.0
+            && let Err(e) = fs::remove_file(path)
+        {
+            log::warn!("could not remove the temporary file {path:?}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_and_read_back() {
+        let dir = std::env::temp_dir().join(format!("unftp-sbe-iso-spool-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut spooled = Spooled::create(&dir).unwrap();
+        spooled.write_all(b"spooled contents").unwrap();
+        assert_eq!(spooled.size(), 16);
+        let mut buf = [0; 8];
+        assert_eq!(IsoSource::read_at(&spooled, 8, &mut buf).unwrap(), 8);
+        assert_eq!(&buf, b"contents");
+        assert_eq!(IsoSource::read_at(&spooled, 16, &mut buf).unwrap(), 0);
+        // Nothing is left behind
+        drop(spooled);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+//! Per-path download statistics, and counters of the downloads under way, done and aborted.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Download counters for a single path in the image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PathStats {
+    /// The number of RETRs started for this path
+    pub downloads: u64,
+    /// The number of bytes served for this path
+    pub bytes: u64,
+}
+
+/// How the downloads of a [`Storage`](crate::Storage) went, as returned by
+/// [`Storage::transfer_stats`](crate::Storage::transfer_stats), to tell how much work aborted
+/// downloads waste.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferStats {
+    /// The downloads under way, whose readers the server holds
+    pub in_flight: u64,
+    /// The downloads that were served to the end
+    pub completed: u64,
+    /// The downloads given up on before the end, because the client aborted or went away or
+    /// because the image couldn't be read
+    pub aborted: u64,
+    /// The bytes read from the image for aborted downloads that never reached the client
+    pub bytes_discarded: u64,
+}
+
+impl TransferStats {
+    fn add(&mut self, other: &TransferStats) {
+        self.in_flight += other.in_flight;
+        self.completed += other.completed;
+        self.aborted += other.aborted;
+        self.bytes_discarded += other.bytes_discarded;
+    }
+}
+
+impl std::iter::Sum for TransferStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |mut sum, stats| {
+            sum.add(&stats);
+            sum
+        })
+    }
+}
+
+/// Collects [`PathStats`] for every path that was downloaded, and the [`TransferStats`].
+/// Shared by all clones of a [`Storage`](crate::Storage).
+#[derive(Debug, Default)]
+pub(crate) struct StatsRegistry {
+    paths: Mutex<HashMap<PathBuf, PathStats>>,
+    in_flight: AtomicU64,
+    completed: AtomicU64,
+    aborted: AtomicU64,
+    bytes_discarded: AtomicU64,
+}
+
+/// A reader of what is served for a download, which tells how many bytes it read from the image
+/// so far, including those not served yet.
+pub(crate) trait Prepared {
+    fn prepared(&self) -> u64;
+}
+
+impl StatsRegistry {
+    pub(crate) fn snapshot(&self) -> HashMap<PathBuf, PathStats> {
+        self.paths.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub(crate) fn transfers(&self) -> TransferStats {
+        TransferStats {
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            aborted: self.aborted.load(Ordering::Relaxed),
+            bytes_discarded: self.bytes_discarded.load(Ordering::Relaxed),
+        }
+    }
+
+    fn update(&self, path: &Path, f: impl FnOnce(&mut PathStats)) {
+        let mut paths = self.paths.lock().unwrap_or_else(|e| e.into_inner());
+        f(paths.entry(path.to_path_buf()).or_default())
+    }
+
+    /// Registers the start of a download and returns a reader that counts the bytes served, and
+    /// whether the download was served to the end once it is dropped.
+    pub(crate) fn track<R: Prepared>(self: &Arc<Self>, path: &Path, inner: R) -> StatsReader<R> {
+        self.update(path, |s| s.downloads += 1);
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        StatsReader {
+            inner,
+            path: path.to_path_buf(),
+            registry: self.clone(),
+            served: 0,
+            done: false,
+        }
+    }
+}
+
+/// Wraps the reader handed to libunftp and adds every byte read to the path's statistics.
+pub(crate) struct StatsReader<R: Prepared> {
+    inner: R,
+    path: PathBuf,
+    registry: Arc<StatsRegistry>,
+    served: u64,
+    /// Whether the end was reached
+    done: bool,
+}
+
+impl<R: AsyncRead + Prepared + Unpin> AsyncRead for StatsReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let room = buf.remaining() > 0;
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = (buf.filled().len() - before) as u64;
+        let this = &mut *self;
+        if read > 0 {
+            this.served += read;
+            this.registry.update(&this.path, |s| s.bytes += read);
+        } else if room && matches!(result, Poll::Ready(Ok(()))) && !this.done {
+            this.done = true;
+            this.registry.completed.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl<R: Prepared> Drop for StatsReader<R> {
+    fn drop(&mut self) {
+        let registry = &self.registry;
+        registry.in_flight.fetch_sub(1, Ordering::Relaxed);
+        if !self.done {
+            registry.aborted.fetch_add(1, Ordering::Relaxed);
+            let discarded = self.inner.prepared().saturating_sub(self.served);
+            registry
+                .bytes_discarded
+                .fetch_add(discarded, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Prepared for std::io::Cursor<Vec<u8>> {
+    fn prepared(&self) -> u64 {
+        self.get_ref().len() as u64
+    }
+}
@@ -0,0 +1,309 @@
+//! Streaming the contents of a file to the data connection. A task reads the file a chunk at a
+//! time into a bounded channel, which the reader handed to the server drains, so a slow client
+//! holds the reads of the image back instead of the file piling up in memory. Each chunk is read
+//! on the blocking thread pool, or the [`IoPool`], only once there is room for it, so that
+//! waiting on the client doesn't take up a thread. Dropping the reader, as the server does when
+//! the client aborts, stops the task.
+
+use crate::{
+    pool::{self, IoPool},
+    slow::SlowLog,
+    stats::Prepared,
+};
+use bytes::{Buf, Bytes};
+use std::{
+    io::{self, Read},
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::mpsc,
+    task::JoinHandle,
+    time::Sleep,
+};
+
+/// The size of the chunks files are read in.
+const CHUNK: usize = 64 * 1024;
+
+/// The number of chunks read ahead of the client.
+const CHUNKS_AHEAD: usize = 4;
+
+/// The most memory a stream of `len` bytes holds at once: the chunks in the channel, the one
+/// being read and the one being sent.
+pub(crate) fn buffered(len: u64) -> u64 {
+    len.min(((CHUNKS_AHEAD + 2) * CHUNK) as u64)
+}
+
+/// Streams the `len` bytes `reader` reads, reading on `pool`. The first chunk is read before
+/// returning, on the calling thread, so that a file that can't be read at all fails the download
+/// up front. A chunk that takes longer than `timeout` to arrive fails the read with
+/// [`io::ErrorKind::TimedOut`]. Chunks that take longer than `slow` to read are logged as reads
+/// of `what`.
+pub(crate) fn stream<R>(
+    mut reader: R,
+    len: u64,
+    timeout: Option<Duration>,
+    pool: Option<IoPool>,
+    slow: SlowLog,
+    what: String,
+) -> io::Result<Streamed>
+where
+    R: Read + Send + 'static,
+{
+    let mut left = len;
+    let timer = slow.start();
+    let first = read_chunk(&mut reader, &mut left)?;
+    timer.finish(|| format!("read of {} bytes of {what}", first.len()));
+    let read = Arc::new(AtomicU64::new(first.len() as u64));
+    let (sender, chunks) = mpsc::channel(CHUNKS_AHEAD);
+    let counted = read.clone();
+    let producer = (left > 0).then(|| {
+        tokio::spawn(async move {
+            while left > 0 {
+                // Stops once the client is gone
+                let Ok(permit) = sender.reserve().await else {
+                    break;
+                };
+                let timer = slow.start();
+                let read = pool::spawn(pool.as_ref(), move || {
+                    let chunk = read_chunk(&mut reader, &mut left);
+                    (reader, left, chunk)
+                });
+                let (back, rest, chunk) = match read.await {
+                    Ok(read) => read,
+                    Err(e) => {
+                        permit.send(Err(e));
+                        break;
+                    }
+                };
+                (reader, left) = (back, rest);
+                timer.finish(|| {
+                    let bytes = chunk.as_ref().map_or(0, Bytes::len);
+                    format!("read of {bytes} bytes of {what}")
+                });
+                if let Ok(chunk) = &chunk {
+                    counted.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                }
+                let failed = chunk.is_err();
+                permit.send(chunk);
+                if failed {
+                    break;
+                }
+            }
+        })
+    });
+    Ok(Streamed {
+        chunk: first,
+        producer,
+        read,
+        chunks,
+        timeout,
+        waiting: None,
+        done: false,
+    })
+}
+
+/// Reads the next chunk of the `left` bytes to stream.
+fn read_chunk(reader: &mut impl Read, left: &mut u64) -> io::Result<Bytes> {
+    let mut buf = vec![0; (*left).min(CHUNK as u64) as usize];
+    reader.read_exact(&mut buf)?;
+    *left -= buf.len() as u64;
+    Ok(buf.into())
+}
+
+/// The reading end of a [stream], which ends when the file does or the first read fails.
+pub(crate) struct Streamed {
+    chunk: Bytes,
+    /// The task reading the chunks, stopped once the client is gone
+    producer: Option<JoinHandle<()>>,
+    /// The bytes read from the image so far
+    read: Arc<AtomicU64>,
+    chunks: mpsc::Receiver<io::Result<Bytes>>,
+    timeout: Option<Duration>,
+    /// Armed while waiting for the next chunk
+    waiting: Option<Pin<Box<Sleep>>>,
+    done: bool,
+}
+
+impl AsyncRead for Streamed {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if !this.chunk.is_empty() {
+                let n = buf.remaining().min(this.chunk.len());
+                buf.put_slice(&this.chunk[..n]);
+                this.chunk.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+            if this.done {
+                return Poll::Ready(Ok(()));
+            }
+            match this.chunks.poll_recv(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    this.chunk = chunk;
+                    this.waiting = None;
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    this.done = true;
+                    return Poll::Ready(Err(e));
+                }
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => {
+                    if let Some(timeout) = this.timeout {
+                        let waiting = this
+                            .waiting
+                            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+                        if waiting.as_mut().poll(cx).is_ready() {
+                            this.done = true;
+                            return Poll::Ready(Err(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                format!("reading the next chunk timed out after {timeout:?}"),
+                            )));
+                        }
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+impl Prepared for Streamed {
+    fn prepared(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Streamed {
+    fn drop(&mut self) {
+        // A chunk being read is still read, but nothing after it
+        if let Some(producer) = &self.producer {
+            producer.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    };
+    use tokio::io::AsyncReadExt;
+
+    /// Reads zeros, counting how many were read.
+    struct Zeros(Arc<AtomicU64>);
+
+    impl Read for Zeros {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            buf.fill(0);
+            self.0.fetch_add(buf.len() as u64, Ordering::Relaxed);
+            Ok(buf.len())
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_stay_ahead_of_the_client_by_a_few_chunks() {
+        let read = Arc::new(AtomicU64::new(0));
+        let len = 100 * CHUNK as u64;
+        let mut streamed = stream(
+            Zeros(read.clone()),
+            len,
+            None,
+            None,
+            SlowLog::default(),
+            String::new(),
+        )
+        .unwrap();
+        let mut buf = vec![0; 10];
+        streamed.read_exact(&mut buf).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let ahead = read.load(Ordering::Relaxed);
+        assert!(ahead > CHUNK as u64);
+        assert!(ahead <= buffered(len));
+
+        let mut rest = Vec::new();
+        streamed.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest.len() as u64, len - 10);
+        assert_eq!(read.load(Ordering::Relaxed), len);
+    }
+
+    #[tokio::test]
+    async fn dropping_stops_the_reads() {
+        let read = Arc::new(AtomicU64::new(0));
+        let mut streamed = stream(
+            Zeros(read.clone()),
+            100 * CHUNK as u64,
+            None,
+            None,
+            SlowLog::default(),
+            String::new(),
+        )
+        .unwrap();
+        let mut buf = vec![0; 10];
+        streamed.read_exact(&mut buf).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let before = read.load(Ordering::Relaxed);
+        drop(streamed);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(read.load(Ordering::Relaxed) <= before + CHUNK as u64);
+    }
+
+    #[tokio::test]
+    async fn short_files_fail() {
+        let reader = io::Cursor::new(vec![1; CHUNK + 10]);
+        let mut streamed = stream(
+            reader,
+            2 * CHUNK as u64,
+            None,
+            None,
+            SlowLog::default(),
+            String::new(),
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        let e = streamed.read_to_end(&mut buf).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(buf.len(), CHUNK);
+        assert!(
+            stream(
+                io::Cursor::new(vec![1; 10]),
+                20,
+                None,
+                None,
+                SlowLog::default(),
+                String::new()
+            )
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn chunks_are_read_on_the_pool() {
+        let reader = io::Cursor::new(vec![1; 3 * CHUNK]);
+        let pool = IoPool::new(1);
+        let mut streamed = stream(
+            reader,
+            3 * CHUNK as u64,
+            None,
+            Some(pool),
+            SlowLog::default(),
+            String::new(),
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        streamed.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, vec![1; 3 * CHUNK]);
+    }
+}
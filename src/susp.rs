@@ -0,0 +1,219 @@
+//! An inventory of the System Use Sharing Protocol entries of the primary hierarchy, like those
+//! of Rock Ridge, as returned by [`Storage::extensions`](crate::Storage::extensions).
+
+use crate::{
+    descriptor::{self, DescriptorKind, SECTOR, read_exact_at},
+    source::IsoSource,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io,
+};
+
+/// The most continuation areas followed from a single record, so that a chain of them that
+/// loops ends.
+const MAX_CONTINUATIONS: usize = 16;
+
+/// The SUSP entries found in the records of the primary hierarchy of an image, as returned by
+/// [`Storage::extensions`](crate::Storage::extensions).
+///
+/// Rock Ridge names and attributes are only as good as the records that carry them: an image
+/// whose ER entry declares Rock Ridge but whose records mostly lack `NM` entries mixes Rock
+/// Ridge names with ISO 9660 ones. Comparing the count of a signature to
+/// [`records`](Self::records) tells how much of the hierarchy it covers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Extensions {
+    /// The records read, one for every entry of the hierarchy with the `.` of every directory
+    pub records: u64,
+    /// For the signature of every kind of entry found, like `PX`, `NM` or `ZF`, the number of
+    /// records carrying one
+    pub entries: BTreeMap<String, u64>,
+    /// The identifiers of the extensions declared by `ER` entries, like `RRIP_1991A`
+    pub declared: Vec<String>,
+}
+
+impl Extensions {
+    /// Tells whether some record carries an entry with `signature`, like `SL`.
+    pub fn contains(&self, signature: &str) -> bool {
+        self.entries.contains_key(signature)
+    }
+
+    /// Tells whether the image declares Rock Ridge in an `ER` entry, under any of the
+    /// identifiers of its versions.
+    pub fn declares_rock_ridge(&self) -> bool {
+        self.declared
+            .iter()
+            .any(|id| matches!(id.as_str(), "RRIP_1991A" | "IEEE_P1282" | "IEEE_1282"))
+    }
+}
+
+/// Reads the entries of every record of the primary hierarchy of the image in `source`.
+pub(crate) fn inventory(source: &dyn IsoSource) -> io::Result<Extensions> {
+    let primary = descriptor::read(source)?
+        .into_iter()
+        .find(|descriptor| matches!(descriptor.kind, DescriptorKind::Primary(_)))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no primary volume"))?;
+    let root = &primary.raw[156..190];
+    let mut walk = Walk {
+        source,
+        len: source.len()?,
+        extensions: Extensions::default(),
+        skip: 0,
+    };
+    let mut directories = vec![(le32(root, 2), le32(root, 10), true)];
+    let mut visited = HashSet::new();
+    while let Some((extent, len, root)) = directories.pop() {
+        if visited.insert(extent) {
+            walk.directory(extent, len, root, &mut directories)?;
+        }
+    }
+    let mut extensions = walk.extensions;
+    let declared: BTreeSet<String> = extensions.declared.drain(..).collect();
+    extensions.declared = declared.into_iter().collect();
+    Ok(extensions)
+}
+
+struct Walk<'a> {
+    source: &'a dyn IsoSource,
+    len: u64,
+    extensions: Extensions,
+    /// The bytes the `SP` entry of the root says to skip at the start of every system use area
+    skip: usize,
+}
+
+impl Walk<'_> {
+    /// Reads the records of the directory of `len` bytes at block `extent`, adding its
+    /// subdirectories to `directories`.
+    fn directory(
+        &mut self,
+        extent: u32,
+        len: u32,
+        root: bool,
+        directories: &mut Vec<(u32, u32, bool)>,
+    ) -> io::Result<()> {
+        let raw = directory(self.source, self.len, extent, len)?;
+        for (i, record) in Records::new(&raw).enumerate() {
+            let identifier = &record[33..33 + usize::from(record[32])];
+            let dot = identifier == [0];
+            if identifier == [1] {
+                continue;
+            }
+            let first = 33 + identifier.len() + (identifier.len() + 1) % 2;
+            let area = record.get(first..).unwrap_or_default();
+            if root && i == 0 {
+                // The `SP` entry that starts the root's `.` tells how many bytes to skip in the
+                // others
+                if area.len() >= 7 && area[..2] == *b"SP" && area[4..6] == [0xbe, 0xef] {
+                    self.skip = usize::from(area[6]);
+                }
+                self.entries(area)?;
+            } else {
+                self.entries(area.get(self.skip..).unwrap_or_default())?;
+            }
+            self.extensions.records += 1;
+            if record[25] & 0x02 != 0 && !dot {
+                directories.push((le32(record, 2), le32(record, 10), false));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts the entries of a system use area, and of the continuation areas it leads to.
+    fn entries(&mut self, area: &[u8]) -> io::Result<()> {
+        let mut signatures = BTreeSet::new();
+        let mut area = area.to_vec();
+        for _ in 0..MAX_CONTINUATIONS {
+            let mut continuation = None;
+            let mut at = 0;
+            while at + 4 <= area.len() {
+                let entry = &area[at..];
+                let len = usize::from(entry[2]);
+                if len < 4 || len > entry.len() {
+                    break;
+                }
+                let entry = &entry[..len];
+                match &entry[..2] {
+                    b"ST" => break,
+                    b"CE" if len >= 28 => {
+                        continuation = Some((le32(entry, 4), le32(entry, 12), le32(entry, 20)));
+                    }
+                    b"ER" if len >= 8 => {
+                        let id = entry.get(8..8 + usize::from(entry[4])).unwrap_or_default();
+                        self.extensions
+                            .declared
+                            .push(String::from_utf8_lossy(id).into_owned());
+                    }
+                    _ => {}
+                }
+                signatures.insert(String::from_utf8_lossy(&entry[..2]).into_owned());
+                at += len;
+            }
+            let Some((block, offset, len)) = continuation else {
+                break;
+            };
+            let start = u64::from(block) * SECTOR as u64 + u64::from(offset);
+            if start + u64::from(len) > self.len {
+                break;
+            }
+            area = vec![0; len as usize];
+            read_exact_at(self.source, start, &mut area)?;
+        }
+        for signature in signatures {
+            *self.extensions.entries.entry(signature).or_default() += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the bytes of the directory of `len` bytes at block `extent` of an image of `image_len`
+/// bytes, as much of it as the image holds.
+pub(crate) fn directory(
+    source: &dyn IsoSource,
+    image_len: u64,
+    extent: u32,
+    len: u32,
+) -> io::Result<Vec<u8>> {
+    let offset = u64::from(extent) * SECTOR as u64;
+    let len = u64::from(len).min(image_len.saturating_sub(offset)) as usize;
+    let mut raw = vec![0; len];
+    read_exact_at(source, offset, &mut raw)?;
+    Ok(raw)
+}
+
+/// The directory records in a directory's bytes. Records don't cross sectors, and the rest of a
+/// sector after the last record in it is zeros.
+pub(crate) struct Records<'a> {
+    raw: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Records<'a> {
+    pub(crate) fn new(raw: &'a [u8]) -> Self {
+        Self { raw, at: 0 }
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.at < self.raw.len() {
+            let len = usize::from(self.raw[self.at]);
+            if len == 0 {
+                self.at = (self.at / SECTOR + 1) * SECTOR;
+                continue;
+            }
+            let record = self.raw.get(self.at..self.at + len)?;
+            if len < 34 || 33 + usize::from(record[32]) > len {
+                return None;
+            }
+            self.at += len;
+            return Some(record);
+        }
+        None
+    }
+}
+
+pub(crate) fn le32(raw: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(raw[at..at + 4].try_into().unwrap())
+}
@@ -0,0 +1,138 @@
+//! Serving every user an image of their own, found by putting their name into a path template,
+//! for [`Storage::templated`](crate::Storage::templated).
+
+use crate::{Storage, StorageBuilder, source::Origin, source::SharedFile, stats::StatsRegistry};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use unftp_core::storage::{Error, ErrorKind, Result};
+
+/// What in a template is replaced by the username.
+pub(crate) const PLACEHOLDER: &str = "{username}";
+
+/// The back-ends of the users of a templated storage, each built like the templated one but for
+/// the image of that user.
+pub(crate) struct Tenants {
+    template: Arc<str>,
+    prototype: StorageBuilder,
+    stats: Arc<StatsRegistry>,
+    storages: Mutex<HashMap<String, Storage>>,
+}
+
+impl Tenants {
+    pub(crate) fn new(
+        template: Arc<str>,
+        prototype: StorageBuilder,
+        stats: Arc<StatsRegistry>,
+    ) -> Self {
+        Self {
+            template,
+            prototype,
+            stats,
+            storages: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the back-end for the image of `username`, building it the first time. Only the
+    /// back-ends of users whose image exists are kept, so that logins under any number of
+    /// names without one don't pile up back-ends.
+    pub(crate) fn storage(&self, username: &str) -> Result<Storage> {
+        let mut storages = self.storages.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(storage) = storages.get(username) {
+            return Ok(storage.clone());
+        }
+        let path = image_path(&self.template, username)?;
+        let exists = path.exists();
+        let mut builder = self.prototype.clone();
+        builder.origin = Origin::Path(Arc::new(SharedFile::new(path)));
+        let storage = builder.build_sharing(self.stats.clone());
+        if exists {
+            storages.insert(username.to_string(), storage.clone());
+        }
+        Ok(storage)
+    }
+}
+
+impl fmt::Debug for Tenants {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tenants")
+            .field("template", &self.template)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Puts `username` into `template`, refusing names that would make it point anywhere but at a
+/// file in the place the template intends: ones that are empty, hold a path separator or a
+/// control character, or are `.` or `..`.
+pub(crate) fn image_path(template: &str, username: &str) -> Result<PathBuf> {
+    let unsafe_name = matches!(username, "" | "." | "..")
+        || username
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c.is_control());
+    if unsafe_name {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!("the username {username:?} can't name an image"),
+        ));
+    }
+    Ok(PathBuf::from(template.replace(PLACEHOLDER, username)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_paths() {
+        let template = "/srv/isos/{username}.iso";
+        assert_eq!(
+            image_path(template, "alice").unwrap(),
+            PathBuf::from("/srv/isos/alice.iso")
+        );
+        assert_eq!(
+            image_path("/srv/{username}/{username}.iso", "bob@example.com").unwrap(),
+            PathBuf::from("/srv/bob@example.com/bob@example.com.iso")
+        );
+        // Dots are fine within a name, which stays a single component
+        assert_eq!(
+            image_path("/srv/isos/{username}", "..alice").unwrap(),
+            PathBuf::from("/srv/isos/..alice")
+        );
+        for name in [
+            "",
+            ".",
+            "..",
+            "../etc/passwd",
+            "a/b",
+            "a\\b",
+            "a\0b",
+            "a\nb",
+        ] {
+            let e = image_path(template, name).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::PermissionDenied, "{name:?}");
+        }
+    }
+
+    #[test]
+    fn only_users_with_an_image_are_kept() {
+        let dir =
+            std::env::temp_dir().join(format!("unftp-sbe-iso-tenants-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("alice.iso"), b"").unwrap();
+        let storage = Storage::templated(&format!("{}/{PLACEHOLDER}.iso", dir.display()));
+        let tenants = storage.inner.tenants.as_ref().unwrap();
+        let kept = || tenants.storages.lock().unwrap().len();
+        for user in ["alice", "alice", "mallory", "trudy", "mallory"] {
+            tenants.storage(user).unwrap();
+        }
+        assert_eq!(kept(), 1);
+        // Once an image turns up, its back-end is kept too
+        std::fs::write(dir.join("mallory.iso"), b"").unwrap();
+        tenants.storage("mallory").unwrap();
+        assert_eq!(kept(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
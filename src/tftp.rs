@@ -0,0 +1,446 @@
+//! A read-only TFTP server for the boot artifacts of the image, for PXE firmware, which fetches
+//! them over TFTP while people browse the same image over FTP.
+
+use crate::Storage;
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    net::UdpSocket,
+    sync::Semaphore,
+    time::{Instant, timeout_at},
+};
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, Metadata, StorageBackend},
+};
+
+// Opcodes
+const RRQ: u16 = 1;
+const WRQ: u16 = 2;
+const DATA: u16 = 3;
+const ACK: u16 = 4;
+const ERROR: u16 = 5;
+const OACK: u16 = 6;
+
+// Error codes
+const NOT_DEFINED: u16 = 0;
+const FILE_NOT_FOUND: u16 = 1;
+const ACCESS_VIOLATION: u16 = 2;
+const ILLEGAL_OPERATION: u16 = 4;
+
+/// The size of blocks unless the client asks for another.
+const DEFAULT_BLOCK: usize = 512;
+/// The largest block there can be, RFC 2348's limit.
+const MAX_BLOCK: usize = 65_464;
+/// The largest block a client may ask for unless told otherwise, the most that fits an Ethernet
+/// frame.
+const DEFAULT_MAX_BLOCK: usize = 1468;
+/// How many transfers run at once unless told otherwise.
+const DEFAULT_TRANSFERS: usize = 64;
+/// How long a block is waited on to be acknowledged before it is sent again, unless the client
+/// asks for another timeout.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often a block is sent before the transfer is given up on.
+const ATTEMPTS: u32 = 5;
+
+/// Serves files of a [`Storage`] over TFTP: the El Torito boot images, `boot.img` and `efi.img`,
+/// which the storage serves with [`boot_images`](crate::StorageBuilder::boot_images) on, and the
+/// files of the [subtrees](Self::subtree) given, like `/isolinux`. Nothing else can be fetched,
+/// nor anything written. Files are sent as they are, in `octet` mode whatever mode is asked for.
+///
+/// The `blksize`, `tsize` and `timeout` options are understood, and block numbers roll over to 0
+/// after 65535 for files larger than that many blocks. Blocks are no larger than a
+/// [limit](Self::max_block), and only so many [transfers](Self::max_transfers) run at once, so
+/// requests with a forged source can't turn the server into an amplifier.
+///
+/// ```no_run
+/// use tokio::net::UdpSocket;
+/// use unftp_sbe_iso::{Storage, TftpServer};
+///
+/// # async fn run() -> std::io::Result<()> {
+/// let storage = Storage::builder("/path/to/your/image.iso")
+///     .boot_images(true)
+///     .build();
+/// let socket = UdpSocket::bind("0.0.0.0:69").await?;
+/// TftpServer::new(storage).subtree("/isolinux").serve(socket).await
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TftpServer {
+    storage: Storage,
+    subtrees: Vec<Vec<String>>,
+    max_block: usize,
+    max_transfers: usize,
+}
+
+/// The names of `path`, with `..` applied and `\` taken as a separator, as PXE configurations
+/// written for Windows servers use it.
+fn names(path: &str) -> Vec<String> {
+    let path = path.replace('\\', "/");
+    let mut names = Vec::new();
+    for comp in Path::new(&path).components() {
+        match comp {
+            Component::Normal(name) => names.push(name.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                names.pop();
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// What a read request asks for.
+#[derive(Debug, PartialEq)]
+struct ReadRequest {
+    file: String,
+    block: Option<usize>,
+    size: bool,
+    timeout: Option<Duration>,
+}
+
+/// An error packet.
+fn error(code: u16, message: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(5 + message.len());
+    packet.extend_from_slice(&ERROR.to_be_bytes());
+    packet.extend_from_slice(&code.to_be_bytes());
+    packet.extend_from_slice(message.as_bytes());
+    packet.push(0);
+    packet
+}
+
+/// Parses a read request, with blocks of at most `max_block` bytes, or returns the error packet
+/// to refuse the request with.
+fn parse_request(packet: &[u8], max_block: usize) -> Result<ReadRequest, Vec<u8>> {
+    let opcode = packet.get(..2).map(|op| u16::from_be_bytes([op[0], op[1]]));
+    match opcode {
+        Some(RRQ) => {}
+        Some(WRQ) => return Err(error(ACCESS_VIOLATION, "read-only server")),
+        _ => return Err(error(ILLEGAL_OPERATION, "expected a read request")),
+    }
+    let mut fields = packet[2..]
+        .split(|&b| b == 0)
+        .map(|field| String::from_utf8_lossy(field).into_owned());
+    let (Some(file), Some(_mode)) = (fields.next(), fields.next().filter(|m| !m.is_empty())) else {
+        return Err(error(ILLEGAL_OPERATION, "malformed request"));
+    };
+    let mut request = ReadRequest {
+        file,
+        block: None,
+        size: false,
+        timeout: None,
+    };
+    // Unknown options and those with values out of range are left out of the OACK
+    while let (Some(option), Some(value)) = (fields.next(), fields.next()) {
+        let value = value.parse::<u64>().ok();
+        match option.to_ascii_lowercase().as_str() {
+            "blksize" => {
+                request.block = value
+                    .filter(|&size| size >= 8)
+                    .map(|size| (size as usize).min(max_block));
+            }
+            "tsize" => request.size = value == Some(0),
+            "timeout" => {
+                request.timeout = value
+                    .filter(|secs| (1..=255).contains(secs))
+                    .map(Duration::from_secs);
+            }
+            _ => {}
+        }
+    }
+    Ok(request)
+}
+
+impl TftpServer {
+    /// Serves the boot images of `storage`.
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            storage,
+            subtrees: Vec::new(),
+            max_block: DEFAULT_MAX_BLOCK,
+            max_transfers: DEFAULT_TRANSFERS,
+        }
+    }
+
+    /// Sends blocks of at most `max` bytes, however large a block the client asks for, and no
+    /// larger than 65464 bytes in any case. Defaults to 1468, so blocks fit an Ethernet frame.
+    pub fn max_block(mut self, max: usize) -> Self {
+        self.max_block = max.clamp(8, MAX_BLOCK);
+        self
+    }
+
+    /// Runs at most `max` transfers at once; further requests aren't read until one of those
+    /// ends. Defaults to 64.
+    pub fn max_transfers(mut self, max: usize) -> Self {
+        self.max_transfers = max;
+        self
+    }
+
+    /// Serves the files below `path` of the image too. Can be called several times.
+    pub fn subtree<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.subtrees.push(names(&path.as_ref().to_string_lossy()));
+        self
+    }
+
+    /// Tells whether the file of the given names may be fetched.
+    fn allows(&self, names: &[String]) -> bool {
+        let eq = |a: &String, b: &String| a.eq_ignore_ascii_case(b);
+        match names {
+            [name]
+                if name.eq_ignore_ascii_case("boot.img")
+                    || name.eq_ignore_ascii_case("efi.img") =>
+            {
+                true
+            }
+            _ => self.subtrees.iter().any(|subtree| {
+                names.len() > subtree.len() && subtree.iter().zip(names).all(|(a, b)| eq(a, b))
+            }),
+        }
+    }
+
+    /// Answers the requests that arrive on `socket`, each transfer running on a task of its own
+    /// from a socket of its own, until receiving fails.
+    pub async fn serve(self, socket: UdpSocket) -> io::Result<()> {
+        let local = socket.local_addr()?;
+        let transfers = Arc::new(Semaphore::new(self.max_transfers));
+        let mut packet = vec![0; MAX_BLOCK + 4];
+        loop {
+            // Requests over the limit wait in the buffer of the socket
+            let permit = transfers
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("the semaphore is never closed");
+            let (len, peer) = socket.recv_from(&mut packet).await?;
+            let request = packet[..len].to_vec();
+            let server = self.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                if let Err(e) = server.transfer(local, peer, &request).await {
+                    log::debug!("TFTP transfer to {peer} failed: {e}");
+                }
+            });
+        }
+    }
+
+    /// Answers one request of `peer`.
+    async fn transfer(
+        &self,
+        local: SocketAddr,
+        peer: SocketAddr,
+        request: &[u8],
+    ) -> io::Result<()> {
+        let socket = UdpSocket::bind(SocketAddr::new(local.ip(), 0)).await?;
+        socket.connect(peer).await?;
+        let request = match parse_request(request, self.max_block) {
+            Ok(request) => request,
+            Err(packet) => return socket.send(&packet).await.map(drop),
+        };
+        let names = names(&request.file);
+        if !self.allows(&names) {
+            let packet = error(ACCESS_VIOLATION, "not served over TFTP");
+            return socket.send(&packet).await.map(drop);
+        }
+        let path: PathBuf = ["/"].into_iter().map(String::from).chain(names).collect();
+        let user = DefaultUser {};
+        let opened = match self.storage.metadata(&user, &path).await {
+            Ok(meta) if meta.is_file() => self
+                .storage
+                .get(&user, &path, 0)
+                .await
+                .map(|reader| (meta.len(), reader)),
+            Ok(_) => Err(ErrorKind::PermanentFileNotAvailable.into()),
+            Err(e) => Err(e),
+        };
+        let (len, reader) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                let packet = match e.kind() {
+                    ErrorKind::PermanentFileNotAvailable => error(FILE_NOT_FOUND, "file not found"),
+                    _ => error(NOT_DEFINED, &e.to_string()),
+                };
+                return socket.send(&packet).await.map(drop);
+            }
+        };
+        let mut transfer = Transfer {
+            socket,
+            block: request.block.unwrap_or(DEFAULT_BLOCK),
+            timeout: request.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        };
+        let mut options = Vec::new();
+        for (option, value, given) in [
+            ("blksize", request.block.map(|b| b as u64), true),
+            ("tsize", Some(len), request.size),
+            ("timeout", request.timeout.map(|t| t.as_secs()), true),
+        ] {
+            if let (Some(value), true) = (value, given) {
+                options.extend_from_slice(option.as_bytes());
+                options.push(0);
+                options.extend_from_slice(value.to_string().as_bytes());
+                options.push(0);
+            }
+        }
+        if !options.is_empty() {
+            let packet = [&OACK.to_be_bytes()[..], &options].concat();
+            if !transfer.send(&packet, 0).await? {
+                return Ok(());
+            }
+        }
+        transfer.send_file(reader).await
+    }
+}
+
+/// A transfer to one client.
+struct Transfer {
+    socket: UdpSocket,
+    block: usize,
+    timeout: Duration,
+}
+
+impl Transfer {
+    /// Sends `packet` until the client acknowledges block `number`, telling whether it did.
+    async fn send(&self, packet: &[u8], number: u16) -> io::Result<bool> {
+        let mut ack = [0; 516];
+        for _ in 0..ATTEMPTS {
+            self.socket.send(packet).await?;
+            // Other packets don't put off sending again
+            let deadline = Instant::now() + self.timeout;
+            loop {
+                match timeout_at(deadline, self.socket.recv(&mut ack)).await {
+                    // Sent again
+                    Err(_) => break,
+                    Ok(Err(e)) => return Err(e),
+                    Ok(Ok(len)) if len >= 4 => {
+                        let opcode = u16::from_be_bytes([ack[0], ack[1]]);
+                        let acked = u16::from_be_bytes([ack[2], ack[3]]);
+                        match opcode {
+                            ACK if acked == number => return Ok(true),
+                            ERROR => return Ok(false),
+                            // A duplicate of an earlier acknowledgement
+                            _ => {}
+                        }
+                    }
+                    Ok(Ok(_)) => {}
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Sends the contents of `reader`, block after block.
+    async fn send_file(&mut self, mut reader: impl AsyncRead + Unpin) -> io::Result<()> {
+        let mut number: u16 = 1;
+        let mut packet = vec![0; 4 + self.block];
+        loop {
+            let mut filled = 0;
+            while filled < self.block {
+                match reader.read(&mut packet[4 + filled..]).await {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) => {
+                        self.socket
+                            .send(&error(NOT_DEFINED, &e.to_string()))
+                            .await?;
+                        return Err(e);
+                    }
+                }
+            }
+            packet[..2].copy_from_slice(&DATA.to_be_bytes());
+            packet[2..4].copy_from_slice(&number.to_be_bytes());
+            if !self.send(&packet[..4 + filled], number).await? {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("block {number} wasn't acknowledged"),
+                ));
+            }
+            // A block shorter than the others ends the transfer
+            if filled < self.block {
+                return Ok(());
+            }
+            number = number.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rrq(fields: &[&str]) -> Vec<u8> {
+        let mut packet = RRQ.to_be_bytes().to_vec();
+        for field in fields {
+            packet.extend_from_slice(field.as_bytes());
+            packet.push(0);
+        }
+        packet
+    }
+
+    #[test]
+    fn requests() {
+        assert_eq!(
+            parse_request(&rrq(&["pxelinux.0", "octet"]), MAX_BLOCK),
+            Ok(ReadRequest {
+                file: "pxelinux.0".into(),
+                block: None,
+                size: false,
+                timeout: None,
+            })
+        );
+        assert_eq!(
+            parse_request(
+                &rrq(&[
+                    "boot.img", "octet", "BLKSIZE", "1468", "tsize", "0", "timeout", "3", "x", "y"
+                ]),
+                MAX_BLOCK
+            ),
+            Ok(ReadRequest {
+                file: "boot.img".into(),
+                block: Some(1468),
+                size: true,
+                timeout: Some(Duration::from_secs(3)),
+            })
+        );
+        let out_of_range = rrq(&["boot.img", "octet", "blksize", "4", "timeout", "0"]);
+        let out_of_range = parse_request(&out_of_range, MAX_BLOCK).unwrap();
+        assert_eq!((out_of_range.block, out_of_range.timeout), (None, None));
+        // Larger blocks are cut down to the limit
+        let large = rrq(&["boot.img", "octet", "blksize", "65464"]);
+        let large = parse_request(&large, DEFAULT_MAX_BLOCK).unwrap();
+        assert_eq!(large.block, Some(DEFAULT_MAX_BLOCK));
+        let mut wrq = rrq(&["x", "octet"]);
+        wrq[1] = WRQ as u8;
+        assert_eq!(
+            parse_request(&wrq, MAX_BLOCK).unwrap_err()[..4],
+            [0, 5, 0, 2]
+        );
+        assert_eq!(
+            parse_request(&[0, 4, 0, 1], MAX_BLOCK).unwrap_err()[..4],
+            [0, 5, 0, 4]
+        );
+        assert_eq!(
+            parse_request(&rrq(&["x"]), MAX_BLOCK).unwrap_err()[..4],
+            [0, 5, 0, 4]
+        );
+    }
+
+    #[test]
+    fn allowed() {
+        let storage = Storage::from_source(Vec::new());
+        let server = TftpServer::new(storage).subtree("/isolinux");
+        let allows = |path: &str| server.allows(&names(path));
+        assert!(allows("boot.img"));
+        assert!(allows("/EFI.IMG"));
+        assert!(allows("isolinux/isolinux.bin"));
+        assert!(allows("\\ISOLINUX\\vmlinuz"));
+        assert!(!allows("isolinux"));
+        assert!(!allows("isolinux/../etc/passwd"));
+        assert!(!allows("/docs/readme.txt"));
+        assert!(!allows("x/boot.img"));
+    }
+}
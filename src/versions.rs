@@ -0,0 +1,116 @@
+//! Files recorded in several versions, like `FILE.TXT;1` next to `FILE.TXT;2`. ISO 9660 allows
+//! it, and cdfs strips the version from the identifiers, which leaves their names equal.
+
+use crate::IsoReader;
+use cdfs::DirectoryEntry;
+use std::collections::HashMap;
+
+/// Which versions of a file recorded in several are exposed, as set with
+/// [`StorageBuilder::file_versions`](crate::StorageBuilder::file_versions).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileVersions {
+    /// Only the highest version, under the plain name
+    #[default]
+    Highest,
+    /// Every version: the highest under the plain name and each superseded one as
+    /// `NAME;VERSION`, e.g. `FILE.TXT;1`
+    All,
+}
+
+/// The version of a file or symbolic link. Directories have none.
+pub(crate) fn version(entry: &DirectoryEntry<IsoReader>) -> Option<u16> {
+    match entry {
+        DirectoryEntry::File(file) => Some(file.version),
+        DirectoryEntry::Symlink(link) => Some(link.version),
+        DirectoryEntry::Directory(_) => None,
+    }
+}
+
+/// Splits a name like `FILE.TXT;1` into the name and the version it ends in, for files named
+/// with the version they are recorded with rather than as presented.
+pub(crate) fn split_version(name: &str) -> Option<(&str, u16)> {
+    let (name, version) = name.rsplit_once(';')?;
+    if name.is_empty() || !version.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((name, version.parse().ok()?))
+}
+
+/// Names the records of a directory, given with their identifier and version in the order of
+/// the directory, as `versions` says. Records left out are dropped; the rest keep their order.
+pub(crate) fn present<T>(
+    records: Vec<(String, Option<u16>, T)>,
+    versions: FileVersions,
+) -> Vec<(String, T)> {
+    let mut highest: HashMap<String, u16> = HashMap::new();
+    for (identifier, version, _) in &records {
+        if let Some(version) = *version {
+            let max = highest.entry(identifier.clone()).or_default();
+            *max = (*max).max(version);
+        }
+    }
+    records
+        .into_iter()
+        .filter_map(|(identifier, version, record)| match version {
+            Some(version) if version < highest[&identifier] => match versions {
+                FileVersions::Highest => None,
+                FileVersions::All => Some((format!("{identifier};{version}"), record)),
+            },
+            _ => Some((identifier, record)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<(String, Option<u16>, u32)> {
+        [
+            ("FILE.TXT", Some(1), 0),
+            ("DIR", None, 1),
+            ("FILE.TXT", Some(3), 2),
+            ("FILE.TXT", Some(2), 3),
+            ("OTHER", Some(5), 4),
+        ]
+        .into_iter()
+        .map(|(name, version, record)| (name.to_string(), version, record))
+        .collect()
+    }
+
+    #[test]
+    fn split_versions() {
+        assert_eq!(split_version("FILE.TXT;1"), Some(("FILE.TXT", 1)));
+        assert_eq!(split_version("A;B;32767"), Some(("A;B", 32767)));
+        for name in [
+            "FILE.TXT",
+            "FILE.TXT;",
+            ";1",
+            "FILE.TXT;+1",
+            "FILE.TXT;70000",
+        ] {
+            assert_eq!(split_version(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn highest() {
+        let presented = present(records(), FileVersions::Highest);
+        let expected = [("DIR", 1), ("FILE.TXT", 2), ("OTHER", 4)].map(|(n, r)| (n.to_string(), r));
+        assert_eq!(presented, expected);
+    }
+
+    #[test]
+    fn all() {
+        let presented = present(records(), FileVersions::All);
+        let expected = [
+            ("FILE.TXT;1", 0),
+            ("DIR", 1),
+            ("FILE.TXT", 2),
+            ("FILE.TXT;2", 3),
+            ("OTHER", 4),
+        ]
+        .map(|(n, r)| (n.to_string(), r));
+        assert_eq!(presented, expected);
+    }
+}
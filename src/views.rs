@@ -0,0 +1,352 @@
+//! Virtual directories that present the files of the image in another arrangement, or as one of
+//! its hierarchies records them, next to the tree of the image itself, as enabled with
+//! [`StorageBuilder::view`](crate::StorageBuilder::view).
+
+use crate::{Duplicates, IsoMeta, Listed, date, duplicates, session::ReadSession};
+use std::{collections::BTreeMap, time::SystemTime};
+
+/// A virtual top-level directory presenting the files of the image in another arrangement.
+///
+/// Views list the files clients can see elsewhere, under the names they have there, and serve
+/// them like there. They are built from a walk of the whole tree the first time a client enters
+/// one, and again after the image changed. A file or directory at the top of the image with the
+/// same name as a view hides the view.
+///
+/// The [namespace views](Self::NAMESPACES) are for diagnostics instead: each presents the tree
+/// as one hierarchy of the image records it, whichever is served, so that forensic analysts can
+/// compare what different operating systems saw on the disc.
+///
+/// ```
+/// use unftp_sbe_iso::{Storage, View};
+///
+/// let storage = Storage::builder("/srv/images/archive.iso")
+///     .view(View::ByDate)
+///     .build();
+///
+/// let diagnostic = View::NAMESPACES
+///     .into_iter()
+///     .fold(Storage::builder("/srv/evidence/disc.iso"), |builder, view| builder.view(view))
+///     .build();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum View {
+    /// `/BY-DATE/<year>/<month>/`, with every file under the year and month it was last
+    /// modified in, like `/BY-DATE/2022/04/README.TXT`. Months are two digits, and dates are in
+    /// UTC like in listings. Files with the same name in one month are told apart by `~2`, `~3`
+    /// and so on, in the order the tree is walked in.
+    ByDate,
+    /// `/ALL/`, with every file of the image in one directory, like `/ALL/README.TXT`. Files
+    /// with the same name are told apart by `~2`, `~3` and so on, in the order the tree is
+    /// walked in.
+    AllFiles,
+    /// `/PRIMARY/`, with the tree as the primary volume records it, under its plain ISO 9660
+    /// names like DOS sees them, even where Rock Ridge names replace them elsewhere
+    Primary,
+    /// `/JOLIET/`, with the tree as the Joliet volume records it, like Windows sees it. Left out
+    /// for images without one.
+    Joliet,
+    /// `/ROCKRIDGE/`, with the tree as the primary volume records it under its Rock Ridge names,
+    /// like Unix systems see it. Left out for images without Rock Ridge entries.
+    RockRidge,
+}
+
+impl View {
+    /// The views of each hierarchy of the image.
+    pub const NAMESPACES: [View; 3] = [View::Primary, View::Joliet, View::RockRidge];
+
+    /// The name of the top-level directory of the view.
+    pub fn name(self) -> &'static str {
+        match self {
+            View::ByDate => "BY-DATE",
+            View::AllFiles => "ALL",
+            View::Primary => "PRIMARY",
+            View::Joliet => "JOLIET",
+            View::RockRidge => "ROCKRIDGE",
+        }
+    }
+
+    /// Tells whether the view presents a hierarchy of the image rather than the files served.
+    pub(crate) fn is_namespace(self) -> bool {
+        View::NAMESPACES.contains(&self)
+    }
+}
+
+/// An entry of a view.
+#[derive(Debug)]
+pub(crate) enum Node {
+    Dir(Dir),
+    /// A file of the image, with the absolute path it has in the tree clients see
+    File {
+        path: String,
+        meta: IsoMeta,
+    },
+    /// A record of a hierarchy, with the contents to serve unless it is a link or its extent
+    /// lies past the end of the image
+    Entry {
+        meta: IsoMeta,
+        contents: Option<Contents>,
+    },
+}
+
+/// Where the contents of a file of a hierarchy lie, found when the view is built.
+#[derive(Debug)]
+pub(crate) struct Contents {
+    pub(crate) session: ReadSession,
+    /// The first sector of the contents in the image
+    pub(crate) extent: u32,
+}
+
+impl Node {
+    fn meta(&self) -> &IsoMeta {
+        match self {
+            Node::Dir(dir) => &dir.meta,
+            Node::File { meta, .. } | Node::Entry { meta, .. } => meta,
+        }
+    }
+}
+
+/// A directory of a view, with its entries by name.
+#[derive(Debug)]
+pub(crate) struct Dir {
+    pub(crate) meta: IsoMeta,
+    pub(crate) entries: BTreeMap<String, Node>,
+}
+
+impl Dir {
+    pub(crate) fn new(modified: SystemTime) -> Self {
+        Self {
+            meta: dir_meta(modified),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// The listing of the directory, with `.` and `..` like the directories of the image.
+    pub(crate) fn listing(&self) -> Vec<Listed> {
+        let dots = [".", ".."].map(|dot| (dot.to_string(), self.meta.clone()));
+        let entries = self
+            .entries
+            .iter()
+            .map(|(name, node)| (name.clone(), node.meta().clone()));
+        dots.into_iter().chain(entries).collect()
+    }
+
+    /// Finds the entry called `name`, exactly or as `eq` compares names.
+    fn get(&self, name: &str, eq: impl Fn(&str, &str) -> bool) -> Option<&Node> {
+        self.entries.get(name).or_else(|| {
+            self.entries
+                .iter()
+                .find(|(entry, _)| eq(entry, name))
+                .map(|(_, node)| node)
+        })
+    }
+}
+
+/// The metadata of a directory of a view.
+pub(crate) fn dir_meta(modified: SystemTime) -> IsoMeta {
+    IsoMeta {
+        len: 0,
+        dir: true,
+        sym: false,
+        group: 0,
+        owner: 0,
+        modified,
+    }
+}
+
+/// The views of an image, by their names.
+#[derive(Debug)]
+pub(crate) struct Views {
+    root: Dir,
+}
+
+impl Views {
+    /// Builds the `views` of the tree whose entries are `tree`, as listed by a walk of the root
+    /// directory, which was last modified at `modified`. The namespace views are given whole in
+    /// `hierarchies`, and left out where they are missing from it.
+    pub(crate) fn build(
+        views: &[View],
+        tree: &[Listed],
+        mut hierarchies: Vec<(View, Dir)>,
+        modified: SystemTime,
+    ) -> Self {
+        let mut root = Dir::new(modified);
+        let files: Vec<(&str, &IsoMeta)> = tree
+            .iter()
+            .filter(|(_, meta)| !meta.dir && !meta.sym)
+            .map(|(path, meta)| (path.as_str(), meta))
+            .collect();
+        for &view in views {
+            let dir = match view {
+                View::ByDate => by_date(&files, modified),
+                View::AllFiles => flat(&files, modified),
+                View::Primary | View::Joliet | View::RockRidge => {
+                    let Some(i) = hierarchies.iter().position(|(v, _)| *v == view) else {
+                        continue;
+                    };
+                    hierarchies.swap_remove(i).1
+                }
+            };
+            root.entries.insert(view.name().to_string(), Node::Dir(dir));
+        }
+        Self { root }
+    }
+
+    /// Finds the entry at the path made up of `names`, if the first is the name of a view.
+    pub(crate) fn find(&self, names: &[String], eq: impl Fn(&str, &str) -> bool) -> Option<&Node> {
+        let (first, rest) = names.split_first()?;
+        let mut node = self.root.get(first, &eq)?;
+        for name in rest {
+            match node {
+                Node::Dir(dir) => node = dir.get(name, &eq)?,
+                Node::File { .. } | Node::Entry { .. } => return None,
+            }
+        }
+        Some(node)
+    }
+}
+
+/// Tells whether the path made up of `names` would be in one of `views`, going by its first
+/// name alone. Saves building the views for paths that can't be in them.
+pub(crate) fn may_contain(
+    views: &[View],
+    names: &[String],
+    eq: impl Fn(&str, &str) -> bool,
+) -> bool {
+    names
+        .first()
+        .is_some_and(|first| views.iter().any(|view| eq(view.name(), first)))
+}
+
+/// Lists the subtree of `dir` depth-first like a walk of the image does, under paths relative to
+/// `dir`.
+pub(crate) fn walk(dir: &Dir) -> Vec<Listed> {
+    let mut entries = Vec::new();
+    let mut pending = vec![(dir, String::new())];
+    while let Some((dir, prefix)) = pending.pop() {
+        let mut subdirs = Vec::new();
+        for (name, meta) in dir.listing().into_iter().skip(2) {
+            let below = format!("{prefix}{name}");
+            if let Some(Node::Dir(subdir)) = dir.entries.get(&name) {
+                subdirs.push((subdir, format!("{below}/")));
+            }
+            entries.push((below, meta));
+        }
+        pending.extend(subdirs.into_iter().rev());
+    }
+    entries
+}
+
+fn by_date(files: &[(&str, &IsoMeta)], modified: SystemTime) -> Dir {
+    let mut months: BTreeMap<(i64, u32), Vec<(&str, &IsoMeta)>> = BTreeMap::new();
+    for &(path, meta) in files {
+        let (year, month, _) = date::civil(meta.modified);
+        months.entry((year, month)).or_default().push((path, meta));
+    }
+    let mut view = Dir::new(modified);
+    for ((year, month), files) in months {
+        let latest = files.iter().map(|(_, meta)| meta.modified).max();
+        let latest = latest.unwrap_or(modified);
+        let Node::Dir(year) = view
+            .entries
+            .entry(year.to_string())
+            .or_insert_with(|| Node::Dir(Dir::new(latest)))
+        else {
+            unreachable!("years are directories");
+        };
+        year.meta.modified = year.meta.modified.max(latest);
+        year.entries
+            .insert(format!("{month:02}"), Node::Dir(flat(&files, latest)));
+    }
+    view
+}
+
+/// A directory holding `files` under their names, with `~N` appended to tell apart those with
+/// the same name.
+fn flat(files: &[(&str, &IsoMeta)], modified: SystemTime) -> Dir {
+    let records = files
+        .iter()
+        .map(|&(path, meta)| {
+            let name = path.rsplit('/').next().unwrap_or(path).to_string();
+            (name, meta.len, (path, meta))
+        })
+        .collect();
+    let mut dir = Dir::new(modified);
+    for (name, (path, meta)) in duplicates::resolve(records, Duplicates::Suffix) {
+        let file = Node::File {
+            path: format!("/{path}"),
+            meta: meta.clone(),
+        };
+        dir.entries.insert(name, file);
+    }
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn at(secs: i64) -> SystemTime {
+        if secs >= 0 {
+            UNIX_EPOCH + Duration::from_secs(secs as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(-secs as u64)
+        }
+    }
+
+    #[test]
+    fn layouts() {
+        let file = |secs| IsoMeta {
+            len: 1,
+            dir: false,
+            sym: false,
+            group: 0,
+            owner: 0,
+            modified: at(secs),
+        };
+        let tree = vec![
+            ("a".to_string(), dir_meta(at(0))),
+            ("a/readme.txt".to_string(), file(1_650_542_400)),
+            ("b".to_string(), dir_meta(at(0))),
+            ("b/readme.txt".to_string(), file(1_650_542_401)),
+            ("c.txt".to_string(), file(0)),
+        ];
+        let eq = |a: &str, b: &str| a.eq_ignore_ascii_case(b);
+        let views = Views::build(&[View::ByDate], &tree, Vec::new(), at(0));
+        let names = |path: &str| path.split('/').map(str::to_string).collect::<Vec<_>>();
+        let Some(Node::Dir(april)) = views.find(&names("by-date/2022/04"), eq) else {
+            panic!("no April");
+        };
+        let listed: Vec<String> = april.listing().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(listed, [".", "..", "readme.txt", "readme.txt~2"]);
+        assert!(matches!(
+            views.find(&names("BY-DATE/2022/04/readme.txt~2"), eq),
+            Some(Node::File { path, .. }) if path == "/b/readme.txt"
+        ));
+        assert!(views.find(&names("BY-DATE/1970/01/c.txt"), eq).is_some());
+        assert!(views.find(&names("BY-DATE/1970/02"), eq).is_none());
+        assert!(views.find(&names("ALL"), eq).is_none());
+
+        let views = Views::build(&[View::ByDate, View::AllFiles], &tree, Vec::new(), at(0));
+        let Some(Node::Dir(all)) = views.find(&names("all"), eq) else {
+            panic!("no ALL");
+        };
+        let listed: Vec<String> = all.listing().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(listed, [".", "..", "c.txt", "readme.txt", "readme.txt~2"]);
+
+        // Missing hierarchies are left out
+        let mut joliet = Dir::new(at(0));
+        joliet.entries.insert(
+            "Read Me.txt".to_string(),
+            Node::Entry {
+                meta: file(0),
+                contents: None,
+            },
+        );
+        let namespaces = View::NAMESPACES;
+        let views = Views::build(&namespaces, &tree, vec![(View::Joliet, joliet)], at(0));
+        assert!(views.find(&names("JOLIET/read me.txt"), eq).is_some());
+        assert!(views.find(&names("PRIMARY"), eq).is_none());
+    }
+}
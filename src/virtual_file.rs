@@ -0,0 +1,108 @@
+//! Files that aren't in the image, served as if they were, as added with
+//! [`StorageBuilder::virtual_file`](crate::StorageBuilder::virtual_file).
+
+use crate::{IsoMeta, path::PathOptions};
+use bytes::Bytes;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// A file served at a path of the tree of the image.
+#[derive(Debug, Clone)]
+pub(crate) struct VirtualFile {
+    /// The names the path is made up of, the last one that of the file
+    names: Vec<String>,
+    contents: Contents,
+}
+
+#[derive(Debug, Clone)]
+enum Contents {
+    Bytes(Bytes),
+    /// A local file, read every time it is served
+    Local(PathBuf),
+}
+
+/// The names a path given by the integrator is made up of, with `..` applied and `\` taken as a
+/// separator, the way the paths of clients are.
+fn names(path: &str) -> Vec<String> {
+    let options = PathOptions {
+        backslash_separators: true,
+        ..PathOptions::default()
+    };
+    options
+        .normalize(Path::new(path))
+        .expect("a str is valid UTF-8")
+}
+
+impl VirtualFile {
+    /// The file at `path` holding `contents`, or `None` if `path` names the root.
+    pub(crate) fn new(path: &str, contents: Bytes) -> Option<Self> {
+        Self::at(path, Contents::Bytes(contents))
+    }
+
+    /// The file at `path` holding what the local file at `local` does at the time, or `None`
+    /// if `path` names the root.
+    pub(crate) fn local(path: &str, local: PathBuf) -> Option<Self> {
+        Self::at(path, Contents::Local(local))
+    }
+
+    fn at(path: &str, contents: Contents) -> Option<Self> {
+        let names = names(path);
+        (!names.is_empty()).then_some(Self { names, contents })
+    }
+
+    /// The name of the file.
+    pub(crate) fn name(&self) -> &str {
+        self.names.last().expect("not the root")
+    }
+
+    /// Tells whether the file is at the path made up of `names`.
+    pub(crate) fn is_at(&self, names: &[String], paths: &PathOptions) -> bool {
+        names.len() == self.names.len()
+            && self
+                .names
+                .iter()
+                .zip(names)
+                .all(|(name, given)| paths.matches(name, given))
+    }
+
+    /// Tells whether the file is in the directory at the path made up of `dir_names`.
+    pub(crate) fn is_in(&self, dir_names: &[String], paths: &PathOptions) -> bool {
+        let (_, dir) = self.names.split_last().expect("not the root");
+        dir.len() == dir_names.len()
+            && dir
+                .iter()
+                .zip(dir_names)
+                .all(|(name, given)| paths.matches(name, given))
+    }
+
+    /// Reads the contents of the file.
+    pub(crate) fn read(&self) -> io::Result<Bytes> {
+        match &self.contents {
+            Contents::Bytes(bytes) => Ok(bytes.clone()),
+            Contents::Local(local) => fs::read(local).map(Bytes::from),
+        }
+    }
+
+    /// The metadata of the file. It was modified at `modified` unless it is a local file, which
+    /// has times of its own.
+    pub(crate) fn meta(&self, modified: SystemTime) -> io::Result<IsoMeta> {
+        let (len, modified) = match &self.contents {
+            Contents::Bytes(bytes) => (bytes.len() as u64, modified),
+            Contents::Local(local) => {
+                let meta = fs::metadata(local)?;
+                (meta.len(), meta.modified().unwrap_or(modified))
+            }
+        };
+        Ok(IsoMeta {
+            len,
+            dir: false,
+            sym: false,
+            group: 0,
+            owner: 0,
+            modified,
+        })
+    }
+}
@@ -0,0 +1,284 @@
+//! Walking a subtree of the image as a stream, for [`Storage::walk`](crate::Storage::walk), and
+//! the limits every walk of the tree keeps to.
+
+use crate::{IsoMeta, Listed, Storage};
+use futures_core::Stream;
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+use unftp_core::storage::{Error, ErrorKind, Metadata, Result};
+
+/// Limits on walks of the tree, which guard against crafted images that nest directories
+/// thousands deep or hold millions of entries. A walk that would go past one fails with
+/// [`ErrorKind::PermissionDenied`] instead of listing the rest. Nothing is limited by default.
+///
+/// They apply to [`Storage::list_recursive`], [`Storage::walk`] and building
+/// [views](crate::View), which walks the whole tree.
+///
+/// ```
+/// use unftp_sbe_iso::{Storage, WalkLimits};
+///
+/// let storage = Storage::builder("/srv/images/upload.iso")
+///     .walk_limits(
+///         WalkLimits::new()
+///             .max_depth(64)
+///             .max_entries(1_000_000)
+///             .max_bytes(100 << 30),
+///     )
+///     .build();
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WalkLimits {
+    max_depth: Option<usize>,
+    max_entries: Option<u64>,
+    max_bytes: Option<u64>,
+}
+
+impl WalkLimits {
+    /// Limits nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails walks that reach entries more than `depth` levels below where they started. The
+    /// entries of the directory walked are 1 level below it.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Fails walks that list more than `entries` entries.
+    pub fn max_entries(mut self, entries: u64) -> Self {
+        self.max_entries = Some(entries);
+        self
+    }
+
+    /// Fails walks whose files add up to more than `bytes`.
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+}
+
+/// What a walk has listed so far, against its limits.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    limits: WalkLimits,
+    entries: u64,
+    bytes: u64,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: WalkLimits) -> Self {
+        Self {
+            limits,
+            entries: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Counts the entry `meta` at `depth` below the start of the walk, failing once that goes
+    /// past a limit. `path` names the entry in the error.
+    pub(crate) fn admit(
+        &mut self,
+        depth: usize,
+        meta: &IsoMeta,
+        path: impl FnOnce() -> PathBuf,
+    ) -> Result<()> {
+        self.entries += 1;
+        if meta.is_file() {
+            self.bytes = self.bytes.saturating_add(meta.len());
+        }
+        let exceeded = match self.limits {
+            WalkLimits {
+                max_depth: Some(max),
+                ..
+            } if depth > max => format!("is more than the maximum of {max} levels deep"),
+            WalkLimits {
+                max_entries: Some(max),
+                ..
+            } if self.entries > max => format!("is past the maximum of {max} entries"),
+            WalkLimits {
+                max_bytes: Some(max),
+                ..
+            } if self.bytes > max => format!("is past the maximum of {max} bytes"),
+            _ => return Ok(()),
+        };
+        Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!("walk stopped: {:?} {exceeded}", path()),
+        ))
+    }
+}
+
+/// The entries of a directory listed by one step of a walk, each with whether to walk into it,
+/// and the extent of the directory itself if it is one of the image.
+pub(crate) type Step = (Option<u32>, Vec<(Listed, bool)>);
+
+type Filter = Arc<dyn Fn(&Path, &IsoMeta) -> bool + Send + Sync>;
+
+/// A directory left to list: its names below the root of the walk, how deep it is and the
+/// extents of the directories above it.
+type Pending = (Vec<String>, usize, Vec<u32>);
+
+type Listing = Pin<Box<dyn Future<Output = Result<Step>> + Send>>;
+
+/// The entries of a subtree of the image, as returned by [`Storage::walk`](crate::Storage::walk).
+///
+/// Entries come depth-first in the order of [`Storage::list_recursive`], each with its path
+/// relative to the root of the walk and its metadata. Directories are listed one at a time as
+/// the stream is polled, so a walk that is stopped early reads no more of the image. A
+/// directory that fails to list yields its error, and the walk carries on with the next one,
+/// but going past the [limits](crate::StorageBuilder::walk_limits) of the storage ends it.
+///
+/// ```no_run
+/// use unftp_core::storage::Metadata;
+/// use unftp_sbe_iso::Storage;
+///
+/// # async fn largest() -> Result<(), Box<dyn std::error::Error>> {
+/// let storage = Storage::new("/srv/images/debian.iso");
+/// let mut walk = storage
+///     .walk("/pool")
+///     .max_depth(3)
+///     .filter(|path, _| !path.ends_with("source"));
+/// while let Some((path, meta)) = walk.next_entry().await? {
+///     if meta.is_file() && meta.len() > 100_000_000 {
+///         println!("{}", path.display());
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Walk {
+    storage: Storage,
+    root: PathBuf,
+    max_depth: Option<usize>,
+    filter: Option<Filter>,
+    budget: Budget,
+    /// The first directory is listed next
+    pending: Vec<Pending>,
+    ready: VecDeque<Result<(PathBuf, IsoMeta)>>,
+    listing: Option<(Pending, Listing)>,
+}
+
+impl Walk {
+    pub(crate) fn new(storage: Storage, root: PathBuf, limits: WalkLimits) -> Self {
+        Self {
+            storage,
+            root,
+            max_depth: None,
+            filter: None,
+            budget: Budget::new(limits),
+            pending: vec![(Vec::new(), 0, Vec::new())],
+            ready: VecDeque::new(),
+            listing: None,
+        }
+    }
+
+    /// Only walks `depth` levels deep: 1 yields the entries of the root alone. Unlimited by
+    /// default.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Only yields the entries `filter` accepts, given their path relative to the root of the
+    /// walk. Directories it rejects aren't walked into either.
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&Path, &IsoMeta) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Returns the next entry, or `None` once the walk is done, like
+    /// [`StreamExt::next`](https://docs.rs/futures/latest/futures/stream/trait.StreamExt.html#method.next)
+    /// without the dependency.
+    pub async fn next_entry(&mut self) -> Result<Option<(PathBuf, IsoMeta)>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+            .await
+            .transpose()
+    }
+
+    fn listed(&mut self, (names, depth, mut ancestors): Pending, step: Step) {
+        let (extent, children) = step;
+        ancestors.extend(extent);
+        let mut subdirs = Vec::new();
+        for ((name, meta), descend) in children {
+            let below: Vec<String> = names.iter().cloned().chain([name]).collect();
+            let path: PathBuf = below.iter().collect();
+            if let Some(filter) = &self.filter
+                && !filter(&path, &meta)
+            {
+                continue;
+            }
+            if let Err(e) = self
+                .budget
+                .admit(depth + 1, &meta, || self.root.join(&path))
+            {
+                // Nothing more is listed past a limit
+                self.ready.push_back(Err(e));
+                self.pending.clear();
+                return;
+            }
+            if descend && self.max_depth.is_none_or(|max| depth + 1 < max) {
+                subdirs.push((below, depth + 1, ancestors.clone()));
+            }
+            self.ready.push_back(Ok((path, meta)));
+        }
+        self.pending.extend(subdirs.into_iter().rev());
+    }
+}
+
+impl Stream for Walk {
+    type Item = Result<(PathBuf, IsoMeta)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(entry) = this.ready.pop_front() {
+                return Poll::Ready(Some(entry));
+            }
+            if let Some((_, listing)) = &mut this.listing {
+                let listed = ready!(listing.as_mut().poll(cx));
+                let (dir, _) = this.listing.take().expect("a listing in flight");
+                match listed {
+                    Ok(step) => this.listed(dir, step),
+                    Err(e) => this.ready.push_back(Err(e)),
+                }
+                continue;
+            }
+            if this.max_depth == Some(0) {
+                return Poll::Ready(None);
+            }
+            let Some(dir) = this.pending.pop() else {
+                return Poll::Ready(None);
+            };
+            let storage = this.storage.clone();
+            let path = this.root.join(dir.0.iter().collect::<PathBuf>());
+            let ancestors = dir.2.clone();
+            let listing = async move {
+                storage
+                    .blocking(move |storage| storage.walk_step(&path, &ancestors))
+                    .await
+            };
+            this.listing = Some((dir, Box::pin(listing)));
+        }
+    }
+}
+
+impl fmt::Debug for Walk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Walk")
+            .field("root", &self.root)
+            .field("max_depth", &self.max_depth)
+            .finish_non_exhaustive()
+    }
+}
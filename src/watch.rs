@@ -0,0 +1,149 @@
+//! Noticing changes to the image file, or to the directory of images, as they happen, with
+//! `notify`: inotify on Linux, FSEvents on macOS and `ReadDirectoryChangesW` on Windows.
+
+use crate::{Inner, source::Origin};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher, event::ModifyKind};
+use std::{
+    io,
+    path::Path,
+    sync::{
+        Arc, Weak,
+        mpsc::{self, Receiver, RecvTimeoutError},
+    },
+    thread,
+    time::Duration,
+};
+
+/// How often the watching thread checks whether the storage is still around.
+const CHECK_EVERY: Duration = Duration::from_secs(1);
+
+/// Starts a thread that clears the caches and has the next operation check the image file
+/// whenever it is modified, replaced or removed, or, for a directory of images, that looks for
+/// the images again whenever one is added, removed or renamed.
+pub(crate) fn watch(inner: &Arc<Inner>) -> io::Result<()> {
+    match (&inner.origin, &inner.library) {
+        (Origin::Path(file), _) => watch_file(inner, file.path()),
+        (Origin::Directory(_), Some(library)) => {
+            let (watcher, events) = watcher(library.dir())?;
+            spawn(watcher, events, inner, |inner, event| {
+                let library = inner.library.as_ref().expect("a directory is watched");
+                let image = |path: &Path| {
+                    path.extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("iso"))
+                };
+                match event {
+                    Some(event) if lists(&event.kind) && event.paths.iter().any(|p| image(p)) => {
+                        library.rescan()
+                    }
+                    Some(_) => {}
+                    // Back to checking the directory before every operation
+                    None => library.set_watched(false),
+                }
+            })?;
+            library.set_watched(true);
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only images given by path and directories of them can be watched",
+        )),
+    }
+}
+
+fn watch_file(inner: &Arc<Inner>, path: &Path) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?
+        .to_os_string();
+    // The directory is watched rather than the file, so that a file moved over it is noticed
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let (watcher, events) = watcher(dir)?;
+    let file = |inner: &Inner| match &inner.origin {
+        Origin::Path(file) => file.clone(),
+        _ => unreachable!("only files are watched"),
+    };
+    spawn(watcher, events, inner, move |inner, event| match event {
+        Some(event) => {
+            let ours = event
+                .paths
+                .iter()
+                .any(|p| p.file_name() == Some(name.as_os_str()));
+            if ours && !matches!(event.kind, EventKind::Access(_)) {
+                file(inner).recheck();
+                inner.caches.clear();
+            }
+        }
+        // Back to checking the file before every operation
+        None => file(inner).set_watched(false),
+    })?;
+    file(inner).set_watched(true);
+    Ok(())
+}
+
+/// Tells whether an event may change which entries a directory lists: one that adds, removes
+/// or renames an entry, or one the platform doesn't tell apart.
+fn lists(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Name(_) | ModifyKind::Any)
+            | EventKind::Any
+            | EventKind::Other
+    )
+}
+
+/// Watches the entries of `dir`, returning the watcher with the channel its events arrive on.
+fn watcher(dir: &Path) -> io::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(into_io)?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(into_io)?;
+    Ok((watcher, events))
+}
+
+fn into_io(e: notify::Error) -> io::Error {
+    match e.kind {
+        notify::ErrorKind::Io(e) => e,
+        _ => io::Error::other(e),
+    }
+}
+
+/// Starts the thread that hands the events of `watcher` to `changed` until the storage is
+/// dropped, which drops the watcher too. `changed` gets `None` once the watcher fails, which
+/// ends the watch.
+fn spawn<F>(
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    inner: &Arc<Inner>,
+    changed: F,
+) -> io::Result<()>
+where
+    F: Fn(&Inner, Option<&Event>) + Send + 'static,
+{
+    let weak: Weak<Inner> = Arc::downgrade(inner);
+    thread::Builder::new()
+        .name("unftp-sbe-iso-watch".into())
+        .spawn(move || {
+            let _watcher = watcher;
+            loop {
+                let received = events.recv_timeout(CHECK_EVERY);
+                let Some(inner) = weak.upgrade() else {
+                    break;
+                };
+                match received {
+                    Ok(Ok(event)) => changed(&inner, Some(&event)),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Ok(Err(_)) | Err(RecvTimeoutError::Disconnected) => {
+                        changed(&inner, None);
+                        break;
+                    }
+                }
+            }
+        })?;
+    Ok(())
+}
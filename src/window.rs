@@ -0,0 +1,133 @@
+//! Subtrees of the presented tree that clients can only reach at certain times.
+
+use std::{
+    path::{Component, Path},
+    time::SystemTime,
+};
+
+/// A table of subtrees that are only accessible within a window of time, for example a release
+/// image embargoed until its announcement, or a beta withdrawn after a date.
+///
+/// Outside its window a subtree doesn't exist as far as clients are concerned: it is left out of
+/// listings, and looking it or anything below it up fails as for a missing path. That holds for
+/// the names a pattern lists, names clients append a version like `;1` to and the entries of
+/// [views](crate::View) presenting the files too. Paths are those clients see, below the
+/// [root](crate::StorageBuilder::root) and under their [aliases](crate::Aliases), with the image
+/// name first for a [directory](crate::Storage::directory) of images. Times are compared with the system clock on
+/// every request, so the subtrees appear and disappear without a refresh.
+///
+/// ```
+/// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+/// use unftp_sbe_iso::AccessWindows;
+///
+/// // 2030-01-01T00:00:00Z
+/// let release = UNIX_EPOCH + Duration::from_secs(1_893_456_000);
+/// let windows = AccessWindows::new()
+///     .opens("/releases/v2.iso", release)
+///     .closes("/betas/v2-rc1.iso", release);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AccessWindows {
+    entries: Vec<Window>,
+}
+
+#[derive(Debug, Clone)]
+struct Window {
+    names: Vec<String>,
+    opens: Option<SystemTime>,
+    closes: Option<SystemTime>,
+}
+
+fn names(path: &Path) -> Vec<String> {
+    let mut names = Vec::new();
+    for comp in path.components() {
+        match comp {
+            Component::Normal(name) => names.push(name.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                names.pop();
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+impl AccessWindows {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `path` and everything below it accessible from `time` on only.
+    pub fn opens<P: AsRef<Path>>(mut self, path: P, time: SystemTime) -> Self {
+        self.window(path.as_ref()).opens = Some(time);
+        self
+    }
+
+    /// Makes `path` and everything below it inaccessible from `time` on.
+    pub fn closes<P: AsRef<Path>>(mut self, path: P, time: SystemTime) -> Self {
+        self.window(path.as_ref()).closes = Some(time);
+        self
+    }
+
+    fn window(&mut self, path: &Path) -> &mut Window {
+        let names = names(path);
+        match self.entries.iter().position(|w| w.names == names) {
+            Some(at) => &mut self.entries[at],
+            None => {
+                self.entries.push(Window {
+                    names,
+                    opens: None,
+                    closes: None,
+                });
+                self.entries.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Tells whether the path of the given names is outside the window of itself or of a
+    /// directory above it at `now`. `eq` compares a name of the table with a name from a client
+    /// path.
+    pub(crate) fn shut(
+        &self,
+        names: &[String],
+        now: SystemTime,
+        eq: impl Fn(&str, &str) -> bool,
+    ) -> bool {
+        self.entries.iter().any(|window| {
+            window.names.len() <= names.len()
+                && window.names.iter().zip(names).all(|(a, b)| eq(a, b))
+                && (window.opens.is_some_and(|opens| now < opens)
+                    || window.closes.is_some_and(|closes| now >= closes))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn path(path: &str) -> Vec<String> {
+        names(Path::new(path))
+    }
+
+    #[test]
+    fn windows() {
+        let t = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let windows = AccessWindows::new()
+            .opens("/releases/v2", t(100))
+            .closes("/releases/v2", t(200))
+            .closes("/betas", t(50));
+        let eq = |a: &str, b: &str| a.eq_ignore_ascii_case(b);
+        let shut = |p: &str, now| windows.shut(&path(p), t(now), eq);
+        assert!(!shut("/releases", 0));
+        assert!(shut("/releases/v2", 99));
+        assert!(shut("/RELEASES/V2/boot.iso", 99));
+        assert!(!shut("/releases/v2/boot.iso", 100));
+        assert!(shut("/releases/v2", 200));
+        assert!(!shut("/betas/rc1", 49));
+        assert!(shut("/betas/rc1", 50));
+        assert!(!shut("/", 50));
+    }
+}
@@ -0,0 +1,130 @@
+//! Giving up on reading files for downloads the client aborted.
+
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{CacheConfig, IsoSource, Storage, TransferStats, fixture::IsoBuilder};
+
+const LEN: usize = 4 * 1024 * 1024;
+
+/// An image on a slow drive, counting the bytes read from it.
+struct Slow {
+    image: Vec<u8>,
+    read: AtomicU64,
+}
+
+impl Slow {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            image: IsoBuilder::new().file("/big.bin", &vec![7; LEN]).build(),
+            read: AtomicU64::new(0),
+        })
+    }
+
+    fn read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+}
+
+impl IsoSource for Slow {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::thread::sleep(Duration::from_millis(1));
+        let n = self.image.read_at(offset, buf)?;
+        self.read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.image.len() as u64)
+    }
+}
+
+/// Checks that nothing is read anymore, apart from reads that were under way.
+async fn assert_stopped(source: &Slow) {
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let read = source.read();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(source.read(), read);
+    assert!(read < LEN as u64);
+}
+
+#[tokio::test]
+async fn files_read_whole() {
+    let source = Slow::new();
+    let storage = Storage::source_builder(source.clone())
+        .cache(CacheConfig {
+            content_cache_mb: 64,
+            ..Default::default()
+        })
+        .read_timeout(Duration::from_secs(10))
+        .build();
+    let get = storage.get(&DefaultUser {}, "/big.bin", 0);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(20), get)
+            .await
+            .is_err()
+    );
+    assert_stopped(&source).await;
+}
+
+#[tokio::test]
+async fn streamed_files() {
+    let source = Slow::new();
+    let storage = Storage::source_builder(source.clone()).build();
+    let mut reader = storage.get(&DefaultUser {}, "/big.bin", 0).await.unwrap();
+    let mut buf = [0; 10];
+    reader.read_exact(&mut buf).await.unwrap();
+    drop(reader);
+    assert_stopped(&source).await;
+}
+
+#[tokio::test]
+async fn counted() {
+    let image = IsoBuilder::new()
+        .file("/big.bin", &[7; 100_000])
+        .file("/small.txt", b"small")
+        .build();
+    let storage = Storage::source_builder(image)
+        .cache(CacheConfig {
+            content_cache_mb: 1,
+            ..Default::default()
+        })
+        .build();
+    let user = DefaultUser {};
+    let mut small = String::new();
+    storage
+        .get(&user, "/small.txt", 0)
+        .await
+        .unwrap()
+        .read_to_string(&mut small)
+        .await
+        .unwrap();
+    let mut reader = storage.get(&user, "/big.bin", 0).await.unwrap();
+    let mut buf = [0; 10];
+    reader.read_exact(&mut buf).await.unwrap();
+    assert_eq!(
+        storage.transfer_stats(),
+        TransferStats {
+            in_flight: 1,
+            completed: 1,
+            ..Default::default()
+        }
+    );
+    drop(reader);
+    assert_eq!(
+        storage.transfer_stats(),
+        TransferStats {
+            in_flight: 0,
+            completed: 1,
+            aborted: 1,
+            bytes_discarded: 100_000 - 10,
+        }
+    );
+}
@@ -0,0 +1,86 @@
+//! Download rates limited per class of user.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::{DefaultUser, UserDetail},
+    storage::StorageBackend,
+};
+use unftp_sbe_iso::{Bandwidth, BandwidthClasses, Storage, TieredBandwidth, fixture::IsoBuilder};
+
+const LEN: usize = 30_000;
+
+fn storage(bandwidth: Bandwidth) -> Storage {
+    let contents: Vec<u8> = (0..LEN).map(|i| i as u8).collect();
+    Storage::source_builder(IsoBuilder::new().file("/big.bin", &contents).build())
+        .bandwidth(bandwidth)
+        .build()
+}
+
+#[derive(Debug)]
+struct Named(&'static str);
+
+impl fmt::Display for Named {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl UserDetail for Named {}
+
+/// Downloads the file, returning how long it took.
+async fn download(storage: &Storage, user: &impl UserDetail) -> Duration {
+    let started = Instant::now();
+    let mut reader = storage.get(user, "/big.bin", 0).await.unwrap();
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).await.unwrap();
+    assert_eq!(contents.len(), LEN);
+    assert!(contents.iter().enumerate().all(|(i, &b)| b == i as u8));
+    started.elapsed()
+}
+
+#[tokio::test]
+async fn tiers() {
+    // A second's worth goes at once, the other half takes half a second
+    let storage = storage(Bandwidth::new(TieredBandwidth::new(Some(20_000), None)));
+    let anonymous = download(&storage, &Named("anonymous")).await;
+    assert!(anonymous >= Duration::from_millis(400), "{anonymous:?}");
+    let authenticated = download(&storage, &Named("alice")).await;
+    assert!(
+        authenticated < Duration::from_millis(400),
+        "{authenticated:?}"
+    );
+}
+
+#[tokio::test]
+async fn shared_by_the_downloads_of_a_user() {
+    let storage = storage(Bandwidth::new(TieredBandwidth::new(None, Some(40_000))));
+    let user = DefaultUser {};
+    let started = Instant::now();
+    let (first, second) = tokio::join!(download(&storage, &user), download(&storage, &user));
+    // 60 000 bytes at 40 000 a second, the first 40 000 at once
+    let both = started.elapsed();
+    assert!(both >= Duration::from_millis(400), "{first:?} {second:?}");
+}
+
+/// Classes of users told by a function of them.
+#[derive(Debug)]
+struct ByName;
+
+impl BandwidthClasses for ByName {
+    fn rate(&self, user: &dyn UserDetail) -> Option<u64> {
+        (user.to_string() == "slow").then_some(10_000)
+    }
+}
+
+#[tokio::test]
+async fn custom_classes() {
+    let storage = storage(Bandwidth::new(ByName));
+    let slow = download(&storage, &Named("slow")).await;
+    assert!(slow >= Duration::from_millis(1500), "{slow:?}");
+    let fast = download(&storage, &Named("fast")).await;
+    assert!(fast < Duration::from_millis(400), "{fast:?}");
+}
@@ -0,0 +1,90 @@
+//! Serving the El Torito boot images as files.
+
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{MemoryBudget, Storage, fixture::IsoBuilder};
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/readme.txt", b"bootable")
+        .boot_image(&[0xB1; 2048])
+        .efi_image(&[0xE1; 1536])
+        .build()
+}
+
+async fn get(storage: &Storage, path: &str, start: u64) -> Vec<u8> {
+    let mut contents = Vec::new();
+    storage
+        .get(&DefaultUser {}, path, start)
+        .await
+        .unwrap()
+        .read_to_end(&mut contents)
+        .await
+        .unwrap();
+    contents
+}
+
+#[tokio::test]
+async fn served() {
+    let storage = Storage::source_builder(image()).boot_images(true).build();
+    let user = DefaultUser {};
+    let mut names: Vec<_> = storage
+        .list(&user, "/")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| (f.path.to_str().unwrap().to_string(), f.metadata.len()))
+        .filter(|(name, _)| name.ends_with(".img"))
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            ("boot.img".to_string(), 2048),
+            ("efi.img".to_string(), 1536)
+        ]
+    );
+    assert_eq!(
+        storage.metadata(&user, "/efi.img").await.unwrap().len(),
+        1536
+    );
+    assert_eq!(get(&storage, "/boot.img", 0).await, [0xB1; 2048]);
+    assert_eq!(get(&storage, "/efi.img", 1000).await, [0xE1; 536]);
+    assert_eq!(get(&storage, "/readme.txt", 0).await, b"bootable");
+}
+
+#[tokio::test]
+async fn off_by_default() {
+    let storage = Storage::from_source(image());
+    assert!(storage.get(&DefaultUser {}, "/boot.img", 0).await.is_err());
+    assert_eq!(storage.list(&DefaultUser {}, "/").await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn files_take_precedence() {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/boot.img", b"a file")
+        .boot_image(&[0xB1; 2048])
+        .build();
+    let storage = Storage::source_builder(image).boot_images(true).build();
+    assert_eq!(get(&storage, "/boot.img", 0).await, b"a file");
+    assert_eq!(storage.list(&DefaultUser {}, "/").await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn streamed() {
+    // Larger than the budget allows holding in memory
+    let boot: Vec<u8> = (0..2 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let image = IsoBuilder::new().joliet(true).boot_image(&boot).build();
+    let storage = Storage::source_builder(image)
+        .boot_images(true)
+        .memory_budget(MemoryBudget::new(512 * 1024).shares(0, 0, 1))
+        .build();
+    assert_eq!(get(&storage, "/boot.img", 0).await, boot);
+    assert_eq!(get(&storage, "/boot.img", 1000).await, boot[1000..]);
+}
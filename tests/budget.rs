@@ -0,0 +1,100 @@
+//! Keeping the caches and downloads of back-ends within a shared memory budget.
+
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{MemoryBudget, Storage, fixture::IsoBuilder};
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .file("/big.bin", &[7; 3000])
+        .file("/small.txt", b"small")
+        .build()
+}
+
+#[tokio::test]
+async fn downloads_share_the_budget() {
+    let budget = MemoryBudget::new(4096).shares(0, 0, 1);
+    let first = Storage::source_builder(image())
+        .memory_budget(budget.clone())
+        .build();
+    let second = Storage::source_builder(image())
+        .memory_budget(budget.clone())
+        .build();
+    let user = DefaultUser {};
+
+    let held = first.get(&user, "/big.bin", 0).await.unwrap();
+    assert_eq!(budget.used(), 3000);
+    let e = second.get(&user, "/big.bin", 0).await.err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::TransientFileNotAvailable);
+    // What is left still fits a small file, and a resumed download only needs the rest
+    let mut contents = String::new();
+    second
+        .get(&user, "/small.txt", 0)
+        .await
+        .unwrap()
+        .read_to_string(&mut contents)
+        .await
+        .unwrap();
+    assert_eq!(contents, "small");
+    let mut rest = Vec::new();
+    second
+        .get(&user, "/big.bin", 2000)
+        .await
+        .unwrap()
+        .read_to_end(&mut rest)
+        .await
+        .unwrap();
+    assert_eq!(rest, [7; 1000]);
+
+    drop(held);
+    assert_eq!(budget.used(), 0);
+    assert!(second.get(&user, "/big.bin", 0).await.is_ok());
+}
+
+#[tokio::test]
+async fn caches_stay_within_their_share() {
+    let budget = MemoryBudget::new(1024 * 1024).shares(1, 1, 2);
+    let storage = Storage::source_builder(image())
+        .memory_budget(budget.clone())
+        .build();
+    let user = DefaultUser {};
+    for path in ["/big.bin", "/small.txt", "/big.bin"] {
+        let mut contents = Vec::new();
+        storage
+            .get(&user, path, 0)
+            .await
+            .unwrap()
+            .read_to_end(&mut contents)
+            .await
+            .unwrap();
+    }
+    let stats = storage.cache_stats();
+    assert!(stats.blocks.misses > 0);
+    assert_eq!(stats.contents.hits, 1);
+    // The blocks and contents cached, and nothing for downloads that are done
+    assert!(budget.used() > 0);
+    assert!(budget.used() <= 512 * 1024);
+
+    let none = MemoryBudget::new(1024).shares(1, 1, 0);
+    let storage = Storage::source_builder(image()).memory_budget(none).build();
+    let e = storage.get(&user, "/small.txt", 0).await.err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::TransientFileNotAvailable);
+}
+
+#[tokio::test]
+async fn streamed_downloads_only_take_their_buffers() {
+    let contents: Vec<u8> = (0..1024 * 1024u32).map(|i| i as u8).collect();
+    let image = IsoBuilder::new().file("/large.bin", &contents).build();
+    let budget = MemoryBudget::new(512 * 1024).shares(0, 0, 1);
+    let storage = Storage::source_builder(image)
+        .memory_budget(budget.clone())
+        .build();
+    let mut reader = storage.get(&DefaultUser {}, "/large.bin", 0).await.unwrap();
+    assert!(budget.used() < 512 * 1024);
+    let mut read = Vec::new();
+    reader.read_to_end(&mut read).await.unwrap();
+    assert!(read == contents);
+}
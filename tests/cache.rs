@@ -0,0 +1,301 @@
+//! Caching parts of the image, listings and file contents.
+
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{CacheConfig, CacheCounters, IsoSource, Storage, fixture::IsoBuilder};
+
+struct Counting {
+    image: Vec<u8>,
+    reads: AtomicUsize,
+}
+
+impl Counting {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            image: IsoBuilder::new()
+                .joliet(true)
+                .file("/docs/readme.txt", b"Hello from the cache")
+                .build(),
+            reads: AtomicUsize::new(0),
+        })
+    }
+
+    fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+}
+
+impl IsoSource for Counting {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.image.read_at(offset, buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.image.len() as u64)
+    }
+}
+
+async fn get(storage: &Storage, path: &str, start: u64) -> String {
+    let mut contents = String::new();
+    storage
+        .get(&DefaultUser {}, path, start)
+        .await
+        .unwrap()
+        .read_to_string(&mut contents)
+        .await
+        .unwrap();
+    contents
+}
+
+#[tokio::test]
+async fn block_cache() {
+    let source = Counting::new();
+    let storage = Storage::source_builder(source.clone())
+        .cache(CacheConfig {
+            block_cache_mb: 1,
+            ..Default::default()
+        })
+        .build();
+    assert!(storage.cwd(&DefaultUser {}, "/docs").await.is_ok());
+    let reads = source.reads();
+    assert!(reads > 0);
+    assert!(storage.cwd(&DefaultUser {}, "/docs").await.is_ok());
+    assert_eq!(
+        get(&storage, "/docs/readme.txt", 0).await,
+        "Hello from the cache"
+    );
+    assert_eq!(source.reads(), reads);
+}
+
+#[tokio::test]
+async fn listing_cache() {
+    let source = Counting::new();
+    let storage = Storage::source_builder(source.clone())
+        .cache(CacheConfig {
+            listing_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .build();
+    let listed = storage.list(&DefaultUser {}, "/docs").await.unwrap().len();
+    let reads = source.reads();
+    assert_eq!(
+        storage.list(&DefaultUser {}, "docs/").await.unwrap().len(),
+        listed
+    );
+    assert_eq!(source.reads(), reads);
+}
+
+#[tokio::test]
+async fn content_cache() {
+    let source = Counting::new();
+    let storage = Storage::source_builder(source.clone())
+        .cache(CacheConfig {
+            content_cache_mb: 1,
+            ..Default::default()
+        })
+        .build();
+    assert_eq!(get(&storage, "/docs/readme.txt", 6).await, "from the cache");
+    let reads = source.reads();
+    assert_eq!(
+        get(&storage, "/docs/readme.txt", 0).await,
+        "Hello from the cache"
+    );
+    assert_eq!(get(&storage, "/docs/readme.txt", 11).await, "the cache");
+    assert_eq!(source.reads(), reads);
+    let stats = storage.cache_stats();
+    assert_eq!((stats.contents.hits, stats.contents.misses), (2, 1));
+    assert_eq!(stats.blocks, CacheCounters::default());
+}
+
+#[tokio::test]
+async fn content_cache_evictions() {
+    // Eight files fit in the megabyte, the ninth makes one of them go
+    const LEN: usize = 120 * 1024;
+    let image = (0..9u8)
+        .fold(IsoBuilder::new(), |builder, i| {
+            builder.file(&format!("/f{i}.bin"), &vec![i; LEN])
+        })
+        .build();
+    let source = Arc::new(Counting {
+        image,
+        reads: AtomicUsize::new(0),
+    });
+    let storage = Storage::source_builder(source.clone())
+        .cache(CacheConfig {
+            content_cache_mb: 1,
+            ..Default::default()
+        })
+        .build();
+    let read = |i: u8| {
+        let storage = storage.clone();
+        async move {
+            let mut contents = Vec::new();
+            let path = format!("/f{i}.bin");
+            let mut reader = storage.get(&DefaultUser {}, path, 0).await.unwrap();
+            reader.read_to_end(&mut contents).await.unwrap();
+            assert_eq!(contents, vec![i; LEN]);
+        }
+    };
+    for i in 0..8 {
+        read(i).await;
+    }
+    assert_eq!(storage.cache_stats().contents.evictions, 0);
+    // The hit makes the first file the most recently used, so the second goes in its place
+    read(0).await;
+    read(8).await;
+    let stats = storage.cache_stats().contents;
+    assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 9, 1));
+    let reads = source.reads();
+    read(0).await;
+    assert_eq!(source.reads(), reads);
+    read(1).await;
+    assert!(source.reads() > reads);
+    let stats = storage.cache_stats().contents;
+    assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 10, 2));
+}
+
+#[tokio::test]
+async fn replaced_file() {
+    let image = |contents: &[u8]| {
+        IsoBuilder::new()
+            .joliet(true)
+            .file("/docs/readme.txt", contents)
+            .build()
+    };
+    let path = std::env::temp_dir().join(format!("unftp-sbe-iso-cache-{}.iso", std::process::id()));
+    std::fs::write(&path, image(b"first")).unwrap();
+    let storage = Storage::builder(&path)
+        .cache(CacheConfig {
+            block_cache_mb: 1,
+            listing_ttl: Some(Duration::from_secs(60)),
+            content_cache_mb: 1,
+            ..Default::default()
+        })
+        .build();
+    let clone = storage.clone();
+    assert_eq!(get(&storage, "/docs/readme.txt", 0).await, "first");
+    assert_eq!(get(&clone, "/docs/readme.txt", 0).await, "first");
+    assert_eq!(storage.cache_stats().contents.hits, 1);
+
+    let replacement = path.with_extension("new");
+    std::fs::write(&replacement, image(b"second one")).unwrap();
+    std::fs::rename(&replacement, &path).unwrap();
+    assert_eq!(get(&clone, "/docs/readme.txt", 0).await, "second one");
+    let listed = clone.list(&DefaultUser {}, "/docs").await.unwrap();
+    assert!(listed.iter().any(|f| f.metadata.len() == 10));
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// An image that can be regenerated in place.
+struct Mutable(std::sync::Mutex<Vec<u8>>);
+
+impl IsoSource for Mutable {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().read_at(offset, buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.0.lock().unwrap().len() as u64)
+    }
+}
+
+#[tokio::test]
+async fn refresh() {
+    let image = |contents: &[u8]| IsoBuilder::new().file("/readme.txt", contents).build();
+    let source = Arc::new(Mutable(std::sync::Mutex::new(image(b"first"))));
+    let storage = Storage::source_builder(source.clone())
+        .cache(CacheConfig {
+            block_cache_mb: 1,
+            content_cache_mb: 1,
+            ..Default::default()
+        })
+        .build();
+    let refresh = storage.refresh_every(Duration::from_millis(10));
+    // Let the task take note of the image as it is
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    assert_eq!(get(&storage, "/readme.txt", 0).await, "first");
+
+    // Same size, but a later modification date
+    let mut regenerated = image(b"again");
+    regenerated[16 * 2048 + 830..][..4].copy_from_slice(b"2099");
+    *source.0.lock().unwrap() = regenerated;
+    assert_eq!(get(&storage, "/readme.txt", 0).await, "first");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(get(&storage, "/readme.txt", 0).await, "again");
+
+    drop(storage);
+    tokio::time::timeout(Duration::from_secs(1), refresh)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn resumed_downloads() {
+    let source = Counting::new();
+    let storage = Storage::from_source(source.clone());
+    let before = source.reads();
+    assert_eq!(
+        get(&storage, "/docs/readme.txt", 0).await,
+        "Hello from the cache"
+    );
+    let first = source.reads() - before;
+
+    // Resuming reads the contents and nothing else
+    let before = source.reads();
+    assert_eq!(get(&storage, "/docs/readme.txt", 6).await, "from the cache");
+    assert_eq!(get(&storage, "/docs/readme.txt", 20).await, "");
+    assert!(source.reads() - before < first);
+    let stats = storage.cache_stats();
+    assert_eq!((stats.sessions.hits, stats.sessions.misses), (2, 1));
+}
+
+#[tokio::test]
+async fn resumed_downloads_of_a_replaced_file() {
+    let image = |contents: &[u8]| IsoBuilder::new().file("/readme.txt", contents).build();
+    let source = Arc::new(Mutable(std::sync::Mutex::new(image(b"first"))));
+    let storage = Storage::from_source(source.clone());
+    let refresh = storage.refresh_every(Duration::from_millis(10));
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    assert_eq!(get(&storage, "/readme.txt", 0).await, "first");
+
+    let mut regenerated = image(b"again");
+    regenerated[16 * 2048 + 830..][..4].copy_from_slice(b"2099");
+    *source.0.lock().unwrap() = regenerated;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(get(&storage, "/readme.txt", 2).await, "ain");
+
+    drop(storage);
+    tokio::time::timeout(Duration::from_secs(1), refresh)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn coalesced_reads() {
+    let source = Counting::new();
+    let storage = Storage::source_builder(source.clone())
+        .coalesce_reads(1024 * 1024)
+        .build();
+    assert!(storage.cwd(&DefaultUser {}, "/docs").await.is_ok());
+    assert_eq!(
+        get(&storage, "/docs/readme.txt", 0).await,
+        "Hello from the cache"
+    );
+    // The whole image at once, and the read that finds its end
+    assert!(source.reads() <= 2, "{} reads", source.reads());
+    assert!(storage.cache_stats().blocks.hits > 0);
+}
@@ -0,0 +1,66 @@
+//! Keeping the indexes of directories on disk.
+
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+async fn names(storage: &Storage, path: &str) -> Vec<String> {
+    let listed = storage.list(&DefaultUser {}, path).await.unwrap();
+    let mut names: Vec<String> = listed
+        .iter()
+        .map(|f| f.path.to_string_lossy().into_owned())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn indexes_survive_restarts() {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/a.txt", b"a")
+        .file("/docs/b.txt", b"b")
+        .file("/src/main.c", b"int main;")
+        .build_file();
+    let dir =
+        std::env::temp_dir().join(format!("unftp-sbe-iso-catalog-test-{}", std::process::id()));
+    let catalog = dir.join("catalog.sqlite");
+    let storage = || Storage::builder(image.path()).catalog(&catalog).build();
+
+    let lookups = async |storage: &Storage| {
+        assert_eq!(names(storage, "/docs").await, ["a.txt", "b.txt"]);
+        let user = DefaultUser {};
+        let metadata = storage.metadata(&user, "/src/main.c").await;
+        assert_eq!(metadata.unwrap().len, 9);
+        assert!(storage.metadata(&user, "/docs/b.txt").await.is_ok());
+    };
+    let first = storage();
+    lookups(&first).await;
+    let written = std::fs::read(&catalog).unwrap();
+    drop(first);
+
+    // Nothing is added for the directories indexed before
+    let second = storage();
+    lookups(&second).await;
+    assert_eq!(std::fs::read(&catalog).unwrap(), written);
+    drop(second);
+
+    // An image rebuilt with a file renamed keeps its size and volume descriptors, but not its
+    // indexes
+    let rebuilt = IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/a.txt", b"a")
+        .file("/docs/c.txt", b"c")
+        .file("/src/main.c", b"int main;")
+        .build();
+    let before = std::fs::read(image.path()).unwrap();
+    assert_eq!(rebuilt.len(), before.len());
+    assert_eq!(rebuilt[16 * 2048..17 * 2048], before[16 * 2048..17 * 2048]);
+    std::fs::write(image.path(), rebuilt).unwrap();
+    let third = storage();
+    assert_eq!(names(&third, "/docs").await, ["a.txt", "c.txt"]);
+    let user = DefaultUser {};
+    assert!(third.metadata(&user, "/docs/c.txt").await.is_ok());
+    assert!(third.metadata(&user, "/docs/b.txt").await.is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,140 @@
+//! A minimal FTP client and server harness for the end-to-end tests.
+
+#![allow(dead_code)]
+
+use libunftp::ServerBuilder;
+use std::net::SocketAddr;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream, tcp::OwnedWriteHalf},
+};
+use unftp_sbe_iso::Storage;
+
+/// Serves the given back-end over FTP on an ephemeral port and returns its address. Every
+/// connection gets a clone of `storage`.
+pub async fn serve(storage: Storage) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let server = ServerBuilder::new(storage.factory())
+                .passive_ports(49152..=65535)
+                .build()
+                .unwrap();
+            tokio::spawn(server.service(stream));
+        }
+    });
+    addr
+}
+
+/// A reply from the server: the three digit code and the text of its last line.
+#[derive(Debug)]
+pub struct Reply {
+    pub code: u16,
+    pub text: String,
+}
+
+impl Reply {
+    pub fn is_success(&self) -> bool {
+        (200..400).contains(&self.code)
+    }
+}
+
+pub struct Client {
+    reader: BufReader<tokio::net::tcp::OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl Client {
+    /// Connects and logs in anonymously.
+    pub async fn login(addr: SocketAddr) -> Client {
+        let (read, write) = TcpStream::connect(addr).await.unwrap().into_split();
+        let mut client = Client {
+            reader: BufReader::new(read),
+            writer: write,
+        };
+        assert_eq!(client.reply().await.code, 220);
+        assert_eq!(client.cmd("USER anonymous").await.code, 331);
+        assert_eq!(client.cmd("PASS test@example.com").await.code, 230);
+        assert_eq!(client.cmd("TYPE I").await.code, 200);
+        client
+    }
+
+    async fn reply(&mut self) -> Reply {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).await.unwrap();
+        let code: u16 = line[..3].parse().unwrap();
+        if line.as_bytes()[3] == b'-' {
+            let end = format!("{code} ");
+            loop {
+                line.clear();
+                self.reader.read_line(&mut line).await.unwrap();
+                if line.starts_with(&end) {
+                    break;
+                }
+            }
+        }
+        Reply {
+            code,
+            text: line[4..].trim_end().to_string(),
+        }
+    }
+
+    /// Sends a command over the control connection and waits for the reply.
+    pub async fn cmd(&mut self, cmd: &str) -> Reply {
+        self.writer
+            .write_all(format!("{cmd}\r\n").as_bytes())
+            .await
+            .unwrap();
+        self.reply().await
+    }
+
+    async fn passive(&mut self) -> TcpStream {
+        let reply = self.cmd("PASV").await;
+        assert_eq!(reply.code, 227, "{reply:?}");
+        let start = reply.text.find('(').unwrap() + 1;
+        let end = reply.text.find(')').unwrap();
+        let n: Vec<u16> = reply.text[start..end]
+            .split(',')
+            .map(|n| n.parse().unwrap())
+            .collect();
+        let addr = format!("{}.{}.{}.{}:{}", n[0], n[1], n[2], n[3], n[4] * 256 + n[5]);
+        TcpStream::connect(addr).await.unwrap()
+    }
+
+    /// Runs a command that transfers data over a passive connection. Returns the data, or the
+    /// failing reply.
+    pub async fn transfer(&mut self, cmd: &str) -> Result<Vec<u8>, Reply> {
+        let mut data = self.passive().await;
+        let reply = self.cmd(cmd).await;
+        if reply.code != 150 && reply.code != 125 {
+            return Err(reply);
+        }
+        let mut contents = Vec::new();
+        data.read_to_end(&mut contents).await.unwrap();
+        let reply = self.reply().await;
+        if reply.code != 226 {
+            return Err(reply);
+        }
+        Ok(contents)
+    }
+
+    /// Returns the names in the LIST output for `path`, leaving out `.` and `..`.
+    pub async fn list(&mut self, path: &str) -> Result<Vec<String>, Reply> {
+        let cmd = if path.is_empty() {
+            "LIST".to_string()
+        } else {
+            format!("LIST {path}")
+        };
+        let listing = String::from_utf8(self.transfer(&cmd).await?).unwrap();
+        Ok(listing
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(8).map(str::to_string))
+            .filter(|name| name != "." && name != "..")
+            .collect())
+    }
+
+    pub async fn retr(&mut self, path: &str) -> Result<Vec<u8>, Reply> {
+        self.transfer(&format!("RETR {path}")).await
+    }
+}
@@ -0,0 +1,45 @@
+//! Reporting how closely images keep to ISO 9660.
+
+use unftp_sbe_iso::{Storage, ViolationKind, fixture::IsoBuilder};
+
+#[tokio::test]
+async fn levels_and_violations() {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/readme.txt", b"hello")
+        .file("/setup.exe", b"MZ")
+        .build();
+    let report = Storage::from_source(image).conformance().await.unwrap();
+    assert!(report.conforms(), "{:?}", report.violations);
+    assert_eq!(report.interchange_level, 1);
+    assert_eq!(report.joliet, Some(3));
+    assert!(!report.rock_ridge);
+    assert_eq!(report.max_depth, 2);
+    assert_eq!(report.max_path_len, "DOCS/README.TXT;1".len());
+
+    let deep = "/a/b/c/d/e/f/g/h/file.txt";
+    let image = IsoBuilder::new()
+        .rock_ridge(true)
+        .file("/release_notes_for_version_two.html", b"notes")
+        .file(deep, b"deep")
+        .file("/setup.exe", b"MZ")
+        .primary_name("/setup.exe", "setup.exe")
+        .build();
+    let report = Storage::from_source(image).conformance().await.unwrap();
+    assert_eq!(report.interchange_level, 2);
+    assert!(report.rock_ridge);
+    assert_eq!(report.max_depth, 9);
+    let kinds: Vec<_> = report.violations.iter().map(|v| &v.kind).collect();
+    assert!(kinds.contains(&&ViolationKind::Depth(9)), "{kinds:?}");
+    assert!(kinds.contains(&&ViolationKind::Characters), "{kinds:?}");
+    assert!(kinds.contains(&&ViolationKind::NoVersion), "{kinds:?}");
+    let setup = report
+        .violations
+        .iter()
+        .find(|v| v.kind == ViolationKind::NoVersion)
+        .unwrap();
+    assert_eq!(
+        setup.to_string(),
+        "/setup.exe: file identifier without a version"
+    );
+}
@@ -0,0 +1,62 @@
+//! Crafted images whose directory records point back at an ancestor.
+
+use std::path::Path;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+/// Builds an image in which `/LOOP/INNER` shares its extent with the root directory.
+fn cyclic_image(path: &std::path::Path) {
+    let mut image = IsoBuilder::new()
+        .file("/loop/inner/file.txt", b"unreachable")
+        .build();
+    // The extent location and data length of the root directory record in the primary volume
+    // descriptor, both in both-endian form.
+    let root = 16 * 2048 + 156;
+    let extent = image[root + 2..root + 18].to_vec();
+    let record = (0..image.len() - 38)
+        .find(|&r| {
+            image[r + 25] & 2 != 0 && image[r + 32] == 5 && &image[r + 33..r + 38] == b"INNER"
+        })
+        .expect("no directory record for INNER");
+    image[record + 2..record + 18].copy_from_slice(&extent);
+    std::fs::write(path, image).unwrap();
+}
+
+#[tokio::test]
+async fn rejects_directory_cycles() {
+    let path = std::env::temp_dir().join(format!("unftp-sbe-iso-cycle-{}.iso", std::process::id()));
+    cyclic_image(&path);
+    let storage = Storage::new(&path);
+    let user = DefaultUser {};
+
+    // The cycle itself can still be looked at, but not entered.
+    assert!(storage.list(&user, "/loop").await.is_ok());
+    for target in ["/loop/inner/loop", "/loop/inner/loop/inner/file.txt"] {
+        let Err(err) = storage.list(&user, target).await else {
+            panic!("{target} was listed");
+        };
+        let message = std::error::Error::source(&err).unwrap().to_string();
+        assert!(message.contains("its own ancestor"), "{target}: {message}");
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn recursive_listing_stops_at_cycles() {
+    let path = std::env::temp_dir().join(format!(
+        "unftp-sbe-iso-cycle-walk-{}.iso",
+        std::process::id()
+    ));
+    cyclic_image(&path);
+    let storage = Storage::new(&path);
+    let listing = storage.list_recursive("/").await.unwrap();
+    let names: Vec<_> = listing.iter().map(|f| f.path.to_str().unwrap()).collect();
+    assert_eq!(names, ["LOOP", "LOOP/INNER"]);
+    let mut walk = storage.walk("/");
+    let mut walked = Vec::new();
+    while let Some((path, _)) = walk.next_entry().await.unwrap() {
+        walked.push(path);
+    }
+    assert_eq!(walked, [Path::new("LOOP"), Path::new("LOOP/INNER")]);
+    std::fs::remove_file(path).unwrap();
+}
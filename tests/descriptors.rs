@@ -0,0 +1,54 @@
+//! Enumerating the volume descriptors.
+
+use std::time::{Duration, UNIX_EPOCH};
+use unftp_sbe_iso::{DescriptorKind, Storage, fixture::IsoBuilder};
+
+#[tokio::test]
+async fn primary_and_joliet() {
+    let image = IsoBuilder::new().volume_id("DATA").joliet(true).build();
+    let storage = Storage::from_source(image);
+    let descriptors = storage.volume_descriptors().await.unwrap();
+    assert_eq!(descriptors.len(), 2);
+    let DescriptorKind::Primary(primary) = &descriptors[0].kind else {
+        panic!("{:?} is not the primary volume", descriptors[0].kind);
+    };
+    assert_eq!(descriptors[0].sector, 16);
+    assert_eq!(primary.volume_id, "DATA");
+    assert_eq!(primary.logical_block_size, 2048);
+    assert_eq!(primary.application_id, "UNFTP-SBE-ISO FIXTURE");
+    assert_eq!(primary.joliet_level(), None);
+    let recorded = UNIX_EPOCH + Duration::from_secs(1_704_164_645);
+    assert_eq!(primary.created, Some(recorded));
+    let DescriptorKind::Supplementary(joliet) = &descriptors[1].kind else {
+        panic!("{:?} is not the Joliet volume", descriptors[1].kind);
+    };
+    assert_eq!(joliet.volume_id, "DATA");
+    assert_eq!(joliet.joliet_level(), Some(3));
+    assert_eq!(descriptors[1].raw.len(), 2048);
+}
+
+#[tokio::test]
+async fn greetings() {
+    let image = IsoBuilder::new()
+        .volume_id("UBUNTU_22_04")
+        .file("/readme.txt", &[b'x'; 2 << 20])
+        .build();
+    let storage = Storage::from_source(image);
+    let greeting = storage.suggested_greeting().await.unwrap();
+    assert!(
+        greeting.starts_with("Serving 'UBUNTU_22_04' (created 2024-01-02, 2."),
+        "{greeting}"
+    );
+    assert!(greeting.ends_with(" MB)"), "{greeting}");
+
+    let image = IsoBuilder::new().volume_id("").joliet(true).build();
+    let greeting = Storage::from_source(image).suggested_greeting().await;
+    assert!(greeting.unwrap().starts_with("Serving an unnamed volume"));
+}
+
+#[tokio::test]
+async fn not_an_image() {
+    let storage = Storage::from_source(vec![0; 40 * 2048]);
+    assert!(storage.volume_descriptors().await.is_err());
+    assert!(storage.suggested_greeting().await.is_err());
+}
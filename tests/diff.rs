@@ -0,0 +1,94 @@
+//! Comparing the trees of two images.
+
+use std::path::PathBuf;
+use unftp_sbe_iso::{ChangeKind, Storage, diff, fixture::IsoBuilder};
+
+fn base() -> IsoBuilder {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/install/vmlinuz", b"kernel 1")
+        .file("/install/initrd.gz", b"initrd")
+        .file("/README.txt", b"read me")
+        .file("/old.txt", b"gone soon")
+}
+
+fn paths(paths: &[&str]) -> Vec<PathBuf> {
+    paths.iter().map(PathBuf::from).collect()
+}
+
+#[tokio::test]
+async fn identical() {
+    let old = Storage::from_source(base().build());
+    let new = Storage::from_source(base().build());
+    assert!(diff(&old, &new).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn changes() {
+    let old = Storage::from_source(base().build());
+    let new = Storage::from_source(
+        IsoBuilder::new()
+            .joliet(true)
+            .file("/install/vmlinuz", b"kernel 1.1")
+            .file("/install/initrd.gz", b"initrd")
+            .file("/README.txt", b"read me")
+            .recorded("/README.txt", 2024, 3, 4)
+            .file("/old.txt/now-a-directory.txt", b"")
+            .file("/tools/new.bin", b"new")
+            .build(),
+    );
+    let report = diff(&old, &new).await.unwrap();
+    assert_eq!(
+        report.added,
+        paths(&["old.txt/now-a-directory.txt", "tools", "tools/new.bin"])
+    );
+    assert!(report.removed.is_empty());
+    let changed: Vec<String> = report.changed.iter().map(|c| c.to_string()).collect();
+    assert_eq!(
+        changed,
+        [
+            "README.txt: modified 2024-01-02 03:04:05 -> 2024-03-04 00:00:00",
+            "install/vmlinuz: size 8 -> 10",
+            "old.txt: type changed",
+        ]
+    );
+    assert_eq!(
+        report.changed[1].kinds,
+        [ChangeKind::Size { old: 8, new: 10 }]
+    );
+
+    // The other way round
+    let report = diff(&new, &old).await.unwrap();
+    assert!(report.added.is_empty());
+    assert_eq!(report.removed.len(), 3);
+    assert_eq!(report.changed.len(), 3);
+}
+
+#[cfg(feature = "checksums")]
+#[tokio::test]
+async fn hashed() {
+    use unftp_sbe_iso::{HashAlgorithm, diff_with_hash};
+
+    let old = Storage::from_source(base().build());
+    let new = Storage::from_source(
+        base()
+            .file("/install/vmlinuz", b"kernel 2")
+            .recorded("/README.txt", 2024, 3, 4)
+            .build(),
+    );
+    // Same sizes and times
+    let report = diff(&old, &new).await.unwrap();
+    assert_eq!(report.changed.len(), 1);
+    let report = diff_with_hash(&old, &new, HashAlgorithm::Sha256)
+        .await
+        .unwrap();
+    let changed: Vec<(&str, &[ChangeKind])> = report
+        .changed
+        .iter()
+        .map(|c| (c.path.to_str().unwrap(), &c.kinds[..]))
+        .collect();
+    assert_eq!(changed[1], ("install/vmlinuz", &[ChangeKind::Contents][..]));
+    // Touched, not rebuilt
+    assert_eq!(changed[0].0, "README.txt");
+    assert!(!changed[0].1.contains(&ChangeKind::Contents));
+}
@@ -0,0 +1,222 @@
+//! Serving every image in a directory.
+
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{ImageNames, Storage, fixture::IsoBuilder};
+
+/// A directory of images, removed on drop.
+struct Images(PathBuf);
+
+impl Images {
+    fn new(test: &str, images: &[(&str, IsoBuilder)]) -> Self {
+        let name = format!("unftp-sbe-iso-{test}-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, image) in images {
+            std::fs::write(dir.join(name), image.build()).unwrap();
+        }
+        Images(dir)
+    }
+}
+
+impl Drop for Images {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn image(label: &str, contents: &[u8]) -> IsoBuilder {
+    IsoBuilder::new()
+        .joliet(true)
+        .volume_id(label)
+        .file("/docs/readme.txt", contents)
+}
+
+async fn names(storage: &Storage, path: &str) -> Vec<String> {
+    let listed = storage.list(&DefaultUser {}, path).await.unwrap();
+    listed
+        .iter()
+        .map(|f| f.path.to_string_lossy().into_owned())
+        .filter(|name| name != "." && name != "..")
+        .collect()
+}
+
+async fn get(storage: &Storage, path: &str) -> String {
+    let mut contents = String::new();
+    storage
+        .get(&DefaultUser {}, path, 0)
+        .await
+        .unwrap()
+        .read_to_string(&mut contents)
+        .await
+        .unwrap();
+    contents
+}
+
+#[tokio::test]
+async fn images_as_directories() {
+    let images = Images::new(
+        "directory",
+        &[
+            ("debian-12.iso", image("DEBIAN", b"debian")),
+            ("Ubuntu.ISO", image("UBUNTU", b"ubuntu")),
+        ],
+    );
+    std::fs::write(images.0.join("notes.txt"), b"not an image").unwrap();
+    std::fs::create_dir(images.0.join("empty.iso")).unwrap();
+    let storage = Storage::directory(&images.0);
+    let user = DefaultUser {};
+    assert_eq!(names(&storage, "/").await, ["Ubuntu", "debian-12"]);
+    assert_eq!(names(&storage, "/debian-12").await, ["docs"]);
+    assert_eq!(get(&storage, "/debian-12/docs/readme.txt").await, "debian");
+    assert_eq!(
+        get(&storage, "/ubuntu/docs/../docs/readme.txt").await,
+        "ubuntu"
+    );
+
+    assert!(storage.metadata(&user, "/").await.unwrap().is_dir());
+    assert!(storage.metadata(&user, "/Ubuntu").await.unwrap().is_dir());
+    let meta = storage
+        .metadata(&user, "/Ubuntu/docs/readme.txt")
+        .await
+        .unwrap();
+    assert_eq!(meta.len(), 6);
+    assert!(storage.cwd(&user, "/").await.is_ok());
+    assert!(storage.cwd(&user, "/debian-12/docs").await.is_ok());
+    assert!(storage.get(&user, "/", 0).await.is_err());
+    let e = storage.metadata(&user, "/notes").await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::PermanentFileNotAvailable);
+    let e = storage.cwd(&user, "/empty").await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::PermanentFileNotAvailable);
+    assert_eq!(names(&storage, "/ubuntu/docs/").await, ["readme.txt"]);
+    let e = storage
+        .list(&user, "/ubuntu/docs/readme.txt/")
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::PermanentDirectoryNotAvailable);
+
+    assert_eq!(
+        storage.stats()[Path::new("/debian-12/docs/readme.txt")].downloads,
+        1
+    );
+}
+
+#[tokio::test]
+async fn named_by_volume_label() {
+    let images = Images::new(
+        "labels",
+        &[
+            ("a.iso", image("INSTALL", b"a")),
+            ("b.iso", image("INSTALL", b"b")),
+            ("c.iso", image("", b"c")),
+        ],
+    );
+    let storage = Storage::directory_builder(&images.0)
+        .image_names(ImageNames::VolumeLabel)
+        .open()
+        .await
+        .unwrap();
+    // Labels that are taken or blank give way to the name of the file
+    assert_eq!(names(&storage, "/").await, ["INSTALL", "b", "c"]);
+    assert_eq!(get(&storage, "/INSTALL/docs/readme.txt").await, "a");
+    assert_eq!(get(&storage, "/b/docs/readme.txt").await, "b");
+}
+
+#[tokio::test]
+async fn missing_directory() {
+    let builder = Storage::directory_builder("/nonexistent/images");
+    assert!(builder.open().await.is_err());
+}
+
+#[tokio::test]
+async fn added_and_removed() {
+    let images = Images::new("rescans", &[("first.iso", image("FIRST", b"first"))]);
+    let storage = Storage::directory(&images.0);
+    assert_eq!(names(&storage, "/").await, ["first"]);
+
+    std::fs::write(
+        images.0.join("second.iso"),
+        image("SECOND", b"second").build(),
+    )
+    .unwrap();
+    assert_eq!(names(&storage, "/").await, ["first", "second"]);
+    assert_eq!(get(&storage, "/second/docs/readme.txt").await, "second");
+
+    std::fs::remove_file(images.0.join("first.iso")).unwrap();
+    assert_eq!(names(&storage, "/").await, ["second"]);
+    let e = storage
+        .metadata(&DefaultUser {}, "/first/docs/readme.txt")
+        .await
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::PermanentFileNotAvailable);
+}
+
+#[tokio::test]
+async fn changed_view() {
+    let v1 = IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/readme.txt", b"read me")
+        .file("/install/vmlinuz", b"kernel 1")
+        .file("/install/initrd.gz", b"initrd")
+        .file("/gone.txt", b"gone");
+    let v2 = IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/readme.txt", b"read me")
+        .file("/install/vmlinuz", b"kernel 1.1")
+        .file("/install/initrd.gz", b"initrd")
+        .file("/tools/new/tool.bin", b"new");
+    let images = Images::new("changed", &[("v1.iso", v1), ("v2.iso", v2)]);
+    let storage = Storage::directory_builder(&images.0)
+        .changed_view("v1", "v2")
+        .build();
+    let user = DefaultUser {};
+    assert_eq!(names(&storage, "/").await, ["v1", "v2", "CHANGED"]);
+    let mut changed = names(&storage, "/CHANGED").await;
+    changed.sort();
+    assert_eq!(changed, ["install", "tools"]);
+    assert_eq!(names(&storage, "/changed/install").await, ["vmlinuz"]);
+    assert_eq!(names(&storage, "/CHANGED/tools/new").await, ["tool.bin"]);
+    assert_eq!(
+        get(&storage, "/CHANGED/install/vmlinuz").await,
+        "kernel 1.1"
+    );
+    assert!(
+        storage
+            .metadata(&user, "/CHANGED/tools")
+            .await
+            .unwrap()
+            .is_dir()
+    );
+    assert!(storage.cwd(&user, "/CHANGED/install").await.is_ok());
+    for unchanged in [
+        "/CHANGED/docs",
+        "/CHANGED/install/initrd.gz",
+        "/CHANGED/gone.txt",
+    ] {
+        let e = storage.metadata(&user, unchanged).await.unwrap_err();
+        assert_eq!(
+            e.kind(),
+            ErrorKind::PermanentFileNotAvailable,
+            "{unchanged}"
+        );
+    }
+
+    // Rebuilding an image compares them again
+    let v2 = IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/readme.txt", b"read me, again")
+        .file("/install/vmlinuz", b"kernel 1")
+        .file("/install/initrd.gz", b"initrd");
+    std::fs::write(images.0.join("v2.iso"), v2.build()).unwrap();
+    assert_eq!(names(&storage, "/CHANGED").await, ["docs"]);
+
+    // Without both images there is no view
+    std::fs::remove_file(images.0.join("v1.iso")).unwrap();
+    assert_eq!(names(&storage, "/").await, ["v2"]);
+    assert!(storage.metadata(&user, "/CHANGED").await.is_err());
+}
@@ -0,0 +1,224 @@
+//! Keeping the blocks of a remote image on local disk.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{AsyncIsoSource, DiskCache, Storage, fixture::IsoBuilder};
+
+const BLOCK: usize = 64 * 1024;
+
+/// An image behind an imaginary network, counting the ranges fetched.
+#[derive(Clone)]
+struct Remote {
+    image: Arc<Vec<u8>>,
+    reads: Arc<AtomicUsize>,
+}
+
+impl Remote {
+    fn new(image: Vec<u8>) -> Self {
+        Self {
+            image: Arc::new(image),
+            reads: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncIsoSource for Remote {
+    async fn read_range(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        let start = (offset as usize).min(self.image.len());
+        let end = (start + len).min(self.image.len());
+        Ok(self.image[start..end].to_vec())
+    }
+
+    async fn len(&self) -> io::Result<u64> {
+        Ok(self.image.len() as u64)
+    }
+}
+
+/// A cache directory, removed on drop.
+struct Dir(PathBuf);
+
+impl Dir {
+    fn new(test: &str) -> Self {
+        let name = format!("unftp-sbe-iso-disk-cache-{test}-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        Dir(dir)
+    }
+
+    fn blocks(&self) -> usize {
+        std::fs::read_dir(&self.0)
+            .unwrap()
+            .filter(|entry| {
+                let path = entry.as_ref().unwrap().path();
+                path.extension().is_some_and(|ext| ext == "blk")
+            })
+            .count()
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn bytes(len: usize) -> Vec<u8> {
+    (0..len as u32).map(|i| (i % 251) as u8).collect()
+}
+
+fn cache(remote: &Remote, dir: &Path) -> DiskCache<Remote> {
+    DiskCache::new(remote.clone(), dir)
+}
+
+#[tokio::test]
+async fn ranges_survive_restarts() {
+    let dir = Dir::new("ranges");
+    let image = bytes(5 * BLOCK + 100);
+    let remote = Remote::new(image.clone());
+    let cached = cache(&remote, &dir.0);
+    assert_eq!(cached.len().await.unwrap(), image.len() as u64);
+    // Across blocks, fetched in one request
+    let range = cached
+        .read_range(BLOCK as u64 - 10, 2 * BLOCK)
+        .await
+        .unwrap();
+    assert_eq!(range, image[BLOCK - 10..3 * BLOCK - 10]);
+    assert_eq!((remote.reads(), dir.blocks()), (1, 3));
+    // Only the missing blocks are fetched
+    let range = cached.read_range(0, 5 * BLOCK).await.unwrap();
+    assert_eq!(range, image[..5 * BLOCK]);
+    assert_eq!(remote.reads(), 2);
+    // The end of the image
+    let tail = cached.read_range(5 * BLOCK as u64, 1000).await.unwrap();
+    assert_eq!(tail, image[5 * BLOCK..]);
+    assert!(
+        cached
+            .read_range(image.len() as u64, 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(remote.reads(), 3);
+
+    let restarted = cache(&remote, &dir.0);
+    let all = restarted.read_range(0, image.len()).await.unwrap();
+    assert_eq!(all, image);
+    assert_eq!(remote.reads(), 3);
+}
+
+#[tokio::test]
+async fn capped() {
+    let dir = Dir::new("capped");
+    let image = bytes(6 * BLOCK);
+    let remote = Remote::new(image.clone());
+    let cached = cache(&remote, &dir.0).max_bytes(2 * BLOCK as u64);
+    for index in [0, 1, 2, 3] {
+        cached.read_range((index * BLOCK) as u64, 10).await.unwrap();
+    }
+    assert_eq!(dir.blocks(), 2);
+    // Block 2 was used before 3, and goes when 0 comes back
+    cached.read_range(2 * BLOCK as u64, 10).await.unwrap();
+    cached.read_range(0, 10).await.unwrap();
+    assert_eq!(remote.reads(), 5);
+    cached.read_range(2 * BLOCK as u64, 10).await.unwrap();
+    assert_eq!(remote.reads(), 5);
+    cached.read_range(3 * BLOCK as u64, 10).await.unwrap();
+    assert_eq!(remote.reads(), 6);
+
+    // A smaller cap takes effect on the next start
+    let restarted = cache(&remote, &dir.0).max_bytes(BLOCK as u64);
+    restarted.read_range(3 * BLOCK as u64, 10).await.unwrap();
+    assert_eq!((remote.reads(), dir.blocks()), (6, 1));
+}
+
+#[tokio::test]
+async fn other_image() {
+    let dir = Dir::new("other");
+    let remote = Remote::new(bytes(2 * BLOCK));
+    cache(&remote, &dir.0)
+        .read_range(0, 2 * BLOCK)
+        .await
+        .unwrap();
+    assert_eq!(dir.blocks(), 2);
+
+    let other = Remote::new(vec![7; 3 * BLOCK]);
+    let cached = cache(&other, &dir.0);
+    assert_eq!(cached.read_range(0, 10).await.unwrap(), [7; 10]);
+    assert_eq!((other.reads(), dir.blocks()), (1, 1));
+}
+
+#[tokio::test]
+async fn cut_short() {
+    let dir = Dir::new("short");
+    let image = bytes(3 * BLOCK);
+    let remote = Remote::new(image.clone());
+    cache(&remote, &dir.0)
+        .read_range(0, 3 * BLOCK)
+        .await
+        .unwrap();
+    assert_eq!(remote.reads(), 1);
+    // A block written partly, as when the disk filled up
+    let block = dir.0.join(format!("{:016x}.blk", 1));
+    std::fs::write(&block, &image[BLOCK..BLOCK + 100]).unwrap();
+
+    let restarted = cache(&remote, &dir.0);
+    assert_eq!(restarted.read_range(0, 3 * BLOCK).await.unwrap(), image);
+    assert_eq!(remote.reads(), 2);
+    // And kept whole from then on
+    assert_eq!(std::fs::metadata(&block).unwrap().len(), BLOCK as u64);
+    assert_eq!(restarted.read_range(0, 3 * BLOCK).await.unwrap(), image);
+    assert_eq!(remote.reads(), 2);
+}
+
+#[tokio::test]
+async fn serving() {
+    let dir = Dir::new("serving");
+    let contents = bytes(200_000);
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/data/large.bin", &contents)
+        .build();
+    let remote = Remote::new(image);
+    let user = DefaultUser {};
+    for _ in 0..2 {
+        let storage = Storage::from_async_source(cache(&remote, &dir.0));
+        let mut read = Vec::new();
+        storage
+            .get(&user, "/data/large.bin", 0)
+            .await
+            .unwrap()
+            .read_to_end(&mut read)
+            .await
+            .unwrap();
+        assert_eq!(read, contents);
+    }
+    let first = remote.reads();
+    assert!(first > 0);
+
+    // A restart reads nothing more from the remote
+    let storage = Storage::from_async_source(cache(&remote, &dir.0));
+    let mut read = Vec::new();
+    storage
+        .get(&user, "/data/large.bin", 0)
+        .await
+        .unwrap()
+        .read_to_end(&mut read)
+        .await
+        .unwrap();
+    assert_eq!(read, contents);
+    assert_eq!(remote.reads(), first);
+}
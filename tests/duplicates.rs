@@ -0,0 +1,69 @@
+//! Directories holding several records with the same identifier.
+
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{Duplicates, Storage, fixture::IsoBuilder};
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .file("/a", b"small")
+        .primary_name("/a", "DATA.BIN;1")
+        .file("/b", b"the largest")
+        .primary_name("/b", "DATA.BIN;1")
+        .file("/c", b"last")
+        .primary_name("/c", "DATA.BIN;1")
+        .file("/other.txt", b"other")
+        .build()
+}
+
+async fn listed(storage: &Storage) -> Vec<String> {
+    let mut names: Vec<_> = storage
+        .list(&DefaultUser {}, "/")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| f.path.to_str().unwrap().to_string())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    names
+}
+
+async fn download(storage: &Storage, path: &str) -> String {
+    let mut reader = storage.get(&DefaultUser {}, path, 0).await.unwrap();
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents).await.unwrap();
+    contents
+}
+
+#[tokio::test]
+async fn winners() {
+    for (duplicates, expected) in [
+        (None, "small"),
+        (Some(Duplicates::First), "small"),
+        (Some(Duplicates::Last), "last"),
+        (Some(Duplicates::Largest), "the largest"),
+    ] {
+        let mut builder = Storage::source_builder(image());
+        if let Some(duplicates) = duplicates {
+            builder = builder.duplicates(duplicates);
+        }
+        let storage = builder.build();
+        assert_eq!(listed(&storage).await, ["DATA.BIN", "OTHER.TXT"]);
+        assert_eq!(download(&storage, "/data.bin").await, expected);
+    }
+}
+
+#[tokio::test]
+async fn suffix() {
+    let storage = Storage::source_builder(image())
+        .duplicates(Duplicates::Suffix)
+        .build();
+    assert_eq!(
+        listed(&storage).await,
+        ["DATA.BIN", "DATA.BIN~2", "DATA.BIN~3", "OTHER.TXT"]
+    );
+    assert_eq!(download(&storage, "/DATA.BIN").await, "small");
+    assert_eq!(download(&storage, "/DATA.BIN~2").await, "the largest");
+    assert_eq!(download(&storage, "/data.bin~3").await, "last");
+}
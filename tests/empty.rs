@@ -0,0 +1,118 @@
+//! Empty files, whose extent is often recorded as block 0 or as anything at all, and downloads
+//! restarted past the end of a file.
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{IsoSource, IsoStorageError, Storage, fixture::IsoBuilder};
+
+const CONTENTS: &[u8] = b"the contents of the file";
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .file("/empty.txt", b"")
+        .file("/readme.txt", CONTENTS)
+        .build()
+}
+
+/// The image with the extent of the empty file recorded past its end.
+fn misrecorded() -> Vec<u8> {
+    let mut image = image();
+    let at = image
+        .windows(b"EMPTY.TXT;1".len())
+        .position(|w| w == b"EMPTY.TXT;1")
+        .unwrap()
+        - 33;
+    image[at + 2..at + 6].copy_from_slice(&0x00ff_ffffu32.to_le_bytes());
+    image[at + 6..at + 10].copy_from_slice(&0x00ff_ffffu32.to_be_bytes());
+    image
+}
+
+/// The image, recording where it is read.
+struct Recording {
+    image: Vec<u8>,
+    reads: Arc<Mutex<Vec<u64>>>,
+}
+
+impl IsoSource for Recording {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.reads.lock().unwrap().push(offset);
+        self.image.read_at(offset, buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.image.len() as u64)
+    }
+}
+
+async fn download(storage: &Storage, path: &str, start: u64) -> Vec<u8> {
+    let mut reader = storage.get(&DefaultUser {}, path, start).await.unwrap();
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).await.unwrap();
+    contents
+}
+
+#[tokio::test]
+async fn empty_files() {
+    for image in [image(), misrecorded()] {
+        let reads = Arc::new(Mutex::new(Vec::new()));
+        let storage = Storage::from_source(Recording {
+            image,
+            reads: reads.clone(),
+        });
+        let user = DefaultUser {};
+        let meta = storage.metadata(&user, "/empty.txt").await.unwrap();
+        assert!(meta.is_file());
+        assert_eq!(meta.len(), 0);
+        let listed = storage.list(&user, "/").await.unwrap();
+        assert!(listed.iter().any(|entry| entry.metadata.len() == 0));
+
+        reads.lock().unwrap().clear();
+        assert!(download(&storage, "/empty.txt", 0).await.is_empty());
+        // Nothing is read of the system area at block 0, where the extent is
+        let reads = reads.lock().unwrap().clone();
+        assert!(reads.iter().all(|&offset| offset >= 16 * 2048), "{reads:?}");
+
+        let mut file = storage.open("/empty.txt").await.unwrap();
+        assert!(file.is_empty());
+        assert_eq!(file.read(&mut [0; 16]).await.unwrap(), 0);
+        assert!(storage.fsck().await.unwrap().is_clean());
+    }
+}
+
+#[tokio::test]
+async fn restarts_past_the_end() {
+    let storage = Storage::from_source(image());
+    let user = DefaultUser {};
+    let len = CONTENTS.len() as u64;
+    assert_eq!(download(&storage, "/readme.txt", 4).await, &CONTENTS[4..]);
+    assert!(download(&storage, "/readme.txt", len).await.is_empty());
+    for (path, start) in [
+        ("/readme.txt", len + 1),
+        ("/empty.txt", 1),
+        ("/readme.txt", u64::MAX),
+    ] {
+        let e = storage.get(&user, path, start).await.err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::PermanentFileNotAvailable);
+        assert!(
+            matches!(
+                IsoStorageError::from_storage_error(&e),
+                Some(IsoStorageError::PastEnd { start: past, .. }) if *past == start
+            ),
+            "{e:?}"
+        );
+    }
+    // Served from the caches, now that the file was read
+    let e = storage
+        .get(&user, "/readme.txt", len + 1)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::PermanentFileNotAvailable);
+}
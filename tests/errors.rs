@@ -0,0 +1,197 @@
+//! The kinds of the errors reported to clients, which decide whether they retry.
+
+use std::{ffi::OsStr, io, os::unix::ffi::OsStrExt, path::Path};
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{Error, ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{Filter, IsoSource, IsoStorageError, Storage, fixture::IsoBuilder};
+
+const CONTENTS: &[u8] = b"the contents of the file";
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new().file("/docs/readme.txt", CONTENTS).build()
+}
+
+/// The image, failing reads of the contents of the file with `kind`.
+struct Failing {
+    image: Vec<u8>,
+    kind: io::ErrorKind,
+}
+
+impl IsoSource for Failing {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let contents = self
+            .image
+            .windows(CONTENTS.len())
+            .position(|w| w == CONTENTS)
+            .unwrap() as u64;
+        if (offset..offset + buf.len() as u64).contains(&contents) {
+            return Err(self.kind.into());
+        }
+        self.image.read_at(offset, buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.image.len() as u64)
+    }
+}
+
+/// What went wrong, besides the kind of error.
+fn message(err: &Error) -> String {
+    std::error::Error::source(err).unwrap().to_string()
+}
+
+#[tokio::test]
+async fn not_found() {
+    let storage = Storage::from_source(image());
+    let user = DefaultUser {};
+    for path in ["/missing.txt", "/missing/readme.txt", "/docs/readme.txt/x"] {
+        let err = storage.metadata(&user, path).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermanentFileNotAvailable, "{path}");
+        let err = storage.get(&user, path, 0).await.err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::PermanentFileNotAvailable, "{path}");
+        let err = storage.cwd(&user, path).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermanentFileNotAvailable, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn bad_names() {
+    let storage = Storage::from_source(image());
+    let path = Path::new(OsStr::from_bytes(b"/docs/\xFF.txt"));
+    let err = storage.metadata(&DefaultUser {}, path).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::FileNameNotAllowedError);
+}
+
+#[tokio::test]
+async fn not_a_directory() {
+    let storage = Storage::from_source(image());
+    let err = storage
+        .list(&DefaultUser {}, "/docs/readme.txt/")
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::PermanentDirectoryNotAvailable);
+}
+
+#[tokio::test]
+async fn cwd_into_a_file() {
+    let storage = Storage::from_source(image());
+    let user = DefaultUser {};
+    assert!(storage.cwd(&user, "/docs").await.is_ok());
+    let err = storage.cwd(&user, "/docs/readme.txt").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermanentDirectoryNotAvailable);
+    assert!(message(&err).contains("not a directory"), "{err}");
+    let err = storage.cwd(&user, "/docs/missing.txt").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermanentFileNotAvailable);
+    assert!(message(&err).contains("not found"), "{err}");
+
+    // Hidden files aren't told apart from missing ones
+    let filtered = Storage::source_builder(image())
+        .filter(Filter::new().exclude_extensions(["txt"]))
+        .build();
+    let err = filtered.cwd(&user, "/docs/readme.txt").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermanentFileNotAvailable);
+}
+
+#[tokio::test]
+async fn failing_reads() {
+    for (kind, expected) in [
+        (
+            io::ErrorKind::TimedOut,
+            ErrorKind::TransientFileNotAvailable,
+        ),
+        (
+            io::ErrorKind::ConnectionReset,
+            ErrorKind::TransientFileNotAvailable,
+        ),
+        (
+            io::ErrorKind::InvalidData,
+            ErrorKind::PermanentFileNotAvailable,
+        ),
+        (io::ErrorKind::PermissionDenied, ErrorKind::PermissionDenied),
+    ] {
+        let storage = Storage::from_source(Failing {
+            image: image(),
+            kind,
+        });
+        let err = storage
+            .get(&DefaultUser {}, "/docs/readme.txt", 0)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), expected, "{kind}");
+    }
+}
+
+#[tokio::test]
+async fn broken_images() {
+    let user = DefaultUser {};
+    let garbage = Storage::from_source(vec![0xAA; 64 * 2048]);
+    let err = garbage.list(&user, "/").await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::LocalError);
+
+    // The image missing is a problem of the server, not a file the client asked for
+    let missing = Storage::new(std::env::temp_dir().join("unftp-sbe-iso-errors-missing.iso"));
+    let err = missing.metadata(&user, "/docs").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::LocalError);
+}
+
+#[tokio::test]
+async fn truncated_image() {
+    // The extent of the large file comes last, so cutting the image short cuts it alone off
+    let mut image = IsoBuilder::new()
+        .file("/docs/readme.txt", CONTENTS)
+        .file("/large.bin", &[7; 8 * 2048])
+        .build();
+    image.truncate(image.len() - 3 * 2048);
+    let storage = Storage::from_source(image);
+    let user = DefaultUser {};
+    let err = storage.get(&user, "/large.bin", 0).await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::PermanentFileNotAvailable);
+    assert!(message(&err).contains("is the image truncated?"), "{err}");
+    // The rest of the image serves as before
+    let mut contents = Vec::new();
+    storage
+        .get(&user, "/docs/readme.txt", 0)
+        .await
+        .unwrap()
+        .read_to_end(&mut contents)
+        .await
+        .unwrap();
+    assert_eq!(contents, CONTENTS);
+    assert_eq!(storage.list(&user, "/docs").await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn inspectable() {
+    let storage = Storage::from_source(image());
+    let user = DefaultUser {};
+    let err = storage.cwd(&user, "/docs/readme.txt").await.unwrap_err();
+    assert!(matches!(
+        IsoStorageError::from_storage_error(&err),
+        Some(IsoStorageError::NotADirectory(path)) if path == Path::new("/docs/readme.txt")
+    ));
+    let err = storage.metadata(&user, "/missing.txt").await.unwrap_err();
+    let inner = IsoStorageError::from_storage_error(&err).unwrap();
+    assert!(
+        matches!(inner, IsoStorageError::ComponentNotFound(name) if name == "missing.txt"),
+        "{inner:?}"
+    );
+    assert_eq!(inner.kind(), err.kind());
+
+    let err = Storage::from_source(vec![0; 4096])
+        .list(&user, "/")
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        matches!(
+            IsoStorageError::from_storage_error(&err),
+            Some(IsoStorageError::UnsupportedImage { .. } | IsoStorageError::Image { .. })
+        ),
+        "{err:?}"
+    );
+}
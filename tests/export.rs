@@ -0,0 +1,151 @@
+//! Exporting the tree of an image to a tar archive.
+
+use std::path::PathBuf;
+use unftp_sbe_iso::{Compression, Filter, Storage, View, fixture::IsoBuilder};
+
+/// An entry of an archive: its name, type flag, mode, link target and contents.
+type Entry = (String, u8, u32, String, Vec<u8>);
+
+fn octal(field: &[u8]) -> u64 {
+    let digits = std::str::from_utf8(field)
+        .unwrap()
+        .trim_end_matches(['\0', ' ']);
+    u64::from_str_radix(digits, 8).unwrap()
+}
+
+fn text(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8(field[..end].to_vec()).unwrap()
+}
+
+/// Reads the entries of a tar archive, applying pax paths.
+fn entries(tar: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut at = 0;
+    let mut long_path = None;
+    // It ends with two empty blocks
+    assert!(tar.len().is_multiple_of(512) && tar[tar.len() - 1024..].iter().all(|&b| b == 0));
+    while tar[at..at + 512].iter().any(|&b| b != 0) {
+        let header = &tar[at..at + 512];
+        let sum: u64 = header[..148]
+            .iter()
+            .chain(&[b' '; 8])
+            .chain(&header[156..])
+            .map(|&b| b as u64)
+            .sum();
+        assert_eq!(octal(&header[148..156]), sum);
+        assert_eq!(&header[257..265], b"ustar\x0000");
+        let size = octal(&header[124..136]) as usize;
+        let contents = tar[at + 512..at + 512 + size].to_vec();
+        at += 512 + size.div_ceil(512) * 512;
+        if header[156] == b'x' {
+            let record = String::from_utf8(contents).unwrap();
+            long_path = record
+                .split_once("path=")
+                .map(|(_, path)| path.trim_end().to_string());
+            continue;
+        }
+        let name = long_path.take().unwrap_or_else(|| text(&header[..100]));
+        entries.push((
+            name,
+            header[156],
+            octal(&header[100..108]) as u32,
+            text(&header[157..257]),
+            contents,
+        ));
+    }
+    entries
+}
+
+fn dest(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "unftp-sbe-iso-export-{}-{name}",
+        std::process::id()
+    ))
+}
+
+/// Exports the tree, returning how many entries were exported, how long the archive is and
+/// its entries.
+async fn export(storage: &Storage, compression: Compression) -> (u64, usize, Vec<Entry>) {
+    let dest = dest(&format!("{compression:?}"));
+    let count = storage.export_tar(&dest, compression).await.unwrap();
+    let written = std::fs::read(&dest).unwrap();
+    std::fs::remove_file(&dest).unwrap();
+    let len = written.len();
+    let tar = match compression {
+        Compression::Zstd => zstd::decode_all(&written[..]).unwrap(),
+        _ => written,
+    };
+    (count, len, entries(&tar))
+}
+
+#[tokio::test]
+async fn rock_ridge_tree() {
+    let large: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    let deep = format!("/{}/{}.txt", "d".repeat(60), "f".repeat(60));
+    let image = IsoBuilder::new()
+        .rock_ridge(true)
+        .file("/docs/readme.txt", b"hello")
+        .file("/data/large.bin", &large)
+        .file(&deep, b"deep")
+        .symlink("/latest", "docs/readme.txt")
+        .build();
+    let storage = Storage::from_source(image);
+    let mut lens = Vec::new();
+    for compression in [Compression::None, Compression::Zstd] {
+        let (count, len, entries) = export(&storage, compression).await;
+        lens.push(len);
+        assert_eq!(count, entries.len() as u64);
+        let find = |name: &str| {
+            entries
+                .iter()
+                .find(|(n, ..)| n == name)
+                .unwrap_or_else(|| panic!("{name} not in {entries:?}"))
+        };
+        assert_eq!(
+            find("docs/"),
+            &("docs/".into(), b'5', 0o755, "".into(), vec![])
+        );
+        assert_eq!(
+            find("docs/readme.txt"),
+            &(
+                "docs/readme.txt".into(),
+                b'0',
+                0o644,
+                "".into(),
+                b"hello".to_vec()
+            )
+        );
+        assert_eq!(find("data/large.bin").4, large);
+        assert_eq!(find(&deep[1..]).4, b"deep");
+        let link = find("latest");
+        assert_eq!((link.1, link.3.as_str()), (b'2', "docs/readme.txt"));
+        // Directories come before what they hold
+        let at = |name: &str| entries.iter().position(|(n, ..)| n == name).unwrap();
+        assert!(at("docs/") < at("docs/readme.txt"));
+    }
+    // The repeating contents of the large file compress well
+    assert!(lens[1] * 10 < lens[0], "{lens:?}");
+}
+
+#[tokio::test]
+async fn plain_iso() {
+    let image = IsoBuilder::new()
+        .file("/docs/readme.txt", b"hello")
+        .file("/hidden.bin", b"hidden")
+        .build();
+    let storage = Storage::source_builder(image)
+        .view(View::ByDate)
+        .filter(Filter::new().exclude_extensions(["bin"]))
+        .build();
+    let (count, _, entries) = export(&storage, Compression::None).await;
+    assert_eq!(count, 2);
+    let names: Vec<(&str, u8, u32)> = entries
+        .iter()
+        .map(|(name, kind, mode, ..)| (name.as_str(), *kind, *mode))
+        .collect();
+    assert_eq!(
+        names,
+        [("DOCS/", b'5', 0o555), ("DOCS/README.TXT", b'0', 0o444)]
+    );
+}
@@ -0,0 +1,35 @@
+//! The inventory of SUSP entries.
+
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+#[tokio::test]
+async fn rock_ridge_entries() {
+    let image = IsoBuilder::new()
+        .rock_ridge(true)
+        .file("/docs/readme.txt", b"hello")
+        .symlink("/latest", "docs/readme.txt")
+        .build();
+    let extensions = Storage::from_source(image).extensions().await.unwrap();
+    // The `.` of the root and of docs, and docs, readme.txt and latest
+    assert_eq!(extensions.records, 5);
+    assert_eq!(extensions.declared, ["RRIP_1991A"]);
+    assert!(extensions.declares_rock_ridge());
+    assert_eq!(extensions.entries["PX"], 5);
+    assert_eq!(extensions.entries["NM"], 3);
+    assert_eq!(extensions.entries["SL"], 1);
+    assert_eq!(extensions.entries["SP"], 1);
+    assert!(extensions.contains("TF"));
+    assert!(!extensions.contains("ZF"));
+
+    let image = IsoBuilder::new().file("/readme.txt", b"hello").build();
+    let extensions = Storage::from_source(image).extensions().await.unwrap();
+    assert_eq!(extensions.records, 2);
+    assert!(extensions.entries.is_empty());
+    assert!(!extensions.declares_rock_ridge());
+    assert!(
+        Storage::from_source(vec![0; 40 * 2048])
+            .extensions()
+            .await
+            .is_err()
+    );
+}
@@ -0,0 +1,120 @@
+//! Random access to files through `Storage::open` and `Storage::read_range`.
+
+use std::io::{self, SeekFrom};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use unftp_sbe_iso::{AsyncIsoSource, Storage, fixture::IsoBuilder};
+
+/// Contents spanning several of the chunks the file reads at once.
+fn contents() -> Vec<u8> {
+    (0..200_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/data/large.bin", &contents())
+        .file("/empty.txt", b"")
+        .boot_image(b"boot sector")
+        .build()
+}
+
+async fn assert_random_access(storage: &Storage) {
+    let expected = contents();
+    let mut file = storage.open("/data/large.bin").await.unwrap();
+    assert_eq!(file.len(), expected.len() as u64);
+
+    let mut all = Vec::new();
+    file.read_to_end(&mut all).await.unwrap();
+    assert_eq!(all, expected);
+
+    assert_eq!(file.seek(SeekFrom::Start(70_000)).await.unwrap(), 70_000);
+    let mut some = [0; 100];
+    file.read_exact(&mut some).await.unwrap();
+    assert_eq!(some[..], expected[70_000..70_100]);
+
+    assert_eq!(file.seek(SeekFrom::Current(-50)).await.unwrap(), 70_050);
+    file.read_exact(&mut some).await.unwrap();
+    assert_eq!(some[..], expected[70_050..70_150]);
+
+    file.seek(SeekFrom::End(-10)).await.unwrap();
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).await.unwrap();
+    assert_eq!(tail, expected[expected.len() - 10..]);
+
+    // Past the end reads nothing, before the start fails
+    file.seek(SeekFrom::End(10)).await.unwrap();
+    assert_eq!(file.read(&mut some).await.unwrap(), 0);
+    assert!(file.seek(SeekFrom::Current(-300_000)).await.is_err());
+}
+
+#[tokio::test]
+async fn random_access() {
+    assert_random_access(&Storage::from_source(image())).await;
+}
+
+struct Remote(Vec<u8>);
+
+#[async_trait::async_trait]
+impl AsyncIsoSource for Remote {
+    async fn read_range(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let start = (offset as usize).min(self.0.len());
+        let end = (start + len).min(self.0.len());
+        Ok(self.0[start..end].to_vec())
+    }
+
+    async fn len(&self) -> io::Result<u64> {
+        Ok(self.0.len() as u64)
+    }
+}
+
+#[tokio::test]
+async fn async_source() {
+    assert_random_access(&Storage::from_async_source(Remote(image()))).await;
+}
+
+#[tokio::test]
+async fn not_files() {
+    let storage = Storage::source_builder(image()).boot_images(true).build();
+    assert!(storage.open("/data").await.is_err());
+    assert!(storage.open("/missing").await.is_err());
+
+    let empty = storage.open("/empty.txt").await.unwrap();
+    assert!(empty.is_empty());
+
+    let mut boot = storage.open("/boot.img").await.unwrap();
+    let mut sector = Vec::new();
+    boot.read_to_end(&mut sector).await.unwrap();
+    assert!(sector.starts_with(b"boot sector"));
+}
+
+#[tokio::test]
+async fn max_file_size() {
+    let storage = Storage::source_builder(image()).max_file_size(1000).build();
+    assert!(storage.open("/data/large.bin").await.is_err());
+    assert!(storage.open("/empty.txt").await.is_ok());
+}
+
+#[tokio::test]
+async fn read_range() {
+    let expected = contents();
+    let storage = Storage::from_source(image());
+    let range = storage
+        .read_range("/data/large.bin", 70_000, 100_000)
+        .await
+        .unwrap();
+    assert_eq!(range[..], expected[70_000..170_000]);
+
+    // Ranges end with the file
+    let tail = storage
+        .read_range("/data/large.bin", 199_990, 100)
+        .await
+        .unwrap();
+    assert_eq!(tail[..], expected[199_990..]);
+    let past = storage.read_range("/data/large.bin", 300_000, 10).await;
+    assert!(past.unwrap().is_empty());
+
+    let remote = Storage::from_async_source(Remote(image()));
+    let range = remote.read_range("/data/large.bin", 5, 10).await.unwrap();
+    assert_eq!(range[..], expected[5..15]);
+    assert!(remote.read_range("/data", 0, 10).await.is_err());
+}
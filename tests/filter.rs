@@ -0,0 +1,85 @@
+//! Serving only the files a filter allows.
+
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{Filter, Storage, fixture::IsoBuilder};
+
+async fn listed(storage: &Storage, path: &str) -> Vec<String> {
+    let mut names: Vec<_> = storage
+        .list(&DefaultUser {}, path)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| f.path.to_str().unwrap().to_string())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    names
+}
+
+fn image() -> IsoBuilder {
+    IsoBuilder::new()
+        .rock_ridge(true)
+        .file("/Packages/tool-1.0.rpm", &[0xed, 0xab, 0xee, 0xdb, 3, 0])
+        .file("/Packages/notes.txt", b"notes")
+        .file("/repodata/repomd.xml", b"<?xml version=\"1.0\"?><repomd/>")
+        .file("/repodata/unlabelled", b"<?xml version=\"1.0\"?><other/>")
+        .file("/bin/tool", b"\x7fELF\x02\x01\x01")
+        .symlink("/tool.rpm", "bin/tool")
+        .symlink("/latest.rpm", "Packages/tool-1.0.rpm")
+}
+
+#[tokio::test]
+async fn by_extension() {
+    let image = image().build_file();
+    let storage = Storage::builder(image.path())
+        .filter(Filter::new().include_extensions(["rpm", "xml"]))
+        .build();
+    let user = DefaultUser {};
+
+    assert_eq!(
+        listed(&storage, "/").await,
+        ["Packages", "bin", "latest.rpm", "repodata"]
+    );
+    assert_eq!(listed(&storage, "/Packages").await, ["tool-1.0.rpm"]);
+    assert_eq!(listed(&storage, "/repodata").await, ["repomd.xml"]);
+    assert!(listed(&storage, "/bin").await.is_empty());
+
+    assert!(
+        storage
+            .get(&user, "/Packages/tool-1.0.rpm", 0)
+            .await
+            .is_ok()
+    );
+    assert!(storage.get(&user, "/latest.rpm", 0).await.is_ok());
+    for path in ["/Packages/notes.txt", "/bin/tool", "/tool.rpm"] {
+        assert!(storage.get(&user, path, 0).await.is_err(), "{path}");
+    }
+    assert!(
+        storage
+            .metadata(&user, "/Packages/notes.txt")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn by_type() {
+    let image = image().build_file();
+    let storage = Storage::builder(image.path())
+        .filter(Filter::new().exclude_types(["application/x-executable"]))
+        .build();
+    let user = DefaultUser {};
+    assert!(listed(&storage, "/bin").await.is_empty());
+    assert!(storage.get(&user, "/bin/tool", 0).await.is_err());
+    assert!(storage.get(&user, "/tool.rpm", 0).await.is_err());
+
+    let storage = Storage::builder(image.path())
+        .filter(Filter::new().include_types(["application/xml"]))
+        .build();
+    assert_eq!(
+        listed(&storage, "/repodata").await,
+        ["repomd.xml", "unlabelled"]
+    );
+    assert!(storage.get(&user, "/repodata/unlabelled", 0).await.is_ok());
+    assert!(storage.get(&user, "/Packages/notes.txt", 0).await.is_err());
+}
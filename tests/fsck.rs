@@ -0,0 +1,79 @@
+//! Checking the structure of whole images with `Storage::fsck`.
+
+use std::path::Path;
+use unftp_sbe_iso::{Namespace, ProblemKind, Storage, fixture::IsoBuilder};
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/readme.txt", b"Hello")
+        .file("/docs/deep/notes.txt", b"Notes")
+        .file("/top.txt", &[7; 5000])
+        .build()
+}
+
+#[tokio::test]
+async fn clean() {
+    let report = Storage::from_source(image()).fsck().await.unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+    // Both hierarchies, each with the root, docs and deep
+    assert_eq!(report.directories, 6);
+    assert_eq!(report.files, 6);
+}
+
+#[tokio::test]
+async fn truncated() {
+    let mut image = image();
+    image.truncate(image.len() - 2048);
+    let report = Storage::from_source(image).fsck().await.unwrap();
+    assert!(
+        report
+            .problems
+            .iter()
+            .any(|p| p.namespace.is_none() && matches!(p.kind, ProblemKind::Truncated { .. }))
+    );
+    let out_of_bounds: Vec<_> = report
+        .problems
+        .iter()
+        .filter(|p| matches!(p.kind, ProblemKind::ExtentOutOfBounds { .. }))
+        .collect();
+    assert!(!out_of_bounds.is_empty());
+    assert!(
+        out_of_bounds
+            .iter()
+            .all(|p| p.path.starts_with("/") && p.namespace.is_some())
+    );
+    assert!(out_of_bounds[0].to_string().contains("ends at byte"));
+}
+
+#[tokio::test]
+async fn cycle() {
+    let mut image = IsoBuilder::new().file("/loop/inner/file.txt", b"x").build();
+    let root = 16 * 2048 + 156;
+    let extent = image[root + 2..root + 18].to_vec();
+    let record = (0..image.len() - 38)
+        .find(|&r| {
+            image[r + 25] & 2 != 0 && image[r + 32] == 5 && &image[r + 33..r + 38] == b"INNER"
+        })
+        .unwrap();
+    image[record + 2..record + 18].copy_from_slice(&extent);
+    let report = Storage::from_source(image).fsck().await.unwrap();
+    assert_eq!(report.problems.len(), 1, "{:?}", report.problems);
+    let problem = &report.problems[0];
+    assert_eq!(problem.namespace, Some(Namespace::Primary));
+    assert_eq!(problem.path, Path::new("/LOOP/INNER"));
+    assert!(matches!(problem.kind, ProblemKind::Cycle { .. }));
+}
+
+#[tokio::test]
+async fn not_an_image() {
+    let report = Storage::from_source(vec![0; 64 * 2048])
+        .fsck()
+        .await
+        .unwrap();
+    assert!(!report.is_clean());
+    assert!(matches!(
+        report.problems[0].kind,
+        ProblemKind::Descriptors(_)
+    ));
+}
@@ -0,0 +1,164 @@
+//! End-to-end tests driving a libunftp server that uses this back-end.
+
+mod common;
+
+use common::{Client, serve};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+fn tree() -> IsoBuilder {
+    IsoBuilder::new()
+        .file("/readme.txt", b"Hello, World!\n")
+        .file("/docs/manual/chapter1.txt", &[b'x'; 10_000])
+        .dir("/empty")
+}
+
+#[tokio::test]
+async fn list_primary_names() {
+    let image = tree().build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    let mut names = client.list("/").await.unwrap();
+    names.sort();
+    assert_eq!(names, ["DOCS", "EMPTY", "README.TXT"]);
+}
+
+#[tokio::test]
+async fn list_joliet_names() {
+    let image = tree().joliet(true).build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    let mut names = client.list("/").await.unwrap();
+    names.sort();
+    assert_eq!(names, ["docs", "empty", "readme.txt"]);
+    assert_eq!(client.list("/docs/manual").await.unwrap(), ["chapter1.txt"]);
+    assert!(client.list("/empty").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn list_rock_ridge_names() {
+    let image = tree()
+        .rock_ridge(true)
+        .symlink("/latest", "docs/manual/chapter1.txt")
+        .build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    let mut names = client.list("/").await.unwrap();
+    names.sort();
+    assert_eq!(names, ["docs", "empty", "latest", "readme.txt"]);
+}
+
+#[tokio::test]
+async fn retr_whole_file() {
+    let image = tree().joliet(true).build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    assert_eq!(
+        client.retr("/readme.txt").await.unwrap(),
+        b"Hello, World!\n"
+    );
+    assert_eq!(
+        client.retr("/docs/manual/chapter1.txt").await.unwrap(),
+        vec![b'x'; 10_000]
+    );
+}
+
+#[tokio::test]
+async fn retr_with_rest() {
+    let image = tree().joliet(true).build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    assert_eq!(client.cmd("REST 7").await.code, 350);
+    assert_eq!(client.retr("/readme.txt").await.unwrap(), b"World!\n");
+    assert_eq!(client.cmd("REST 9000").await.code, 350);
+    assert_eq!(
+        client
+            .retr("/docs/manual/chapter1.txt")
+            .await
+            .unwrap()
+            .len(),
+        1_000
+    );
+}
+
+#[tokio::test]
+async fn retr_is_case_insensitive_in_primary_namespace() {
+    let image = tree().build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    assert_eq!(
+        client.retr("/readme.txt").await.unwrap(),
+        b"Hello, World!\n"
+    );
+}
+
+#[tokio::test]
+async fn retr_failures() {
+    let image = tree().joliet(true).build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    assert!(!client.retr("/missing.txt").await.unwrap_err().is_success());
+    assert!(!client.retr("/docs").await.unwrap_err().is_success());
+}
+
+#[tokio::test]
+async fn list_a_file() {
+    let image = tree().joliet(true).build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    assert_eq!(client.list("/readme.txt").await.unwrap(), ["readme.txt"]);
+    assert_eq!(
+        client.list("docs/manual/chapter1.txt").await.unwrap(),
+        ["chapter1.txt"]
+    );
+    assert!(!client.list("/missing.txt").await.unwrap_err().is_success());
+}
+
+#[tokio::test]
+async fn list_patterns() {
+    let image = tree().joliet(true).build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    assert_eq!(client.list("*.txt").await.unwrap(), ["readme.txt"]);
+    assert_eq!(client.cmd("CWD /docs/manual").await.code, 250);
+    let nlst = client.transfer("NLST chapter?.*").await.unwrap();
+    assert_eq!(String::from_utf8(nlst).unwrap(), "chapter1.txt\r\n");
+}
+
+#[tokio::test]
+async fn cwd_and_relative_paths() {
+    let image = tree().joliet(true).build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    assert_eq!(client.cmd("CWD /docs").await.code, 250);
+    assert_eq!(client.cmd("PWD").await.text, "\"/docs\"");
+    assert_eq!(client.list("").await.unwrap(), ["manual"]);
+    assert_eq!(client.cmd("CWD manual").await.code, 250);
+    assert_eq!(client.retr("chapter1.txt").await.unwrap().len(), 10_000);
+    assert_eq!(client.cmd("CDUP").await.code, 250);
+    assert_eq!(client.cmd("PWD").await.text, "\"/docs\"");
+    assert_eq!(client.cmd("CWD ..").await.code, 250);
+    let mut names = client.list("").await.unwrap();
+    names.sort();
+    assert_eq!(names, ["docs", "empty", "readme.txt"]);
+    assert_eq!(
+        client
+            .retr("//docs///manual/chapter1.txt")
+            .await
+            .unwrap()
+            .len(),
+        10_000
+    );
+    assert!(!client.cmd("CWD /nowhere").await.is_success());
+}
+
+#[tokio::test]
+async fn mdtm_and_size() {
+    let image = tree().joliet(true).build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    let reply = client.cmd("MDTM /readme.txt").await;
+    assert_eq!(reply.code, 213);
+    assert_eq!(reply.text, "20240102030405");
+    let reply = client.cmd("SIZE /readme.txt").await;
+    assert_eq!(reply.code, 213);
+    assert_eq!(reply.text, "14");
+}
+
+#[tokio::test]
+async fn modifications_are_refused() {
+    let image = tree().joliet(true).build_file();
+    let mut client = Client::login(serve(Storage::new(image.path())).await).await;
+    assert!(!client.cmd("DELE /readme.txt").await.is_success());
+    assert!(!client.cmd("MKD /new").await.is_success());
+    assert!(!client.cmd("RMD /empty").await.is_success());
+    assert!(client.transfer("STOR /new.txt").await.is_err());
+}
@@ -0,0 +1,55 @@
+//! Patterns in the last component of listed paths, like `LIST *.iso`.
+
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+fn storage() -> Storage {
+    Storage::from_source(
+        IsoBuilder::new()
+            .joliet(true)
+            .file("/pub/debian.iso", b"debian")
+            .file("/pub/fedora.iso", b"fedora")
+            .file("/pub/fedora.iso.sig", b"signature")
+            .file("/pub/RELEASE-1.0", b"1.0")
+            .file("/pub/RELEASE-2.0", b"2.0")
+            .file("/pub/star*.txt", b"a star")
+            .build(),
+    )
+}
+
+async fn listed(storage: &Storage, path: &str) -> Option<Vec<String>> {
+    let mut names: Vec<_> = storage
+        .list(&DefaultUser {}, path)
+        .await
+        .ok()?
+        .into_iter()
+        .map(|f| f.path.to_str().unwrap().to_string())
+        .collect();
+    names.sort();
+    Some(names)
+}
+
+#[tokio::test]
+async fn patterns() {
+    let storage = storage();
+    for (path, expected) in [
+        ("/pub/*.iso", &["debian.iso", "fedora.iso"][..]),
+        ("/pub/RELEASE-*", &["RELEASE-1.0", "RELEASE-2.0"]),
+        ("/pub/release-?.0", &["RELEASE-1.0", "RELEASE-2.0"]),
+        ("/pub/[d-e]*", &["debian.iso"]),
+        ("/pub/fedora.*", &["fedora.iso", "fedora.iso.sig"]),
+    ] {
+        assert_eq!(listed(&storage, path).await.unwrap(), expected, "{path}");
+    }
+    assert_eq!(listed(&storage, "/pub/*.exe").await, None);
+    assert_eq!(listed(&storage, "/missing/*.iso").await, None);
+}
+
+#[tokio::test]
+async fn names_that_exist_are_not_patterns() {
+    let storage = storage();
+    assert_eq!(
+        listed(&storage, "/pub/star*.txt").await.unwrap(),
+        ["star*.txt"]
+    );
+}
@@ -0,0 +1,109 @@
+//! Serving compressed files decompressed with `StorageBuilder::gunzip`.
+
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+/// `Hello, gzip!\n`, compressed.
+const README_GZ: [u8; 33] = [
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7,
+    0x51, 0x48, 0xaf, 0xca, 0x2c, 0x50, 0xe4, 0x02, 0x00, 0x05, 0x14, 0xa6, 0xf3, 0x0d, 0x00, 0x00,
+    0x00,
+];
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/doc/README.txt.gz", &README_GZ)
+        .file("/doc/both.txt", b"plain")
+        .file("/doc/both.txt.gz", &README_GZ)
+        .file("/doc/broken.txt.gz", &README_GZ[..20])
+        .build()
+}
+
+async fn download(storage: &Storage, path: &str, start: u64) -> Result<Vec<u8>, ()> {
+    let mut reader = storage
+        .get(&DefaultUser {}, path, start)
+        .await
+        .map_err(drop)?;
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).await.unwrap();
+    Ok(contents)
+}
+
+#[tokio::test]
+async fn decompressed() {
+    let storage = Storage::source_builder(image()).gunzip(true).build();
+    let user = DefaultUser {};
+    assert_eq!(
+        download(&storage, "/doc/README.txt", 0).await.unwrap(),
+        b"Hello, gzip!\n"
+    );
+    assert_eq!(
+        download(&storage, "/doc/readme.txt", 7).await.unwrap(),
+        b"gzip!\n"
+    );
+    let meta = storage.metadata(&user, "/doc/README.txt").await.unwrap();
+    assert_eq!(meta.len(), 13);
+    assert!(meta.is_file());
+
+    let mut file = storage.open("/doc/README.txt").await.unwrap();
+    assert_eq!(file.len(), 13);
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).await.unwrap();
+    assert_eq!(contents, "Hello, gzip!\n");
+
+    // The compressed files are still served and listed as they are
+    assert_eq!(
+        download(&storage, "/doc/README.txt.gz", 0).await.unwrap(),
+        README_GZ
+    );
+    let listed: Vec<String> = storage
+        .list(&user, "/doc")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| f.path.to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    assert!(
+        !listed.iter().any(|name| name == "README.txt"),
+        "{listed:?}"
+    );
+}
+
+#[tokio::test]
+async fn files_take_precedence() {
+    let storage = Storage::source_builder(image()).gunzip(true).build();
+    assert_eq!(
+        download(&storage, "/doc/both.txt", 0).await.unwrap(),
+        b"plain"
+    );
+}
+
+#[tokio::test]
+async fn off_by_default() {
+    let storage = Storage::from_source(image());
+    assert!(download(&storage, "/doc/README.txt", 0).await.is_err());
+    assert!(
+        storage
+            .metadata(&DefaultUser {}, "/doc/README.txt")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn limits() {
+    let storage = Storage::source_builder(image()).gunzip(true).build();
+    assert!(download(&storage, "/doc/broken.txt", 0).await.is_err());
+
+    let storage = Storage::source_builder(image())
+        .gunzip(true)
+        .max_file_size(10)
+        .build();
+    assert!(download(&storage, "/doc/README.txt", 0).await.is_err());
+}
@@ -0,0 +1,163 @@
+//! Digests of files through `Storage::hash` and `SITE MD5`.
+
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, FEATURE_SITEMD5, StorageBackend},
+};
+use unftp_sbe_iso::{
+    HashAlgorithm, HashFunction, HashState, IsoSource, Storage, fixture::IsoBuilder,
+};
+
+/// Counts the bytes read from the image.
+struct Counting {
+    image: Vec<u8>,
+    read: AtomicUsize,
+}
+
+impl IsoSource for Counting {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.image.read_at(offset, buf)?;
+        self.read.fetch_add(n, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.image.len() as u64)
+    }
+}
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/hello.txt", b"hello")
+        .file("/large.bin", &vec![7; 1_000_000])
+        .file("/empty.txt", b"")
+        .build()
+}
+
+#[tokio::test]
+async fn digests() {
+    let storage = Storage::from_source(image());
+    for (algorithm, expected) in [
+        (HashAlgorithm::Md5, "5d41402abc4b2a76b9719d911017c592"),
+        (
+            HashAlgorithm::Sha1,
+            "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d",
+        ),
+        (
+            HashAlgorithm::Sha256,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+        ),
+    ] {
+        let digest = storage.hash("/docs/hello.txt", algorithm).await.unwrap();
+        assert_eq!(digest.to_string(), expected, "{algorithm}");
+        assert_eq!(digest.algorithm(), algorithm);
+    }
+    let empty = storage
+        .hash("/empty.txt", HashAlgorithm::Md5)
+        .await
+        .unwrap();
+    assert_eq!(empty.to_string(), "d41d8cd98f00b204e9800998ecf8427e");
+    let large = storage
+        .hash("/large.bin", HashAlgorithm::Sha256)
+        .await
+        .unwrap();
+    assert_eq!(large.as_bytes().len(), 32);
+
+    let err = storage.hash("/docs", HashAlgorithm::Md5).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermanentFileNotAvailable);
+    assert!(storage.hash("/missing", HashAlgorithm::Md5).await.is_err());
+}
+
+#[tokio::test]
+async fn kept_by_extent() {
+    let source = Arc::new(Counting {
+        image: image(),
+        read: AtomicUsize::new(0),
+    });
+    let storage = Storage::from_source(source.clone());
+    let first = storage
+        .hash("/large.bin", HashAlgorithm::Md5)
+        .await
+        .unwrap();
+    // Resolving the path still reads directories, but not the contents again
+    let read = source.read.load(Ordering::Relaxed);
+    assert!(read > 1_000_000);
+    let again = storage
+        .hash("/LARGE.BIN", HashAlgorithm::Md5)
+        .await
+        .unwrap();
+    assert_eq!(again, first);
+    assert!(source.read.load(Ordering::Relaxed) - read < 100_000);
+    let other = storage
+        .hash("/large.bin", HashAlgorithm::Sha1)
+        .await
+        .unwrap();
+    assert_ne!(other.as_bytes(), first.as_bytes());
+}
+
+#[tokio::test]
+async fn site_md5() {
+    let storage = Storage::from_source(image());
+    let features = StorageBackend::<DefaultUser>::supported_features(&storage);
+    assert_ne!(features & FEATURE_SITEMD5, 0);
+    assert_eq!(
+        storage
+            .md5(&DefaultUser {}, "/docs/hello.txt")
+            .await
+            .unwrap(),
+        "5d41402abc4b2a76b9719d911017c592"
+    );
+}
+
+/// A registered hash function: the sum of the bytes.
+#[derive(Debug)]
+struct Sum;
+
+impl HashFunction for Sum {
+    fn start(&self) -> Box<dyn HashState> {
+        Box::new(SumState(0))
+    }
+}
+
+struct SumState(u32);
+
+impl HashState for SumState {
+    fn update(&mut self, data: &[u8]) {
+        self.0 = data.iter().fold(self.0, |sum, &b| sum + u32::from(b));
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+}
+
+#[tokio::test]
+async fn registered() {
+    let storage = Storage::source_builder(image())
+        .hash_function("SUM", Arc::new(Sum))
+        .build();
+    let sum = HashAlgorithm::Custom("SUM");
+    let digest = storage.hash("/large.bin", sum).await.unwrap();
+    assert_eq!(digest.as_bytes(), 7_000_000u32.to_be_bytes());
+    assert_eq!(digest.algorithm(), sum);
+    // Kept apart from the digests of other functions
+    let md5 = storage
+        .hash("/large.bin", HashAlgorithm::Md5)
+        .await
+        .unwrap();
+    assert_eq!(md5.as_bytes().len(), 16);
+
+    let err = storage
+        .hash("/large.bin", HashAlgorithm::Custom("BLAKE3"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::CommandNotImplemented);
+}
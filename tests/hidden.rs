@@ -0,0 +1,104 @@
+//! Entries the image flags hidden, shown only to the users a policy lets see them.
+
+use std::fmt;
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::{DefaultUser, UserDetail},
+    storage::{ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{Storage, StorageBuilder, fixture::IsoBuilder};
+
+fn image() -> IsoBuilder {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/readme.txt", b"for everyone")
+        .file("/secret.txt", b"for admins")
+        .file("/private/deeper/key.txt", b"for admins too")
+        .hidden("/secret.txt")
+        .hidden("/private")
+}
+
+#[derive(Debug)]
+struct Named(&'static str);
+
+impl fmt::Display for Named {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl UserDetail for Named {}
+
+fn admins(builder: StorageBuilder) -> Storage {
+    builder
+        .hidden_entries(|user| user.to_string() == "admin")
+        .build()
+}
+
+async fn names(storage: &Storage, user: &impl UserDetail) -> Vec<String> {
+    let mut names: Vec<String> = storage
+        .list(user, "/")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.path.to_string_lossy().to_lowercase())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    names
+}
+
+async fn download(storage: &Storage, user: &impl UserDetail, path: &str) -> Vec<u8> {
+    let mut reader = storage.get(user, path, 0).await.unwrap();
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).await.unwrap();
+    contents
+}
+
+#[tokio::test]
+async fn per_user() {
+    let file = image().build_file();
+    for storage in [
+        admins(Storage::source_builder(image().build())),
+        admins(Storage::builder(file.path())),
+    ] {
+        for user in [Named("anonymous"), Named("someone")] {
+            assert_eq!(names(&storage, &user).await, ["readme.txt"]);
+            for path in ["/secret.txt", "/private", "/private/deeper/key.txt"] {
+                let e = storage.metadata(&user, path).await.unwrap_err();
+                assert_eq!(e.kind(), ErrorKind::PermanentFileNotAvailable, "{path}");
+            }
+            assert!(storage.get(&user, "/secret.txt", 0).await.is_err());
+            assert!(storage.cwd(&user, "/private/deeper").await.is_err());
+        }
+
+        let admin = Named("admin");
+        assert_eq!(
+            names(&storage, &admin).await,
+            ["private", "readme.txt", "secret.txt"]
+        );
+        assert_eq!(
+            download(&storage, &admin, "/private/deeper/key.txt").await,
+            b"for admins too"
+        );
+        assert_eq!(
+            download(&storage, &admin, "/secret.txt").await,
+            b"for admins"
+        );
+        // Their view doesn't leak into the others'
+        assert_eq!(names(&storage, &DefaultUser {}).await, ["readme.txt"]);
+        assert_eq!(
+            download(&storage, &DefaultUser {}, "/readme.txt").await,
+            b"for everyone"
+        );
+    }
+}
+
+#[tokio::test]
+async fn shown_without_a_policy() {
+    let storage = Storage::from_source(image().build());
+    assert_eq!(
+        names(&storage, &DefaultUser {}).await,
+        ["private", "readme.txt", "secret.txt"]
+    );
+}
@@ -0,0 +1,228 @@
+//! Hostile input: paths, offsets and images crafted to trip the back-end up must be refused
+//! with errors, never panic the task serving the client.
+
+use std::{ffi::OsStr, io, os::unix::ffi::OsStrExt, path::Path, time::Duration};
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{
+    Aliases, FileVersions, IoPool, IsoSource, Namespace, Storage, StorageBuilder, View,
+    fixture::IsoBuilder,
+};
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .rock_ridge(true)
+        .joliet(true)
+        .file("/docs/readme.txt", b"the contents of the file")
+        .file("/docs/guide/intro.txt", b"welcome")
+        .dir("/empty")
+        .symlink("/latest", "docs/readme.txt")
+        .symlink("/loop", "loop")
+        .build()
+}
+
+/// Paths no honest client sends.
+fn paths() -> Vec<Vec<u8>> {
+    let mut paths: Vec<Vec<u8>> = [
+        "",
+        "/",
+        "..",
+        "/../../../etc/passwd",
+        "/docs/../../..",
+        "/docs/readme.txt/..",
+        "/docs/readme.txt/x",
+        "\\..\\..\\docs",
+        "C:\\docs\\readme.txt",
+        "/docs/README.TXT;1",
+        "/docs/readme.txt;",
+        "/docs/readme.txt;;1",
+        "/docs/readme.txt;99999999999999999999",
+        "/docs/readme.txt;-1",
+        "/docs/.;1",
+        "/docs/readme.txt.",
+        "/DOCS~1/READM~99",
+        "/DOCS~/~",
+        "/~/~1",
+        "/latest/x",
+        "/loop",
+        "/loop/x",
+        "/*",
+        "/docs/*.txt",
+        "/docs/[",
+        "/\u{0301}",
+        "/docs/re\u{0301}adme.txt",
+        "/\u{feff}docs",
+        "/ByDate/99999/99/99",
+        "/ByDate/0000",
+        "/AllFiles/readme.txt/x",
+        "/#joliet/../..",
+        "/#rock-ridge/docs/readme.txt/..",
+    ]
+    .into_iter()
+    .map(|path| path.as_bytes().to_vec())
+    .collect();
+    paths.push(b"/docs/\xFF\xFE.txt".to_vec());
+    paths.push(b"/docs/a\0b".to_vec());
+    paths.push(b"\0".to_vec());
+    paths.push(b"/docs/\r\n".to_vec());
+    paths.push(b"/a".repeat(10_000));
+    paths.push(b"/../".repeat(10_000));
+    paths.push([b"/".as_slice(), &[b'x'; 70_000]].concat());
+    paths
+}
+
+/// Every operation of a client on `path`, whatever they fail with.
+async fn poke(storage: &Storage, path: &Path) {
+    let user = DefaultUser {};
+    let _ = storage.metadata(&user, path).await;
+    let _ = storage.list(&user, path).await;
+    let _ = storage.cwd(&user, path).await;
+    let _ = storage.md5(&user, path).await;
+    for start in [0, 1, 24, 25, 4096, u64::MAX / 2, u64::MAX] {
+        if let Ok(mut reader) = storage.get(&user, path, start).await {
+            let mut contents = Vec::new();
+            let _ = tokio::io::AsyncReadExt::read_to_end(&mut reader, &mut contents).await;
+        }
+    }
+    let _ = storage.read_range(path, u64::MAX, u64::MAX).await;
+    let _ = storage.read_range(path, 3, u64::MAX).await;
+}
+
+fn configurations() -> Vec<StorageBuilder> {
+    let builder = || Storage::source_builder(image());
+    vec![
+        builder(),
+        builder().short_names(true),
+        builder().backslash_separators(true),
+        builder().unicode_normalization(true),
+        builder().file_versions(FileVersions::All),
+        builder().namespace(Namespace::Primary),
+        builder()
+            .namespace(Namespace::Joliet)
+            .namespace_fallback(true),
+        builder().root("/docs"),
+        builder().root("/missing"),
+        builder().aliases(Aliases::new().alias("/manual", "/docs/guide")),
+        builder()
+            .view(View::ByDate)
+            .view(View::AllFiles)
+            .view(View::Joliet)
+            .view(View::RockRidge),
+        builder().boot_images(true).gunzip(true),
+    ]
+}
+
+#[tokio::test]
+async fn hostile_paths() {
+    for builder in configurations() {
+        let storage = builder.build();
+        for path in paths() {
+            poke(&storage, Path::new(OsStr::from_bytes(&path))).await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn hostile_usernames() {
+    let image = IsoBuilder::new().file("/readme.txt", b"hi").build_file();
+    let template = image.path().to_str().unwrap().to_string() + "{username}";
+    let storage = Storage::templated(&template);
+    for name in [
+        "", ".", "..", "../x", "a/b", "a\\b", "\0", "\u{202e}", "x\n",
+    ] {
+        let user = Named(name);
+        let _ = storage.metadata(&user, "/readme.txt").await;
+        let _ = storage.list(&user, "/").await;
+        let _ = storage.get(&user, "/readme.txt", 0).await;
+    }
+}
+
+#[derive(Debug)]
+struct Named(&'static str);
+
+impl std::fmt::Display for Named {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl unftp_core::auth::UserDetail for Named {}
+
+/// Images with bytes of their descriptors and directories overwritten, which must fail the
+/// operations that read them, or serve what they can, but not panic.
+#[tokio::test]
+async fn damaged_images() {
+    let pristine = image();
+    let user = DefaultUser {};
+    // A fixed seed, so that a failure can be reproduced
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for _ in 0..1500 {
+        let mut image = pristine.clone();
+        for _ in 0..1 + random() % 4 {
+            let at = 16 * 2048 + (random() as usize) % (image.len() - 16 * 2048);
+            image[at] = match random() % 4 {
+                0 => 0,
+                1 => 0xff,
+                _ => random() as u8,
+            };
+        }
+        let storage = Storage::source_builder(image).lenient(true).build();
+        let _ = storage.fsck().await;
+        let _ = storage.conformance().await;
+        let _ = storage.extensions().await;
+        let _ = storage.volume_descriptors().await;
+        for path in ["/", "/docs", "/docs/guide", "/docs/readme.txt", "/latest"] {
+            if let Ok(entries) = storage.list(&user, path).await {
+                for entry in entries {
+                    let _ = entry.metadata.len();
+                    let _ = entry.metadata.modified();
+                }
+            }
+            if let Ok(mut reader) = storage.get(&user, path, 0).await {
+                let mut contents = Vec::new();
+                let _ = tokio::io::AsyncReadExt::read_to_end(&mut reader, &mut contents).await;
+            }
+        }
+    }
+}
+
+/// A source that panics, standing in for a parser tripped up by an image.
+struct Panicking;
+
+impl IsoSource for Panicking {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> io::Result<usize> {
+        panic!("tripped up");
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(1 << 20)
+    }
+}
+
+#[tokio::test]
+async fn panics_fail_the_operation() {
+    let user = DefaultUser {};
+    for storage in [
+        Storage::from_source(Panicking),
+        Storage::source_builder(Panicking)
+            .read_timeout(Duration::from_secs(5))
+            .build(),
+        Storage::source_builder(Panicking)
+            .io_pool(IoPool::new(1))
+            .build(),
+    ] {
+        let e = storage.metadata(&user, "/readme.txt").await.unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::LocalError);
+        // The back-end serves on after a panic
+        let e = storage.list(&user, "/").await.err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::LocalError);
+    }
+}
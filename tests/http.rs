@@ -0,0 +1,250 @@
+//! The tree of the image served over HTTP.
+
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use unftp_sbe_iso::{HttpGateway, Storage, fixture::IsoBuilder};
+
+const CONTENTS: &[u8] = b"0123456789abcdefghij";
+
+fn gateway() -> HttpGateway {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/readme.txt", CONTENTS)
+        .file("/docs/a b & <c>.txt", b"odd")
+        .file("/docs/guide/intro.txt", b"welcome")
+        .file("/empty.txt", b"")
+        .build();
+    HttpGateway::new(Storage::from_source(image))
+}
+
+struct Response {
+    status: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn text(&self) -> &str {
+        std::str::from_utf8(&self.body).unwrap()
+    }
+}
+
+async fn request(gateway: &HttpGateway, request: &str) -> Response {
+    let (mut client, server) = tokio::io::duplex(1024);
+    let gateway = gateway.clone();
+    let served = tokio::spawn(async move { gateway.handle(server).await });
+    // Requests refused before they are read whole find the connection closed
+    let _ = client.write_all(request.as_bytes()).await;
+    let mut raw = Vec::new();
+    client.read_to_end(&mut raw).await.unwrap();
+    served.await.unwrap().unwrap();
+
+    let at = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let head = std::str::from_utf8(&raw[..at]).unwrap();
+    let mut lines = head.split("\r\n");
+    let status = lines.next().unwrap().to_string();
+    let headers = lines
+        .map(|line| {
+            let (name, value) = line.split_once(": ").unwrap();
+            (name.to_string(), value.to_string())
+        })
+        .collect();
+    Response {
+        status,
+        headers,
+        body: raw[at + 4..].to_vec(),
+    }
+}
+
+fn get(path: &str, headers: &str) -> String {
+    format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n{headers}\r\n")
+}
+
+#[tokio::test]
+async fn files() {
+    let gateway = gateway();
+    let response = request(&gateway, &get("/docs/readme.txt", "")).await;
+    assert_eq!(response.status, "HTTP/1.1 200 OK");
+    assert_eq!(response.body, CONTENTS);
+    assert_eq!(response.header("Content-Length"), Some("20"));
+    assert_eq!(response.header("Accept-Ranges"), Some("bytes"));
+    assert_eq!(
+        response.header("Last-Modified"),
+        Some("Tue, 02 Jan 2024 03:04:05 GMT")
+    );
+
+    let response = request(&gateway, &get("/DOCS/a%20b%20%26%20%3Cc%3E.txt?x=1", "")).await;
+    assert_eq!(response.body, b"odd");
+
+    let response = request(&gateway, "HEAD /docs/readme.txt HTTP/1.1\r\n\r\n").await;
+    assert_eq!(response.status, "HTTP/1.1 200 OK");
+    assert_eq!(response.header("Content-Length"), Some("20"));
+    assert!(response.body.is_empty());
+
+    let response = request(&gateway, &get("/empty.txt", "")).await;
+    assert_eq!(response.status, "HTTP/1.1 200 OK");
+    assert!(response.body.is_empty());
+}
+
+#[tokio::test]
+async fn ranges() {
+    let gateway = gateway();
+    for (range, body, content_range) in [
+        ("bytes=2-5", &CONTENTS[2..6], "bytes 2-5/20"),
+        ("bytes=15-", &CONTENTS[15..], "bytes 15-19/20"),
+        ("bytes=-3", &CONTENTS[17..], "bytes 17-19/20"),
+        ("bytes=10-99", &CONTENTS[10..], "bytes 10-19/20"),
+    ] {
+        let response = request(
+            &gateway,
+            &get("/docs/readme.txt", &format!("Range: {range}\r\n")),
+        )
+        .await;
+        assert_eq!(response.status, "HTTP/1.1 206 Partial Content", "{range}");
+        assert_eq!(response.body, body, "{range}");
+        assert_eq!(response.header("Content-Range"), Some(content_range));
+    }
+
+    let response = request(&gateway, &get("/docs/readme.txt", "Range: bytes=20-\r\n")).await;
+    assert_eq!(response.status, "HTTP/1.1 416 Range Not Satisfiable");
+    assert_eq!(response.header("Content-Range"), Some("bytes */20"));
+    // Several ranges are served as the whole file
+    let response = request(
+        &gateway,
+        &get("/docs/readme.txt", "Range: bytes=0-1,4-5\r\n"),
+    )
+    .await;
+    assert_eq!(response.status, "HTTP/1.1 200 OK");
+    assert_eq!(response.body, CONTENTS);
+}
+
+#[tokio::test]
+async fn index_pages() {
+    let gateway = gateway();
+    let response = request(&gateway, &get("/docs", "")).await;
+    assert_eq!(response.status, "HTTP/1.1 301 Moved Permanently");
+    assert_eq!(response.header("Location"), Some("/docs/"));
+
+    let response = request(&gateway, &get("/docs/", "")).await;
+    assert_eq!(response.status, "HTTP/1.1 200 OK");
+    assert_eq!(
+        response.header("Content-Type"),
+        Some("text/html; charset=utf-8")
+    );
+    let page = response.text();
+    assert!(page.contains("<title>Index of /docs/</title>"), "{page}");
+    assert!(page.contains("<a href=\"../\">../</a>"), "{page}");
+    assert!(page.contains("<a href=\"guide/\">guide/</a>"), "{page}");
+    assert!(
+        page.contains("<a href=\"readme.txt\">readme.txt</a>"),
+        "{page}"
+    );
+    assert!(
+        page.contains("<a href=\"a%20b%20%26%20%3Cc%3E.txt\">a b &amp; &lt;c&gt;.txt</a>"),
+        "{page}"
+    );
+    // Directories come first
+    assert!(page.find("guide/").unwrap() < page.find("readme.txt").unwrap());
+
+    let page = request(&gateway, &get("/", "")).await;
+    assert!(!page.text().contains("../"));
+    assert!(page.text().contains("<a href=\"docs/\">docs/</a>"));
+}
+
+#[tokio::test]
+async fn refused() {
+    let gateway = gateway();
+    for (raw, status) in [
+        (get("/missing.txt", ""), "HTTP/1.1 404 Not Found"),
+        (get("/docs/readme.txt/x", ""), "HTTP/1.1 404 Not Found"),
+        (
+            "PUT /docs/readme.txt HTTP/1.1\r\n\r\n".to_string(),
+            "HTTP/1.1 405 Method Not Allowed",
+        ),
+        (get("/%ff", ""), "HTTP/1.1 400 Bad Request"),
+        (get("relative", ""), "HTTP/1.1 400 Bad Request"),
+        ("GARBAGE\r\n\r\n".to_string(), "HTTP/1.1 400 Bad Request"),
+        (
+            get("/", &format!("X-Long: {}\r\n", "x".repeat(20_000))),
+            "HTTP/1.1 431 Request Header Fields Too Large",
+        ),
+    ] {
+        assert_eq!(request(&gateway, &raw).await.status, status, "{raw:.40}");
+    }
+}
+
+#[tokio::test]
+async fn over_tcp() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(gateway().serve(listener));
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(get("/docs/guide/intro.txt", "").as_bytes())
+        .await
+        .unwrap();
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).await.unwrap();
+    assert!(raw.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    assert!(raw.ends_with(b"\r\n\r\nwelcome"));
+}
+
+#[tokio::test]
+async fn stalled_clients() {
+    let gateway = gateway().head_timeout(Duration::from_millis(100));
+    let (mut client, server) = tokio::io::duplex(1024);
+    // Half a head, and then nothing
+    client
+        .write_all(b"GET /docs/readme.txt HTTP/1.1\r\n")
+        .await
+        .unwrap();
+    let started = Instant::now();
+    let err = gateway.handle(server).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // Over TCP the connection is closed, and a stalled client only holds its own
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(gateway.serve(listener));
+    let mut stalled = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut served = tokio::net::TcpStream::connect(addr).await.unwrap();
+    served
+        .write_all(get("/docs/guide/intro.txt", "").as_bytes())
+        .await
+        .unwrap();
+    let mut raw = Vec::new();
+    served.read_to_end(&mut raw).await.unwrap();
+    assert!(raw.ends_with(b"welcome"));
+    let mut rest = Vec::new();
+    let read = tokio::time::timeout(Duration::from_secs(5), stalled.read_to_end(&mut rest));
+    assert_eq!(read.await.unwrap().unwrap(), 0);
+}
+
+#[tokio::test]
+async fn connections_limited() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(gateway().max_connections(1).serve(listener));
+    let stalled = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut waiting = tokio::net::TcpStream::connect(addr).await.unwrap();
+    waiting
+        .write_all(get("/docs/guide/intro.txt", "").as_bytes())
+        .await
+        .unwrap();
+    let mut raw = Vec::new();
+    // Not served while the first connection holds the only one
+    let early = tokio::time::timeout(Duration::from_millis(200), waiting.read_to_end(&mut raw));
+    assert!(early.await.is_err());
+    drop(stalled);
+    let read = tokio::time::timeout(Duration::from_secs(5), waiting.read_to_end(&mut raw));
+    read.await.unwrap().unwrap();
+    assert!(raw.starts_with(b"HTTP/1.1 200 OK\r\n"), "{raw:?}");
+}
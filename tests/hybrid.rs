@@ -0,0 +1,110 @@
+//! Isohybrid images, with partition tables in the system area and padding after the volume.
+
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{PartitionKind, Storage, fixture::IsoBuilder};
+
+const EFI_SYSTEM: [u8; 16] = [
+    0x28, 0x73, 0x2a, 0xc1, 0x1f, 0xf8, 0xd2, 0x11, 0xba, 0x4b, 0x00, 0xa0, 0xc9, 0x3e, 0xc9, 0x3b,
+];
+
+fn plain() -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/readme.txt", b"Hello")
+        .efi_image(&[0xEF; 4096])
+        .build()
+}
+
+/// Lays out `image` the way isohybrid does: an MBR with the ISO and an EFI partition, a GPT
+/// with the same EFI partition, and padding up to the next MiB.
+fn hybrid(mut image: Vec<u8>) -> Vec<u8> {
+    let sectors = image.len() as u32 / 512;
+    let mbr = &mut image[..512];
+    mbr[446] = 0x80;
+    mbr[446 + 4] = 0x17;
+    mbr[446 + 12..446 + 16].copy_from_slice(&sectors.to_le_bytes());
+    mbr[462 + 4] = 0xEF;
+    mbr[462 + 8..462 + 12].copy_from_slice(&64u32.to_le_bytes());
+    mbr[462 + 12..462 + 16].copy_from_slice(&8u32.to_le_bytes());
+    mbr[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+    let header = &mut image[512..1024];
+    header[..8].copy_from_slice(b"EFI PART");
+    header[72..80].copy_from_slice(&2u64.to_le_bytes());
+    header[80..84].copy_from_slice(&4u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+
+    let entry = &mut image[1024..1152];
+    entry[..16].copy_from_slice(&EFI_SYSTEM);
+    entry[32..40].copy_from_slice(&64u64.to_le_bytes());
+    entry[40..48].copy_from_slice(&71u64.to_le_bytes());
+    for (i, unit) in "EFI boot".encode_utf16().enumerate() {
+        entry[56 + i * 2..58 + i * 2].copy_from_slice(&unit.to_le_bytes());
+    }
+
+    image.resize(image.len().next_multiple_of(1024 * 1024), 0);
+    image
+}
+
+#[tokio::test]
+async fn layout() {
+    let image = plain();
+    let len = image.len() as u64;
+    let layout = Storage::from_source(hybrid(image))
+        .hybrid_layout()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(layout.mbr.len(), 2);
+    assert_eq!(layout.mbr[0].kind, PartitionKind::Mbr(0x17));
+    assert!(layout.mbr[0].bootable);
+    assert_eq!((layout.mbr[0].start, layout.mbr[0].len), (0, len));
+    assert_eq!(layout.mbr[1].number, 2);
+    assert_eq!(
+        (layout.mbr[1].start, layout.mbr[1].len),
+        (64 * 512, 8 * 512)
+    );
+    let gpt = layout.gpt.unwrap();
+    assert_eq!(gpt.len(), 1);
+    assert_eq!(gpt[0].kind, PartitionKind::Gpt(EFI_SYSTEM));
+    assert_eq!((gpt[0].start, gpt[0].len), (64 * 512, 8 * 512));
+    assert_eq!(gpt[0].name, "EFI boot");
+}
+
+#[tokio::test]
+async fn plain_images() {
+    let storage = Storage::from_source(plain());
+    assert_eq!(storage.hybrid_layout().await.unwrap(), None);
+    // Too short to hold even an MBR
+    let storage = Storage::from_source(vec![0; 100]);
+    assert_eq!(storage.hybrid_layout().await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn served_like_plain_images() {
+    let storage = Storage::source_builder(hybrid(plain()))
+        .boot_images(true)
+        .build();
+    let mut reader = storage
+        .get(&DefaultUser {}, "/docs/readme.txt", 1)
+        .await
+        .unwrap();
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).await.unwrap();
+    assert_eq!(contents, b"ello");
+    assert_eq!(
+        storage
+            .metadata(&DefaultUser {}, "/efi.img")
+            .await
+            .unwrap()
+            .len(),
+        4096
+    );
+    assert!(storage.volume_descriptors().await.unwrap().len() >= 2);
+    let report = storage.fsck().await.unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+}
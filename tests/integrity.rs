@@ -0,0 +1,79 @@
+//! Checking served files against the checksum lists in the image.
+#![cfg(feature = "checksums")]
+
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{CacheConfig, IntegrityMode, Storage, StorageBuilder, fixture::IsoBuilder};
+
+fn image() -> Vec<u8> {
+    let sha256 = "5a64d1da606d38c523cff37e977c894e45590641d5e85f52150ea05b0213042d  ./docs/good.txt\n\
+                  5a64d1da606d38c523cff37e977c894e45590641d5e85f52150ea05b0213042d  ./docs/bad.txt\n";
+    let md5 = "0140c31db86293a1a1e080ce9b91305f *docs/old.txt\n";
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/sha256sum.txt", sha256.as_bytes())
+        .file("/md5sum.txt", md5.as_bytes())
+        .file("/docs/good.txt", b"good contents\n")
+        .file("/docs/bad.txt", b"gooD contents\n")
+        .file("/docs/old.txt", b"old contents\n")
+        .file("/docs/unlisted.txt", b"anything")
+        .build()
+}
+
+async fn download(storage: &Storage, path: &str, start: u64) -> Option<Vec<u8>> {
+    let mut reader = storage.get(&DefaultUser {}, path, start).await.ok()?;
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).await.unwrap();
+    Some(contents)
+}
+
+async fn assert_aborts(builder: StorageBuilder) {
+    let storage = builder
+        .integrity(IntegrityMode::Abort)
+        .open()
+        .await
+        .unwrap();
+    assert_eq!(
+        download(&storage, "/docs/good.txt", 0).await.unwrap(),
+        b"good contents\n"
+    );
+    assert_eq!(
+        download(&storage, "/docs/GOOD.TXT", 5).await.unwrap(),
+        b"contents\n"
+    );
+    assert!(download(&storage, "/docs/old.txt", 0).await.is_some());
+    assert!(download(&storage, "/docs/unlisted.txt", 0).await.is_some());
+    assert!(download(&storage, "/docs/bad.txt", 0).await.is_none());
+    assert!(download(&storage, "/docs/bad.txt", 3).await.is_none());
+}
+
+#[tokio::test]
+async fn abort() {
+    assert_aborts(Storage::source_builder(image())).await;
+}
+
+#[tokio::test]
+async fn abort_with_content_cache() {
+    let cache = CacheConfig {
+        content_cache_mb: 1,
+        ..CacheConfig::default()
+    };
+    assert_aborts(Storage::source_builder(image()).cache(cache)).await;
+}
+
+#[tokio::test]
+async fn log() {
+    let storage = Storage::source_builder(image())
+        .integrity(IntegrityMode::Log)
+        .build();
+    assert_eq!(
+        download(&storage, "/docs/bad.txt", 0).await.unwrap(),
+        b"gooD contents\n"
+    );
+}
+
+#[tokio::test]
+async fn off_by_default() {
+    let storage = Storage::from_source(image());
+    assert!(download(&storage, "/docs/bad.txt", 0).await.is_some());
+}
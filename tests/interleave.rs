@@ -0,0 +1,79 @@
+//! Files recorded in interleaved mode, in units of sectors separated by gaps.
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+const SECTOR: usize = 2048;
+
+fn contents() -> Vec<u8> {
+    (0..5 * SECTOR as u32 + 100)
+        .map(|i| (i % 251) as u8)
+        .collect()
+}
+
+/// An image whose `/MOVIE.MPG` is recorded in units of 2 sectors with gaps of 1 sector of junk
+/// between them, moved to the end of the image.
+fn image() -> Vec<u8> {
+    let contents = contents();
+    let mut image = IsoBuilder::new()
+        .file("/movie.mpg", &contents)
+        .file("/after.txt", b"after")
+        .build();
+    let record = (0..image.len() - 42)
+        .find(|&r| &image[r + 33..r + 42] == b"MOVIE.MPG")
+        .unwrap();
+    let lba = (image.len() / SECTOR) as u32;
+    for unit in contents.chunks(2 * SECTOR) {
+        let mut sectors = unit.to_vec();
+        sectors.resize(2 * SECTOR, 0);
+        image.extend(sectors);
+        image.extend([0xEE; SECTOR]);
+    }
+    image[record + 2..record + 6].copy_from_slice(&lba.to_le_bytes());
+    image[record + 6..record + 10].copy_from_slice(&lba.to_be_bytes());
+    image[record + 26] = 2;
+    image[record + 27] = 1;
+    image
+}
+
+#[tokio::test]
+async fn served_in_order() {
+    let storage = Storage::from_source(image());
+    let user = DefaultUser {};
+    for start in [0, 3 * SECTOR + 7] {
+        let mut served = Vec::new();
+        storage
+            .get(&user, "/movie.mpg", start as u64)
+            .await
+            .unwrap()
+            .read_to_end(&mut served)
+            .await
+            .unwrap();
+        assert_eq!(served, contents()[start..]);
+    }
+    let report = storage.fsck().await.unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+}
+
+#[tokio::test]
+async fn random_access() {
+    let storage = Storage::from_source(image());
+    let mut file = storage.open("/MOVIE.MPG").await.unwrap();
+    assert_eq!(file.len(), contents().len() as u64);
+    file.seek(std::io::SeekFrom::Start(4 * SECTOR as u64 - 10))
+        .await
+        .unwrap();
+    let mut across = [0; 20];
+    file.read_exact(&mut across).await.unwrap();
+    assert_eq!(across[..], contents()[4 * SECTOR - 10..4 * SECTOR + 10]);
+}
+
+#[tokio::test]
+async fn truncated() {
+    let mut image = image();
+    // Leaves 50 of the 100 bytes of the last unit
+    image.truncate(image.len() - 3 * SECTOR + 50);
+    let storage = Storage::from_source(image);
+    assert!(storage.get(&DefaultUser {}, "/movie.mpg", 0).await.is_err());
+}
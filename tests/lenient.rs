@@ -0,0 +1,129 @@
+//! Opening images whose volume descriptors don't conform with `StorageBuilder::lenient`, and
+//! images with structural problems with `StorageBuilder::validation`.
+
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{Storage, ValidationMode, fixture::IsoBuilder};
+
+const PRIMARY: usize = 16 * 2048;
+const JOLIET: usize = 17 * 2048;
+
+fn image(joliet: bool) -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(joliet)
+        .file("/docs/Readme.txt", b"Hello")
+        .build()
+}
+
+/// The names in the root directory, or `None` if the image can't be listed.
+async fn root(image: &[u8], lenient: bool) -> Option<Vec<String>> {
+    let storage = Storage::source_builder(image.to_vec())
+        .lenient(lenient)
+        .build();
+    let mut names: Vec<_> = storage
+        .list(&DefaultUser {}, "/docs")
+        .await
+        .ok()?
+        .into_iter()
+        .map(|f| f.path.to_str().unwrap().to_string())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    Some(names)
+}
+
+async fn assert_repaired(image: &[u8], expected: &str) {
+    assert_eq!(root(image, false).await, None);
+    assert_eq!(root(image, true).await.unwrap(), [expected]);
+}
+
+#[tokio::test]
+async fn conforming_images_are_untouched() {
+    let image = image(true);
+    assert_eq!(root(&image, true).await.unwrap(), ["Readme.txt"]);
+    assert_eq!(root(&image, false).await.unwrap(), ["Readme.txt"]);
+}
+
+#[tokio::test]
+async fn descriptor_version() {
+    let mut image = image(true);
+    image[JOLIET + 6] = 3;
+    assert_repaired(&image, "Readme.txt").await;
+}
+
+#[tokio::test]
+async fn enhanced_volume() {
+    let mut image = image(true);
+    image[JOLIET + 6] = 2;
+    // The enhanced volume is skipped, which leaves the primary one
+    assert_repaired(&image, "README.TXT").await;
+}
+
+#[tokio::test]
+async fn invalid_dates() {
+    let mut image = image(false);
+    image[PRIMARY + 813..PRIMARY + 829].copy_from_slice(b"2024-01-02 03:04");
+    image[PRIMARY + 847..PRIMARY + 863].fill(b' ');
+    assert_repaired(&image, "README.TXT").await;
+}
+
+#[tokio::test]
+async fn missing_terminator() {
+    let mut image = image(false);
+    image[JOLIET..JOLIET + 2048].fill(0);
+    assert_repaired(&image, "README.TXT").await;
+}
+
+#[tokio::test]
+async fn byte_orders_disagree() {
+    let mut image = image(true);
+    // The little-endian copy of the root extent, which cdfs reads, points past the image
+    image[JOLIET + 158..JOLIET + 162].copy_from_slice(&0x00FF_FFFFu32.to_le_bytes());
+    assert_repaired(&image, "Readme.txt").await;
+}
+
+#[tokio::test]
+async fn validation_modes() {
+    let contents = vec![7; 100_000];
+    let mut image = IsoBuilder::new()
+        .file("/readme.txt", b"hello")
+        .file("/zz.bin", &contents)
+        .build();
+    // Cut short in the last file, which is what a broken download looks like
+    image.truncate(image.len() - 50_000);
+    let user = DefaultUser {};
+    let storage = |mode| {
+        Storage::source_builder(image.clone())
+            .validation(mode)
+            .build()
+    };
+
+    let strict = storage(ValidationMode::Strict);
+    let e = strict.list(&user, "/").await.err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::LocalError);
+    let message = std::error::Error::source(&e).unwrap().to_string();
+    assert!(message.contains("structural problems"), "{message}");
+    assert!(strict.get(&user, "/readme.txt", 0).await.is_err());
+    for mode in [ValidationMode::Standard, ValidationMode::Lenient] {
+        assert!(storage(mode).list(&user, "/").await.is_ok(), "{mode:?}");
+    }
+    let mut readme = String::new();
+    storage(ValidationMode::Lenient)
+        .get(&user, "/readme.txt", 0)
+        .await
+        .unwrap()
+        .read_to_string(&mut readme)
+        .await
+        .unwrap();
+    assert_eq!(readme, "hello");
+
+    // A conforming image passes
+    let image = IsoBuilder::new().file("/readme.txt", b"hello").build();
+    let strict = Storage::source_builder(image)
+        .validation(ValidationMode::Strict)
+        .build();
+    assert!(strict.list(&user, "/").await.is_ok());
+}
@@ -0,0 +1,111 @@
+//! Limits on what the back-end serves.
+
+use std::time::{Duration, Instant};
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{Storage, WalkLimits, fixture::IsoBuilder};
+
+#[tokio::test]
+async fn max_file_size() {
+    let image = IsoBuilder::new()
+        .file("/small.bin", &[0; 1000])
+        .file("/exact.bin", &[0; 1024])
+        .file("/large.bin", &[0; 1025])
+        .build_file();
+    let storage = Storage::builder(image.path()).max_file_size(1024).build();
+    let user = DefaultUser {};
+
+    assert!(storage.get(&user, "/small.bin", 0).await.is_ok());
+    assert!(storage.get(&user, "/exact.bin", 0).await.is_ok());
+    let Err(err) = storage.get(&user, "/large.bin", 0).await else {
+        panic!("served a file over the limit");
+    };
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    let message = std::error::Error::source(&err).unwrap().to_string();
+    assert!(message.contains("1025 bytes"), "{message}");
+    assert!(message.contains("maximum of 1024 bytes"), "{message}");
+
+    // Big files are still listed
+    assert_eq!(storage.list(&user, "/").await.unwrap().len(), 5);
+}
+
+/// Reading from a FIFO that nobody writes to blocks forever, like a hung network mount.
+#[cfg(unix)]
+#[tokio::test]
+async fn read_timeout() {
+    let fifo = std::env::temp_dir().join(format!("unftp-sbe-iso-fifo-{}", std::process::id()));
+    let status = std::process::Command::new("mkfifo")
+        .arg(&fifo)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let storage = Storage::builder(&fifo)
+        .read_timeout(Duration::from_millis(200))
+        .build();
+    let user = DefaultUser {};
+
+    let started = Instant::now();
+    let Err(err) = storage.list(&user, "/").await else {
+        panic!("listed a FIFO");
+    };
+    assert_eq!(err.kind(), ErrorKind::TransientFileNotAvailable);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let err = storage.get(&user, "/file", 0).await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::TransientFileNotAvailable);
+
+    // Opening the FIFO for writing releases all reads blocked on opening it
+    drop(std::fs::OpenOptions::new().write(true).open(&fifo).unwrap());
+    std::fs::remove_file(fifo).unwrap();
+}
+
+#[tokio::test]
+async fn walk_limits() {
+    let image = IsoBuilder::new()
+        .file("/a/b/c/deep.txt", &[0; 100])
+        .file("/a/one.txt", &[0; 100])
+        .file("/top.txt", &[0; 100])
+        .build();
+    let limited = |limits| {
+        Storage::source_builder(image.clone())
+            .walk_limits(limits)
+            .build()
+    };
+    let message = |err: &unftp_core::storage::Error| {
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        std::error::Error::source(err).unwrap().to_string()
+    };
+
+    let storage = limited(WalkLimits::new().max_depth(4).max_entries(6).max_bytes(300));
+    assert_eq!(storage.list_recursive("/").await.unwrap().len(), 6);
+
+    let storage = limited(WalkLimits::new().max_depth(3));
+    let err = storage.list_recursive("/").await.err().unwrap();
+    let deep = message(&err);
+    assert!(deep.contains("A/B/C/DEEP.TXT"), "{deep}");
+    assert!(deep.contains("maximum of 3 levels"), "{deep}");
+    // Depth counts from where the walk starts
+    assert_eq!(storage.list_recursive("/a").await.unwrap().len(), 4);
+
+    let storage = limited(WalkLimits::new().max_entries(5));
+    let err = storage.list_recursive("/").await.err().unwrap();
+    assert!(message(&err).contains("maximum of 5 entries"));
+    // The stream yields what fit and then the error, and ends there
+    let mut walk = storage.walk("/");
+    let mut walked = 0;
+    let err = loop {
+        match walk.next_entry().await {
+            Ok(Some(_)) => walked += 1,
+            Ok(None) => panic!("walked past the limit"),
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(walked, 5);
+    assert!(message(&err).contains("maximum of 5 entries"));
+    assert!(walk.next_entry().await.unwrap().is_none());
+
+    let storage = limited(WalkLimits::new().max_bytes(299));
+    let err = storage.list_recursive("/").await.err().unwrap();
+    assert!(message(&err).contains("maximum of 299 bytes"));
+}
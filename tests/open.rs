@@ -0,0 +1,70 @@
+//! Opening the image when the back-end is built.
+
+use std::time::Duration;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{CacheConfig, Storage, fixture::IsoBuilder};
+
+#[tokio::test]
+async fn missing_image() {
+    let path = std::env::temp_dir().join("unftp-sbe-iso-missing.iso");
+    let lazy = Storage::new(&path);
+    assert!(lazy.cwd(&DefaultUser {}, "/").await.is_err());
+    assert!(Storage::builder(&path).open().await.is_err());
+}
+
+#[tokio::test]
+async fn root_not_a_directory() {
+    let image = IsoBuilder::new().file("/file.txt", b"file").build_file();
+    let Err(err) = Storage::builder(image.path())
+        .root("/file.txt")
+        .open()
+        .await
+    else {
+        panic!("opened with a file as the root");
+    };
+    assert_eq!(err.kind(), ErrorKind::PermanentDirectoryNotAvailable);
+}
+
+#[tokio::test]
+async fn warms_caches() {
+    let image = IsoBuilder::new().file("/file.txt", b"file").build_file();
+    let storage = Storage::builder(image.path())
+        .cache(CacheConfig {
+            listing_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .open()
+        .await
+        .unwrap();
+    assert_eq!(storage.cache_stats().listings.misses, 1);
+    assert!(storage.list(&DefaultUser {}, "/").await.is_ok());
+    assert_eq!(storage.cache_stats().listings.hits, 1);
+}
+
+#[tokio::test]
+async fn shared_between_connections() {
+    let image = IsoBuilder::new().file("/file.txt", b"file").build_file();
+    let factory = Storage::builder(image.path())
+        .cache(CacheConfig {
+            listing_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .build()
+        .factory();
+    // What one connection reads, the next one finds in the caches
+    assert!(factory().list(&DefaultUser {}, "/").await.is_ok());
+    assert!(factory().list(&DefaultUser {}, "/").await.is_ok());
+    assert_eq!(factory().cache_stats().listings.misses, 1);
+    assert_eq!(factory().cache_stats().listings.hits, 1);
+
+    let shared = Storage::shared(image.path());
+    assert!(
+        shared()
+            .metadata(&DefaultUser {}, "/file.txt")
+            .await
+            .is_ok()
+    );
+}
@@ -0,0 +1,123 @@
+//! The spans of operations, with their OpenTelemetry attributes.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tokio::io::AsyncReadExt;
+use tracing::{
+    Event, Metadata, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+};
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+/// The fields of every span, by span, with the spans entered.
+#[derive(Default)]
+struct Spans {
+    fields: Arc<Mutex<Vec<HashMap<String, String>>>>,
+    metadata: Mutex<Vec<&'static Metadata<'static>>>,
+    entered: Mutex<Vec<Id>>,
+}
+
+struct Fields<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(
+            field.name().to_string(),
+            format!("{value:?}").trim_matches('"').to_string(),
+        );
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+}
+
+impl Subscriber for Spans {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = HashMap::new();
+        span.record(&mut Fields(&mut fields));
+        let mut spans = self.fields.lock().unwrap();
+        spans.push(fields);
+        self.metadata.lock().unwrap().push(span.metadata());
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.fields.lock().unwrap();
+        values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1]));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> tracing_core::span::Current {
+        match self.entered.lock().unwrap().last() {
+            Some(id) => {
+                let metadata = self.metadata.lock().unwrap()[id.into_u64() as usize - 1];
+                tracing_core::span::Current::new(id.clone(), metadata)
+            }
+            None => tracing_core::span::Current::none(),
+        }
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn spans_carry_attributes() {
+    let subscriber = Spans::default();
+    let spans = subscriber.fields.clone();
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let image = IsoBuilder::new()
+        .volume_id("RELEASE_1")
+        .file("/readme.txt", b"hello")
+        .build();
+    let storage = Storage::from_source(image);
+    let user = DefaultUser {};
+    assert!(storage.list(&user, "/").await.is_ok());
+    assert!(storage.metadata(&user, "/missing").await.is_err());
+    let mut readme = Vec::new();
+    storage
+        .get(&user, "/readme.txt", 0)
+        .await
+        .unwrap()
+        .read_to_end(&mut readme)
+        .await
+        .unwrap();
+
+    let spans = spans.lock().unwrap();
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span["otel.name"] == name)
+            .unwrap_or_else(|| panic!("no {name} span"))
+    };
+    let list = span("iso.list");
+    assert_eq!(list["iso.volume_id"], "RELEASE_1");
+    assert_eq!(list["ftp.user"], user.to_string());
+    assert_eq!(list["file.path"], "/");
+    assert_eq!(list["otel.status_code"], "OK");
+    let lookup = span("iso.lookup");
+    assert_eq!(lookup["otel.status_code"], "ERROR");
+    assert!(lookup.contains_key("error.message"));
+    let get = span("iso.get");
+    assert_eq!(get["file.path"], "/readme.txt");
+    assert_eq!(get["bytes"], "5");
+}
@@ -0,0 +1,612 @@
+//! The odd path forms FTP clients send resolve like their normal form.
+
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{Filter, Namespace, Storage, fixture::IsoBuilder};
+
+/// The sorted names in the listing of `path`, leaving out `.` and `..`.
+async fn listed(storage: &Storage, path: &str) -> Vec<String> {
+    let mut names: Vec<_> = storage
+        .list(&DefaultUser {}, path)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| f.path.to_str().unwrap().to_string())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn odd_forms_resolve() {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/dir/sub/file.txt", b"contents")
+        .build_file();
+    let storage = Storage::new(image.path());
+    let user = DefaultUser {};
+
+    for path in [
+        "/dir/sub/file.txt",
+        "dir/sub/file.txt",
+        "//dir///sub//file.txt",
+        "/dir/sub/file.txt/",
+        "/./dir/./sub/file.txt",
+        "/dir/other/../sub/file.txt",
+        "/../dir/sub/file.txt",
+    ] {
+        let meta = storage.metadata(&user, path).await.unwrap();
+        assert_eq!(meta.len(), 8, "{path}");
+        assert!(storage.get(&user, path, 0).await.is_ok(), "{path}");
+    }
+
+    for path in ["/dir/", "//dir//sub//", "dir/sub/..", "/dir/sub/../../dir"] {
+        assert!(storage.cwd(&user, path).await.is_ok(), "{path}");
+        assert!(
+            storage.metadata(&user, path).await.unwrap().is_dir(),
+            "{path}"
+        );
+    }
+
+    for path in ["", "/", "//", "/..", "/dir/.."] {
+        assert!(storage.list(&user, path).await.is_ok(), "{path}");
+    }
+}
+
+#[tokio::test]
+async fn stats_use_the_normal_form() {
+    let image = IsoBuilder::new().file("/dir/file.txt", b"x").build_file();
+    let storage = Storage::new(image.path());
+    for path in ["/dir/file.txt", "//dir//file.txt", "dir/./file.txt"] {
+        storage.get(&DefaultUser {}, path, 0).await.unwrap();
+    }
+    let stats = storage.stats();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[std::path::Path::new("/dir/file.txt")].downloads, 3);
+}
+
+#[tokio::test]
+async fn backslash_separators() {
+    let image = IsoBuilder::new()
+        .file("/DIR/FILE.TXT", b"contents")
+        .build_file();
+    let user = DefaultUser {};
+
+    let storage = Storage::new(image.path());
+    assert!(storage.metadata(&user, "DIR\\FILE.TXT").await.is_err());
+
+    let storage = Storage::builder(image.path())
+        .backslash_separators(true)
+        .build();
+    assert!(storage.get(&user, "DIR\\FILE.TXT", 0).await.is_ok());
+    assert!(storage.cwd(&user, "\\DIR\\").await.is_ok());
+    let listing = storage.list(&user, "\\").await.unwrap();
+    assert!(
+        listing
+            .iter()
+            .any(|f| f.path == std::path::Path::new("DIR"))
+    );
+}
+
+#[tokio::test]
+async fn unicode_normalization() {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/caf\u{e9}/r\u{e9}sum\u{e9}.txt", b"contents")
+        .build_file();
+    let user = DefaultUser {};
+    let decomposed = "/cafe\u{301}/re\u{301}sume\u{301}.txt";
+
+    let storage = Storage::new(image.path());
+    assert!(
+        storage
+            .get(&user, "/caf\u{e9}/r\u{e9}sum\u{e9}.txt", 0)
+            .await
+            .is_ok()
+    );
+    assert!(storage.get(&user, decomposed, 0).await.is_err());
+
+    let storage = Storage::builder(image.path())
+        .unicode_normalization(true)
+        .build();
+    assert!(storage.get(&user, decomposed, 0).await.is_ok());
+    assert!(
+        storage
+            .get(&user, "/CAF\u{e9}/r\u{e9}sum\u{e9}.txt", 0)
+            .await
+            .is_ok()
+    );
+}
+
+/// Every name in a listing must resolve to the entry it was listed for, whatever namespace the
+/// image uses.
+async fn assert_round_trip(storage: &Storage, dir: &str) {
+    use tokio::io::AsyncReadExt;
+
+    let user = DefaultUser {};
+    for entry in storage.list(&user, dir).await.unwrap() {
+        let name = entry.path.to_str().unwrap();
+        if name == "." || name == ".." {
+            continue;
+        }
+        let path = format!("{dir}/{name}");
+        let meta = storage.metadata(&user, &path).await.unwrap();
+        assert_eq!(meta.is_dir(), entry.metadata.is_dir(), "{path}");
+        if meta.is_dir() {
+            Box::pin(assert_round_trip(storage, &path)).await;
+        } else {
+            let mut contents = String::new();
+            storage
+                .get(&user, &path, 0)
+                .await
+                .unwrap()
+                .read_to_string(&mut contents)
+                .await
+                .unwrap();
+            assert_eq!(contents, path, "{path}");
+        }
+    }
+}
+
+fn names_differing_in_case() -> IsoBuilder {
+    IsoBuilder::new()
+        .file("/Readme.txt", b"/Readme.txt")
+        .file("/README.txt", b"/README.txt")
+        .primary_name("/README.txt", "README_2.TXT;1")
+        .file("/Docs/a.txt", b"/Docs/a.txt")
+        .file("/DOCS/A.txt", b"/DOCS/A.txt")
+        .primary_name("/DOCS", "DOCS_2")
+}
+
+#[tokio::test]
+async fn listed_names_round_trip() {
+    let primary = IsoBuilder::new()
+        .file("/readme.txt", b"/README.TXT")
+        .file("/docs/a", b"/DOCS/A")
+        .dir("/empty");
+    for builder in [
+        primary,
+        names_differing_in_case().rock_ridge(true),
+        names_differing_in_case().joliet(true),
+    ] {
+        let image = builder.build_file();
+        assert_round_trip(&Storage::new(image.path()), "").await;
+        let storage = Storage::builder(image.path())
+            .unicode_normalization(true)
+            .build();
+        assert_round_trip(&storage, "").await;
+    }
+}
+
+#[tokio::test]
+async fn short_names() {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file(
+            "/Program Files/Read Me First.txt",
+            b"/PROGRA~1/README~1.TXT",
+        )
+        .file("/Program Files/readme.txt", b"/PROGRA~1/README.TXT")
+        .file("/autoexec.bat", b"/AUTOEXEC.BAT")
+        .build_file();
+    let storage = Storage::builder(image.path()).short_names(true).build();
+    let user = DefaultUser {};
+
+    let mut names: Vec<_> = storage
+        .list(&user, "/PROGRA~1")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| f.path.to_str().unwrap().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, [".", "..", "README.TXT", "README~1.TXT"]);
+    assert_round_trip(&storage, "").await;
+
+    // The names stored in the image keep working
+    assert!(
+        storage
+            .get(&user, "/Program Files/Read Me First.txt", 0)
+            .await
+            .is_ok()
+    );
+    assert!(
+        storage
+            .get(&user, "/progra~1/readme~1.txt", 0)
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn chroot() {
+    let image = IsoBuilder::new()
+        .rock_ridge(true)
+        .file("/pub/files/readme.txt", b"readme")
+        .file("/pub/files/docs/guide.txt", b"guide")
+        .file("/pub/secret.txt", b"secret")
+        .file("/private.txt", b"private")
+        .symlink("/pub/files/absolute", "/docs/guide.txt")
+        .symlink("/pub/files/escape", "../../private.txt")
+        .symlink("/pub/files/docs/up", "../readme.txt")
+        // Would reach `/readme.txt` of the subtree by staying at its root
+        .symlink("/pub/files/sneaky", "../readme.txt")
+        .build_file();
+    let storage = Storage::builder(image.path()).root("/pub/files").build();
+    let user = DefaultUser {};
+
+    assert_eq!(
+        listed(&storage, "/").await,
+        ["absolute", "docs", "escape", "readme.txt", "sneaky"]
+    );
+    assert!(storage.get(&user, "/docs/up", 0).await.is_ok());
+    let err = storage.get(&user, "/escape", 0).await.err().unwrap();
+    let message = std::error::Error::source(&err).unwrap().to_string();
+    assert!(
+        message.contains("'/escape' points outside of the root"),
+        "{message}"
+    );
+    assert!(storage.get(&user, "/readme.txt", 0).await.is_ok());
+    assert!(storage.get(&user, "/docs/guide.txt", 0).await.is_ok());
+    assert!(storage.get(&user, "/absolute", 0).await.is_ok());
+    for path in [
+        "/pub/files/readme.txt",
+        "/../secret.txt",
+        "/../../private.txt",
+        "/escape",
+        "/sneaky",
+        "/docs/../../secret.txt",
+    ] {
+        assert!(storage.get(&user, path, 0).await.is_err(), "{path}");
+    }
+    // `..` at the root of the subtree stays there
+    assert!(storage.get(&user, "/../readme.txt", 0).await.is_ok());
+
+    let storage = Storage::builder(image.path())
+        .root("/pub/secret.txt")
+        .build();
+    assert!(storage.list(&user, "/").await.is_err());
+}
+
+#[tokio::test]
+async fn single_directory_descended() {
+    let wrapped = IsoBuilder::new()
+        .joliet(true)
+        .file("/debian-12.5.0/readme.txt", b"readme")
+        .file("/debian-12.5.0/pool/main.deb", b"deb")
+        .build();
+    let storage = Storage::source_builder(wrapped.clone())
+        .descend_single_directory(true)
+        .build();
+    let user = DefaultUser {};
+    assert_eq!(listed(&storage, "/").await, ["pool", "readme.txt"]);
+    assert_eq!(listed(&storage, "/../..").await, ["pool", "readme.txt"]);
+    assert!(storage.get(&user, "/pool/main.deb", 0).await.is_ok());
+    assert!(storage.metadata(&user, "/debian-12.5.0").await.is_err());
+    // Looked for in the configured root, which holds more than one entry
+    let storage = Storage::source_builder(wrapped)
+        .root("/debian-12.5.0")
+        .descend_single_directory(true)
+        .build();
+    assert_eq!(listed(&storage, "/").await, ["pool", "readme.txt"]);
+
+    // Not when the root holds anything besides the directory
+    let unwrapped = IsoBuilder::new()
+        .joliet(true)
+        .file("/readme.txt", b"readme")
+        .file("/pool/main.deb", b"deb")
+        .build();
+    let storage = Storage::source_builder(unwrapped)
+        .descend_single_directory(true)
+        .build();
+    assert_eq!(listed(&storage, "/").await, ["pool", "readme.txt"]);
+}
+
+#[tokio::test]
+async fn aliases() {
+    use unftp_sbe_iso::Aliases;
+
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/x86_64/images/boot.iso", b"boot")
+        .file("/x86_64/images/efi.img", b"efi")
+        .file("/x86_64/readme.txt", b"readme")
+        .file("/docs/old.txt", b"old")
+        .file("/usr/share/doc/new.txt", b"new")
+        .build_file();
+    let aliases = Aliases::parse(
+        "/boot.iso = /x86_64/images/boot.iso\n\
+         /docs = /usr/share/doc\n\
+         /missing = /nowhere\n",
+    )
+    .unwrap()
+    .alias("/os", "/x86_64");
+    let storage = Storage::builder(image.path()).aliases(aliases).build();
+    let user = DefaultUser {};
+
+    assert_eq!(
+        listed(&storage, "/").await,
+        ["boot.iso", "docs", "os", "usr"]
+    );
+    assert_eq!(listed(&storage, "/docs").await, ["new.txt"]);
+    assert_eq!(listed(&storage, "/os/images").await, ["efi.img"]);
+    assert_eq!(listed(&storage, "/usr/share").await, Vec::<String>::new());
+
+    assert!(storage.get(&user, "/boot.iso", 0).await.is_ok());
+    assert!(storage.get(&user, "/BOOT.ISO", 0).await.is_ok());
+    assert!(storage.get(&user, "/docs/new.txt", 0).await.is_ok());
+    assert!(storage.get(&user, "/os/readme.txt", 0).await.is_ok());
+    for path in [
+        "/x86_64/readme.txt",
+        "/os/images/boot.iso",
+        "/docs/old.txt",
+        "/usr/share/doc/new.txt",
+        "/missing",
+    ] {
+        assert!(storage.get(&user, path, 0).await.is_err(), "{path}");
+    }
+    assert!(storage.metadata(&user, "/boot.iso").await.unwrap().len() == 4);
+}
+
+#[tokio::test]
+async fn namespaces() {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/Long Name.txt", b"contents")
+        .build();
+    let serve = |namespace| {
+        Storage::source_builder(image.clone())
+            .namespace(namespace)
+            .build()
+    };
+    assert_eq!(
+        listed(&serve(Namespace::Auto), "/").await,
+        ["Long Name.txt"]
+    );
+    assert_eq!(
+        listed(&serve(Namespace::Joliet), "/").await,
+        ["Long Name.txt"]
+    );
+    assert_eq!(
+        listed(&serve(Namespace::Primary), "/").await,
+        ["LONG NAME.TXT"]
+    );
+    assert!(
+        serve(Namespace::RockRidge)
+            .list(&DefaultUser {}, "/")
+            .await
+            .is_err()
+    );
+
+    let primary_only = Storage::source_builder(IsoBuilder::new().build())
+        .namespace(Namespace::Joliet)
+        .build();
+    assert!(primary_only.list(&DefaultUser {}, "/").await.is_err());
+}
+
+#[tokio::test]
+async fn namespace_fallback() {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/Docs/Read Me.txt", b"long")
+        .primary_name("/Docs/Read Me.txt", "README.TXT;1")
+        .file("/Docs/Only Joliet.txt", b"joliet")
+        .build();
+    let serve = |namespace, fallback| {
+        Storage::source_builder(image.clone())
+            .namespace(namespace)
+            .namespace_fallback(fallback)
+            .build()
+    };
+    let read = |storage: Storage, path: &'static str| async move {
+        let mut file = storage.get(&DefaultUser {}, path, 0).await.ok()?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).await.unwrap();
+        Some(contents)
+    };
+
+    assert_eq!(
+        read(serve(Namespace::Joliet, false), "/DOCS/README.TXT").await,
+        None
+    );
+    let joliet = serve(Namespace::Joliet, true);
+    assert_eq!(
+        read(joliet.clone(), "/docs/readme.txt").await.unwrap(),
+        b"long"
+    );
+    assert_eq!(
+        read(joliet.clone(), "/Docs/Read Me.txt").await.unwrap(),
+        b"long"
+    );
+    // Found, but not listed
+    assert_eq!(
+        listed(&joliet, "/Docs").await,
+        ["Only Joliet.txt", "Read Me.txt"]
+    );
+
+    let primary = serve(Namespace::Primary, true);
+    assert_eq!(
+        read(primary.clone(), "/Docs/Read Me.txt").await.unwrap(),
+        b"long"
+    );
+    assert_eq!(
+        listed(&primary, "/DOCS").await,
+        ["ONLY JOLIET.TXT", "README.TXT"]
+    );
+    assert!(read(primary, "/Docs/Nowhere.txt").await.is_none());
+}
+
+#[tokio::test]
+async fn path_table() {
+    let mut image = IsoBuilder::new()
+        .file("/A/B/C/F.TXT", b"deep")
+        .file("/A/G.TXT", b"shallow")
+        .build();
+    // Rename the record of B in A, so that only the path table still knows B by its name, and
+    // the records win
+    let record = (0..image.len() - 34)
+        .find(|&r| image[r] == 34 && image[r + 25] & 2 != 0 && image[r + 32..r + 34] == [1, b'B'])
+        .unwrap();
+    image[record + 33] = b'X';
+    let storage = Storage::from_source(image);
+    let user = DefaultUser {};
+
+    for path in ["/A/B", "/A/B/C", "/A/B/C/F.TXT"] {
+        assert!(storage.metadata(&user, path).await.is_err(), "{path}");
+    }
+    let mut file = storage.get(&user, "/A/X/C/F.TXT", 0).await.unwrap();
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).await.unwrap();
+    assert_eq!(contents, b"deep");
+    assert!(storage.metadata(&user, "/A/X").await.unwrap().is_dir());
+    assert_eq!(listed(&storage, "/A").await, ["G.TXT", "X"]);
+}
+
+#[tokio::test]
+async fn large_directory() {
+    let image = (0..3000)
+        .fold(IsoBuilder::new().joliet(true), |builder, i| {
+            builder.file(&format!("/wide/File{i:04}.txt"), i.to_string().as_bytes())
+        })
+        .file("/wide/file0001.txt", b"exact")
+        .build();
+    let storage = Storage::from_source(image);
+    let user = DefaultUser {};
+    for i in [0, 1, 1499, 2999] {
+        let path = format!("/wide/File{i:04}.txt");
+        let meta = storage.metadata(&user, &path).await.unwrap();
+        assert_eq!(meta.len(), i.to_string().len() as u64, "{path}");
+    }
+    // An exact match wins over one in another case, wherever the records lie
+    assert_eq!(
+        storage
+            .metadata(&user, "/wide/file0001.txt")
+            .await
+            .unwrap()
+            .len(),
+        5
+    );
+    assert_eq!(
+        storage
+            .metadata(&user, "/wide/FILE2999.TXT")
+            .await
+            .unwrap()
+            .len(),
+        4
+    );
+    assert!(storage.metadata(&user, "/wide/File3000.txt").await.is_err());
+}
+
+#[tokio::test]
+async fn list_a_file() {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/dir/file.txt", b"contents")
+        .file("/dir/hidden.bin", b"hidden")
+        .boot_image(&[0xB1; 2048])
+        .build();
+    let storage = Storage::source_builder(image)
+        .boot_images(true)
+        .filter(Filter::new().exclude_extensions(["bin"]))
+        .build();
+    let user = DefaultUser {};
+    for (path, name, len) in [
+        ("/dir/file.txt", "file.txt", 8),
+        ("/DIR/FILE.TXT", "FILE.TXT", 8),
+        ("/boot.img", "boot.img", 2048),
+    ] {
+        let listing = storage.list(&user, path).await.unwrap();
+        assert_eq!(listing.len(), 1, "{path}");
+        assert_eq!(listing[0].path, Path::new(name));
+        assert_eq!(listing[0].metadata.len(), len, "{path}");
+        assert!(listing[0].metadata.is_file(), "{path}");
+    }
+    assert!(storage.list(&user, "/dir/hidden.bin").await.is_err());
+    assert!(storage.list(&user, "/dir/missing.txt").await.is_err());
+    // Only directories are named with a trailing separator
+    for path in ["/dir/file.txt/", "/boot.img/"] {
+        let e = storage.list(&user, path).await.err().unwrap();
+        assert_eq!(
+            e.kind(),
+            ErrorKind::PermanentDirectoryNotAvailable,
+            "{path}"
+        );
+    }
+    let e = storage.list(&user, "/dir/hidden.bin/").await.err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::PermanentFileNotAvailable);
+    assert_eq!(listed(&storage, "/dir/").await, ["file.txt"]);
+}
+
+#[tokio::test]
+async fn sloppy_identifiers() {
+    let image = IsoBuilder::new()
+        .file("/a.txt", b"trailing dot")
+        .primary_name("/a.txt", "README.TXT.;1")
+        .file("/b.txt", b"leading spaces")
+        .primary_name("/b.txt", "  LEAD.TXT;1")
+        .file("/c.txt", b"separator")
+        .primary_name("/c.txt", "A/B.TXT;1")
+        .file("/d.txt", b"trailing spaces")
+        .primary_name("/d.txt", "SPACE  ;1")
+        .file("/dir/inner.txt", b"inner")
+        .primary_name("/dir", "DIR.")
+        .build_file();
+    let storage = Storage::new(image.path());
+    let user = DefaultUser {};
+    assert_eq!(
+        listed(&storage, "/").await,
+        ["A_B.TXT", "DIR", "LEAD.TXT", "README.TXT", "SPACE"]
+    );
+    for (path, contents) in [
+        ("/README.TXT", "trailing dot"),
+        ("/LEAD.TXT", "leading spaces"),
+        ("/A_B.TXT", "separator"),
+        ("/SPACE", "trailing spaces"),
+        ("/DIR/INNER.TXT", "inner"),
+        // As the names are recorded
+        ("/README.TXT.", "trailing dot"),
+        ("/  LEAD.TXT", "leading spaces"),
+        ("/DIR./INNER.TXT", "inner"),
+    ] {
+        let mut reader = storage.get(&user, path, 0).await.unwrap();
+        let mut read = String::new();
+        reader.read_to_string(&mut read).await.unwrap();
+        assert_eq!(read, contents, "{path}");
+    }
+    assert_eq!(listed(&storage, "/DIR").await, ["INNER.TXT"]);
+}
+
+#[tokio::test]
+async fn control_characters_escaped() {
+    for builder in [
+        IsoBuilder::new().joliet(true),
+        IsoBuilder::new().rock_ridge(true),
+    ] {
+        let image = builder
+            .file("/evil\r\n226 done.txt", b"crafted")
+            .file("/tab\there/bell\x07.txt", b"nested")
+            .build_file();
+        let storage = Storage::new(image.path());
+        let user = DefaultUser {};
+        assert_eq!(
+            listed(&storage, "/").await,
+            ["evil%0D%0A226 done.txt", "tab%09here"]
+        );
+        assert_eq!(listed(&storage, "/tab%09here").await, ["bell%07.txt"]);
+        for (path, contents) in [
+            ("/evil%0D%0A226 done.txt", "crafted"),
+            ("/tab%09here/bell%07.txt", "nested"),
+        ] {
+            let mut reader = storage.get(&user, path, 0).await.unwrap();
+            let mut read = String::new();
+            reader.read_to_string(&mut read).await.unwrap();
+            assert_eq!(read, contents, "{path}");
+            assert!(storage.metadata(&user, path).await.unwrap().is_file());
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Reading the image on a dedicated pool of threads.
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    thread,
+};
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{IoPool, IsoSource, Storage, fixture::IsoBuilder};
+
+/// An image that remembers the threads it was read on.
+struct Threads {
+    image: Vec<u8>,
+    names: Mutex<Vec<String>>,
+}
+
+impl IsoSource for Threads {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let name = thread::current().name().unwrap_or_default().to_string();
+        self.names.lock().unwrap().push(name);
+        self.image.read_at(offset, buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.image.len() as u64)
+    }
+}
+
+#[tokio::test]
+async fn reads_run_on_the_pool() {
+    let contents: Vec<u8> = (0..300_000u32).map(|i| i as u8).collect();
+    let source = Arc::new(Threads {
+        image: IsoBuilder::new()
+            .file("/big.bin", &contents)
+            .file("/small.txt", b"small")
+            .build(),
+        names: Mutex::new(Vec::new()),
+    });
+    let storage = Storage::source_builder(source.clone())
+        .io_pool(IoPool::new(2))
+        .build();
+    let user = DefaultUser {};
+    assert!(storage.list(&user, "/").await.is_ok());
+    let mut read = Vec::new();
+    storage
+        .get(&user, "/big.bin", 0)
+        .await
+        .unwrap()
+        .read_to_end(&mut read)
+        .await
+        .unwrap();
+    assert!(read == contents);
+    let mut small = String::new();
+    storage
+        .open("/small.txt")
+        .await
+        .unwrap()
+        .read_to_string(&mut small)
+        .await
+        .unwrap();
+    assert_eq!(small, "small");
+
+    let names = source.names.lock().unwrap();
+    assert!(!names.is_empty());
+    assert!(
+        names
+            .iter()
+            .all(|name| name.starts_with("unftp-sbe-iso-io-")),
+        "{names:?}"
+    );
+}
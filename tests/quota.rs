@@ -0,0 +1,127 @@
+//! Per-user download quotas.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::UserDetail,
+    storage::{ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{Quota, QuotaStore, Storage, fixture::IsoBuilder};
+
+#[derive(Debug)]
+struct Named(&'static str);
+
+impl fmt::Display for Named {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl UserDetail for Named {}
+
+fn storage(quota: Quota) -> Storage {
+    let image = IsoBuilder::new()
+        .file("/small.txt", b"0123456789")
+        .file("/big.bin", &vec![7; 5000])
+        .build();
+    Storage::source_builder(image).quota(quota).build()
+}
+
+/// Downloads the file whole, or fails with the kind of error that stopped it.
+async fn download(storage: &Storage, user: &Named, path: &str) -> Result<Vec<u8>, ErrorKind> {
+    let mut reader = storage.get(user, path, 0).await.map_err(|e| e.kind())?;
+    let mut contents = Vec::new();
+    reader
+        .read_to_end(&mut contents)
+        .await
+        .map_err(|_| ErrorKind::ExceededStorageAllocationError)?;
+    Ok(contents)
+}
+
+#[tokio::test]
+async fn metered_and_exhausted() {
+    let quota = Quota::new(20);
+    let storage = storage(quota.clone());
+    let alice = Named("alice");
+    download(&storage, &alice, "/small.txt").await.unwrap();
+    assert_eq!(quota.remaining("alice"), 10);
+    download(&storage, &alice, "/small.txt").await.unwrap();
+    assert_eq!(quota.remaining("alice"), 0);
+    // Further downloads are refused outright
+    assert_eq!(
+        download(&storage, &alice, "/small.txt").await,
+        Err(ErrorKind::ExceededStorageAllocationError)
+    );
+    // Other users have their own quota
+    assert_eq!(quota.remaining("bob"), 20);
+    let bob = download(&storage, &Named("bob"), "/small.txt").await;
+    assert!(bob.is_ok());
+}
+
+#[tokio::test]
+async fn cut_off_at_the_limit() {
+    let quota = Quota::new(1000);
+    let short = storage(quota.clone());
+    let alice = Named("alice");
+    let mut reader = short.get(&alice, "/big.bin", 0).await.unwrap();
+    let mut contents = Vec::new();
+    assert!(reader.read_to_end(&mut contents).await.is_err());
+    // No more than the quota was served, and all of it was charged
+    assert_eq!(contents, vec![7; 1000]);
+    assert_eq!(quota.remaining("alice"), 0);
+    // A file that fits exactly is served whole
+    let quota = Quota::new(10);
+    let exact = storage(quota.clone());
+    assert_eq!(
+        download(&exact, &alice, "/small.txt").await.unwrap(),
+        b"0123456789"
+    );
+    assert_eq!(quota.remaining("alice"), 0);
+}
+
+/// A store that starts from counters of before.
+#[derive(Debug, Default)]
+struct Recorded {
+    used: Mutex<HashMap<String, u64>>,
+    adds: Mutex<Vec<(String, u64)>>,
+}
+
+impl QuotaStore for Recorded {
+    fn used(&self, user: &str) -> u64 {
+        self.used.lock().unwrap().get(user).copied().unwrap_or(0)
+    }
+
+    fn add(&self, user: &str, bytes: u64) {
+        *self.used.lock().unwrap().entry(user.into()).or_default() += bytes;
+        self.adds.lock().unwrap().push((user.into(), bytes));
+    }
+}
+
+#[tokio::test]
+async fn custom_store() {
+    let store = Arc::new(Recorded::default());
+    store.used.lock().unwrap().insert("alice".into(), 995);
+    let storage = storage(Quota::with_store(1000, store.clone()));
+    // What the store recorded before counts: five bytes are left
+    let mut reader = storage.get(&Named("alice"), "/small.txt", 0).await.unwrap();
+    let mut contents = Vec::new();
+    assert!(reader.read_to_end(&mut contents).await.is_err());
+    assert_eq!(contents, b"01234");
+    assert_eq!(store.used("alice"), 1000);
+    // Every byte served went through the store, charged to whoever downloaded it
+    download(&storage, &Named("bob"), "/small.txt")
+        .await
+        .unwrap();
+    let adds = store.adds.lock().unwrap();
+    let charged = |user: &str| -> u64 {
+        adds.iter()
+            .filter(|(name, _)| name == user)
+            .map(|(_, bytes)| bytes)
+            .sum()
+    };
+    assert_eq!((charged("alice"), charged("bob")), (5, 10));
+}
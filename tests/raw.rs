@@ -0,0 +1,105 @@
+//! Serving images dumped as raw sectors, alone or in CloneCD dumps, and telling damaged dumps
+//! apart.
+
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{
+    CloneCd, RawSectors, SectorStats, Storage,
+    fixture::{IsoBuilder, raw_sectors},
+};
+
+#[tokio::test]
+async fn damaged_dumps_fail_downloads() {
+    let contents: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let image = IsoBuilder::new()
+        .file("/readme.txt", b"hello")
+        .file("/data.bin", &contents)
+        .build();
+    let raw = raw_sectors(&image);
+    let user = DefaultUser {};
+
+    let storage = Storage::from_source(RawSectors::new(raw.clone()).verify(true));
+    let mut read = Vec::new();
+    storage
+        .get(&user, "/data.bin", 0)
+        .await
+        .unwrap()
+        .read_to_end(&mut read)
+        .await
+        .unwrap();
+    assert!(read == contents);
+
+    // Damage the last sector, which holds the end of the file
+    let mut damaged = raw;
+    let end = damaged.len() - 2352 + 16 + 100;
+    damaged[end] ^= 0xff;
+    let source = Arc::new(RawSectors::new(damaged).verify(true));
+    let storage = Storage::from_source(source.clone());
+    let mut readme = String::new();
+    storage
+        .get(&user, "/readme.txt", 0)
+        .await
+        .unwrap()
+        .read_to_string(&mut readme)
+        .await
+        .unwrap();
+    assert_eq!(readme, "hello");
+    let e = storage.get(&user, "/data.bin", 0).await.err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::PermanentFileNotAvailable);
+    let SectorStats { verified, corrupt } = source.stats();
+    assert!(verified > 0);
+    assert!(corrupt >= 1);
+}
+
+#[tokio::test]
+async fn clonecd_dumps() {
+    let image = IsoBuilder::new().file("/readme.txt", b"hello").build();
+    let mut raw = raw_sectors(&image);
+    let data_sectors = (raw.len() / 2352) as u64;
+    // An audio track follows the data track
+    raw.extend(std::iter::repeat_n(0x55, 10 * 2352));
+    let sheet = format!(
+        "[CloneCD]\nVersion=3\n[Entry 0]\nSession=1\nPoint=0xa2\nControl=0x04\nPLBA={}\n\
+         [Entry 1]\nSession=1\nPoint=0x01\nControl=0x04\nPLBA=0\n\
+         [Entry 2]\nSession=1\nPoint=0x02\nControl=0x00\nPLBA={data_sectors}\n\
+         [TRACK 1]\nMODE=1\nINDEX 1=0\n[TRACK 2]\nMODE=0\nINDEX 1={data_sectors}\n",
+        data_sectors + 10
+    );
+    let dir = std::env::temp_dir().join(format!("unftp-sbe-iso-clonecd-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("disc.ccd"), sheet).unwrap();
+    std::fs::write(dir.join("disc.img"), &raw).unwrap();
+
+    let dump = CloneCd::open(dir.join("disc.ccd")).unwrap();
+    assert_eq!(dump.info().data_track, 1);
+    assert_eq!(dump.info().tracks.len(), 2);
+    assert_eq!(dump.info().tracks[0].sectors, Some(data_sectors));
+    assert_eq!(dump.info().subchannel, None);
+
+    // The subchannels are reported, not read, whole or not
+    std::fs::write(dir.join("disc.sub"), vec![0; 96 * 5]).unwrap();
+    let dump = CloneCd::open(dir.join("disc.img")).unwrap().verify(true);
+    let subchannel = dump.info().subchannel.clone().unwrap();
+    assert_eq!(subchannel.sectors, 5);
+    assert!(!subchannel.complete);
+    let storage = Storage::from_source(dump);
+    let mut readme = String::new();
+    storage
+        .get(&DefaultUser {}, "/readme.txt", 0)
+        .await
+        .unwrap()
+        .read_to_string(&mut readme)
+        .await
+        .unwrap();
+    assert_eq!(readme, "hello");
+
+    // A sheet without a data track is refused
+    std::fs::write(dir.join("audio.ccd"), "[TRACK 1]\nMODE=0\nINDEX 1=0\n").unwrap();
+    std::fs::write(dir.join("audio.img"), vec![0; 2352]).unwrap();
+    assert!(CloneCd::open(dir.join("audio.ccd")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
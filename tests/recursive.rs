@@ -0,0 +1,110 @@
+//! Listing whole subtrees with `Storage::list_recursive` and `Storage::walk`.
+
+use unftp_core::storage::Metadata;
+use unftp_sbe_iso::{Filter, Storage, Walk, fixture::IsoBuilder};
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .rock_ridge(true)
+        .file("/a/one.txt", b"one")
+        .file("/a/deep/two.txt", b"two")
+        .file("/a/deep/hidden.bin", b"hidden")
+        .dir("/b")
+        .file("/top.txt", b"top")
+        .symlink("/a/up", "..")
+        .build()
+}
+
+async fn walked(storage: &Storage, path: &str) -> Vec<String> {
+    storage
+        .list_recursive(path)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| {
+            let name = f.path.to_str().unwrap().to_string();
+            if f.metadata.is_dir() {
+                name + "/"
+            } else {
+                name
+            }
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn depth_first() {
+    let storage = Storage::from_source(image());
+    // Each directory's entries, then those of its subdirectories in turn. The link isn't
+    // followed, which would list the root again under `a/up`.
+    assert_eq!(
+        walked(&storage, "/").await,
+        [
+            "a/",
+            "b/",
+            "top.txt",
+            "a/deep/",
+            "a/one.txt",
+            "a/up",
+            "a/deep/hidden.bin",
+            "a/deep/two.txt",
+        ]
+    );
+    assert_eq!(walked(&storage, "/a/deep").await, ["hidden.bin", "two.txt"]);
+    assert_eq!(walked(&storage, "/b").await, Vec::<String>::new());
+    assert_eq!(walked(&storage, "/top.txt").await, ["top.txt"]);
+    assert!(storage.list_recursive("/missing").await.is_err());
+}
+
+#[tokio::test]
+async fn filtered() {
+    let storage = Storage::source_builder(image())
+        .filter(Filter::new().exclude_extensions(["bin"]))
+        .build();
+    assert_eq!(
+        walked(&storage, "/a").await,
+        ["deep/", "one.txt", "up", "deep/two.txt"]
+    );
+}
+
+async fn streamed(mut walk: Walk) -> Vec<String> {
+    let mut names = Vec::new();
+    while let Some((path, meta)) = walk.next_entry().await.unwrap() {
+        let name = path.to_str().unwrap().to_string();
+        names.push(if meta.is_dir() { name + "/" } else { name });
+    }
+    names
+}
+
+#[tokio::test]
+async fn streamed_walks() {
+    let storage = Storage::from_source(image());
+    for path in ["/", "/a", "/b", "/top.txt"] {
+        assert_eq!(
+            streamed(storage.walk(path)).await,
+            walked(&storage, path).await
+        );
+    }
+    assert_eq!(
+        streamed(storage.walk("/").max_depth(1)).await,
+        ["a/", "b/", "top.txt"]
+    );
+    assert_eq!(
+        streamed(storage.walk("/").max_depth(2)).await,
+        ["a/", "b/", "top.txt", "a/deep/", "a/one.txt", "a/up"]
+    );
+    assert!(streamed(storage.walk("/").max_depth(0)).await.is_empty());
+    assert!(storage.walk("/missing").next_entry().await.is_err());
+}
+
+#[tokio::test]
+async fn streamed_walks_skip_what_the_filter_rejects() {
+    let storage = Storage::from_source(image());
+    // A rejected directory isn't walked into
+    let walk = storage
+        .walk("/")
+        .filter(|path, _| !path.ends_with("deep") && !path.ends_with("top.txt"));
+    assert_eq!(streamed(walk).await, ["a/", "b/", "a/one.txt", "a/up"]);
+    let walk = storage.walk("/a").filter(|_, meta| !meta.is_dir());
+    assert_eq!(streamed(walk).await, ["one.txt", "up"]);
+}
@@ -0,0 +1,59 @@
+//! Serving on while the image file is replaced or removed underneath the back-end.
+
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+fn image(readme: &[u8], fill: u8) -> Vec<u8> {
+    IsoBuilder::new()
+        .file("/readme.txt", readme)
+        .file("/big.bin", &vec![fill; 1024 * 1024])
+        .build()
+}
+
+async fn get(storage: &Storage, path: &str) -> Vec<u8> {
+    let mut contents = Vec::new();
+    storage
+        .get(&DefaultUser {}, path, 0)
+        .await
+        .unwrap()
+        .read_to_end(&mut contents)
+        .await
+        .unwrap();
+    contents
+}
+
+#[tokio::test]
+async fn replaced_and_removed() {
+    let dir = std::env::temp_dir().join(format!("unftp-sbe-iso-rotation-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("image.iso");
+    std::fs::write(&path, image(b"first", 1)).unwrap();
+    let storage = Storage::new(&path);
+    assert_eq!(get(&storage, "/readme.txt").await, b"first");
+
+    // A download under way finishes on the file it started on
+    let mut download = storage.get(&DefaultUser {}, "/big.bin", 0).await.unwrap();
+    let mut head = [0; 10];
+    download.read_exact(&mut head).await.unwrap();
+    let replacement = dir.join("image.new");
+    std::fs::write(&replacement, image(b"second", 2)).unwrap();
+    std::fs::rename(&replacement, &path).unwrap();
+    assert_eq!(get(&storage, "/readme.txt").await, b"second");
+    let mut rest = Vec::new();
+    download.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.iter().all(|&b| b == 1));
+    assert_eq!(rest.len(), 1024 * 1024 - 10);
+
+    // A removed file is served on until another one takes its place
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(get(&storage, "/readme.txt").await, b"second");
+    assert!(storage.list(&DefaultUser {}, "/").await.is_ok());
+    std::fs::write(&path, image(b"third", 3)).unwrap();
+    assert_eq!(get(&storage, "/readme.txt").await, b"third");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Without a file ever opened, there is nothing to serve
+    let missing = Storage::new(dir.join("image.iso"));
+    assert!(missing.list(&DefaultUser {}, "/").await.is_err());
+}
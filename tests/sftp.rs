@@ -0,0 +1,223 @@
+//! The tree of the image served to SFTP clients.
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use unftp_sbe_iso::{SftpSubsystem, Storage, fixture::IsoBuilder};
+
+const CONTENTS: &[u8] = b"0123456789abcdefghij";
+
+/// A client of the subsystem, speaking version 3 of the protocol.
+struct Client {
+    stream: DuplexStream,
+    next_id: u32,
+}
+
+/// A response, with its fields after the id.
+struct Response {
+    kind: u8,
+    id: u32,
+    fields: Vec<u8>,
+}
+
+impl Response {
+    fn u32_at(&self, at: usize) -> u32 {
+        u32::from_be_bytes(self.fields[at..at + 4].try_into().unwrap())
+    }
+
+    /// The status code of a STATUS response.
+    fn status(&self) -> u32 {
+        assert_eq!(self.kind, 101, "not a status");
+        self.u32_at(0)
+    }
+
+    /// The string field of a HANDLE or DATA response.
+    fn string(&self) -> Vec<u8> {
+        let len = self.u32_at(0) as usize;
+        self.fields[4..4 + len].to_vec()
+    }
+
+    /// The names of a NAME response.
+    fn names(&self) -> Vec<String> {
+        assert_eq!(self.kind, 104, "not a name");
+        let mut names = Vec::new();
+        let mut at = 4;
+        for _ in 0..self.u32_at(0) {
+            let len = self.u32_at(at) as usize;
+            names.push(String::from_utf8(self.fields[at + 4..at + 4 + len].to_vec()).unwrap());
+            at += 4 + len;
+            let long = self.u32_at(at) as usize;
+            at += 4 + long;
+            // The attributes: flags, size, permissions, times
+            at += 4 + 8 + 4 + 8;
+        }
+        names
+    }
+}
+
+fn string(value: &[u8]) -> Vec<u8> {
+    [&(value.len() as u32).to_be_bytes(), value].concat()
+}
+
+impl Client {
+    async fn start() -> Self {
+        let image = IsoBuilder::new()
+            .joliet(true)
+            .file("/docs/readme.txt", CONTENTS)
+            .file("/docs/guide/intro.txt", b"welcome")
+            .build();
+        let (stream, server) = tokio::io::duplex(64 * 1024);
+        let subsystem = SftpSubsystem::new(Storage::from_source(image));
+        tokio::spawn(async move { subsystem.serve(server).await });
+        let mut client = Self { stream, next_id: 1 };
+        client.send(1, &3u32.to_be_bytes()).await;
+        let version = client.receive().await;
+        assert_eq!((version.kind, version.id), (2, 3));
+        client
+    }
+
+    async fn send(&mut self, kind: u8, payload: &[u8]) {
+        let len = (payload.len() + 1) as u32;
+        let packet = [&len.to_be_bytes(), &[kind][..], payload].concat();
+        self.stream.write_all(&packet).await.unwrap();
+    }
+
+    async fn receive(&mut self) -> Response {
+        let len = self.stream.read_u32().await.unwrap() as usize;
+        let mut packet = vec![0; len];
+        self.stream.read_exact(&mut packet).await.unwrap();
+        Response {
+            kind: packet[0],
+            id: u32::from_be_bytes(packet[1..5].try_into().unwrap()),
+            fields: packet[5..].to_vec(),
+        }
+    }
+
+    async fn request(&mut self, kind: u8, fields: &[u8]) -> Response {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(kind, &[&id.to_be_bytes(), fields].concat()).await;
+        let response = self.receive().await;
+        assert_eq!(response.id, id);
+        response
+    }
+
+    async fn open(&mut self, path: &str, flags: u32) -> Response {
+        let fields = [
+            string(path.as_bytes()),
+            flags.to_be_bytes().to_vec(),
+            vec![0; 4],
+        ]
+        .concat();
+        self.request(3, &fields).await
+    }
+
+    async fn read(&mut self, handle: &[u8], offset: u64, len: u32) -> Response {
+        let fields = [
+            string(handle),
+            offset.to_be_bytes().to_vec(),
+            len.to_be_bytes().to_vec(),
+        ]
+        .concat();
+        self.request(5, &fields).await
+    }
+}
+
+#[tokio::test]
+async fn reading_files() {
+    let mut client = Client::start().await;
+    let opened = client.open("/docs/readme.txt", 0x01).await;
+    assert_eq!(opened.kind, 102);
+    let handle = opened.string();
+
+    let mut contents = Vec::new();
+    loop {
+        let read = client.read(&handle, contents.len() as u64, 6).await;
+        if read.kind == 101 {
+            assert_eq!(read.status(), 1);
+            break;
+        }
+        assert_eq!(read.kind, 103);
+        contents.extend(read.string());
+    }
+    assert_eq!(contents, CONTENTS);
+    // Out of order, as clients reading ahead do
+    assert_eq!(
+        client.read(&handle, 15, 100).await.string(),
+        &CONTENTS[15..]
+    );
+    assert_eq!(client.read(&handle, 2, 3).await.string(), &CONTENTS[2..5]);
+    assert_eq!(client.read(&handle, 100, 3).await.status(), 1);
+
+    let stat = client.request(8, &string(&handle)).await;
+    assert_eq!(stat.kind, 105);
+    // The size follows the flags
+    assert_eq!(&stat.fields[4..12], &20u64.to_be_bytes());
+    assert_eq!(client.request(4, &string(&handle)).await.status(), 0);
+    assert_eq!(client.read(&handle, 0, 3).await.status(), 4);
+}
+
+#[tokio::test]
+async fn listing_directories() {
+    let mut client = Client::start().await;
+    let opened = client.request(11, &string(b"/docs")).await;
+    assert_eq!(opened.kind, 102);
+    let handle = opened.string();
+    let mut names = client.request(12, &string(&handle)).await.names();
+    names.sort();
+    assert_eq!(names, [".", "..", "guide", "readme.txt"]);
+    assert_eq!(client.request(12, &string(&handle)).await.status(), 1);
+    assert_eq!(client.request(4, &string(&handle)).await.status(), 0);
+
+    let real = client.request(16, &string(b"docs/guide/../.")).await;
+    assert_eq!(real.names(), ["/docs"]);
+    let stat = client.request(17, &string(b"/docs/guide")).await;
+    assert_eq!(stat.kind, 105);
+    // A directory, readable by everyone
+    assert_eq!(stat.u32_at(12), 0o040_555);
+}
+
+#[tokio::test]
+async fn refused() {
+    let mut client = Client::start().await;
+    assert_eq!(client.open("/missing.txt", 0x01).await.status(), 2);
+    assert_eq!(client.request(17, &string(b"/missing")).await.status(), 2);
+    assert_eq!(
+        client
+            .request(11, &string(b"/docs/readme.txt"))
+            .await
+            .status(),
+        4
+    );
+    assert_eq!(client.open("/docs", 0x01).await.status(), 4);
+    // Writing, creating and truncating
+    for flags in [0x02, 0x01 | 0x08, 0x10] {
+        assert_eq!(client.open("/docs/readme.txt", flags).await.status(), 3);
+    }
+    for (kind, attrs) in [(13, &[][..]), (14, &[0; 4]), (15, &[])] {
+        let fields = [string(b"/docs"), attrs.to_vec()].concat();
+        assert_eq!(client.request(kind, &fields).await.status(), 3);
+    }
+    // Requests that can't be read are answered without their id
+    for (kind, fields) in [(200, &[0, 0, 0, 9][..]), (3, &[0, 0, 0, 9, 0, 0])] {
+        client.send(kind, fields).await;
+        let response = client.receive().await;
+        assert_eq!((response.status(), response.id), (5, 0));
+    }
+    // The session goes on
+    assert_eq!(client.request(17, &string(b"/docs")).await.kind, 105);
+}
+
+#[tokio::test]
+async fn ends_with_the_channel() {
+    let image = IsoBuilder::new().file("/readme.txt", CONTENTS).build();
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let subsystem = SftpSubsystem::new(Storage::from_source(image));
+    let served = tokio::spawn(async move { subsystem.serve(server).await });
+    let init = [&5u32.to_be_bytes()[..], &[1], &3u32.to_be_bytes()].concat();
+    client.write_all(&init).await.unwrap();
+    client.read_u32().await.unwrap();
+    drop(client);
+    tokio::time::timeout(std::time::Duration::from_secs(5), served)
+        .await
+        .expect("the session outlived its channel")
+        .unwrap();
+}
@@ -0,0 +1,202 @@
+//! Refusing image files that don't match the signature given with
+//! `StorageBuilder::image_signature`.
+#![cfg(feature = "checksums")]
+
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{HashAlgorithm, ImageSignature, IntegrityMode, Storage, fixture::IsoBuilder};
+
+fn image(contents: &[u8]) -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/readme.txt", contents)
+        .build()
+}
+
+/// The SHA-256 digest of `image` in hex.
+async fn digest(image: Vec<u8>) -> String {
+    // An image within the image, to have the storage compute it
+    let outer = IsoBuilder::new().file("/image.iso", &image).build();
+    Storage::from_source(outer)
+        .hash("/image.iso", HashAlgorithm::Sha256)
+        .await
+        .unwrap()
+        .to_string()
+}
+
+async fn download(storage: &Storage) -> Result<Vec<u8>, ErrorKind> {
+    let mut reader = storage
+        .get(&DefaultUser {}, "/docs/readme.txt", 0)
+        .await
+        .map_err(|e| e.kind())?;
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).await.unwrap();
+    Ok(contents)
+}
+
+#[tokio::test]
+async fn sha256() {
+    let blessed = ImageSignature::sha256(&digest(image(b"blessed")).await).unwrap();
+    let storage = Storage::source_builder(image(b"blessed"))
+        .image_signature(blessed.clone(), IntegrityMode::Abort)
+        .open()
+        .await
+        .unwrap();
+    assert_eq!(download(&storage).await.unwrap(), b"blessed");
+    assert!(storage.image_verified().await.unwrap());
+
+    let tampered = Storage::source_builder(image(b"tampered"))
+        .image_signature(blessed.clone(), IntegrityMode::Abort);
+    let e = tampered.clone().open().await.err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::LocalError);
+    let storage = tampered.build();
+    assert_eq!(download(&storage).await, Err(ErrorKind::LocalError));
+    assert!(!storage.image_verified().await.unwrap());
+
+    // Served, but flagged
+    let storage = Storage::source_builder(image(b"tampered"))
+        .image_signature(blessed, IntegrityMode::Log)
+        .open()
+        .await
+        .unwrap();
+    assert_eq!(download(&storage).await.unwrap(), b"tampered");
+    assert!(!storage.image_verified().await.unwrap());
+
+    let unsigned = Storage::from_source(image(b"blessed"));
+    assert!(!unsigned.image_verified().await.unwrap());
+}
+
+/// A directory of files, removed on drop.
+struct Dir(PathBuf);
+
+impl Dir {
+    fn new(test: &str) -> Self {
+        let name = format!("unftp-sbe-iso-signature-{test}-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        Dir(dir)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Writes a checksum list of `images`, its signature and the key to check it with to `dir`.
+async fn sign(dir: &Dir, images: &[(&str, Vec<u8>)]) -> ImageSignature {
+    let mut list = String::new();
+    for (name, image) in images {
+        list += &format!("{}  ./{name}\n", digest(image.clone()).await);
+    }
+    let pair = Ed25519KeyPair::from_seed_unchecked(&[3; 32]).unwrap();
+    // The DER encoding of the public key, as OpenSSL writes it
+    let mut key = vec![
+        0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+    ];
+    key.extend(pair.public_key().as_ref());
+    std::fs::write(dir.path("release.der"), key).unwrap();
+    std::fs::write(dir.path("SHA256SUMS"), &list).unwrap();
+    std::fs::write(
+        dir.path("SHA256SUMS.sig"),
+        pair.sign(list.as_bytes()).as_ref(),
+    )
+    .unwrap();
+    ImageSignature::ed25519_files(
+        dir.path("release.der"),
+        dir.path("SHA256SUMS"),
+        dir.path("SHA256SUMS.sig"),
+    )
+    .unwrap()
+}
+
+fn write(path: &Path, image: &[u8]) {
+    let replacement = path.with_extension("new");
+    std::fs::write(&replacement, image).unwrap();
+    std::fs::rename(&replacement, path).unwrap();
+}
+
+#[tokio::test]
+async fn signed_list() {
+    let dir = Dir::new("list");
+    let images = [
+        ("netinst.iso", image(b"netinst")),
+        ("dvd.iso", image(b"dvd")),
+    ];
+    let signature = sign(&dir, &images).await;
+    write(&dir.path("netinst.iso"), &images[0].1);
+    let storage = Storage::builder(dir.path("netinst.iso"))
+        .image_signature(signature.clone(), IntegrityMode::Abort)
+        .open()
+        .await
+        .unwrap();
+    assert_eq!(download(&storage).await.unwrap(), b"netinst");
+
+    // Replaced by an image the list doesn't vouch for
+    write(&dir.path("netinst.iso"), &images[1].1);
+    assert_eq!(download(&storage).await, Err(ErrorKind::LocalError));
+    write(&dir.path("netinst.iso"), &images[0].1);
+    assert_eq!(download(&storage).await.unwrap(), b"netinst");
+
+    // Unlisted images
+    write(&dir.path("other.iso"), &images[0].1);
+    let storage = Storage::builder(dir.path("other.iso"))
+        .image_signature(signature, IntegrityMode::Abort)
+        .build();
+    assert_eq!(download(&storage).await, Err(ErrorKind::LocalError));
+
+    // A list that was altered after it was signed
+    std::fs::write(dir.path("SHA256SUMS"), "altered").unwrap();
+    let altered = ImageSignature::ed25519_files(
+        dir.path("release.der"),
+        dir.path("SHA256SUMS"),
+        dir.path("SHA256SUMS.sig"),
+    )
+    .unwrap();
+    let storage = Storage::builder(dir.path("netinst.iso"))
+        .image_signature(altered, IntegrityMode::Log)
+        .build();
+    assert_eq!(download(&storage).await.unwrap(), b"netinst");
+    assert!(!storage.image_verified().await.unwrap());
+}
+
+#[tokio::test]
+async fn directory() {
+    let dir = Dir::new("directory");
+    let images = [("a.iso", image(b"a")), ("b.iso", image(b"b"))];
+    let signature = sign(&dir, &images[..1]).await;
+    let served = dir.path("served");
+    std::fs::create_dir(&served).unwrap();
+    for (name, image) in &images {
+        write(&served.join(name), image);
+    }
+    let storage = Storage::directory_builder(&served)
+        .image_signature(signature, IntegrityMode::Abort)
+        .build();
+    let user = DefaultUser {};
+    let mut contents = Vec::new();
+    storage
+        .get(&user, "/a/docs/readme.txt", 0)
+        .await
+        .unwrap()
+        .read_to_end(&mut contents)
+        .await
+        .unwrap();
+    assert_eq!(contents, b"a");
+    let e = storage
+        .get(&user, "/b/docs/readme.txt", 0)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::LocalError);
+}
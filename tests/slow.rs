@@ -0,0 +1,93 @@
+//! Logging operations slower than a threshold.
+
+use std::{
+    io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{IsoSource, Storage, fixture::IsoBuilder};
+
+/// The messages logged.
+static LOGGED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        LOGGED.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+/// An image that is slow to read while `slow` is set.
+struct Sluggish {
+    image: Vec<u8>,
+    slow: AtomicBool,
+}
+
+impl IsoSource for Sluggish {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if self.slow.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        self.image.read_at(offset, buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.image.len() as u64)
+    }
+}
+
+#[tokio::test]
+async fn slow_operations_are_logged() {
+    log::set_logger(&Logger).unwrap();
+    log::set_max_level(log::LevelFilter::Warn);
+    let source = Arc::new(Sluggish {
+        image: IsoBuilder::new()
+            .file("/docs/readme.txt", b"hello")
+            .file("/big.bin", &vec![1; 200_000])
+            .build(),
+        slow: AtomicBool::new(false),
+    });
+    let storage = Storage::source_builder(source.clone())
+        .log_slow_operations(Duration::from_millis(10))
+        .build();
+    let user = DefaultUser {};
+    assert!(storage.list(&user, "/").await.is_ok());
+    assert!(LOGGED.lock().unwrap().is_empty());
+
+    source.slow.store(true, Ordering::Relaxed);
+    assert!(storage.list(&user, "/docs").await.is_ok());
+    assert!(storage.metadata(&user, "/docs/readme.txt").await.is_ok());
+    let mut read = Vec::new();
+    storage
+        .get(&user, "/big.bin", 0)
+        .await
+        .unwrap()
+        .read_to_end(&mut read)
+        .await
+        .unwrap();
+    let logged = LOGGED.lock().unwrap();
+    let find = |start: &str| {
+        logged
+            .iter()
+            .find(|message| message.starts_with(start))
+            .unwrap_or_else(|| panic!("no {start:?} in {logged:#?}"))
+    };
+    let listing = find("slow listing of \"/docs\"");
+    assert!(listing.contains("3 entries"), "{listing}");
+    assert!(listing.ends_with(" ms"), "{listing}");
+    find("slow lookup of \"/docs/readme.txt\"");
+    find("slow opening of \"/big.bin\"");
+    find("slow read of 65536 bytes of \"/big.bin\"");
+}
@@ -0,0 +1,115 @@
+//! Serving images from custom sources.
+
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{AsyncIsoSource, IsoSource, Storage, fixture::IsoBuilder};
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/readme.txt", b"Hello from memory")
+        .build()
+}
+
+#[tokio::test]
+async fn memory_source() {
+    let storage = Storage::from_source(image());
+    let user = DefaultUser {};
+    assert_eq!(storage.list(&user, "/docs").await.unwrap().len(), 3);
+    let mut contents = String::new();
+    storage
+        .get(&user, "/docs/readme.txt", 6)
+        .await
+        .unwrap()
+        .read_to_string(&mut contents)
+        .await
+        .unwrap();
+    assert_eq!(contents, "from memory");
+}
+
+/// Counts the reads made, and can be shared with the storage through an `Arc`.
+struct Counting {
+    image: Vec<u8>,
+    reads: AtomicUsize,
+}
+
+impl IsoSource for Counting {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.image.read_at(offset, buf)
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.image.len() as u64)
+    }
+}
+
+#[tokio::test]
+async fn custom_source() {
+    let source = Arc::new(Counting {
+        image: image(),
+        reads: AtomicUsize::new(0),
+    });
+    let storage = Storage::source_builder(source.clone()).build();
+    assert!(storage.cwd(&DefaultUser {}, "/docs").await.is_ok());
+    assert!(source.reads.load(Ordering::Relaxed) > 0);
+}
+
+#[tokio::test]
+async fn truncated_source() {
+    let mut image = image();
+    image.truncate(image.len() - 2048);
+    let storage = Storage::from_source(image);
+    assert!(
+        storage
+            .get(&DefaultUser {}, "/docs/readme.txt", 0)
+            .await
+            .is_err()
+    );
+}
+
+/// An image behind an imaginary network, fetched range by range.
+struct Remote(Vec<u8>);
+
+#[async_trait::async_trait]
+impl AsyncIsoSource for Remote {
+    async fn read_range(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        let start = (offset as usize).min(self.0.len());
+        let end = (start + len).min(self.0.len());
+        Ok(self.0[start..end].to_vec())
+    }
+
+    async fn len(&self) -> io::Result<u64> {
+        Ok(self.0.len() as u64)
+    }
+}
+
+#[tokio::test]
+async fn async_source() {
+    let storage = Storage::from_async_source(Remote(image()));
+    let user = DefaultUser {};
+    assert!(storage.cwd(&user, "/docs").await.is_ok());
+    let mut contents = String::new();
+    storage
+        .get(&user, "/docs/readme.txt", 0)
+        .await
+        .unwrap()
+        .read_to_string(&mut contents)
+        .await
+        .unwrap();
+    assert_eq!(contents, "Hello from memory");
+}
+
+#[test]
+fn storage_is_shareable() {
+    fn shareable<T: Send + Sync + Clone + 'static>() {}
+    shareable::<Storage>();
+}
@@ -0,0 +1,187 @@
+//! Spilling files read whole to temporary files, as set with `StorageBuilder::spool_dir`.
+
+use std::path::PathBuf;
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{MemoryBudget, Storage, StorageBuilder, fixture::IsoBuilder};
+
+/// More than the 1 MiB held in memory regardless.
+const LARGE: usize = 2 * 1024 * 1024 + 100;
+
+fn contents() -> Vec<u8> {
+    (0..LARGE as u32).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Wraps `data` in a gzip member of stored blocks.
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut member = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3];
+    let chunks: Vec<&[u8]> = data.chunks(0xFFFF).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        member.push(u8::from(i == chunks.len() - 1));
+        member.extend((chunk.len() as u16).to_le_bytes());
+        member.extend((!(chunk.len() as u16)).to_le_bytes());
+        member.extend(*chunk);
+    }
+    member.extend(crc32(data).to_le_bytes());
+    member.extend((data.len() as u32).to_le_bytes());
+    member
+}
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/data/large.bin.gz", &gzip(&contents()))
+        .file("/data/small.txt.gz", &gzip(b"small"))
+        .build()
+}
+
+/// A spool directory, removed on drop.
+struct Dir(PathBuf);
+
+impl Dir {
+    fn new(test: &str) -> Self {
+        let name = format!("unftp-sbe-iso-spool-{test}-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        Dir(dir)
+    }
+
+    fn files(&self) -> usize {
+        std::fs::read_dir(&self.0).unwrap().count()
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+async fn download(storage: &Storage, path: &str, start: u64) -> Result<Vec<u8>, ()> {
+    let mut reader = storage
+        .get(&DefaultUser {}, path, start)
+        .await
+        .map_err(drop)?;
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).await.unwrap();
+    Ok(contents)
+}
+
+/// Leaves 512 KiB for transfers, less than the large file but enough for the buffers of a
+/// download.
+fn budgeted(builder: StorageBuilder) -> Storage {
+    builder
+        .gunzip(true)
+        .memory_budget(MemoryBudget::new(512 * 1024).shares(0, 0, 1))
+        .build()
+}
+
+#[tokio::test]
+async fn decompressed() {
+    let dir = Dir::new("decompressed");
+    let storage = budgeted(Storage::source_builder(image()).spool_dir(&dir.0));
+    let large = contents();
+    assert_eq!(
+        download(&storage, "/data/large.bin", 0).await.unwrap(),
+        large
+    );
+    assert_eq!(
+        download(&storage, "/data/large.bin", 1_000_000)
+            .await
+            .unwrap(),
+        large[1_000_000..]
+    );
+    assert_eq!(
+        download(&storage, "/data/small.txt", 0).await.unwrap(),
+        b"small"
+    );
+    assert_eq!(dir.files(), 0);
+
+    // Held in memory, the file doesn't fit the budget
+    let storage = budgeted(Storage::source_builder(image()));
+    assert!(download(&storage, "/data/large.bin", 0).await.is_err());
+    assert_eq!(
+        download(&storage, "/data/small.txt", 0).await.unwrap(),
+        b"small"
+    );
+}
+
+#[tokio::test]
+async fn limits() {
+    let dir = Dir::new("limits");
+    let storage = Storage::source_builder(image())
+        .gunzip(true)
+        .spool_dir(&dir.0)
+        .max_file_size(LARGE as u64 - 1)
+        .build();
+    assert!(download(&storage, "/data/large.bin", 0).await.is_err());
+    assert_eq!(dir.files(), 0);
+
+    let missing = dir.0.join("missing");
+    let storage = Storage::source_builder(image())
+        .gunzip(true)
+        .spool_dir(missing)
+        .build();
+    assert!(download(&storage, "/data/large.bin", 0).await.is_err());
+    // Small files don't need the spool
+    assert_eq!(
+        download(&storage, "/data/small.txt", 0).await.unwrap(),
+        b"small"
+    );
+}
+
+#[cfg(feature = "checksums")]
+#[tokio::test]
+async fn verified() {
+    use unftp_sbe_iso::{HashAlgorithm, IntegrityMode};
+
+    let large = contents();
+    let digest = Storage::from_source(
+        IsoBuilder::new()
+            .joliet(true)
+            .file("/large.bin", &large)
+            .build(),
+    )
+    .hash("/large.bin", HashAlgorithm::Sha256)
+    .await
+    .unwrap();
+    let sums = format!("{digest}  ./data/large.bin\n{digest}  ./data/bad.bin\n");
+    let mut bad = large.clone();
+    bad[LARGE - 1] ^= 1;
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .file("/sha256sum.txt", sums.as_bytes())
+        .file("/data/large.bin", &large)
+        .file("/data/bad.bin", &bad)
+        .file("/data/large.txt.gz", &gzip(&large))
+        .build();
+    let dir = Dir::new("verified");
+    let storage = budgeted(
+        Storage::source_builder(image)
+            .spool_dir(&dir.0)
+            .integrity(IntegrityMode::Abort),
+    );
+    assert_eq!(
+        download(&storage, "/data/large.bin", 0).await.unwrap(),
+        large
+    );
+    assert!(download(&storage, "/data/bad.bin", 0).await.is_err());
+    // Digests of decompressed files are computed over the spooled file
+    let spooled = storage
+        .hash("/data/large.txt", HashAlgorithm::Sha256)
+        .await
+        .unwrap();
+    assert_eq!(spooled, digest);
+    assert_eq!(dir.files(), 0);
+}
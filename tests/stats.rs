@@ -0,0 +1,117 @@
+//! Per-path download statistics and their periodic reports.
+
+use std::{path::Path, sync::mpsc, time::Duration};
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{PathStats, Storage, fixture::IsoBuilder};
+
+fn storage() -> Storage {
+    let image = IsoBuilder::new()
+        .file("/docs/readme.txt", b"hello")
+        .file("/big.bin", &vec![7; 300_000])
+        .build();
+    Storage::from_source(image)
+}
+
+async fn read(storage: &Storage, path: &str, start: u64) -> usize {
+    let mut reader = storage.get(&DefaultUser {}, path, start).await.unwrap();
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).await.unwrap();
+    contents.len()
+}
+
+#[tokio::test]
+async fn counted_per_path() {
+    let storage = storage();
+    assert!(storage.stats().is_empty());
+    assert_eq!(read(&storage, "/docs/readme.txt", 0).await, 5);
+    assert_eq!(read(&storage, "/docs/readme.txt", 2).await, 3);
+    assert_eq!(read(&storage, "/big.bin", 0).await, 300_000);
+    let stats = storage.stats();
+    assert_eq!(
+        stats[Path::new("/docs/readme.txt")],
+        PathStats {
+            downloads: 2,
+            bytes: 8
+        }
+    );
+    assert_eq!(
+        stats[Path::new("/big.bin")],
+        PathStats {
+            downloads: 1,
+            bytes: 300_000
+        }
+    );
+    // A download only started counts, but not bytes that weren't read
+    let reader = storage.get(&DefaultUser {}, "/big.bin", 0).await.unwrap();
+    drop(reader);
+    assert_eq!(
+        storage.stats()[Path::new("/big.bin")],
+        PathStats {
+            downloads: 2,
+            bytes: 300_000
+        }
+    );
+}
+
+#[tokio::test]
+async fn reported_until_dropped() {
+    let storage = storage();
+    let (sender, reports) = mpsc::channel();
+    let task = storage.report_stats(Duration::from_millis(10), move |stats| {
+        let _ = sender.send(stats.clone());
+    });
+    read(&storage, "/docs/readme.txt", 0).await;
+    let reported = loop {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        if let Some(stats) = reports.try_iter().last()
+            && !stats.is_empty()
+        {
+            break stats;
+        }
+    };
+    assert_eq!(reported, storage.stats());
+    // Once the back-end is gone, the task ends
+    drop(storage);
+    tokio::time::timeout(Duration::from_secs(5), task)
+        .await
+        .expect("the reporter outlived the back-end")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn reported_for_a_directory() {
+    let name = format!("unftp-sbe-iso-stats-{}", std::process::id());
+    let dir = std::env::temp_dir().join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    let image = IsoBuilder::new().file("/docs/readme.txt", b"hello").build();
+    std::fs::write(dir.join("debian.iso"), image).unwrap();
+    let storage = Storage::directory(&dir);
+    let (sender, reports) = mpsc::channel();
+    let task = storage.report_stats(Duration::from_millis(10), move |stats| {
+        let _ = sender.send(stats.clone());
+    });
+    read(&storage, "/debian/docs/readme.txt", 0).await;
+    // The downloads of the images are reported under their directory
+    let reported = loop {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        if let Some(stats) = reports.try_iter().last()
+            && !stats.is_empty()
+        {
+            break stats;
+        }
+    };
+    assert_eq!(
+        reported[Path::new("/debian/docs/readme.txt")],
+        PathStats {
+            downloads: 1,
+            bytes: 5
+        }
+    );
+    drop(storage);
+    tokio::time::timeout(Duration::from_secs(5), task)
+        .await
+        .expect("the reporter outlived the back-end")
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
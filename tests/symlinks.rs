@@ -0,0 +1,89 @@
+//! Resolution of Rock Ridge symbolic links.
+
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+async fn read(storage: &Storage, path: &str) -> unftp_core::storage::Result<Vec<u8>> {
+    let mut reader = storage.get(&DefaultUser {}, path, 0).await?;
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).await.unwrap();
+    Ok(buf)
+}
+
+/// The message of the error's source, which is where the back-end puts the details.
+fn message(err: unftp_core::storage::Error) -> String {
+    std::error::Error::source(&err)
+        .map(|source| source.to_string())
+        .unwrap_or_default()
+}
+
+fn image() -> IsoBuilder {
+    IsoBuilder::new()
+        .rock_ridge(true)
+        .file("/data/v2/notes.txt", b"notes")
+        .symlink("/data/current", "v2")
+        .symlink("/notes", "data/current/notes.txt")
+        .symlink("/absolute", "/data/v2/notes.txt")
+        .symlink("/data/v2/up", "../../data")
+        .symlink("/here", ".")
+}
+
+#[tokio::test]
+async fn follows_links() {
+    let image = image().build_file();
+    let storage = Storage::new(image.path());
+    assert_eq!(read(&storage, "/notes").await.unwrap(), b"notes");
+    assert_eq!(read(&storage, "/absolute").await.unwrap(), b"notes");
+    assert_eq!(
+        read(&storage, "/data/current/notes.txt").await.unwrap(),
+        b"notes"
+    );
+    assert_eq!(
+        read(&storage, "/data/v2/up/v2/up/current/notes.txt")
+            .await
+            .unwrap(),
+        b"notes"
+    );
+    assert_eq!(read(&storage, "/here/here/notes").await.unwrap(), b"notes");
+    let user = DefaultUser {};
+    assert!(storage.cwd(&user, "/data/current").await.is_ok());
+    assert_eq!(storage.list(&user, "/data/current").await.unwrap().len(), 4);
+    // Like lstat(2), metadata describes the link itself
+    let meta = storage.metadata(&user, "/data/current").await.unwrap();
+    assert!(!meta.is_dir());
+    assert!(meta.sym);
+    let meta = storage.metadata(&user, "/data/current/up").await.unwrap();
+    assert!(meta.sym);
+}
+
+#[tokio::test]
+async fn detects_loops() {
+    let image = IsoBuilder::new()
+        .rock_ridge(true)
+        .symlink("/a", "b")
+        .symlink("/b", "a")
+        .symlink("/self", "self")
+        .symlink("/dir/loop", "../dir/loop")
+        .build_file();
+    let storage = Storage::new(image.path());
+    for path in ["/a", "/b", "/self", "/dir/loop", "/a/deeper"] {
+        let err = message(read(&storage, path).await.unwrap_err());
+        assert!(err.contains("loop"), "{path}: {err}");
+    }
+}
+
+#[tokio::test]
+async fn limits_hops() {
+    let builder = (0..50).fold(IsoBuilder::new().rock_ridge(true), |builder, i| {
+        builder.symlink(&format!("/link{i}"), &format!("link{}", i + 1))
+    });
+    let image = builder.file("/link50", b"end").build_file();
+    let storage = Storage::new(image.path());
+    assert_eq!(read(&storage, "/link20").await.unwrap(), b"end");
+    let err = message(read(&storage, "/link0").await.unwrap_err());
+    assert!(err.contains("too many levels"), "{err}");
+}
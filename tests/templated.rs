@@ -0,0 +1,93 @@
+//! Serving every user an image of their own.
+
+use std::{fmt, path::PathBuf};
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::UserDetail,
+    storage::{ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+#[derive(Debug)]
+struct User(&'static str);
+
+impl fmt::Display for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl UserDetail for User {}
+
+/// A directory with an image for each of the users, removed on drop.
+struct Images(PathBuf);
+
+impl Images {
+    fn new(test: &str, users: &[&str]) -> Self {
+        let name = format!("unftp-sbe-iso-{test}-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        for user in users {
+            let image = IsoBuilder::new()
+                .joliet(true)
+                .file("/whoami.txt", user.as_bytes())
+                .build();
+            std::fs::write(dir.join(format!("{user}.iso")), image).unwrap();
+        }
+        Images(dir)
+    }
+
+    fn template(&self) -> String {
+        format!("{}/{{username}}.iso", self.0.display())
+    }
+}
+
+impl Drop for Images {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+async fn whoami(storage: &Storage, user: &User) -> String {
+    let mut contents = String::new();
+    storage
+        .get(user, "/whoami.txt", 0)
+        .await
+        .unwrap()
+        .read_to_string(&mut contents)
+        .await
+        .unwrap();
+    contents
+}
+
+#[tokio::test]
+async fn images_per_user() {
+    let images = Images::new("tenants", &["alice", "bob"]);
+    let storage = Storage::templated(&images.template());
+    assert_eq!(whoami(&storage, &User("alice")).await, "alice");
+    assert_eq!(whoami(&storage, &User("bob")).await, "bob");
+    assert_eq!(whoami(&storage, &User("alice")).await, "alice");
+    let listed = storage.list(&User("bob"), "/").await.unwrap();
+    assert!(listed.iter().any(|f| f.path.to_str() == Some("whoami.txt")));
+    assert!(storage.cwd(&User("alice"), "/").await.is_ok());
+    assert_eq!(storage.stats().len(), 1);
+
+    // A user without an image has nothing to be served
+    let e = storage.list(&User("carol"), "/").await.err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::LocalError);
+    // Nor does anything without a user
+    assert!(storage.volume_descriptors().await.is_err());
+}
+
+#[tokio::test]
+async fn names_that_would_escape() {
+    let images = Images::new("escapes", &["alice"]);
+    let storage = Storage::templated(&images.template());
+    for name in ["..", "../alice", "alice/..", ""] {
+        let e = storage
+            .metadata(&User(name), "/whoami.txt")
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::PermissionDenied, "{name:?}");
+    }
+}
@@ -0,0 +1,255 @@
+//! The boot artifacts of the image served over TFTP.
+
+use std::{net::SocketAddr, time::Duration};
+use tokio::{net::UdpSocket, time::timeout};
+use unftp_sbe_iso::{Storage, TftpServer, fixture::IsoBuilder};
+
+/// Starts a server for an image with a boot image and an `/isolinux` directory.
+async fn server() -> SocketAddr {
+    serve(|server| server).await
+}
+
+/// Starts a server like [`server`], set up by `setup`.
+async fn serve(setup: impl FnOnce(TftpServer) -> TftpServer) -> SocketAddr {
+    let kernel: Vec<u8> = (0..2000u32).map(|i| i as u8).collect();
+    let image = IsoBuilder::new()
+        .boot_image(b"boot sector")
+        .file("/isolinux/isolinux.cfg", b"default linux")
+        .file("/isolinux/vmlinuz", &kernel)
+        .file("/secret.txt", b"not for PXE")
+        .build();
+    let storage = Storage::source_builder(image).boot_images(true).build();
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let server = setup(TftpServer::new(storage).subtree("/isolinux"));
+    tokio::spawn(server.serve(socket));
+    addr
+}
+
+fn request(opcode: u8, fields: &[&str]) -> Vec<u8> {
+    let mut packet = vec![0, opcode];
+    for field in fields {
+        packet.extend_from_slice(field.as_bytes());
+        packet.push(0);
+    }
+    packet
+}
+
+/// What a read request was answered with.
+#[derive(Debug)]
+enum Answer {
+    File {
+        options: Vec<String>,
+        contents: Vec<u8>,
+        blocks: usize,
+    },
+    Error(u16),
+}
+
+/// Fetches `fields` from the server, acknowledging every block.
+async fn fetch(server: SocketAddr, fields: &[&str]) -> Answer {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.send_to(&request(1, fields), server).await.unwrap();
+    let mut options = Vec::new();
+    let mut contents = Vec::new();
+    let mut blocks = 0;
+    let mut block = 512;
+    let mut packet = vec![0; 70_000];
+    loop {
+        let (len, from) = socket.recv_from(&mut packet).await.unwrap();
+        // Transfers run from a port of their own
+        assert_ne!(from, server);
+        let packet = &packet[..len];
+        let ack = |block: &[u8]| [&[0, 4][..], block].concat();
+        match packet[1] {
+            3 => {
+                blocks += 1;
+                contents.extend_from_slice(&packet[4..]);
+                socket.send_to(&ack(&packet[2..4]), from).await.unwrap();
+                if len < 4 + block {
+                    return Answer::File {
+                        options,
+                        contents,
+                        blocks,
+                    };
+                }
+            }
+            5 => return Answer::Error(u16::from_be_bytes([packet[2], packet[3]])),
+            6 => {
+                options = packet[2..len - 1]
+                    .split(|&b| b == 0)
+                    .map(|field| String::from_utf8(field.to_vec()).unwrap())
+                    .collect::<Vec<_>>();
+                if let Some(at) = options.iter().position(|o| o == "blksize") {
+                    block = options[at + 1].parse().unwrap();
+                }
+                socket.send_to(&ack(&[0, 0]), from).await.unwrap();
+            }
+            other => panic!("unexpected opcode {other}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn boot_images_and_subtrees() {
+    let server = server().await;
+    let Answer::File { contents, .. } = fetch(server, &["boot.img", "octet"]).await else {
+        panic!("boot image not served");
+    };
+    assert!(contents.starts_with(b"boot sector"));
+
+    let Answer::File {
+        contents, blocks, ..
+    } = fetch(server, &["/isolinux/vmlinuz", "octet"]).await
+    else {
+        panic!("kernel not served");
+    };
+    assert_eq!(contents.len(), 2000);
+    assert_eq!(contents[1999], (1999u32 as u8));
+    assert_eq!(blocks, 4);
+
+    // Windows separators, other cases and netascii
+    let Answer::File { contents, .. } =
+        fetch(server, &["\\ISOLINUX\\isolinux.cfg", "netascii"]).await
+    else {
+        panic!("configuration not served");
+    };
+    assert_eq!(contents, b"default linux");
+}
+
+#[tokio::test]
+async fn options() {
+    let server = server().await;
+    let answer = fetch(
+        server,
+        &[
+            "isolinux/vmlinuz",
+            "octet",
+            "blksize",
+            "1024",
+            "tsize",
+            "0",
+            "multicast",
+            "",
+        ],
+    )
+    .await;
+    let Answer::File {
+        options,
+        contents,
+        blocks,
+    } = answer
+    else {
+        panic!("kernel not served: {answer:?}");
+    };
+    assert_eq!(options, ["blksize", "1024", "tsize", "2000"]);
+    assert_eq!(contents.len(), 2000);
+    assert_eq!(blocks, 2);
+}
+
+#[tokio::test]
+async fn refused() {
+    let server = server().await;
+    for (fields, code) in [
+        (&["secret.txt", "octet"][..], 2),
+        (&["isolinux/../secret.txt", "octet"], 2),
+        (&["isolinux/missing", "octet"], 1),
+        (&["efi.img", "octet"], 1),
+        (&["isolinux", "octet"], 2),
+    ] {
+        assert!(
+            matches!(fetch(server, fields).await, Answer::Error(c) if c == code),
+            "{fields:?}"
+        );
+    }
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut packet = [0; 100];
+    for (opcode, code) in [(2, 2), (4, 4)] {
+        socket
+            .send_to(&request(opcode, &["boot.img", "octet"]), server)
+            .await
+            .unwrap();
+        socket.recv_from(&mut packet).await.unwrap();
+        assert_eq!(packet[..4], [0, 5, 0, code]);
+    }
+}
+
+#[tokio::test]
+async fn blocks_limited() {
+    let server = server().await;
+    let answer = fetch(server, &["isolinux/vmlinuz", "octet", "blksize", "65464"]).await;
+    let Answer::File {
+        options, blocks, ..
+    } = answer
+    else {
+        panic!("kernel not served: {answer:?}");
+    };
+    assert_eq!(options, ["blksize", "1468"]);
+    assert_eq!(blocks, 2);
+    let server = serve(|server| server.max_block(4096)).await;
+    let answer = fetch(server, &["isolinux/vmlinuz", "octet", "blksize", "65464"]).await;
+    assert!(
+        matches!(answer, Answer::File { blocks: 1, .. }),
+        "{answer:?}"
+    );
+}
+
+#[tokio::test]
+async fn transfers_limited() {
+    let server = serve(|server| server.max_transfers(1)).await;
+    // A transfer whose first block is never acknowledged
+    let stalled = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut packet = [0; 600];
+    stalled
+        .send_to(&request(1, &["boot.img", "octet"]), server)
+        .await
+        .unwrap();
+    let (_, transfer) = stalled.recv_from(&mut packet).await.unwrap();
+    // The next request isn't answered while it runs
+    let waiting = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    waiting
+        .send_to(&request(1, &["isolinux/isolinux.cfg", "octet"]), server)
+        .await
+        .unwrap();
+    let early = timeout(Duration::from_millis(300), waiting.recv_from(&mut packet)).await;
+    assert!(early.is_err());
+    // Once the stalled one is aborted, it is
+    stalled
+        .send_to(&[0, 5, 0, 0, b'x', 0], transfer)
+        .await
+        .unwrap();
+    let (len, _) = timeout(Duration::from_secs(5), waiting.recv_from(&mut packet))
+        .await
+        .expect("the request waited for good")
+        .unwrap();
+    assert_eq!(&packet[4..len], b"default linux");
+}
+
+#[tokio::test]
+async fn sent_again_despite_junk() {
+    let server = server().await;
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut packet = [0; 600];
+    socket
+        .send_to(&request(1, &["boot.img", "octet"]), server)
+        .await
+        .unwrap();
+    let (_, transfer) = socket.recv_from(&mut packet).await.unwrap();
+    assert_eq!(packet[..4], [0, 3, 0, 1]);
+    // Packets that aren't the acknowledgement don't put off sending the block again
+    let again = async {
+        loop {
+            socket.send_to(&[0, 4, 0, 9], transfer).await.unwrap();
+            let got = timeout(Duration::from_millis(100), socket.recv_from(&mut packet)).await;
+            if let Ok(got) = got {
+                got.unwrap();
+                return packet[..4].to_vec();
+            }
+        }
+    };
+    let again = timeout(Duration::from_secs(3), again)
+        .await
+        .expect("the block wasn't sent again");
+    assert_eq!(again, [0, 3, 0, 1]);
+}
@@ -0,0 +1,138 @@
+//! Files recorded in several versions, like `FILE.TXT;1` next to `FILE.TXT;2`.
+
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{CacheConfig, FileVersions, Storage, StorageBuilder, fixture::IsoBuilder};
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .file("/v1", b"first")
+        .primary_name("/v1", "FILE.TXT;1")
+        .file("/v3", b"third revision")
+        .primary_name("/v3", "FILE.TXT;3")
+        .file("/v2", b"second")
+        .primary_name("/v2", "FILE.TXT;2")
+        .file("/other.txt", b"other")
+        .build()
+}
+
+async fn listed(storage: &Storage) -> Vec<(String, u64)> {
+    let mut names: Vec<_> = storage
+        .list(&DefaultUser {}, "/")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| (f.path.to_str().unwrap().to_string(), f.metadata.len()))
+        .filter(|(name, _)| name != "." && name != "..")
+        .collect();
+    names.sort();
+    names
+}
+
+async fn download(storage: &Storage, path: &str) -> Option<String> {
+    let mut reader = storage.get(&DefaultUser {}, path, 0).await.ok()?;
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents).await.unwrap();
+    Some(contents)
+}
+
+fn builders() -> [StorageBuilder; 2] {
+    let cache = CacheConfig {
+        listing_ttl: Some(Duration::from_secs(60)),
+        block_cache_mb: 1,
+        ..CacheConfig::default()
+    };
+    [
+        Storage::source_builder(image()),
+        Storage::source_builder(image()).cache(cache),
+    ]
+}
+
+#[tokio::test]
+async fn highest_by_default() {
+    for builder in builders() {
+        let storage = builder.build();
+        assert_eq!(
+            listed(&storage).await,
+            [("FILE.TXT".to_string(), 14), ("OTHER.TXT".to_string(), 5)]
+        );
+        assert_eq!(
+            download(&storage, "/file.txt").await.unwrap(),
+            "third revision"
+        );
+        assert_eq!(
+            storage
+                .metadata(&DefaultUser {}, "/FILE.TXT")
+                .await
+                .unwrap()
+                .len(),
+            14
+        );
+        assert_eq!(download(&storage, "/FILE.TXT;1").await, None);
+    }
+}
+
+#[tokio::test]
+async fn all() {
+    for builder in builders() {
+        let storage = builder.file_versions(FileVersions::All).build();
+        assert_eq!(
+            listed(&storage).await,
+            [
+                ("FILE.TXT".to_string(), 14),
+                ("FILE.TXT;1".to_string(), 5),
+                ("FILE.TXT;2".to_string(), 6),
+                ("OTHER.TXT".to_string(), 5)
+            ]
+        );
+        assert_eq!(
+            download(&storage, "/FILE.TXT").await.unwrap(),
+            "third revision"
+        );
+        assert_eq!(download(&storage, "/file.txt;1").await.unwrap(), "first");
+        assert_eq!(download(&storage, "/FILE.TXT;2").await.unwrap(), "second");
+    }
+}
+
+#[tokio::test]
+async fn suffixed_names() {
+    for builder in builders() {
+        // Files listed without a version are still found under the one they are recorded with
+        let storage = builder.build();
+        assert_eq!(
+            download(&storage, "/FILE.TXT;3").await.unwrap(),
+            "third revision"
+        );
+        assert_eq!(download(&storage, "/other.txt;1").await.unwrap(), "other");
+        assert_eq!(download(&storage, "/OTHER.TXT;2").await, None);
+        assert!(
+            storage
+                .metadata(&DefaultUser {}, "/OTHER.TXT;1")
+                .await
+                .is_ok()
+        );
+    }
+    let storage = Storage::source_builder(image())
+        .file_versions(FileVersions::All)
+        .build();
+    assert_eq!(
+        download(&storage, "/file.txt;3").await.unwrap(),
+        "third revision"
+    );
+    assert_eq!(download(&storage, "/FILE.TXT;2").await.unwrap(), "second");
+
+    // And when long names are presented
+    for image in [
+        IsoBuilder::new().joliet(true),
+        IsoBuilder::new().rock_ridge(true),
+    ] {
+        let image = image.file("/Read Me.txt", b"long").build();
+        let storage = Storage::from_source(image);
+        assert_eq!(download(&storage, "/Read Me.txt;1").await.unwrap(), "long");
+        assert_eq!(download(&storage, "/read me.txt").await.unwrap(), "long");
+    }
+}
@@ -0,0 +1,228 @@
+//! Virtual views of the files of the image.
+
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{Filter, Storage, View, fixture::IsoBuilder};
+
+fn image() -> IsoBuilder {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/docs/readme.txt", b"docs")
+        .file("/src/readme.txt", b"src")
+        .file("/src/main.c", b"int main;")
+        .file("/old.doc", b"old")
+        .recorded("/docs/readme.txt", 2022, 4, 21)
+        .recorded("/src/readme.txt", 2022, 4, 1)
+        .recorded("/src/main.c", 2021, 12, 31)
+        .recorded("/old.doc", 1999, 1, 1)
+}
+
+async fn names(storage: &Storage, path: &str) -> Vec<String> {
+    let listed = storage.list(&DefaultUser {}, path).await.unwrap();
+    let mut names: Vec<String> = listed
+        .iter()
+        .map(|f| f.path.to_string_lossy().into_owned())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    names
+}
+
+async fn get(storage: &Storage, path: &str) -> String {
+    let mut contents = String::new();
+    storage
+        .get(&DefaultUser {}, path, 0)
+        .await
+        .unwrap()
+        .read_to_string(&mut contents)
+        .await
+        .unwrap();
+    contents
+}
+
+#[tokio::test]
+async fn by_date() {
+    let storage = Storage::source_builder(image().build())
+        .view(View::ByDate)
+        .build();
+    let user = DefaultUser {};
+    assert_eq!(
+        names(&storage, "/").await,
+        ["BY-DATE", "docs", "old.doc", "src"]
+    );
+    assert_eq!(names(&storage, "/BY-DATE").await, ["1999", "2021", "2022"]);
+    assert_eq!(names(&storage, "/by-date/2022").await, ["04"]);
+    assert_eq!(
+        names(&storage, "/BY-DATE/2022/04").await,
+        ["readme.txt", "readme.txt~2"]
+    );
+    assert_eq!(get(&storage, "/BY-DATE/2022/04/readme.txt").await, "docs");
+    assert_eq!(get(&storage, "/BY-DATE/2022/04/README.TXT~2").await, "src");
+    assert_eq!(get(&storage, "/BY-DATE/2021/12/main.c").await, "int main;");
+
+    let meta = storage
+        .metadata(&user, "/BY-DATE/1999/01/old.doc")
+        .await
+        .unwrap();
+    assert_eq!(meta.len(), 3);
+    assert!(
+        storage
+            .metadata(&user, "/BY-DATE/2022")
+            .await
+            .unwrap()
+            .is_dir()
+    );
+    assert!(storage.cwd(&user, "/BY-DATE/2022/04").await.is_ok());
+    let err = storage
+        .cwd(&user, "/BY-DATE/2022/04/readme.txt")
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermanentDirectoryNotAvailable);
+    let err = storage.metadata(&user, "/BY-DATE/2020").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermanentFileNotAvailable);
+    assert!(storage.get(&user, "/BY-DATE/2022", 0).await.is_err());
+    let range = storage
+        .read_range("/BY-DATE/2021/12/main.c", 4, 4)
+        .await
+        .unwrap();
+    assert_eq!(&range[..], b"main");
+
+    // The view is listed in full, but the root doesn't walk into it
+    let walked = storage.list_recursive("/BY-DATE/2022").await.unwrap();
+    let walked: Vec<_> = walked.iter().map(|f| f.path.to_str().unwrap()).collect();
+    assert_eq!(walked, ["04", "04/readme.txt", "04/readme.txt~2"]);
+    let mut walk = storage.walk("/BY-DATE/2022");
+    let mut streamed = Vec::new();
+    while let Some((path, _)) = walk.next_entry().await.unwrap() {
+        streamed.push(path.to_str().unwrap().to_string());
+    }
+    assert_eq!(streamed, walked);
+    let walked = storage.list_recursive("/").await.unwrap();
+    assert!(walked.iter().any(|f| f.path.to_str() == Some("BY-DATE")));
+    assert!(
+        !walked
+            .iter()
+            .any(|f| f.path.to_str().unwrap().starts_with("BY-DATE/"))
+    );
+}
+
+#[tokio::test]
+async fn hidden_files_stay_hidden() {
+    let storage = Storage::source_builder(image().build())
+        .view(View::ByDate)
+        .filter(Filter::new().exclude_extensions(["doc"]))
+        .build();
+    assert_eq!(names(&storage, "/BY-DATE").await, ["2021", "2022"]);
+    let err = storage
+        .get(&DefaultUser {}, "/BY-DATE/1999/01/old.doc", 0)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::PermanentFileNotAvailable);
+}
+
+#[tokio::test]
+async fn shadowed_or_disabled() {
+    // An entry of the image wins over the view
+    let shadowed = image().file("/by-date/mine.txt", b"mine").build();
+    let storage = Storage::source_builder(shadowed).view(View::ByDate).build();
+    assert_eq!(
+        names(&storage, "/").await,
+        ["by-date", "docs", "old.doc", "src"]
+    );
+    assert_eq!(names(&storage, "/BY-DATE").await, ["mine.txt"]);
+
+    let storage = Storage::from_source(image().build());
+    assert!(storage.cwd(&DefaultUser {}, "/BY-DATE").await.is_err());
+}
+
+#[tokio::test]
+async fn all_files() {
+    let storage = Storage::source_builder(image().build())
+        .view(View::AllFiles)
+        .view(View::ByDate)
+        .build();
+    assert_eq!(
+        names(&storage, "/").await,
+        ["ALL", "BY-DATE", "docs", "old.doc", "src"]
+    );
+    assert_eq!(
+        names(&storage, "/ALL").await,
+        ["main.c", "old.doc", "readme.txt", "readme.txt~2"]
+    );
+    assert_eq!(get(&storage, "/all/readme.txt").await, "docs");
+    assert_eq!(get(&storage, "/ALL/readme.txt~2").await, "src");
+    let meta = storage
+        .metadata(&DefaultUser {}, "/ALL/main.c")
+        .await
+        .unwrap();
+    assert_eq!(meta.len(), 9);
+    assert!(storage.cwd(&DefaultUser {}, "/ALL/docs").await.is_err());
+}
+
+#[tokio::test]
+async fn namespaces() {
+    let image = IsoBuilder::new()
+        .joliet(true)
+        .rock_ridge(true)
+        .file("/Docs/Read Me.txt", b"long")
+        .primary_name("/Docs/Read Me.txt", "README.TXT;1")
+        .symlink("/Docs/latest", "Read Me.txt")
+        .build();
+    let diagnostic = |builder: unftp_sbe_iso::StorageBuilder| {
+        View::NAMESPACES
+            .into_iter()
+            .fold(builder, |builder, view| builder.view(view))
+            .build()
+    };
+    let storage = diagnostic(Storage::source_builder(image.clone()));
+    assert_eq!(
+        names(&storage, "/").await,
+        ["Docs", "JOLIET", "PRIMARY", "ROCKRIDGE"]
+    );
+    assert_eq!(names(&storage, "/PRIMARY").await, ["DOCS"]);
+    assert_eq!(
+        names(&storage, "/PRIMARY/DOCS").await,
+        ["LATEST", "README.TXT"]
+    );
+    assert_eq!(names(&storage, "/JOLIET/Docs").await, ["Read Me.txt"]);
+    assert_eq!(
+        names(&storage, "/ROCKRIDGE/Docs").await,
+        ["Read Me.txt", "latest"]
+    );
+    for path in [
+        "/PRIMARY/docs/readme.txt",
+        "/JOLIET/Docs/Read Me.txt",
+        "/ROCKRIDGE/Docs/Read Me.txt",
+    ] {
+        assert_eq!(get(&storage, path).await, "long", "{path}");
+    }
+    let user = DefaultUser {};
+    let link = storage
+        .metadata(&user, "/ROCKRIDGE/Docs/latest")
+        .await
+        .unwrap();
+    assert!(link.sym);
+    assert!(
+        storage
+            .get(&user, "/ROCKRIDGE/Docs/latest", 0)
+            .await
+            .is_err()
+    );
+    let range = storage
+        .read_range("/JOLIET/Docs/Read Me.txt", 1, 2)
+        .await
+        .unwrap();
+    assert_eq!(&range[..], b"on");
+
+    // Hierarchies the image lacks are left out, and the views keep to the served subtree
+    let plain = IsoBuilder::new().file("/Docs/a.txt", b"a").build();
+    let storage = diagnostic(Storage::source_builder(plain));
+    assert_eq!(names(&storage, "/").await, ["DOCS", "PRIMARY"]);
+    assert!(storage.list(&user, "/JOLIET").await.is_err());
+    let storage = diagnostic(Storage::source_builder(image).root("/Docs"));
+    assert_eq!(names(&storage, "/JOLIET").await, ["Read Me.txt"]);
+}
@@ -0,0 +1,189 @@
+//! Serving files that aren't in the image, as added with `StorageBuilder::virtual_file`.
+
+use std::path::PathBuf;
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{Metadata, StorageBackend},
+};
+use unftp_sbe_iso::{Storage, fixture::IsoBuilder};
+
+const POLICY: &[u8] = b"Mirrored for internal use only.\n";
+
+fn image() -> Vec<u8> {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/README.TXT", b"from the image")
+        .file("/docs/guide.txt", b"guide")
+        .build()
+}
+
+async fn get(storage: &Storage, path: &str, start: u64) -> Result<Vec<u8>, ()> {
+    let mut reader = storage
+        .get(&DefaultUser {}, path, start)
+        .await
+        .map_err(drop)?;
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).await.unwrap();
+    Ok(contents)
+}
+
+async fn names(storage: &Storage, path: &str) -> Vec<String> {
+    let mut names: Vec<_> = storage
+        .list(&DefaultUser {}, path)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| f.path.to_str().unwrap().to_string())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn served() {
+    let storage = Storage::source_builder(image())
+        .virtual_file("/MIRROR-POLICY.txt", POLICY)
+        .virtual_file("docs/NOTICE", "nested")
+        .virtual_file("/missing/NOTICE", "nowhere")
+        .virtual_file("/missing/../docs/./LICENSE", "normalized")
+        .virtual_file("/", "ignored")
+        .build();
+    let user = DefaultUser {};
+    assert_eq!(
+        names(&storage, "/").await,
+        ["MIRROR-POLICY.txt", "README.TXT", "docs"]
+    );
+    assert_eq!(
+        names(&storage, "/docs").await,
+        ["LICENSE", "NOTICE", "guide.txt"]
+    );
+    assert_eq!(
+        get(&storage, "/docs/LICENSE", 0).await.unwrap(),
+        b"normalized"
+    );
+    let meta = storage.metadata(&user, "/MIRROR-POLICY.txt").await.unwrap();
+    assert_eq!(meta.len(), POLICY.len() as u64);
+    assert!(meta.is_file());
+    assert_eq!(
+        get(&storage, "/MIRROR-POLICY.txt", 0).await.unwrap(),
+        POLICY
+    );
+    assert_eq!(
+        get(&storage, "/MIRROR-POLICY.txt", 9).await.unwrap(),
+        &POLICY[9..]
+    );
+    assert!(get(&storage, "/MIRROR-POLICY.txt", 100).await.is_err());
+    // Matched like the names of the image
+    assert_eq!(get(&storage, "/docs/notice", 0).await.unwrap(), b"nested");
+    // No directory is made up for a file
+    assert!(storage.metadata(&user, "/missing").await.is_err());
+    assert!(get(&storage, "/missing/NOTICE", 0).await.is_err());
+
+    let mut file = storage.open("/MIRROR-POLICY.txt").await.unwrap();
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).await.unwrap();
+    assert_eq!(contents, POLICY);
+}
+
+#[tokio::test]
+async fn image_first() {
+    let storage = Storage::source_builder(image())
+        .virtual_file("/README.TXT", "virtual")
+        .virtual_file("/docs", "virtual")
+        .build();
+    assert_eq!(names(&storage, "/").await, ["README.TXT", "docs"]);
+    assert_eq!(
+        get(&storage, "/README.TXT", 0).await.unwrap(),
+        b"from the image"
+    );
+    assert_eq!(names(&storage, "/docs").await, ["guide.txt"]);
+}
+
+/// A directory of files, removed on drop.
+struct Dir(PathBuf);
+
+impl Dir {
+    fn new(test: &str) -> Self {
+        let name = format!("unftp-sbe-iso-virtual-{test}-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        Dir(dir)
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[tokio::test]
+async fn local() {
+    let dir = Dir::new("local");
+    let policy = dir.0.join("policy.txt");
+    std::fs::write(&policy, "first").unwrap();
+    let storage = Storage::source_builder(image())
+        .virtual_file_from("/POLICY.txt", &policy)
+        .build();
+    let user = DefaultUser {};
+    assert_eq!(get(&storage, "/POLICY.txt", 0).await.unwrap(), b"first");
+
+    // Edits show right away
+    std::fs::write(&policy, "second edition").unwrap();
+    assert_eq!(
+        storage.metadata(&user, "/POLICY.txt").await.unwrap().len(),
+        14
+    );
+    assert_eq!(
+        get(&storage, "/POLICY.txt", 0).await.unwrap(),
+        b"second edition"
+    );
+
+    // Once it is gone, it fails to be served but the listing goes on without it
+    std::fs::remove_file(&policy).unwrap();
+    assert!(get(&storage, "/POLICY.txt", 0).await.is_err());
+    assert!(storage.metadata(&user, "/POLICY.txt").await.is_err());
+    assert_eq!(names(&storage, "/").await, ["README.TXT", "docs"]);
+}
+
+#[tokio::test]
+async fn directory() {
+    let dir = Dir::new("directory");
+    std::fs::write(dir.0.join("a.iso"), image()).unwrap();
+    std::fs::write(dir.0.join("b.iso"), image()).unwrap();
+    let storage = Storage::directory_builder(&dir.0)
+        .virtual_file("/MIRROR-POLICY.txt", POLICY)
+        .build();
+    for image in ["a", "b"] {
+        let path = format!("/{image}/MIRROR-POLICY.txt");
+        assert_eq!(get(&storage, &path, 0).await.unwrap(), POLICY);
+        assert!(
+            names(&storage, &format!("/{image}"))
+                .await
+                .contains(&"MIRROR-POLICY.txt".to_string())
+        );
+    }
+    // Not in the listing of the images themselves
+    assert!(get(&storage, "/MIRROR-POLICY.txt", 0).await.is_err());
+}
+
+#[cfg(feature = "checksums")]
+#[tokio::test]
+async fn hashed() {
+    use unftp_sbe_iso::HashAlgorithm;
+
+    let storage = Storage::source_builder(image())
+        .virtual_file("/MIRROR-POLICY.txt", POLICY)
+        .build();
+    let expected = Storage::from_source(IsoBuilder::new().file("/policy", POLICY).build())
+        .hash("/policy", HashAlgorithm::Sha256)
+        .await
+        .unwrap();
+    let digest = storage
+        .hash("/MIRROR-POLICY.txt", HashAlgorithm::Sha256)
+        .await
+        .unwrap();
+    assert_eq!(digest, expected);
+}
@@ -0,0 +1,82 @@
+//! Watching the image file for changes.
+#![cfg(feature = "watch")]
+
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use unftp_core::{auth::DefaultUser, storage::StorageBackend};
+use unftp_sbe_iso::{CacheConfig, Storage, fixture::IsoBuilder};
+
+async fn get(storage: &Storage, path: &str) -> String {
+    let mut contents = String::new();
+    storage
+        .get(&DefaultUser {}, path, 0)
+        .await
+        .unwrap()
+        .read_to_string(&mut contents)
+        .await
+        .unwrap();
+    contents
+}
+
+#[tokio::test]
+async fn replaced_file() {
+    let image = |contents: &[u8]| IsoBuilder::new().file("/readme.txt", contents).build();
+    let dir = std::env::temp_dir().join(format!("unftp-sbe-iso-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("image.iso");
+    std::fs::write(&path, image(b"first")).unwrap();
+    let storage = Storage::builder(&path)
+        .cache(CacheConfig {
+            block_cache_mb: 1,
+            content_cache_mb: 1,
+            ..Default::default()
+        })
+        .build();
+    storage.watch().unwrap();
+    assert_eq!(get(&storage, "/readme.txt").await, "first");
+
+    std::fs::write(dir.join("image.new"), image(b"again")).unwrap();
+    std::fs::rename(dir.join("image.new"), &path).unwrap();
+    let mut contents = get(&storage, "/readme.txt").await;
+    for _ in 0..100 {
+        if contents == "again" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        contents = get(&storage, "/readme.txt").await;
+    }
+    assert_eq!(contents, "again");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn directory_of_images() {
+    let image = |contents: &[u8]| IsoBuilder::new().file("/readme.txt", contents).build();
+    let name = format!("unftp-sbe-iso-watch-dir-{}", std::process::id());
+    let dir = std::env::temp_dir().join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("first.iso"), image(b"first")).unwrap();
+    let storage = Storage::directory(&dir);
+    storage.watch().unwrap();
+    assert_eq!(get(&storage, "/first/readme.txt").await, "first");
+
+    std::fs::write(dir.join("second.iso"), image(b"second")).unwrap();
+    let user = DefaultUser {};
+    let mut found = storage.metadata(&user, "/second").await.is_ok();
+    for _ in 0..100 {
+        if found {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        found = storage.metadata(&user, "/second").await.is_ok();
+    }
+    assert!(found);
+    assert_eq!(get(&storage, "/second/readme.txt").await, "second");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn only_files() {
+    let storage = Storage::from_source(IsoBuilder::new().build());
+    assert!(storage.watch().is_err());
+}
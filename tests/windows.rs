@@ -0,0 +1,135 @@
+//! Subtrees accessible within windows of time only.
+
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncReadExt;
+use unftp_core::{
+    auth::DefaultUser,
+    storage::{ErrorKind, StorageBackend},
+};
+use unftp_sbe_iso::{AccessWindows, Storage, View, fixture::IsoBuilder};
+
+fn image() -> IsoBuilder {
+    IsoBuilder::new()
+        .joliet(true)
+        .file("/readme.txt", b"hello")
+        .file("/releases/v1/boot.img", b"v1")
+        .file("/releases/v2/boot.img", b"v2")
+        .file("/betas/rc1.img", b"rc1")
+}
+
+fn windows() -> AccessWindows {
+    let hour = Duration::from_secs(3600);
+    AccessWindows::new()
+        .opens("/releases/v2", SystemTime::now() + hour)
+        .opens("/releases/v1", SystemTime::now() - hour)
+        .closes("/betas/rc1.img", SystemTime::now() - hour)
+}
+
+async fn names(storage: &Storage, path: &str) -> Vec<String> {
+    let mut names: Vec<String> = storage
+        .list(&DefaultUser {}, path)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.path.to_string_lossy().to_lowercase())
+        .filter(|name| name != "." && name != "..")
+        .collect();
+    names.sort();
+    names
+}
+
+async fn assert_missing(storage: &Storage, path: &str) {
+    let user = DefaultUser {};
+    let e = storage.metadata(&user, path).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::PermanentFileNotAvailable, "{path}");
+    assert!(storage.get(&user, path, 0).await.is_err(), "{path}");
+    assert!(storage.list(&user, path).await.is_err(), "{path}");
+    assert!(storage.cwd(&user, path).await.is_err(), "{path}");
+}
+
+#[tokio::test]
+async fn outside_their_windows() {
+    let storage = Storage::source_builder(image().build())
+        .access_windows(windows())
+        .build();
+    assert_eq!(names(&storage, "/releases").await, ["v1"]);
+    assert!(names(&storage, "/betas").await.is_empty());
+    for path in [
+        "/releases/v2",
+        "/releases/v2/boot.img",
+        "/RELEASES/V2/BOOT.IMG",
+        "/releases/v1/../v2",
+        "/betas/rc1.img",
+    ] {
+        assert_missing(&storage, path).await;
+    }
+
+    let mut contents = Vec::new();
+    let mut reader = storage
+        .get(&DefaultUser {}, "/releases/v1/boot.img", 0)
+        .await
+        .unwrap();
+    reader.read_to_end(&mut contents).await.unwrap();
+    assert_eq!(contents, b"v1");
+}
+
+#[tokio::test]
+async fn images_of_a_directory() {
+    let dir = std::env::temp_dir().join(format!("unftp-sbe-iso-windows-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("stable.iso"), image().build()).unwrap();
+    std::fs::write(dir.join("next.iso"), image().build()).unwrap();
+    let hour = Duration::from_secs(3600);
+    let storage = Storage::directory_builder(&dir)
+        .access_windows(
+            AccessWindows::new()
+                .opens("/next", SystemTime::now() + hour)
+                .opens("/stable/releases/v2", SystemTime::now() + hour),
+        )
+        .build();
+    assert_eq!(names(&storage, "/").await, ["stable"]);
+    assert_eq!(names(&storage, "/stable/releases").await, ["v1"]);
+    assert_missing(&storage, "/next/readme.txt").await;
+    assert_missing(&storage, "/stable/releases/v2").await;
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn resolved_paths() {
+    let storage = Storage::source_builder(image().file("/betas/rc2.img", b"rc2").build())
+        .access_windows(windows())
+        .view(View::AllFiles)
+        .view(View::Joliet)
+        .build();
+    // Patterns list the entries of the directory above them
+    assert_eq!(names(&storage, "/betas/*.img").await, ["rc2.img"]);
+    assert_eq!(names(&storage, "/releases/v*").await, ["v1"]);
+    // Versions and trailing dots clients add
+    for path in ["/betas/rc1.img;1", "/BETAS/RC1.IMG;1", "/betas/rc1.img."] {
+        assert_missing(&storage, path).await;
+    }
+    // Views presenting the files
+    let all = names(&storage, "/ALL").await;
+    assert!(!all.contains(&"rc1.img".to_string()), "{all:?}");
+    assert!(all.contains(&"rc2.img".to_string()), "{all:?}");
+    assert!(
+        !names(&storage, "/JOLIET/betas")
+            .await
+            .contains(&"rc1.img".to_string())
+    );
+    for path in [
+        "/ALL/rc1.img",
+        "/JOLIET/betas/rc1.img",
+        "/JOLIET/releases/v2",
+    ] {
+        assert_missing(&storage, path).await;
+    }
+
+    let mut contents = Vec::new();
+    let mut reader = storage
+        .get(&DefaultUser {}, "/ALL/rc2.img", 0)
+        .await
+        .unwrap();
+    reader.read_to_end(&mut contents).await.unwrap();
+    assert_eq!(contents, b"rc2");
+}